@@ -0,0 +1,60 @@
+//! Heap allocations and time per legacy step once every agent has met all its neighbors:
+//! `cargo bench --bench allocations -- [side] [steps]`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use coop::{EnvironmentBuilder, Strategy};
+
+/// The system allocator, counting allocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    // `cargo bench` passes `--bench`; only the numbers are ours.
+    let mut numbers = std::env::args()
+        .skip(1)
+        .filter_map(|a| a.parse::<usize>().ok());
+    let side = numbers.next().unwrap_or(200);
+    let steps = numbers.next().unwrap_or(10);
+    let mut env = EnvironmentBuilder::new()
+        .size(side, side)
+        .noise(0.1)
+        .seed(1)
+        .strategies(&Strategy::ALL)
+        .memory_window(4)
+        .build()
+        .expect("valid benchmark parameters");
+    // Warm up so per-opponent histories exist and have reached their capacity.
+    env.run_with(10, drop);
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    env.run_with(steps, drop);
+    let elapsed = start.elapsed() / steps as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - before) / steps;
+    println!(
+        "{}x{} grid: {} allocations ({:.2} per cell), {:.1?} per step",
+        side,
+        side,
+        allocations,
+        allocations as f64 / (side * side) as f64,
+        elapsed
+    );
+}
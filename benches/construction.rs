@@ -0,0 +1,51 @@
+//! Time to construct many small environments, built one by one or stamped out of a template:
+//! `cargo bench --bench construction -- [side] [count]`.
+
+use std::time::{Duration, Instant};
+
+use coop::{EnvironmentBuilder, Strategy};
+
+fn builder() -> EnvironmentBuilder<'static> {
+    EnvironmentBuilder::new().strategies(&[Strategy::Deflect, Strategy::TicToc])
+}
+
+/// Total time to construct `count` environments with `construct`, which gets the seed.
+fn time(count: u64, mut construct: impl FnMut(u64) -> usize) -> Duration {
+    let start = Instant::now();
+    let cells: usize = (0..count).map(&mut construct).sum();
+    let elapsed = start.elapsed();
+    // Keeps the environments from being optimized away.
+    assert!(cells > 0);
+    elapsed
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; only the numbers are ours.
+    let mut numbers = std::env::args()
+        .skip(1)
+        .filter_map(|a| a.parse::<usize>().ok());
+    let side = numbers.next().unwrap_or(20);
+    let count = numbers.next().unwrap_or(10_000) as u64;
+
+    println!("{} environments of {}x{}", count, side, side);
+    let built = time(count, |seed| {
+        let env = builder().size(side, side).seed(seed).build();
+        env.expect("valid benchmark parameters").population()
+    });
+    let mut template = builder()
+        .size(side, side)
+        .template()
+        .expect("valid benchmark parameters");
+    let stamped = time(count, |seed| {
+        let env = template.stamp(seed);
+        env.expect("valid benchmark parameters").population()
+    });
+    for (name, total) in [("Built:", built), ("Stamped:", stamped)] {
+        println!(
+            "{:<10} {:>8.1?} total, {:>8.1?} each",
+            name,
+            total,
+            total / count as u32
+        );
+    }
+}
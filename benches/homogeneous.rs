@@ -0,0 +1,52 @@
+//! Time per step on a grid of mostly unconditional strategies, whose actions need no lookup
+//! of what they know, against one of mostly history-dependent ones:
+//! `cargo bench --bench homogeneous -- [side] [steps]`.
+
+use std::time::{Duration, Instant};
+
+use coop::{EnvironmentBuilder, Placement, Strategy, UpdateMode};
+
+fn time_steps(side: usize, steps: usize, mode: UpdateMode, mostly: Strategy) -> Duration {
+    let rest = if mostly == Strategy::Deflect {
+        Strategy::TicToc
+    } else {
+        Strategy::Deflect
+    };
+    let mut env = EnvironmentBuilder::new()
+        .size(side, side)
+        .noise(0.1)
+        .seed(1)
+        .mix(&[(mostly, 0.95), (rest, 0.05)], Placement::Shuffled)
+        .memory_window(4)
+        .update_mode(mode)
+        .build()
+        .expect("valid benchmark parameters");
+    let start = Instant::now();
+    env.run_with(steps, drop);
+    start.elapsed() / steps as u32
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; only the numbers are ours.
+    let mut numbers = std::env::args()
+        .skip(1)
+        .filter_map(|a| a.parse::<usize>().ok());
+    let side = numbers.next().unwrap_or(500);
+    let steps = numbers.next().unwrap_or(5);
+
+    println!(
+        "{}x{} grid, {} steps, 95% of one strategy",
+        side, side, steps
+    );
+    for mode in [UpdateMode::Legacy, UpdateMode::Synchronous] {
+        let tictoc = time_steps(side, steps, mode, Strategy::TicToc);
+        let deflect = time_steps(side, steps, mode, Strategy::Deflect);
+        println!(
+            "{:<12} TicToc {:>8.1?}/step, Deflect {:>8.1?}/step ({:.1}x)",
+            format!("{:?}:", mode),
+            tictoc,
+            deflect,
+            tictoc.as_secs_f64() / deflect.as_secs_f64()
+        );
+    }
+}
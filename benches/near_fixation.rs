@@ -0,0 +1,63 @@
+//! Time per step on a large grid close to fixation, where few cells change from one step to
+//! the next, so the metric's strategy counts and snapshot only need updating in a few places:
+//! `cargo bench --bench near_fixation -- [side] [steps]`.
+
+use std::time::{Duration, Instant};
+
+use coop::{Environment, EnvironmentBuilder, Placement, Strategy, UpdateMode};
+
+fn build(side: usize, mode: UpdateMode) -> Environment {
+    EnvironmentBuilder::new()
+        .size(side, side)
+        .noise(0.01)
+        .seed(1)
+        .mix(
+            &[(Strategy::Deflect, 0.999), (Strategy::TicToc, 0.001)],
+            Placement::Shuffled,
+        )
+        .memory_window(1)
+        .update_mode(mode)
+        .build()
+        .expect("valid benchmark parameters")
+}
+
+/// Time per step, and the mean number of cells that changed strategy per step.
+fn time_steps(side: usize, steps: usize, mode: UpdateMode) -> (Duration, f64) {
+    let mut env = build(side, mode);
+    // Let the few cooperators die out first.
+    env.run_with(5, drop);
+    let mut previous = env.step().snapshot;
+    let mut changed = 0;
+    let start = Instant::now();
+    env.run_with(steps, |metric| {
+        changed += (metric.snapshot.cells().iter())
+            .zip(previous.cells())
+            .filter(|(a, b)| a != b)
+            .count();
+        previous = metric.snapshot;
+    });
+    (
+        start.elapsed() / steps as u32,
+        changed as f64 / steps as f64,
+    )
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; only the numbers are ours.
+    let mut numbers = std::env::args()
+        .skip(1)
+        .filter_map(|a| a.parse::<usize>().ok());
+    let side = numbers.next().unwrap_or(1000);
+    let steps = numbers.next().unwrap_or(10);
+
+    println!("{}x{} grid, {} steps", side, side, steps);
+    for mode in [UpdateMode::Legacy, UpdateMode::Synchronous] {
+        let (time, changed) = time_steps(side, steps, mode);
+        println!(
+            "{:<12} {:>8.1?}/step, {:.0} cells changed per step",
+            format!("{:?}:", mode),
+            time,
+            changed
+        );
+    }
+}
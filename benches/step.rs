@@ -0,0 +1,56 @@
+//! Time per step for the legacy and synchronous update modes, the latter on one thread and,
+//! with the `parallel` feature, on all of them:
+//! `cargo bench [--features parallel] -- [side] [steps]`.
+
+use std::time::{Duration, Instant};
+
+use coop::{EnvironmentBuilder, Strategy, UpdateMode};
+
+fn time_steps(side: usize, steps: usize, mode: UpdateMode) -> Duration {
+    let mut env = EnvironmentBuilder::new()
+        .size(side, side)
+        .noise(0.1)
+        .seed(1)
+        .strategies(&Strategy::ALL)
+        .memory_window(4)
+        .update_mode(mode)
+        .build()
+        .expect("valid benchmark parameters");
+    let start = Instant::now();
+    env.run_with(steps, drop);
+    start.elapsed() / steps as u32
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; only the numbers are ours.
+    let mut numbers = std::env::args()
+        .skip(1)
+        .filter_map(|a| a.parse::<usize>().ok());
+    let side = numbers.next().unwrap_or(1000);
+    let steps = numbers.next().unwrap_or(3);
+
+    println!("{}x{} grid, {} steps", side, side, steps);
+    let legacy = time_steps(side, steps, UpdateMode::Legacy);
+    println!("legacy:                 {:>8.1?}/step", legacy);
+    #[cfg(not(feature = "parallel"))]
+    {
+        let serial = time_steps(side, steps, UpdateMode::Synchronous);
+        println!("synchronous:            {:>8.1?}/step", serial);
+    }
+    #[cfg(feature = "parallel")]
+    {
+        let single = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .expect("a one-thread pool");
+        let serial = single.install(|| time_steps(side, steps, UpdateMode::Synchronous));
+        println!("synchronous, 1 thread:  {:>8.1?}/step", serial);
+        let parallel = time_steps(side, steps, UpdateMode::Synchronous);
+        println!(
+            "synchronous, {} threads: {:>8.1?}/step ({:.1}x)",
+            rayon::current_num_threads(),
+            parallel,
+            serial.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
+}
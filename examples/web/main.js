@@ -0,0 +1,50 @@
+import init, { WasmSim } from "./pkg/coop.js";
+
+const ROWS = 100;
+const COLS = 100;
+const CELL_PX = 5;
+// Indexed by strategy number: Deflect, TicToc, Coop, Random, Choosy, Trusting, Deceiver, Wary,
+// GenerousTFT, RandomP, MemoryOne (the TUI's classic palette).
+const COLORS = [
+  [205, 49, 49], [229, 229, 16], [13, 188, 121], [188, 63, 188], [17, 168, 205],
+  [35, 209, 139], [214, 112, 214], [36, 114, 200], [245, 245, 67], [241, 76, 76],
+  [41, 184, 219],
+];
+
+await init();
+const sim = new WasmSim(ROWS, COLS, 0.1, 42n);
+
+const canvas = document.getElementById("grid");
+canvas.width = COLS;
+canvas.height = ROWS;
+canvas.style.width = `${COLS * CELL_PX}px`;
+canvas.style.height = `${ROWS * CELL_PX}px`;
+const context = canvas.getContext("2d");
+const image = context.createImageData(COLS, ROWS);
+
+const noise = document.getElementById("noise");
+noise.addEventListener("input", () => sim.setNoise(Number(noise.value)));
+let running = true;
+const pause = document.getElementById("pause");
+pause.addEventListener("click", () => {
+  running = !running;
+  pause.textContent = running ? "Pause" : "Resume";
+});
+
+function draw(cells) {
+  cells.forEach((strategy, i) => {
+    image.data.set([...COLORS[strategy], 255], i * 4);
+  });
+  context.putImageData(image, 0, 0);
+}
+
+function frame() {
+  if (running) {
+    const result = sim.step();
+    draw(result.cells);
+    document.getElementById("metrics").textContent =
+      JSON.stringify(JSON.parse(result.metrics), null, 2);
+  }
+  requestAnimationFrame(frame);
+}
+requestAnimationFrame(frame);
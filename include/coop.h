@@ -0,0 +1,104 @@
+/* C interface to the coop simulator, built with `cargo build --release --features ffi`.
+ * Generated from src/ffi.rs by cbindgen with cbindgen.toml; don't edit by hand. */
+
+#ifndef COOP_H
+#define COOP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * The call succeeded.
+ */
+#define COOP_OK 0
+
+/**
+ * A required pointer was null.
+ */
+#define COOP_ERR_NULL -1
+
+/**
+ * An argument was out of range, e.g. a NaN noise.
+ */
+#define COOP_ERR_INVALID -2
+
+/**
+ * The library panicked; the environment may be left mid-step and should be freed.
+ */
+#define COOP_ERR_PANIC -3
+
+/**
+ * The byte [`coop_env_snapshot`] writes for a cell without an agent.
+ */
+#define COOP_VACANT 255
+
+/**
+ * A simulation owned by the caller, created with [`coop_env_new`] and released with
+ * [`coop_env_free`].
+ */
+typedef struct CoopEnv CoopEnv;
+
+/**
+ * The scalar metrics of one step. The other fields are only meaningful when `status` is
+ * [`COOP_OK`].
+ */
+typedef struct CoopMetricHandle {
+  int32_t status;
+  /**
+   * Zero-based number of the step.
+   */
+  uint64_t step;
+  int32_t coop_actions;
+  /**
+   * Fraction of the step's actions that cooperated, in `[0, 1]`.
+   */
+  float coop_rate;
+  /**
+   * Agents alive after the step.
+   */
+  uint64_t population;
+} CoopMetricHandle;
+
+/**
+ * A seeded simulation of random Deflect and TicToc agents, or null if the grid is empty,
+ * `noise` isn't in `[0, 1]`, or construction panicked.
+ */
+struct CoopEnv *coop_env_new(size_t rows, size_t cols, float noise, uint64_t seed);
+
+/**
+ * Takes one step.
+ *
+ * # Safety
+ * `env` must be null or a live pointer from [`coop_env_new`], not used concurrently.
+ */
+struct CoopMetricHandle coop_env_step(struct CoopEnv *env);
+
+/**
+ * Writes the current strategy number of every cell (see `Strategy::index`) to `out_buf` in
+ * row-major order, or [`COOP_VACANT`] for a cell without an agent, if it has room for
+ * `rows * cols` bytes. Returns the number of cells either way, so a call with a null buffer
+ * asks for the size; returns 0 for a null `env` or a panic.
+ *
+ * # Safety
+ * `env` must be null or a live pointer from [`coop_env_new`]; `out_buf` must be null or
+ * valid for writes of `len` bytes.
+ */
+size_t coop_env_snapshot(const struct CoopEnv *env, uint8_t *out_buf, size_t len);
+
+/**
+ * Sets the noise, clamped to `[0, 1]`.
+ *
+ * # Safety
+ * `env` must be null or a live pointer from [`coop_env_new`], not used concurrently.
+ */
+int32_t coop_env_set_noise(struct CoopEnv *env, float noise);
+
+/**
+ * Releases a simulation; null is ignored.
+ *
+ * # Safety
+ * `env` must be null or a pointer from [`coop_env_new`] that hasn't been freed yet.
+ */
+void coop_env_free(struct CoopEnv *env);
+
+#endif  /* COOP_H */
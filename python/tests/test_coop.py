@@ -0,0 +1,62 @@
+"""Run with `maturin develop && python -m pytest python/tests` from the repository root."""
+
+import pytest
+
+import coop
+
+
+def test_strategies():
+    assert coop.STRATEGIES == ["Deflect", "TicToc", "Coop", "Random", "Choosy",
+                               "Trusting", "Deceiver", "Wary", "GenerousTFT",
+                               "RandomP", "MemoryOne"]
+
+
+def test_step():
+    env = coop.CoopEnv(4, 5, noise=0.1, seed=7)
+    metric = env.step()
+    assert metric["step"] == 0
+    assert 0.0 <= metric["coop_rate"] <= 1.0
+    assert sorted(metric["strategies"]) == sorted(coop.STRATEGIES)
+    assert sum(metric["strategies"].values()) == 20
+    assert env.steps_taken == 1
+
+
+def test_run_is_seeded():
+    first = coop.CoopEnv(6, 6, seed=3).run(10)
+    second = coop.CoopEnv(6, 6, seed=3).run(10)
+    assert len(first) == 10
+    assert [m["step"] for m in first] == list(range(10))
+    assert first == second
+
+
+def test_snapshot():
+    env = coop.CoopEnv(3, 4, seed=1, strategy_mix={"coop": 1.0})
+    assert env.shape == (3, 4)
+    assert env.snapshot() == [[coop.STRATEGIES.index("Coop")] * 4] * 3
+    assert coop.VACANT >= len(coop.STRATEGIES)
+
+
+def test_strategy_mix():
+    env = coop.CoopEnv(10, 10, seed=5, strategy_mix={"TicToc": 1, "Deflect": 1})
+    cells = [s for row in env.snapshot() for s in row]
+    assert set(cells) == {0, 1}
+
+
+def test_noise():
+    env = coop.CoopEnv(2, 2)
+    env.noise = 2.0
+    assert env.noise == 1.0
+
+
+@pytest.mark.parametrize(
+    "kwargs",
+    [
+        {"rows": 0, "cols": 3},
+        {"rows": 3, "cols": 3, "noise": 1.5},
+        {"rows": 3, "cols": 3, "strategy_mix": {"pavlov": 1.0}},
+        {"rows": 3, "cols": 3, "strategy_mix": {"coop": -1.0}},
+    ],
+)
+def test_invalid_arguments(kwargs):
+    with pytest.raises(ValueError):
+        coop.CoopEnv(**kwargs)
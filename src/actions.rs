@@ -0,0 +1,302 @@
+//! Action histories packed one bit per action, set for cooperation, so an agent's memory of
+//! each neighbor takes an eighth of a byte per game.
+//!
+//! [`ActionBits`] owns a history; [`Actions`] is a cheap view of a run of one, which is what
+//! [`Agent::history_with`](crate::Agent::history_with) and
+//! [`ActionContext`](crate::ActionContext) hand out.
+
+use std::fmt;
+
+use crate::agent::Action;
+
+const WORD: usize = u64::BITS as usize;
+
+/// A growable sequence of actions, oldest first, packed into 64-bit words.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ActionBits {
+    /// Action `i` is bit `i % 64` of word `i / 64`; bits past `len` are clear.
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl ActionBits {
+    pub fn new() -> ActionBits {
+        ActionBits::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, action: Action) {
+        if self.len.is_multiple_of(WORD) {
+            self.words.push(0);
+        }
+        if action == Action::Coop {
+            self.words[self.len / WORD] |= 1 << (self.len % WORD);
+        }
+        self.len += 1;
+    }
+
+    /// Drops all but the last `n` actions.
+    pub fn keep_last(&mut self, n: usize) {
+        let Some(dropped) = self.len.checked_sub(n).filter(|&d| d > 0) else {
+            return;
+        };
+        let words = n.div_ceil(WORD);
+        for i in 0..words {
+            self.words[i] = bits_from(&self.words, dropped + i * WORD);
+        }
+        self.words.truncate(words);
+        if let Some(last) = self.words.last_mut() {
+            *last &= low_bits(n - (words - 1) * WORD);
+        }
+        self.len = n;
+    }
+
+    /// A view of every action.
+    pub fn actions(&self) -> Actions<'_> {
+        Actions {
+            words: &self.words,
+            start: 0,
+            len: self.len,
+        }
+    }
+
+    /// Bytes of heap memory held.
+    pub fn heap_bytes(&self) -> usize {
+        self.words.capacity() * std::mem::size_of::<u64>()
+    }
+}
+
+impl FromIterator<Action> for ActionBits {
+    fn from_iter<I: IntoIterator<Item = Action>>(actions: I) -> ActionBits {
+        let mut bits = ActionBits::new();
+        actions.into_iter().for_each(|a| bits.push(a));
+        bits
+    }
+}
+
+impl From<&[Action]> for ActionBits {
+    fn from(actions: &[Action]) -> ActionBits {
+        actions.iter().copied().collect()
+    }
+}
+
+impl fmt::Debug for ActionBits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.actions().fmt(f)
+    }
+}
+
+/// The 64 bits of `words` starting at bit `at`, zero past the end.
+fn bits_from(words: &[u64], at: usize) -> u64 {
+    let (i, offset) = (at / WORD, at % WORD);
+    let low = words.get(i).map_or(0, |w| w >> offset);
+    match words.get(i + 1) {
+        Some(next) if offset > 0 => low | next << (WORD - offset),
+        _ => low,
+    }
+}
+
+/// A word with its lowest `n` bits set, for `n` up to 64.
+fn low_bits(n: usize) -> u64 {
+    if n >= WORD {
+        u64::MAX
+    } else {
+        (1 << n) - 1
+    }
+}
+
+/// A run of actions in an [`ActionBits`], oldest first; the packed counterpart of
+/// `&[Action]`, and as cheap to copy.
+#[derive(Clone, Copy, Default)]
+pub struct Actions<'a> {
+    words: &'a [u64],
+    /// Bit of `words` holding the first action.
+    start: usize,
+    len: usize,
+}
+
+impl<'a> Actions<'a> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The `i`-th action, oldest first.
+    pub fn get(&self, i: usize) -> Option<Action> {
+        (i < self.len).then(|| {
+            let bit = self.start + i;
+            if self.words[bit / WORD] >> (bit % WORD) & 1 == 1 {
+                Action::Coop
+            } else {
+                Action::Deflect
+            }
+        })
+    }
+
+    /// The most recent action.
+    pub fn last(&self) -> Option<Action> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    /// Every action, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Action> + ExactSizeIterator + 'a {
+        let actions = *self;
+        (0..self.len).map(move |i| actions.get(i).unwrap_or(Action::Coop))
+    }
+
+    /// The last `n` actions, or all of them if there are fewer.
+    pub fn last_n(&self, n: usize) -> Actions<'a> {
+        let n = n.min(self.len);
+        Actions {
+            start: self.start + self.len - n,
+            len: n,
+            ..*self
+        }
+    }
+
+    /// How many of the last `k` actions were cooperative, a word at a time.
+    pub fn count_coop_in_last(&self, k: usize) -> usize {
+        let recent = self.last_n(k);
+        let mut count = 0;
+        let mut at = recent.start;
+        let mut left = recent.len;
+        while left > 0 {
+            let n = left.min(WORD);
+            count += (bits_from(self.words, at) & low_bits(n)).count_ones() as usize;
+            at += n;
+            left -= n;
+        }
+        count
+    }
+
+    pub fn to_vec(&self) -> Vec<Action> {
+        self.iter().collect()
+    }
+}
+
+impl fmt::Debug for Actions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for Actions<'_> {
+    fn eq(&self, other: &Actions<'_>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Actions<'_> {}
+
+impl PartialEq<[Action]> for Actions<'_> {
+    fn eq(&self, other: &[Action]) -> bool {
+        self.len == other.len() && self.iter().eq(other.iter().copied())
+    }
+}
+
+impl PartialEq<&[Action]> for Actions<'_> {
+    fn eq(&self, other: &&[Action]) -> bool {
+        *self == **other
+    }
+}
+
+impl<const N: usize> PartialEq<[Action; N]> for Actions<'_> {
+    fn eq(&self, other: &[Action; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<const N: usize> PartialEq<&[Action; N]> for Actions<'_> {
+    fn eq(&self, other: &&[Action; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl PartialEq<Vec<Action>> for Actions<'_> {
+    fn eq(&self, other: &Vec<Action>) -> bool {
+        *self == other[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use Action::{Coop as C, Deflect as D};
+
+    #[test]
+    fn test_accessors() {
+        let bits = ActionBits::from(&[C, D, D][..]);
+        let actions = bits.actions();
+        assert_eq!((actions.len(), actions.is_empty()), (3, false));
+        assert_eq!(
+            (actions.get(0), actions.get(2), actions.get(3)),
+            (Some(C), Some(D), None)
+        );
+        assert_eq!(actions.last(), Some(D));
+        assert_eq!(actions.iter().rev().collect::<Vec<_>>(), [D, D, C]);
+        assert_eq!(actions.last_n(2), [D, D]);
+        assert_eq!(actions.last_n(9), [C, D, D]);
+        assert_eq!(actions.count_coop_in_last(3), 1);
+        assert_eq!(actions.count_coop_in_last(2), 0);
+        assert_eq!(format!("{:?}", actions), "[Coop, Deflect, Deflect]");
+        let empty = Actions::default();
+        assert!(empty.is_empty() && empty.last().is_none());
+        assert_eq!(empty.count_coop_in_last(4), 0);
+        assert_eq!(empty, ActionBits::new().actions());
+    }
+
+    /// Random pushes and trims, long enough to cross word boundaries, checked against a plain
+    /// `Vec<Action>` after every operation.
+    #[test]
+    fn test_matches_a_vec() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..50 {
+            let (mut bits, mut plain) = (ActionBits::new(), Vec::new());
+            for _ in 0..400 {
+                if rng.gen_bool(0.05) {
+                    let n = rng.gen_range(0..=plain.len() + 2);
+                    bits.keep_last(n);
+                    plain.drain(..plain.len().saturating_sub(n));
+                } else {
+                    let action = if rng.gen_bool(0.5) { C } else { D };
+                    bits.push(action);
+                    plain.push(action);
+                }
+                let actions = bits.actions();
+                assert_eq!(actions, plain);
+                assert_eq!(actions.last(), plain.last().copied());
+                let k = rng.gen_range(0..150);
+                let tail = &plain[plain.len().saturating_sub(k)..];
+                assert_eq!(actions.last_n(k), tail);
+                let coop = tail.iter().filter(|&&a| a == C).count();
+                assert_eq!(actions.count_coop_in_last(k), coop);
+            }
+            // Trimming leaves no stray bits behind, so equal histories are equal bit for bit.
+            assert_eq!(bits, ActionBits::from(&plain[..]));
+        }
+    }
+
+    #[test]
+    fn test_an_eighth_of_a_byte_per_action() {
+        let bits: ActionBits = (0..6400).map(|i| if i % 3 == 0 { C } else { D }).collect();
+        assert!(
+            bits.heap_bytes() <= 6400 / 8 * 2,
+            "{} bytes",
+            bits.heap_bytes()
+        );
+        assert_eq!(bits.actions().count_coop_in_last(6400), 2134);
+    }
+}
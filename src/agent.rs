@@ -1,9 +1,10 @@
-use std::{
-    collections::{HashMap},
-    usize,
-};
+use std::{collections::HashMap, fmt, str::FromStr};
 
-use rand::{seq::SliceRandom, thread_rng, Rng};
+use rand::{seq::SliceRandom, thread_rng, Rng, RngCore};
+
+use crate::actions::{ActionBits, Actions};
+use crate::env::Payoffs;
+use crate::error::Error;
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum Action {
@@ -12,8 +13,7 @@ pub enum Action {
 }
 
 impl Action {
-    pub(crate) fn with_noise(&self, prob: f32) -> Action {
-        let mut rng = thread_rng();
+    pub(crate) fn with_noise<R: Rng + ?Sized>(&self, prob: f32, rng: &mut R) -> Action {
         if rng.gen::<f32>() < prob {
             match *self {
                 Action::Coop => Action::Deflect,
@@ -25,115 +25,1217 @@ impl Action {
     }
 }
 
+/// A probability stored in millionths, so strategies that carry one stay `Eq`, `Ord` and
+/// `Hash`.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash, PartialOrd, Ord)]
+pub struct Prob(u32);
+
+impl Prob {
+    const SCALE: f64 = 1_000_000.0;
+
+    /// `p` rounded to the nearest millionth and clipped to `[0, 1]`; NaN becomes 0.
+    pub fn new(p: f64) -> Prob {
+        let p = if p.is_nan() { 0.0 } else { p.clamp(0.0, 1.0) };
+        Prob((p * Prob::SCALE).round() as u32)
+    }
+
+    pub const fn from_millionths(millionths: u32) -> Prob {
+        Prob(if millionths > 1_000_000 {
+            1_000_000
+        } else {
+            millionths
+        })
+    }
+
+    pub fn get(self) -> f64 {
+        self.0 as f64 / Prob::SCALE
+    }
+
+    /// `true` with this probability.
+    fn draw<R: Rng + ?Sized>(self, rng: &mut R) -> bool {
+        rng.gen::<f64>() < self.get()
+    }
+
+    /// This probability moved by Gaussian noise with standard deviation `sigma`, clipped.
+    fn mutate<R: Rng + ?Sized>(self, rng: &mut R, sigma: f64) -> Prob {
+        Prob::new(self.get() + sigma * gaussian(rng))
+    }
+}
+
+/// A standard normal draw, by the Box-Muller transform.
+fn gaussian<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let radius = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+    radius * (std::f64::consts::TAU * rng.gen::<f64>()).cos()
+}
+
+/// A way of playing. Most strategies are fixed rules; the parameterized ones carry
+/// probabilities that imitation can mutate (see [`Strategy::mutate`]), and count as one
+/// strategy whatever their parameters (see [`Strategy::kind`]).
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Hash, PartialOrd, Ord)]
 pub enum Strategy {
     Deflect,
     TicToc,
     Coop,
     Random,
+    /// TicToc that refuses to play anyone who defected in at least two of their last three
+    /// moves against it, when the environment allows partner choice.
+    Choosy,
+    /// Cooperates with whoever signaled they'd cooperate, under cheap talk; with anyone
+    /// otherwise.
+    Trusting,
+    /// Signals that it will cooperate, then defects.
+    Deceiver,
+    /// Trusting that also remembers: defects against anyone who signaled defection or defected
+    /// in their last move against it, like TicToc.
+    Wary,
+    /// TicToc that forgives a defection, cooperating anyway, with this probability.
+    GenerousTFT(Prob),
+    /// Cooperates with this probability.
+    RandomP(Prob),
+    /// Cooperates with a probability that depends on both sides' last moves: after mutual
+    /// cooperation, after being exploited, after exploiting and after mutual defection, in
+    /// that order. Opens by cooperating.
+    MemoryOne([Prob; 4]),
 }
 
 impl Strategy {
-    pub fn get_action(self: &Self, history: &Vec<Action>) -> Action {
-        let mut rand = thread_rng();
+    /// Every strategy, in declaration (and display) order. Parameterized strategies appear
+    /// with their default parameters: a forgiveness of 1/3, even odds, and win-stay lose-shift.
+    pub const ALL: [Strategy; 11] = [
+        Strategy::Deflect,
+        Strategy::TicToc,
+        Strategy::Coop,
+        Strategy::Random,
+        Strategy::Choosy,
+        Strategy::Trusting,
+        Strategy::Deceiver,
+        Strategy::Wary,
+        Strategy::GenerousTFT(Prob::from_millionths(333_333)),
+        Strategy::RandomP(Prob::from_millionths(500_000)),
+        Strategy::MemoryOne([
+            Prob::from_millionths(1_000_000),
+            Prob::from_millionths(0),
+            Prob::from_millionths(0),
+            Prob::from_millionths(1_000_000),
+        ]),
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Deflect => "Deflect",
+            Strategy::TicToc => "TicToc",
+            Strategy::Coop => "Coop",
+            Strategy::Random => "Random",
+            Strategy::Choosy => "Choosy",
+            Strategy::Trusting => "Trusting",
+            Strategy::Deceiver => "Deceiver",
+            Strategy::Wary => "Wary",
+            Strategy::GenerousTFT(_) => "GenerousTFT",
+            Strategy::RandomP(_) => "RandomP",
+            Strategy::MemoryOne(_) => "MemoryOne",
+        }
+    }
+
+    /// A small number identifying the strategy in compact encodings, e.g. snapshots handed to
+    /// other languages. Numbers are never reused, so encoded data stays readable across
+    /// versions; they match the order of `ALL`.
+    pub fn index(self) -> u8 {
+        match self {
+            Strategy::Deflect => 0,
+            Strategy::TicToc => 1,
+            Strategy::Coop => 2,
+            Strategy::Random => 3,
+            Strategy::Choosy => 4,
+            Strategy::Trusting => 5,
+            Strategy::Deceiver => 6,
+            Strategy::Wary => 7,
+            Strategy::GenerousTFT(_) => 8,
+            Strategy::RandomP(_) => 9,
+            Strategy::MemoryOne(_) => 10,
+        }
+    }
+
+    /// The strategy numbered `index`, see [`Strategy::index`]; parameterized strategies come
+    /// with their default parameters.
+    pub fn from_index(index: u8) -> Option<Strategy> {
+        Strategy::ALL.into_iter().find(|s| s.index() == index)
+    }
+
+    /// The strategy with its default parameters, which metrics count it under; the strategy
+    /// itself if it has none.
+    pub fn kind(self) -> Strategy {
+        Strategy::ALL[self.index() as usize]
+    }
+
+    /// The strategy's parameters by name, in a fixed order; empty for fixed rules.
+    pub fn params(&self) -> Vec<(&'static str, f64)> {
         match *self {
-            Strategy::Deflect => Action::Deflect,
-            Strategy::TicToc => history.last().unwrap_or(&Action::Coop).to_owned(),
+            Strategy::GenerousTFT(forgiveness) => vec![("forgiveness", forgiveness.get())],
+            Strategy::RandomP(p) => vec![("p", p.get())],
+            Strategy::MemoryOne(q) => ["cc", "cd", "dc", "dd"]
+                .into_iter()
+                .zip(q.map(Prob::get))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The same strategy with every parameter moved by independent Gaussian noise with
+    /// standard deviation `sigma` and clipped back to `[0, 1]`. Fixed rules, and any strategy
+    /// at a `sigma` of 0, come back unchanged without drawing from `rng`.
+    pub fn mutate<R: Rng + ?Sized>(&self, rng: &mut R, sigma: f64) -> Strategy {
+        if sigma <= 0.0 {
+            return *self;
+        }
+        match *self {
+            Strategy::GenerousTFT(forgiveness) => {
+                Strategy::GenerousTFT(forgiveness.mutate(rng, sigma))
+            }
+            Strategy::RandomP(p) => Strategy::RandomP(p.mutate(rng, sigma)),
+            Strategy::MemoryOne(q) => Strategy::MemoryOne(q.map(|p| p.mutate(rng, sigma))),
+            strategy => strategy,
+        }
+    }
+
+    /// Short names accepted by `from_str` besides the full ones.
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Strategy::Deflect => &["d", "defect"],
+            Strategy::TicToc => &["t", "tft"],
+            Strategy::Coop => &["c"],
+            Strategy::Random => &["r"],
+            Strategy::Choosy => &["ch"],
+            Strategy::Trusting => &["tr"],
+            Strategy::Deceiver => &["de", "liar"],
+            Strategy::Wary => &["w"],
+            Strategy::GenerousTFT(_) => &["gtft", "generous"],
+            Strategy::RandomP(_) => &["rp"],
+            Strategy::MemoryOne(_) => &["m1", "wsls"],
+        }
+    }
+
+    /// The action against the opponent in `ctx`, given everything known about it.
+    pub fn get_action(&self, ctx: &mut ActionContext<'_>) -> Action {
+        let last = ctx.history.last().unwrap_or(Action::Coop);
+        let signal = ctx.signal.unwrap_or(Action::Coop);
+        let rng = &mut *ctx.rng;
+        match *self {
+            Strategy::Deflect | Strategy::Deceiver => Action::Deflect,
+            Strategy::TicToc | Strategy::Choosy => last,
             Strategy::Coop => Action::Coop,
-            Strategy::Random => [Action::Coop, Action::Deflect]
-                .choose(&mut rand)
-                .unwrap()
-                .to_owned(),
+            Strategy::Random => *[Action::Coop, Action::Deflect]
+                .choose(rng)
+                .unwrap_or(&Action::Coop),
+            Strategy::Trusting => signal,
+            Strategy::Wary if last == Action::Coop => signal,
+            Strategy::Wary => Action::Deflect,
+            Strategy::GenerousTFT(forgiveness) => match last {
+                Action::Deflect if !forgiveness.draw(rng) => Action::Deflect,
+                _ => Action::Coop,
+            },
+            Strategy::RandomP(p) => cooperate_if(p.draw(rng)),
+            Strategy::MemoryOne(q) => match (ctx.own_history.last(), ctx.history.last()) {
+                (Some(mine), Some(theirs)) => {
+                    let after = match (mine, theirs) {
+                        (Action::Coop, Action::Coop) => q[0],
+                        (Action::Coop, Action::Deflect) => q[1],
+                        (Action::Deflect, Action::Coop) => q[2],
+                        (Action::Deflect, Action::Deflect) => q[3],
+                    };
+                    cooperate_if(after.draw(rng))
+                }
+                _ => Action::Coop,
+            },
+        }
+    }
+
+    /// The action this strategy plays whatever it knows, if it always plays the same one.
+    pub fn fixed_action(&self) -> Option<Action> {
+        match *self {
+            Strategy::Deflect | Strategy::Deceiver => Some(Action::Deflect),
+            Strategy::Coop => Some(Action::Coop),
+            _ => None,
         }
     }
+
+    /// The intent an agent playing this strategy announces to all its neighbors before a
+    /// round, under cheap talk. Signals are not binding: only Deflect admits to defecting,
+    /// Random announces cooperation it keeps half the time, and Deceiver lies.
+    pub fn signal(&self) -> Action {
+        match *self {
+            Strategy::Deflect => Action::Deflect,
+            Strategy::TicToc
+            | Strategy::Coop
+            | Strategy::Random
+            | Strategy::Choosy
+            | Strategy::Trusting
+            | Strategy::Deceiver
+            | Strategy::Wary
+            | Strategy::GenerousTFT(_)
+            | Strategy::RandomP(_)
+            | Strategy::MemoryOne(_) => Action::Coop,
+        }
+    }
+
+    /// Whether an agent playing this strategy refuses to play an opponent that played
+    /// `history` against it, when partner choice is on.
+    pub fn refuses(&self, history: Actions<'_>) -> bool {
+        match *self {
+            Strategy::Choosy => {
+                let recent = history.last_n(3);
+                recent.len() - recent.count_coop_in_last(3) >= 2
+            }
+            Strategy::Deflect
+            | Strategy::TicToc
+            | Strategy::Coop
+            | Strategy::Random
+            | Strategy::Trusting
+            | Strategy::Deceiver
+            | Strategy::Wary
+            | Strategy::GenerousTFT(_)
+            | Strategy::RandomP(_)
+            | Strategy::MemoryOne(_) => false,
+        }
+    }
+}
+
+fn cooperate_if(cooperate: bool) -> Action {
+    if cooperate {
+        Action::Coop
+    } else {
+        Action::Deflect
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A strategy name that didn't match any strategy or alias.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseStrategyError(pub String);
+
+impl fmt::Display for ParseStrategyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<String> = Strategy::ALL
+            .iter()
+            .map(|s| format!("{} ({})", s.name().to_lowercase(), s.aliases().join(", ")))
+            .collect();
+        write!(
+            f,
+            "unknown strategy {:?}, expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ParseStrategyError {}
+
+impl FromStr for Strategy {
+    type Err = ParseStrategyError;
+
+    /// Case-insensitive full names or aliases, e.g. "TicToc", "tictoc" or "tft".
+    fn from_str(text: &str) -> Result<Strategy, ParseStrategyError> {
+        let lower = text.trim().to_lowercase();
+        Strategy::ALL
+            .into_iter()
+            .find(|s| s.name().to_lowercase() == lower || s.aliases().contains(&lower.as_str()))
+            .ok_or_else(|| ParseStrategyError(text.to_string()))
+    }
 }
 
 pub type Coord = (usize, usize);
 
+/// What an agent knows when choosing how to play an opponent; see [`Strategy::get_action`].
+pub struct ActionContext<'a> {
+    pub opponent: Coord,
+    /// What the opponent played against the agent after noise, oldest first.
+    pub history: Actions<'a>,
+    /// What the agent played against the opponent after noise, oldest first.
+    pub own_history: Actions<'a>,
+    /// The last game with the opponent, if one is remembered.
+    pub last_outcome: Option<&'a InteractionOutcome>,
+    /// What the opponent signaled it would play this round, under cheap talk.
+    pub signal: Option<Action>,
+    /// Zero-based step, or round of a tournament match, being played.
+    pub step: usize,
+    /// The matrix the agent is scored with.
+    pub payoffs: &'a Payoffs,
+    /// Source of every random choice, so seeded runs stay reproducible.
+    pub rng: &'a mut dyn RngCore,
+}
+
+impl<'a> ActionContext<'a> {
+    /// A first meeting with `opponent` in step 0: no history, no signal.
+    pub fn new(opponent: Coord, payoffs: &'a Payoffs, rng: &'a mut dyn RngCore) -> Self {
+        ActionContext {
+            opponent,
+            history: Actions::default(),
+            own_history: Actions::default(),
+            last_outcome: None,
+            signal: None,
+            step: 0,
+            payoffs,
+            rng,
+        }
+    }
+}
+
+impl fmt::Debug for ActionContext<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ActionContext")
+            .field("opponent", &self.opponent)
+            .field("history", &self.history)
+            .field("own_history", &self.own_history)
+            .field("last_outcome", &self.last_outcome)
+            .field("signal", &self.signal)
+            .field("step", &self.step)
+            .field("payoffs", &self.payoffs)
+            .finish_non_exhaustive()
+    }
+}
+
+/// One game between an agent and a neighbor, as the agent saw it. Both sides of a pair are
+/// told the same realized actions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InteractionOutcome {
+    pub opponent: Coord,
+    /// What the agent chose to play, before noise.
+    pub intended: Action,
+    /// What the agent played after noise, as the opponent saw it.
+    pub realized: Action,
+    /// What the opponent played after noise.
+    pub their_action: Action,
+    /// What the game paid the agent, before its stake is applied.
+    pub payoff: f64,
+    /// Zero-based step the game was played in.
+    pub step: usize,
+}
+
+#[cfg(test)]
+impl InteractionOutcome {
+    /// A game in step 0 in which the agent cooperated as intended and `opponent` played
+    /// `their_action`.
+    pub(crate) fn against(opponent: Coord, their_action: Action, payoff: f64) -> Self {
+        InteractionOutcome {
+            opponent,
+            intended: Action::Coop,
+            realized: Action::Coop,
+            their_action,
+            payoff,
+            step: 0,
+        }
+    }
+}
+
+/// What agents compare when deciding whom to imitate.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum Imitation {
+    /// Scores as earned, so agents with larger stakes look more successful whatever they play.
+    #[default]
+    Score,
+    /// Scores divided by stakes, which compares how well strategies did rather than how much
+    /// their players had at stake.
+    PerStake,
+}
+
+/// An agent's role in an asymmetric game, fixed for life. Under an
+/// [`Asymmetric`](crate::Asymmetric) game agents only play the other class and only imitate
+/// their own; otherwise the class is ignored.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default, Hash, PartialOrd, Ord)]
+pub enum Class {
+    #[default]
+    Host,
+    Visitor,
+}
+
+impl Class {
+    pub const ALL: [Class; 2] = [Class::Host, Class::Visitor];
+
+    /// Position in `ALL`.
+    pub fn index(self) -> usize {
+        match self {
+            Class::Host => 0,
+            Class::Visitor => 1,
+        }
+    }
+
+    /// Hosts on cells whose row and column add up to an even number, visitors on the rest, so
+    /// every agent's side neighbors are of the other class.
+    pub fn checkerboard((row, col): Coord) -> Class {
+        if (row + col).is_multiple_of(2) {
+            Class::Host
+        } else {
+            Class::Visitor
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Agent {
     pub coord: Coord,
-    history: HashMap<Coord, Vec<Action>>,
+    /// Unique within an environment, which numbers its founding agents in row-major order
+    /// from 0. Imitating a neighbor changes an agent's strategy, not its identity.
+    pub id: u64,
+    /// The agent this one last imitated, or `None` while it still plays its own strategy.
+    pub parent_id: Option<u64>,
+    /// The founding agent whose strategy this one carries, handed down by imitation.
+    pub founder_id: u64,
+    /// What happened in the games with each opponent.
+    history: HashMap<Coord, Exchanges>,
+    memory_window: Option<usize>,
     pub strategy: Strategy,
-    pub score: f32,
+    /// Role in an asymmetric game; never changes, not even by imitation.
+    pub class: Class,
+    pub score: f64,
+    /// Multiplies every payoff this agent receives, modeling differences in wealth or
+    /// ability; 1 by default. Imitation copies strategies, not stakes.
+    pub stake: f32,
+    /// Reserve that payoffs fill and living costs drain, when the environment has an energy
+    /// budget; see [`EnergyConfig`](crate::EnergyConfig).
+    pub energy: f64,
+    /// Games recorded, one per [`Agent::record_outcome`]; with `score`, what per-game
+    /// comparisons divide by.
+    pub interactions: u64,
+    /// Steps this agent has lived through, counting the one it was born in. Imitation keeps
+    /// it, since the agent stays the same.
+    pub age: usize,
+}
+
+/// The games an agent played with one opponent. With a memory window of `k` each list holds
+/// at most `2k` entries and is cut back to the last `k` when it fills, so trimming is amortized.
+#[derive(Clone, Debug)]
+struct Exchanges {
+    /// Actions the opponent played, after noise.
+    received: ActionBits,
+    /// Actions the agent played, after noise.
+    played: ActionBits,
+    last: InteractionOutcome,
+    /// Everything earned in these games, times the stake; never trimmed.
+    payoff: f64,
+}
+
+impl Exchanges {
+    /// Cuts both lists back to their last `window` entries.
+    fn trim(&mut self, window: usize) {
+        for actions in [&mut self.received, &mut self.played] {
+            actions.keep_last(window);
+        }
+    }
+}
+
+/// The strategy and lineage an imitated agent hands to its imitator, copied out so a grid can
+/// be updated from a frozen view of itself.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Inheritance {
+    strategy: Strategy,
+    parent_id: u64,
+    founder_id: u64,
+}
+
+impl Inheritance {
+    /// The same inheritance with its strategy's parameters mutated; see [`Strategy::mutate`].
+    pub(crate) fn mutated<R: Rng + ?Sized>(self, rng: &mut R, sigma: f64) -> Inheritance {
+        Inheritance {
+            strategy: self.strategy.mutate(rng, sigma),
+            ..self
+        }
+    }
 }
 
 impl Agent {
-    pub fn adapt(self: &mut Self, neighbors: Vec<&Agent>) {
-        let best_neighbor = neighbors
+    /// Switches to the strategy of the best scoring neighbor if it beat this agent. A NaN score
+    /// counts as the worst possible, so such neighbors are never copied and such agents always
+    /// switch.
+    pub fn adapt(&mut self, neighbors: Vec<&Agent>) {
+        self.adapt_by(neighbors, Imitation::Score);
+    }
+
+    /// `adapt`, comparing what `imitation` says instead of raw scores.
+    pub fn adapt_by(&mut self, neighbors: Vec<&Agent>, imitation: Imitation) {
+        if let Some(model) = self.imitated(neighbors, imitation) {
+            self.inherit(model.inheritance());
+        }
+    }
+
+    /// The neighbor `adapt_by` would imitate, if any.
+    pub(crate) fn imitated<'a>(
+        &self,
+        neighbors: impl IntoIterator<Item = &'a Agent>,
+        imitation: Imitation,
+    ) -> Option<&'a Agent> {
+        let mine = self.merit(imitation);
+        neighbors
             .into_iter()
-            .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
+            .map(|n| (n, n.merit(imitation)))
+            .filter(|(_, merit)| !merit.is_nan())
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|(_, merit)| mine.is_nan() || *merit > mine)
+            .map(|(n, _)| n)
+    }
+
+    /// What imitation compares this agent by.
+    fn merit(&self, imitation: Imitation) -> f64 {
+        match imitation {
+            Imitation::Score => self.score,
+            Imitation::PerStake => self.score_per_stake(),
+        }
+    }
+
+    /// The score divided by the stake: what the agent would have earned at a stake of 1.
+    pub fn score_per_stake(&self) -> f64 {
+        self.score / self.stake as f64
+    }
 
-        if let Some(n) = best_neighbor {
-            if n.score > self.score {
-                self.strategy = n.strategy;
+    /// The score divided by the games played, so agents with more neighbors don't come out
+    /// ahead just for playing more; 0 before the first game.
+    pub fn score_per_interaction(&self) -> f64 {
+        match self.interactions {
+            0 => 0.0,
+            n => self.score / n as f64,
+        }
+    }
+
+    /// The score divided by the age, so agents born late aren't behind just for having
+    /// lived less; 0 before the first step.
+    pub fn score_per_step(&self) -> f64 {
+        match self.age {
+            0 => 0.0,
+            n => self.score / n as f64,
+        }
+    }
+
+    /// What an agent imitating this one takes over.
+    pub(crate) fn inheritance(&self) -> Inheritance {
+        Inheritance {
+            strategy: self.strategy,
+            parent_id: self.id,
+            founder_id: self.founder_id,
+        }
+    }
+
+    /// Takes over `from`; returns whether that changed anything.
+    pub(crate) fn inherit(&mut self, from: Inheritance) -> bool {
+        let changed = self.strategy != from.strategy
+            || self.parent_id != Some(from.parent_id)
+            || self.founder_id != from.founder_id;
+        self.strategy = from.strategy;
+        self.parent_id = Some(from.parent_id);
+        self.founder_id = from.founder_id;
+        changed
+    }
+
+    /// What this agent knows going into a game with `opponent` in `step`, scored with
+    /// `payoffs`; the signal is left unset.
+    pub fn context<'a>(
+        &'a self,
+        opponent: Coord,
+        step: usize,
+        payoffs: &'a Payoffs,
+        rng: &'a mut dyn RngCore,
+    ) -> ActionContext<'a> {
+        ActionContext {
+            history: self.history_with(opponent),
+            own_history: self.own_history_with(opponent),
+            last_outcome: self.last_outcome_with(opponent),
+            step,
+            ..ActionContext::new(opponent, payoffs, rng)
+        }
+    }
+
+    /// Whether this agent refuses to play `agent`, given what `agent` has played against it.
+    pub fn refuses(&self, agent: &Agent) -> bool {
+        self.strategy.refuses(self.history_with(agent.coord))
+    }
+
+    /// Remembers the game in `outcome` and earns its payoff times the stake.
+    pub fn record_outcome(&mut self, outcome: InteractionOutcome) {
+        let exchanges = self
+            .history
+            .entry(outcome.opponent)
+            .or_insert_with(|| Exchanges {
+                received: ActionBits::new(),
+                played: ActionBits::new(),
+                last: outcome,
+                payoff: 0.0,
+            });
+        exchanges.received.push(outcome.their_action);
+        exchanges.played.push(outcome.realized);
+        exchanges.last = outcome;
+        exchanges.payoff += outcome.payoff * self.stake as f64;
+        self.interactions += 1;
+        if let Some(window) = self.memory_window {
+            if exchanges.received.len() >= 2 * window {
+                exchanges.trim(window);
             }
         }
+        self.score = self.score * 1.0 + outcome.payoff * self.stake as f64;
     }
 
-    pub fn get_action(self: &Self, agent: &Agent) -> Action {
-        let empty: Vec<Action> = vec![];
-        let history: &Vec<Action> = self.history.get(&agent.coord).unwrap_or(&empty);
-        self.strategy.get_action(history)
+    /// What this agent earned, times the stake, in all its games with `opponent`; 0 if it
+    /// has no recorded history with it.
+    pub fn payoff_from(&self, opponent: Coord) -> f64 {
+        self.history.get(&opponent).map_or(0.0, |e| e.payoff)
     }
 
-    pub fn score(self: &mut Self, agnet: &Agent, other_action: Action, score: f32) {
-        if let Some(history) = self.history.get_mut(&agnet.coord) {
-            history.push(other_action);
-        } else {
-            self.history.insert(agnet.coord, vec![other_action]);
+    /// [`Agent::payoff_from`] for every opponent with a recorded history, in no particular
+    /// order: at most one entry per neighbor. The payoffs add up to the score, less outside
+    /// options and whatever was earned from opponents since forgotten.
+    pub fn payoff_breakdown(&self) -> impl Iterator<Item = (Coord, f64)> + '_ {
+        self.history.iter().map(|(&coord, e)| (coord, e.payoff))
+    }
+
+    /// The most recent game with `opponent`, if any is remembered. Games a frozen run only
+    /// scores aren't recorded, so this stays at the last step played in full; see
+    /// [`Environment::step`](crate::Environment::step).
+    pub fn last_outcome_with(&self, opponent: Coord) -> Option<&InteractionOutcome> {
+        self.history.get(&opponent).map(|e| &e.last)
+    }
+
+    /// Actions `opponent` has played against this agent, oldest first; only the last
+    /// `memory_window` of them when a window is set. Stops growing while the run is frozen;
+    /// see [`Environment::step`](crate::Environment::step).
+    pub fn history_with(&self, opponent: Coord) -> Actions<'_> {
+        self.windowed(self.history.get(&opponent).map(|e| &e.received))
+    }
+
+    /// Actions this agent has played against `opponent`, after noise, oldest first; only the
+    /// last `memory_window` of them when a window is set.
+    pub fn own_history_with(&self, opponent: Coord) -> Actions<'_> {
+        self.windowed(self.history.get(&opponent).map(|e| &e.played))
+    }
+
+    fn windowed<'a>(&self, actions: Option<&'a ActionBits>) -> Actions<'a> {
+        let actions = actions.map(ActionBits::actions).unwrap_or_default();
+        match self.memory_window {
+            Some(window) => actions.last_n(window),
+            None => actions,
+        }
+    }
+
+    /// The most recent action `opponent` played against this agent.
+    pub fn last_action_from(&self, opponent: Coord) -> Option<Action> {
+        self.history_with(opponent).last()
+    }
+
+    /// Every opponent this agent has a recorded history with, in no particular order.
+    pub fn opponents(&self) -> impl Iterator<Item = Coord> + '_ {
+        self.history.keys().copied()
+    }
+
+    pub fn memory_window(&self) -> Option<usize> {
+        self.memory_window
+    }
+
+    /// Remembers only the last `window` actions per opponent from now on (`None` keeps
+    /// everything). Existing histories are trimmed right away.
+    pub fn set_memory_window(&mut self, window: Option<usize>) {
+        self.memory_window = window;
+        if let Some(window) = window {
+            for exchanges in self.history.values_mut() {
+                exchanges.trim(window);
+            }
+        }
+    }
+
+    /// Adds `points`, times the stake, to the score without a game, e.g. an outside option.
+    pub(crate) fn earn(&mut self, points: f64) {
+        self.score += points * self.stake as f64;
+    }
+
+    /// `earn`, crediting the points to `opponent` as if from a game that isn't recorded, as
+    /// when a frozen run replays its last step.
+    pub(crate) fn earn_from(&mut self, opponent: Coord, points: f64) {
+        let points = points * self.stake as f64;
+        if let Some(exchanges) = self.history.get_mut(&opponent) {
+            exchanges.payoff += points;
         }
-        self.score = self.score * 1.0 + score;
+        self.score += points;
     }
 
+    /// Drops the history with `opponent`, e.g. when it died and its cell may be reused.
+    pub(crate) fn forget(&mut self, opponent: Coord) {
+        self.history.remove(&opponent);
+    }
+
+    /// Drops every history, as if it had never played.
+    pub(crate) fn forget_all(&mut self) {
+        self.history.clear();
+    }
+
+    /// Number of distinct opponents this agent has a recorded history with.
+    pub fn num_opponents(&self) -> usize {
+        self.history.len()
+    }
+
+    /// An agent with ID 0; environments renumber the agents they're built from. Allocates
+    /// nothing until its first game, so building many environments stays cheap.
     pub fn new(coord: Coord, strategy: Strategy) -> Agent {
         Agent {
             coord,
+            id: 0,
+            parent_id: None,
+            founder_id: 0,
             history: HashMap::new(),
+            memory_window: None,
             strategy,
+            class: Class::default(),
             score: 0.0,
+            stake: 1.0,
+            energy: 0.0,
+            interactions: 0,
+            age: 0,
         }
     }
 
-    pub fn random(coord: Coord, strategies: &Vec<Strategy>) -> Agent {
-        let mut rng = thread_rng();
-        Agent::new(coord, strategies.choose(&mut rng).unwrap().to_owned())
+    /// This agent with payoffs multiplied by `stake`.
+    pub fn with_stake(mut self, stake: f32) -> Agent {
+        self.stake = stake;
+        self
+    }
+
+    /// This agent in `class`.
+    pub fn with_class(mut self, class: Class) -> Agent {
+        self.class = class;
+        self
+    }
+
+    /// An agent with a strategy drawn uniformly from `strategies`, which must not be empty.
+    pub fn random(coord: Coord, strategies: &[Strategy]) -> Result<Agent, Error> {
+        Agent::random_with_rng(coord, strategies, &mut thread_rng())
+    }
+
+    pub fn random_with_rng<R: Rng + ?Sized>(
+        coord: Coord,
+        strategies: &[Strategy],
+        rng: &mut R,
+    ) -> Result<Agent, Error> {
+        strategies
+            .choose(rng)
+            .map(|s| Agent::new(coord, *s))
+            .ok_or(Error::EmptyStrategyPool)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
     use super::*;
 
+    /// `strategy`'s action against an opponent that played `history`, with nothing else known.
+    fn act(strategy: Strategy, history: &[Action], rng: &mut dyn RngCore) -> Action {
+        let payoffs = Payoffs::default();
+        let history = ActionBits::from(history);
+        strategy.get_action(&mut ActionContext {
+            history: history.actions(),
+            ..ActionContext::new((0, 0), &payoffs, rng)
+        })
+    }
+
+    /// `agent`'s action against `opponent` in step 0.
+    fn play(agent: &Agent, opponent: &Agent, rng: &mut dyn RngCore) -> Action {
+        let payoffs = Payoffs::default();
+        let mut ctx = agent.context(opponent.coord, 0, &payoffs, rng);
+        agent.strategy.get_action(&mut ctx)
+    }
+
     #[test]
     fn test_strategy() {
+        let mut rng = thread_rng();
         let deflect_history = vec![Action::Deflect];
         let coop_history = vec![Action::Coop];
         for history in [deflect_history, coop_history] {
             assert_eq!(
-                Strategy::TicToc.get_action(&history),
+                act(Strategy::TicToc, &history, &mut rng),
                 *history.last().unwrap()
             );
-            assert_eq!(Strategy::Coop.get_action(&history), Action::Coop);
-            assert_eq!(Strategy::Deflect.get_action(&history), Action::Deflect);
+            assert_eq!(act(Strategy::Coop, &history, &mut rng), Action::Coop);
+            assert_eq!(act(Strategy::Deflect, &history, &mut rng), Action::Deflect);
         }
     }
 
     #[test]
     fn test_agent() {
+        let mut rng = thread_rng();
         let mut agent = Agent::new((0, 0), Strategy::TicToc);
         let mut other_agent = Agent::new((0, 1), Strategy::Deflect);
 
-        assert_eq!(agent.get_action(&other_agent), Action::Coop);
-        assert_eq!(other_agent.get_action(&agent), Action::Deflect);
+        assert_eq!(play(&agent, &other_agent, &mut rng), Action::Coop);
+        assert_eq!(play(&other_agent, &agent, &mut rng), Action::Deflect);
 
-        agent.score(&other_agent, Action::Deflect, 0.0);
-        other_agent.score(&agent, Action::Coop, 3.0);
+        agent.record_outcome(InteractionOutcome::against(
+            other_agent.coord,
+            Action::Deflect,
+            0.0,
+        ));
+        other_agent.record_outcome(InteractionOutcome::against(agent.coord, Action::Coop, 3.0));
 
-        assert_eq!(agent.get_action(&other_agent), Action::Deflect);
-        assert_eq!(other_agent.get_action(&agent), Action::Deflect);
+        assert_eq!(play(&agent, &other_agent, &mut rng), Action::Deflect);
+        assert_eq!(play(&other_agent, &agent, &mut rng), Action::Deflect);
+
+        assert_eq!(agent.history_with((0, 1)), &[Action::Deflect]);
+        assert_eq!(agent.history_with((5, 5)), &[] as &[Action]);
+        assert_eq!(agent.num_opponents(), 1);
 
         agent.adapt(vec![&other_agent]);
         assert_eq!(agent.strategy, Strategy::Deflect);
     }
+
+    #[test]
+    fn test_random_agent() {
+        let mut rng = thread_rng();
+        assert_eq!(
+            Agent::random((0, 0), &[]).unwrap_err(),
+            Error::EmptyStrategyPool
+        );
+        assert!(Agent::random_with_rng((0, 0), &[], &mut rng).is_err());
+        let agent = Agent::random_with_rng((2, 3), &[Strategy::Coop], &mut rng).unwrap();
+        assert_eq!((agent.coord, agent.strategy), ((2, 3), Strategy::Coop));
+        for _ in 0..20 {
+            let action = act(Strategy::Random, &[], &mut rng);
+            assert!(matches!(action, Action::Coop | Action::Deflect));
+        }
+    }
+
+    #[test]
+    fn test_small_payoffs_count_on_large_scores() {
+        // 1e8 is past where an f32 can tell it from 1e8 + 3.
+        let mut agent = Agent::new((0, 0), Strategy::TicToc);
+        let mut neighbor = Agent::new((0, 1), Strategy::Deflect);
+        agent.score = 1e8;
+        neighbor.score = 1e8;
+        agent.adapt(vec![&neighbor]);
+        assert_eq!(agent.strategy, Strategy::TicToc);
+        neighbor.record_outcome(InteractionOutcome::against(agent.coord, Action::Coop, 3.0));
+        agent.adapt(vec![&neighbor]);
+        assert_eq!(agent.strategy, Strategy::Deflect);
+    }
+
+    #[test]
+    fn test_adapt_with_nan_scores() {
+        let with_score = |coord, strategy, score| Agent {
+            score,
+            ..Agent::new(coord, strategy)
+        };
+        // NaN neighbors are never copied, however the others compare.
+        let mut agent = with_score((0, 0), Strategy::TicToc, 1.0);
+        let nan = with_score((0, 1), Strategy::Deflect, f64::NAN);
+        let better = with_score((1, 0), Strategy::Coop, 2.0);
+        let worse = with_score((1, 1), Strategy::Random, 0.5);
+        agent.adapt(vec![&nan, &worse]);
+        assert_eq!(agent.strategy, Strategy::TicToc);
+        agent.adapt(vec![&nan, &better, &nan]);
+        assert_eq!(agent.strategy, Strategy::Coop);
+        // An agent with a NaN score takes the best valid neighbor's strategy.
+        let mut agent = with_score((0, 0), Strategy::TicToc, f64::NAN);
+        agent.adapt(vec![&worse, &nan]);
+        assert_eq!(agent.strategy, Strategy::Random);
+        agent.adapt(vec![&nan]);
+        assert_eq!(agent.strategy, Strategy::Random);
+    }
+
+    #[test]
+    fn test_strategy_names_round_trip() {
+        for s in Strategy::ALL {
+            assert_eq!(s.to_string(), s.name());
+            assert_eq!(s.to_string().parse::<Strategy>(), Ok(s));
+            assert_eq!(s.name().to_uppercase().parse::<Strategy>(), Ok(s));
+        }
+    }
+
+    #[test]
+    fn test_strategy_aliases() {
+        assert_eq!("tft".parse(), Ok(Strategy::TicToc));
+        assert_eq!("D".parse(), Ok(Strategy::Deflect));
+        assert_eq!("defect".parse(), Ok(Strategy::Deflect));
+        assert_eq!(" coop ".parse(), Ok(Strategy::Coop));
+        assert_eq!("r".parse(), Ok(Strategy::Random));
+        assert_eq!("ch".parse(), Ok(Strategy::Choosy));
+        assert_eq!("liar".parse(), Ok(Strategy::Deceiver));
+    }
+
+    #[test]
+    fn test_unknown_strategy() {
+        let err = "pavlov".parse::<Strategy>().unwrap_err();
+        assert_eq!(err, ParseStrategyError("pavlov".to_string()));
+        let message = err.to_string();
+        assert!(
+            message.starts_with("unknown strategy \"pavlov\""),
+            "{}",
+            message
+        );
+        assert!(message.contains("tictoc (t, tft)"), "{}", message);
+        assert!("".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn test_all_lists_every_variant() {
+        // Adding a variant breaks this match, forcing ALL to be revisited.
+        let index = |s: Strategy| match s {
+            Strategy::Deflect => 0,
+            Strategy::TicToc => 1,
+            Strategy::Coop => 2,
+            Strategy::Random => 3,
+            Strategy::Choosy => 4,
+            Strategy::Trusting => 5,
+            Strategy::Deceiver => 6,
+            Strategy::Wary => 7,
+            Strategy::GenerousTFT(_) => 8,
+            Strategy::RandomP(_) => 9,
+            Strategy::MemoryOne(_) => 10,
+        };
+        for (i, s) in Strategy::ALL.into_iter().enumerate() {
+            assert_eq!(index(s), i);
+            assert_eq!(s.index() as usize, i);
+            assert_eq!(Strategy::from_index(s.index()), Some(s));
+        }
+        assert_eq!(Strategy::from_index(Strategy::ALL.len() as u8), None);
+    }
+
+    #[test]
+    fn test_choosy_refuses_repeat_defectors() {
+        use Action::{Coop as C, Deflect as D};
+        let choosy = Strategy::Choosy;
+        let refuses =
+            |s: Strategy, history: &[Action]| s.refuses(ActionBits::from(history).actions());
+        assert!(!refuses(choosy, &[]));
+        assert!(!refuses(choosy, &[D]));
+        assert!(refuses(choosy, &[D, D]));
+        assert!(refuses(choosy, &[D, C, D]));
+        // Only the last three moves count.
+        assert!(!refuses(choosy, &[D, D, C, C, D]));
+        assert!(refuses(choosy, &[C, C, D, C, D]));
+        assert!(!refuses(Strategy::TicToc, &[D, D, D]));
+        // Otherwise it plays TicToc.
+        let mut rng = thread_rng();
+        assert_eq!(act(choosy, &[], &mut rng), C);
+        assert_eq!(act(choosy, &[C, D], &mut rng), D);
+    }
+
+    #[test]
+    fn test_signal_aware_strategies() {
+        use Action::{Coop as C, Deflect as D};
+        let mut rng = thread_rng();
+        let payoffs = Payoffs::default();
+        let mut respond = |s: Strategy, history: &[Action], signal| {
+            s.get_action(&mut ActionContext {
+                history: ActionBits::from(history).actions(),
+                signal,
+                ..ActionContext::new((0, 1), &payoffs, &mut rng)
+            })
+        };
+        // The deceiver announces cooperation and defects, which the trusting take at its word...
+        assert_eq!(Strategy::Deceiver.signal(), C);
+        assert_eq!(respond(Strategy::Deceiver, &[], Some(C)), D);
+        assert_eq!(respond(Strategy::Trusting, &[D, D], Some(C)), C);
+        assert_eq!(respond(Strategy::Trusting, &[C], Some(D)), D);
+        // ...while the wary stop believing it once it has defected.
+        assert_eq!(respond(Strategy::Wary, &[], Some(C)), C);
+        assert_eq!(respond(Strategy::Wary, &[C], Some(D)), D);
+        assert_eq!(respond(Strategy::Wary, &[C, D], Some(C)), D);
+        // Without cheap talk they fall back to Coop and TicToc.
+        assert_eq!(respond(Strategy::Trusting, &[D], None), C);
+        assert_eq!(respond(Strategy::Wary, &[D], None), D);
+        assert_eq!(Strategy::Deflect.signal(), D);
+    }
+
+    #[test]
+    fn test_parameterized_strategies() {
+        use Action::{Coop as C, Deflect as D};
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(Prob::new(1.5), Prob::new(1.0));
+        assert_eq!(Prob::new(f64::NAN).get(), 0.0);
+        assert_eq!(Prob::new(0.25).get(), 0.25);
+        // Never forgiving is TicToc, always forgiving is Coop.
+        let never = Strategy::GenerousTFT(Prob::new(0.0));
+        let always = Strategy::GenerousTFT(Prob::new(1.0));
+        assert_eq!(act(never, &[C, D], &mut rng), D);
+        assert_eq!(act(always, &[C, D], &mut rng), C);
+        assert_eq!(never.kind(), Strategy::ALL[8]);
+        assert_eq!(never.name(), "GenerousTFT");
+        assert_eq!(act(Strategy::RandomP(Prob::new(0.0)), &[], &mut rng), D);
+        // The default MemoryOne is win-stay lose-shift.
+        let wsls = Strategy::ALL[10];
+        let payoffs = Payoffs::default();
+        let after = |mine: &[Action], theirs: &[Action], rng: &mut StdRng| {
+            wsls.get_action(&mut ActionContext {
+                history: ActionBits::from(theirs).actions(),
+                own_history: ActionBits::from(mine).actions(),
+                ..ActionContext::new((0, 1), &payoffs, rng)
+            })
+        };
+        assert_eq!(after(&[], &[], &mut rng), C);
+        assert_eq!(after(&[C], &[C], &mut rng), C);
+        assert_eq!(after(&[C], &[D], &mut rng), D);
+        assert_eq!(after(&[D], &[C], &mut rng), D);
+        assert_eq!(after(&[D, D], &[C, D], &mut rng), C);
+        assert_eq!(
+            wsls.params(),
+            vec![("cc", 1.0), ("cd", 0.0), ("dc", 0.0), ("dd", 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_mutation_stays_in_range() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let generous = Strategy::GenerousTFT(Prob::new(0.9));
+        assert_eq!(generous.mutate(&mut rng, 0.0), generous);
+        assert_eq!(Strategy::TicToc.mutate(&mut rng, 0.5), Strategy::TicToc);
+        let mutants: Vec<Strategy> = (0..200).map(|_| generous.mutate(&mut rng, 0.2)).collect();
+        assert!(mutants.iter().all(|m| m.kind() == generous.kind()));
+        let values: Vec<f64> = mutants.iter().map(|m| m.params()[0].1).collect();
+        assert!(values.iter().all(|v| (0.0..=1.0).contains(v)));
+        // Clipping piles some mutants up at the top of the range.
+        assert!(values.contains(&1.0));
+        assert!(values.iter().any(|v| *v < 0.8));
+        let memory = Strategy::ALL[10].mutate(&mut rng, 0.1);
+        assert_ne!(memory, Strategy::ALL[10]);
+    }
+
+    #[test]
+    fn test_record_outcome() {
+        let mut agent = Agent::new((1, 1), Strategy::TicToc).with_stake(2.0);
+        let outcome = InteractionOutcome {
+            opponent: (0, 1),
+            intended: Action::Coop,
+            realized: Action::Deflect,
+            their_action: Action::Coop,
+            payoff: 4.0,
+            step: 7,
+        };
+        agent.record_outcome(outcome);
+        assert_eq!(agent.last_outcome_with((0, 1)), Some(&outcome));
+        assert_eq!(agent.last_outcome_with((1, 0)), None);
+        assert_eq!(agent.history_with((0, 1)), &[Action::Coop]);
+        assert_eq!(agent.score, 8.0);
+        agent.forget((0, 1));
+        assert_eq!(agent.last_outcome_with((0, 1)), None);
+        agent.record_outcome(outcome);
+        agent.forget_all();
+        assert_eq!(agent.last_outcome_with((0, 1)), None);
+    }
+
+    #[test]
+    fn test_context_carries_what_the_agent_knows() {
+        use Action::{Coop as C, Deflect as D};
+        let mut agent = Agent::new((1, 1), Strategy::ALL[10]);
+        agent.set_memory_window(Some(2));
+        for (step, (mine, theirs)) in [(C, D), (D, D), (D, C)].into_iter().enumerate() {
+            agent.record_outcome(InteractionOutcome {
+                opponent: (0, 1),
+                intended: C,
+                realized: mine,
+                their_action: theirs,
+                payoff: 1.0,
+                step,
+            });
+        }
+        let payoffs = Payoffs {
+            reward: 2.0,
+            ..Payoffs::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let ctx = agent.context((0, 1), 3, &payoffs, &mut rng);
+        assert_eq!((ctx.opponent, ctx.step, ctx.signal), ((0, 1), 3, None));
+        assert_eq!(
+            (ctx.history.to_vec(), ctx.own_history.to_vec()),
+            (vec![D, C], vec![D, D])
+        );
+        assert_eq!(ctx.last_outcome.map(|o| (o.realized, o.step)), Some((D, 2)));
+        assert_eq!(ctx.payoffs.reward, 2.0);
+        let stranger = agent.context((2, 1), 3, &payoffs, &mut rng);
+        assert!(stranger.history.is_empty() && stranger.own_history.is_empty());
+        assert_eq!(stranger.last_outcome, None);
+    }
+
+    #[test]
+    fn test_history_accessors() {
+        let mut agent = Agent::new((1, 1), Strategy::TicToc);
+        let a = Agent::new((0, 1), Strategy::Coop);
+        let b = Agent::new((1, 0), Strategy::Deflect);
+        assert_eq!(agent.last_action_from((0, 1)), None);
+        agent.record_outcome(InteractionOutcome::against(a.coord, Action::Coop, 3.0));
+        agent.record_outcome(InteractionOutcome::against(b.coord, Action::Deflect, 0.0));
+        agent.record_outcome(InteractionOutcome::against(a.coord, Action::Deflect, 0.0));
+        assert_eq!(agent.last_action_from((0, 1)), Some(Action::Deflect));
+        assert_eq!(agent.last_action_from((1, 0)), Some(Action::Deflect));
+        let mut opponents: Vec<Coord> = agent.opponents().collect();
+        opponents.sort();
+        assert_eq!(opponents, vec![(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_memory_window_caps_history() {
+        let mut agent = Agent::new((0, 0), Strategy::TicToc);
+        let other = Agent::new((0, 1), Strategy::Coop);
+        agent.set_memory_window(Some(3));
+        let actions = [Action::Coop, Action::Deflect];
+        for i in 0..1000 {
+            agent.record_outcome(InteractionOutcome::against(
+                other.coord,
+                actions[i % 3 % 2],
+                0.0,
+            ));
+            assert!(agent.history[&(0, 1)].received.len() < 6);
+        }
+        // 997 % 3 = 1, 998 % 3 = 2, 999 % 3 = 0.
+        assert_eq!(
+            agent.history_with((0, 1)),
+            &[Action::Deflect, Action::Coop, Action::Coop]
+        );
+        assert_eq!(agent.last_action_from((0, 1)), Some(Action::Coop));
+    }
+
+    /// Unwindowed histories still grow by a bit per game and side, not a byte.
+    #[test]
+    fn test_long_history_footprint() {
+        let mut agent = Agent::new((1, 1), Strategy::TicToc);
+        assert_eq!(agent.history.capacity(), 0);
+        let opponents: Vec<Coord> = (0..8).map(|i| (i / 3, i % 3)).collect();
+        for step in 0..10_000 {
+            for &opponent in &opponents {
+                let action = if step % 7 == 0 {
+                    Action::Deflect
+                } else {
+                    Action::Coop
+                };
+                agent.record_outcome(InteractionOutcome::against(opponent, action, 0.0));
+            }
+        }
+        let bytes: usize = agent
+            .history
+            .values()
+            .map(|e| e.received.heap_bytes() + e.played.heap_bytes())
+            .sum();
+        // 8 opponents, 2 sides, 10000 games: 20000 bytes packed, with room for `Vec` growth.
+        assert!(bytes <= 2 * 20_000, "{} bytes", bytes);
+        let history = agent.history_with((0, 0));
+        assert_eq!(history.len(), 10_000);
+        assert_eq!(history.count_coop_in_last(7), 6);
+        assert_eq!(history.count_coop_in_last(10_000), 10_000 - 1429);
+    }
+
+    #[test]
+    fn test_setting_window_trims() {
+        let mut agent = Agent::new((0, 0), Strategy::TicToc);
+        let other = Agent::new((0, 1), Strategy::Coop);
+        for _ in 0..10 {
+            agent.record_outcome(InteractionOutcome::against(other.coord, Action::Coop, 3.0));
+        }
+        agent.record_outcome(InteractionOutcome::against(
+            other.coord,
+            Action::Deflect,
+            0.0,
+        ));
+        agent.set_memory_window(Some(2));
+        assert_eq!(agent.history[&(0, 1)].received.len(), 2);
+        assert_eq!(agent.history_with((0, 1)), &[Action::Coop, Action::Deflect]);
+        agent.set_memory_window(None);
+        assert_eq!(agent.history_with((0, 1)).len(), 2);
+    }
 }
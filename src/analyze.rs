@@ -0,0 +1,427 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::agent::Strategy;
+use crate::history::History;
+use crate::observe::Interaction;
+use crate::pairings::PairingOutcomes;
+use crate::snapshot::Snapshot;
+
+/// Cooperation rates closer than this count as equal in [`compare_runs`].
+pub const DIVERGENCE_TOLERANCE: f32 = 1e-6;
+
+/// Reduces a snapshot by `block`x`block` tiles, each represented by the majority strategy of
+/// its cells, or `None` when several strategies tie for the majority. Edge tiles of grids whose
+/// size isn't divisible by `block` are smaller and only count the cells they contain. Vacant
+/// cells don't count, and a tile without agents is `None` too.
+pub fn aggregate_blocks(snapshot: &Snapshot, block: usize) -> Vec<Vec<Option<Strategy>>> {
+    let block = block.max(1);
+    let (rows, cols) = snapshot.dimensions();
+    (0..rows.div_ceil(block))
+        .map(|br| {
+            (0..cols.div_ceil(block))
+                .map(|bc| {
+                    let mut counts: BTreeMap<Strategy, usize> = BTreeMap::new();
+                    for r in br * block..((br + 1) * block).min(rows) {
+                        for c in bc * block..((bc + 1) * block).min(cols) {
+                            if let Some(s) = snapshot.occupant(r, c) {
+                                *counts.entry(s).or_insert(0) += 1;
+                            }
+                        }
+                    }
+                    majority(&counts)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Tallies recorded games by the strategies that met, e.g. the records of a
+/// [`SamplingRecorder`](crate::SamplingRecorder).
+pub fn pairing_outcomes<'a>(
+    interactions: impl IntoIterator<Item = &'a Interaction>,
+) -> PairingOutcomes {
+    let mut outcomes = PairingOutcomes::new();
+    for interaction in interactions {
+        outcomes.record(interaction);
+    }
+    outcomes
+}
+
+/// Cells that differ between two snapshots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotDiff {
+    /// `changed[r][c]` is true where the snapshots disagree; shaped like the first snapshot.
+    pub changed: Vec<Vec<bool>>,
+    pub count: usize,
+}
+
+/// Compares `a` against `b` cell by cell. Cells of `a` with no counterpart in `b` count as
+/// changed, and so do cells vacant in only one of them.
+pub fn diff_snapshots(a: &Snapshot, b: &Snapshot) -> SnapshotDiff {
+    let changed: Vec<Vec<bool>> = (0..a.rows())
+        .map(|r| {
+            (0..a.cols())
+                .map(|c| b.get(r, c).is_none() || a.occupant(r, c) != b.occupant(r, c))
+                .collect()
+        })
+        .collect();
+    let count = changed.iter().flatten().filter(|c| **c).count();
+    SnapshotDiff { changed, count }
+}
+
+/// Reduces a cell mask to `rows`x`cols` tiles that are set when any of their cells is.
+pub fn any_in_tiles(mask: &[Vec<bool>], rows: usize, cols: usize) -> Vec<Vec<bool>> {
+    let (rows, cols) = (rows.max(1), cols.max(1));
+    let width = mask.first().map_or(0, |r| r.len());
+    mask.chunks(rows)
+        .map(|band| {
+            (0..width.div_ceil(cols))
+                .map(|tc| {
+                    band.iter()
+                        .any(|row| row.iter().skip(tc * cols).take(cols).any(|c| *c))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// How one step of two runs differs, as the second run minus the first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepDiff {
+    pub step: usize,
+    pub coop_rate: f32,
+    /// Agents playing each strategy, for the strategies whose counts differ.
+    pub strategies: BTreeMap<Strategy, i64>,
+}
+
+impl StepDiff {
+    /// Agents that would have to switch strategy to turn one step into the other, counting
+    /// a population difference as switches too.
+    pub fn strategy_distance(&self) -> u64 {
+        self.strategies.values().map(|d| d.unsigned_abs()).sum()
+    }
+}
+
+/// How two recorded runs differ, step by step; see [`compare_runs`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunDiff {
+    /// Every step both runs retained, in step order.
+    pub steps: Vec<StepDiff>,
+    /// The first of those steps whose cooperation rates differ by more than the tolerance or
+    /// whose strategy counts differ at all.
+    pub first_divergence: Option<usize>,
+    /// The step with the largest difference in cooperation rate, the earliest on ties.
+    pub max_divergence: Option<usize>,
+    /// Mean over the steps of the absolute difference in cooperation rate.
+    pub mean_abs_coop_rate: f64,
+    /// Mean over the steps of [`StepDiff::strategy_distance`].
+    pub mean_strategy_distance: f64,
+}
+
+impl RunDiff {
+    /// Whether the runs agree on every compared step.
+    pub fn is_identical(&self) -> bool {
+        self.first_divergence.is_none()
+    }
+
+    /// The summary followed by one line per divergent step, as aligned text.
+    pub fn to_text(&self) -> String {
+        let step = |s: Option<usize>| s.map_or("none".to_string(), |s| s.to_string());
+        let mut text = format!(
+            "{} steps compared
+First divergence: {}
+Max divergence: {}
+             Mean |coop rate difference|: {:.4}
+Mean strategy distance: {:.2}
+",
+            self.steps.len(),
+            step(self.first_divergence),
+            step(self.max_divergence),
+            self.mean_abs_coop_rate,
+            self.mean_strategy_distance
+        );
+        for diff in self.steps.iter().filter(|d| d.strategy_distance() > 0) {
+            let counts: Vec<String> = (diff.strategies.iter())
+                .map(|(s, d)| format!("{} {:+}", s.name(), d))
+                .collect();
+            let _ = writeln!(
+                text,
+                "{:>6} {:>+8.4} {}",
+                diff.step,
+                diff.coop_rate,
+                counts.join(", ")
+            );
+        }
+        text
+    }
+
+    /// One row per compared step: the differences in cooperation rate and in the number of
+    /// agents playing each strategy.
+    pub fn to_csv(&self) -> String {
+        let names: Vec<String> = Strategy::ALL
+            .iter()
+            .map(|s| format!("diff_{}", s.name().to_lowercase()))
+            .collect();
+        let mut csv = format!("step,diff_coop_rate,{}\n", names.join(","));
+        for diff in &self.steps {
+            let counts =
+                Strategy::ALL.map(|s| diff.strategies.get(&s).copied().unwrap_or(0).to_string());
+            let _ = writeln!(csv, "{},{},{}", diff.step, diff.coop_rate, counts.join(","));
+        }
+        csv
+    }
+}
+
+/// Compares two recorded runs step by step, e.g. the runs of a comparison or the same
+/// parameters under two seeds, using [`DIVERGENCE_TOLERANCE`]. Only steps both histories
+/// retained are compared.
+pub fn compare_runs(a: &History, b: &History) -> RunDiff {
+    compare_runs_within(a, b, DIVERGENCE_TOLERANCE)
+}
+
+/// [`compare_runs`], counting cooperation rates up to `tolerance` apart as equal.
+pub fn compare_runs_within(a: &History, b: &History, tolerance: f32) -> RunDiff {
+    let steps: Vec<StepDiff> = a
+        .iter()
+        .filter_map(|(step, first)| {
+            let second = b.get(step)?;
+            let strategies = Strategy::ALL
+                .into_iter()
+                .filter_map(|s| {
+                    let count = |m: &BTreeMap<Strategy, usize>| m.get(&s).copied().unwrap_or(0);
+                    let diff = count(&second.strategies) as i64 - count(&first.strategies) as i64;
+                    (diff != 0).then_some((s, diff))
+                })
+                .collect();
+            Some(StepDiff {
+                step,
+                coop_rate: second.coop_rate - first.coop_rate,
+                strategies,
+            })
+        })
+        .collect();
+    let first_divergence = steps
+        .iter()
+        .find(|d| d.coop_rate.abs() > tolerance || !d.strategies.is_empty())
+        .map(|d| d.step);
+    let max_divergence = steps
+        .iter()
+        .rev()
+        .max_by(|x, y| x.coop_rate.abs().total_cmp(&y.coop_rate.abs()))
+        .map(|d| d.step);
+    let mean = |value: &dyn Fn(&StepDiff) -> f64| match steps.len() {
+        0 => 0.0,
+        n => steps.iter().map(value).sum::<f64>() / n as f64,
+    };
+    RunDiff {
+        first_divergence,
+        max_divergence,
+        mean_abs_coop_rate: mean(&|d| d.coop_rate.abs() as f64),
+        mean_strategy_distance: mean(&|d| d.strategy_distance() as f64),
+        steps,
+    }
+}
+
+fn majority(counts: &BTreeMap<Strategy, usize>) -> Option<Strategy> {
+    let max = counts.values().max()?;
+    let mut winners = counts.iter().filter(|(_, c)| *c == max);
+    let (winner, _) = winners.next()?;
+    winners.next().is_none().then_some(*winner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    #[test]
+    fn test_majority_blocks() {
+        let snapshot = Snapshot::from(vec![
+            vec![C, C, D, D],
+            vec![C, D, D, D],
+            vec![T, T, C, D],
+            vec![T, C, D, C],
+        ]);
+        assert_eq!(
+            aggregate_blocks(&snapshot, 2),
+            vec![vec![Some(C), Some(D)], vec![Some(T), None]]
+        );
+    }
+
+    #[test]
+    fn test_block_of_one_is_identity() {
+        let snapshot = Snapshot::from(vec![vec![C, D], vec![T, C]]);
+        assert_eq!(
+            aggregate_blocks(&snapshot, 1),
+            vec![vec![Some(C), Some(D)], vec![Some(T), Some(C)]]
+        );
+        assert_eq!(
+            aggregate_blocks(&snapshot, 0),
+            aggregate_blocks(&snapshot, 1)
+        );
+    }
+
+    #[test]
+    fn test_non_divisible_edges() {
+        let snapshot = Snapshot::from(vec![
+            vec![C, C, D, D, T],
+            vec![C, C, D, D, T],
+            vec![D, D, C, C, C],
+        ]);
+        assert_eq!(
+            aggregate_blocks(&snapshot, 2),
+            vec![
+                vec![Some(C), Some(D), Some(T)],
+                vec![Some(D), Some(C), Some(C)],
+            ]
+        );
+        // A block larger than the grid covers it all.
+        assert_eq!(aggregate_blocks(&snapshot, 10), vec![vec![Some(C)]]);
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let a = Snapshot::from(vec![vec![C, D], vec![T, C]]);
+        let b = Snapshot::from(vec![vec![C, C], vec![T, D]]);
+        let diff = diff_snapshots(&a, &b);
+        assert_eq!(diff.changed, vec![vec![false, true], vec![false, true]]);
+        assert_eq!(diff.count, 2);
+        assert_eq!(diff_snapshots(&a, &a).count, 0);
+    }
+
+    #[test]
+    fn test_diff_mismatched_shapes() {
+        let a = Snapshot::from(vec![vec![C, D], vec![T, C]]);
+        let b = Snapshot::from(vec![vec![C]]);
+        let diff = diff_snapshots(&a, &b);
+        assert_eq!(diff.changed, vec![vec![false, true], vec![true, true]]);
+        assert_eq!(diff.count, 3);
+        assert_eq!(diff_snapshots(&Snapshot::default(), &a).count, 0);
+    }
+
+    #[test]
+    fn test_any_in_tiles() {
+        let mask = vec![
+            vec![false, false, true],
+            vec![false, false, false],
+            vec![true, false, false],
+        ];
+        assert_eq!(any_in_tiles(&mask, 1, 1), mask);
+        assert_eq!(
+            any_in_tiles(&mask, 2, 1),
+            vec![vec![false, false, true], vec![true, false, false]]
+        );
+        assert_eq!(
+            any_in_tiles(&mask, 2, 2),
+            vec![vec![false, true], vec![true, false]]
+        );
+        assert_eq!(any_in_tiles(&mask, 4, 4), vec![vec![true]]);
+        assert!(any_in_tiles(&[], 2, 2).is_empty());
+    }
+
+    /// A history of `steps` steps of a seeded 10x10 run, leaving out the first `skip`.
+    fn recorded(seed: u64, skip: usize, steps: usize) -> History {
+        let mut env = crate::EnvironmentBuilder::new()
+            .size(10, 10)
+            .noise(0.05)
+            .seed(seed)
+            .strategies(&[C, D, T])
+            .build()
+            .unwrap();
+        env.run(skip);
+        let mut history = History::new(steps, 1);
+        env.run_with(steps, |m| {
+            history.push(m);
+        });
+        history
+    }
+
+    #[test]
+    fn test_identical_runs() {
+        let diff = compare_runs(&recorded(1, 0, 20), &recorded(1, 0, 20));
+        assert!(diff.is_identical());
+        assert_eq!(diff.steps.len(), 20);
+        assert_eq!(
+            (diff.mean_abs_coop_rate, diff.mean_strategy_distance),
+            (0.0, 0.0)
+        );
+        assert_eq!(diff.max_divergence, Some(0));
+        assert_eq!(diff.to_csv().lines().count(), 21);
+        assert!(diff
+            .to_text()
+            .starts_with("20 steps compared\nFirst divergence: none\n"));
+    }
+
+    #[test]
+    fn test_runs_one_step_apart() {
+        let (a, b) = (recorded(1, 0, 20), recorded(1, 1, 20));
+        let diff = compare_runs(&a, &b);
+        // Every step of `b` is the next step of `a`.
+        assert_eq!(diff.first_divergence, Some(0));
+        for d in &diff.steps[..19] {
+            let (now, next) = (a.get(d.step).unwrap(), a.get(d.step + 1).unwrap());
+            assert_eq!(d.coop_rate, next.coop_rate - now.coop_rate);
+        }
+        // Going the other way flips every sign.
+        let back = compare_runs(&b, &a);
+        assert_eq!(back.steps[3].coop_rate, -diff.steps[3].coop_rate);
+        assert_eq!(back.mean_abs_coop_rate, diff.mean_abs_coop_rate);
+        // Only steps both histories hold are compared.
+        assert_eq!(compare_runs(&a, &recorded(1, 0, 5)).steps.len(), 5);
+    }
+
+    #[test]
+    fn test_different_runs() {
+        let diff = compare_runs(&recorded(1, 0, 20), &recorded(2, 0, 20));
+        assert_eq!(diff.first_divergence, Some(0));
+        assert!(diff.mean_strategy_distance > 0.0);
+        let max = diff.max_divergence.unwrap();
+        assert!(diff
+            .steps
+            .iter()
+            .all(|d| d.coop_rate.abs() <= diff.steps[max].coop_rate.abs()));
+        let row = diff.to_csv().lines().nth(1).unwrap().to_string();
+        assert!(row.starts_with("0,"));
+        assert_eq!(row.split(',').count(), 2 + Strategy::ALL.len());
+        // A tolerance wider than any difference still sees the strategy counts diverge.
+        assert_eq!(
+            compare_runs_within(&recorded(1, 0, 20), &recorded(2, 0, 20), 1.0).first_divergence,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_empty_snapshot() {
+        assert!(aggregate_blocks(&Snapshot::default(), 2).is_empty());
+    }
+
+    #[test]
+    fn test_pairing_outcomes_from_records() {
+        use crate::builder::EnvironmentBuilder;
+        use crate::observe::SamplingRecorder;
+        use std::sync::{Arc, Mutex};
+
+        // Recording every game and tallying afterwards matches tallying as they're played.
+        let recorder = Arc::new(Mutex::new(SamplingRecorder::new(1.0, 0).unwrap()));
+        let live = Arc::new(Mutex::new(PairingOutcomes::new()));
+        let build = |observer: crate::SharedObserver| {
+            EnvironmentBuilder::new()
+                .size(6, 6)
+                .seed(3)
+                .noise(0.1)
+                .strategies(&[C, D, T])
+                .observer(observer)
+                .build()
+                .unwrap()
+        };
+        let (mut a, mut b) = (build(recorder.clone()), build(live.clone()));
+        for _ in 0..4 {
+            a.step();
+            b.step();
+        }
+        let outcomes = pairing_outcomes(recorder.lock().unwrap().records());
+        assert!(outcomes.games() > 0);
+        assert_eq!(outcomes, *live.lock().unwrap());
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+
+/// Steps marked as interesting, in step order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bookmarks {
+    steps: BTreeSet<usize>,
+}
+
+impl Bookmarks {
+    /// Adds `step`, or removes it if it's already bookmarked. Returns whether it's now
+    /// bookmarked.
+    pub fn toggle(&mut self, step: usize) -> bool {
+        if self.steps.remove(&step) {
+            false
+        } else {
+            self.steps.insert(step)
+        }
+    }
+
+    pub fn contains(&self, step: usize) -> bool {
+        self.steps.contains(&step)
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.steps.iter().copied()
+    }
+
+    /// The first bookmark after `step`, wrapping around to the earliest one.
+    pub fn next_after(&self, step: usize) -> Option<usize> {
+        self.steps
+            .range(step + 1..)
+            .next()
+            .or_else(|| self.steps.first())
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle() {
+        let mut bookmarks = Bookmarks::default();
+        assert!(bookmarks.toggle(5));
+        assert!(bookmarks.toggle(2));
+        assert!(bookmarks.contains(5));
+        assert_eq!(bookmarks.iter().collect::<Vec<_>>(), vec![2, 5]);
+        assert!(!bookmarks.toggle(5));
+        assert!(!bookmarks.contains(5));
+        assert_eq!(bookmarks.len(), 1);
+        bookmarks.clear();
+        assert!(bookmarks.is_empty());
+    }
+
+    #[test]
+    fn test_next_after_wraps() {
+        let mut bookmarks = Bookmarks::default();
+        assert_eq!(bookmarks.next_after(0), None);
+        bookmarks.toggle(3);
+        bookmarks.toggle(9);
+        assert_eq!(bookmarks.next_after(0), Some(3));
+        assert_eq!(bookmarks.next_after(3), Some(9));
+        assert_eq!(bookmarks.next_after(7), Some(9));
+        assert_eq!(bookmarks.next_after(9), Some(3));
+        assert_eq!(bookmarks.next_after(100), Some(3));
+    }
+}
@@ -0,0 +1,618 @@
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+
+use crate::agent::{Agent, Class, Coord, Imitation, Strategy};
+use crate::env::{
+    Asymmetric, EnergyConfig, EnvFeedback, Environment, NonFinitePolicy, PartnerChoice, PayoffCtx,
+    PayoffFn, Payoffs, UpdateMode,
+};
+use crate::error::Error;
+use crate::groups::Groups;
+use crate::mix::{self, Placement};
+use crate::neighbors::{Neighborhood, Tables};
+use crate::observe::SharedObserver;
+use crate::shocks::Shock;
+
+/// Grid size used when `size` isn't called.
+pub const DEFAULT_SIZE: (usize, usize) = (50, 50);
+
+/// Places the initial agents; receives the environment's RNG.
+type AgentFn<'a> = Box<dyn FnMut(Coord, &mut StdRng) -> Agent + 'a>;
+
+/// Collects the parameters of an [`Environment`] and validates them together in `build`.
+/// Unset parameters default to a 50x50 grid, no noise, the standard payoffs, a random seed, and
+/// agents drawn uniformly from Deflect and TicToc.
+pub struct EnvironmentBuilder<'a> {
+    size: (usize, usize),
+    noise: f32,
+    seed: Option<u64>,
+    payoffs: Payoffs,
+    payoff_fn: Option<PayoffFn>,
+    non_finite: NonFinitePolicy,
+    memory_window: Option<usize>,
+    update_mode: UpdateMode,
+    imitation: Imitation,
+    mutation_sigma: f64,
+    exploration_rate: f32,
+    interaction: Neighborhood,
+    adaptation: Neighborhood,
+    energy: Option<EnergyConfig>,
+    partner_choice: Option<PartnerChoice>,
+    signaling: bool,
+    payoff_flow: bool,
+    coop_rate_map: bool,
+    thumbnails: bool,
+    observer: Option<SharedObserver>,
+    groups: Option<Groups>,
+    feedback: Option<EnvFeedback>,
+    shocks: Vec<Shock>,
+    asymmetric: Option<Asymmetric>,
+    /// Strategies drawn from when no agent function or mix is given.
+    strategies: Vec<Strategy>,
+    mix: Option<(Vec<(Strategy, f32)>, Placement)>,
+    agents: Option<AgentFn<'a>>,
+}
+
+impl<'a> EnvironmentBuilder<'a> {
+    pub fn new() -> EnvironmentBuilder<'a> {
+        EnvironmentBuilder {
+            size: DEFAULT_SIZE,
+            noise: 0.0,
+            seed: None,
+            payoffs: Payoffs::default(),
+            payoff_fn: None,
+            non_finite: NonFinitePolicy::default(),
+            memory_window: None,
+            update_mode: UpdateMode::default(),
+            imitation: Imitation::default(),
+            mutation_sigma: 0.0,
+            exploration_rate: 0.0,
+            interaction: Neighborhood::default(),
+            adaptation: Neighborhood::default(),
+            energy: None,
+            partner_choice: None,
+            signaling: false,
+            payoff_flow: false,
+            coop_rate_map: false,
+            thumbnails: false,
+            observer: None,
+            groups: None,
+            feedback: None,
+            shocks: Vec::new(),
+            asymmetric: None,
+            strategies: vec![Strategy::Deflect, Strategy::TicToc],
+            mix: None,
+            agents: None,
+        }
+    }
+
+    /// The grid's rows and columns; building fails if either is zero. The grid doesn't wrap
+    /// around, so neighborhoods are cut off at its edges: on a single row or column agents
+    /// only have the neighbors along it, and a lone agent has none, never plays and never
+    /// changes strategy.
+    pub fn size(mut self, rows: usize, cols: usize) -> Self {
+        self.size = (rows, cols);
+        self
+    }
+
+    /// Probability that an action is flipped.
+    pub fn noise(mut self, noise: f32) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn payoffs(mut self, payoffs: Payoffs) -> Self {
+        self.payoffs = payoffs;
+        self
+    }
+
+    /// Scores games with `payoff_fn` instead of the payoff matrix; see
+    /// [`Environment::set_payoff_fn`].
+    pub fn payoff_fn<F>(mut self, payoff_fn: F) -> Self
+    where
+        F: Fn(&PayoffCtx<'_>) -> (f64, f64) + Send + Sync + 'static,
+    {
+        self.payoff_fn = Some(Arc::new(payoff_fn));
+        self
+    }
+
+    /// Handles NaN and infinite payoffs from the payoff function under `policy`; see
+    /// [`NonFinitePolicy`].
+    pub fn non_finite_policy(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    /// Keeps only the last `window` actions per opponent in each agent's history; see
+    /// [`Environment::set_memory_window`]. Building fails if `window` is 0.
+    pub fn memory_window(mut self, window: usize) -> Self {
+        self.memory_window = Some(window);
+        self
+    }
+
+    pub fn update_mode(mut self, mode: UpdateMode) -> Self {
+        self.update_mode = mode;
+        self
+    }
+
+    /// The cells each agent plays; the 8 surrounding ones by default.
+    pub fn interaction_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.interaction = neighborhood;
+        self
+    }
+
+    /// The cells each agent may imitate; the 8 surrounding ones by default.
+    pub fn adaptation_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.adaptation = neighborhood;
+        self
+    }
+
+    /// What agents compare when choosing whom to imitate; raw scores by default.
+    pub fn imitation(mut self, imitation: Imitation) -> Self {
+        self.imitation = imitation;
+        self
+    }
+
+    /// Standard deviation of the Gaussian noise imitation adds to strategy parameters; 0, an
+    /// exact copy, by default.
+    pub fn mutation_sigma(mut self, sigma: f64) -> Self {
+        self.mutation_sigma = sigma;
+        self
+    }
+
+    /// Chance that an agent copies a random neighbor instead of imitating by payoff; see
+    /// [`Environment::set_exploration_rate`]. 0 by default.
+    pub fn exploration_rate(mut self, rate: f32) -> Self {
+        self.exploration_rate = rate;
+        self
+    }
+
+    /// Puts the agents on an energy budget, under which they can starve and reproduce.
+    pub fn energy(mut self, energy: EnergyConfig) -> Self {
+        self.energy = Some(energy);
+        self
+    }
+
+    /// Lets agents refuse to play neighbors their strategy won't play with; see
+    /// [`PartnerChoice`].
+    pub fn partner_choice(mut self, partner_choice: PartnerChoice) -> Self {
+        self.partner_choice = Some(partner_choice);
+        self
+    }
+
+    /// Has every agent announce an intended action to its neighbors before each round, which
+    /// signal-aware strategies such as [`Strategy::Trusting`] act on.
+    pub fn signaling(mut self, signaling: bool) -> Self {
+        self.signaling = signaling;
+        self
+    }
+
+    /// Fills in [`Metric::payoff_flow`](crate::Metric::payoff_flow), what agents of each strategy earned off each other
+    /// strategy, every step.
+    pub fn payoff_flow(mut self, track: bool) -> Self {
+        self.payoff_flow = track;
+        self
+    }
+
+    /// Fills in [`Metric::coop_rate_map`](crate::Metric::coop_rate_map), how cooperative
+    /// every cell's games were, every step.
+    pub fn coop_rate_map(mut self, track: bool) -> Self {
+        self.coop_rate_map = track;
+        self
+    }
+
+    /// Fills in [`Metric::thumbnail`](crate::Metric::thumbnail), a small copy of the grid
+    /// for overviews of long runs, every step.
+    pub fn thumbnails(mut self, thumbnails: bool) -> Self {
+        self.thumbnails = thumbnails;
+        self
+    }
+
+    /// Shows `observer` every game played; see [`Environment::set_observer`].
+    pub fn observer(mut self, observer: SharedObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Tiles the grid into groups that compete every so often; see [`Groups`].
+    pub fn groups(mut self, groups: Groups) -> Self {
+        self.groups = Some(groups);
+        self
+    }
+
+    /// Couples the payoffs to a shared environment that starts out rich; the payoffs set with
+    /// [`payoffs`](Self::payoffs) are then unused.
+    pub fn feedback(mut self, feedback: EnvFeedback) -> Self {
+        self.feedback = Some(feedback);
+        self
+    }
+
+    /// Adds a shock that scrambles a region of the grid on schedule; see [`Shock`].
+    pub fn shock(mut self, shock: Shock) -> Self {
+        self.shocks.push(shock);
+        self
+    }
+
+    /// Plays an asymmetric game between hosts and visitors; see [`Asymmetric`]. Agents keep
+    /// the class the agent function gives them; without one, classes alternate in a
+    /// checkerboard.
+    pub fn asymmetric(mut self, game: Asymmetric) -> Self {
+        self.asymmetric = Some(game);
+        self
+    }
+
+    /// Draws every initial agent's strategy uniformly from `strategies`; ignored when `agents`
+    /// or `mix` is set.
+    pub fn strategies(mut self, strategies: &[Strategy]) -> Self {
+        self.strategies = strategies.to_vec();
+        self
+    }
+
+    /// Gives each strategy of `mix` exactly its share of the cells, rounded by largest
+    /// remainder, placed by `placement`. Shares are relative weights, e.g. `[(Coop, 10.0),
+    /// (Deflect, 90.0)]`. Ignored when `agents` is set.
+    pub fn mix(mut self, mix: &[(Strategy, f32)], placement: Placement) -> Self {
+        self.mix = Some((mix.to_vec(), placement));
+        self
+    }
+
+    /// Places the initial agents, in row-major order, using the environment's seeded RNG.
+    pub fn agents(mut self, agent_fn: impl FnMut(Coord, &mut StdRng) -> Agent + 'a) -> Self {
+        self.agents = Some(Box::new(agent_fn));
+        self
+    }
+
+    pub fn build(mut self) -> Result<Environment, Error> {
+        self.validate()?;
+        let tables = self.tables();
+        let seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+        self.assemble(seed, &tables)
+    }
+
+    /// Validates the parameters once and works out the neighbor tables, so that many
+    /// environments differing only in their seed can be stamped out cheaply; see
+    /// [`EnvironmentTemplate`]. The seed set here, if any, is ignored.
+    pub fn template(self) -> Result<EnvironmentTemplate<'a>, Error> {
+        self.validate()?;
+        Ok(EnvironmentTemplate {
+            tables: self.tables(),
+            builder: self,
+        })
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let (rows, cols) = self.size;
+        if rows == 0 || cols == 0 {
+            return Err(Error::EmptyGrid { rows, cols });
+        }
+        if !(0.0..=1.0).contains(&self.noise) {
+            return Err(Error::InvalidNoise(self.noise));
+        }
+        if !self.payoffs.is_finite() {
+            return Err(Error::InvalidPayoffs(self.payoffs));
+        }
+        if let Some(energy) = self.energy.filter(|e| !e.is_valid()) {
+            return Err(Error::InvalidEnergy(energy));
+        }
+        if let Some(choice) = self
+            .partner_choice
+            .filter(|p| !p.outside_option.is_finite())
+        {
+            return Err(Error::InvalidPartnerChoice(choice));
+        }
+        if let Some(feedback) = self.feedback.filter(|f| !f.is_valid()) {
+            return Err(Error::InvalidFeedback(feedback));
+        }
+        if let Some(groups) = self.groups.filter(|g| !g.fits(self.size)) {
+            return Err(Error::InvalidGroups(groups));
+        }
+        if let Some(&shock) = self.shocks.iter().find(|s| !s.is_valid()) {
+            return Err(Error::InvalidShock(shock));
+        }
+        if let Some(&n) = [self.interaction, self.adaptation]
+            .iter()
+            .find(|n| !n.is_valid())
+        {
+            return Err(Error::InvalidNeighborhood(n));
+        }
+        if self.agents.is_none() && self.mix.is_none() && self.strategies.is_empty() {
+            return Err(Error::EmptyStrategyPool);
+        }
+        if let (None, Some((mix, _))) = (&self.agents, &self.mix) {
+            mix::exact_counts(mix, rows * cols)?;
+        }
+        Ok(())
+    }
+
+    /// The tables of the full grid, before vacancies or classes.
+    fn tables(&self) -> Tables {
+        let (rows, cols) = self.size;
+        Tables::new(rows, cols, self.interaction, self.adaptation)
+    }
+
+    /// A validated environment seeded with `seed` that shares `tables`.
+    fn assemble(&mut self, seed: u64, tables: &Tables) -> Result<Environment, Error> {
+        let (rows, cols) = self.size;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mixed = match (&self.agents, &self.mix) {
+            (None, Some((mix, placement))) => {
+                let counts = mix::exact_counts(mix, rows * cols)?;
+                Some(mix::place(&counts, *placement, &mut rng))
+            }
+            _ => None,
+        };
+        let class = |c: Coord| match self.asymmetric {
+            Some(_) => Class::checkerboard(c),
+            None => Class::Host,
+        };
+        let mut grid: Vec<Agent> = Vec::with_capacity(rows * cols);
+        for i in 0..rows {
+            for j in 0..cols {
+                let agent = match (&mut self.agents, &mixed) {
+                    (Some(agent_fn), _) => agent_fn((i, j), &mut rng),
+                    (None, Some(mixed)) => {
+                        Agent::new((i, j), mixed[i * cols + j]).with_class(class((i, j)))
+                    }
+                    (None, None) => Agent::random_with_rng((i, j), &self.strategies, &mut rng)?
+                        .with_class(class((i, j))),
+                };
+                if agent.coord != (i, j) {
+                    return Err(Error::MisplacedAgent {
+                        expected: (i, j),
+                        found: agent.coord,
+                    });
+                }
+                if !(agent.stake.is_finite() && agent.stake > 0.0) {
+                    return Err(Error::InvalidStake {
+                        coord: agent.coord,
+                        stake: agent.stake,
+                    });
+                }
+                grid.push(agent);
+            }
+        }
+        let mut env =
+            Environment::from_parts(self.size, self.noise, self.payoffs, grid, seed, rng, tables);
+        env.set_memory_window(self.memory_window)?;
+        env.set_update_mode(self.update_mode);
+        env.set_imitation(self.imitation);
+        env.set_mutation_sigma(self.mutation_sigma)?;
+        env.set_exploration_rate(self.exploration_rate)?;
+        if let Some(energy) = self.energy {
+            env.enable_energy(energy);
+        }
+        env.set_partner_choice(self.partner_choice);
+        env.set_signaling(self.signaling);
+        env.set_payoff_flow(self.payoff_flow);
+        env.set_coop_rate_map(self.coop_rate_map);
+        env.set_thumbnails(self.thumbnails);
+        env.set_non_finite_policy(self.non_finite);
+        env.set_observer(self.observer.clone());
+        env.set_groups(self.groups)?;
+        if let Some(feedback) = self.feedback {
+            env.enable_feedback(feedback);
+        }
+        env.set_shocks(self.shocks.clone())?;
+        if self.asymmetric.is_some() {
+            env.set_asymmetric(self.asymmetric)?;
+        }
+        if let Some(payoff_fn) = self.payoff_fn.clone() {
+            env.set_payoff_fn(move |ctx: &PayoffCtx<'_>| payoff_fn(ctx));
+        }
+        Ok(env)
+    }
+}
+
+/// A validated [`EnvironmentBuilder`] with its neighbor tables worked out, from which
+/// environments are stamped out by seed, e.g. the thousands of small runs a fixation
+/// probability needs. Every stamped environment shares the template's tables until cells
+/// empty or classes split it, and equals what the builder would have built with that seed.
+pub struct EnvironmentTemplate<'a> {
+    builder: EnvironmentBuilder<'a>,
+    tables: Tables,
+}
+
+impl EnvironmentTemplate<'_> {
+    /// A new environment seeded with `seed`. Fails only where an agent function misplaces an
+    /// agent or gives it an invalid stake, as `build` would.
+    pub fn stamp(&mut self, seed: u64) -> Result<Environment, Error> {
+        self.builder.assemble(seed, &self.tables)
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.builder.size
+    }
+}
+
+impl Default for EnvironmentBuilder<'_> {
+    fn default() -> Self {
+        EnvironmentBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+    use crate::Action;
+
+    #[test]
+    fn test_defaults() {
+        let env = EnvironmentBuilder::new().build().unwrap();
+        assert_eq!(env.dimensions(), DEFAULT_SIZE);
+        assert_eq!(env.noise(), 0.0);
+        assert_eq!(env.payoffs(), Payoffs::default());
+        assert_eq!(env.memory_window(), None);
+        let env = EnvironmentBuilder::new().memory_window(4).build().unwrap();
+        assert!(env.iter_agents().all(|(_, a)| a.memory_window() == Some(4)));
+        assert!(env
+            .iter_agents()
+            .all(|(_, a)| matches!(a.strategy, Strategy::Deflect | Strategy::TicToc)));
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let build = |b: EnvironmentBuilder| b.build().err();
+        assert_eq!(
+            build(EnvironmentBuilder::new().size(0, 5)),
+            Some(Error::EmptyGrid { rows: 0, cols: 5 })
+        );
+        assert_eq!(
+            build(EnvironmentBuilder::new().size(3, 0)),
+            Some(Error::EmptyGrid { rows: 3, cols: 0 })
+        );
+        assert_eq!(
+            build(EnvironmentBuilder::new().noise(1.5)),
+            Some(Error::InvalidNoise(1.5))
+        );
+        assert_eq!(
+            build(EnvironmentBuilder::new().noise(-0.1)),
+            Some(Error::InvalidNoise(-0.1))
+        );
+        assert!(matches!(
+            build(EnvironmentBuilder::new().noise(f32::NAN)),
+            Some(Error::InvalidNoise(_))
+        ));
+        let payoffs = Payoffs {
+            sucker: f64::INFINITY,
+            ..Payoffs::default()
+        };
+        assert_eq!(
+            build(EnvironmentBuilder::new().payoffs(payoffs)),
+            Some(Error::InvalidPayoffs(payoffs))
+        );
+        assert_eq!(
+            build(
+                EnvironmentBuilder::new()
+                    .size(2, 2)
+                    .agents(|_, _| Agent::new((0, 0), Strategy::Coop))
+            ),
+            Some(Error::MisplacedAgent {
+                expected: (0, 1),
+                found: (0, 0)
+            })
+        );
+        assert_eq!(
+            build(EnvironmentBuilder::new().memory_window(0)),
+            Some(Error::EmptyMemoryWindow)
+        );
+        assert_eq!(
+            build(EnvironmentBuilder::new().strategies(&[])),
+            Some(Error::EmptyStrategyPool)
+        );
+        // An agent function makes the pool irrelevant.
+        assert!(build(
+            EnvironmentBuilder::new()
+                .strategies(&[])
+                .agents(|c, _| Agent::new(c, Strategy::Coop))
+        )
+        .is_none());
+        assert!(Error::InvalidNoise(2.0).to_string().contains("[0, 1]"));
+    }
+
+    #[test]
+    fn test_matches_direct_constructor() {
+        let pool = [Strategy::Random, Strategy::TicToc];
+        let mut built = EnvironmentBuilder::new()
+            .size(6, 7)
+            .noise(0.1)
+            .seed(42)
+            .strategies(&pool)
+            .build()
+            .unwrap();
+        let mut direct = Environment::new_with_seed(6, 7, 0.1, 42, |c, rng| {
+            Agent::random_with_rng(c, &pool, rng).unwrap()
+        })
+        .unwrap();
+        assert_eq!(built.seed(), 42);
+        for _ in 0..5 {
+            assert_eq!(built.step(), direct.step());
+        }
+    }
+
+    #[test]
+    fn test_template_matches_builder() {
+        let configs: [fn() -> EnvironmentBuilder<'static>; 3] = [
+            || {
+                EnvironmentBuilder::new()
+                    .size(8, 9)
+                    .noise(0.05)
+                    .strategies(&[Strategy::Random, Strategy::TicToc, Strategy::Deflect])
+            },
+            || {
+                EnvironmentBuilder::new()
+                    .size(6, 6)
+                    .mix(
+                        &[(Strategy::Coop, 0.7), (Strategy::Deflect, 0.3)],
+                        Placement::Shuffled,
+                    )
+                    .adaptation_neighborhood(Neighborhood::VonNeumann(2))
+                    .energy(EnergyConfig::default())
+            },
+            || {
+                EnvironmentBuilder::new()
+                    .size(5, 7)
+                    .interaction_neighborhood(Neighborhood::VonNeumann(1))
+                    .asymmetric(Asymmetric {
+                        host: Payoffs::default(),
+                        visitor: Payoffs {
+                            reward: 2.0,
+                            ..Payoffs::default()
+                        },
+                    })
+                    .agents(|c, rng| {
+                        let agent = Agent::random_with_rng(c, &Strategy::ALL, rng).unwrap();
+                        agent.with_class(Class::checkerboard(c))
+                    })
+            },
+        ];
+        for config in configs {
+            let mut template = config().template().unwrap();
+            for seed in 0..4 {
+                let mut stamped = template.stamp(seed).unwrap();
+                let mut built = config().seed(seed).build().unwrap();
+                assert_eq!(stamped.seed(), seed);
+                for _ in 0..8 {
+                    assert_eq!(stamped.step(), built.step());
+                }
+            }
+        }
+        assert_eq!(
+            EnvironmentBuilder::new().size(0, 3).template().err(),
+            Some(Error::EmptyGrid { rows: 0, cols: 3 })
+        );
+        let mut misplacing = EnvironmentBuilder::new()
+            .size(2, 2)
+            .agents(|_, _| Agent::new((1, 1), Strategy::Coop))
+            .template()
+            .unwrap();
+        assert!(matches!(
+            misplacing.stamp(0),
+            Err(Error::MisplacedAgent { .. })
+        ));
+    }
+
+    #[test]
+    fn test_custom_payoffs() {
+        let payoffs = Payoffs {
+            reward: 1.0,
+            ..Payoffs::default()
+        };
+        assert_eq!(payoffs.score(Action::Coop, Action::Coop), 1.0);
+        assert_eq!(payoffs.score(Action::Deflect, Action::Coop), 4.0);
+        let mut env = EnvironmentBuilder::new()
+            .size(1, 2)
+            .payoffs(payoffs)
+            .agents(|c, _| Agent::new(c, Strategy::Coop))
+            .build()
+            .unwrap();
+        let metric = env.step();
+        assert_eq!(metric.scores, Grid::from(vec![vec![1.0, 1.0]]));
+    }
+}
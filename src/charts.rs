@@ -0,0 +1,287 @@
+use coop::{Coord, Metric, Strategy};
+
+/// Reduces `values` to at most `width` points by averaging equal-width buckets. Each point is
+/// `(x, y)` where `x` is the bucket's center in the original index space, so the x-axis keeps
+/// spanning the whole series.
+pub fn downsample(values: &[f64], width: usize) -> Vec<(f64, f64)> {
+    if width == 0 || values.is_empty() {
+        return Vec::new();
+    }
+    if values.len() <= width {
+        return values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as f64, *v))
+            .collect();
+    }
+    (0..width)
+        .map(|b| {
+            let start = b * values.len() / width;
+            let end = ((b + 1) * values.len() / width).max(start + 1);
+            let bucket = &values[start..end];
+            let mean = bucket.iter().sum::<f64>() / bucket.len() as f64;
+            ((start + end - 1) as f64 / 2.0, mean)
+        })
+        .collect()
+}
+
+/// Population of `strategy` at every buffered step.
+pub fn population_series<'a>(
+    buffer: impl IntoIterator<Item = &'a Metric>,
+    strategy: Strategy,
+) -> Vec<f64> {
+    buffer
+        .into_iter()
+        .map(|m| m.strategies.get(&strategy).cloned().unwrap_or(0) as f64)
+        .collect()
+}
+
+/// Cooperation rate at every buffered step.
+pub fn coop_rate_series<'a>(buffer: impl IntoIterator<Item = &'a Metric>) -> Vec<f64> {
+    buffer.into_iter().map(|m| m.coop_rate as f64).collect()
+}
+
+/// Strategy and score of the agent at `coord` at every buffered step that has one there.
+pub fn agent_series<'a>(
+    buffer: impl IntoIterator<Item = &'a Metric>,
+    coord: Coord,
+) -> Vec<(Strategy, f64)> {
+    let (r, c) = coord;
+    buffer
+        .into_iter()
+        .filter_map(|m| {
+            let strategy = m.snapshot.occupant(r, c)?;
+            let score = *m.scores.get(r, c)?;
+            Some((strategy, score))
+        })
+        .collect()
+}
+
+/// Maps the x of points computed over a sparse series from series index to step number,
+/// interpolating between the steps of neighboring indices. `steps[i]` is the step of index `i`.
+pub fn index_to_step(points: &[(f64, f64)], steps: &[usize]) -> Vec<(f64, f64)> {
+    let Some(last) = steps.len().checked_sub(1) else {
+        return Vec::new();
+    };
+    points
+        .iter()
+        .map(|(x, y)| {
+            let x = x.clamp(0.0, last as f64);
+            let (lo, hi) = (x.floor() as usize, x.ceil() as usize);
+            let step = steps[lo] as f64 + (x - lo as f64) * (steps[hi] as f64 - steps[lo] as f64);
+            (step, *y)
+        })
+        .collect()
+}
+
+/// Trailing moving average: each output is the mean of up to `window` values ending at that
+/// index. A window of 0 or 1 returns the input unchanged.
+pub fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    let window = window.max(1);
+    let mut sum = 0.0;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            sum += v;
+            if i >= window {
+                sum -= values[i - window];
+            }
+            sum / (i + 1).min(window) as f64
+        })
+        .collect()
+}
+
+/// Y-axis bounds covering `values`. Falls back to `[0, 1]` for an empty series and widens a
+/// flat series by half a unit on each side so the line isn't drawn on the border.
+pub fn axis_bounds(values: &[f64]) -> [f64; 2] {
+    let (min, max) = values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(*v), hi.max(*v))
+        });
+    if min > max {
+        [0.0, 1.0]
+    } else if min == max {
+        [min - 0.5, max + 0.5]
+    } else {
+        [min, max]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coop::Grid;
+
+    #[test]
+    fn test_short_series_unchanged() {
+        assert_eq!(
+            downsample(&[1.0, 2.0, 3.0], 10),
+            vec![(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)]
+        );
+        assert_eq!(downsample(&[1.0, 2.0], 2), vec![(0.0, 1.0), (1.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_empty_and_zero_width() {
+        assert!(downsample(&[], 10).is_empty());
+        assert!(downsample(&[1.0], 0).is_empty());
+    }
+
+    #[test]
+    fn test_even_buckets() {
+        let values: Vec<f64> = (0..8).map(|i| i as f64).collect();
+        assert_eq!(
+            downsample(&values, 4),
+            vec![(0.5, 0.5), (2.5, 2.5), (4.5, 4.5), (6.5, 6.5)]
+        );
+    }
+
+    #[test]
+    fn test_uneven_buckets_cover_everything() {
+        let values: Vec<f64> = vec![1.0; 1001];
+        let points = downsample(&values, 80);
+        assert_eq!(points.len(), 80);
+        assert!(points.iter().all(|(_, y)| *y == 1.0));
+        assert!(points.first().unwrap().0 < 7.0);
+        assert!(points.last().unwrap().0 > 990.0);
+        assert!(points.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(moving_average(&values, 1), values.to_vec());
+        assert_eq!(moving_average(&values, 0), values.to_vec());
+        assert_eq!(moving_average(&values, 2), vec![1.0, 1.5, 2.5, 3.5, 4.5]);
+        assert_eq!(moving_average(&values, 10), vec![1.0, 1.5, 2.0, 2.5, 3.0]);
+        assert!(moving_average(&[], 3).is_empty());
+        assert_eq!(moving_average(&[0.25], 3), vec![0.25]);
+    }
+
+    #[test]
+    fn test_axis_bounds() {
+        assert_eq!(axis_bounds(&[]), [0.0, 1.0]);
+        assert_eq!(axis_bounds(&[0.5]), [0.0, 1.0]);
+        assert_eq!(axis_bounds(&[0.2, 0.2]), [-0.3, 0.7]);
+        assert_eq!(axis_bounds(&[0.3, 0.1, 0.9]), [0.1, 0.9]);
+    }
+
+    #[test]
+    fn test_index_to_step() {
+        let steps = [0, 100, 200, 201, 202];
+        let points = [(0.0, 1.0), (1.5, 2.0), (3.0, 3.0), (4.0, 4.0)];
+        assert_eq!(
+            index_to_step(&points, &steps),
+            vec![(0.0, 1.0), (150.0, 2.0), (201.0, 3.0), (202.0, 4.0)]
+        );
+        // Dense steps leave x unchanged.
+        assert_eq!(
+            index_to_step(&[(2.5, 0.0)], &[0, 1, 2, 3]),
+            vec![(2.5, 0.0)]
+        );
+        assert!(index_to_step(&points, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_agent_series() {
+        let metric = |snapshot: Vec<Vec<Strategy>>, scores: Vec<Vec<f64>>| Metric {
+            strategies: Default::default(),
+            max_score: Default::default(),
+            avg_score: Default::default(),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 0,
+            coop_actions: 0,
+            coop_rate: 0.0,
+            snapshot: snapshot.into(),
+            founder_lineages: 0,
+            population: 0,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: scores.into(),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        };
+        use Strategy::{Coop as C, Deflect as D};
+        let buffer = vec![
+            metric(
+                vec![vec![C, D], vec![D, D]],
+                vec![vec![0.0, 1.0], vec![2.0, 3.0]],
+            ),
+            metric(
+                vec![vec![C, C], vec![D, C]],
+                vec![vec![4.0, 5.0], vec![6.0, 7.0]],
+            ),
+        ];
+        assert_eq!(agent_series(&buffer, (0, 1)), vec![(D, 1.0), (C, 5.0)]);
+        assert_eq!(agent_series(&buffer, (1, 0)), vec![(D, 2.0), (D, 6.0)]);
+        assert!(agent_series(&buffer, (2, 0)).is_empty());
+        assert!(agent_series(&[], (0, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_population_series() {
+        let mut a = Metric {
+            strategies: Default::default(),
+            max_score: Default::default(),
+            avg_score: Default::default(),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 0,
+            coop_actions: 0,
+            coop_rate: 0.0,
+            snapshot: Default::default(),
+            founder_lineages: 0,
+            population: 0,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: Grid::default(),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        };
+        a.strategies.insert(Strategy::Coop, 3);
+        let mut b = a.clone();
+        b.strategies.insert(Strategy::Coop, 5);
+        b.strategies.insert(Strategy::Deflect, 1);
+        let buffer = vec![a, b];
+        assert_eq!(population_series(&buffer, Strategy::Coop), vec![3.0, 5.0]);
+        assert_eq!(
+            population_series(&buffer, Strategy::Deflect),
+            vec![0.0, 1.0]
+        );
+    }
+}
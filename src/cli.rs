@@ -0,0 +1,424 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use coop::{presets, Payoffs, Preset, StopCondition};
+
+use crate::config::Override;
+use crate::palette::{Palette, PALETTES};
+use crate::playback::{PlaybackEnd, PLAYBACK_ENDS};
+
+/// Full metrics kept for the most recent steps by default.
+pub const DEFAULT_HISTORY: usize = 2000;
+/// Older steps kept as keyframes by default: one in this many.
+pub const DEFAULT_KEYFRAME_EVERY: usize = 500;
+/// Steps between group competitions by default.
+pub const DEFAULT_GROUP_EVERY: usize = 50;
+/// Region a shock hits by default.
+pub const DEFAULT_SHOCK_SIZE: (usize, usize) = (5, 5);
+
+/// Command line options.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Args {
+    pub palette: Palette,
+    /// Number of recent steps whose metrics are kept in full.
+    pub history: usize,
+    /// Older steps are thinned to every `keyframe_every`-th one.
+    pub keyframe_every: usize,
+    /// Steps at the start of the run left out of summary statistics.
+    pub burn_in: usize,
+    /// Keep only thumbnails of the steps older than the `history` most recent ones.
+    pub compact_history: bool,
+    /// Keep stepping while browsing history instead of pausing.
+    pub keep_running: bool,
+    /// What history playback does when it reaches the newest step.
+    pub playback_end: PlaybackEnd,
+    /// Run a second simulation side by side, with these changes to the first one's config.
+    pub compare: Option<Vec<Override>>,
+    /// Show the setup screen before the run; any command line argument skips it.
+    pub setup: bool,
+    /// Tile the grid into competing groups of `(rows, cols)`.
+    pub groups: Option<(usize, usize)>,
+    /// Steps between group competitions.
+    pub group_every: usize,
+    /// Scramble a random region every this many steps.
+    pub shock_every: Option<usize>,
+    /// Scramble random regions this many times per step on average.
+    pub shock_rate: Option<f64>,
+    /// `(rows, cols)` of the regions shocks hit.
+    pub shock_size: (usize, usize),
+    /// Play an asymmetric game on a checkerboard of hosts and visitors, visitors earning
+    /// these payoffs and hosts the standard ones.
+    pub visitor_payoffs: Option<Payoffs>,
+    /// Write charts of the run here on quitting, as SVG or PNG by extension.
+    pub plot: Option<PathBuf>,
+    /// Pause the run once any of these holds for the primary run.
+    pub stop: Vec<StopCondition>,
+    /// Pause the run once it has gone on this long in wall-clock time.
+    pub max_seconds: Option<Duration>,
+    /// Run this experiment instead of the default mix.
+    pub preset: Option<Preset>,
+    /// Print the presets and exit.
+    pub list_presets: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            palette: Palette::default(),
+            history: DEFAULT_HISTORY,
+            keyframe_every: DEFAULT_KEYFRAME_EVERY,
+            burn_in: 0,
+            compact_history: false,
+            keep_running: false,
+            playback_end: PlaybackEnd::default(),
+            compare: None,
+            setup: true,
+            groups: None,
+            group_every: DEFAULT_GROUP_EVERY,
+            shock_every: None,
+            shock_rate: None,
+            shock_size: DEFAULT_SHOCK_SIZE,
+            visitor_payoffs: None,
+            plot: None,
+            stop: Vec::new(),
+            max_seconds: None,
+            preset: None,
+            list_presets: false,
+        }
+    }
+}
+
+impl Args {
+    /// Parses the arguments (without the program name). Flags take their value either as the
+    /// next argument or after `=`, e.g. `--palette mono` or `--history=5000`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            parsed.setup = false;
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--palette" => {
+                    let name = value()?;
+                    parsed.palette = Palette::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = PALETTES.iter().map(|p| p.name()).collect();
+                        format!(
+                            "unknown palette {:?}, expected one of {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                }
+                "--history" => parsed.history = positive(&flag, &value()?)?,
+                "--keyframe-every" => parsed.keyframe_every = positive(&flag, &value()?)?,
+                "--burn-in" => {
+                    let steps = value()?;
+                    parsed.burn_in = steps.parse().map_err(|_| {
+                        format!("{} expects a number of steps, got {:?}", flag, steps)
+                    })?;
+                }
+                "--playback-end" => {
+                    let name = value()?;
+                    parsed.playback_end = PlaybackEnd::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = PLAYBACK_ENDS.iter().map(|e| e.name()).collect();
+                        format!(
+                            "unknown playback end {:?}, expected one of {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                }
+                "--compare" => parsed.compare = Some(Override::parse_list(&value()?)?),
+                "--groups" => parsed.groups = Some(size(&flag, &value()?)?),
+                "--group-every" => parsed.group_every = positive(&flag, &value()?)?,
+                "--shock-every" => parsed.shock_every = Some(positive(&flag, &value()?)?),
+                "--shock-rate" => {
+                    let rate = value()?;
+                    parsed.shock_rate = match rate.parse::<f64>() {
+                        Ok(r) if r.is_finite() && r > 0.0 => Some(r),
+                        _ => {
+                            return Err(format!(
+                                "{} expects a positive number, got {:?}",
+                                flag, rate
+                            ))
+                        }
+                    };
+                }
+                "--shock-size" => parsed.shock_size = size(&flag, &value()?)?,
+                "--visitor-payoffs" => {
+                    let text = value()?;
+                    let invalid = || format!("{} expects R,T,S,P, got {:?}", flag, text);
+                    let values = text
+                        .split(',')
+                        .map(|v| v.trim().parse::<f64>().ok().filter(|v| v.is_finite()))
+                        .collect::<Option<Vec<f64>>>()
+                        .ok_or_else(invalid)?;
+                    let [reward, temptation, sucker, punishment] = values[..] else {
+                        return Err(invalid());
+                    };
+                    parsed.visitor_payoffs = Some(Payoffs {
+                        reward,
+                        temptation,
+                        sucker,
+                        punishment,
+                    });
+                }
+                "--stop" => parsed
+                    .stop
+                    .push(value()?.parse().map_err(|e| format!("{}: {}", flag, e))?),
+                "--max-seconds" => {
+                    let seconds = value()?;
+                    parsed.max_seconds = match seconds.parse::<f64>() {
+                        Ok(s) if s.is_finite() && s > 0.0 => Some(Duration::from_secs_f64(s)),
+                        _ => {
+                            return Err(format!(
+                                "{} expects a positive number of seconds, got {:?}",
+                                flag, seconds
+                            ))
+                        }
+                    };
+                }
+                "--preset" => {
+                    let name = value()?;
+                    parsed.preset = Some(presets::by_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = presets::all().iter().map(|p| p.name).collect();
+                        format!(
+                            "unknown preset {:?}, expected one of {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?);
+                }
+                #[cfg(feature = "plots")]
+                "--plot" => parsed.plot = Some(value()?.into()),
+                "--keep-running" if inline.is_none() => parsed.keep_running = true,
+                "--compact-history" if inline.is_none() => parsed.compact_history = true,
+                "--list-presets" if inline.is_none() => parsed.list_presets = true,
+                _ => return Err(format!("unknown argument: {}", flag)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A `ROWSxCOLS` size with both sides positive.
+fn size(flag: &str, value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("{} expects ROWSxCOLS, got {:?}", flag, value);
+    let (rows, cols) = value.split_once('x').ok_or_else(invalid)?;
+    Ok((
+        positive(flag, rows).map_err(|_| invalid())?,
+        positive(flag, cols).map_err(|_| invalid())?,
+    ))
+}
+
+fn positive(flag: &str, value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "{} expects a positive integer, got {:?}",
+            flag, value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(&[]), Ok(Args::default()));
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(
+            parse(&["--palette", "mono"]).unwrap().palette,
+            Palette::Mono
+        );
+        assert_eq!(
+            parse(&["--palette=colorblind"]).unwrap().palette,
+            Palette::Colorblind
+        );
+        assert!(parse(&["--palette"]).is_err());
+        assert!(parse(&["--palette", "sepia"]).is_err());
+        assert!(parse(&["--palettes=mono"]).is_err());
+    }
+
+    #[test]
+    fn test_history_flags() {
+        let args = parse(&["--history", "100", "--keyframe-every=7"]).unwrap();
+        assert_eq!((args.history, args.keyframe_every), (100, 7));
+        assert_eq!(args.palette, Palette::Classic);
+        assert!(parse(&["--history", "0"]).is_err());
+        assert!(parse(&["--history", "-3"]).is_err());
+        assert!(parse(&["--keyframe-every", "lots"]).is_err());
+        assert_eq!(parse(&["--burn-in=0"]).unwrap().burn_in, 0);
+        assert_eq!(parse(&["--burn-in", "250"]).unwrap().burn_in, 250);
+        assert!(parse(&["--burn-in", "-1"]).is_err());
+        assert!(parse(&["--speed"]).is_err());
+    }
+
+    #[test]
+    fn test_switches() {
+        assert!(!Args::default().keep_running);
+        assert!(parse(&["--keep-running"]).unwrap().keep_running);
+        assert!(parse(&["--keep-running=yes"]).is_err());
+        assert!(!Args::default().compact_history);
+        assert!(parse(&["--compact-history"]).unwrap().compact_history);
+    }
+
+    #[test]
+    fn test_playback_end() {
+        assert_eq!(Args::default().playback_end, PlaybackEnd::Stop);
+        assert_eq!(
+            parse(&["--playback-end", "live"]).unwrap().playback_end,
+            PlaybackEnd::Live
+        );
+        assert!(parse(&["--playback-end=loop"]).is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(Args::default().compare, None);
+        assert_eq!(
+            parse(&["--compare", "noise=0.3"]).unwrap().compare,
+            Some(vec![Override::Noise(0.3)])
+        );
+        assert!(parse(&["--compare"]).is_err());
+        assert!(parse(&["--compare=volume=11"]).is_err());
+    }
+
+    #[test]
+    fn test_groups() {
+        assert_eq!(Args::default().groups, None);
+        let args = parse(&["--groups", "10x5", "--group-every=20"]).unwrap();
+        assert_eq!((args.groups, args.group_every), (Some((10, 5)), 20));
+        assert_eq!(
+            parse(&["--groups=4x4"]).unwrap().group_every,
+            DEFAULT_GROUP_EVERY
+        );
+        for bad in ["10", "0x5", "4x", "axb", "4x4x4"] {
+            assert!(parse(&["--groups", bad]).is_err(), "{:?}", bad);
+        }
+        assert!(parse(&["--group-every", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_shocks() {
+        let args = parse(&["--shock-every", "30", "--shock-size=3x8"]).unwrap();
+        assert_eq!(
+            (args.shock_every, args.shock_rate, args.shock_size),
+            (Some(30), None, (3, 8))
+        );
+        let args = parse(&["--shock-rate", "0.05"]).unwrap();
+        assert_eq!(
+            (args.shock_rate, args.shock_size),
+            (Some(0.05), DEFAULT_SHOCK_SIZE)
+        );
+        for bad in ["0", "-1", "often", "inf"] {
+            assert!(parse(&["--shock-rate", bad]).is_err(), "{:?}", bad);
+        }
+        assert!(parse(&["--shock-every", "0"]).is_err());
+        assert!(parse(&["--shock-size", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_visitor_payoffs() {
+        assert_eq!(Args::default().visitor_payoffs, None);
+        let payoffs = parse(&["--visitor-payoffs", "2,4,-1,0.5"])
+            .unwrap()
+            .visitor_payoffs
+            .unwrap();
+        assert_eq!(
+            [
+                payoffs.reward,
+                payoffs.temptation,
+                payoffs.sucker,
+                payoffs.punishment
+            ],
+            [2.0, 4.0, -1.0, 0.5]
+        );
+        for bad in ["2,4,-1", "2,4,-1,0,3", "2,4,x,0", "2,4,inf,0", ""] {
+            assert!(parse(&["--visitor-payoffs", bad]).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_stop() {
+        assert!(Args::default().stop.is_empty());
+        let args = parse(&["--stop", "coop_below=0.05:100", "--stop=extinct=tft"]).unwrap();
+        assert_eq!(
+            args.stop,
+            vec![
+                StopCondition::CoopRateBelow(0.05, 100),
+                StopCondition::StrategyExtinct(coop::Strategy::TicToc)
+            ]
+        );
+        for bad in ["coop_below", "coop_below=2", "max_steps=-1", "volume=11"] {
+            assert!(parse(&["--stop", bad]).is_err(), "{:?}", bad);
+        }
+        assert!(parse(&["--stop"]).is_err());
+    }
+
+    #[test]
+    fn test_max_seconds() {
+        assert_eq!(Args::default().max_seconds, None);
+        assert_eq!(
+            parse(&["--max-seconds", "1.5"]).unwrap().max_seconds,
+            Some(Duration::from_millis(1500))
+        );
+        for bad in ["0", "-3", "inf", "soon"] {
+            assert!(parse(&["--max-seconds", bad]).is_err(), "{:?}", bad);
+        }
+        // The same limit can be given as a stop condition.
+        assert_eq!(
+            parse(&["--stop=max_seconds=60"]).unwrap().stop,
+            vec![StopCondition::WallClock(Duration::from_secs(60))]
+        );
+    }
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(Args::default().preset, None);
+        assert_eq!(
+            parse(&["--preset", "single_invader"]).unwrap().preset,
+            Some(presets::single_invader())
+        );
+        assert!(parse(&["--preset=kaleidoscope"]).is_err());
+        assert!(parse(&["--preset"]).is_err());
+        assert!(parse(&["--list-presets"]).unwrap().list_presets);
+        assert!(parse(&["--list-presets=all"]).is_err());
+    }
+
+    #[cfg(feature = "plots")]
+    #[test]
+    fn test_plot() {
+        assert_eq!(Args::default().plot, None);
+        assert_eq!(
+            parse(&["--plot", "run.svg"]).unwrap().plot,
+            Some(PathBuf::from("run.svg"))
+        );
+        assert!(parse(&["--plot"]).is_err());
+    }
+
+    #[test]
+    fn test_flags_skip_setup() {
+        assert!(parse(&[]).unwrap().setup);
+        assert!(!parse(&["--palette", "classic"]).unwrap().setup);
+        assert!(!parse(&["--keep-running"]).unwrap().setup);
+    }
+}
@@ -0,0 +1,206 @@
+//! Colors for strategies, shared by everything that draws a grid or a chart. The builtin
+//! strategies have fixed colors; any other strategy, such as a variant with its own
+//! parameters, gets the next color of a cycle the first time it's drawn and keeps it.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::agent::{ParseStrategyError, Strategy};
+
+/// An sRGB color.
+pub type Rgb = [u8; 3];
+
+/// Colors of `Strategy::ALL`, in that order; the same as in `examples/web`.
+pub const BUILTIN_COLORS: [Rgb; 11] = [
+    [205, 49, 49],
+    [229, 229, 16],
+    [13, 188, 121],
+    [188, 63, 188],
+    [17, 168, 205],
+    [35, 209, 139],
+    [214, 112, 214],
+    [36, 114, 200],
+    [245, 245, 67],
+    [241, 76, 76],
+    [41, 184, 219],
+];
+
+/// Colors handed out, in order, to strategies without one of their own (Tableau 10).
+pub const CYCLE_COLORS: [Rgb; 10] = [
+    [78, 121, 167],
+    [242, 142, 43],
+    [225, 87, 89],
+    [118, 183, 178],
+    [89, 161, 79],
+    [237, 201, 72],
+    [176, 122, 161],
+    [255, 157, 167],
+    [156, 117, 95],
+    [186, 176, 172],
+];
+
+/// Which color each strategy is drawn in: an override set by name if there is one, else the
+/// strategy's builtin color, else one assigned from the cycle on first sight. Assignments are
+/// remembered, so a strategy keeps its color for as long as the palette lives.
+///
+/// The color type is a parameter so front ends can use their own, e.g. named terminal colors.
+#[derive(Debug)]
+pub struct Palette<C = Rgb> {
+    builtin: Vec<(Strategy, C)>,
+    cycle: Vec<C>,
+    /// By strategy kind, so an override covers every parameterization.
+    overrides: BTreeMap<Strategy, C>,
+    assigned: Mutex<Vec<(Strategy, C)>>,
+}
+
+impl<C: Copy> Palette<C> {
+    /// A palette with these colors for particular strategies, handing out `cycle` to the
+    /// rest. With an empty cycle the rest are drawn in their kind's color.
+    pub fn new(
+        builtin: impl IntoIterator<Item = (Strategy, C)>,
+        cycle: impl IntoIterator<Item = C>,
+    ) -> Palette<C> {
+        Palette {
+            builtin: builtin.into_iter().collect(),
+            cycle: cycle.into_iter().collect(),
+            overrides: BTreeMap::new(),
+            assigned: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Draws every strategy named `name` (a full name or alias, any case) in `color`, whatever
+    /// its parameters.
+    pub fn set(&mut self, name: &str, color: C) -> Result<(), ParseStrategyError> {
+        let strategy: Strategy = name.parse()?;
+        self.overrides.insert(strategy.kind(), color);
+        Ok(())
+    }
+
+    /// The color of `strategy`, assigning it one if it has none yet.
+    pub fn color(&self, strategy: Strategy) -> C {
+        if let Some(color) = self.overrides.get(&strategy.kind()) {
+            return *color;
+        }
+        if let Some(color) = find(&self.builtin, strategy) {
+            return color;
+        }
+        let mut assigned = self.assigned.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(color) = find(&assigned, strategy) {
+            return color;
+        }
+        let color = if self.cycle.is_empty() {
+            find(&self.builtin, strategy.kind())
+                .or_else(|| self.builtin.first().map(|(_, c)| *c))
+                .expect("a palette needs at least one color")
+        } else {
+            self.cycle[assigned.len() % self.cycle.len()]
+        };
+        assigned.push((strategy, color));
+        color
+    }
+
+    /// Strategies assigned a color from the cycle so far, in order of first sight.
+    pub fn assigned(&self) -> Vec<(Strategy, C)> {
+        self.assigned
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+fn find<C: Copy>(colors: &[(Strategy, C)], strategy: Strategy) -> Option<C> {
+    colors.iter().find(|(s, _)| *s == strategy).map(|(_, c)| *c)
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new(Strategy::ALL.into_iter().zip(BUILTIN_COLORS), CYCLE_COLORS)
+    }
+}
+
+impl<C: Copy> Clone for Palette<C> {
+    fn clone(&self) -> Self {
+        Palette {
+            builtin: self.builtin.clone(),
+            cycle: self.cycle.clone(),
+            overrides: self.overrides.clone(),
+            assigned: Mutex::new(self.assigned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Prob;
+    use Strategy::{Coop as C, Deflect as D, GenerousTFT, RandomP};
+
+    fn generous(millionths: u32) -> Strategy {
+        GenerousTFT(Prob::from_millionths(millionths))
+    }
+
+    #[test]
+    fn test_builtin_colors() {
+        let palette = Palette::default();
+        assert_eq!(palette.color(D), [205, 49, 49]);
+        for (s, color) in Strategy::ALL.into_iter().zip(BUILTIN_COLORS) {
+            assert_eq!(palette.color(s), color);
+        }
+        assert!(palette.assigned().is_empty());
+    }
+
+    #[test]
+    fn test_assignment_is_stable() {
+        let palette = Palette::default();
+        let variants = [
+            generous(100_000),
+            RandomP(Prob::from_millionths(1)),
+            generous(900_000),
+        ];
+        let first: Vec<Rgb> = variants.iter().map(|s| palette.color(*s)).collect();
+        assert_eq!(first, CYCLE_COLORS[..3]);
+        // Asking again, in another order, changes nothing.
+        for s in variants.iter().rev() {
+            assert_eq!(
+                palette.color(*s),
+                first[variants.iter().position(|v| v == s).unwrap()]
+            );
+        }
+        let order: Vec<Strategy> = palette.assigned().into_iter().map(|(s, _)| s).collect();
+        assert_eq!(order, variants);
+        assert_eq!(palette.clone().color(variants[1]), first[1]);
+    }
+
+    #[test]
+    fn test_parameterized_variants_distinct() {
+        let palette = Palette::default();
+        let (a, b) = (generous(100_000), generous(200_000));
+        assert_ne!(palette.color(a), palette.color(b));
+        assert_ne!(
+            palette.color(a),
+            palette.color(GenerousTFT(Prob::from_millionths(333_333)))
+        );
+    }
+
+    #[test]
+    fn test_override_precedence() {
+        let mut palette = Palette::default();
+        let variant = generous(100_000);
+        let assigned = palette.color(variant);
+        palette.set("gtft", [1, 2, 3]).unwrap();
+        palette.set("Deflect", [4, 5, 6]).unwrap();
+        assert_eq!(palette.color(variant), [1, 2, 3]);
+        assert_eq!(palette.color(Strategy::ALL[8]), [1, 2, 3]);
+        assert_eq!(palette.color(D), [4, 5, 6]);
+        assert_eq!(palette.color(C), BUILTIN_COLORS[2]);
+        assert!(palette.set("Grudger", [0, 0, 0]).is_err());
+        assert_eq!(palette.assigned(), vec![(variant, assigned)]);
+    }
+
+    #[test]
+    fn test_empty_cycle_falls_back_to_kind() {
+        let palette = Palette::new([(D, 'd'), (Strategy::ALL[8], 'g')], []);
+        assert_eq!(palette.color(generous(5)), 'g');
+        assert_eq!(palette.color(C), 'd');
+    }
+}
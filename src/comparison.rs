@@ -0,0 +1,151 @@
+use crate::env::Environment;
+use crate::history::History;
+
+/// A simulation and the metrics recorded from it.
+pub struct Run {
+    pub env: Environment,
+    pub history: History,
+}
+
+impl Run {
+    /// Wraps `env` and records its first step into `history`.
+    pub fn new(mut env: Environment, mut history: History) -> Run {
+        history.clear();
+        history.push(env.step());
+        Run { env, history }
+    }
+
+    /// Swaps in a new environment, keeping the history's retention policy.
+    pub fn restart(&mut self, env: Environment) {
+        self.env = env;
+        self.history.clear();
+        self.history.push(self.env.step());
+    }
+}
+
+/// Runs stepped in lockstep, so every history holds the same step numbers. The first run is
+/// the primary one whose history drives navigation.
+pub struct ComparisonRun {
+    runs: Vec<Run>,
+}
+
+impl ComparisonRun {
+    /// Panics if `runs` is empty.
+    pub fn new(runs: Vec<Run>) -> ComparisonRun {
+        assert!(!runs.is_empty(), "a comparison needs at least one run");
+        ComparisonRun { runs }
+    }
+
+    /// Advances every run by one step and returns the new step number.
+    pub fn step(&mut self) -> usize {
+        let mut step = 0;
+        for run in &mut self.runs {
+            step = run.history.push(run.env.step());
+        }
+        step
+    }
+
+    pub fn primary(&self) -> &Run {
+        &self.runs[0]
+    }
+
+    pub fn runs(&self) -> &[Run] {
+        &self.runs
+    }
+
+    pub fn runs_mut(&mut self) -> &mut [Run] {
+        &mut self.runs
+    }
+
+    /// Bookmarks `step` in every run, or removes the bookmarks, so all runs keep it retained.
+    /// Returns whether it's now bookmarked.
+    pub fn toggle_bookmark(&mut self, step: usize) -> bool {
+        let mut bookmarked = false;
+        for run in &mut self.runs {
+            bookmarked = run.history.toggle_bookmark(step);
+        }
+        bookmarked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Agent, Strategy};
+
+    fn env(seed: u64, noise: f32) -> Environment {
+        Environment::new_with_seed(6, 6, noise, seed, |c, rng| {
+            Agent::random_with_rng(c, &[Strategy::Random, Strategy::TicToc], rng).unwrap()
+        })
+        .unwrap()
+    }
+
+    fn run(seed: u64, noise: f32) -> Run {
+        Run::new(env(seed, noise), History::new(100, 10))
+    }
+
+    #[test]
+    fn test_runs_advance_together() {
+        let mut comparison = ComparisonRun::new(vec![run(1, 0.1), run(1, 0.3)]);
+        assert_eq!(comparison.step(), 1);
+        assert_eq!(comparison.step(), 2);
+        for run in comparison.runs() {
+            assert_eq!(run.history.len(), 3);
+        }
+        assert_eq!(comparison.primary().env.noise(), 0.1);
+    }
+
+    #[test]
+    fn test_runs_are_independent() {
+        let mut alone = run(7, 0.2);
+        for _ in 0..5 {
+            alone.history.push(alone.env.step());
+        }
+        let mut comparison = ComparisonRun::new(vec![run(3, 0.2), run(7, 0.2)]);
+        for _ in 0..5 {
+            comparison.step();
+        }
+        // Stepping next to another run doesn't disturb either one's RNG.
+        let paired = &comparison.runs()[1].history;
+        for (step, metric) in alone.history.iter() {
+            assert_eq!(paired.get(step), Some(metric));
+        }
+        assert_ne!(
+            comparison.runs()[0].history.get(5),
+            comparison.runs()[1].history.get(5)
+        );
+    }
+
+    #[test]
+    fn test_bookmarks_apply_to_every_run() {
+        let mut comparison = ComparisonRun::new(vec![run(1, 0.1), run(2, 0.1)]);
+        comparison.step();
+        assert!(comparison.toggle_bookmark(1));
+        assert!(comparison
+            .runs()
+            .iter()
+            .all(|r| r.history.bookmarks().contains(1)));
+        assert!(!comparison.toggle_bookmark(1));
+        assert!(comparison
+            .runs()
+            .iter()
+            .all(|r| r.history.bookmarks().is_empty()));
+    }
+
+    #[test]
+    fn test_restart_keeps_policy() {
+        let mut run = run(1, 0.1);
+        for _ in 0..20 {
+            run.history.push(run.env.step());
+        }
+        run.restart(env(2, 0.1));
+        assert_eq!(run.history.len(), 1);
+        assert_eq!(run.env.seed(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_needs_a_run() {
+        ComparisonRun::new(vec![]);
+    }
+}
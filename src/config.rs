@@ -0,0 +1,332 @@
+use coop::presets::Start;
+use coop::{Agent, Asymmetric, Class, Environment, Error, Groups, Preset, Shock, Strategy};
+use rand::{thread_rng, Rng};
+
+/// Everything needed to (re)build an environment, kept so the TUI can restart a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimConfig {
+    pub rows: usize,
+    pub cols: usize,
+    pub noise: f32,
+    pub seed: u64,
+    /// Initial strategy probabilities; whatever the list doesn't cover falls to the last entry.
+    pub mix: Vec<(Strategy, f32)>,
+    /// Groups that compete every so often, if any.
+    pub groups: Option<Groups>,
+    /// Disruptions that scramble regions of the grid.
+    pub shocks: Vec<Shock>,
+    /// An asymmetric game between a checkerboard of hosts and visitors, if any.
+    pub asymmetric: Option<Asymmetric>,
+    /// The experiment whose payoffs, update mode and starting grid the run uses instead of
+    /// the defaults and `mix`, if any. Size, noise and seed still come from above. Boxed so
+    /// the config stays small enough to pass around by value.
+    pub preset: Option<Box<Preset>>,
+}
+
+impl SimConfig {
+    /// The configuration of `preset`, as shipped.
+    pub fn from_preset(preset: &Preset) -> SimConfig {
+        SimConfig {
+            rows: preset.rows,
+            cols: preset.cols,
+            noise: preset.noise,
+            seed: preset.seed,
+            mix: match &preset.start {
+                Start::Mix(mix) => mix.clone(),
+                Start::Invader { resident, .. } => vec![(*resident, 1.0)],
+            },
+            preset: Some(Box::new(preset.clone())),
+            ..SimConfig::default()
+        }
+    }
+
+    pub fn build(&self) -> Result<Environment, Error> {
+        let mut env = match &self.preset {
+            Some(preset) => {
+                let preset = Preset {
+                    rows: self.rows,
+                    cols: self.cols,
+                    noise: self.noise,
+                    seed: self.seed,
+                    ..Preset::clone(preset)
+                };
+                let builder = preset.builder();
+                match self.asymmetric {
+                    Some(game) => builder.asymmetric(game),
+                    None => builder,
+                }
+                .build()?
+            }
+            None => Environment::new_with_seed(
+                self.rows,
+                self.cols,
+                self.noise,
+                self.seed,
+                |c, rng| {
+                    let agent = Agent::new(c, pick_strategy(&self.mix, rng.gen()));
+                    match self.asymmetric {
+                        Some(_) => agent.with_class(Class::checkerboard(c)),
+                        None => agent,
+                    }
+                },
+            )?,
+        };
+        env.set_groups(self.groups)?;
+        env.set_shocks(self.shocks.clone())?;
+        env.set_asymmetric(self.asymmetric)?;
+        Ok(env)
+    }
+
+    /// This configuration with `overrides` applied in order.
+    pub fn with_overrides(&self, overrides: &[Override]) -> SimConfig {
+        let mut config = self.clone();
+        for o in overrides {
+            match *o {
+                Override::Rows(rows) => config.rows = rows,
+                Override::Cols(cols) => config.cols = cols,
+                Override::Noise(noise) => config.noise = noise,
+                Override::Seed(seed) => config.seed = seed,
+            }
+        }
+        config
+    }
+
+    /// The same configuration with a fresh random seed.
+    pub fn reseeded(&self) -> SimConfig {
+        SimConfig {
+            seed: thread_rng().gen(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            rows: 50,
+            cols: 50,
+            noise: 0.1,
+            seed: thread_rng().gen(),
+            mix: vec![
+                (Strategy::Coop, 0.0),
+                (Strategy::Random, 0.1),
+                (Strategy::TicToc, 0.4),
+                (Strategy::Deflect, 0.5),
+            ],
+            groups: None,
+            shocks: Vec::new(),
+            asymmetric: None,
+            preset: None,
+        }
+    }
+}
+
+/// A single parameter change, e.g. for the second run of a comparison.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Override {
+    Rows(usize),
+    Cols(usize),
+    Noise(f32),
+    Seed(u64),
+}
+
+impl Override {
+    /// Parses a comma-separated `key=value` list, e.g. `noise=0.2,seed=7`.
+    pub fn parse_list(text: &str) -> Result<Vec<Override>, String> {
+        text.split(',').map(Override::parse).collect()
+    }
+
+    fn parse(item: &str) -> Result<Override, String> {
+        let (key, value) = item
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got {:?}", item))?;
+        let invalid = || format!("invalid value for {}: {:?}", key, value);
+        match key {
+            "rows" | "cols" => {
+                let n: usize = value.parse().map_err(|_| invalid())?;
+                if n == 0 {
+                    return Err(invalid());
+                }
+                Ok(if key == "rows" {
+                    Override::Rows(n)
+                } else {
+                    Override::Cols(n)
+                })
+            }
+            "noise" => match value.parse::<f32>() {
+                Ok(n) if (0.0..=1.0).contains(&n) => Ok(Override::Noise(n)),
+                _ => Err(invalid()),
+            },
+            "seed" => value.parse().map(Override::Seed).map_err(|_| invalid()),
+            _ => Err(format!(
+                "unknown parameter {:?}, expected rows, cols, noise or seed",
+                key
+            )),
+        }
+    }
+}
+
+/// Picks from cumulative probabilities given a uniform draw `r` in `[0, 1)`.
+pub fn pick_strategy(mix: &[(Strategy, f32)], r: f32) -> Strategy {
+    let mut acc = 0.0;
+    for (strategy, prob) in mix {
+        acc += prob;
+        if r < acc {
+            return *strategy;
+        }
+    }
+    mix.last().map_or(Strategy::Deflect, |(s, _)| *s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coop::{Payoffs, Schedule, ShockEffect};
+
+    #[test]
+    fn test_pick_strategy() {
+        let mix = [
+            (Strategy::Coop, 0.0),
+            (Strategy::Random, 0.1),
+            (Strategy::TicToc, 0.4),
+        ];
+        assert_eq!(pick_strategy(&mix, 0.0), Strategy::Random);
+        assert_eq!(pick_strategy(&mix, 0.3), Strategy::TicToc);
+        assert_eq!(pick_strategy(&mix, 0.99), Strategy::TicToc);
+        assert_eq!(pick_strategy(&[], 0.5), Strategy::Deflect);
+    }
+
+    #[test]
+    fn test_preset_config() {
+        let preset = coop::presets::single_invader();
+        let config = SimConfig::from_preset(&preset);
+        assert_eq!(
+            config.build().unwrap().step(),
+            preset.build().unwrap().step()
+        );
+        assert_eq!(config.mix, vec![(Strategy::TicToc, 1.0)]);
+        // Overrides still apply; the invader stays in the middle of a resized grid.
+        let bigger = config.with_overrides(&[Override::Rows(31), Override::Noise(0.2)]);
+        let env = bigger.build().unwrap();
+        assert_eq!((env.dimensions(), env.noise()), ((31, 21), 0.2));
+        assert_eq!(env.agent_at((15, 10)).unwrap().strategy, Strategy::Deflect);
+        assert_eq!(env.payoffs(), preset.payoffs);
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_first_step() {
+        let config = SimConfig {
+            rows: 12,
+            cols: 12,
+            ..SimConfig::default()
+        };
+        assert_eq!(
+            config.build().unwrap().step(),
+            config.build().unwrap().step()
+        );
+        assert_eq!(config.build().unwrap().seed(), config.seed);
+    }
+
+    #[test]
+    fn test_parse_overrides() {
+        assert_eq!(
+            Override::parse_list("noise=0.25,seed=9"),
+            Ok(vec![Override::Noise(0.25), Override::Seed(9)])
+        );
+        assert_eq!(
+            Override::parse_list("rows=10,cols=20"),
+            Ok(vec![Override::Rows(10), Override::Cols(20)])
+        );
+        for bad in [
+            "",
+            "noise",
+            "noise=2",
+            "rows=0",
+            "seed=-1",
+            "speed=3",
+            "noise=0.1,",
+        ] {
+            assert!(Override::parse_list(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_with_overrides() {
+        let config = SimConfig {
+            seed: 1,
+            ..SimConfig::default()
+        };
+        let other = config.with_overrides(&[Override::Noise(0.3), Override::Rows(8)]);
+        assert_eq!((other.rows, other.cols, other.noise), (8, 50, 0.3));
+        assert_eq!((other.seed, &other.mix), (1, &config.mix));
+        assert_eq!(config.with_overrides(&[]), config);
+    }
+
+    #[test]
+    fn test_reseeded_keeps_parameters() {
+        let config = SimConfig {
+            rows: 7,
+            noise: 0.25,
+            seed: 1,
+            ..SimConfig::default()
+        };
+        let other = config.reseeded();
+        assert_eq!(
+            (other.rows, other.cols, other.noise, &other.mix),
+            (7, 50, 0.25, &config.mix)
+        );
+        let env = other.build().unwrap();
+        assert_eq!(env.dimensions(), (7, 50));
+        assert_eq!(env.seed(), other.seed);
+    }
+
+    #[test]
+    fn test_groups() {
+        let config = SimConfig {
+            rows: 10,
+            cols: 20,
+            groups: Some(Groups::new(5, 5, 10)),
+            ..SimConfig::default()
+        };
+        assert_eq!(config.build().unwrap().groups(), config.groups);
+        // Overriding the size can leave groups that no longer tile the grid.
+        let other = config.with_overrides(&[Override::Rows(12)]);
+        assert!(matches!(other.build(), Err(Error::InvalidGroups(_))));
+    }
+
+    #[test]
+    fn test_shocks() {
+        let config = SimConfig {
+            rows: 10,
+            cols: 10,
+            seed: 1,
+            shocks: vec![Shock::new(
+                Schedule::Every(2),
+                (3, 3),
+                ShockEffect::Set(Strategy::Coop),
+            )],
+            ..SimConfig::default()
+        };
+        let mut env = config.build().unwrap();
+        assert_eq!(env.shocks(), &config.shocks[..]);
+        assert!(env.step().shocks.is_empty());
+        assert_eq!(env.step().shocks.len(), 1);
+    }
+
+    #[test]
+    fn test_asymmetric() {
+        let config = SimConfig {
+            rows: 4,
+            cols: 4,
+            asymmetric: Some(Asymmetric {
+                host: Payoffs::default(),
+                visitor: Payoffs::default(),
+            }),
+            ..SimConfig::default()
+        };
+        let env = config.build().unwrap();
+        assert_eq!(env.asymmetric(), config.asymmetric);
+        assert_eq!(env.agent_at((0, 1)).unwrap().class, Class::Visitor);
+        assert_eq!(env.agent_at((1, 1)).unwrap().class, Class::Host);
+    }
+}
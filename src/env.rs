@@ -1,167 +1,4959 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    usize,
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, RngCore, SeedableRng};
+
+use crate::agent::{
+    Action, Agent, Class, Coord, Imitation, Inheritance, InteractionOutcome, Strategy,
 };
+use crate::builder::EnvironmentBuilder;
+use crate::error::Error;
+use crate::flow::PayoffFlow;
+use crate::grid::Grid;
+use crate::groups::{GroupMetric, Groups};
+use crate::interventions::{Agenda, Intervention};
+use crate::mix::Placement;
+use crate::neighbors::{NeighborTable, Neighborhood, Tables};
+use crate::observe::{Interaction, SharedObserver};
+use crate::schedule;
+use crate::shocks::{FiredShock, Shock, ShockEffect};
+use crate::snapshot::{Snapshot, THUMBNAIL_SIZE};
+use crate::stop::{StopCondition, Stopper};
 
-use crate::agent::{Action, Agent, Coord, Strategy};
+/// Most metrics `run_while` reserves room for up front; larger caps grow as needed.
+const MAX_RESERVE: usize = 4096;
+/// Tags separating a cell's random draws for choosing actions from those for noise.
+const ACTION_DRAWS: u64 = 0;
+const NOISE_DRAWS: u64 = 1;
+/// Tag of a cell's draws for placing and mutating its offspring.
+const BIRTH_DRAWS: u64 = 2;
+/// Tag of a shock's draws for when it fires, where it hits and what it leaves behind.
+const SHOCK_DRAWS: u64 = 3;
+/// Tag of a cell's draws for mutating the parameters it imitates.
+const MUTATION_DRAWS: u64 = 4;
+/// Tag of a cell's draws for whether it explores, and which neighbor it copies if so.
+const EXPLORATION_DRAWS: u64 = 5;
+/// Settled steps after which a run freezes by default; see [`Environment::is_frozen`].
+const FREEZE_AFTER: usize = 5;
+/// Steps between the full recounts that check the tally in debug builds.
+const TALLY_CHECK_EVERY: usize = 16;
 
+/// A grid of agents and everything needed to step it. Cloning copies the whole state,
+/// RNG included, so a clone replays the original's future exactly; see
+/// [`Environment::fork`] for a branch that diverges.
+///
+/// Environments are `Send` and `Sync`, so one can be stepped on another thread (see
+/// [`runner`](crate::runner)) or read from several; stepping needs `&mut`, so it's never
+/// observed halfway. Custom payoff functions must be `Send + Sync` to keep it that way.
+#[derive(Clone)]
 pub struct Environment {
     num_row: usize,
     num_col: usize,
     noise: f32,
+    payoffs: Payoffs,
     grid: Vec<Agent>,
+    neighbors: Arc<NeighborTable>,
+    seed: u64,
+    rng: StdRng,
+    /// Number of steps taken so far.
+    steps_taken: usize,
+    /// Actions each agent remembers per opponent; `None` keeps everything.
+    memory_window: Option<usize>,
+    update_mode: UpdateMode,
+    /// The order the last step visited the agents in, when it drew one.
+    last_order: Option<Vec<usize>>,
+    /// What agents compare when choosing whom to imitate.
+    imitation: Imitation,
+    /// Standard deviation of the noise added to imitated strategy parameters.
+    mutation_sigma: f64,
+    /// Chance that an agent copies a random neighbor instead of imitating by payoff.
+    exploration_rate: f32,
+    /// Scores pairs in place of `payoffs` when set.
+    payoff_fn: Option<PayoffFn>,
+    /// What to do when `payoff_fn` returns a payoff that isn't finite.
+    non_finite: NonFinitePolicy,
+    /// Non-finite payoffs `payoff_fn` returned since the last metric.
+    rejected_payoffs: usize,
+    /// Living costs, starvation and reproduction, when set.
+    energy: Option<EnergyConfig>,
+    /// Whether agents may refuse to play, and what they get instead.
+    partner_choice: Option<PartnerChoice>,
+    /// Whether agents announce an intended action to their neighbors before every round.
+    signaling: bool,
+    /// This step's payoffs by the strategies on both sides, when they're tracked.
+    payoff_flow: Option<PayoffFlow>,
+    /// This step's share of cooperative moves in every cell's games, row-major, when tracked.
+    coop_rate_map: Option<Vec<f32>>,
+    /// Whether metrics carry a thumbnail of the grid.
+    thumbnails: bool,
+    /// Sees every game played, when set; shared with clones of the environment.
+    observer: Option<SharedObserver>,
+    /// Payoffs that follow the state of a shared environment, when set.
+    feedback: Option<EnvFeedback>,
+    /// How rich the shared environment is, from 0 (degraded) to 1 (rich).
+    env_state: f64,
+    /// Tiles that compete every so often, when set.
+    groups: Option<Groups>,
+    /// Payoff each group earned since its last competition.
+    group_payoffs: Vec<f64>,
+    /// Disruptions that scramble regions of the grid on schedule.
+    shocks: Vec<Shock>,
+    /// Interventions waiting for their step.
+    agenda: Agenda,
+    /// The strategies of the last metric, kept up to date incrementally.
+    tally: Tally,
+    /// Settled steps after which the run freezes; `None` never freezes.
+    freeze_after: Option<usize>,
+    /// Consecutive steps up to now that started settled and in which nobody switched.
+    still_steps: usize,
+    /// Separate payoffs for hosts and visitors, who only play each other, when set.
+    asymmetric: Option<Asymmetric>,
+    /// Who plays whom and who imitates whom, before vacancies and classes.
+    interaction: Neighborhood,
+    adaptation: Neighborhood,
+    /// The neighbors each agent may imitate, when that's not the ones it plays: under another
+    /// adaptation neighborhood, or an asymmetric game's own class.
+    models: Option<Arc<NeighborTable>>,
+    /// Cells whose agent died, row-major. Vacant cells are left out of `neighbors`.
+    vacant: Vec<bool>,
+    /// Every cell's interaction neighbors, vacant or not, where offspring can be placed.
+    topology: Arc<NeighborTable>,
+    /// The ID the next newborn agent gets.
+    next_id: u64,
+}
+
+/// A custom payoff function; shared so environments stay cheap to clone.
+pub(crate) type PayoffFn = Arc<dyn Fn(&PayoffCtx<'_>) -> (f64, f64) + Send + Sync>;
+
+/// One game between two neighbors, as seen by a custom payoff function. `first` is the agent
+/// that comes first in row-major order. Agents are shown as they were before this step's
+/// scoring, so the order pairs are scored in doesn't matter.
+#[derive(Clone, Copy, Debug)]
+pub struct PayoffCtx<'a> {
+    pub first: &'a Agent,
+    pub second: &'a Agent,
+    /// The actions both played, after noise.
+    pub first_action: Action,
+    pub second_action: Action,
+}
+
+impl PayoffCtx<'_> {
+    /// `(first, second)` coordinates.
+    pub fn coords(&self) -> (Coord, Coord) {
+        (self.first.coord, self.second.coord)
+    }
+}
+
+/// What happens when a custom payoff function returns a payoff that's NaN or infinite. Left
+/// alone, it would spread through every score it's added to and every agent that imitates
+/// by them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Panics at once, naming the pair and the step.
+    #[default]
+    Panic,
+    /// Pays the side that got the bad payoff nothing for the game instead; the other side
+    /// keeps theirs.
+    ClampToZero,
+    /// Leaves the game out of the scores entirely: neither side is paid for it or remembers
+    /// it, and observers and the payoff flow don't see it. Its actions still count toward the
+    /// cooperation rate.
+    SkipInteraction,
+}
+
+/// Payoffs a custom payoff function gave every slot, after the [`NonFinitePolicy`].
+struct PairPayoffs {
+    payoffs: Vec<f64>,
+    /// Slots of games skipped for a non-finite payoff, if there were any.
+    skipped: Option<Vec<bool>>,
+    /// Payoffs that weren't finite.
+    rejected: usize,
+}
+
+/// How the agents of a grid are updated within a step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// Agents adapt one after another in row-major order, seeing the strategies that earlier
+    /// cells just adopted, and every random draw comes from the environment's one RNG. This is
+    /// the original behavior, so seeded runs keep their results.
+    #[default]
+    Legacy,
+    /// Agents all adapt to the same frozen grid, and every random decision draws from its own
+    /// stream, keyed by the run's seed, the step, the cell, the neighbor it concerns and what
+    /// the draw is for. Results then depend neither on the order cells are processed in nor
+    /// on how many draws other decisions made, so adding a strategy that draws more leaves
+    /// every other agent's dice alone, and with the `parallel` feature cells are spread over
+    /// all cores with identical results for any number of threads.
+    Synchronous,
+    /// Agents adapt one after another, each seeing the strategies that cells before it just
+    /// adopted, in a fresh random order every step; see [`schedule::permutation`] and
+    /// [`Environment::last_update_order`]. Every other draw comes from keyed streams, and
+    /// games are played as under `Synchronous`.
+    Asynchronous,
+}
+
+/// An ecological layer on top of the game: agents pay to live, bank their payoffs as energy,
+/// starve when it runs out and reproduce when they have plenty.
+///
+/// After each step's games, every agent gains its payoffs from that step and pays
+/// `living_cost`. Agents left with negative energy die, leaving their cell vacant. Then every
+/// agent with at least `reproduction_threshold` energy places an offspring in a random vacant
+/// neighboring cell, if it has one, and gives it half its energy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnergyConfig {
+    /// Energy every agent starts with.
+    pub initial: f64,
+    /// Energy every agent pays per step.
+    pub living_cost: f64,
+    pub reproduction_threshold: f64,
+    /// Chance that an offspring plays a strategy drawn uniformly from all strategies, which
+    /// may be its parent's, instead of inheriting its parent's.
+    pub mutation_rate: f64,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        EnergyConfig {
+            initial: 10.0,
+            living_cost: 1.0,
+            reproduction_threshold: 20.0,
+            mutation_rate: 0.0,
+        }
+    }
+}
+
+impl EnergyConfig {
+    /// Whether every amount is finite, the threshold positive and the mutation rate a
+    /// probability.
+    pub fn is_valid(&self) -> bool {
+        [self.initial, self.living_cost, self.reproduction_threshold]
+            .iter()
+            .all(|v| v.is_finite())
+            && self.reproduction_threshold > 0.0
+            && (0.0..=1.0).contains(&self.mutation_rate)
+    }
+}
+
+/// Lets agents refuse to play a neighbor, as [`Strategy::refuses`] decides from what the
+/// neighbor played against them. A game takes place only if neither side refuses it; otherwise
+/// neither side plays, scores or remembers anything for it, and both earn `outside_option`
+/// instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PartnerChoice {
+    /// Points each side of a refused game earns instead of playing.
+    pub outside_option: f64,
+}
+
+/// What was played in a step: intended actions, and pairs that refused to play.
+#[derive(Clone, Debug, Default)]
+struct Played {
+    coop_actions: usize,
+    actions: usize,
+    refusals: usize,
+    /// Played games whose intended action matched, or didn't match, the signal sent before.
+    honest_signals: usize,
+    dishonest_signals: usize,
+    /// Intended cooperative actions and all actions of each group's agents, under groups.
+    groups: Vec<(usize, usize)>,
+}
+
+/// Payoffs coupled to a shared environment that cooperation replenishes and defection
+/// depletes, as in models of the tragedy of the commons.
+///
+/// The environment's state `n` runs from 0 (degraded) to 1 (rich), and every step is scored
+/// with the entry-wise interpolation `n * rich + (1 - n) * degraded`. After the step, with `x`
+/// the fraction of actions that were cooperative, the state moves by
+/// `recovery_rate * x * (1 - n) - degradation_rate * (1 - x) * n`, which keeps it in `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvFeedback {
+    /// Payoffs in a fully rich environment.
+    pub rich: Payoffs,
+    /// Payoffs in a fully degraded environment.
+    pub degraded: Payoffs,
+    /// How fast cooperation restores the environment, in `[0, 1]`.
+    pub recovery_rate: f64,
+    /// How fast defection depletes the environment, in `[0, 1]`.
+    pub degradation_rate: f64,
+}
+
+impl EnvFeedback {
+    /// Whether both matrices are finite and both rates in `[0, 1]`.
+    pub fn is_valid(&self) -> bool {
+        self.rich.is_finite()
+            && self.degraded.is_finite()
+            && (0.0..=1.0).contains(&self.recovery_rate)
+            && (0.0..=1.0).contains(&self.degradation_rate)
+    }
+
+    /// The payoffs when the environment's state is `state`.
+    pub fn payoffs_at(&self, state: f64) -> Payoffs {
+        let mix = |rich: f64, degraded: f64| state * rich + (1.0 - state) * degraded;
+        Payoffs {
+            reward: mix(self.rich.reward, self.degraded.reward),
+            temptation: mix(self.rich.temptation, self.degraded.temptation),
+            sucker: mix(self.rich.sucker, self.degraded.sucker),
+            punishment: mix(self.rich.punishment, self.degraded.punishment),
+        }
+    }
+
+    /// The state after a step at `state` whose actions were cooperative at `coop_rate`.
+    pub fn next_state(&self, state: f64, coop_rate: f64) -> f64 {
+        let change = self.recovery_rate * coop_rate * (1.0 - state)
+            - self.degradation_rate * (1.0 - coop_rate) * state;
+        (state + change).clamp(0.0, 1.0)
+    }
+}
+
+/// A game between two classes of agents, hosts and visitors, each scored with its own matrix.
+/// Agents only play neighbors of the other class and only imitate neighbors of their own, so
+/// the two populations evolve separately; see [`Class`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Asymmetric {
+    /// What hosts earn, by their own action and the visitor's.
+    pub host: Payoffs,
+    /// What visitors earn, by their own action and the host's.
+    pub visitor: Payoffs,
+}
+
+impl Asymmetric {
+    /// The matrix agents of `class` are scored with.
+    pub fn payoffs(&self, class: Class) -> Payoffs {
+        match class {
+            Class::Host => self.host,
+            Class::Visitor => self.visitor,
+        }
+    }
+}
+
+/// Births and deaths of a step.
+#[derive(Clone, Copy, Debug, Default)]
+struct Turnover {
+    births: usize,
+    deaths: usize,
+}
+
+/// What a step's adaptation changed.
+#[derive(Clone, Copy, Debug, Default)]
+struct Switches {
+    /// Whether any agent took over anything from a neighbor, if only its lineage.
+    any: bool,
+    /// Agents whose strategy changed by imitating a neighbor's payoff, and by exploring.
+    imitated: usize,
+    explored: usize,
+}
+
+impl Switches {
+    /// Has `agent` take over `inheritance` from the neighbor it explored to or imitated.
+    fn inherit(&mut self, agent: &mut Agent, inheritance: Inheritance, explored: bool) {
+        let before = agent.strategy;
+        self.any |= agent.inherit(inheritance);
+        if agent.strategy != before {
+            if explored {
+                self.explored += 1;
+            } else {
+                self.imitated += 1;
+            }
+        }
+    }
+}
+
+/// The strategies and lineages as of the last metric, carried from step to step so that
+/// recording the next one only touches the cells that changed in between.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Tally {
+    /// Every cell's strategy, vacant ones included.
+    strategies: Grid<Strategy>,
+    founders: Vec<u64>,
+    vacant: Vec<bool>,
+    /// Living agents per strategy kind, by [`Strategy::index`].
+    counts: [usize; Strategy::ALL.len()],
+    /// Living agents per founder, for the founders some agent still descends from.
+    lineages: HashMap<u64, usize>,
+}
+
+impl Tally {
+    /// A tally from scratch of `grid`, `rows` x `cols` agents with `vacant` cells.
+    fn count(rows: usize, cols: usize, grid: &[Agent], vacant: &[bool]) -> Tally {
+        let strategies: Vec<Strategy> = grid.iter().map(|a| a.strategy).collect();
+        let mut tally = Tally {
+            strategies: Grid::new(rows, cols, strategies),
+            founders: grid.iter().map(|a| a.founder_id).collect(),
+            vacant: vacant.to_vec(),
+            ..Tally::default()
+        };
+        for (agent, _) in grid.iter().zip(vacant).filter(|(_, &v)| !v) {
+            tally.add(agent.strategy, agent.founder_id);
+        }
+        tally
+    }
+
+    fn add(&mut self, strategy: Strategy, founder: u64) {
+        self.counts[strategy.index() as usize] += 1;
+        *self.lineages.entry(founder).or_insert(0) += 1;
+    }
+
+    fn remove(&mut self, strategy: Strategy, founder: u64) {
+        self.counts[strategy.index() as usize] -= 1;
+        if let Some(n) = self.lineages.get_mut(&founder) {
+            *n -= 1;
+            if *n == 0 {
+                self.lineages.remove(&founder);
+            }
+        }
+    }
+
+    /// Brings cell `i` up to `agent`, whatever changed it, moving its counts if it did. The
+    /// strategy grid is only copied if the last metric's snapshot still shares it and some
+    /// strategy changed.
+    fn update(&mut self, i: usize, agent: &Agent, vacant: bool) {
+        let (strategy, founder, was_vacant) =
+            (self.strategies.cells()[i], self.founders[i], self.vacant[i]);
+        if (agent.strategy, agent.founder_id, vacant) == (strategy, founder, was_vacant) {
+            return;
+        }
+        if !was_vacant {
+            self.remove(strategy, founder);
+        }
+        if !vacant {
+            self.add(agent.strategy, agent.founder_id);
+        }
+        if agent.strategy != strategy {
+            self.strategies.cells_mut()[i] = agent.strategy;
+        }
+        self.founders[i] = agent.founder_id;
+        self.vacant[i] = vacant;
+    }
+}
+
+/// Points an agent earns per game given its action and its opponent's. The defaults are the
+/// original fixed values: mutual cooperation 3, exploiting a cooperator 4, otherwise 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Payoffs {
+    /// Both cooperate.
+    pub reward: f64,
+    /// Defect against a cooperator.
+    pub temptation: f64,
+    /// Cooperate against a defector.
+    pub sucker: f64,
+    /// Both defect.
+    pub punishment: f64,
+}
+
+impl Default for Payoffs {
+    fn default() -> Self {
+        Payoffs {
+            reward: 3.0,
+            temptation: 4.0,
+            sucker: 0.0,
+            punishment: 0.0,
+        }
+    }
+}
+
+impl Payoffs {
+    /// Points for playing `mine` against `theirs`.
+    pub fn score(&self, mine: Action, theirs: Action) -> f64 {
+        match (mine, theirs) {
+            (Action::Coop, Action::Coop) => self.reward,
+            (Action::Deflect, Action::Coop) => self.temptation,
+            (Action::Coop, Action::Deflect) => self.sucker,
+            (Action::Deflect, Action::Deflect) => self.punishment,
+        }
+    }
+
+    /// Whether every payoff is a finite number, as environments and tournaments require.
+    pub fn is_finite(&self) -> bool {
+        [self.reward, self.temptation, self.sucker, self.punishment]
+            .iter()
+            .all(|v| v.is_finite())
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Mean and population standard deviation of one strategy parameter over the agents
+/// playing the strategy.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParamStats {
+    pub mean: f64,
+    pub std: f64,
+}
+
+/// Per-strategy maps are keyed by [`Strategy::kind`], so parameterized strategies are counted
+/// together whatever their parameters.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Metric {
     pub strategies: BTreeMap<Strategy, usize>,
-    pub max_score: BTreeMap<Strategy, f32>,
+    pub max_score: BTreeMap<Strategy, f64>,
+    /// Mean score of the agents playing each strategy.
+    pub avg_score: BTreeMap<Strategy, f64>,
+    /// Mean stake of the agents playing each strategy.
+    pub mean_stake: BTreeMap<Strategy, f64>,
+    /// Mean, over the agents playing each strategy, of score divided by games played; see
+    /// [`Agent::score_per_interaction`]. Unlike `max_score` and `avg_score` it doesn't favor
+    /// agents with more neighbors, such as those away from a bounded grid's edges.
+    pub score_per_interaction: BTreeMap<Strategy, f64>,
+    /// Mean, over the agents playing each strategy, of score divided by age; see
+    /// [`Agent::score_per_step`].
+    pub score_per_step: BTreeMap<Strategy, f64>,
+    /// The agent playing each strategy with the highest score per interaction, the first in
+    /// row-major order on ties.
+    pub top_agent: BTreeMap<Strategy, Coord>,
+    /// Every parameter of the parameterized strategies being played, by strategy and
+    /// parameter name, e.g. `(GenerousTFT, "forgiveness")`.
+    pub parameters: BTreeMap<(Strategy, &'static str), ParamStats>,
+    /// Zero-based number of the step this metric was recorded at.
+    pub step_index: usize,
     pub coop_actions: i32,
-    pub snapshot: Vec<Vec<Strategy>>,
+    /// Fraction of this step's actions that were cooperative, in `[0, 1]`.
+    pub coop_rate: f32,
+    pub snapshot: Snapshot,
+    /// Number of founding agents whose strategy some agent still carries.
+    pub founder_lineages: usize,
+    /// Agents alive after this step; every cell holds one unless there's an energy budget.
+    pub population: usize,
+    /// Agents born and agents starved this step, under an energy budget.
+    pub births: usize,
+    pub deaths: usize,
+    /// Agents that switched strategy this step by imitating a more successful neighbor.
+    pub imitations: usize,
+    /// Agents that switched strategy this step by copying a random neighbor, whatever it
+    /// scored; see [`Environment::set_exploration_rate`].
+    pub explorations: usize,
+    /// Pairs of neighbors that didn't play this step because one side refused, under
+    /// partner choice.
+    pub refusals: usize,
+    /// Payoffs the custom payoff function returned this step that were NaN or infinite; see
+    /// [`NonFinitePolicy`].
+    pub rejected_payoffs: usize,
+    /// Games this step in which the intended action kept, or broke, the promise signaled
+    /// before it, under cheap talk; one per side of every game played.
+    pub honest_signals: usize,
+    pub dishonest_signals: usize,
+    /// Strategy counts of each class, in [`Class::ALL`] order, under an [`Asymmetric`] game;
+    /// empty otherwise.
+    pub class_strategies: Vec<BTreeMap<Strategy, usize>>,
+    /// Every group's results this step in group order, under [`Groups`]; empty otherwise.
+    pub groups: Vec<GroupMetric>,
+    /// The shared environment's state after this step under [`EnvFeedback`], from 0
+    /// (degraded) to 1 (rich); always 1 without feedback.
+    pub env_state: f32,
+    /// The shocks that hit the grid this step, in the order they fired.
+    pub shocks: Vec<FiredShock>,
+    /// IDs, as [`Environment::schedule`] returned them, of the interventions applied this
+    /// step, in the order they ran.
+    pub interventions: Vec<usize>,
+    /// Whether the run was frozen after this step; see [`Environment::is_frozen`].
+    pub frozen: bool,
+    /// Every agent's score after this step, shaped like `snapshot`.
+    pub scores: Grid<f64>,
+    /// This step's payoffs by the strategies of the agents that earned them and of their
+    /// opponents, when tracked with [`EnvironmentBuilder::payoff_flow`]; `None` otherwise.
+    pub payoff_flow: Option<PayoffFlow>,
+    /// For every cell, the share of cooperative moves, its own and its opponents', in the
+    /// games it played this step, when tracked with [`EnvironmentBuilder::coop_rate_map`];
+    /// `None` otherwise. Cells that played no games are 0. Unlike the snapshot it shows where
+    /// agents whose strategies look cooperative are defecting against each other.
+    pub coop_rate_map: Option<Grid<f32>>,
+    /// `snapshot` shrunk to at most [`THUMBNAIL_SIZE`] cells a side, small enough to keep for
+    /// every step of a long run, when enabled with [`EnvironmentBuilder::thumbnails`]; `None`
+    /// otherwise.
+    pub thumbnail: Option<Snapshot>,
+}
+
+impl Metric {
+    /// The grid as recorded: the full snapshot, or the thumbnail where the snapshot was
+    /// dropped to save memory, see [`History::compact_snapshots`](crate::History::compact_snapshots).
+    pub fn overview(&self) -> &Snapshot {
+        match &self.thumbnail {
+            Some(thumbnail) if self.snapshot.is_empty() => thumbnail,
+            _ => &self.snapshot,
+        }
+    }
 }
 
 impl Environment {
+    /// Plays one step: imitation, then the games, and returns its metric.
+    ///
+    /// Once the run [is frozen](Environment::is_frozen), which it may be by default, the
+    /// games are only scored, not recorded: [`Agent::history_with`] and
+    /// [`Agent::last_outcome_with`] keep the last step played in full, where a run with
+    /// [`set_freeze_after(None)`](Environment::set_freeze_after) goes on recording every
+    /// game. Metrics, scores and [`Agent::payoff_from`] come out the same either way, and
+    /// since only strategies that ignore histories are left, so does play.
     pub fn step(&mut self) -> Metric {
+        self.step_with(cfg!(feature = "parallel"))
+    }
+
+    /// A step whose synchronous phases run on all cores when `parallel` is set; a no-op
+    /// without the `parallel` feature.
+    fn step_with(&mut self, parallel: bool) -> Metric {
+        let settled = self.freeze_after.is_some() && self.settled();
+        let frozen = settled && self.is_frozen();
+        let scores_before: Vec<f64> = match (self.energy, self.groups) {
+            (None, None) => Vec::new(),
+            _ => self.grid.iter().map(|a| a.score).collect(),
+        };
+        self.last_order = None;
+        let switches = match self.update_mode {
+            UpdateMode::Legacy => self.imitate_legacy(),
+            UpdateMode::Synchronous => self.imitate_synchronous(parallel),
+            UpdateMode::Asynchronous => self.imitate_asynchronous(),
+        };
+        let switched = switches.any;
+        self.still_steps = if settled && !switched {
+            self.still_steps + 1
+        } else {
+            0
+        };
+        if frozen && !switched {
+            let played = self.play_frozen();
+            return self.record_metric(
+                played,
+                Turnover::default(),
+                switches,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+            );
+        }
+        let interventions = self.intervene();
+        let played = match self.update_mode {
+            UpdateMode::Legacy => self.play_legacy(),
+            UpdateMode::Synchronous | UpdateMode::Asynchronous => self.play_synchronous(parallel),
+        };
+        let mut groups = match self.groups {
+            Some(groups) => self.group_metrics(groups, &played, &scores_before),
+            None => Vec::new(),
+        };
+        let turnover = match self.energy {
+            Some(energy) => self.live_and_die(energy, &scores_before),
+            None => Turnover::default(),
+        };
+        if let Some(g) = self
+            .groups
+            .filter(|g| (self.steps_taken + 1).is_multiple_of(g.period))
+        {
+            self.compete(g, &mut groups);
+        }
+        let shocks = self.apply_shocks();
+        self.record_metric(played, turnover, switches, groups, shocks, interventions)
+    }
+
+    /// Lets every agent imitate in place, cell by cell, so later cells see earlier cells' new
+    /// strategies.
+    fn imitate_legacy(&mut self) -> Switches {
+        let (imitation, sigma) = (self.imitation, self.mutation_sigma);
+        let exploration = self.exploration_rate;
+        let mut rng = self.rng.clone();
+        let mut switches = Switches::default();
         self.for_each_cell(|curr, neighbors| {
-            curr.adapt(neighbors);
+            let (model, explored) = match explore(exploration, neighbors.clone(), &mut rng) {
+                Some(model) => (Some(model), true),
+                None => (curr.imitated(neighbors, imitation), false),
+            };
+            if let Some(model) = model {
+                let inheritance = model.inheritance().mutated(&mut rng, sigma);
+                switches.inherit(curr, inheritance, explored);
+            }
         });
-        let mut actions: HashMap<(Coord, Coord), Action> = HashMap::new();
-        self.for_each_cell(|curr, neighbors| {
-            for n in neighbors {
-                actions.insert((curr.coord, n.coord), curr.get_action(n));
+        self.rng = rng;
+        switches
+    }
+
+    /// Plays the games of a step, cell by cell from the shared RNG; returns what was played.
+    fn play_legacy(&mut self) -> Played {
+        let width = self.neighbors.width();
+        let refused = self.refused_slots();
+        let refused = refused.as_deref();
+        let skip = |slot: usize| refused.is_some_and(|r| r[slot]);
+        let signals = self.signals();
+        let signals = signals.as_deref();
+        let (payoffs, step) = (self.class_payoffs(), self.steps_taken);
+        let table = &self.neighbors;
+        let grid = &self.grid;
+        let mut actions = vec![Action::Coop; grid.len() * width];
+        for (i, slots) in actions.chunks_mut(width).enumerate() {
+            // Strategies that play one action whatever they know never draw, so skipping
+            // their contexts leaves the shared RNG where it would be.
+            if let Some(action) = grid[i].strategy.fixed_action() {
+                slots.fill(action);
+                continue;
+            }
+            let payoffs = &payoffs[grid[i].class.index()];
+            for (k, (slot, &j)) in slots.iter_mut().zip(table.of(i)).enumerate() {
+                if !skip(i * width + k) {
+                    let mut ctx = grid[i].context(grid[j].coord, step, payoffs, &mut self.rng);
+                    ctx.signal = signals.map(|s| s[j]);
+                    *slot = grid[i].strategy.get_action(&mut ctx);
+                }
+            }
+        }
+        let mut realized = actions.clone();
+        for (i, slots) in realized.chunks_mut(width).enumerate() {
+            for (k, slot) in slots[..table.of(i).len()].iter_mut().enumerate() {
+                if !skip(i * width + k) {
+                    *slot = slot.with_noise(self.noise, &mut self.rng);
+                }
+            }
+        }
+        self.score_slots(&actions, &realized, refused, false);
+        self.played(&actions, refused)
+    }
+
+    /// Lets every agent imitate in place like `imitate_legacy`, but in this step's random
+    /// order and with exploration and mutations from keyed streams.
+    fn imitate_asynchronous(&mut self) -> Switches {
+        let order = schedule::permutation(self.seed, self.steps_taken, self.grid.len());
+        let (imitation, sigma) = (self.imitation, self.mutation_sigma);
+        let (seed, step, exploration) = (self.seed, self.steps_taken, self.exploration_rate);
+        let mut switches = Switches::default();
+        self.for_each_cell_in(order.iter().copied(), |curr, neighbors| {
+            let i = neighbors.index;
+            // Seeding a stream costs more than the rest of the cell's adaptation.
+            let explored = if exploration > 0.0 {
+                explore(
+                    exploration,
+                    neighbors.clone(),
+                    &mut cell_rng(seed, step, i, EXPLORATION_DRAWS),
+                )
+            } else {
+                None
+            };
+            let (model, explored) = match explored {
+                Some(model) => (Some(model), true),
+                None => (curr.imitated(neighbors, imitation), false),
+            };
+            if let Some(model) = model {
+                let mut inheritance = model.inheritance();
+                if sigma > 0.0 {
+                    let mut rng = cell_rng(seed, step, i, MUTATION_DRAWS);
+                    inheritance = inheritance.mutated(&mut rng, sigma);
+                }
+                switches.inherit(curr, inheritance, explored);
+            }
+        });
+        self.last_order = Some(order);
+        switches
+    }
+
+    /// Picks every agent's new strategy from a frozen view of the grid.
+    fn imitate_synchronous(&mut self, parallel: bool) -> Switches {
+        let table = self.models.as_deref().unwrap_or(&self.neighbors);
+        let (grid, imitation, sigma) = (&self.grid, self.imitation, self.mutation_sigma);
+        let (seed, step, exploration) = (self.seed, self.steps_taken, self.exploration_rate);
+        let inheritances = map_cells(grid.len(), parallel, |i| {
+            let models = table.of(i).iter().map(|&j| &grid[j]);
+            // Seeding a stream costs more than the rest of the cell's adaptation.
+            let explored = if exploration > 0.0 {
+                explore(
+                    exploration,
+                    models.clone(),
+                    &mut cell_rng(seed, step, i, EXPLORATION_DRAWS),
+                )
+            } else {
+                None
+            };
+            let (model, explored) = match explored {
+                Some(model) => (model, true),
+                None => (grid[i].imitated(models, imitation)?, false),
+            };
+            let inheritance = model.inheritance();
+            if sigma > 0.0 {
+                let mut rng = cell_rng(seed, step, i, MUTATION_DRAWS);
+                Some((inheritance.mutated(&mut rng, sigma), explored))
+            } else {
+                Some((inheritance, explored))
+            }
+        });
+        let mut switches = Switches::default();
+        for (agent, inheritance) in self.grid.iter_mut().zip(inheritances) {
+            if let Some((inheritance, explored)) = inheritance {
+                switches.inherit(agent, inheritance, explored);
+            }
+        }
+        switches
+    }
+
+    /// Plays the games of a frozen step, whose every action is known up front, by only adding
+    /// up their payoffs; the agents' histories aren't extended. Under [`UpdateMode::Legacy`]
+    /// the shared RNG still makes the noise draws the games would have, so a run that thaws
+    /// goes on as if it had never frozen.
+    fn play_frozen(&mut self) -> Played {
+        let width = self.neighbors.width();
+        let mut actions = vec![Action::Coop; self.grid.len() * width];
+        for (slots, agent) in actions.chunks_mut(width).zip(&self.grid) {
+            slots.fill(agent.strategy.fixed_action().unwrap_or(Action::Coop));
+        }
+        let payoffs = self.matrix_payoffs(&actions, None);
+        self.tally_flow(&payoffs, None);
+        self.observe(&actions, None, &payoffs);
+        self.map_cooperation(&actions, None);
+        let table = &self.neighbors;
+        let num_col = self.num_col;
+        for (i, agent) in self.grid.iter_mut().enumerate() {
+            for (&j, payoff) in table.of(i).iter().zip(&payoffs[i * width..]) {
+                agent.earn_from((j / num_col, j % num_col), *payoff);
+                agent.interactions += 1;
+            }
+        }
+        if self.update_mode == UpdateMode::Legacy {
+            for _ in 0..table.pairs() {
+                Action::Coop.with_noise(self.noise, &mut self.rng);
+            }
+        }
+        self.played(&actions, None)
+    }
+
+    /// Plays the games of a step in phases that each only read what earlier phases wrote:
+    /// every intended action, then the noise, then every agent scores itself. Returns the
+    /// same counts as `play_legacy`.
+    fn play_synchronous(&mut self, parallel: bool) -> Played {
+        let (seed, step) = (self.seed, self.steps_taken);
+        let width = self.neighbors.width();
+
+        let refused = self.refused_slots();
+        let refused = refused.as_deref();
+        let skip = |slot: usize| refused.is_some_and(|r| r[slot]);
+        let signals = self.signals();
+        let signals = signals.as_deref();
+        let payoffs = self.class_payoffs();
+        let table = &self.neighbors;
+        let grid = &self.grid;
+        let mut actions = vec![Action::Coop; grid.len() * width];
+        for_each_chunk(&mut actions, width, parallel, |i, slots| {
+            if let Some(action) = grid[i].strategy.fixed_action() {
+                slots.fill(action);
+                return;
+            }
+            let payoffs = &payoffs[grid[i].class.index()];
+            for (k, (slot, &j)) in slots.iter_mut().zip(table.of(i)).enumerate() {
+                if !skip(i * width + k) {
+                    let mut rng = slot_rng(seed, step, i, k, ACTION_DRAWS);
+                    let mut ctx = grid[i].context(grid[j].coord, step, payoffs, &mut rng);
+                    ctx.signal = signals.map(|s| s[j]);
+                    *slot = grid[i].strategy.get_action(&mut ctx);
+                }
             }
         });
+
+        let mut realized = actions.clone();
         let noise = self.noise;
-        self.for_each_cell(|curr, neighbors| {
-            for n in neighbors {
-                let my_action = actions[&(curr.coord, n.coord)].with_noise(noise);
-                let their_action = actions[&(n.coord, curr.coord)].with_noise(noise);
-                curr.score(n, their_action, Environment::score(my_action, their_action));
+        for_each_chunk(&mut realized, width, parallel, |i, slots| {
+            for (k, slot) in slots[..table.of(i).len()].iter_mut().enumerate() {
+                if !skip(i * width + k) {
+                    *slot = slot.with_noise(noise, &mut slot_rng(seed, step, i, k, NOISE_DRAWS));
+                }
             }
         });
+        self.score_slots(&actions, &realized, refused, parallel);
+        self.played(&actions, refused)
+    }
 
-        let mut strategies: BTreeMap<Strategy, usize> = BTreeMap::new();
-        let mut max_score: BTreeMap<Strategy, f32> = BTreeMap::new();
-        self.grid.iter().for_each(|curr| {
-            let count = strategies.get(&curr.strategy).cloned().unwrap_or(0) + 1;
-            strategies.insert(curr.strategy, count);
+    /// The matrix each class is scored with, in `Class::ALL` order; the same one for both
+    /// without an asymmetric game.
+    fn class_payoffs(&self) -> [Payoffs; 2] {
+        match self.asymmetric {
+            Some(game) => Class::ALL.map(|c| game.payoffs(c)),
+            None => [self.payoffs; 2],
+        }
+    }
 
-            let score = max_score.get(&curr.strategy).cloned().unwrap_or(0.0);
-            max_score.insert(curr.strategy, score.max(curr.score));
+    /// Rebuilds who plays and who imitates whom, after vacancies, classes, neighborhoods or
+    /// the game changed.
+    fn link_neighbors(&mut self) {
+        let (rows, cols) = self.dimensions();
+        let (grid, asymmetric) = (&self.grid, self.asymmetric.is_some());
+        self.neighbors = Arc::new(NeighborTable::linking(
+            rows,
+            cols,
+            self.interaction,
+            &self.vacant,
+            |a, b| !asymmetric || grid[a].class != grid[b].class,
+        ));
+        self.models = (asymmetric || self.adaptation != self.interaction).then(|| {
+            Arc::new(NeighborTable::linking(
+                rows,
+                cols,
+                self.adaptation,
+                &self.vacant,
+                |a, b| !asymmetric || grid[a].class == grid[b].class,
+            ))
         });
-        let snapshot: Vec<Vec<Strategy>> = self
-            .grid
-            .iter()
-            .map(|a| a.strategy)
-            .collect::<Vec<Strategy>>()
-            .chunks(self.num_col)
-            .map(|c| c.iter().cloned().collect())
-            .collect();
+    }
+
+    /// Which neighbor slots won't be played this step because either side refuses, laid out
+    /// like the actions; `None` without partner choice, when every game is played.
+    fn refused_slots(&self) -> Option<Vec<bool>> {
+        self.partner_choice?;
+        let width = self.neighbors.width();
+        let table = &self.neighbors;
+        let grid = &self.grid;
+        let mut refused = vec![false; grid.len() * width];
+        for (i, slots) in refused.chunks_mut(width).enumerate() {
+            for (slot, &j) in slots.iter_mut().zip(table.of(i)) {
+                *slot = grid[i].refuses(&grid[j]) || grid[j].refuses(&grid[i]);
+            }
+        }
+        Some(refused)
+    }
 
-        let coop_actions = actions.values().filter(|a| (**a == Action::Coop)).count() as i32;
+    /// The intent every agent announces this round, row-major; `None` without cheap talk.
+    fn signals(&self) -> Option<Vec<Action>> {
+        self.signaling
+            .then(|| self.grid.iter().map(|a| a.strategy.signal()).collect())
+    }
 
-        Metric {
+    /// Points for a refused game; nothing without partner choice.
+    fn outside_option(&self) -> f64 {
+        self.partner_choice.map_or(0.0, |p| p.outside_option)
+    }
+
+    /// Counts the intended actions of the games that were played, the refused pairs, and
+    /// the kept and broken promises under cheap talk.
+    fn played(&self, actions: &[Action], refused: Option<&[bool]>) -> Played {
+        let width = self.neighbors.width();
+        let signals = self.signals();
+        let table = &self.neighbors;
+        let num_col = self.num_col;
+        let mut groups = match self.groups {
+            Some(g) => vec![(0, 0); g.count((self.num_row, num_col))],
+            None => Vec::new(),
+        };
+        let (mut coop_actions, mut refused_slots, mut honest) = (0, 0, 0);
+        for i in 0..table.len() {
+            let slots = &actions[i * width..][..table.of(i).len()];
+            let (mut coop, mut played) = (0, 0);
+            for (k, action) in slots.iter().enumerate() {
+                if refused.is_some_and(|r| r[i * width + k]) {
+                    refused_slots += 1;
+                } else {
+                    played += 1;
+                    coop += usize::from(*action == Action::Coop);
+                    honest += usize::from(signals.as_ref().is_some_and(|s| s[i] == *action));
+                }
+            }
+            coop_actions += coop;
+            if let Some(g) = self.groups {
+                let group = &mut groups[g.of((i / num_col, i % num_col), num_col)];
+                group.0 += coop;
+                group.1 += played;
+            }
+        }
+        let actions = table.pairs() - refused_slots;
+        Played {
             coop_actions,
-            strategies,
-            max_score,
-            snapshot,
+            actions,
+            honest_signals: honest,
+            dishonest_signals: if signals.is_some() {
+                actions - honest
+            } else {
+                0
+            },
+            // Both sides of a refused pair have a slot for it.
+            refusals: refused_slots / 2,
+            groups,
+        }
+    }
+
+    /// Every group's payoff since `scores_before` and cooperation this step. Adds the payoffs
+    /// to what the groups earned since their last competition.
+    fn group_metrics(
+        &mut self,
+        groups: Groups,
+        played: &Played,
+        scores_before: &[f64],
+    ) -> Vec<GroupMetric> {
+        let mut metrics: Vec<GroupMetric> = played
+            .groups
+            .iter()
+            .map(|&(coop, actions)| GroupMetric {
+                coop_rate: if actions == 0 {
+                    0.0
+                } else {
+                    coop as f32 / actions as f32
+                },
+                ..GroupMetric::default()
+            })
+            .collect();
+        for (i, (agent, before)) in self.grid.iter().zip(scores_before).enumerate() {
+            if !self.vacant[i] {
+                let group = groups.of(agent.coord, self.num_col);
+                metrics[group].total_payoff += agent.score - before;
+            }
+        }
+        for (total, metric) in self.group_payoffs.iter_mut().zip(&metrics) {
+            *total += metric.total_payoff;
         }
+        metrics
     }
 
-    pub fn new(num_row: usize, num_col: usize, noise: f32) -> Environment {
-        let strategies = vec![Strategy::Deflect, Strategy::TicToc];
-        Environment::new_with_agent_func(num_row, num_col, noise, |c| Agent::random(c, &strategies))
+    /// Replaces the group that earned the least since the last competition with a copy of the
+    /// one that earned the most, ties going to the first group, and starts the next round of
+    /// earnings. Nothing is replaced when all groups earned the same.
+    fn compete(&mut self, groups: Groups, metrics: &mut [GroupMetric]) {
+        let payoffs = std::mem::replace(&mut self.group_payoffs, vec![0.0; metrics.len()]);
+        let best = (0..payoffs.len()).fold(0, |b, g| if payoffs[g] > payoffs[b] { g } else { b });
+        let worst = (0..payoffs.len()).fold(0, |w, g| if payoffs[g] < payoffs[w] { g } else { w });
+        if payoffs[best] <= payoffs[worst] {
+            return;
+        }
+        self.copy_group(groups, best, worst);
+        metrics[worst].replaced_by = Some(best);
     }
 
-    pub fn new_with_agent_func<F>(
-        num_row: usize,
-        num_col: usize,
-        noise: f32,
-        mut agent_fn: F,
-    ) -> Environment
-    where
-        F: FnMut(Coord) -> Agent,
-    {
-        let mut grid: Vec<Agent> = Vec::with_capacity(num_row * num_col);
-        for i in 0..num_row {
-            for j in 0..num_col {
-                grid.push(agent_fn((i, j)));
+    /// Copies every agent of group `from` onto the same cell of group `to`: strategy, lineage,
+    /// score and energy, but no history, which the copies' neighbors forget too. Vacant cells
+    /// are copied as vacant, and a copy into a vacant cell is a newborn.
+    fn copy_group(&mut self, groups: Groups, from: usize, to: usize) {
+        let ((from_row, from_col), (to_row, to_col)) = (
+            groups.origin(from, self.num_col),
+            groups.origin(to, self.num_col),
+        );
+        let mut vacancies_changed = false;
+        for r in 0..groups.rows {
+            for c in 0..groups.cols {
+                let source = self.to_vec_index((from_row + r, from_col + c));
+                let target = self.to_vec_index((to_row + r, to_col + c));
+                if self.vacant[source] {
+                    if !self.vacant[target] {
+                        self.vacate(target);
+                        vacancies_changed = true;
+                    }
+                    continue;
+                }
+                let original = &self.grid[source];
+                let coord = self.grid[target].coord;
+                let mut copy = Agent::new(coord, original.strategy).with_class(original.class);
+                copy.inherit(original.inheritance());
+                copy.score = original.score;
+                copy.energy = original.energy;
+                copy.stake = original.stake;
+                copy.interactions = original.interactions;
+                copy.age = original.age;
+                copy.id = if self.vacant[target] {
+                    self.next_id += 1;
+                    self.vacant[target] = false;
+                    vacancies_changed = true;
+                    self.next_id - 1
+                } else {
+                    self.grid[target].id
+                };
+                copy.set_memory_window(self.memory_window);
+                for &j in self.topology.of(target) {
+                    self.grid[j].forget(coord);
+                }
+                self.grid[target] = copy;
             }
         }
+        // Copies bring their class along, which can change who plays whom.
+        if vacancies_changed || self.asymmetric.is_some() {
+            self.link_neighbors();
+        }
+    }
 
-        Environment {
-            num_row,
-            num_col,
-            noise,
-            grid,
+    /// Fires the shocks due this step; returns where they hit. Each struck agent gets its new
+    /// strategy and loses its history, which its neighbors forget too, but keeps its score,
+    /// energy and lineage. Vacant cells stay vacant.
+    fn apply_shocks(&mut self) -> Vec<FiredShock> {
+        let mut fired = Vec::new();
+        for (index, shock) in self.shocks.clone().into_iter().enumerate() {
+            let mut rng = cell_rng(self.seed, self.steps_taken, index, SHOCK_DRAWS);
+            for _ in 0..shock.firings(self.steps_taken, &mut rng) {
+                let hit = shock.fire(index, self.dimensions(), &mut rng);
+                for coord in hit.cells() {
+                    let i = self.to_vec_index(coord);
+                    if self.vacant[i] {
+                        continue;
+                    }
+                    let agent = &mut self.grid[i];
+                    agent.strategy = match shock.effect {
+                        ShockEffect::Randomize => {
+                            *Strategy::ALL.choose(&mut rng).unwrap_or(&agent.strategy)
+                        }
+                        ShockEffect::Set(strategy) => strategy,
+                    };
+                    agent.forget_all();
+                    for &j in self.topology.of(i) {
+                        self.grid[j].forget(coord);
+                    }
+                }
+                fired.push(hit);
+            }
+        }
+        fired
+    }
+
+    /// Scores every agent on `realized`, each agent's action toward each neighbor slot after
+    /// noise, and records one [`InteractionOutcome`] per game, so both sides of a pair see the
+    /// same actions. `intended` holds the actions before noise. Both sides of a `refused` pair
+    /// earn the outside option instead.
+    fn score_slots(
+        &mut self,
+        intended: &[Action],
+        realized: &[Action],
+        refused: Option<&[bool]>,
+        parallel: bool,
+    ) {
+        let (payoffs, skipped) = match self.payoff_fn.clone() {
+            Some(payoff_fn) => {
+                let scored = self.pair_payoffs(realized, refused, &payoff_fn);
+                self.rejected_payoffs += scored.rejected;
+                (scored.payoffs, scored.skipped)
+            }
+            None => (self.matrix_payoffs(realized, refused), None),
+        };
+        // Skipped games go unscored just like refused ones, minus the outside option.
+        let unplayed = skipped.as_ref().map(|skipped| match refused {
+            Some(refused) => skipped.iter().zip(refused).map(|(s, r)| s | r).collect(),
+            None => skipped.clone(),
+        });
+        let unplayed: Option<&[bool]> = unplayed.as_deref().or(refused);
+        self.tally_flow(&payoffs, unplayed);
+        self.observe(realized, unplayed, &payoffs);
+        self.map_cooperation(realized, unplayed);
+        let width = self.neighbors.width();
+        let skip = |slot: usize| refused.is_some_and(|r| r[slot]);
+        let skipped = skipped.as_deref();
+        let table = &self.neighbors;
+        let (num_col, outside, step) = (self.num_col, self.outside_option(), self.steps_taken);
+        for_each_chunk(&mut self.grid, 1, parallel, |i, agent| {
+            for (k, &j) in table.of(i).iter().enumerate() {
+                let slot = i * width + k;
+                if skip(slot) {
+                    agent[0].earn(outside);
+                    continue;
+                }
+                if skipped.is_some_and(|s| s[slot]) {
+                    continue;
+                }
+                agent[0].record_outcome(InteractionOutcome {
+                    opponent: (j / num_col, j % num_col),
+                    intended: intended[slot],
+                    realized: realized[slot],
+                    their_action: realized[j * width + table.back(i, k)],
+                    payoff: payoffs[slot],
+                    step,
+                });
+            }
+        });
+    }
+
+    /// Adds what every played slot pays to this step's payoff flow, if it's tracked.
+    fn tally_flow(&mut self, payoffs: &[f64], refused: Option<&[bool]>) {
+        let Some(flow) = self.payoff_flow.as_mut() else {
+            return;
+        };
+        let (table, grid) = (&self.neighbors, &self.grid);
+        let width = table.width();
+        for (i, agent) in grid.iter().enumerate() {
+            for (k, &j) in table.of(i).iter().enumerate() {
+                let slot = i * width + k;
+                if !refused.is_some_and(|r| r[slot]) {
+                    flow.add(agent.strategy, grid[j].strategy, payoffs[slot]);
+                }
+            }
         }
     }
 
-    fn score(a: Action, b: Action) -> f32 {
-        match (a, b) {
-            (Action::Coop, Action::Coop) => 3.0,
-            (Action::Deflect, Action::Coop) => 4.0,
-            _ => 0.0,
+    /// Fills in this step's cooperation rate map, if it's tracked, from the games played.
+    fn map_cooperation(&mut self, realized: &[Action], unplayed: Option<&[bool]>) {
+        let Some(map) = self.coop_rate_map.as_mut() else {
+            return;
+        };
+        let table = &self.neighbors;
+        let width = table.width();
+        for (i, rate) in map.iter_mut().enumerate() {
+            let (mut games, mut coop) = (0, 0);
+            for (k, &j) in table.of(i).iter().enumerate() {
+                let slot = i * width + k;
+                if unplayed.is_some_and(|u| u[slot]) {
+                    continue;
+                }
+                let theirs = realized[j * width + table.back(i, k)];
+                games += 1;
+                coop += usize::from(realized[slot] == Action::Coop)
+                    + usize::from(theirs == Action::Coop);
+            }
+            *rate = if games == 0 {
+                0.0
+            } else {
+                coop as f32 / (2 * games) as f32
+            };
         }
     }
 
-    fn for_each_cell<F>(&mut self, mut f: F)
-    where
-        F: FnMut(&mut Agent, Vec<&Agent>),
-    {
-        for x in 0..self.num_row {
-            for y in 0..self.num_col {
-                // Make sure all indicies are unique.
-                let mut all_coords: Vec<Coord> = self
-                    .neighbor_coord((x, y))
-                    .into_iter()
-                    .chain(vec![(x, y)])
-                    .collect();
-                all_coords.sort();
-                let before_dedup = all_coords.len();
-                all_coords.dedup();
-                let after_dedpup = all_coords.len();
-                assert_eq!(before_dedup, after_dedpup);
-                // Make sure all index are valid.
-                all_coords
-                    .into_iter()
-                    .map(|c| self.to_vec_index(c))
-                    .for_each(|i| assert!(i < self.grid.len()));
-
-                // SAFETY: All indexes are within grid.len and are unique.
-                unsafe {
-                    let ptr = self.grid.as_mut_ptr();
-                    let current = ptr.add(self.to_vec_index((x, y))).as_mut().unwrap();
-                    let agents: Vec<&Agent> = self
-                        .neighbor_coord((x, y))
-                        .iter()
-                        .map(|c| ptr.add(self.to_vec_index(*c)).as_ref().unwrap())
-                        .collect();
-                    f(current, agents);
+    /// Shows the observer, if any, every pair of neighbors that played, once per pair.
+    fn observe(&self, realized: &[Action], refused: Option<&[bool]>, payoffs: &[f64]) {
+        let Some(observer) = &self.observer else {
+            return;
+        };
+        let mut observer = observer.lock().unwrap_or_else(|e| e.into_inner());
+        let (table, grid) = (&self.neighbors, &self.grid);
+        let width = table.width();
+        for (i, agent) in grid.iter().enumerate() {
+            for (k, &j) in table.of(i).iter().enumerate() {
+                let slot = i * width + k;
+                if j < i || refused.is_some_and(|r| r[slot]) {
+                    continue;
                 }
+                let back = j * width + table.back(i, k);
+                observer.on_interaction(&Interaction {
+                    step: self.steps_taken,
+                    first: agent.coord,
+                    second: grid[j].coord,
+                    first_strategy: agent.strategy,
+                    second_strategy: grid[j].strategy,
+                    first_action: realized[slot],
+                    second_action: realized[back],
+                    first_payoff: payoffs[slot],
+                    second_payoff: payoffs[back],
+                });
             }
         }
     }
 
-    fn neighbor_coord(&self, coord: Coord) -> Vec<Coord> {
-        let mut result: Vec<Coord> = Vec::new();
-        let upper = |bond: usize| move |s: usize| (s < bond).then_some(s);
-        for dx in [-1, 0, 1] {
-            for dy in [-1, 0, 1] {
-                if dx != 0 || dy != 0 {
-                    let x = coord.0.checked_add_signed(dx).and_then(upper(self.num_row));
-                    let y = coord.1.checked_add_signed(dy).and_then(upper(self.num_col));
+    /// What every played slot of `realized` pays its agent under the agent's class's matrix.
+    fn matrix_payoffs(&self, realized: &[Action], refused: Option<&[bool]>) -> Vec<f64> {
+        let width = self.neighbors.width();
+        let skip = |slot: usize| refused.is_some_and(|r| r[slot]);
+        let (table, payoffs) = (&self.neighbors, self.class_payoffs());
+        let mut earned = vec![0.0; realized.len()];
+        for (i, agent) in self.grid.iter().enumerate() {
+            for (k, &j) in table.of(i).iter().enumerate() {
+                let slot = i * width + k;
+                if !skip(slot) {
+                    let their_action = realized[j * width + table.back(i, k)];
+                    earned[slot] = payoffs[agent.class.index()].score(realized[slot], their_action);
+                }
+            }
+        }
+        earned
+    }
 
-                    if let (Some(x), Some(y)) = (x, y) {
-                        result.push((x, y));
+    /// What every played slot of `realized` pays its agent under `payoff_fn`, called once per
+    /// pair of neighbors that played, with non-finite payoffs handled under the environment's
+    /// [`NonFinitePolicy`].
+    fn pair_payoffs(
+        &self,
+        realized: &[Action],
+        refused: Option<&[bool]>,
+        payoff_fn: &PayoffFn,
+    ) -> PairPayoffs {
+        let width = self.neighbors.width();
+        let skip = |slot: usize| refused.is_some_and(|r| r[slot]);
+        let table = &self.neighbors;
+        let grid = &self.grid;
+        let mut scored = PairPayoffs {
+            payoffs: vec![0.0; grid.len() * width],
+            skipped: None,
+            rejected: 0,
+        };
+        for i in 0..grid.len() {
+            for (k, &j) in table.of(i).iter().enumerate() {
+                if j < i || skip(i * width + k) {
+                    continue;
+                }
+                let back = j * width + table.back(i, k);
+                let (mut first, mut second) = payoff_fn(&PayoffCtx {
+                    first: &grid[i],
+                    second: &grid[j],
+                    first_action: realized[i * width + k],
+                    second_action: realized[back],
+                });
+                let rejected = usize::from(!first.is_finite()) + usize::from(!second.is_finite());
+                if rejected > 0 {
+                    scored.rejected += rejected;
+                    match self.non_finite {
+                        NonFinitePolicy::Panic => panic!(
+                            "payoff function returned ({}, {}) for the game between {:?} and \
+                             {:?} at step {}",
+                            first, second, grid[i].coord, grid[j].coord, self.steps_taken
+                        ),
+                        NonFinitePolicy::ClampToZero => {
+                            first = if first.is_finite() { first } else { 0.0 };
+                            second = if second.is_finite() { second } else { 0.0 };
+                        }
+                        NonFinitePolicy::SkipInteraction => {
+                            let skipped = scored
+                                .skipped
+                                .get_or_insert_with(|| vec![false; grid.len() * width]);
+                            skipped[i * width + k] = true;
+                            skipped[back] = true;
+                            continue;
+                        }
                     }
                 }
+                scored.payoffs[i * width + k] = first;
+                scored.payoffs[back] = second;
             }
         }
-        result
+        scored
     }
 
-    fn to_vec_index(&self, coord: Coord) -> usize {
-        self.num_col * coord.0 + coord.1
+    /// Pays every living agent what it scored since `scores_before` minus the living cost,
+    /// removes the agents that ran out of energy and lets the rich ones reproduce.
+    fn live_and_die(&mut self, energy: EnergyConfig, scores_before: &[f64]) -> Turnover {
+        for ((agent, before), vacant) in self.grid.iter_mut().zip(scores_before).zip(&self.vacant) {
+            if !vacant {
+                agent.energy += agent.score - before - energy.living_cost;
+            }
+        }
+        let starved: Vec<usize> = (0..self.grid.len())
+            .filter(|&i| !self.vacant[i] && self.grid[i].energy < 0.0)
+            .collect();
+        for &i in &starved {
+            self.vacate(i);
+        }
+        let births = self.reproduce(energy);
+        if births > 0 || !starved.is_empty() {
+            self.link_neighbors();
+        }
+        Turnover {
+            births,
+            deaths: starved.len(),
+        }
+    }
+
+    /// Empties the cell at `index`; its neighbors forget the agent that lived there.
+    fn vacate(&mut self, index: usize) {
+        self.vacant[index] = true;
+        let agent = &self.grid[index];
+        let (coord, strategy, class) = (agent.coord, agent.strategy, agent.class);
+        for &j in self.topology.of(index) {
+            self.grid[j].forget(coord);
+        }
+        // Keep the strategy and class so snapshots can still tell what played there last.
+        self.grid[index] = Agent::new(coord, strategy).with_class(class);
+    }
+
+    /// Lets every living agent at or above the reproduction threshold place an offspring in a
+    /// random vacant neighboring cell, splitting its energy with it. Returns the births.
+    fn reproduce(&mut self, energy: EnergyConfig) -> usize {
+        let parents: Vec<usize> = (0..self.grid.len())
+            .filter(|&i| !self.vacant[i] && self.grid[i].energy >= energy.reproduction_threshold)
+            .collect();
+        let mut births = 0;
+        for i in parents {
+            let mut rng = cell_rng(self.seed, self.steps_taken, i, BIRTH_DRAWS);
+            let empty: Vec<usize> = self
+                .topology
+                .of(i)
+                .iter()
+                .copied()
+                .filter(|&j| self.vacant[j])
+                .collect();
+            let Some(&j) = empty.choose(&mut rng) else {
+                continue;
+            };
+            let coord = self.grid[j].coord;
+            let parent = &mut self.grid[i];
+            parent.energy /= 2.0;
+            let mut child = Agent::new(coord, parent.strategy).with_class(parent.class);
+            if rng.gen::<f64>() < energy.mutation_rate {
+                child.strategy = *Strategy::ALL.choose(&mut rng).unwrap_or(&parent.strategy);
+            }
+            child.id = self.next_id;
+            child.parent_id = Some(parent.id);
+            child.founder_id = parent.founder_id;
+            child.energy = parent.energy;
+            child.stake = parent.stake;
+            child.set_memory_window(self.memory_window);
+            self.next_id += 1;
+            self.grid[j] = child;
+            self.vacant[j] = false;
+            births += 1;
+        }
+        births
+    }
+
+    /// Checks the tally against a full recount every `TALLY_CHECK_EVERY` steps, in debug
+    /// builds.
+    fn check_tally(&self) {
+        if cfg!(debug_assertions) && self.steps_taken.is_multiple_of(TALLY_CHECK_EVERY) {
+            let recount = Tally::count(self.num_row, self.num_col, &self.grid, &self.vacant);
+            assert_eq!(
+                self.tally, recount,
+                "tally drifted by step {}",
+                self.steps_taken
+            );
+        }
+    }
+
+    /// The metric of the step just played, which advances the step count.
+    fn record_metric(
+        &mut self,
+        played: Played,
+        turnover: Turnover,
+        switches: Switches,
+        groups: Vec<GroupMetric>,
+        shocks: Vec<FiredShock>,
+        interventions: Vec<usize>,
+    ) -> Metric {
+        if self.tally.vacant.len() != self.grid.len() {
+            self.tally = Tally::count(self.num_row, self.num_col, &self.grid, &self.vacant);
+        }
+        // One pass over the agents: the tally only changes where they did, and what changes
+        // every step, the scores, goes into flat per-kind arrays rather than maps.
+        const KINDS: usize = Strategy::ALL.len();
+        let (mut max_score, mut total_score, mut total_stake) =
+            ([0.0; KINDS], [0.0; KINDS], [0.0; KINDS]);
+        let (mut per_interaction, mut per_step) = ([0.0; KINDS], [0.0; KINDS]);
+        let mut top: [Option<(Coord, f64)>; KINDS] = [None; KINDS];
+        let mut values: BTreeMap<(Strategy, &'static str), Vec<f64>> = BTreeMap::new();
+        let mut scores = Vec::with_capacity(self.grid.len());
+        for (i, (curr, &vacant)) in self.grid.iter_mut().zip(&self.vacant).enumerate() {
+            self.tally.update(i, curr, vacant);
+            if vacant {
+                scores.push(0.0);
+                continue;
+            }
+            curr.age += 1;
+            scores.push(curr.score);
+            let kind = curr.strategy.index() as usize;
+            max_score[kind] = f64::max(max_score[kind], curr.score);
+            total_score[kind] += curr.score;
+            total_stake[kind] += curr.stake as f64;
+            let normalized = curr.score_per_interaction();
+            per_interaction[kind] += normalized;
+            per_step[kind] += curr.score_per_step();
+            if top[kind].is_none_or(|(_, best)| normalized > best) {
+                top[kind] = Some((curr.coord, normalized));
+            }
+            for (name, value) in curr.strategy.params() {
+                values
+                    .entry((curr.strategy.kind(), name))
+                    .or_default()
+                    .push(value);
+            }
+        }
+        self.check_tally();
+        let counts = self.tally.counts;
+        let per_kind = |value: &dyn Fn(usize) -> f64| -> BTreeMap<Strategy, f64> {
+            (Strategy::ALL.into_iter().enumerate())
+                .filter(|&(i, _)| counts[i] > 0)
+                .map(|(i, kind)| (kind, value(i)))
+                .collect()
+        };
+        let strategies: BTreeMap<Strategy, usize> = (Strategy::ALL.into_iter().zip(counts))
+            .filter(|&(_, n)| n > 0)
+            .collect();
+        let max_score = per_kind(&|i| max_score[i]);
+        let avg_score = per_kind(&|i| total_score[i] / counts[i] as f64);
+        let mean_stake = per_kind(&|i| total_stake[i] / counts[i] as f64);
+        let score_per_interaction = per_kind(&|i| per_interaction[i] / counts[i] as f64);
+        let score_per_step = per_kind(&|i| per_step[i] / counts[i] as f64);
+        let top_agent = (Strategy::ALL.into_iter().zip(top))
+            .filter_map(|(kind, top)| top.map(|(coord, _)| (kind, coord)))
+            .collect();
+        let parameters = values
+            .into_iter()
+            .map(|(key, values)| {
+                let n = values.len() as f64;
+                let mean = values.iter().sum::<f64>() / n;
+                let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+                let std = variance.sqrt();
+                (key, ParamStats { mean, std })
+            })
+            .collect();
+        let mut class_strategies = match self.asymmetric {
+            Some(_) => vec![BTreeMap::new(); Class::ALL.len()],
+            None => Vec::new(),
+        };
+        if !class_strategies.is_empty() {
+            for agent in self.living() {
+                *class_strategies[agent.class.index()]
+                    .entry(agent.strategy.kind())
+                    .or_insert(0) += 1;
+            }
+        }
+        let mut snapshot =
+            Snapshot::new(self.tally.strategies.clone()).with_vacancies(&self.vacant);
+        if self.asymmetric.is_some() {
+            snapshot = snapshot.with_classes(self.grid.iter().map(|a| a.class));
+        }
+        let scores = Grid::new(self.num_row, self.num_col, scores);
+        let thumbnail = self.thumbnails.then(|| snapshot.thumbnail(THUMBNAIL_SIZE));
+
+        let coop_rate = if played.actions == 0 {
+            0.0
+        } else {
+            played.coop_actions as f32 / played.actions as f32
+        };
+        if let Some(feedback) = self.feedback {
+            self.set_env_state(feedback.next_state(self.env_state, coop_rate as f64));
+        }
+
+        let step_index = self.steps_taken;
+        if let Some(observer) = &self.observer {
+            observer
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .end_step(step_index);
+        }
+        self.steps_taken += 1;
+
+        Metric {
+            coop_actions: played.coop_actions as i32,
+            coop_rate,
+            strategies,
+            max_score,
+            avg_score,
+            mean_stake,
+            score_per_interaction,
+            score_per_step,
+            top_agent,
+            parameters,
+            step_index,
+            snapshot,
+            founder_lineages: self.tally.lineages.len(),
+            population: counts.iter().sum(),
+            births: turnover.births,
+            deaths: turnover.deaths,
+            imitations: switches.imitated,
+            explorations: switches.explored,
+            refusals: played.refusals,
+            rejected_payoffs: std::mem::take(&mut self.rejected_payoffs),
+            honest_signals: played.honest_signals,
+            dishonest_signals: played.dishonest_signals,
+            class_strategies,
+            groups,
+            env_state: self.env_state as f32,
+            shocks,
+            interventions,
+            frozen: self.is_frozen(),
+            scores,
+            payoff_flow: self.payoff_flow.as_mut().map(std::mem::take),
+            coop_rate_map: (self.coop_rate_map.as_ref())
+                .map(|map| Grid::new(self.num_row, self.num_col, map.clone())),
+            thumbnail,
+        }
+    }
+
+    /// An endless iterator that steps this environment on every `next`, so runs read as
+    /// iterator chains, e.g. `env.steps().take(100)` or `.take_while(|m| m.coop_rate < 0.5)`.
+    pub fn steps(&mut self) -> StepIter<'_> {
+        StepIter { env: self }
+    }
+
+    /// Takes `n` steps and returns their metrics.
+    pub fn run(&mut self, n: usize) -> Vec<Metric> {
+        let mut metrics = Vec::with_capacity(n);
+        self.run_with(n, |m| metrics.push(m));
+        metrics
+    }
+
+    /// Steps until `pred` returns false for a metric or `max` steps are taken. The metric that
+    /// failed the predicate is included; the flag tells whether the run stopped early.
+    /// `env.run_while(|m| !m.frozen, max)` stops once nothing changes any more.
+    pub fn run_while(
+        &mut self,
+        mut pred: impl FnMut(&Metric) -> bool,
+        max: usize,
+    ) -> (Vec<Metric>, bool) {
+        let mut metrics = Vec::with_capacity(max.min(MAX_RESERVE));
+        for _ in 0..max {
+            let metric = self.step();
+            let keep_going = pred(&metric);
+            metrics.push(metric);
+            if !keep_going {
+                return (metrics, true);
+            }
+        }
+        (metrics, false)
+    }
+
+    /// Steps until one of `conditions` holds and returns the metrics, the last of which
+    /// triggered it, along with the condition; the first listed wins if several hold at
+    /// once. Never returns if none ever holds, so bound the run with
+    /// [`StopCondition::MaxSteps`] or [`StopCondition::WallClock`]. Without any conditions,
+    /// takes no steps and returns no condition.
+    pub fn run_until(
+        &mut self,
+        conditions: &[StopCondition],
+    ) -> (Vec<Metric>, Option<StopCondition>) {
+        let started = Instant::now();
+        self.run_until_with_clock(conditions, || started.elapsed())
+    }
+
+    /// [`Environment::run_until`] timing [`StopCondition::WallClock`] with `elapsed`, called
+    /// after every step for the time since the run started.
+    pub fn run_until_with_clock(
+        &mut self,
+        conditions: &[StopCondition],
+        mut elapsed: impl FnMut() -> Duration,
+    ) -> (Vec<Metric>, Option<StopCondition>) {
+        let mut stopper = Stopper::new(conditions.iter().copied());
+        let mut metrics = Vec::new();
+        if conditions.is_empty() {
+            return (metrics, None);
+        }
+        loop {
+            let metric = self.step();
+            let stopped = stopper.check_at(&metric, elapsed());
+            metrics.push(metric);
+            if stopped.is_some() {
+                return (metrics, stopped);
+            }
+        }
+    }
+
+    /// Takes `n` steps, handing each metric to `sink` instead of collecting them, for long
+    /// runs that only need aggregates.
+    pub fn run_with(&mut self, n: usize, mut sink: impl FnMut(Metric)) {
+        for _ in 0..n {
+            sink(self.step());
+        }
+    }
+
+    /// Number of steps taken so far; the `step_index` of the next metric.
+    pub fn steps_taken(&self) -> usize {
+        self.steps_taken
+    }
+
+    /// Random Deflect and TicToc agents with a random seed. Fails if the grid is empty or
+    /// `noise` isn't in `[0, 1]`; see [`EnvironmentBuilder`] for the other parameters.
+    pub fn new(num_row: usize, num_col: usize, noise: f32) -> Result<Environment, Error> {
+        EnvironmentBuilder::new()
+            .size(num_row, num_col)
+            .noise(noise)
+            .build()
+    }
+
+    /// Fails on invalid parameters, like [`Environment::new`].
+    pub fn new_with_agent_func<F>(
+        num_row: usize,
+        num_col: usize,
+        noise: f32,
+        mut agent_fn: F,
+    ) -> Result<Environment, Error>
+    where
+        F: FnMut(Coord) -> Agent,
+    {
+        EnvironmentBuilder::new()
+            .size(num_row, num_col)
+            .noise(noise)
+            .agents(|c, _| agent_fn(c))
+            .build()
+    }
+
+    /// Creates an environment whose randomness is fully determined by `seed`: `agent_fn`
+    /// receives the environment's RNG for initial placement, and the same RNG then drives
+    /// noise and random strategies, so equal seeds give identical runs. Fails on invalid
+    /// parameters, like [`Environment::new`].
+    pub fn new_with_seed<F>(
+        num_row: usize,
+        num_col: usize,
+        noise: f32,
+        seed: u64,
+        agent_fn: F,
+    ) -> Result<Environment, Error>
+    where
+        F: FnMut(Coord, &mut StdRng) -> Agent,
+    {
+        EnvironmentBuilder::new()
+            .size(num_row, num_col)
+            .noise(noise)
+            .seed(seed)
+            .agents(agent_fn)
+            .build()
+    }
+
+    /// Exactly `mix`'s share of each strategy, rounded by largest remainder and shuffled with
+    /// the seeded RNG; see [`EnvironmentBuilder::mix`] for blocked placement. Fails on an
+    /// invalid mix or parameters, like [`Environment::new`].
+    pub fn new_with_mix(
+        num_row: usize,
+        num_col: usize,
+        noise: f32,
+        mix: &[(Strategy, f32)],
+        seed: u64,
+    ) -> Result<Environment, Error> {
+        EnvironmentBuilder::new()
+            .size(num_row, num_col)
+            .noise(noise)
+            .seed(seed)
+            .mix(mix, Placement::Shuffled)
+            .build()
+    }
+
+    /// Assembles an environment from already validated parts: `grid` holds one agent per cell
+    /// in row-major order, each at its own coordinate. `rng` must be the one the grid was
+    /// placed with, seeded from `seed`. The agents become founders numbered by their index.
+    pub(crate) fn from_parts(
+        (num_row, num_col): (usize, usize),
+        noise: f32,
+        payoffs: Payoffs,
+        mut grid: Vec<Agent>,
+        seed: u64,
+        rng: StdRng,
+        tables: &Tables,
+    ) -> Environment {
+        debug_assert_eq!(grid.len(), num_row * num_col);
+        debug_assert_eq!(tables.topology.len(), grid.len());
+        for (id, agent) in (0..).zip(&mut grid) {
+            agent.id = id;
+            agent.parent_id = None;
+            agent.founder_id = id;
+        }
+        Environment {
+            num_row,
+            num_col,
+            noise,
+            payoffs,
+            vacant: vec![false; grid.len()],
+            next_id: grid.len() as u64,
+            grid,
+            topology: Arc::clone(&tables.topology),
+            neighbors: Arc::clone(&tables.topology),
+            seed,
+            rng,
+            steps_taken: 0,
+            memory_window: None,
+            update_mode: UpdateMode::default(),
+            imitation: Imitation::default(),
+            mutation_sigma: 0.0,
+            exploration_rate: 0.0,
+            payoff_fn: None,
+            non_finite: NonFinitePolicy::default(),
+            rejected_payoffs: 0,
+            energy: None,
+            partner_choice: None,
+            signaling: false,
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnails: false,
+            observer: None,
+            groups: None,
+            group_payoffs: Vec::new(),
+            shocks: Vec::new(),
+            agenda: Agenda::default(),
+            tally: Tally::default(),
+            freeze_after: Some(FREEZE_AFTER),
+            still_steps: 0,
+            asymmetric: None,
+            interaction: tables.interaction,
+            adaptation: tables.adaptation,
+            models: tables.models.clone(),
+            feedback: None,
+            env_state: 1.0,
+            last_order: None,
+        }
+    }
+
+    pub fn update_mode(&self) -> UpdateMode {
+        self.update_mode
+    }
+
+    /// Changes how subsequent steps update the agents.
+    pub fn set_update_mode(&mut self, mode: UpdateMode) {
+        self.update_mode = mode;
+    }
+
+    /// The row-major indices of the agents in the order the last step visited them, when it
+    /// drew a random order; `None` otherwise.
+    pub fn last_update_order(&self) -> Option<&[usize]> {
+        self.last_order.as_deref()
+    }
+
+    pub fn imitation(&self) -> Imitation {
+        self.imitation
+    }
+
+    /// Changes what agents compare when choosing whom to imitate from the next step on.
+    pub fn set_imitation(&mut self, imitation: Imitation) {
+        self.imitation = imitation;
+    }
+
+    /// How far imitated strategy parameters stray from the model's; 0 copies them exactly.
+    pub fn mutation_sigma(&self) -> f64 {
+        self.mutation_sigma
+    }
+
+    /// Makes imitation copy strategy parameters with Gaussian noise of standard deviation
+    /// `sigma` from the next step on (see [`Strategy::mutate`]), which turns the run into a
+    /// model of continuous trait evolution. Fails unless `sigma` is finite and non-negative.
+    pub fn set_mutation_sigma(&mut self, sigma: f64) -> Result<(), Error> {
+        if !(sigma.is_finite() && sigma >= 0.0) {
+            return Err(Error::InvalidMutation(sigma));
+        }
+        self.mutation_sigma = sigma;
+        Ok(())
+    }
+
+    pub fn exploration_rate(&self) -> f32 {
+        self.exploration_rate
+    }
+
+    /// Has every agent, with probability `rate` each step, copy a neighbor picked at random
+    /// instead of imitating by payoff, from the next step on. That models copying errors
+    /// rather than innovation: nothing new enters the grid, and at 1 payoffs stop mattering,
+    /// which is the voter model. Works with any update mode and [`Imitation`]; copies mutate
+    /// like imitated ones. Fails unless `rate` is in [0, 1].
+    pub fn set_exploration_rate(&mut self, rate: f32) -> Result<(), Error> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(Error::InvalidExploration(rate));
+        }
+        self.exploration_rate = rate;
+        Ok(())
+    }
+
+    pub fn memory_window(&self) -> Option<usize> {
+        self.memory_window
+    }
+
+    /// Bounds every agent's per-opponent history to the last `window` actions (`None` keeps
+    /// them all). Most strategies read only the latest actions, theirs and the opponent's,
+    /// so a window of 1 leaves their play unaffected; Choosy weighs the last 3 when choosing
+    /// partners, and refuses fewer with a shorter window. Fails if `window` is 0, which
+    /// would leave agents answering as if they had never met.
+    pub fn set_memory_window(&mut self, window: Option<usize>) -> Result<(), Error> {
+        if window == Some(0) {
+            return Err(Error::EmptyMemoryWindow);
+        }
+        self.memory_window = window;
+        for agent in &mut self.grid {
+            agent.set_memory_window(window);
+        }
+        Ok(())
+    }
+
+    /// The payoff matrix the next step is scored with; under [`EnvFeedback`], the one for the
+    /// environment's current state.
+    pub fn payoffs(&self) -> Payoffs {
+        self.payoffs
+    }
+
+    /// The environmental feedback on payoffs, if any.
+    pub fn feedback(&self) -> Option<EnvFeedback> {
+        self.feedback
+    }
+
+    /// Couples the payoffs to the shared environment, starting from its current state.
+    pub(crate) fn enable_feedback(&mut self, feedback: EnvFeedback) {
+        self.feedback = Some(feedback);
+        self.set_env_state(self.env_state);
+    }
+
+    /// How rich the shared environment is, from 0 (degraded) to 1 (rich). Stays 1 without
+    /// feedback.
+    pub fn env_state(&self) -> f64 {
+        self.env_state
+    }
+
+    /// Moves the shared environment to `state`, clamped to `[0, 1]`, and under feedback scores
+    /// the next step accordingly.
+    pub fn set_env_state(&mut self, state: f64) {
+        self.env_state = state.clamp(0.0, 1.0);
+        if let Some(feedback) = self.feedback {
+            self.payoffs = feedback.payoffs_at(self.env_state);
+        }
+    }
+
+    /// Scores every game with `payoff_fn` instead of the payoff matrix from the next step on.
+    /// It gets both agents and the actions they played and returns both their points. Each
+    /// pair of neighbors plays once per step, with noise applied once to each side's action,
+    /// so the function is called exactly once per pair.
+    pub fn set_payoff_fn<F>(&mut self, payoff_fn: F)
+    where
+        F: Fn(&PayoffCtx<'_>) -> (f64, f64) + Send + Sync + 'static,
+    {
+        self.payoff_fn = Some(Arc::new(payoff_fn));
+    }
+
+    /// Goes back to scoring with the payoff matrix.
+    pub fn clear_payoff_fn(&mut self) {
+        self.payoff_fn = None;
+    }
+
+    pub fn has_payoff_fn(&self) -> bool {
+        self.payoff_fn.is_some()
+    }
+
+    /// What happens when the payoff function returns a NaN or infinite payoff.
+    pub fn non_finite_policy(&self) -> NonFinitePolicy {
+        self.non_finite
+    }
+
+    /// Handles NaN and infinite payoffs from the payoff function under `policy` from the next
+    /// step on.
+    pub fn set_non_finite_policy(&mut self, policy: NonFinitePolicy) {
+        self.non_finite = policy;
+    }
+
+    pub fn noise(&self) -> f32 {
+        self.noise
+    }
+
+    /// Sets the action noise for subsequent steps, clamped to `[0, 1]`, and returns the value
+    /// applied. NaN is ignored and leaves the current noise unchanged.
+    pub fn set_noise(&mut self, noise: f32) -> f32 {
+        if !noise.is_nan() {
+            self.noise = noise.clamp(0.0, 1.0);
+        }
+        self.noise
+    }
+
+    /// The seed this environment's RNG was created from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A copy of the current state, agents, histories, scores and step count included, whose
+    /// random draws differ from this environment's from here on. The branch's seed is the next
+    /// number this environment's RNG would draw, taken from a copy of it so this environment's
+    /// own future is untouched; forking twice without stepping in between gives equal branches.
+    pub fn fork(&self) -> Environment {
+        self.fork_with_seed(self.rng.clone().gen())
+    }
+
+    /// A copy of the current state that draws from a fresh RNG seeded by `seed`, which also
+    /// becomes the branch's [`seed`](Environment::seed). Branches with the same seed play
+    /// identical futures.
+    pub fn fork_with_seed(&self, seed: u64) -> Environment {
+        Environment {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            ..self.clone()
+        }
+    }
+
+    /// Grid size as `(rows, cols)`.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.num_row, self.num_col)
+    }
+
+    /// The agent at `coord`, or `None` if the coordinate is outside the grid or vacant.
+    pub fn agent_at(&self, coord: Coord) -> Option<&Agent> {
+        if coord.0 < self.num_row && coord.1 < self.num_col {
+            let index = self.to_vec_index(coord);
+            self.grid.get(index).filter(|_| !self.vacant[index])
+        } else {
+            None
+        }
+    }
+
+    /// Mutable access to the agent at `coord`, or `None` if it's outside the grid or vacant.
+    pub fn agent_at_mut(&mut self, coord: Coord) -> Option<&mut Agent> {
+        if coord.0 < self.num_row && coord.1 < self.num_col {
+            let index = self.to_vec_index(coord);
+            if self.vacant[index] {
+                return None;
+            }
+            self.grid.get_mut(index)
+        } else {
+            None
+        }
+    }
+
+    /// The energy budget, if agents live on one.
+    pub fn energy(&self) -> Option<EnergyConfig> {
+        self.energy
+    }
+
+    /// Partner choice, if agents may refuse to play.
+    pub fn partner_choice(&self) -> Option<PartnerChoice> {
+        self.partner_choice
+    }
+
+    /// Lets agents refuse games from the next step on, or makes them play every neighbor
+    /// again with `None`.
+    pub fn set_partner_choice(&mut self, partner_choice: Option<PartnerChoice>) {
+        self.partner_choice = partner_choice;
+    }
+
+    /// Whether agents signal an intended action before every round.
+    pub fn signaling(&self) -> bool {
+        self.signaling
+    }
+
+    /// Turns cheap talk on or off from the next step on.
+    pub fn set_signaling(&mut self, signaling: bool) {
+        self.signaling = signaling;
+    }
+
+    /// Whether metrics break payoffs down by who earned them off whom.
+    pub fn tracks_payoff_flow(&self) -> bool {
+        self.payoff_flow.is_some()
+    }
+
+    /// Starts or stops filling in [`Metric::payoff_flow`] from the next step on.
+    pub fn set_payoff_flow(&mut self, track: bool) {
+        self.payoff_flow = track.then(PayoffFlow::default);
+    }
+
+    pub fn tracks_coop_rate_map(&self) -> bool {
+        self.coop_rate_map.is_some()
+    }
+
+    /// Starts or stops filling in [`Metric::coop_rate_map`] from the next step on.
+    pub fn set_coop_rate_map(&mut self, track: bool) {
+        self.coop_rate_map = track.then(|| vec![0.0; self.grid.len()]);
+    }
+
+    /// Whether metrics carry a thumbnail of the grid.
+    pub fn thumbnails(&self) -> bool {
+        self.thumbnails
+    }
+
+    /// Starts or stops filling in [`Metric::thumbnail`] from the next step on.
+    pub fn set_thumbnails(&mut self, thumbnails: bool) {
+        self.thumbnails = thumbnails;
+    }
+
+    /// Shows `observer` every game from the next step on, or stops showing them with `None`.
+    /// It's called once per game, so keep it cheap on big grids; a
+    /// [`SamplingRecorder`](crate::SamplingRecorder) keeps just a fraction. Observing draws
+    /// nothing from the run's random streams.
+    pub fn set_observer(&mut self, observer: Option<SharedObserver>) {
+        self.observer = observer;
+    }
+
+    /// The groups that compete, if any.
+    pub fn groups(&self) -> Option<Groups> {
+        self.groups
+    }
+
+    /// Tiles the grid into competing groups from the next step on, or stops competition with
+    /// `None`. The next competition comes once the step count reaches a multiple of the
+    /// period, counting only what groups earn from now on. Fails if the tiles don't cover the
+    /// grid exactly.
+    pub fn set_groups(&mut self, groups: Option<Groups>) -> Result<(), Error> {
+        if let Some(g) = groups.filter(|g| !g.fits(self.dimensions())) {
+            return Err(Error::InvalidGroups(g));
+        }
+        self.groups = groups;
+        self.group_payoffs = match groups {
+            Some(g) => vec![0.0; g.count(self.dimensions())],
+            None => Vec::new(),
+        };
+        Ok(())
+    }
+
+    /// The cells each agent plays.
+    pub fn interaction_neighborhood(&self) -> Neighborhood {
+        self.interaction
+    }
+
+    /// The cells each agent may imitate.
+    pub fn adaptation_neighborhood(&self) -> Neighborhood {
+        self.adaptation
+    }
+
+    /// Has agents play every cell of `neighborhood` from the next step on. Agents keep their
+    /// histories with former partners, and offspring are placed within the new neighborhood.
+    /// Fails on an empty neighborhood.
+    pub fn set_interaction_neighborhood(
+        &mut self,
+        neighborhood: Neighborhood,
+    ) -> Result<(), Error> {
+        if !neighborhood.is_valid() {
+            return Err(Error::InvalidNeighborhood(neighborhood));
+        }
+        self.interaction = neighborhood;
+        let (rows, cols) = self.dimensions();
+        self.topology = Arc::new(NeighborTable::linking(
+            rows,
+            cols,
+            neighborhood,
+            &[],
+            |_, _| true,
+        ));
+        self.link_neighbors();
+        Ok(())
+    }
+
+    /// Lets agents imitate every cell of `neighborhood` from the next step on. Fails on an
+    /// empty neighborhood.
+    pub fn set_adaptation_neighborhood(&mut self, neighborhood: Neighborhood) -> Result<(), Error> {
+        if !neighborhood.is_valid() {
+            return Err(Error::InvalidNeighborhood(neighborhood));
+        }
+        self.adaptation = neighborhood;
+        self.link_neighbors();
+        Ok(())
+    }
+
+    /// The asymmetric game, if hosts and visitors play different roles.
+    pub fn asymmetric(&self) -> Option<Asymmetric> {
+        self.asymmetric
+    }
+
+    /// Plays an asymmetric game between the agents' classes from the next step on, or lets
+    /// everyone play and imitate every neighbor again with `None`. The payoffs set otherwise,
+    /// feedback included, are unused while it's on. Fails on non-finite payoffs.
+    pub fn set_asymmetric(&mut self, game: Option<Asymmetric>) -> Result<(), Error> {
+        if let Some(game) = game {
+            if let Some(&bad) = [game.host, game.visitor].iter().find(|p| !p.is_finite()) {
+                return Err(Error::InvalidPayoffs(bad));
+            }
+        }
+        self.asymmetric = game;
+        self.link_neighbors();
+        Ok(())
+    }
+
+    /// The shocks that may hit the grid, in the order they're tried each step.
+    pub fn shocks(&self) -> &[Shock] {
+        &self.shocks
+    }
+
+    /// Replaces the shocks from the next step on; an empty list stops them. Fails on the first
+    /// shock with an empty region or an unusable schedule.
+    pub fn set_shocks(&mut self, shocks: Vec<Shock>) -> Result<(), Error> {
+        if let Some(&shock) = shocks.iter().find(|s| !s.is_valid()) {
+            return Err(Error::InvalidShock(shock));
+        }
+        self.shocks = shocks;
+        Ok(())
+    }
+
+    /// Queues `intervention` to apply during step `at_step`, after its imitation phase and
+    /// before its games; see [`interventions`](crate::interventions). A step that has already
+    /// been taken means the next one. Returns an ID the step's
+    /// [`Metric::interventions`] will list.
+    ///
+    /// Clones and forks carry the pending interventions along, except custom ones, which can
+    /// only run once.
+    pub fn schedule(&mut self, at_step: usize, intervention: Intervention) -> usize {
+        self.agenda.push(at_step, intervention)
+    }
+
+    /// Whether the run has settled into a state every later step repeats, scores aside. That's
+    /// the case once, for `freeze_after` steps in a row, every agent played one action
+    /// whatever it knew and nobody switched strategy, while nothing was left that could change
+    /// that: no noise, energy budget, groups, feedback, shocks, partner choice, payoff
+    /// function, exploration or pending intervention.
+    ///
+    /// A frozen [`step`](Environment::step) skips the games and only adds up their known
+    /// payoffs, so it costs little and its metric is the one a full step would give, but the
+    /// agents' histories stop growing. Scores still grow, so imitation is still checked: if
+    /// someone switches after all, or a setting changes, the run thaws and steps in full.
+    pub fn is_frozen(&self) -> bool {
+        self.freeze_after.is_some_and(|k| self.still_steps >= k) && self.settled()
+    }
+
+    pub fn freeze_after(&self) -> Option<usize> {
+        self.freeze_after
+    }
+
+    /// Freezes the run after `steps` settled steps (see [`is_frozen`](Self::is_frozen)), or
+    /// never with `None`, e.g. to keep every history complete. Defaults to 5.
+    pub fn set_freeze_after(&mut self, steps: Option<usize>) {
+        self.freeze_after = steps;
+    }
+
+    /// Whether only imitation could change how the next steps play out; see `is_frozen`.
+    fn settled(&self) -> bool {
+        self.noise == 0.0
+            && self.energy.is_none()
+            && self.groups.is_none()
+            && self.feedback.is_none()
+            && self.shocks.is_empty()
+            && self.partner_choice.is_none()
+            && self.payoff_fn.is_none()
+            && self.exploration_rate == 0.0
+            && self.agenda.len() == 0
+            && self.living().all(|a| a.strategy.fixed_action().is_some())
+    }
+
+    /// Number of scheduled interventions that haven't been applied yet.
+    pub fn pending_interventions(&self) -> usize {
+        self.agenda.len()
+    }
+
+    /// Applies the interventions due this step, in the order they were scheduled; returns
+    /// their IDs.
+    fn intervene(&mut self) -> Vec<usize> {
+        let due = self.agenda.take_due(self.steps_taken);
+        let mut applied = Vec::with_capacity(due.len());
+        for (id, intervention) in due {
+            match intervention {
+                Intervention::SetStrategyRegion {
+                    origin: (top, left),
+                    rows,
+                    cols,
+                    strategy,
+                } => {
+                    let bottom = top.saturating_add(rows).min(self.num_row);
+                    let right = left.saturating_add(cols).min(self.num_col);
+                    for row in top..bottom {
+                        for col in left..right {
+                            self.set_strategy((row, col), strategy);
+                        }
+                    }
+                }
+                Intervention::SetNoise(noise) => {
+                    self.set_noise(noise);
+                }
+                Intervention::ClearHistories => self.grid.iter_mut().for_each(Agent::forget_all),
+                Intervention::ReplaceAgent { coord, strategy } => {
+                    self.replace_agent(coord, strategy)
+                }
+                Intervention::Custom(apply) => apply(self),
+            }
+            applied.push(id);
+        }
+        applied
+    }
+
+    /// Puts a newcomer playing `strategy` on `coord`, empty or not, with a fresh ID and
+    /// lineage; its neighbors forget whoever lived there. Ignored outside the grid.
+    fn replace_agent(&mut self, coord: Coord, strategy: Strategy) {
+        if coord.0 >= self.num_row || coord.1 >= self.num_col {
+            return;
+        }
+        let index = self.to_vec_index(coord);
+        for &j in self.topology.of(index) {
+            self.grid[j].forget(coord);
+        }
+        let mut agent = Agent::new(coord, strategy).with_class(self.grid[index].class);
+        agent.id = self.next_id;
+        agent.founder_id = self.next_id;
+        agent.energy = self.energy.map_or(0.0, |e| e.initial);
+        agent.set_memory_window(self.memory_window);
+        self.next_id += 1;
+        self.grid[index] = agent;
+        if std::mem::replace(&mut self.vacant[index], false) {
+            self.link_neighbors();
+        }
+    }
+
+    /// Puts the agents on `energy`'s budget, each starting with its initial energy.
+    pub(crate) fn enable_energy(&mut self, energy: EnergyConfig) {
+        for agent in &mut self.grid {
+            agent.energy = energy.initial;
+        }
+        self.energy = Some(energy);
+    }
+
+    /// Number of living agents.
+    pub fn population(&self) -> usize {
+        self.living().count()
+    }
+
+    /// Whether `coord` is inside the grid and has no agent.
+    pub fn is_vacant(&self, coord: Coord) -> bool {
+        coord.0 < self.num_row && coord.1 < self.num_col && self.vacant[self.to_vec_index(coord)]
+    }
+
+    /// Switches the agent at `coord` to `strategy` from the next step on and returns its
+    /// previous strategy, or `None` if `coord` is outside the grid.
+    pub fn set_strategy(&mut self, coord: Coord, strategy: Strategy) -> Option<Strategy> {
+        let agent = self.agent_at_mut(coord)?;
+        Some(std::mem::replace(&mut agent.strategy, strategy))
+    }
+
+    /// The ID of the founding agent whose strategy the agent at `coord` carries, or `None` if
+    /// `coord` is outside the grid or vacant.
+    pub fn founder_of(&self, coord: Coord) -> Option<u64> {
+        self.agent_at(coord).map(|a| a.founder_id)
+    }
+
+    /// Number of founding agents whose strategy some agent still carries. Imitation only
+    /// copies existing lineages, so this never grows from one step to the next unless an
+    /// intervention brings in a newcomer.
+    pub fn founder_lineages(&self) -> usize {
+        let mut founders: Vec<u64> = self.living().map(|a| a.founder_id).collect();
+        founders.sort_unstable();
+        founders.dedup();
+        founders.len()
+    }
+
+    /// Every living agent with its coordinate, in row-major order.
+    pub fn iter_agents(&self) -> impl Iterator<Item = (Coord, &Agent)> {
+        self.living().map(|a| (a.coord, a))
+    }
+
+    fn living(&self) -> impl Iterator<Item = &Agent> {
+        self.grid
+            .iter()
+            .zip(&self.vacant)
+            .filter(|(_, &vacant)| !vacant)
+            .map(|(a, _)| a)
+    }
+
+    /// Living agents adjacent to `coord` that it plays, in row-major order: all of them, or
+    /// only those of the other class under an asymmetric game. Empty if `coord` is outside the
+    /// grid or vacant.
+    pub fn neighbors(&self, coord: Coord) -> Vec<&Agent> {
+        if self.agent_at(coord).is_none() {
+            return Vec::new();
+        }
+        self.neighbors
+            .of(self.to_vec_index(coord))
+            .iter()
+            .map(|&i| &self.grid[i])
+            .collect()
+    }
+
+    /// Calls `f` with every agent, in row-major order, and the neighbors it may imitate. Agents
+    /// are updated in place, so later cells see what `f` did to earlier ones.
+    fn for_each_cell<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut Agent, SplitNeighbors<'_>),
+    {
+        let indices = 0..self.grid.len();
+        let indices: Box<dyn Iterator<Item = usize>> = if reversed() {
+            Box::new(indices.rev())
+        } else {
+            Box::new(indices)
+        };
+        self.for_each_cell_in(indices, f);
+    }
+
+    /// Like `for_each_cell`, visiting the cells at `indices` in that order.
+    fn for_each_cell_in<F>(&mut self, indices: impl Iterator<Item = usize>, mut f: F)
+    where
+        F: FnMut(&mut Agent, SplitNeighbors<'_>),
+    {
+        for index in indices {
+            // A cell is never its own neighbor, so every neighbor lies on one side of it.
+            let (before, rest) = self.grid.split_at_mut(index);
+            let (current, after) = rest.split_at_mut(1);
+            let neighbors = SplitNeighbors {
+                index,
+                before,
+                after,
+                cells: self
+                    .models
+                    .as_deref()
+                    .unwrap_or(&self.neighbors)
+                    .of(index)
+                    .iter(),
+            };
+            f(&mut current[0], neighbors);
+        }
+    }
+
+    fn to_vec_index(&self, coord: Coord) -> usize {
+        self.num_col * coord.0 + coord.1
+    }
+}
+
+/// The neighbor an agent copies when it explores instead of imitating by payoff, which it
+/// does with probability `rate`: any of `neighbors`, all equally likely. Draws nothing from
+/// `rng` when `rate` is 0.
+fn explore<'a, R: Rng>(
+    rate: f32,
+    neighbors: impl Iterator<Item = &'a Agent> + Clone,
+    rng: &mut R,
+) -> Option<&'a Agent> {
+    if rate == 0.0 || !rng.gen_bool(f64::from(rate)) {
+        return None;
+    }
+    let count = neighbors.clone().count();
+    if count == 0 {
+        return None;
+    }
+    neighbors.into_iter().nth(rng.gen_range(0..count))
+}
+
+/// The neighbors of the cell at `index`, looked up on either side of it while the cell itself
+/// is borrowed mutably.
+#[derive(Clone)]
+struct SplitNeighbors<'a> {
+    index: usize,
+    /// The cells before and after `index`.
+    before: &'a [Agent],
+    after: &'a [Agent],
+    cells: std::slice::Iter<'a, usize>,
+}
+
+impl<'a> Iterator for SplitNeighbors<'a> {
+    type Item = &'a Agent;
+
+    fn next(&mut self) -> Option<&'a Agent> {
+        let &i = self.cells.next()?;
+        Some(if i < self.index {
+            &self.before[i]
+        } else {
+            &self.after[i - self.index - 1]
+        })
+    }
+}
+
+/// `f` of every cell index, computed on all cores if `parallel` is set and the `parallel`
+/// feature is on.
+fn map_cells<T, F>(len: usize, parallel: bool, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    if parallel {
+        use rayon::prelude::*;
+        return (0..len).into_par_iter().map(f).collect();
+    }
+    let _ = parallel;
+    if reversed() {
+        let mut out: Vec<T> = (0..len).rev().map(f).collect();
+        out.reverse();
+        return out;
+    }
+    (0..len).map(f).collect()
+}
+
+/// Calls `f` with the index and contents of every `width`-sized chunk of `items`, like
+/// `map_cells`.
+fn for_each_chunk<T, F>(items: &mut [T], width: usize, parallel: bool, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    if parallel {
+        use rayon::prelude::*;
+        items
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(i, chunk)| f(i, chunk));
+        return;
+    }
+    let _ = parallel;
+    let chunks = items.chunks_mut(width).enumerate();
+    if reversed() {
+        chunks.rev().for_each(|(i, chunk)| f(i, chunk));
+    } else {
+        chunks.for_each(|(i, chunk)| f(i, chunk));
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Makes every sequential pass over the cells on this thread run backwards, for tests of
+    /// what depends on processing order.
+    static REVERSED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Whether passes over the cells run backwards, which only tests ask for.
+fn reversed() -> bool {
+    #[cfg(test)]
+    return REVERSED.with(|r| r.get());
+    #[cfg(not(test))]
+    false
+}
+
+/// The RNG for one kind of draw by one cell in one step. It depends on nothing else, so
+/// results don't change with the order or the threads cells are processed on.
+fn cell_rng(seed: u64, step: usize, cell: usize, draws: u64) -> StdRng {
+    let mixed = [step as u64, cell as u64, draws]
+        .into_iter()
+        .fold(splitmix64(seed), |h, v| splitmix64(h ^ v));
+    StdRng::seed_from_u64(mixed)
+}
+
+/// The stream for one kind of decision a cell makes about its `slot`-th neighbor in one step.
+/// Unlike [`cell_rng`], the cell's decisions about other neighbors draw from streams of their
+/// own, so how many draws one of them takes can't shift what the others see.
+fn slot_rng(seed: u64, step: usize, cell: usize, slot: usize, draws: u64) -> KeyedRng {
+    let key = [step as u64, cell as u64, slot as u64, draws]
+        .into_iter()
+        .fold(splitmix64(seed), |h, v| splitmix64(h ^ v));
+    KeyedRng { state: key }
+}
+
+/// A counter-based generator: its n-th output is a hash of its key and n, so streams are
+/// free to create, one per decision, and need no state beyond a counter.
+#[derive(Clone, Debug)]
+struct KeyedRng {
+    state: u64,
+}
+
+impl RngCore for KeyedRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // `splitmix64` adds the increment itself, so outputs are hashes of key + n * increment.
+        let out = splitmix64(self.state);
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        out
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// The SplitMix64 finalizer, a cheap bijective hash of `x`.
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Iterator returned by [`Environment::steps`]; it never ends.
+pub struct StepIter<'a> {
+    env: &'a mut Environment,
+}
+
+impl Iterator for StepIter<'_> {
+    type Item = Metric;
+
+    fn next(&mut self) -> Option<Metric> {
+        Some(self.env.step())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Prob;
+    use crate::shocks::Schedule;
+
+    /// GenerousTFT as metrics count it.
+    const GTFT: Strategy = Strategy::GenerousTFT(Prob::from_millionths(333_333));
+
+    #[test]
+    fn test_agent_at() {
+        let env = Environment::new_with_agent_func(3, 4, 0.0, |c| {
+            let strategy = if c == (2, 1) {
+                Strategy::Coop
+            } else {
+                Strategy::Deflect
+            };
+            Agent::new(c, strategy)
+        })
+        .unwrap();
+        assert_eq!(env.dimensions(), (3, 4));
+        assert_eq!(env.agent_at((2, 1)).unwrap().strategy, Strategy::Coop);
+        assert_eq!(env.agent_at((2, 1)).unwrap().coord, (2, 1));
+        assert_eq!(env.agent_at((0, 3)).unwrap().strategy, Strategy::Deflect);
+        assert!(env.agent_at((3, 0)).is_none());
+        assert!(env.agent_at((0, 4)).is_none());
+
+        let corner: Vec<Coord> = env.neighbors((0, 0)).iter().map(|a| a.coord).collect();
+        assert_eq!(corner, vec![(0, 1), (1, 0), (1, 1)]);
+        assert_eq!(env.neighbors((1, 1)).len(), 8);
+        assert!(env.neighbors((9, 9)).is_empty());
+    }
+
+    #[test]
+    fn test_empty_grids_rejected() {
+        for (rows, cols) in [(0, 0), (0, 10), (10, 0)] {
+            let empty = Some(Error::EmptyGrid { rows, cols });
+            assert_eq!(Environment::new(rows, cols, 0.0).err(), empty);
+            let seeded = Environment::new_with_seed(rows, cols, 0.0, 1, |c, _| {
+                Agent::new(c, Strategy::Coop)
+            });
+            assert_eq!(seeded.err(), empty);
+            let mix = Environment::new_with_mix(rows, cols, 0.0, &[(Strategy::Coop, 1.0)], 1);
+            assert_eq!(mix.err(), empty);
+            let template = EnvironmentBuilder::new().size(rows, cols).template();
+            assert_eq!(template.err(), empty);
+        }
+    }
+
+    #[test]
+    fn test_lone_agent_steps() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = EnvironmentBuilder::new()
+                .size(1, 1)
+                .noise(0.5)
+                .update_mode(mode)
+                .strategies(&[Strategy::Random])
+                .build()
+                .unwrap();
+            assert!(env.neighbors((0, 0)).is_empty());
+            for metric in env.run(10) {
+                // Nobody to play or imitate: nothing happens, and the metric says so.
+                assert_eq!((metric.coop_actions, metric.coop_rate), (0, 0.0));
+                assert_eq!(metric.strategies, BTreeMap::from([(Strategy::Random, 1)]));
+                assert_eq!(
+                    metric.snapshot,
+                    Snapshot::from(vec![vec![Strategy::Random]])
+                );
+                assert_eq!(metric.scores.cells(), &[0.0]);
+                assert_eq!(metric.avg_score[&Strategy::Random], 0.0);
+                assert_eq!(metric.score_per_interaction[&Strategy::Random], 0.0);
+            }
+            assert_eq!(env.agent_at((0, 0)).unwrap().interactions, 0);
+        }
+    }
+
+    #[test]
+    fn test_single_row_and_column_grids() {
+        // Degrees along a line of 50 cells: the ends, the cells next to them, the rest.
+        let cases = [
+            (Neighborhood::Moore(1), [1, 2, 2]),
+            (Neighborhood::VonNeumann(1), [1, 2, 2]),
+            (Neighborhood::Moore(2), [2, 3, 4]),
+            (Neighborhood::VonNeumann(2), [2, 3, 4]),
+        ];
+        for (neighborhood, degrees) in cases {
+            for (rows, cols) in [(1, 50), (50, 1)] {
+                let mut env = EnvironmentBuilder::new()
+                    .size(rows, cols)
+                    .interaction_neighborhood(neighborhood)
+                    .adaptation_neighborhood(neighborhood)
+                    .strategies(&[Strategy::Coop])
+                    .build()
+                    .unwrap();
+                let along = |i: usize| if rows == 1 { (0, i) } else { (i, 0) };
+                for (i, degree) in [0, 1, 25].into_iter().zip(degrees) {
+                    assert_eq!(env.neighbors(along(i)).len(), degree, "{:?}", neighborhood);
+                    assert_eq!(env.neighbors(along(49 - i.min(24))).len(), degree);
+                }
+                let games: usize = (0..50).map(|i| env.neighbors(along(i)).len()).sum();
+                let metric = env.step();
+                assert_eq!(metric.coop_actions as usize, games);
+                assert_eq!(metric.coop_rate, 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_agents_row_major() {
+        let env = Environment::new(2, 3, 0.1).unwrap();
+        let coords: Vec<Coord> = env.iter_agents().map(|(c, _)| c).collect();
+        assert_eq!(coords, vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+        assert!(env.iter_agents().all(|(c, a)| a.coord == c));
+    }
+
+    #[test]
+    fn test_mutate_agent_mid_run() {
+        let mut env =
+            Environment::new_with_agent_func(4, 4, 0.0, |c| Agent::new(c, Strategy::Coop)).unwrap();
+        env.step();
+        assert_eq!(
+            env.set_strategy((1, 2), Strategy::Deflect),
+            Some(Strategy::Coop)
+        );
+        assert_eq!(env.set_strategy((4, 0), Strategy::Deflect), None);
+        assert!(env.agent_at_mut((0, 4)).is_none());
+        let metric = env.step();
+        assert_eq!(metric.snapshot[(1, 2)], Strategy::Deflect);
+        assert!(metric.strategies[&Strategy::Deflect] >= 1);
+
+        env.agent_at_mut((2, 1)).unwrap().strategy = Strategy::TicToc;
+        assert_eq!(env.step().snapshot[(2, 1)], Strategy::TicToc);
+    }
+
+    #[test]
+    fn test_coop_rate() {
+        let mut env =
+            Environment::new_with_agent_func(2, 2, 0.0, |c| Agent::new(c, Strategy::Coop)).unwrap();
+        let metric = env.step();
+        assert_eq!(metric.coop_actions, 12);
+        assert_eq!(metric.coop_rate, 1.0);
+
+        let mut env = Environment::new_with_agent_func(1, 2, 0.0, |c| {
+            let strategy = if c.1 == 0 {
+                Strategy::Coop
+            } else {
+                Strategy::Deflect
+            };
+            Agent::new(c, strategy)
+        })
+        .unwrap();
+        assert_eq!(env.step().coop_rate, 0.5);
+    }
+
+    #[test]
+    fn test_scores_match_agents() {
+        let mut env = Environment::new(3, 4, 0.1).unwrap();
+        let metric = env.step();
+        assert_eq!(metric.scores.dimensions(), (3, 4));
+        assert_eq!(metric.scores[(2, 1)], env.agent_at((2, 1)).unwrap().score);
+    }
+
+    #[test]
+    fn test_avg_score_per_strategy() {
+        let mut env = Environment::new(6, 6, 0.1).unwrap();
+        let metric = env.step();
+        for (strategy, avg) in &metric.avg_score {
+            let scores: Vec<f64> = metric
+                .snapshot
+                .cells()
+                .iter()
+                .zip(metric.scores.cells())
+                .filter(|(s, _)| *s == strategy)
+                .map(|(_, score)| *score)
+                .collect();
+            let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+            assert!((avg - mean).abs() < 1e-3, "{:?}", strategy);
+            assert!(*avg <= metric.max_score[strategy]);
+        }
+        assert_eq!(
+            metric.avg_score.keys().collect::<Vec<_>>(),
+            metric.strategies.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_same_seed_same_run() {
+        let make = |seed| {
+            Environment::new_with_seed(8, 8, 0.1, seed, |c, rng| {
+                Agent::random_with_rng(c, &[Strategy::Random, Strategy::TicToc], rng).unwrap()
+            })
+            .unwrap()
+        };
+        let (mut a, mut b, mut c) = (make(7), make(7), make(8));
+        assert_eq!(a.seed(), 7);
+        let run = |env: &mut Environment| (0..5).map(|_| env.step()).collect::<Vec<_>>();
+        let (ra, rb, rc) = (run(&mut a), run(&mut b), run(&mut c));
+        assert_eq!(ra, rb);
+        assert_ne!(ra, rc);
+    }
+
+    #[test]
+    fn test_mix_gives_exact_counts() {
+        use Strategy::{Coop as C, Deflect as D, TicToc as T};
+        let count =
+            |env: &Environment, s| env.iter_agents().filter(|(_, a)| a.strategy == s).count();
+        // 20x20 at 10% is 40 exactly, not roughly.
+        for seed in 0..5 {
+            let env = Environment::new_with_mix(20, 20, 0.0, &[(C, 0.1), (D, 0.9)], seed).unwrap();
+            assert_eq!((count(&env, C), count(&env, D)), (40, 360));
+        }
+        let cases: [(&[(Strategy, f32)], _); 3] = [
+            (&[(C, 1.0), (D, 1.0), (T, 1.0)], [17, 16, 16]),
+            (&[(C, 0.15), (D, 0.35), (T, 0.5)], [7, 17, 25]),
+            (&[(C, 2.0), (D, 97.0), (T, 1.0)], [1, 48, 0]),
+        ];
+        for (mix, expected) in cases {
+            let env = Environment::new_with_mix(7, 7, 0.0, mix, 3).unwrap();
+            assert_eq!([C, D, T].map(|s| count(&env, s)), expected, "{:?}", mix);
+        }
+        assert!(matches!(
+            Environment::new_with_mix(3, 3, 0.0, &[(D, 1.0), (C, f32::NAN)], 1),
+            Err(Error::InvalidMix { strategy: C, .. })
+        ));
+        assert_eq!(
+            Environment::new_with_mix(3, 3, 0.0, &[], 1).err(),
+            Some(Error::EmptyStrategyPool)
+        );
+    }
+
+    #[test]
+    fn test_mix_placement() {
+        use Strategy::{Coop as C, Deflect as D};
+        let mix = [(C, 0.3), (D, 0.7)];
+        let cells = |env: Environment| -> Vec<Strategy> {
+            env.iter_agents().map(|(_, a)| a.strategy).collect()
+        };
+        let placed = |seed| cells(Environment::new_with_mix(10, 10, 0.0, &mix, seed).unwrap());
+        assert_eq!(placed(5), placed(5));
+        assert_ne!(placed(5), placed(6));
+        // Shuffled placement scatters the cooperators beyond the first three rows.
+        assert!(placed(5)[30..].contains(&C));
+        let blocked = EnvironmentBuilder::new()
+            .size(10, 10)
+            .seed(5)
+            .mix(&mix, Placement::Blocked)
+            .build()
+            .unwrap();
+        for ((r, _), agent) in blocked.iter_agents() {
+            assert_eq!(agent.strategy, if r < 3 { C } else { D });
+        }
+        // An agent function takes precedence over a mix.
+        let env = EnvironmentBuilder::new()
+            .size(2, 2)
+            .mix(&mix, Placement::Blocked)
+            .agents(|c, _| Agent::new(c, Strategy::TicToc))
+            .build()
+            .unwrap();
+        assert!(cells(env).iter().all(|&s| s == Strategy::TicToc));
+    }
+
+    /// A TicToc agent in the middle of a bounded 3x3 grid of cooperators plays eight games a
+    /// step, the corners three: raw scores reward the position, normalized ones don't.
+    #[test]
+    fn test_normalized_scores() {
+        let (center, coop, tictoc) = ((1, 1), Strategy::Coop, Strategy::TicToc);
+        let mut env = Environment::new_with_agent_func(3, 3, 0.0, |c| {
+            Agent::new(c, if c == center { tictoc } else { coop })
+        })
+        .unwrap();
+        let reward = Payoffs::default().reward;
+        let metric = env.step();
+        // Four corners with three games and four edges with five.
+        assert_eq!(metric.avg_score[&coop], 4.0 * reward);
+        assert_eq!(metric.avg_score[&tictoc], 8.0 * reward);
+        assert_eq!(metric.score_per_interaction[&coop], reward);
+        assert_eq!(metric.score_per_interaction[&tictoc], reward);
+        assert_eq!(metric.score_per_step, metric.avg_score);
+        assert_eq!(metric.top_agent[&coop], (0, 0));
+        assert_eq!(metric.top_agent[&tictoc], center);
+        let corner = env.agent_at((0, 0)).unwrap();
+        assert_eq!((corner.interactions, corner.age), (3, 1));
+
+        // Everyone imitates the center, and per game they all still earn the same.
+        let metric = env.step();
+        assert_eq!(metric.strategies[&tictoc], 9);
+        assert_eq!(metric.score_per_interaction[&tictoc], reward);
+        assert_eq!(metric.top_agent[&tictoc], (0, 0));
+        let per_step = (8.0 + 4.0 * 5.0 + 4.0 * 3.0) / 9.0 * reward;
+        assert!((metric.score_per_step[&tictoc] - per_step).abs() < 1e-9);
+        assert!(env.iter_agents().all(|(_, a)| a.age == 2));
+    }
+
+    /// An all-cooperator grid: with no noise every score ties, so nobody imitates and only
+    /// interventions change anything.
+    fn cooperators() -> Environment {
+        Environment::new_with_seed(6, 6, 0.0, 3, |c, _| Agent::new(c, Strategy::Coop)).unwrap()
+    }
+
+    #[test]
+    fn test_region_intervention() {
+        let mut env = cooperators();
+        let id = env.schedule(
+            2,
+            Intervention::SetStrategyRegion {
+                origin: (4, 4),
+                rows: 5,
+                cols: 5,
+                strategy: Strategy::Deflect,
+            },
+        );
+        for _ in 0..2 {
+            let metric = env.step();
+            assert!(metric.interventions.is_empty());
+            assert_eq!(metric.strategies[&Strategy::Coop], 36);
+        }
+        let metric = env.step();
+        assert_eq!(metric.interventions, [id]);
+        // Clipped to the 2x2 corner inside the grid.
+        assert_eq!(metric.strategies[&Strategy::Deflect], 4);
+        for ((r, c), strategy) in metric.snapshot.iter_with_coords() {
+            let deflects = r >= 4 && c >= 4;
+            assert_eq!(strategy == Strategy::Deflect, deflects, "{:?}", (r, c));
+        }
+        // The switched agents keep what they remember.
+        assert_eq!(env.agent_at((4, 4)).unwrap().history_with((3, 4)).len(), 3);
+        assert_eq!(env.pending_interventions(), 0);
+    }
+
+    #[test]
+    fn test_noise_intervention() {
+        let mut env = cooperators();
+        let id = env.schedule(1, Intervention::SetNoise(1.5));
+        assert!(env.step().interventions.is_empty());
+        assert_eq!(env.noise(), 0.0);
+        let metric = env.step();
+        assert_eq!(metric.interventions, [id]);
+        assert_eq!(env.noise(), 1.0);
+        // Full noise flips every cooperation this step already.
+        let seen = env.agent_at((2, 2)).unwrap().history_with((2, 3));
+        assert_eq!(seen.last(), Some(Action::Deflect));
+    }
+
+    #[test]
+    fn test_clear_histories_intervention() {
+        let mut env = cooperators();
+        env.schedule(3, Intervention::ClearHistories);
+        let games = |env: &Environment| env.agent_at((2, 2)).unwrap().history_with((2, 3)).len();
+        env.step();
+        let after_one = games(&env);
+        env.step();
+        env.step();
+        assert_eq!(games(&env), 3 * after_one);
+        env.step();
+        assert_eq!(games(&env), after_one);
+    }
+
+    #[test]
+    fn test_replace_agent_intervention() {
+        let mut env = cooperators();
+        env.step();
+        let old = env.agent_at((2, 2)).unwrap().clone();
+        let id = env.schedule(
+            1,
+            Intervention::ReplaceAgent {
+                coord: (2, 2),
+                strategy: Strategy::TicToc,
+            },
+        );
+        env.schedule(
+            1,
+            Intervention::ReplaceAgent {
+                coord: (6, 0),
+                strategy: Strategy::TicToc,
+            },
+        );
+        let metric = env.step();
+        assert_eq!(metric.interventions, [id, id + 1]);
+        assert_eq!(metric.snapshot[(2, 2)], Strategy::TicToc);
+        assert_eq!(metric.strategies[&Strategy::TicToc], 1);
+        let newcomer = env.agent_at((2, 2)).unwrap();
+        assert_ne!(newcomer.id, old.id);
+        assert_eq!(newcomer.founder_id, newcomer.id);
+        assert_eq!(newcomer.class, old.class);
+        // One step of games on both sides, none from before.
+        let first_step = old.history_with((2, 3)).len();
+        assert_eq!(newcomer.history_with((2, 3)).len(), first_step);
+        assert_eq!(
+            env.agent_at((2, 3)).unwrap().history_with((2, 2)).len(),
+            first_step
+        );
+    }
+
+    #[test]
+    fn test_custom_interventions_and_scheduling() {
+        let mut env = cooperators();
+        env.step();
+        env.step();
+        // Overdue interventions apply at the next step, in the order they were scheduled.
+        let first = env.schedule(
+            0,
+            Intervention::Custom(Box::new(|env| {
+                env.set_strategy((0, 0), Strategy::Deflect);
+            })),
+        );
+        let second = env.schedule(
+            2,
+            Intervention::Custom(Box::new(|env| {
+                assert_eq!(env.agent_at((0, 0)).unwrap().strategy, Strategy::Deflect);
+                env.set_strategy((0, 1), Strategy::Deflect);
+            })),
+        );
+        env.schedule(5, Intervention::Custom(Box::new(|_| {})));
+        env.schedule(5, Intervention::ClearHistories);
+        // Clones keep all but the custom interventions.
+        assert_eq!(env.clone().pending_interventions(), 1);
+        let metric = env.step();
+        assert_eq!(metric.interventions, [first, second]);
+        assert_eq!(metric.snapshot[(0, 0)], Strategy::Deflect);
+        assert_eq!(metric.snapshot[(0, 1)], Strategy::Deflect);
+        assert_eq!(env.pending_interventions(), 2);
+    }
+
+    /// Deflectors and two cooperators the deflectors soon take over, under payoffs that pay
+    /// mutual defection so that scores keep growing afterwards.
+    fn fixating(mode: UpdateMode) -> Environment {
+        EnvironmentBuilder::new()
+            .size(8, 8)
+            .seed(4)
+            .update_mode(mode)
+            .payoffs(Payoffs {
+                punishment: 1.0,
+                ..Payoffs::default()
+            })
+            .agents(|(r, c), _| {
+                let coop = r == 3 && c < 2;
+                Agent::new(
+                    (r, c),
+                    if coop {
+                        Strategy::Coop
+                    } else {
+                        Strategy::Deflect
+                    },
+                )
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fixated_grid_freezes() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let (mut env, mut full) = (fixating(mode), fixating(mode));
+            full.set_freeze_after(None);
+            let metrics = env.run(30);
+            let first = metrics.iter().position(|m| m.frozen).expect("never froze");
+            assert!(first < 15, "{:?}: froze at {}", mode, first);
+            assert!(metrics[first..].iter().all(|m| m.frozen));
+            assert!(!metrics[first].strategies.contains_key(&Strategy::Coop));
+            assert!(env.is_frozen());
+            // Frozen steps give the metrics full ones do, but histories stop growing.
+            let thawed = |m: Metric| Metric { frozen: false, ..m };
+            assert_eq!(
+                metrics.into_iter().map(thawed).collect::<Vec<_>>(),
+                full.run(30)
+            );
+            let games =
+                |env: &Environment| env.agent_at((0, 0)).unwrap().history_with((0, 1)).len();
+            assert!(games(&env) < games(&full));
+            // Noise thaws the run, which goes on as if it had never frozen.
+            env.set_noise(0.1);
+            full.set_noise(0.1);
+            assert!(!env.is_frozen());
+            assert_eq!(env.run(10), full.run(10));
+        }
+    }
+
+    /// Every point an agent scores comes from some neighbor, frozen steps included.
+    #[test]
+    fn test_payoff_breakdown_adds_up() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = fixating(mode);
+            env.set_noise(0.05);
+            env.run(20);
+            env.set_noise(0.0);
+            assert!(env.run(20).last().unwrap().frozen);
+            for (coord, agent) in env.iter_agents() {
+                let neighbors: Vec<Coord> = env.neighbors(coord).iter().map(|n| n.coord).collect();
+                let mut total = 0.0;
+                for (opponent, payoff) in agent.payoff_breakdown() {
+                    assert!(neighbors.contains(&opponent));
+                    assert_eq!(agent.payoff_from(opponent), payoff);
+                    total += payoff;
+                }
+                assert!((total - agent.score).abs() < 1e-6, "{:?}", coord);
+                assert_eq!(agent.payoff_breakdown().count(), neighbors.len());
+            }
+        }
+        assert_eq!(Agent::new((0, 0), Strategy::Coop).payoff_from((0, 1)), 0.0);
+    }
+
+    #[test]
+    fn test_random_agent_prevents_freezing() {
+        let mut env = fixating(UpdateMode::Legacy);
+        env.set_strategy((5, 5), Strategy::Random);
+        let metrics = env.run(30);
+        let random = |m: &Metric| m.strategies.contains_key(&Strategy::Random);
+        assert!(random(&metrics[0]));
+        assert!(metrics.iter().all(|m| !(m.frozen && random(m))));
+        // Neither history-dependent strategies nor pending interventions are settled.
+        let mut env = fixating(UpdateMode::Legacy);
+        env.run(30);
+        assert!(env.is_frozen());
+        env.set_strategy((5, 5), Strategy::TicToc);
+        assert!(!env.is_frozen());
+        env.set_strategy((5, 5), Strategy::Deflect);
+        assert!(env.is_frozen());
+        env.schedule(100, Intervention::ClearHistories);
+        assert!(!env.is_frozen());
+        assert!(!env.step().frozen);
+        // Without a waiting period a settled grid is frozen from the start.
+        let mut env = cooperators();
+        assert!(!env.is_frozen());
+        env.set_freeze_after(Some(0));
+        assert!(env.is_frozen());
+        env.set_freeze_after(None);
+        assert!(!env.is_frozen());
+    }
+
+    #[test]
+    fn test_steps_iterator_matches_manual_steps() {
+        let make = || {
+            Environment::new_with_seed(6, 6, 0.1, 11, |c, rng| {
+                Agent::random_with_rng(c, &[Strategy::Random, Strategy::TicToc], rng).unwrap()
+            })
+            .unwrap()
+        };
+        let (mut manual, mut iterated) = (make(), make());
+        let expected: Vec<Metric> = (0..10).map(|_| manual.step()).collect();
+        let metrics: Vec<Metric> = iterated.steps().take(10).collect();
+        assert_eq!(metrics, expected);
+        assert_eq!(
+            metrics.iter().map(|m| m.step_index).collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+        // The iterator only borrows: stepping continues where it left off.
+        assert_eq!(iterated.steps_taken(), 10);
+        assert_eq!(iterated.steps().next().unwrap().step_index, 10);
+    }
+
+    fn seeded() -> Environment {
+        Environment::new_with_seed(5, 5, 0.2, 3, |c, rng| {
+            Agent::random_with_rng(c, &[Strategy::Random, Strategy::Deflect], rng).unwrap()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_run_matches_manual_steps() {
+        let mut manual = seeded();
+        let expected: Vec<Metric> = (0..8).map(|_| manual.step()).collect();
+        let metrics = seeded().run(8);
+        assert_eq!(metrics, expected);
+        assert!(metrics.capacity() >= 8);
+
+        let mut sunk = Vec::new();
+        seeded().run_with(8, |m| sunk.push(m));
+        assert_eq!(sunk, expected);
+        assert!(seeded().run(0).is_empty());
+    }
+
+    #[test]
+    fn test_run_while_stops_early() {
+        let (metrics, early) = seeded().run_while(|m| m.step_index < 3, 10);
+        assert!(early);
+        assert_eq!(metrics.len(), 4);
+        assert_eq!(metrics.last().unwrap().step_index, 3);
+        assert_eq!(metrics, seeded().run(4));
+    }
+
+    #[test]
+    fn test_run_while_hits_cap() {
+        let mut env = seeded();
+        let (metrics, early) = env.run_while(|_| true, 6);
+        assert!(!early);
+        assert_eq!(metrics.len(), 6);
+        assert_eq!(env.steps_taken(), 6);
+        assert_eq!(env.run_while(|_| false, 0), (vec![], false));
+    }
+
+    #[test]
+    fn test_memory_window_keeps_play_identical() {
+        let make = |window| {
+            let mut env = Environment::new_with_seed(6, 6, 0.2, 5, |c, rng| {
+                Agent::random_with_rng(
+                    c,
+                    &[Strategy::TicToc, Strategy::Deflect, Strategy::Random],
+                    rng,
+                )
+                .unwrap()
+            })
+            .unwrap();
+            env.set_memory_window(window).unwrap();
+            env
+        };
+        let (mut bounded, mut unbounded) = (make(Some(1)), make(None));
+        assert_eq!(bounded.run(200), unbounded.run(200));
+        assert_eq!(bounded.memory_window(), Some(1));
+        for (coord, agent) in bounded.iter_agents() {
+            for opponent in agent.opponents() {
+                assert_eq!(agent.history_with(opponent).len(), 1);
+                assert_eq!(
+                    agent.last_action_from(opponent),
+                    unbounded
+                        .agent_at(coord)
+                        .unwrap()
+                        .last_action_from(opponent)
+                );
+            }
+        }
+        assert_eq!(
+            unbounded
+                .agent_at((2, 2))
+                .unwrap()
+                .history_with((2, 3))
+                .len(),
+            200
+        );
+        assert_eq!(
+            bounded.set_memory_window(Some(0)),
+            Err(Error::EmptyMemoryWindow)
+        );
+        assert_eq!(bounded.memory_window(), Some(1));
+    }
+
+    #[test]
+    fn test_steps_take_while() {
+        let mut env = Environment::new_with_agent_func(3, 3, 0.0, |c| {
+            Agent::new(
+                c,
+                if c == (1, 1) {
+                    Strategy::Deflect
+                } else {
+                    Strategy::Coop
+                },
+            )
+        })
+        .unwrap();
+        let taken = env
+            .steps()
+            .take_while(|m| m.strategies.len() > 1)
+            .take(100)
+            .count();
+        assert!(taken < 100);
+        assert_eq!(env.step().strategies.len(), 1);
+    }
+
+    #[test]
+    fn test_set_noise_clamps() {
+        let mut env = Environment::new(2, 2, 0.1).unwrap();
+        assert_eq!(env.noise(), 0.1);
+        assert_eq!(env.set_noise(0.3), 0.3);
+        assert_eq!(env.set_noise(-0.5), 0.0);
+        assert_eq!(env.set_noise(1.5), 1.0);
+        assert_eq!(env.set_noise(f32::NAN), 1.0);
+        assert_eq!(env.noise(), 1.0);
+    }
+
+    #[test]
+    fn test_constructors_reject_invalid_parameters() {
+        assert_eq!(
+            Environment::new(0, 4, 0.1).err(),
+            Some(Error::EmptyGrid { rows: 0, cols: 4 })
+        );
+        assert_eq!(
+            Environment::new(3, 3, 1.5).err(),
+            Some(Error::InvalidNoise(1.5))
+        );
+        assert!(
+            Environment::new_with_agent_func(2, 0, 0.0, |c| Agent::new(c, Strategy::Coop)).is_err()
+        );
+        let misplaced =
+            Environment::new_with_seed(2, 2, 0.0, 1, |_, _| Agent::new((5, 5), Strategy::Coop));
+        assert_eq!(
+            misplaced.err(),
+            Some(Error::MisplacedAgent {
+                expected: (0, 0),
+                found: (5, 5)
+            })
+        );
+    }
+
+    #[test]
+    fn test_nan_scores_dont_stop_the_run() {
+        let mut env =
+            Environment::new_with_agent_func(3, 3, 0.0, |c| Agent::new(c, Strategy::Coop)).unwrap();
+        env.agent_at_mut((0, 0)).unwrap().score = f64::NAN;
+        env.agent_at_mut((1, 1)).unwrap().strategy = Strategy::Deflect;
+        env.agent_at_mut((1, 1)).unwrap().score = 100.0;
+        env.step();
+        assert_eq!(env.agent_at((0, 0)).unwrap().strategy, Strategy::Deflect);
+        assert_eq!(env.run(5).len(), 5);
+    }
+
+    #[test]
+    fn test_environment_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Environment>();
+        assert_send_sync::<Metric>();
+    }
+
+    #[test]
+    fn test_fork_with_seed_replays_identically() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = seeded();
+            env.set_update_mode(mode);
+            env.run(5);
+            let mut a = env.fork_with_seed(99);
+            let mut b = env.fork_with_seed(99);
+            assert_eq!(a.seed(), 99);
+            assert_eq!(a.steps_taken(), 5);
+            assert_eq!(a.run(10), b.run(10));
+        }
+    }
+
+    #[test]
+    fn test_fork_diverges_from_original() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = seeded();
+            env.set_update_mode(mode);
+            env.run(5);
+            let mut branch = env.fork();
+            assert_ne!(branch.seed(), env.seed());
+            assert_eq!(branch.steps_taken(), env.steps_taken());
+            for ((_, mine), (_, theirs)) in env.iter_agents().zip(branch.iter_agents()) {
+                assert_eq!(mine.strategy, theirs.strategy);
+                assert_eq!(mine.score, theirs.score);
+                for opponent in mine.opponents() {
+                    assert_eq!(mine.history_with(opponent), theirs.history_with(opponent));
+                }
+            }
+            let mut twin = env.clone();
+            let ahead = branch.run(10);
+            // Stepping the branch leaves the original's future as it was.
+            let original = env.run(10);
+            assert_eq!(original, twin.run(10));
+            assert_ne!(ahead, original);
+        }
+    }
+
+    #[test]
+    fn test_golden_run() {
+        // Recorded once both sides of a pair shared their noisy actions; any change here changes
+        // results.
+        let mut env = EnvironmentBuilder::new()
+            .size(8, 9)
+            .noise(0.1)
+            .seed(2024)
+            // The strategies there were when it was recorded.
+            .strategies(&[
+                Strategy::Deflect,
+                Strategy::TicToc,
+                Strategy::Coop,
+                Strategy::Random,
+            ])
+            .build()
+            .unwrap();
+        let metrics = env.run(25);
+        let coop: Vec<i32> = metrics.iter().map(|m| m.coop_actions).collect();
+        assert_eq!(
+            coop,
+            vec![
+                313, 143, 242, 207, 244, 330, 343, 338, 342, 387, 406, 402, 409, 408, 417, 425,
+                426, 429, 431, 435, 439, 428, 430, 432, 423
+            ]
+        );
+        let last = metrics.last().unwrap();
+        let rows: Vec<String> = last
+            .snapshot
+            .iter_rows()
+            .map(|row| row.iter().map(|s| &s.name()[..1]).collect())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                "CCCCCCRRR",
+                "CCCCCCRRR",
+                "CCCCCCRRR",
+                "CCCCCCCCC",
+                "CCCCCCCTT",
+                "CCCCCCCTT",
+                "CCCCCCTTT",
+                "CCCCCTTTT"
+            ]
+        );
+        assert_eq!(last.scores.cells().iter().sum::<f64>(), 27668.0);
+    }
+
+    #[test]
+    fn test_synchronous_adapts_to_frozen_grid() {
+        // Scores fall off to the right; in place, the Coop strategy travels two cells in one
+        // step, synchronously only one.
+        let snapshot_after_step = |mode| {
+            let mut env = EnvironmentBuilder::new()
+                .size(1, 3)
+                .update_mode(mode)
+                .agents(|c, _| {
+                    Agent::new(
+                        c,
+                        [Strategy::Coop, Strategy::Deflect, Strategy::TicToc][c.1],
+                    )
+                })
+                .build()
+                .unwrap();
+            for (col, score) in [10.0, 5.0, 0.0].into_iter().enumerate() {
+                env.agent_at_mut((0, col)).unwrap().score = score;
+            }
+            env.step().snapshot
+        };
+        use Strategy::{Coop, Deflect};
+        assert_eq!(
+            snapshot_after_step(UpdateMode::Legacy),
+            Snapshot::from(vec![vec![Coop, Coop, Coop]])
+        );
+        assert_eq!(
+            snapshot_after_step(UpdateMode::Synchronous),
+            Snapshot::from(vec![vec![Coop, Coop, Deflect]])
+        );
+    }
+
+    #[test]
+    fn test_founder_ids_unique() {
+        let env = EnvironmentBuilder::new()
+            .size(3, 4)
+            .agents(|c, _| Agent::new(c, Strategy::Coop))
+            .build()
+            .unwrap();
+        let ids: Vec<u64> = env.iter_agents().map(|(_, a)| a.id).collect();
+        assert_eq!(ids, (0..12).collect::<Vec<u64>>());
+        for (_, agent) in env.iter_agents() {
+            assert_eq!((agent.parent_id, agent.founder_id), (None, agent.id));
+        }
+        assert_eq!(env.founder_lineages(), 12);
+        assert_eq!(env.founder_of((2, 3)), Some(11));
+        assert_eq!(env.founder_of((3, 0)), None);
+    }
+
+    #[test]
+    fn test_imitation_records_lineage() {
+        // As in the frozen grid test, the Coop strategy of cell 0 spreads to the right.
+        let lineage_after_step = |mode| {
+            let mut env = EnvironmentBuilder::new()
+                .size(1, 3)
+                .update_mode(mode)
+                .agents(|c, _| Agent::new(c, Strategy::Deflect))
+                .build()
+                .unwrap();
+            for (col, score) in [10.0, 5.0, 0.0].into_iter().enumerate() {
+                env.agent_at_mut((0, col)).unwrap().score = score;
+            }
+            let metric = env.step();
+            let lineage: Vec<(Option<u64>, u64)> = env
+                .iter_agents()
+                .map(|(_, a)| (a.parent_id, a.founder_id))
+                .collect();
+            (lineage, metric.founder_lineages)
+        };
+        assert_eq!(
+            lineage_after_step(UpdateMode::Legacy),
+            (vec![(None, 0), (Some(0), 0), (Some(1), 0)], 1)
+        );
+        assert_eq!(
+            lineage_after_step(UpdateMode::Synchronous),
+            (vec![(None, 0), (Some(0), 0), (Some(1), 1)], 2)
+        );
+    }
+
+    #[test]
+    fn test_founder_lineages_never_grow() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = seeded();
+            env.set_update_mode(mode);
+            let lineages: Vec<usize> = env.run(30).iter().map(|m| m.founder_lineages).collect();
+            assert!(lineages[0] <= 25);
+            assert!(lineages.windows(2).all(|w| w[1] <= w[0]), "{:?}", lineages);
+            assert!(lineages[29] < 25);
+        }
+    }
+
+    #[test]
+    fn test_outcomes_record_the_game() {
+        for (noise, realized) in [
+            (0.0, [Action::Coop, Action::Deflect]),
+            (1.0, [Action::Deflect, Action::Coop]),
+        ] {
+            for mode in [
+                UpdateMode::Legacy,
+                UpdateMode::Synchronous,
+                UpdateMode::Asynchronous,
+            ] {
+                let mut env = EnvironmentBuilder::new()
+                    .size(1, 2)
+                    .noise(noise)
+                    .update_mode(mode)
+                    .agents(|c, _| {
+                        Agent::new(
+                            c,
+                            if c.1 == 0 {
+                                Strategy::Coop
+                            } else {
+                                Strategy::Deflect
+                            },
+                        )
+                    })
+                    .build()
+                    .unwrap();
+                env.step();
+                let payoffs = Payoffs::default();
+                let coop = env.agent_at((0, 0)).unwrap().last_outcome_with((0, 1));
+                assert_eq!(
+                    coop,
+                    Some(&InteractionOutcome {
+                        opponent: (0, 1),
+                        intended: Action::Coop,
+                        realized: realized[0],
+                        their_action: realized[1],
+                        payoff: payoffs.score(realized[0], realized[1]),
+                        step: 0,
+                    })
+                );
+                let deflect = env
+                    .agent_at((0, 1))
+                    .unwrap()
+                    .last_outcome_with((0, 0))
+                    .unwrap();
+                assert_eq!(
+                    (deflect.intended, deflect.realized, deflect.their_action),
+                    (Action::Deflect, realized[1], realized[0])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_both_sides_see_the_same_game() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = synchronous_env(3);
+            env.set_update_mode(mode);
+            env.run(4);
+            let payoffs = env.payoffs();
+            let mut flipped = 0;
+            for (coord, agent) in env.iter_agents() {
+                for neighbor in env.neighbors(coord) {
+                    let mine = agent.last_outcome_with(neighbor.coord).unwrap();
+                    let theirs = neighbor.last_outcome_with(coord).unwrap();
+                    assert_eq!((mine.step, theirs.step), (3, 3));
+                    assert_eq!(
+                        (mine.realized, mine.their_action),
+                        (theirs.their_action, theirs.realized)
+                    );
+                    assert_eq!(mine.payoff, payoffs.score(mine.realized, mine.their_action));
+                    assert_eq!(
+                        agent.last_action_from(neighbor.coord),
+                        Some(mine.their_action)
+                    );
+                    flipped += usize::from(mine.intended != mine.realized);
+                }
+            }
+            assert!(flipped > 0);
+        }
+    }
+
+    #[test]
+    fn test_payoff_fn_rewards_reciprocity() {
+        // Cooperating with a neighbor whose last action toward you was cooperative earns a
+        // bonus on top of the matrix payoff.
+        let mut env = EnvironmentBuilder::new()
+            .size(1, 2)
+            .agents(|c, _| Agent::new(c, Strategy::TicToc))
+            .payoff_fn(|ctx: &PayoffCtx<'_>| {
+                let payoffs = Payoffs::default();
+                let bonus = |me: &Agent, them: &Agent, action| {
+                    let reciprocated = me.last_action_from(them.coord) == Some(Action::Coop);
+                    if action == Action::Coop && reciprocated {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                };
+                (
+                    payoffs.score(ctx.first_action, ctx.second_action)
+                        + bonus(ctx.first, ctx.second, ctx.first_action),
+                    payoffs.score(ctx.second_action, ctx.first_action)
+                        + bonus(ctx.second, ctx.first, ctx.second_action),
+                )
+            })
+            .build()
+            .unwrap();
+        assert!(env.has_payoff_fn());
+        assert_eq!(env.step().scores, Grid::from(vec![vec![3.0, 3.0]]));
+        assert_eq!(env.step().scores, Grid::from(vec![vec![7.0, 7.0]]));
+        env.clear_payoff_fn();
+        assert_eq!(env.step().scores, Grid::from(vec![vec![10.0, 10.0]]));
+    }
+
+    #[test]
+    fn test_payoff_fn_called_once_per_pair() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let mut env = seeded();
+            env.set_update_mode(mode);
+            let counter = Arc::clone(&calls);
+            env.set_payoff_fn(move |ctx: &PayoffCtx<'_>| {
+                counter.fetch_add(1, Ordering::Relaxed);
+                let (first, second) = ctx.coords();
+                assert!(first < second);
+                (1.0, 2.0)
+            });
+            env.run(3);
+            let pairs = env.neighbors.pairs() / 2;
+            assert_eq!(calls.load(Ordering::Relaxed), 3 * pairs);
+            // Every agent is `first` toward its later neighbors and `second` toward the rest.
+            let agent = env.agent_at((2, 2)).unwrap();
+            assert_eq!(agent.score, 3.0 * (4.0 * 1.0 + 4.0 * 2.0));
+        }
+    }
+
+    #[test]
+    fn test_matrix_payoff_fn_matches_builtin() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let make = || {
+                EnvironmentBuilder::new()
+                    .size(6, 7)
+                    .noise(0.1)
+                    .seed(8)
+                    .update_mode(mode)
+                    .strategies(&Strategy::ALL)
+                    .build()
+                    .unwrap()
+            };
+            let mut hooked = make();
+            hooked.set_payoff_fn(|ctx: &PayoffCtx<'_>| {
+                let payoffs = Payoffs::default();
+                (
+                    payoffs.score(ctx.first_action, ctx.second_action),
+                    payoffs.score(ctx.second_action, ctx.first_action),
+                )
+            });
+            assert_eq!(hooked.run(10), make().run(10));
+        }
+    }
+
+    /// One defection poisons a grid of TicToc players: every game the poisoned agent plays
+    /// from then on is an echo, one side defecting in turn, though the grid never shows
+    /// anything but TicToc.
+    #[test]
+    fn test_coop_rate_map_shows_hidden_defection() {
+        let center = (3, 3);
+        let mut env = EnvironmentBuilder::new()
+            .size(7, 7)
+            .coop_rate_map(true)
+            .agents(|c, _| {
+                Agent::new(
+                    c,
+                    if c == center {
+                        Strategy::Deflect
+                    } else {
+                        Strategy::TicToc
+                    },
+                )
+            })
+            .build()
+            .unwrap();
+        assert!(env.tracks_coop_rate_map());
+        let first = env.step().coop_rate_map.unwrap();
+        assert_eq!(first[center], 0.5);
+        env.agent_at_mut(center).unwrap().strategy = Strategy::TicToc;
+        for metric in env.run(6) {
+            assert_eq!(metric.strategies[&Strategy::TicToc], 49);
+            let map = metric.coop_rate_map.unwrap();
+            assert_eq!(map[center], 0.5);
+            for (coord, &rate) in map.iter_with_coords() {
+                let (dr, dc) = (coord.0.abs_diff(center.0), coord.1.abs_diff(center.1));
+                let expected = match dr.max(dc) {
+                    0 => 0.5,
+                    // One echoing game out of eight.
+                    1 => 15.0 / 16.0,
+                    _ => 1.0,
+                };
+                assert_eq!(rate, expected, "{:?}", coord);
+            }
+        }
+        env.set_coop_rate_map(false);
+        assert_eq!(env.step().coop_rate_map, None);
+    }
+
+    /// A row of three Coops whose payoff function pays every side 1, except that from step 2
+    /// on the first agent gets NaN for its game with the second.
+    fn nan_from_step_two(policy: NonFinitePolicy) -> Environment {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        // Two pairs, so two calls a step.
+        let calls = AtomicUsize::new(0);
+        EnvironmentBuilder::new()
+            .size(1, 3)
+            .non_finite_policy(policy)
+            .agents(|c, _| Agent::new(c, Strategy::Coop))
+            .payoff_fn(move |ctx: &PayoffCtx<'_>| {
+                let call = calls.fetch_add(1, Ordering::Relaxed);
+                if call >= 4 && ctx.coords() == ((0, 0), (0, 1)) {
+                    (f64::NAN, 1.0)
+                } else {
+                    (1.0, 1.0)
+                }
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_non_finite_payoff_panics_with_context() {
+        let mut env = nan_from_step_two(NonFinitePolicy::default());
+        assert_eq!(env.non_finite_policy(), NonFinitePolicy::Panic);
+        env.run(2);
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| env.step()))
+            .expect_err("a NaN payoff panics");
+        assert_eq!(
+            panic.downcast_ref::<String>().unwrap(),
+            "payoff function returned (NaN, 1) for the game between (0, 0) and (0, 1) at step 2"
+        );
+    }
+
+    #[test]
+    fn test_non_finite_payoff_clamped_to_zero() {
+        let mut env = nan_from_step_two(NonFinitePolicy::ClampToZero);
+        let metrics = env.run(4);
+        let rejected: Vec<usize> = metrics.iter().map(|m| m.rejected_payoffs).collect();
+        assert_eq!(rejected, [0, 0, 1, 1]);
+        // The NaN side earns nothing for the last two games; its opponent is still paid.
+        assert_eq!(metrics[3].scores, Grid::from(vec![vec![2.0, 8.0, 4.0]]));
+        assert!(metrics.iter().all(|m| m.coop_rate == 1.0));
+    }
+
+    #[test]
+    fn test_non_finite_payoff_skips_the_game() {
+        let outcomes = Arc::new(std::sync::Mutex::new(
+            crate::pairings::PairingOutcomes::new(),
+        ));
+        let mut env = nan_from_step_two(NonFinitePolicy::SkipInteraction);
+        env.set_observer(Some(outcomes.clone()));
+        let metrics = env.run(4);
+        assert_eq!(metrics[3].rejected_payoffs, 1);
+        // Neither side is paid for, or remembers, the last two games between them.
+        assert_eq!(metrics[3].scores, Grid::from(vec![vec![2.0, 6.0, 4.0]]));
+        let (first, second) = (env.agent_at((0, 0)).unwrap(), env.agent_at((0, 1)).unwrap());
+        assert_eq!(first.history_with((0, 1)).len(), 2);
+        assert_eq!(second.payoff_from((0, 0)), 2.0);
+        assert_eq!(second.history_with((0, 2)).len(), 4);
+        assert_eq!(outcomes.lock().unwrap().games(), 6);
+        // The skipped games were still played.
+        assert!(metrics.iter().all(|m| m.coop_rate == 1.0));
+    }
+
+    /// The legacy step as it was written before the flat action buffer, for comparison.
+    fn hashmap_step(env: &mut Environment) {
+        use std::collections::HashMap;
+
+        env.for_each_cell(|curr, neighbors| curr.adapt(neighbors.collect()));
+        let mut rng = env.rng.clone();
+        let (payoffs, step) = (env.payoffs, env.steps_taken);
+        let mut actions: HashMap<(Coord, Coord), Action> = HashMap::new();
+        env.for_each_cell(|curr, neighbors| {
+            for n in neighbors {
+                let mut ctx = curr.context(n.coord, step, &payoffs, &mut rng);
+                actions.insert((curr.coord, n.coord), curr.strategy.get_action(&mut ctx));
+            }
+        });
+        let noise = env.noise;
+        let mut realized: HashMap<(Coord, Coord), Action> = HashMap::new();
+        env.for_each_cell(|curr, neighbors| {
+            for n in neighbors {
+                let pair = (curr.coord, n.coord);
+                realized.insert(pair, actions[&pair].with_noise(noise, &mut rng));
+            }
+        });
+        env.for_each_cell(|curr, neighbors| {
+            for n in neighbors {
+                let (mine, theirs) = (
+                    realized[&(curr.coord, n.coord)],
+                    realized[&(n.coord, curr.coord)],
+                );
+                curr.record_outcome(InteractionOutcome {
+                    opponent: n.coord,
+                    intended: actions[&(curr.coord, n.coord)],
+                    realized: mine,
+                    their_action: theirs,
+                    payoff: payoffs.score(mine, theirs),
+                    step,
+                });
+            }
+        });
+        env.rng = rng;
+        env.steps_taken += 1;
+    }
+
+    #[test]
+    fn test_legacy_step_matches_hashmap_step() {
+        for seed in 0..4 {
+            let build = || {
+                EnvironmentBuilder::new()
+                    .size(5, 7)
+                    .noise(0.15)
+                    .seed(seed)
+                    .strategies(&Strategy::ALL)
+                    .build()
+                    .unwrap()
+            };
+            let (mut flat, mut reference) = (build(), build());
+            for _ in 0..8 {
+                flat.step();
+                hashmap_step(&mut reference);
+                for ((_, a), (_, b)) in flat.iter_agents().zip(reference.iter_agents()) {
+                    assert_eq!((a.strategy, a.score), (b.strategy, b.score));
+                    for opponent in b.opponents() {
+                        assert_eq!(a.history_with(opponent), b.history_with(opponent));
+                        assert_eq!(a.last_outcome_with(opponent), b.last_outcome_with(opponent));
+                    }
+                }
+            }
+        }
+    }
+
+    fn synchronous_env(seed: u64) -> Environment {
+        EnvironmentBuilder::new()
+            .size(7, 6)
+            .noise(0.2)
+            .seed(seed)
+            .strategies(&Strategy::ALL)
+            .update_mode(UpdateMode::Synchronous)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_synchronous_same_seed_same_run() {
+        let mut env = synchronous_env(5);
+        assert_eq!(env.update_mode(), UpdateMode::Synchronous);
+        assert_eq!(env.run(10), synchronous_env(5).run(10));
+        assert_ne!(synchronous_env(5).run(10), synchronous_env(6).run(10));
+        // Every agent played every neighbor: 262 ordered pairs on a 7x6 grid.
+        let played: usize = env.iter_agents().map(|(_, a)| a.num_opponents()).sum();
+        assert_eq!(played, 262);
+    }
+
+    #[test]
+    fn test_processing_order() {
+        let build = |mode| {
+            EnvironmentBuilder::new()
+                .size(12, 12)
+                .noise(0.1)
+                .seed(3)
+                .strategies(&Strategy::ALL)
+                .update_mode(mode)
+                .build()
+                .unwrap()
+        };
+        let backwards = |mode| {
+            REVERSED.with(|r| r.set(true));
+            let metrics = build(mode).run(15);
+            REVERSED.with(|r| r.set(false));
+            metrics
+        };
+        let forwards = |mode| build(mode).run(15);
+        assert_eq!(
+            backwards(UpdateMode::Synchronous),
+            forwards(UpdateMode::Synchronous)
+        );
+        assert_ne!(backwards(UpdateMode::Legacy), forwards(UpdateMode::Legacy));
+    }
+
+    #[test]
+    fn test_keyed_streams() {
+        let draws = |mut rng: KeyedRng| (0..4).map(|_| rng.next_u64()).collect::<Vec<_>>();
+        let stream = draws(slot_rng(1, 2, 3, 4, NOISE_DRAWS));
+        assert_eq!(stream, draws(slot_rng(1, 2, 3, 4, NOISE_DRAWS)));
+        for other in [
+            slot_rng(1, 2, 3, 5, NOISE_DRAWS),
+            slot_rng(1, 2, 3, 4, ACTION_DRAWS),
+            slot_rng(1, 2, 4, 4, NOISE_DRAWS),
+            slot_rng(1, 3, 3, 4, NOISE_DRAWS),
+            slot_rng(2, 2, 3, 4, NOISE_DRAWS),
+        ] {
+            assert_ne!(draws(other), stream);
+        }
+        let mut bytes = [0; 12];
+        slot_rng(1, 2, 3, 4, NOISE_DRAWS).fill_bytes(&mut bytes);
+        assert_eq!(bytes[..8], stream[0].to_le_bytes());
+        assert_eq!(bytes[8..], stream[1].to_le_bytes()[..4]);
+        // Uniform enough for noise: a fair coin lands close to half the time.
+        let mut rng = slot_rng(7, 0, 0, 0, NOISE_DRAWS);
+        let heads = (0..10_000).filter(|_| rng.gen_bool(0.5)).count();
+        assert!((4_800..5_200).contains(&heads), "{}", heads);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_serial() {
+        let (mut serial, mut parallel) = (synchronous_env(9), synchronous_env(9));
+        for _ in 0..10 {
+            assert_eq!(serial.step_with(false), parallel.step_with(true));
+        }
+        let pool = |threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+        };
+        let one = pool(1).install(|| synchronous_env(9).run(10));
+        let four = pool(4).install(|| synchronous_env(9).run(10));
+        assert_eq!(one, four);
+    }
+
+    #[test]
+    fn test_agent_history_after_step() {
+        let mut env =
+            Environment::new_with_agent_func(2, 2, 0.0, |c| Agent::new(c, Strategy::Coop)).unwrap();
+        env.step();
+        let agent = env.agent_at((0, 0)).unwrap();
+        assert_eq!(agent.num_opponents(), 3);
+        assert_eq!(agent.history_with((1, 1)), &[Action::Coop]);
+    }
+
+    fn with_energy(strategies: &[Strategy], energy: EnergyConfig) -> Environment {
+        EnvironmentBuilder::new()
+            .size(12, 12)
+            .seed(4)
+            .strategies(strategies)
+            .energy(energy)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reproduction_conserves_energy() {
+        let energy = EnergyConfig {
+            reproduction_threshold: 5.0,
+            ..EnergyConfig::default()
+        };
+        let mut env = EnvironmentBuilder::new()
+            .size(1, 3)
+            .agents(|c, _| Agent::new(c, Strategy::TicToc))
+            .energy(energy)
+            .build()
+            .unwrap();
+        env.vacate(2);
+        env.grid[0].energy = 1.0;
+        env.grid[1].energy = 12.0;
+        let total = |env: &Environment| env.living().map(|a| a.energy).sum::<f64>();
+        let before = total(&env);
+        assert_eq!(env.reproduce(energy), 1);
+        assert_eq!(total(&env), before);
+        let child = env.agent_at((0, 2)).unwrap();
+        assert_eq!((child.energy, env.grid[1].energy), (6.0, 6.0));
+        assert_eq!(child.strategy, Strategy::TicToc);
+        assert_eq!(
+            (child.id, child.parent_id, child.founder_id),
+            (3, Some(1), 1)
+        );
+        // Without a vacant neighbor there's nowhere to put an offspring.
+        env.grid[1].energy = 12.0;
+        assert_eq!(env.reproduce(energy), 0);
+        assert_eq!(env.grid[1].energy, 12.0);
+    }
+
+    #[test]
+    fn test_no_negative_energy_survivors() {
+        let energy = EnergyConfig {
+            living_cost: 6.0,
+            reproduction_threshold: 15.0,
+            mutation_rate: 0.1,
+            ..EnergyConfig::default()
+        };
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = with_energy(&Strategy::ALL, energy);
+            env.set_update_mode(mode);
+            let mut population = env.population();
+            let (mut births, mut deaths) = (0, 0);
+            for metric in env.run(25) {
+                assert_eq!(
+                    metric.population,
+                    population + metric.births - metric.deaths
+                );
+                assert_eq!(metric.strategies.values().sum::<usize>(), metric.population);
+                assert_eq!(metric.snapshot.vacancies(), 144 - metric.population);
+                population = metric.population;
+                births += metric.births;
+                deaths += metric.deaths;
+            }
+            assert!(
+                births > 0 && deaths > 0,
+                "{} births, {} deaths",
+                births,
+                deaths
+            );
+            assert!(env.iter_agents().all(|(_, a)| a.energy >= 0.0));
+        }
+    }
+
+    #[test]
+    fn test_incremental_metrics_match_a_recount() {
+        let energy = EnergyConfig {
+            living_cost: 6.0,
+            reproduction_threshold: 15.0,
+            mutation_rate: 0.1,
+            ..EnergyConfig::default()
+        };
+        let mut env = with_energy(&Strategy::ALL, energy);
+        let mut recorded = Vec::new();
+        for step in 0..40 {
+            // Changes made outside a step are picked up too.
+            if step % 5 == 2 {
+                let coords: Vec<Coord> = env.iter_agents().map(|(c, _)| c).collect();
+                env.set_strategy(coords[step % coords.len()], Strategy::Wary);
+                env.agent_at_mut(coords[0]).unwrap().founder_id = 1_000 + step as u64;
+            }
+            let metric = env.step();
+            let mut strategies = BTreeMap::new();
+            for (_, agent) in env.iter_agents() {
+                *strategies.entry(agent.strategy.kind()).or_insert(0) += 1;
+            }
+            assert_eq!(metric.strategies, strategies, "step {}", step);
+            assert_eq!(metric.founder_lineages, env.founder_lineages());
+            let cells: Vec<Strategy> = env.grid.iter().map(|a| a.strategy).collect();
+            assert_eq!(metric.snapshot.cells(), cells);
+            recorded.push((metric.snapshot, cells));
+        }
+        // Later steps copy the grid they update rather than change earlier snapshots.
+        for (snapshot, cells) in recorded {
+            assert_eq!(snapshot.cells(), cells);
+        }
+    }
+
+    #[test]
+    fn test_defectors_starve_when_living_costs_more_than_defecting_pays() {
+        let energy = EnergyConfig {
+            living_cost: Payoffs::default().punishment + 0.5,
+            ..EnergyConfig::default()
+        };
+        let mut env = with_energy(&[Strategy::Deflect], energy);
+        let (metrics, stopped) = env.run_while(|m| m.population > 0, 100);
+        assert!(stopped);
+        assert_eq!(metrics.iter().map(|m| m.deaths).sum::<usize>(), 144);
+        assert_eq!(metrics.iter().map(|m| m.births).sum::<usize>(), 0);
+        let last = metrics.last().unwrap();
+        assert!(last.strategies.is_empty());
+        assert_eq!(last.coop_rate, 0.0);
+        // An empty grid keeps stepping.
+        assert_eq!(env.step().population, 0);
+    }
+
+    #[test]
+    fn test_vacant_cells_drop_out() {
+        let mut env = EnvironmentBuilder::new()
+            .size(3, 3)
+            .agents(|c, _| Agent::new(c, Strategy::Coop))
+            .energy(EnergyConfig {
+                reproduction_threshold: 1e9,
+                ..EnergyConfig::default()
+            })
+            .build()
+            .unwrap();
+        env.step();
+        env.vacate(4);
+        env.neighbors = Arc::new(NeighborTable::without(3, 3, &env.vacant));
+        assert!(env.is_vacant((1, 1)));
+        assert!(env.agent_at((1, 1)).is_none());
+        assert!(env.neighbors((1, 1)).is_empty());
+        assert_eq!(env.neighbors((0, 0)).len(), 2);
+        assert!(env
+            .agent_at((0, 0))
+            .unwrap()
+            .history_with((1, 1))
+            .is_empty());
+        assert_eq!(env.iter_agents().count(), 8);
+        let metric = env.step();
+        assert!(metric.snapshot.is_vacant(1, 1));
+        assert_eq!(metric.scores[(1, 1)], 0.0);
+        assert_eq!(metric.population, 8);
+    }
+
+    #[test]
+    fn test_invalid_energy() {
+        let energy = EnergyConfig {
+            mutation_rate: 2.0,
+            ..EnergyConfig::default()
+        };
+        let result = EnvironmentBuilder::new().energy(energy).build();
+        assert_eq!(result.err(), Some(Error::InvalidEnergy(energy)));
+    }
+
+    /// A Choosy agent that has already seen its Deflect neighbor defect twice.
+    fn burned_choosy(outside_option: f64) -> Environment {
+        let mut env = EnvironmentBuilder::new()
+            .size(1, 2)
+            .agents(|c, _| {
+                let strategy = if c == (0, 0) {
+                    Strategy::Choosy
+                } else {
+                    Strategy::Deflect
+                };
+                Agent::new(c, strategy)
+            })
+            .partner_choice(PartnerChoice { outside_option })
+            .build()
+            .unwrap();
+        let choosy = env.agent_at_mut((0, 0)).unwrap();
+        choosy.record_outcome(InteractionOutcome::against((0, 1), Action::Deflect, 0.0));
+        choosy.record_outcome(InteractionOutcome::against((0, 1), Action::Deflect, 0.0));
+        env
+    }
+
+    #[test]
+    fn test_refused_games_pay_the_outside_option() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = burned_choosy(1.5);
+            env.set_update_mode(mode);
+            for (step, metric) in env.run(5).into_iter().enumerate() {
+                assert_eq!(metric.refusals, 1);
+                assert_eq!((metric.coop_actions, metric.coop_rate), (0, 0.0));
+                // Both earn the same, so neither imitates the other.
+                let earned = 1.5 * (step + 1) as f64;
+                assert_eq!(metric.scores, Grid::from(vec![vec![earned, earned]]));
+                assert_eq!(metric.strategies[&Strategy::Deflect], 1);
+            }
+            // Refused games aren't remembered by either side.
+            assert_eq!(env.agent_at((0, 0)).unwrap().history_with((0, 1)).len(), 2);
+            assert!(env
+                .agent_at((0, 1))
+                .unwrap()
+                .history_with((0, 0))
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn test_refusals_bypass_the_payoff_fn() {
+        let mut env = burned_choosy(0.0);
+        env.set_payoff_fn(|_| panic!("refused games aren't scored"));
+        assert_eq!(env.step().refusals, 1);
+    }
+
+    #[test]
+    fn test_without_partner_choice_everyone_plays() {
+        let mut env = burned_choosy(1.5);
+        env.set_partner_choice(None);
+        let metric = env.step();
+        assert_eq!(metric.refusals, 0);
+        // Choosy answers the defections like TicToc.
+        assert_eq!(metric.scores, Grid::from(vec![vec![0.0, 0.0]]));
+        assert_eq!(env.agent_at((0, 0)).unwrap().history_with((0, 1)).len(), 3);
+    }
+
+    #[test]
+    fn test_choosy_isolates_a_deflect_invader() {
+        // Suckers lose so much that the invader's first victims don't copy it.
+        let payoffs = Payoffs {
+            reward: 3.0,
+            temptation: 5.0,
+            sucker: -10.0,
+            punishment: 0.0,
+        };
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = EnvironmentBuilder::new()
+                .size(5, 5)
+                .payoffs(payoffs)
+                .agents(|c, _| {
+                    let strategy = if c == (0, 0) {
+                        Strategy::Deflect
+                    } else {
+                        Strategy::Choosy
+                    };
+                    Agent::new(c, strategy)
+                })
+                .partner_choice(PartnerChoice::default())
+                .update_mode(mode)
+                .build()
+                .unwrap();
+            let metrics = env.run(10);
+            // Its neighbors have seen it defect twice after two steps, and refuse it from then
+            // on, even once it has given up and copied them.
+            assert!(metrics[..2].iter().all(|m| m.refusals == 0));
+            for m in &metrics[2..] {
+                assert_eq!(m.refusals, 3);
+                assert_eq!(m.scores[(0, 0)], 3.0 * payoffs.temptation);
+            }
+            assert!(metrics[1].scores[(0, 1)] < metrics[9].scores[(0, 1)]);
+        }
+    }
+
+    #[test]
+    fn test_noise_breaks_choosy_partnerships_for_good() {
+        let mut env = EnvironmentBuilder::new()
+            .size(10, 10)
+            .noise(0.2)
+            .seed(6)
+            .strategies(&[Strategy::Choosy])
+            .partner_choice(PartnerChoice::default())
+            .build()
+            .unwrap();
+        let metrics = env.run(20);
+        assert_eq!(metrics[0].refusals, 0);
+        // Nobody plays a refused partner again, so there's never anything to forgive.
+        assert!(metrics.windows(2).all(|w| w[0].refusals <= w[1].refusals));
+        assert!(metrics.last().unwrap().refusals > 0);
+        for (coord, agent) in env.iter_agents() {
+            for neighbor in env.neighbors(coord) {
+                if agent.refuses(neighbor) {
+                    assert!(agent
+                        .history_with(neighbor.coord)
+                        .iter()
+                        .any(|a| a == Action::Deflect));
+                }
+            }
+        }
+    }
+
+    /// Cooperators in the left 2x2 group, defectors in the right one.
+    fn two_groups(period: usize) -> Environment {
+        EnvironmentBuilder::new()
+            .size(2, 4)
+            .agents(|c, _| {
+                let strategy = if c.1 < 2 {
+                    Strategy::Coop
+                } else {
+                    Strategy::Deflect
+                };
+                Agent::new(c, strategy)
+            })
+            .groups(Groups::new(2, 2, period))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_group_totals() {
+        let mut env = two_groups(100);
+        let metric = env.step();
+        // Every cooperator only meets cooperators and suckers; the defectors next to them
+        // exploit two each.
+        assert_eq!(
+            metric.groups,
+            vec![
+                GroupMetric {
+                    total_payoff: 4.0 * 9.0,
+                    coop_rate: 1.0,
+                    replaced_by: None,
+                },
+                GroupMetric {
+                    total_payoff: 2.0 * 8.0,
+                    coop_rate: 0.0,
+                    replaced_by: None,
+                },
+            ]
+        );
+        assert_eq!(env.group_payoffs, vec![36.0, 16.0]);
+    }
+
+    #[test]
+    fn test_losing_group_becomes_a_copy() {
+        let mut env = two_groups(1);
+        let before = env.clone();
+        let metric = env.step();
+        assert_eq!(metric.groups[0].replaced_by, None);
+        assert_eq!(metric.groups[1].replaced_by, Some(0));
+        assert_eq!(metric.strategies[&Strategy::Coop], 8);
+        for r in 0..2 {
+            for c in 0..2 {
+                let original = env.agent_at((r, c)).unwrap();
+                let copy = env.agent_at((r, c + 2)).unwrap();
+                assert_eq!(copy.strategy, original.strategy);
+                assert_eq!(copy.score, original.score);
+                assert_eq!(copy.founder_id, original.founder_id);
+                assert_eq!(copy.parent_id, Some(original.id));
+                // The copy keeps the replaced agent's identity, but not its memories.
+                assert_eq!(copy.id, before.agent_at((r, c + 2)).unwrap().id);
+                assert_eq!(copy.num_opponents(), 0);
+            }
+        }
+        // The copies' neighbors forgot the defectors that lived there.
+        let border = env.agent_at((0, 1)).unwrap();
+        assert!(border.history_with((0, 2)).is_empty());
+        assert_eq!(border.history_with((0, 0)), &[Action::Coop]);
+        // Earnings start over, and equal earnings replace nothing.
+        assert_eq!(env.group_payoffs, vec![0.0, 0.0]);
+        assert!(env.step().groups.iter().all(|g| g.replaced_by.is_none()));
+    }
+
+    #[test]
+    fn test_groups_must_tile_the_grid() {
+        let groups = Groups::new(2, 3, 1);
+        let result = EnvironmentBuilder::new().size(4, 4).groups(groups).build();
+        assert_eq!(result.err(), Some(Error::InvalidGroups(groups)));
+        let mut env = two_groups(1);
+        assert_eq!(
+            env.set_groups(Some(groups)),
+            Err(Error::InvalidGroups(groups))
+        );
+        assert!(env.set_groups(None).is_ok());
+        assert!(env.step().groups.is_empty());
+    }
+
+    /// Defection pays while the environment is rich, only mutual cooperation once degraded.
+    const COMMONS: EnvFeedback = EnvFeedback {
+        rich: Payoffs {
+            reward: 3.0,
+            temptation: 5.0,
+            sucker: 0.0,
+            punishment: 0.0,
+        },
+        degraded: Payoffs {
+            reward: 1.0,
+            temptation: 0.0,
+            sucker: 0.0,
+            punishment: 0.0,
+        },
+        recovery_rate: 0.1,
+        degradation_rate: 0.3,
+    };
+
+    fn commons(strategies: &[Strategy], noise: f32) -> Environment {
+        EnvironmentBuilder::new()
+            .size(20, 20)
+            .noise(noise)
+            .seed(1)
+            .strategies(strategies)
+            .feedback(COMMONS)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_feedback_interpolates_payoffs() {
+        assert_eq!(COMMONS.payoffs_at(1.0), COMMONS.rich);
+        assert_eq!(COMMONS.payoffs_at(0.0), COMMONS.degraded);
+        assert_eq!(COMMONS.payoffs_at(0.5).temptation, 2.5);
+        assert_eq!(COMMONS.next_state(0.5, 1.0), 0.55);
+        assert_eq!(COMMONS.next_state(0.5, 0.0), 0.35);
+        let mut env = commons(&[Strategy::Coop], 0.0);
+        assert_eq!(env.payoffs(), COMMONS.rich);
+        env.set_env_state(0.5);
+        assert_eq!(env.payoffs(), COMMONS.payoffs_at(0.5));
+        // The step is scored at the state it started from.
+        assert_eq!(env.step().max_score[&Strategy::Coop], 8.0 * 2.0);
+    }
+
+    #[test]
+    fn test_cooperation_restores_the_environment() {
+        let mut env = commons(&[Strategy::Coop], 0.0);
+        env.set_env_state(0.0);
+        let metrics = env.run(100);
+        assert!(metrics.windows(2).all(|w| w[0].env_state < w[1].env_state));
+        assert!(metrics[99].env_state > 0.9999);
+    }
+
+    #[test]
+    fn test_defection_depletes_the_environment() {
+        let mut env = commons(&[Strategy::Deflect], 0.0);
+        let metrics = env.run(50);
+        assert!(metrics.windows(2).all(|w| w[0].env_state > w[1].env_state));
+        assert!(metrics[49].env_state < 1e-6);
+        assert_eq!(env.payoffs(), COMMONS.payoffs_at(env.env_state()));
+    }
+
+    /// How many times the commons of a well-mixed 5x5 grid collapses and then recovers in
+    /// 300 steps, with a stray defector appearing every other step or so and, if `trickle`,
+    /// a 2x2 block of cooperators as often.
+    fn commons_cycles(seed: u64, trickle: bool) -> usize {
+        let mut builder = EnvironmentBuilder::new()
+            .size(5, 5)
+            .seed(seed)
+            .strategies(&[Strategy::Coop, Strategy::Deflect])
+            .feedback(COMMONS)
+            .interaction_neighborhood(Neighborhood::Moore(2))
+            .adaptation_neighborhood(Neighborhood::Moore(2))
+            .shock(Shock::new(
+                Schedule::Poisson(0.5),
+                (1, 1),
+                ShockEffect::Set(Strategy::Deflect),
+            ));
+        if trickle {
+            builder = builder.shock(Shock::new(
+                Schedule::Poisson(0.5),
+                (2, 2),
+                ShockEffect::Set(Strategy::Coop),
+            ));
+        }
+        let (mut cycles, mut collapsed) = (0, false);
+        for metric in builder.build().unwrap().run(300) {
+            if metric.env_state < 0.2 {
+                collapsed = true;
+            } else if collapsed && metric.env_state > 0.8 {
+                cycles += 1;
+                collapsed = false;
+            }
+        }
+        cycles
+    }
+
+    #[test]
+    fn test_commons_oscillates() {
+        // A stray defector overruns the rich commons, and a block of cooperators takes the
+        // poor one back, since it can't pay for defection. Single runs differ in how often
+        // that happens, so it's counted over many seeds.
+        let runs: Vec<(usize, usize)> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..32)
+                .map(|seed| {
+                    s.spawn(move || (commons_cycles(seed, true), commons_cycles(seed, false)))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let repeated = runs.iter().filter(|&&(cycles, _)| cycles >= 2).count();
+        assert!(repeated >= 24, "{:?}", runs);
+        // Without the cooperators the commons collapses for good.
+        assert!(runs.iter().all(|&(_, cycles)| cycles == 0), "{:?}", runs);
+    }
+
+    #[test]
+    fn test_invalid_feedback() {
+        let feedback = EnvFeedback {
+            recovery_rate: 1.5,
+            ..COMMONS
+        };
+        let result = EnvironmentBuilder::new().feedback(feedback).build();
+        assert_eq!(result.err(), Some(Error::InvalidFeedback(feedback)));
+    }
+
+    /// A 7x7 grid of cooperators around a defector that has already earned a lot.
+    fn marked(interaction: Neighborhood, adaptation: Neighborhood) -> Environment {
+        let mut env = EnvironmentBuilder::new()
+            .size(7, 7)
+            .seed(0)
+            .interaction_neighborhood(interaction)
+            .adaptation_neighborhood(adaptation)
+            .strategies(&[Strategy::Coop])
+            .build()
+            .unwrap();
+        env.set_strategy((3, 3), Strategy::Deflect);
+        env.agent_at_mut((3, 3)).unwrap().score = 1000.0;
+        env
+    }
+
+    #[test]
+    fn test_phases_use_their_own_neighborhoods() {
+        use Neighborhood::{Moore, VonNeumann};
+        for (interaction, adaptation) in [
+            (VonNeumann(1), Moore(2)),
+            (Moore(2), VonNeumann(1)),
+            (Moore(1), Moore(1)),
+        ] {
+            let mut env = marked(interaction, adaptation);
+            let metric = env.step();
+            // Exactly the cells that can see the marked agent copy it...
+            let copied: Vec<Coord> = env
+                .iter_agents()
+                .filter(|(c, a)| *c != (3, 3) && a.strategy == Strategy::Deflect)
+                .map(|(c, _)| c)
+                .collect();
+            assert_eq!(copied.len(), adaptation.size(), "{:?}", adaptation);
+            assert_eq!(metric.strategies[&Strategy::Deflect], adaptation.size() + 1);
+            // ...and exactly the cells it plays remember it.
+            let marked = env.agent_at((3, 3)).unwrap();
+            assert_eq!(
+                marked.num_opponents(),
+                interaction.size(),
+                "{:?}",
+                interaction
+            );
+            assert_eq!(env.neighbors((3, 3)).len(), interaction.size());
+            let partners = env
+                .iter_agents()
+                .filter(|(_, a)| a.last_action_from((3, 3)).is_some());
+            assert_eq!(partners.count(), interaction.size());
+        }
+    }
+
+    #[test]
+    fn test_invalid_neighborhood() {
+        let result = EnvironmentBuilder::new()
+            .adaptation_neighborhood(Neighborhood::VonNeumann(0))
+            .build();
+        assert_eq!(
+            result.err(),
+            Some(Error::InvalidNeighborhood(Neighborhood::VonNeumann(0)))
+        );
+        let mut env = seeded();
+        assert!(env
+            .set_interaction_neighborhood(Neighborhood::Moore(0))
+            .is_err());
+        assert_eq!(env.interaction_neighborhood(), Neighborhood::Moore(1));
+    }
+
+    /// A Deceiver in the middle of a 12x12 grid of `field`, talking before every round.
+    fn lone_deceiver(field: Strategy, mode: UpdateMode) -> Environment {
+        EnvironmentBuilder::new()
+            .size(12, 12)
+            .seed(0)
+            .update_mode(mode)
+            .signaling(true)
+            .agents(move |c, _| match c {
+                (6, 6) => Agent::new(c, Strategy::Deceiver),
+                _ => Agent::new(c, field),
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_signals_are_counted() {
+        let mut env = lone_deceiver(Strategy::Trusting, UpdateMode::Synchronous);
+        let metric = env.step();
+        // The deceiver lies to each of its eight neighbors; everyone else keeps their word.
+        assert_eq!(metric.dishonest_signals, 8);
+        assert_eq!(
+            metric.honest_signals + metric.dishonest_signals,
+            metric.coop_actions as usize + 8
+        );
+        env.set_signaling(false);
+        let metric = env.step();
+        assert_eq!((metric.honest_signals, metric.dishonest_signals), (0, 0));
+    }
+
+    #[test]
+    fn test_wary_agents_punish_deceivers() {
+        for mode in [UpdateMode::Legacy, UpdateMode::Synchronous] {
+            // The trusting keep falling for the lie, so deception never dies out...
+            let metrics = lone_deceiver(Strategy::Trusting, mode).run(40);
+            assert!(metrics
+                .iter()
+                .all(|m| m.strategies.contains_key(&Strategy::Deceiver)));
+            assert!(metrics.iter().all(|m| m.dishonest_signals > 0));
+            // ...while the wary believe each deceiver once, and out-earn it from then on.
+            let metrics = lone_deceiver(Strategy::Wary, mode).run(40);
+            let last = metrics.last().unwrap();
+            assert_eq!(last.strategies, BTreeMap::from([(Strategy::Wary, 144)]));
+            // Only the grudges against former deceivers are left.
+            assert!(last.coop_rate > 0.9, "{}", last.coop_rate);
+        }
+    }
+
+    /// A TicToc next to a Coop with five times its stake; both only ever cooperate.
+    fn rich_neighbor(imitation: Imitation) -> Environment {
+        EnvironmentBuilder::new()
+            .size(1, 2)
+            .seed(0)
+            .imitation(imitation)
+            .agents(|c, _| match c {
+                (0, 0) => Agent::new(c, Strategy::TicToc),
+                _ => Agent::new(c, Strategy::Coop).with_stake(5.0),
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_stake_multiplies_payoffs() {
+        let mut env = rich_neighbor(Imitation::Score);
+        let metric = env.step();
+        assert_eq!(metric.scores, Grid::from(vec![vec![3.0, 15.0]]));
+        assert_eq!(
+            metric.mean_stake,
+            BTreeMap::from([(Strategy::TicToc, 1.0), (Strategy::Coop, 5.0)])
+        );
+        assert_eq!(env.agent_at((0, 1)).unwrap().score_per_stake(), 3.0);
+        // Custom payoffs and the outside option are multiplied too.
+        let mut env = rich_neighbor(Imitation::Score);
+        env.set_payoff_fn(|_: &PayoffCtx<'_>| (1.0, 2.0));
+        assert_eq!(env.step().scores, Grid::from(vec![vec![1.0, 10.0]]));
+        let mut env = rich_neighbor(Imitation::Score);
+        env.set_partner_choice(Some(PartnerChoice {
+            outside_option: 1.0,
+        }));
+        env.set_strategy((0, 0), Strategy::Choosy);
+        let choosy = env.agent_at_mut((0, 0)).unwrap();
+        for _ in 0..2 {
+            choosy.record_outcome(InteractionOutcome::against((0, 1), Action::Deflect, 0.0));
+        }
+        assert_eq!(env.step().scores, Grid::from(vec![vec![1.0, 5.0]]));
+    }
+
+    #[test]
+    fn test_per_stake_imitation_ignores_wealth() {
+        // Both earn 3 per unit of stake, but the richer one's raw score is five times larger.
+        let mut env = rich_neighbor(Imitation::Score);
+        let metric = env.run(2).pop().unwrap();
+        let copier = env.agent_at((0, 0)).unwrap();
+        assert_eq!(copier.strategy, Strategy::Coop);
+        assert_eq!(copier.stake, 1.0);
+        assert_eq!(metric.mean_stake, BTreeMap::from([(Strategy::Coop, 3.0)]));
+
+        let mut env = rich_neighbor(Imitation::PerStake);
+        env.run(10);
+        assert_eq!(env.agent_at((0, 0)).unwrap().strategy, Strategy::TicToc);
+        assert_eq!(env.imitation(), Imitation::PerStake);
+    }
+
+    #[test]
+    fn test_invalid_stake() {
+        for stake in [0.0, -1.0, f32::NAN] {
+            let result = EnvironmentBuilder::new()
+                .size(2, 2)
+                .agents(|c, _| Agent::new(c, Strategy::Coop).with_stake(stake))
+                .build();
+            assert!(
+                matches!(result, Err(Error::InvalidStake { coord: (0, 0), .. })),
+                "{}",
+                stake
+            );
+        }
+    }
+
+    /// A 20x20 grid of generous TicTocs, forgiving a third of defections, among a few
+    /// defectors, under a little noise.
+    fn generous(mode: UpdateMode, sigma: f64) -> Environment {
+        let generous = Strategy::GenerousTFT(Prob::new(1.0 / 3.0));
+        EnvironmentBuilder::new()
+            .size(20, 20)
+            .seed(4)
+            .noise(0.05)
+            .update_mode(mode)
+            .mutation_sigma(sigma)
+            .agents(move |c, rng| {
+                let defects = rng.gen::<f64>() < 0.1;
+                Agent::new(c, if defects { Strategy::Deflect } else { generous })
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_zero_sigma_is_plain_imitation() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let plain = generous(mode, 0.0).run(20);
+            let forgiveness = plain.last().unwrap().parameters[&(GTFT, "forgiveness")];
+            assert!((forgiveness.mean - 1.0 / 3.0).abs() < 1e-6);
+            assert!(forgiveness.std < 1e-9);
+            // Fixed rules have nothing to mutate, so nothing is drawn and the run is the one
+            // without mutation.
+            let fixed = |sigma| {
+                EnvironmentBuilder::new()
+                    .size(8, 8)
+                    .seed(1)
+                    .noise(0.1)
+                    .update_mode(mode)
+                    .mutation_sigma(sigma)
+                    .build()
+                    .unwrap()
+                    .run(20)
+            };
+            assert_eq!(fixed(0.2), fixed(0.0));
+        }
+    }
+
+    #[test]
+    fn test_forgiveness_evolves() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let metrics = generous(mode, 0.1).run(60);
+            let forgiveness = |m: &Metric| m.parameters[&(GTFT, "forgiveness")];
+            let (first, last) = (
+                forgiveness(&metrics[0]),
+                forgiveness(metrics.last().unwrap()),
+            );
+            assert!(first.std < 1e-9);
+            assert!(last.std > 0.01);
+            assert!(
+                (last.mean - 1.0 / 3.0).abs() > 0.05,
+                "{:?} -> {:?}",
+                first,
+                last
+            );
+            // Strategies are counted together whatever their parameters.
+            assert_eq!(
+                metrics
+                    .last()
+                    .unwrap()
+                    .strategies
+                    .keys()
+                    .copied()
+                    .collect::<Vec<_>>(),
+                vec![GTFT]
+            );
+            assert_eq!(metrics, generous(mode, 0.1).run(60));
+        }
+    }
+
+    #[test]
+    fn test_invalid_mutation_sigma() {
+        for sigma in [-0.1, f64::NAN, f64::INFINITY] {
+            let result = EnvironmentBuilder::new().mutation_sigma(sigma).build();
+            assert!(matches!(result, Err(Error::InvalidMutation(_))));
+        }
+        let mut env = seeded();
+        assert!(env.set_mutation_sigma(-1.0).is_err());
+        assert_eq!(env.mutation_sigma(), 0.0);
+    }
+
+    /// An agent that always explores ignores payoffs, so the run is the voter model: the same
+    /// seed gives the same grids under any payoff matrix.
+    #[test]
+    fn test_full_exploration_is_the_voter_model() {
+        use Strategy::{Coop as C, Deflect as D, TicToc as T};
+        let skewed = Payoffs {
+            reward: 0.0,
+            temptation: 10.0,
+            sucker: -5.0,
+            punishment: 1.0,
+        };
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let run = |payoffs: Payoffs| {
+                EnvironmentBuilder::new()
+                    .size(8, 8)
+                    .seed(13)
+                    .update_mode(mode)
+                    .payoffs(payoffs)
+                    .exploration_rate(1.0)
+                    .strategies(&[C, D, T])
+                    .build()
+                    .unwrap()
+                    .run(30)
+            };
+            let (standard, other) = (run(Payoffs::default()), run(skewed));
+            for (a, b) in standard.iter().zip(&other) {
+                assert_eq!(a.snapshot, b.snapshot);
+                assert_eq!((a.imitations, b.imitations), (0, 0));
+            }
+            assert!(standard.iter().map(|m| m.explorations).sum::<usize>() > 0);
+        }
+    }
+
+    #[test]
+    fn test_switches_by_cause() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = EnvironmentBuilder::new()
+                .size(10, 10)
+                .seed(2)
+                .noise(0.05)
+                .update_mode(mode)
+                .exploration_rate(0.2)
+                .strategies(&[Strategy::Coop, Strategy::Deflect, Strategy::TicToc])
+                .build()
+                .unwrap();
+            let strategies = |env: &Environment| {
+                env.iter_agents()
+                    .map(|(_, a)| a.strategy)
+                    .collect::<Vec<_>>()
+            };
+            let (mut imitations, mut explorations) = (0, 0);
+            for _ in 0..20 {
+                let before = strategies(&env);
+                let metric = env.step();
+                let changed = (before.iter().zip(strategies(&env)))
+                    .filter(|(a, b)| **a != *b)
+                    .count();
+                assert_eq!(metric.imitations + metric.explorations, changed);
+                imitations += metric.imitations;
+                explorations += metric.explorations;
+            }
+            assert!(imitations > 0 && explorations > 0, "{:?}", mode);
+        }
+        // Without exploration every switch is an imitation, and seeded runs are as before.
+        let mut env = seeded();
+        assert_eq!(env.exploration_rate(), 0.0);
+        assert!(env.run(10).iter().all(|m| m.explorations == 0));
+    }
+
+    #[test]
+    fn test_invalid_exploration_rate() {
+        for rate in [-0.1, 1.5, f32::NAN] {
+            let result = EnvironmentBuilder::new().exploration_rate(rate).build();
+            assert!(matches!(result, Err(Error::InvalidExploration(_))));
+        }
+        let mut env = seeded();
+        assert!(env.set_exploration_rate(2.0).is_err());
+        assert_eq!(env.exploration_rate(), 0.0);
+    }
+
+    /// Hosts on the diagonal of a 2x2 grid, the host at the origin defecting.
+    fn host_and_visitors(mode: UpdateMode) -> Environment {
+        let game = Asymmetric {
+            host: Payoffs {
+                reward: 3.0,
+                temptation: 5.0,
+                sucker: -1.0,
+                punishment: 0.0,
+            },
+            visitor: Payoffs {
+                reward: 2.0,
+                temptation: 4.0,
+                sucker: -3.0,
+                punishment: 1.0,
+            },
+        };
+        EnvironmentBuilder::new()
+            .size(2, 2)
+            .seed(0)
+            .update_mode(mode)
+            .asymmetric(game)
+            .agents(|c, _| {
+                let strategy = if c == (0, 0) {
+                    Strategy::Deflect
+                } else {
+                    Strategy::Coop
+                };
+                Agent::new(c, strategy).with_class(Class::checkerboard(c))
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_roles_score_with_their_own_matrix() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = host_and_visitors(mode);
+            let metric = env.step();
+            // Hosts play both visitors, visitors both hosts.
+            assert_eq!(
+                metric.scores,
+                Grid::from(vec![
+                    vec![5.0 + 5.0, -3.0 + 2.0],
+                    vec![-3.0 + 2.0, 3.0 + 3.0]
+                ])
+            );
+            assert_eq!(metric.coop_actions, 6);
+            assert_eq!(metric.snapshot.class(0, 1), Some(Class::Visitor));
+            assert_eq!(env.neighbors((0, 0)).len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_classes_never_play_themselves() {
+        let mut env = host_and_visitors(UpdateMode::Legacy);
+        env.run(3);
+        for (coord, agent) in env.iter_agents() {
+            let mut opponents: Vec<Coord> = agent.opponents().collect();
+            opponents.sort();
+            let expected = match agent.class {
+                Class::Host => vec![(0, 1), (1, 0)],
+                Class::Visitor => vec![(0, 0), (1, 1)],
+            };
+            assert_eq!(opponents, expected, "{:?}", coord);
+        }
+    }
+
+    #[test]
+    fn test_classes_only_imitate_themselves() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut env = host_and_visitors(mode);
+            env.step();
+            // The defecting host out-earns everyone, but only the other host may copy it.
+            let metric = env.step();
+            let deflect = BTreeMap::from([(Strategy::Deflect, 2)]);
+            let coop = BTreeMap::from([(Strategy::Coop, 2)]);
+            assert_eq!(metric.class_strategies, vec![deflect, coop]);
+        }
+        // Without the game the visitors copy the defector too.
+        let mut env = host_and_visitors(UpdateMode::Legacy);
+        env.set_asymmetric(None).unwrap();
+        env.run(2);
+        assert!(env
+            .iter_agents()
+            .all(|(_, a)| a.strategy == Strategy::Deflect));
+    }
+
+    #[test]
+    fn test_asymmetric_defaults_to_a_checkerboard() {
+        let game = Asymmetric {
+            host: Payoffs::default(),
+            visitor: Payoffs::default(),
+        };
+        let env = EnvironmentBuilder::new()
+            .size(3, 3)
+            .asymmetric(game)
+            .build()
+            .unwrap();
+        assert!(env
+            .iter_agents()
+            .all(|(c, a)| a.class == Class::checkerboard(c)));
+        assert_eq!(env.neighbors((1, 1)).len(), 4);
+        let bad = Payoffs {
+            sucker: f64::INFINITY,
+            ..Payoffs::default()
+        };
+        let result = EnvironmentBuilder::new()
+            .asymmetric(Asymmetric {
+                visitor: bad,
+                ..game
+            })
+            .build();
+        assert_eq!(result.err(), Some(Error::InvalidPayoffs(bad)));
+    }
+
+    fn shocked(shock: Shock, seed: u64) -> Environment {
+        EnvironmentBuilder::new()
+            .size(10, 10)
+            .seed(seed)
+            .strategies(&[Strategy::Coop])
+            .shock(shock)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shocks_fire_on_schedule() {
+        let storm = Shock::new(Schedule::At(3), (2, 3), ShockEffect::Set(Strategy::Deflect));
+        let mut env = shocked(storm, 1);
+        let metrics = env.run(3);
+        assert!(metrics.iter().all(|m| m.shocks.is_empty()));
+        let metric = env.step();
+        assert_eq!(metric.shocks.len(), 1);
+        let hit = metric.shocks[0];
+        assert_eq!((hit.shock, hit.rows, hit.cols), (0, 2, 3));
+        assert_eq!(metric.strategies[&Strategy::Deflect], 6);
+        for (coord, agent) in env.iter_agents() {
+            let struck = hit.contains(coord);
+            assert_eq!(agent.strategy == Strategy::Deflect, struck);
+            assert_eq!(agent.num_opponents() == 0, struck, "{:?}", coord);
+            assert!(agent.opponents().all(|o| !hit.contains(o)));
+        }
+        assert!(env.run(5).iter().all(|m| m.shocks.is_empty()));
+
+        let every = Shock::new(Schedule::Every(2), (1, 1), ShockEffect::Randomize);
+        let fired: Vec<usize> = shocked(every, 1)
+            .run(6)
+            .iter()
+            .map(|m| m.shocks.len())
+            .collect();
+        assert_eq!(fired, vec![0, 1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_poisson_shocks_replay_under_a_seed() {
+        let rain = Shock::new(Schedule::Poisson(0.5), (3, 3), ShockEffect::Randomize);
+        let fired = |seed| -> Vec<Vec<FiredShock>> {
+            shocked(rain, seed)
+                .run(200)
+                .into_iter()
+                .map(|m| m.shocks)
+                .collect()
+        };
+        let first = fired(7);
+        assert_eq!(first, fired(7));
+        assert_ne!(first, fired(8));
+        let count: usize = first.iter().map(Vec::len).sum();
+        assert!((70..130).contains(&count), "{}", count);
+        assert!(first.iter().any(|s| s.len() > 1));
+    }
+
+    #[test]
+    fn test_invalid_shock() {
+        let shock = Shock::new(Schedule::Every(0), (2, 2), ShockEffect::Randomize);
+        let result = EnvironmentBuilder::new().shock(shock).build();
+        assert_eq!(result.err(), Some(Error::InvalidShock(shock)));
+        let mut env = seeded();
+        assert_eq!(env.set_shocks(vec![shock]), Err(Error::InvalidShock(shock)));
+        assert!(env.shocks().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_partner_choice() {
+        let choice = PartnerChoice {
+            outside_option: f64::INFINITY,
+        };
+        let result = EnvironmentBuilder::new().partner_choice(choice).build();
+        assert_eq!(result.err(), Some(Error::InvalidPartnerChoice(choice)));
     }
 }
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use crate::agent::{Coord, Strategy};
+use crate::env::{EnergyConfig, EnvFeedback, PartnerChoice, Payoffs};
+use crate::groups::Groups;
+use crate::neighbors::Neighborhood;
+use crate::shocks::Shock;
+
+/// Why an environment or agent couldn't be created.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The grid has no rows or no columns.
+    EmptyGrid { rows: usize, cols: usize },
+    /// Noise is a probability and must be in `[0, 1]`.
+    InvalidNoise(f32),
+    /// Every payoff must be a finite number.
+    InvalidPayoffs(Payoffs),
+    /// A memory window must remember at least one action.
+    EmptyMemoryWindow,
+    /// The agent function placed an agent with a different coordinate than it was asked for.
+    MisplacedAgent { expected: Coord, found: Coord },
+    /// An agent's stake must be a positive, finite number.
+    InvalidStake { coord: Coord, stake: f32 },
+    /// A random strategy was asked for from an empty list of strategies.
+    EmptyStrategyPool,
+    /// Energy amounts must be finite, the reproduction threshold positive and the mutation
+    /// rate a probability.
+    InvalidEnergy(EnergyConfig),
+    /// The outside option of partner choice must be a finite number.
+    InvalidPartnerChoice(PartnerChoice),
+    /// Groups must be non-empty tiles that cover the grid exactly, with a positive period.
+    InvalidGroups(Groups),
+    /// Feedback payoffs must be finite and its rates in `[0, 1]`.
+    InvalidFeedback(EnvFeedback),
+    /// A neighborhood must hold at least one cell.
+    InvalidNeighborhood(Neighborhood),
+    /// A shock must hit a non-empty region, on a positive period or at a finite,
+    /// non-negative rate.
+    InvalidShock(Shock),
+    /// The standard deviation of parameter mutation must be finite and non-negative.
+    InvalidMutation(f64),
+    /// Every share of an initial strategy mix must be finite and non-negative.
+    InvalidMix { strategy: Strategy, fraction: f32 },
+    /// The fraction of games a sampling recorder keeps must be in [0, 1].
+    InvalidSampleRate(f64),
+    /// The chance that an agent explores must be in [0, 1].
+    InvalidExploration(f32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EmptyGrid { rows, cols } => {
+                write!(f, "grid must not be empty, got {}x{}", rows, cols)
+            }
+            Error::InvalidNoise(noise) => write!(f, "noise must be in [0, 1], got {}", noise),
+            Error::InvalidPayoffs(p) => write!(f, "payoffs must be finite, got {:?}", p),
+            Error::EmptyMemoryWindow => write!(f, "memory window must be at least 1"),
+            Error::MisplacedAgent { expected, found } => {
+                write!(f, "agent for {:?} was placed at {:?}", expected, found)
+            }
+            Error::InvalidStake { coord, stake } => {
+                write!(f, "agent at {:?} has invalid stake {}", coord, stake)
+            }
+            Error::EmptyStrategyPool => write!(f, "need at least one strategy to pick from"),
+            Error::InvalidEnergy(e) => write!(f, "invalid energy budget {:?}", e),
+            Error::InvalidPartnerChoice(p) => write!(f, "invalid partner choice {:?}", p),
+            Error::InvalidFeedback(e) => write!(f, "invalid environmental feedback {:?}", e),
+            Error::InvalidNeighborhood(n) => write!(f, "empty neighborhood {:?}", n),
+            Error::InvalidShock(s) => write!(f, "invalid shock {:?}", s),
+            Error::InvalidMutation(sigma) => {
+                write!(f, "mutation sigma must be finite and >= 0, got {}", sigma)
+            }
+            Error::InvalidMix { strategy, fraction } => {
+                write!(
+                    f,
+                    "share of {} must be finite and >= 0, got {}",
+                    strategy, fraction
+                )
+            }
+            Error::InvalidSampleRate(rate) => {
+                write!(f, "sample rate must be in [0, 1], got {}", rate)
+            }
+            Error::InvalidExploration(rate) => {
+                write!(f, "exploration rate must be in [0, 1], got {}", rate)
+            }
+            Error::InvalidGroups(g) => write!(
+                f,
+                "groups of {}x{} every {} steps don't tile the grid",
+                g.rows, g.cols, g.period
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,881 @@
+//! Canned experiments that run many seeded environments and summarize them.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::SeedableRng;
+
+use crate::agent::{Agent, Coord, Strategy};
+use crate::builder::EnvironmentBuilder;
+use crate::env::{Environment, Payoffs, UpdateMode};
+use crate::error::Error;
+
+/// How the invaders are placed on the resident grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Seeding {
+    /// One invader in the middle of the grid.
+    Single,
+    /// A square of invaders with this side in the middle of the grid, cut to the grid's size.
+    Cluster(usize),
+    /// This many invaders on distinct cells drawn at random for each replicate, cut to the
+    /// grid's size.
+    Scattered(usize),
+}
+
+/// The setup shared by every replicate of an invasion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvasionConfig {
+    pub rows: usize,
+    pub cols: usize,
+    pub noise: f32,
+    pub payoffs: Payoffs,
+    pub update_mode: UpdateMode,
+    pub seeding: Seeding,
+    /// Runs of the invasion, replicate `r` seeded with `seed + r`.
+    pub replicates: usize,
+    /// Steps after which a replicate where neither side has won counts as unresolved.
+    pub max_steps: usize,
+    /// Steps without any change in the number of invaders after which a replicate is taken to
+    /// have settled, and stops early as unresolved; `None` runs every replicate to the end.
+    pub patience: Option<usize>,
+    pub seed: u64,
+}
+
+impl Default for InvasionConfig {
+    fn default() -> Self {
+        InvasionConfig {
+            rows: 11,
+            cols: 11,
+            noise: 0.0,
+            payoffs: Payoffs::default(),
+            update_mode: UpdateMode::default(),
+            seeding: Seeding::Single,
+            replicates: 20,
+            max_steps: 200,
+            patience: None,
+            seed: 0,
+        }
+    }
+}
+
+/// How a replicate ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The invader took over the whole grid.
+    Fixation,
+    /// The invader died out.
+    Extinction,
+    /// Both strategies were still around after `max_steps`, or when the replicate settled.
+    Unresolved,
+}
+
+/// One run of an invasion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replicate {
+    pub seed: u64,
+    pub outcome: Outcome,
+    /// Steps taken until fixation, extinction or settling, or `max_steps`.
+    pub steps: usize,
+    /// Sizes of the invader's connected clusters at the end, largest first.
+    pub clusters: Vec<usize>,
+}
+
+/// What the replicates of an invasion came to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvasionReport {
+    pub resident: Strategy,
+    pub invader: Strategy,
+    pub replicates: Vec<Replicate>,
+}
+
+impl InvasionReport {
+    /// Fraction of replicates in which the invader took over the grid.
+    pub fn fixation_probability(&self) -> f64 {
+        self.fraction(Outcome::Fixation)
+    }
+
+    /// Fraction of replicates in which the invader died out.
+    pub fn extinction_probability(&self) -> f64 {
+        self.fraction(Outcome::Extinction)
+    }
+
+    /// Mean steps to fixation over the replicates that fixated, if any did.
+    pub fn mean_fixation_time(&self) -> Option<f64> {
+        self.mean_steps(Outcome::Fixation)
+    }
+
+    /// Mean steps to extinction over the replicates that went extinct, if any did.
+    pub fn mean_extinction_time(&self) -> Option<f64> {
+        self.mean_steps(Outcome::Extinction)
+    }
+
+    /// How many final invader clusters of each size there were, over all replicates.
+    pub fn cluster_sizes(&self) -> BTreeMap<usize, usize> {
+        let mut sizes = BTreeMap::new();
+        for size in self.replicates.iter().flat_map(|r| &r.clusters) {
+            *sizes.entry(*size).or_insert(0) += 1;
+        }
+        sizes
+    }
+
+    fn fraction(&self, outcome: Outcome) -> f64 {
+        if self.replicates.is_empty() {
+            return 0.0;
+        }
+        let n = self
+            .replicates
+            .iter()
+            .filter(|r| r.outcome == outcome)
+            .count();
+        n as f64 / self.replicates.len() as f64
+    }
+
+    fn mean_steps(&self, outcome: Outcome) -> Option<f64> {
+        let steps: Vec<usize> = self
+            .replicates
+            .iter()
+            .filter(|r| r.outcome == outcome)
+            .map(|r| r.steps)
+            .collect();
+        (!steps.is_empty()).then(|| steps.iter().sum::<usize>() as f64 / steps.len() as f64)
+    }
+}
+
+/// Runs `config.replicates` invasions of a grid of `resident` by `invader` and reports how
+/// they ended. Fails if the config can't build an environment.
+pub fn invasion(
+    resident: Strategy,
+    invader: Strategy,
+    config: InvasionConfig,
+) -> Result<InvasionReport, Error> {
+    let seeds: Vec<u64> = (0..config.replicates as u64)
+        .map(|r| config.seed.wrapping_add(r))
+        .collect();
+    let replicates = map_jobs(seeds.len(), |i| {
+        let env = seeded_invasion(resident, invader, &config, seeds[i])?;
+        Ok(run_invasion(env, invader, &config, seeds[i]))
+    })
+    .into_iter()
+    .collect::<Result<_, _>>()?;
+    Ok(InvasionReport {
+        resident,
+        invader,
+        replicates,
+    })
+}
+
+/// Who invades whom in a fixation estimate, and where the invaders start.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvaderSetup {
+    pub resident: Strategy,
+    pub invader: Strategy,
+    pub seeding: Seeding,
+}
+
+/// How likely an invader is to take over the grid, from many independent trials.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixationEstimate {
+    pub trials: usize,
+    /// Trials in which the invader took over, died out, or neither.
+    pub fixated: usize,
+    pub lost: usize,
+    pub undecided: usize,
+    /// Fraction of the decided trials in which the invader took over; 0 if none were decided.
+    pub probability: f64,
+    /// Wilson score interval around `probability` at 95% confidence.
+    pub interval: (f64, f64),
+    /// Steps to fixation and to loss, in ascending order.
+    pub fixation_times: Vec<usize>,
+    pub loss_times: Vec<usize>,
+}
+
+impl FixationEstimate {
+    fn new(report: &InvasionReport) -> FixationEstimate {
+        let times = |outcome| {
+            let mut steps: Vec<usize> = report
+                .replicates
+                .iter()
+                .filter(|r| r.outcome == outcome)
+                .map(|r| r.steps)
+                .collect();
+            steps.sort_unstable();
+            steps
+        };
+        let (fixation_times, loss_times) = (times(Outcome::Fixation), times(Outcome::Extinction));
+        let (fixated, lost) = (fixation_times.len(), loss_times.len());
+        let decided = fixated + lost;
+        FixationEstimate {
+            trials: report.replicates.len(),
+            fixated,
+            lost,
+            undecided: report.replicates.len() - decided,
+            probability: if decided == 0 {
+                0.0
+            } else {
+                fixated as f64 / decided as f64
+            },
+            interval: wilson_interval(fixated, decided),
+            fixation_times,
+            loss_times,
+        }
+    }
+
+    /// Whether `p` lies within the confidence interval.
+    pub fn contains(&self, p: f64) -> bool {
+        (self.interval.0..=self.interval.1).contains(&p)
+    }
+}
+
+/// Estimates the probability that `setup.invader` takes over a grid of `setup.resident`, from
+/// `n_trials` trials of at most `max_steps` steps run as [`invasion`] replicates, on all cores
+/// with the `parallel` feature. `config` gives the grid, game and seed; its own seeding,
+/// replicate count and step limit are replaced by the other arguments, and its `patience`
+/// lets settled trials stop early as undecided. Fails if the config can't build an
+/// environment.
+pub fn fixation_probability(
+    config: &InvasionConfig,
+    setup: InvaderSetup,
+    n_trials: usize,
+    max_steps: usize,
+) -> Result<FixationEstimate, Error> {
+    let config = InvasionConfig {
+        seeding: setup.seeding,
+        replicates: n_trials,
+        max_steps,
+        ..*config
+    };
+    let report = invasion(setup.resident, setup.invader, config)?;
+    Ok(FixationEstimate::new(&report))
+}
+
+/// The Wilson score interval at 95% confidence for `successes` out of `trials`; all of
+/// `[0, 1]` without trials.
+fn wilson_interval(successes: usize, trials: usize) -> (f64, f64) {
+    const Z: f64 = 1.96;
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let (n, p) = (trials as f64, successes as f64 / trials as f64);
+    let denominator = 1.0 + Z * Z / n;
+    let center = (p + Z * Z / (2.0 * n)) / denominator;
+    let half = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+    ((center - half).max(0.0), (center + half).min(1.0))
+}
+
+/// A grid of `resident` with `invader`s placed as `config.seeding` says.
+fn seeded_invasion(
+    resident: Strategy,
+    invader: Strategy,
+    config: &InvasionConfig,
+    seed: u64,
+) -> Result<Environment, Error> {
+    let invaded: Vec<bool> = match config.seeding {
+        Seeding::Single => centered(config, 1),
+        Seeding::Cluster(side) => centered(config, side.max(1)),
+        Seeding::Scattered(count) => {
+            let cells = config.rows * config.cols;
+            // Placed with draws of their own, so they don't line up with the run's.
+            let mut rng = StdRng::seed_from_u64(!seed);
+            let mut invaded = vec![false; cells];
+            for i in index::sample(&mut rng, cells, count.min(cells)) {
+                invaded[i] = true;
+            }
+            invaded
+        }
+    };
+    EnvironmentBuilder::new()
+        .size(config.rows, config.cols)
+        .noise(config.noise)
+        .payoffs(config.payoffs)
+        .update_mode(config.update_mode)
+        .seed(seed)
+        .agents(|c, _| {
+            let invaded = invaded[c.0 * config.cols + c.1];
+            Agent::new(c, if invaded { invader } else { resident })
+        })
+        .build()
+}
+
+/// Row-major flags of the cells in a `side` x `side` square in the middle of the grid.
+fn centered(config: &InvasionConfig, side: usize) -> Vec<bool> {
+    let block = |len: usize| {
+        let side = side.min(len);
+        let start = (len - side) / 2;
+        start..start + side
+    };
+    let (rows, cols) = (block(config.rows), block(config.cols));
+    (0..config.rows * config.cols)
+        .map(|i| rows.contains(&(i / config.cols)) && cols.contains(&(i % config.cols)))
+        .collect()
+}
+
+/// Steps `env` until `invader` has taken over or died out, or `config.max_steps` are up.
+fn run_invasion(
+    mut env: Environment,
+    invader: Strategy,
+    config: &InvasionConfig,
+    seed: u64,
+) -> Replicate {
+    let total = config.rows * config.cols;
+    let mut outcome = Outcome::Unresolved;
+    let (mut last, mut unchanged) = (None, 0);
+    let (metrics, _) = env.run_while(
+        |m| {
+            let invaders = m.strategies.get(&invader).copied().unwrap_or(0);
+            outcome = match invaders {
+                0 => Outcome::Extinction,
+                n if n == total => Outcome::Fixation,
+                _ => Outcome::Unresolved,
+            };
+            unchanged = if last == Some(invaders) {
+                unchanged + 1
+            } else {
+                0
+            };
+            last = Some(invaders);
+            outcome == Outcome::Unresolved && config.patience.is_none_or(|p| unchanged < p)
+        },
+        config.max_steps,
+    );
+    Replicate {
+        seed,
+        outcome,
+        steps: metrics.len(),
+        clusters: clusters(&env, invader),
+    }
+}
+
+/// Sizes of the connected groups of agents playing `strategy`, largest first, where agents are
+/// connected if they're neighbors.
+fn clusters(env: &Environment, strategy: Strategy) -> Vec<usize> {
+    let (rows, cols) = env.dimensions();
+    let mut seen = vec![false; rows * cols];
+    let mut sizes = Vec::new();
+    for (coord, agent) in env.iter_agents() {
+        if agent.strategy != strategy || seen[coord.0 * cols + coord.1] {
+            continue;
+        }
+        seen[coord.0 * cols + coord.1] = true;
+        let mut stack: Vec<Coord> = vec![coord];
+        let mut size = 0;
+        while let Some(c) = stack.pop() {
+            size += 1;
+            for n in env.neighbors(c) {
+                let i = n.coord.0 * cols + n.coord.1;
+                if n.strategy == strategy && !seen[i] {
+                    seen[i] = true;
+                    stack.push(n.coord);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes
+}
+
+/// A parameter a phase diagram sweeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SweepParam {
+    Noise,
+    Reward,
+    Temptation,
+    Sucker,
+    Punishment,
+    /// [`RunConfig::mutation_sigma`].
+    MutationRate,
+}
+
+impl SweepParam {
+    pub fn name(self) -> &'static str {
+        match self {
+            SweepParam::Noise => "noise",
+            SweepParam::Reward => "reward",
+            SweepParam::Temptation => "temptation",
+            SweepParam::Sucker => "sucker",
+            SweepParam::Punishment => "punishment",
+            SweepParam::MutationRate => "mutation_sigma",
+        }
+    }
+
+    /// `config` with this parameter set to `value`.
+    fn apply(self, config: &RunConfig, value: f64) -> RunConfig {
+        let mut config = config.clone();
+        match self {
+            SweepParam::Noise => config.noise = value as f32,
+            SweepParam::Reward => config.payoffs.reward = value,
+            SweepParam::Temptation => config.payoffs.temptation = value,
+            SweepParam::Sucker => config.payoffs.sucker = value,
+            SweepParam::Punishment => config.payoffs.punishment = value,
+            SweepParam::MutationRate => config.mutation_sigma = value,
+        }
+        config
+    }
+}
+
+/// A run of a randomly populated grid for a fixed number of steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunConfig {
+    pub rows: usize,
+    pub cols: usize,
+    pub noise: f32,
+    pub payoffs: Payoffs,
+    pub update_mode: UpdateMode,
+    /// How far imitated strategy parameters stray; see
+    /// [`EnvironmentBuilder::mutation_sigma`].
+    pub mutation_sigma: f64,
+    /// Strategies the initial agents are drawn from uniformly.
+    pub strategies: Vec<Strategy>,
+    pub steps: usize,
+    /// Replicate `r` is seeded with `seed + r`.
+    pub seed: u64,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig {
+            rows: 20,
+            cols: 20,
+            noise: 0.0,
+            payoffs: Payoffs::default(),
+            update_mode: UpdateMode::default(),
+            mutation_sigma: 0.0,
+            strategies: vec![Strategy::Deflect, Strategy::TicToc],
+            steps: 100,
+            seed: 0,
+        }
+    }
+}
+
+impl RunConfig {
+    /// The cooperation rate of the last step of a run seeded with `seed`.
+    fn final_coop_rate(&self, seed: u64) -> Result<f64, Error> {
+        let mut env = EnvironmentBuilder::new()
+            .size(self.rows, self.cols)
+            .noise(self.noise)
+            .payoffs(self.payoffs)
+            .update_mode(self.update_mode)
+            .mutation_sigma(self.mutation_sigma)
+            .strategies(&self.strategies)
+            .seed(seed)
+            .build()?;
+        let mut rate = 0.0;
+        env.run_with(self.steps, |m| rate = m.coop_rate as f64);
+        Ok(rate)
+    }
+}
+
+/// Mean final cooperation over a grid of parameter values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseDiagram {
+    pub x_param: SweepParam,
+    pub x_values: Vec<f64>,
+    pub y_param: SweepParam,
+    pub y_values: Vec<f64>,
+    /// `cooperation[y][x]` is the cooperation rate of the last step, averaged over the
+    /// replicates run with `y_values[y]` and `x_values[x]`.
+    pub cooperation: Vec<Vec<f64>>,
+}
+
+impl PhaseDiagram {
+    /// The cooperation grid with the y values down the first column and the x values across
+    /// the header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\\{}", self.y_param.name(), self.x_param.name());
+        for x in &self.x_values {
+            csv.push_str(&format!(",{}", x));
+        }
+        csv.push('\n');
+        for (y, row) in self.y_values.iter().zip(&self.cooperation) {
+            csv.push_str(&y.to_string());
+            for value in row {
+                csv.push_str(&format!(",{}", value));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Writes the diagram to `path`: as Parquet if the name ends in `.parquet`, which needs
+    /// the `arrow` feature, and as [`to_csv`](PhaseDiagram::to_csv) otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|e| e == "parquet") {
+            #[cfg(feature = "arrow")]
+            return crate::parquet::write_phase_diagram(self, path);
+            #[cfg(not(feature = "arrow"))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Parquet export needs the `arrow` feature",
+            ));
+        }
+        fs::write(path, self.to_csv())
+    }
+}
+
+/// `n` evenly spaced values from `start` to `end`, both included.
+pub fn linspace(start: f64, end: f64, n: usize) -> Vec<f64> {
+    match n {
+        0 => Vec::new(),
+        1 => vec![start],
+        _ => (0..n)
+            .map(|i| start + (end - start) * i as f64 / (n - 1) as f64)
+            .collect(),
+    }
+}
+
+/// Runs `replicates` seeded runs of `base` at every combination of the two parameters' values
+/// and averages their final cooperation. Every point uses the same seeds, so differences
+/// between points come from the parameters. Fails if some point can't build an environment,
+/// e.g. noise outside `[0, 1]`.
+pub fn phase_diagram(
+    x_param: SweepParam,
+    x_values: &[f64],
+    y_param: SweepParam,
+    y_values: &[f64],
+    base: &RunConfig,
+    replicates: usize,
+) -> Result<PhaseDiagram, Error> {
+    let (width, replicates) = (x_values.len(), replicates.max(1));
+    let rates = map_jobs(width * y_values.len() * replicates, |job| {
+        let (point, replicate) = (job / replicates, job % replicates);
+        let config = y_param.apply(
+            &x_param.apply(base, x_values[point % width]),
+            y_values[point / width],
+        );
+        config.final_coop_rate(base.seed.wrapping_add(replicate as u64))
+    })
+    .into_iter()
+    .collect::<Result<Vec<f64>, _>>()?;
+    let cooperation = rates
+        .chunks(replicates)
+        .map(|runs| runs.iter().sum::<f64>() / replicates as f64)
+        .collect::<Vec<f64>>()
+        .chunks(width.max(1))
+        .map(<[f64]>::to_vec)
+        .collect();
+    Ok(PhaseDiagram {
+        x_param,
+        x_values: x_values.to_vec(),
+        y_param,
+        y_values: y_values.to_vec(),
+        cooperation,
+    })
+}
+
+/// Calls `f` with every index below `len`, on all cores with the `parallel` feature; results
+/// are in index order either way.
+fn map_jobs<T, F>(len: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        (0..len).into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    (0..len).map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Prob;
+
+    use Strategy::*;
+
+    /// Axelrod's payoffs, under which defectors earn something against each other, so a
+    /// defector cluster can grow instead of starving itself.
+    fn config() -> InvasionConfig {
+        InvasionConfig {
+            rows: 7,
+            cols: 7,
+            noise: 0.01,
+            payoffs: Payoffs {
+                reward: 3.0,
+                temptation: 5.0,
+                sucker: 0.0,
+                punishment: 1.0,
+            },
+            replicates: 10,
+            max_steps: 100,
+            seed: 3,
+            ..InvasionConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_defectors_invade_cooperators() {
+        let report = invasion(Coop, Deflect, config()).unwrap();
+        assert!(
+            report.fixation_probability() >= 0.8,
+            "{:?}",
+            report.replicates
+        );
+        assert!(report.mean_fixation_time().unwrap() > 1.0);
+    }
+
+    #[test]
+    fn test_cooperators_cant_invade_defectors() {
+        let report = invasion(Deflect, Coop, config()).unwrap();
+        assert_eq!(report.extinction_probability(), 1.0);
+        assert_eq!(report.fixation_probability(), 0.0);
+        assert!(report.mean_extinction_time().is_some());
+        assert_eq!(report.mean_fixation_time(), None);
+        assert!(report.cluster_sizes().is_empty());
+    }
+
+    #[test]
+    fn test_replicates_are_seeded() {
+        let report = invasion(Coop, Deflect, config()).unwrap();
+        let seeds: Vec<u64> = report.replicates.iter().map(|r| r.seed).collect();
+        assert_eq!(seeds, (3..13).collect::<Vec<_>>());
+        assert_eq!(report, invasion(Coop, Deflect, config()).unwrap());
+    }
+
+    #[test]
+    fn test_cluster_seeding() {
+        let config = InvasionConfig {
+            rows: 5,
+            cols: 6,
+            seeding: Seeding::Cluster(2),
+            ..config()
+        };
+        let env = seeded_invasion(TicToc, Deflect, &config, 0).unwrap();
+        let invaders: Vec<Coord> = env
+            .iter_agents()
+            .filter(|(_, a)| a.strategy == Deflect)
+            .map(|(c, _)| c)
+            .collect();
+        assert_eq!(invaders, vec![(1, 2), (1, 3), (2, 2), (2, 3)]);
+        assert_eq!(clusters(&env, Deflect), vec![4]);
+        // A cluster larger than the grid fills it.
+        let config = InvasionConfig {
+            seeding: Seeding::Cluster(10),
+            ..config
+        };
+        let env = seeded_invasion(TicToc, Deflect, &config, 0).unwrap();
+        assert_eq!(clusters(&env, Deflect), vec![30]);
+        assert_eq!(clusters(&env, TicToc), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_scattered_seeding() {
+        let config = InvasionConfig {
+            seeding: Seeding::Scattered(5),
+            ..config()
+        };
+        let invaders = |seed| {
+            let env = seeded_invasion(Coop, Deflect, &config, seed).unwrap();
+            env.iter_agents()
+                .filter(|(_, a)| a.strategy == Deflect)
+                .map(|(c, _)| c)
+                .collect::<Vec<Coord>>()
+        };
+        assert_eq!(invaders(0).len(), 5);
+        assert_eq!(invaders(0), invaders(0));
+        assert_ne!(invaders(0), invaders(1));
+    }
+
+    /// Coop and Trusting play alike without cheap talk, so neither is favored.
+    fn neutral() -> InvaderSetup {
+        InvaderSetup {
+            resident: Coop,
+            invader: Trusting,
+            seeding: Seeding::Scattered(1),
+        }
+    }
+
+    #[test]
+    fn test_neutral_fixation_is_one_in_n() {
+        let config = InvasionConfig {
+            rows: 3,
+            cols: 3,
+            noise: 0.1,
+            ..config()
+        };
+        let estimate = fixation_probability(&config, neutral(), 600, 500).unwrap();
+        assert_eq!(estimate.undecided, 0);
+        assert_eq!(estimate.fixated + estimate.lost, 600);
+        assert!(estimate.contains(1.0 / 9.0), "{:?}", estimate.interval);
+        assert!(estimate.interval.1 - estimate.interval.0 < 0.1);
+        assert!(estimate.fixation_times.is_sorted());
+    }
+
+    #[test]
+    fn test_favored_invader_fixates() {
+        let setup = InvaderSetup {
+            resident: Coop,
+            invader: Deflect,
+            seeding: Seeding::Single,
+        };
+        let estimate = fixation_probability(&config(), setup, 40, 100).unwrap();
+        assert!(estimate.probability > 0.9, "{:?}", estimate);
+        assert!(estimate.interval.0 > 0.8, "{:?}", estimate.interval);
+        assert_eq!(estimate.fixation_times.len(), estimate.fixated);
+    }
+
+    #[test]
+    fn test_settled_trials_stop_early() {
+        // Without noise nobody out-earns anyone in the middle of the grid, so most trials
+        // freeze with both strategies around.
+        let config = InvasionConfig {
+            noise: 0.0,
+            patience: Some(5),
+            ..config()
+        };
+        let report = invasion(Coop, Trusting, config).unwrap();
+        assert!(report
+            .replicates
+            .iter()
+            .any(|r| r.outcome == Outcome::Unresolved));
+        assert!(report.replicates.iter().all(|r| r.steps < 100));
+        let estimate = FixationEstimate::new(&report);
+        assert_eq!(estimate.undecided + estimate.fixated + estimate.lost, 10);
+    }
+
+    #[test]
+    fn test_wilson_interval() {
+        assert_eq!(wilson_interval(0, 0), (0.0, 1.0));
+        let (low, high) = wilson_interval(5, 10);
+        assert!((low - 0.2366).abs() < 1e-4 && (high - 0.7634).abs() < 1e-4);
+        assert_eq!(wilson_interval(0, 20).0, 0.0);
+        assert_eq!(wilson_interval(20, 20).1, 1.0);
+    }
+
+    #[test]
+    fn test_clusters_connect_diagonally() {
+        let env = EnvironmentBuilder::new()
+            .size(3, 3)
+            .agents(|c, _| {
+                let strategy = if c == (0, 0) || c == (1, 1) || c == (0, 2) {
+                    Coop
+                } else {
+                    Deflect
+                };
+                Agent::new(c, strategy)
+            })
+            .build()
+            .unwrap();
+        assert_eq!(clusters(&env, Coop), vec![3]);
+        assert_eq!(clusters(&env, Deflect), vec![6]);
+    }
+
+    fn tiny() -> RunConfig {
+        RunConfig {
+            rows: 6,
+            cols: 6,
+            steps: 15,
+            seed: 1,
+            ..RunConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_phase_diagram() {
+        let sweep = || {
+            phase_diagram(
+                SweepParam::Temptation,
+                &[3.5, 6.0],
+                SweepParam::Noise,
+                &[0.0, 0.3],
+                &tiny(),
+                2,
+            )
+            .unwrap()
+        };
+        let diagram = sweep();
+        assert_eq!(diagram.cooperation.len(), 2);
+        assert!(diagram.cooperation.iter().all(|row| row.len() == 2));
+        assert!(diagram
+            .cooperation
+            .iter()
+            .flatten()
+            .all(|c| (0.0..=1.0).contains(c)));
+        assert_eq!(diagram, sweep());
+        let cells: Vec<f64> = diagram.cooperation.concat();
+        assert!(
+            cells.iter().any(|&c| c != cells[0]),
+            "the sweep didn't vary anything: {:?}",
+            cells
+        );
+        let csv = diagram.to_csv();
+        assert!(csv.starts_with("noise\\temptation,3.5,6\n"), "{}", csv);
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(2).unwrap().starts_with("0.3,"));
+    }
+
+    #[test]
+    fn test_phase_diagram_rejects_invalid_points() {
+        let result = phase_diagram(
+            SweepParam::Noise,
+            &[0.5, 1.5],
+            SweepParam::Reward,
+            &[3.0],
+            &tiny(),
+            1,
+        );
+        assert_eq!(result, Err(Error::InvalidNoise(1.5)));
+        let result = phase_diagram(
+            SweepParam::MutationRate,
+            &[0.1, -0.1],
+            SweepParam::Reward,
+            &[3.0],
+            &tiny(),
+            1,
+        );
+        assert_eq!(result, Err(Error::InvalidMutation(-0.1)));
+    }
+
+    #[test]
+    fn test_phase_diagram_sweeps_mutation() {
+        let base = RunConfig {
+            noise: 0.1,
+            strategies: vec![GenerousTFT(Prob::new(0.3)), Deflect],
+            ..tiny()
+        };
+        assert_eq!(
+            SweepParam::MutationRate.apply(&base, 0.3).mutation_sigma,
+            0.3
+        );
+        let diagram = phase_diagram(
+            SweepParam::MutationRate,
+            &[0.0, 0.3],
+            SweepParam::Reward,
+            &[3.0],
+            &base,
+            2,
+        )
+        .unwrap();
+        assert!(diagram.cooperation[0]
+            .iter()
+            .all(|c| (0.0..=1.0).contains(c)));
+        let csv = diagram.to_csv();
+        assert!(csv.starts_with("reward\\mutation_sigma,0,0.3\n"), "{}", csv);
+    }
+
+    #[test]
+    fn test_linspace() {
+        assert_eq!(linspace(0.0, 1.0, 5), vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(linspace(2.0, 3.0, 1), vec![2.0]);
+        assert!(linspace(0.0, 1.0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = InvasionConfig {
+            noise: 2.0,
+            ..config()
+        };
+        assert_eq!(
+            invasion(Coop, Deflect, config),
+            Err(Error::InvalidNoise(2.0))
+        );
+    }
+}
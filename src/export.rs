@@ -0,0 +1,762 @@
+use std::collections::HashMap;
+
+use coop::snapshot::THUMBNAIL_SIZE;
+use coop::{Bookmarks, Metric, Snapshot, Strategy};
+use ratatui::{crossterm::event::KeyCode, style::Color};
+
+use crate::palette::Palette;
+
+/// Pixels per grid cell in exported PNG frames.
+pub const PNG_CELL_PX: usize = 8;
+/// Pixels per grid cell in exported GIF frames.
+pub const GIF_CELL_PX: usize = 4;
+/// Pixel size of a thumbnail cell in an overview GIF, which stands for a block of cells.
+pub const OVERVIEW_CELL_PX: usize = 12;
+/// Longest exported GIF; longer histories are sampled evenly down to this many frames.
+pub const MAX_GIF_FRAMES: usize = 500;
+/// Delay between GIF frames in hundredths of a second.
+pub const GIF_DELAY_CS: u16 = 5;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ExportKind {
+    Frame,
+    History,
+    Metrics,
+}
+
+/// Menu entries in display order.
+pub const EXPORT_KINDS: [ExportKind; 3] =
+    [ExportKind::Frame, ExportKind::History, ExportKind::Metrics];
+
+impl ExportKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportKind::Frame => "current frame → PNG",
+            ExportKind::History => "history → GIF",
+            ExportKind::Metrics => "metrics → CSV",
+        }
+    }
+
+    fn stem(self) -> &'static str {
+        match self {
+            ExportKind::Frame => "frame",
+            ExportKind::History => "history",
+            ExportKind::Metrics => "metrics",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportKind::Frame => "png",
+            ExportKind::History => "gif",
+            ExportKind::Metrics => "csv",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum MenuEvent {
+    Pending,
+    Cancelled,
+    Chosen(ExportKind),
+}
+
+/// The export menu opened with 'e'.
+#[derive(Clone, Debug, Default)]
+pub struct ExportMenu {
+    selected: usize,
+}
+
+impl ExportMenu {
+    pub fn selected(&self) -> ExportKind {
+        EXPORT_KINDS[self.selected]
+    }
+
+    /// Up/Down (or Tab) move the selection, Enter picks it, digits pick an entry directly and
+    /// Esc cancels.
+    pub fn handle_key(&mut self, code: KeyCode) -> MenuEvent {
+        let len = EXPORT_KINDS.len();
+        match code {
+            KeyCode::Esc => return MenuEvent::Cancelled,
+            KeyCode::Enter => return MenuEvent::Chosen(self.selected()),
+            KeyCode::Up | KeyCode::BackTab => self.selected = (self.selected + len - 1) % len,
+            KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % len,
+            KeyCode::Char(c) => {
+                if let Some(kind) = c
+                    .to_digit(10)
+                    .and_then(|d| (d as usize).checked_sub(1))
+                    .and_then(|i| EXPORT_KINDS.get(i))
+                {
+                    return MenuEvent::Chosen(*kind);
+                }
+            }
+            _ => {}
+        }
+        MenuEvent::Pending
+    }
+}
+
+/// File name for an export started `unix_secs` seconds after the epoch, e.g.
+/// "coop-frame-20240131-235959.png" (UTC).
+pub fn export_filename(kind: ExportKind, unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs = unix_secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "coop-{}-{:04}{:02}{:02}-{:02}{:02}{:02}.{}",
+        kind.stem(),
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        kind.extension()
+    )
+}
+
+/// Gregorian date of a day count since 1970-01-01 (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// An indexed-color image; each pixel is an index into `palette`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub palette: Vec<[u8; 3]>,
+    pub pixels: Vec<u8>,
+}
+
+/// Draws a snapshot with every cell as a `cell_px` square in its palette color.
+pub fn snapshot_image(snapshot: &Snapshot, cell_px: usize, palette: Palette) -> Image {
+    snapshot_frames([snapshot], cell_px, palette).remove(0)
+}
+
+/// Draws snapshots like `snapshot_image`, sharing one color table as a GIF needs: the builtin
+/// strategies' colors in order, then any other colors as they're first drawn.
+pub fn snapshot_frames<'a>(
+    snapshots: impl IntoIterator<Item = &'a Snapshot>,
+    cell_px: usize,
+    palette: Palette,
+) -> Vec<Image> {
+    let mut colors: Vec<[u8; 3]> = Strategy::ALL
+        .iter()
+        .map(|s| rgb(palette.color(*s)))
+        .collect();
+    let mut index = |s: Strategy| {
+        let color = rgb(palette.color(s));
+        match colors.iter().position(|c| *c == color) {
+            Some(i) => i as u8,
+            // An indexed image holds 256 colors; past that, fall back to the kind's.
+            None if colors.len() == 256 => s.index(),
+            None => {
+                colors.push(color);
+                (colors.len() - 1) as u8
+            }
+        }
+    };
+    let mut images: Vec<Image> = snapshots
+        .into_iter()
+        .map(|snapshot| {
+            let (rows, cols) = snapshot.dimensions();
+            let (width, height) = (cols * cell_px, rows * cell_px);
+            let mut pixels = Vec::with_capacity(width * height);
+            for row in snapshot.iter_rows() {
+                let line: Vec<u8> = row
+                    .iter()
+                    .flat_map(|s| std::iter::repeat_n(index(*s), cell_px))
+                    .collect();
+                for _ in 0..cell_px {
+                    pixels.extend_from_slice(&line);
+                }
+            }
+            Image {
+                width,
+                height,
+                palette: Vec::new(),
+                pixels,
+            }
+        })
+        .collect();
+    for image in &mut images {
+        image.palette = colors.clone();
+    }
+    images
+}
+
+/// The grids a GIF of `metrics` shows and their cell size in pixels: the full snapshots, or
+/// in overview mode, once any of them was dropped for its thumbnail, every step's thumbnail so
+/// the frames line up.
+pub fn gif_snapshots(metrics: &[&Metric]) -> (Vec<Snapshot>, usize) {
+    if metrics.iter().all(|m| !m.snapshot.is_empty()) {
+        let snapshots = metrics.iter().map(|m| m.snapshot.clone()).collect();
+        return (snapshots, GIF_CELL_PX);
+    }
+    let thumbnails = metrics
+        .iter()
+        .map(|m| {
+            m.thumbnail
+                .clone()
+                .unwrap_or_else(|| m.snapshot.thumbnail(THUMBNAIL_SIZE))
+        })
+        .collect();
+    (thumbnails, OVERVIEW_CELL_PX)
+}
+
+/// RGB value of a terminal color, using the xterm defaults for named colors.
+pub fn rgb(color: Color) -> [u8; 3] {
+    match color {
+        Color::Rgb(r, g, b) => [r, g, b],
+        Color::Black => [0, 0, 0],
+        Color::Red => [205, 0, 0],
+        Color::Green => [0, 205, 0],
+        Color::Yellow => [205, 205, 0],
+        Color::Blue => [0, 0, 238],
+        Color::Magenta => [205, 0, 205],
+        Color::Cyan => [0, 205, 205],
+        Color::White => [255, 255, 255],
+        _ => [128, 128, 128],
+    }
+}
+
+/// Indices of at most `max` frames spread evenly over `len`, always keeping the first and last.
+pub fn sample_frames(len: usize, max: usize) -> Vec<usize> {
+    if len <= max {
+        return (0..len).collect();
+    }
+    match max {
+        0 => return Vec::new(),
+        1 => return vec![len - 1],
+        _ => {}
+    }
+    (0..max).map(|i| i * (len - 1) / (max - 1)).collect()
+}
+
+/// One row per `(step, metric)`: cooperation, population per strategy and best score per
+/// strategy.
+pub fn metrics_csv<'a>(
+    rows: impl IntoIterator<Item = (usize, &'a Metric)>,
+    bookmarks: &Bookmarks,
+    burn_in: usize,
+) -> String {
+    let names: Vec<String> = Strategy::ALL
+        .iter()
+        .map(|s| s.name().to_lowercase())
+        .collect();
+    let mut csv = format!(
+        "step,coop_rate,coop_actions,{},{},bookmarked,burn_in\n",
+        names.join(","),
+        names
+            .iter()
+            .map(|n| format!("max_score_{}", n))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    for (step, metric) in rows {
+        let counts = Strategy::ALL.map(|s| metric.strategies.get(&s).copied().unwrap_or(0));
+        let scores = Strategy::ALL.map(|s| metric.max_score.get(&s).copied().unwrap_or(0.0));
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            step,
+            metric.coop_rate,
+            metric.coop_actions,
+            counts.map(|c| c.to_string()).join(","),
+            scores.map(|s| s.to_string()).join(","),
+            u8::from(bookmarks.contains(step)),
+            u8::from(step < burn_in)
+        ));
+    }
+    csv
+}
+
+/// Encodes an 8-bit indexed PNG. Pixel data is stored uncompressed (deflate "stored" blocks),
+/// which keeps the encoder small at the cost of larger files.
+pub fn encode_png(image: &Image) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(image.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(image.height as u32).to_be_bytes());
+    // Bit depth 8, color type 3 (indexed), default compression, filter and interlace.
+    ihdr.extend_from_slice(&[8, 3, 0, 0, 0]);
+    png_chunk(&mut png, b"IHDR", &ihdr);
+    png_chunk(&mut png, b"PLTE", &image.palette.concat());
+    let mut raw = Vec::with_capacity((image.width + 1) * image.height);
+    for row in image.pixels.chunks(image.width.max(1)) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65_521;
+        b = (b + a) % 65_521;
+    }
+    (b << 16) | a
+}
+
+/// Encodes a looping GIF animation. All frames share the first frame's size and palette.
+pub fn encode_gif(frames: &[Image], delay_cs: u16) -> Vec<u8> {
+    let (width, height, colors) = frames
+        .first()
+        .map_or((0, 0, &[][..]), |f| (f.width, f.height, &f.palette[..]));
+    // Color table size is 2^(bits); GIF needs at least 2 entries and LZW at least 2 bits.
+    let bits = (1..=8).find(|b| 1usize << b >= colors.len()).unwrap_or(8);
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend_from_slice(&(width as u16).to_le_bytes());
+    gif.extend_from_slice(&(height as u16).to_le_bytes());
+    gif.extend_from_slice(&[0x80 | ((bits - 1) << 4) | (bits - 1), 0, 0]);
+    for i in 0..1usize << bits {
+        gif.extend_from_slice(colors.get(i).unwrap_or(&[0, 0, 0]));
+    }
+    // NETSCAPE2.0 extension: loop forever.
+    gif.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+    let min_code_size = bits.max(2);
+    for frame in frames {
+        gif.extend_from_slice(&[0x21, 0xf9, 4, 0]);
+        gif.extend_from_slice(&delay_cs.to_le_bytes());
+        gif.extend_from_slice(&[0, 0]);
+        gif.push(0x2c);
+        gif.extend_from_slice(&[0, 0, 0, 0]);
+        gif.extend_from_slice(&(frame.width as u16).to_le_bytes());
+        gif.extend_from_slice(&(frame.height as u16).to_le_bytes());
+        gif.push(0);
+        gif.push(min_code_size);
+        for block in lzw_encode(&frame.pixels, min_code_size).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3b);
+    gif
+}
+
+/// Variable-width GIF LZW, starting with a clear code and resetting the table when it fills.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = min_code_size + 1;
+    let mut out = BitWriter::default();
+    out.write(clear, size);
+    let mut prefix: Option<u16> = None;
+    for &k in indices {
+        let Some(p) = prefix else {
+            prefix = Some(k as u16);
+            continue;
+        };
+        if let Some(&code) = table.get(&(p, k)) {
+            prefix = Some(code);
+            continue;
+        }
+        out.write(p, size);
+        if next == 4096 {
+            out.write(clear, size);
+            table.clear();
+            next = end + 1;
+            size = min_code_size + 1;
+        } else {
+            table.insert((p, k), next);
+            next += 1;
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+        }
+        prefix = Some(k as u16);
+    }
+    if let Some(p) = prefix {
+        out.write(p, size);
+    }
+    out.write(end, size);
+    out.finish()
+}
+
+/// Packs codes least significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    len: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.acc |= (code as u32) << self.len;
+        self.len += size;
+        while self.len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coop::Grid;
+    use std::collections::BTreeMap;
+    use Strategy::{Coop as C, Deflect as D};
+
+    /// Reference GIF LZW decoder used to check the encoder.
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1u16 << min_code_size;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..clear + 2).map(|i| vec![i as u8]).collect() };
+        let mut table = reset();
+        let mut size = min_code_size + 1;
+        let (mut acc, mut len, mut pos) = (0u32, 0u8, 0);
+        let mut prev: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+        loop {
+            while len < size {
+                acc |= (data[pos] as u32) << len;
+                pos += 1;
+                len += 8;
+            }
+            let code = (acc & ((1 << size) - 1)) as u16;
+            acc >>= size;
+            len -= size;
+            if code == clear {
+                table = reset();
+                size = min_code_size + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = match (table.get(code as usize), &prev) {
+                (Some(e), _) => e.clone(),
+                (None, Some(p)) => [&p[..], &p[..1]].concat(),
+                (None, None) => panic!("invalid code {}", code),
+            };
+            if let Some(p) = prev {
+                table.push([&p[..], &entry[..1]].concat());
+                if table.len() == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+            out.extend_from_slice(&entry);
+            prev = Some(entry);
+        }
+    }
+
+    /// Concatenated payload of the stored deflate blocks in a zlib stream.
+    fn unstore(zlib: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pos = 2;
+        loop {
+            let last = zlib[pos] & 1 == 1;
+            let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]) as usize;
+            out.extend_from_slice(&zlib[pos + 5..pos + 5 + len]);
+            pos += 5 + len;
+            if last {
+                return out;
+            }
+        }
+    }
+
+    #[test]
+    fn test_filename() {
+        assert_eq!(
+            export_filename(ExportKind::Frame, 0),
+            "coop-frame-19700101-000000.png"
+        );
+        assert_eq!(
+            export_filename(ExportKind::History, 1_706_745_599),
+            "coop-history-20240131-235959.gif"
+        );
+        // 2024-02-29 12:34:56, a leap day.
+        assert_eq!(
+            export_filename(ExportKind::Metrics, 1_709_210_096),
+            "coop-metrics-20240229-123456.csv"
+        );
+    }
+
+    #[test]
+    fn test_menu() {
+        let mut menu = ExportMenu::default();
+        assert_eq!(menu.selected(), ExportKind::Frame);
+        assert_eq!(menu.handle_key(KeyCode::Down), MenuEvent::Pending);
+        assert_eq!(menu.handle_key(KeyCode::Down), MenuEvent::Pending);
+        assert_eq!(menu.selected(), ExportKind::Metrics);
+        menu.handle_key(KeyCode::Down);
+        assert_eq!(menu.selected(), ExportKind::Frame);
+        menu.handle_key(KeyCode::Up);
+        assert_eq!(
+            menu.handle_key(KeyCode::Enter),
+            MenuEvent::Chosen(ExportKind::Metrics)
+        );
+        assert_eq!(menu.handle_key(KeyCode::Esc), MenuEvent::Cancelled);
+    }
+
+    #[test]
+    fn test_menu_digits() {
+        let mut menu = ExportMenu::default();
+        assert_eq!(
+            menu.handle_key(KeyCode::Char('2')),
+            MenuEvent::Chosen(ExportKind::History)
+        );
+        assert_eq!(menu.handle_key(KeyCode::Char('0')), MenuEvent::Pending);
+        assert_eq!(menu.handle_key(KeyCode::Char('4')), MenuEvent::Pending);
+        assert_eq!(menu.handle_key(KeyCode::Char('x')), MenuEvent::Pending);
+        assert_eq!(menu.selected(), ExportKind::Frame);
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_zlib_stored_splits_blocks() {
+        let data: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+        let zlib = zlib_stored(&data);
+        assert_eq!(unstore(&zlib), data);
+        assert_eq!(zlib[zlib.len() - 4..], adler32(&data).to_be_bytes());
+        assert!(unstore(&zlib_stored(&[])).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_image() {
+        let image = snapshot_image(&vec![vec![C, D]].into(), 2, Palette::Classic);
+        assert_eq!((image.width, image.height), (4, 2));
+        let (c, d) = (C.index(), D.index());
+        assert_eq!(image.pixels, vec![c, c, d, d, c, c, d, d]);
+        assert_eq!(image.palette.len(), Strategy::ALL.len());
+    }
+
+    #[test]
+    fn test_snapshot_frames_share_colors() {
+        let variant = |m| Strategy::GenerousTFT(coop::Prob::from_millionths(m));
+        let (a, b) = (variant(10_001), variant(20_002));
+        let snapshots: [Snapshot; 2] = [vec![vec![C, a]].into(), vec![vec![b, a]].into()];
+        let frames = snapshot_frames(&snapshots, 1, Palette::Colorblind);
+        assert_eq!(frames[0].palette, frames[1].palette);
+        assert_eq!(frames[0].palette.len(), Strategy::ALL.len() + 2);
+        let colors = &frames[0].palette;
+        let (pa, pb) = (frames[1].pixels[1], frames[1].pixels[0]);
+        assert_eq!(frames[0].pixels, vec![C.index(), pa]);
+        assert_ne!(colors[usize::from(pa)], colors[usize::from(pb)]);
+        assert_eq!(colors[usize::from(pa)], rgb(Palette::Colorblind.color(a)));
+    }
+
+    #[test]
+    fn test_png_layout() {
+        let image = snapshot_image(&vec![vec![C, D], vec![D, C]].into(), 1, Palette::Classic);
+        let png = encode_png(&image);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 2]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+        // Walk the chunks, checking each CRC and collecting the image data.
+        let (mut pos, mut idat) = (8, Vec::new());
+        while pos < png.len() {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let body = &png[pos + 4..pos + 8 + len];
+            let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(crc32(body), crc);
+            if &body[..4] == b"IDAT" {
+                idat.extend_from_slice(&body[4..]);
+            }
+            pos += 12 + len;
+        }
+        let (c, d) = (C.index(), D.index());
+        assert_eq!(unstore(&idat), vec![0, c, d, 0, d, c]);
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![3],
+            vec![0; 10_000],
+            (0..50_000u32).map(|i| ((i * 7919) % 4) as u8).collect(),
+            (0..50_000u32).map(|i| ((i / 3) % 4) as u8).collect(),
+        ];
+        for input in inputs {
+            assert_eq!(lzw_decode(&lzw_encode(&input, 2), 2), input);
+        }
+    }
+
+    #[test]
+    fn test_gif_layout() {
+        let frames = vec![
+            snapshot_image(&vec![vec![C, D]].into(), 1, Palette::Classic),
+            snapshot_image(&vec![vec![D, D]].into(), 1, Palette::Classic),
+        ];
+        let gif = encode_gif(&frames, 7);
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[2, 0, 1, 0]);
+        // Eleven strategies need a 16-entry (4-bit) global color table.
+        assert_eq!(gif[10], 0x80 | 0x33);
+        assert_eq!(*gif.last().unwrap(), 0x3b);
+        assert_eq!(
+            gif.windows(4).filter(|w| *w == [0x21, 0xf9, 4, 0]).count(),
+            2
+        );
+        assert!(gif.windows(2).any(|w| w == 7u16.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_sample_frames() {
+        assert_eq!(sample_frames(3, 10), vec![0, 1, 2]);
+        assert_eq!(sample_frames(11, 3), vec![0, 5, 10]);
+        let sampled = sample_frames(100_000, MAX_GIF_FRAMES);
+        assert_eq!(sampled.len(), MAX_GIF_FRAMES);
+        assert_eq!(sampled.last(), Some(&99_999));
+        assert_eq!(sample_frames(5, 1), vec![4]);
+        assert!(sample_frames(5, 0).is_empty());
+    }
+
+    fn metric() -> Metric {
+        Metric {
+            strategies: BTreeMap::from([(C, 3), (D, 1)]),
+            max_score: BTreeMap::from([(C, 12.0)]),
+            avg_score: Default::default(),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 0,
+            coop_actions: 6,
+            coop_rate: 0.75,
+            snapshot: Default::default(),
+            founder_lineages: 0,
+            population: 0,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: Grid::default(),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn test_gif_overview() {
+        let full = Snapshot::from(vec![vec![C; 64]; 64]);
+        let thumbnail = full.thumbnail(THUMBNAIL_SIZE);
+        let kept = Metric {
+            snapshot: full.clone(),
+            thumbnail: Some(thumbnail.clone()),
+            ..metric()
+        };
+        let (snapshots, cell_px) = gif_snapshots(&[&kept, &kept]);
+        assert_eq!((snapshots, cell_px), (vec![full.clone(); 2], GIF_CELL_PX));
+        let dropped = Metric {
+            snapshot: Snapshot::default(),
+            ..kept.clone()
+        };
+        let untracked = Metric {
+            snapshot: full,
+            ..metric()
+        };
+        let (snapshots, cell_px) = gif_snapshots(&[&dropped, &kept, &untracked]);
+        assert_eq!(cell_px, OVERVIEW_CELL_PX);
+        assert_eq!(snapshots, vec![thumbnail; 3]);
+    }
+
+    #[test]
+    fn test_metrics_csv() {
+        let metric = metric();
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.toggle(1);
+        let csv = metrics_csv([(0, &metric), (1, &metric)], &bookmarks, 1);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "step,coop_rate,coop_actions,deflect,tictoc,coop,random,choosy,trusting,\
+                 deceiver,wary,generoustft,randomp,memoryone,max_score_deflect,\
+                 max_score_tictoc,max_score_coop,max_score_random,max_score_choosy,\
+                 max_score_trusting,max_score_deceiver,max_score_wary,max_score_generoustft,\
+                 max_score_randomp,max_score_memoryone,bookmarked,burn_in",
+                "0,0.75,6,1,0,3,0,0,0,0,0,0,0,0,0,0,12,0,0,0,0,0,0,0,0,0,1",
+                "1,0.75,6,1,0,3,0,0,0,0,0,0,0,0,0,0,12,0,0,0,0,0,0,0,0,1,0",
+            ]
+        );
+    }
+}
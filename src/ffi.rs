@@ -0,0 +1,294 @@
+//! A C ABI for driving a simulation from other languages; cbindgen generates its declarations
+//! in `include/coop.h` from this file. Build the `cdylib` with
+//! `cargo build --release --features ffi`.
+//!
+//! Every function accepts null pointers and reports them instead of crashing, and a panic
+//! inside the library is caught at the boundary and reported as [`COOP_ERR_PANIC`] rather
+//! than unwinding into the caller.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::builder::EnvironmentBuilder;
+use crate::env::Environment;
+use crate::snapshot::VACANT_CELL;
+
+/// The call succeeded.
+pub const COOP_OK: i32 = 0;
+/// A required pointer was null.
+pub const COOP_ERR_NULL: i32 = -1;
+/// An argument was out of range, e.g. a NaN noise.
+pub const COOP_ERR_INVALID: i32 = -2;
+/// The library panicked; the environment may be left mid-step and should be freed.
+pub const COOP_ERR_PANIC: i32 = -3;
+/// The byte [`coop_env_snapshot`] writes for a cell without an agent.
+// A literal rather than `VACANT_CELL`, which cbindgen can't resolve.
+pub const COOP_VACANT: u8 = 255;
+const _: () = assert!(COOP_VACANT == VACANT_CELL);
+
+/// A simulation owned by the caller, created with [`coop_env_new`] and released with
+/// [`coop_env_free`].
+pub struct CoopEnv {
+    env: Environment,
+}
+
+/// The scalar metrics of one step. The other fields are only meaningful when `status` is
+/// [`COOP_OK`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CoopMetricHandle {
+    pub status: i32,
+    /// Zero-based number of the step.
+    pub step: u64,
+    pub coop_actions: i32,
+    /// Fraction of the step's actions that cooperated, in `[0, 1]`.
+    pub coop_rate: f32,
+    /// Agents alive after the step.
+    pub population: u64,
+}
+
+impl CoopMetricHandle {
+    fn error(status: i32) -> CoopMetricHandle {
+        CoopMetricHandle {
+            status,
+            ..CoopMetricHandle::default()
+        }
+    }
+}
+
+/// Runs `f`, turning a panic into `on_panic`.
+fn guard<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// A seeded simulation of random Deflect and TicToc agents, or null if the grid is empty,
+/// `noise` isn't in `[0, 1]`, or construction panicked.
+#[no_mangle]
+pub extern "C" fn coop_env_new(rows: usize, cols: usize, noise: f32, seed: u64) -> *mut CoopEnv {
+    guard(ptr::null_mut(), || {
+        match EnvironmentBuilder::new()
+            .size(rows, cols)
+            .noise(noise)
+            .seed(seed)
+            .build()
+        {
+            Ok(env) => Box::into_raw(Box::new(CoopEnv { env })),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+/// Takes one step.
+///
+/// # Safety
+/// `env` must be null or a live pointer from [`coop_env_new`], not used concurrently.
+#[no_mangle]
+pub unsafe extern "C" fn coop_env_step(env: *mut CoopEnv) -> CoopMetricHandle {
+    // SAFETY: the caller promises `env` is null or live and unaliased.
+    let Some(env) = (unsafe { env.as_mut() }) else {
+        return CoopMetricHandle::error(COOP_ERR_NULL);
+    };
+    guard(CoopMetricHandle::error(COOP_ERR_PANIC), || {
+        let metric = env.env.step();
+        CoopMetricHandle {
+            status: COOP_OK,
+            step: metric.step_index as u64,
+            coop_actions: metric.coop_actions,
+            coop_rate: metric.coop_rate,
+            population: metric.population as u64,
+        }
+    })
+}
+
+/// Writes the current strategy number of every cell (see `Strategy::index`) to `out_buf` in
+/// row-major order, or [`COOP_VACANT`] for a cell without an agent, if it has room for
+/// `rows * cols` bytes. Returns the number of cells either way, so a call with a null buffer
+/// asks for the size; returns 0 for a null `env` or a panic.
+///
+/// # Safety
+/// `env` must be null or a live pointer from [`coop_env_new`]; `out_buf` must be null or
+/// valid for writes of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn coop_env_snapshot(
+    env: *const CoopEnv,
+    out_buf: *mut u8,
+    len: usize,
+) -> usize {
+    // SAFETY: the caller promises `env` is null or live.
+    let Some(env) = (unsafe { env.as_ref() }) else {
+        return 0;
+    };
+    guard(0, || {
+        let (rows, cols) = env.env.dimensions();
+        let cells = rows * cols;
+        if out_buf.is_null() || len < cells {
+            return cells;
+        }
+        // SAFETY: the caller promises `out_buf` is valid for `len >= cells` bytes.
+        let out = unsafe { std::slice::from_raw_parts_mut(out_buf, cells) };
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = env
+                .env
+                .agent_at((i / cols, i % cols))
+                .map_or(COOP_VACANT, |agent| agent.strategy.index());
+        }
+        cells
+    })
+}
+
+/// Sets the noise, clamped to `[0, 1]`.
+///
+/// # Safety
+/// `env` must be null or a live pointer from [`coop_env_new`], not used concurrently.
+#[no_mangle]
+pub unsafe extern "C" fn coop_env_set_noise(env: *mut CoopEnv, noise: f32) -> i32 {
+    // SAFETY: the caller promises `env` is null or live and unaliased.
+    let Some(env) = (unsafe { env.as_mut() }) else {
+        return COOP_ERR_NULL;
+    };
+    if noise.is_nan() {
+        return COOP_ERR_INVALID;
+    }
+    guard(COOP_ERR_PANIC, || {
+        env.env.set_noise(noise);
+        COOP_OK
+    })
+}
+
+/// Releases a simulation; null is ignored.
+///
+/// # Safety
+/// `env` must be null or a pointer from [`coop_env_new`] that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn coop_env_free(env: *mut CoopEnv) {
+    if !env.is_null() {
+        // SAFETY: the caller promises `env` came from `Box::into_raw` in `coop_env_new` and
+        // is freed only once.
+        guard((), || drop(unsafe { Box::from_raw(env) }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Strategy;
+    use crate::env::EnergyConfig;
+
+    #[test]
+    fn test_lifecycle() {
+        let env = coop_env_new(3, 4, 0.1, 42);
+        assert!(!env.is_null());
+        unsafe {
+            let first = coop_env_step(env);
+            assert_eq!(
+                (first.status, first.step, first.population),
+                (COOP_OK, 0, 12)
+            );
+            assert!((0.0..=1.0).contains(&first.coop_rate));
+            assert_eq!(coop_env_step(env).step, 1);
+
+            assert_eq!(coop_env_snapshot(env, ptr::null_mut(), 0), 12);
+            let mut cells = [0xff; 12];
+            assert_eq!(coop_env_snapshot(env, cells.as_mut_ptr(), cells.len()), 12);
+            assert!(cells.iter().all(|&i| Strategy::from_index(i).is_some()));
+
+            assert_eq!(coop_env_set_noise(env, 2.0), COOP_OK);
+            assert_eq!((*env).env.noise(), 1.0);
+            coop_env_free(env);
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_run() {
+        let run = || unsafe {
+            let env = coop_env_new(5, 5, 0.2, 9);
+            for _ in 0..5 {
+                coop_env_step(env);
+            }
+            let mut cells = [0; 25];
+            coop_env_snapshot(env, cells.as_mut_ptr(), cells.len());
+            coop_env_free(env);
+            cells
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_snapshot_marks_vacant_cells() {
+        // Living costs more than defectors earn, so they starve and leave their cells empty.
+        let mut env = CoopEnv {
+            env: EnvironmentBuilder::new()
+                .size(5, 6)
+                .seed(3)
+                .energy(EnergyConfig {
+                    living_cost: 20.0,
+                    ..EnergyConfig::default()
+                })
+                .build()
+                .unwrap(),
+        };
+        unsafe {
+            coop_env_step(&mut env);
+            let mut cells = [0; 30];
+            assert_eq!(coop_env_snapshot(&env, cells.as_mut_ptr(), cells.len()), 30);
+            assert!(env.env.iter_agents().count() < 30);
+            for (i, &byte) in cells.iter().enumerate() {
+                let coord = (i / 6, i % 6);
+                match env.env.agent_at(coord) {
+                    Some(agent) => assert_eq!(byte, agent.strategy.index()),
+                    None => assert_eq!(byte, COOP_VACANT),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_paths() {
+        assert!(coop_env_new(0, 4, 0.1, 1).is_null());
+        assert!(coop_env_new(3, 4, 1.5, 1).is_null());
+        assert!(coop_env_new(3, 4, f32::NAN, 1).is_null());
+        unsafe {
+            assert_eq!(coop_env_step(ptr::null_mut()).status, COOP_ERR_NULL);
+            assert_eq!(coop_env_snapshot(ptr::null(), ptr::null_mut(), 0), 0);
+            assert_eq!(coop_env_set_noise(ptr::null_mut(), 0.5), COOP_ERR_NULL);
+            coop_env_free(ptr::null_mut());
+
+            let env = coop_env_new(2, 2, 0.0, 1);
+            // Too small a buffer is left untouched.
+            let mut cells = [0xff; 3];
+            assert_eq!(coop_env_snapshot(env, cells.as_mut_ptr(), cells.len()), 4);
+            assert_eq!(cells, [0xff; 3]);
+            assert_eq!(coop_env_set_noise(env, f32::NAN), COOP_ERR_INVALID);
+            coop_env_free(env);
+        }
+    }
+
+    #[test]
+    fn test_panics_become_errors() {
+        assert_eq!(guard(COOP_ERR_PANIC, || panic!("boom")), COOP_ERR_PANIC);
+        assert_eq!(guard(COOP_ERR_PANIC, || COOP_OK), COOP_OK);
+    }
+
+    #[test]
+    fn test_header_is_generated() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+        let mut generated = Vec::new();
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(dir.join("src/ffi.rs"))
+            .generate()
+            .unwrap()
+            .write(&mut generated);
+        let generated = String::from_utf8(generated).unwrap();
+        let path = dir.join("include/coop.h");
+        if std::env::var_os("REGEN_HEADER").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            generated,
+            "coop.h is stale; rerun with REGEN_HEADER=1"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Who earns off whom: the payoffs of a step split by the strategies on both sides of every
+//! game, which shows exploitation that per-strategy scores average away.
+
+use std::fmt;
+
+use crate::agent::Strategy;
+
+const KINDS: usize = Strategy::ALL.len();
+
+/// Payoffs earned in one step by agents of each strategy kind in games against agents of each
+/// kind, e.g. all that Deflect agents earned from their Coop neighbors. Every played game
+/// adds its payoff once for each side; games refused under partner choice add nothing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PayoffFlow {
+    /// Row-major by the earning kind's index, then the opponent's.
+    payoffs: Vec<f64>,
+    games: Vec<usize>,
+}
+
+impl Default for PayoffFlow {
+    fn default() -> Self {
+        PayoffFlow {
+            payoffs: vec![0.0; KINDS * KINDS],
+            games: vec![0; KINDS * KINDS],
+        }
+    }
+}
+
+impl PayoffFlow {
+    /// What agents of `from`'s kind earned playing agents of `to`'s kind.
+    pub fn get(&self, from: Strategy, to: Strategy) -> f64 {
+        self.payoffs[index(from, to)]
+    }
+
+    /// How many games agents of `from`'s kind played against agents of `to`'s kind.
+    pub fn games(&self, from: Strategy, to: Strategy) -> usize {
+        self.games[index(from, to)]
+    }
+
+    /// Everything earned in the step.
+    pub fn total(&self) -> f64 {
+        self.payoffs.iter().sum()
+    }
+
+    /// The kinds that played at least one game, in `Strategy::ALL` order.
+    pub fn strategies(&self) -> Vec<Strategy> {
+        Strategy::ALL
+            .into_iter()
+            .filter(|&s| {
+                Strategy::ALL
+                    .into_iter()
+                    .any(|t| self.games(s, t) > 0 || self.games(t, s) > 0)
+            })
+            .collect()
+    }
+
+    pub(crate) fn add(&mut self, from: Strategy, to: Strategy, payoff: f64) {
+        let i = index(from, to);
+        self.payoffs[i] += payoff;
+        self.games[i] += 1;
+    }
+
+    /// The full matrix with a row per earning kind and a column per opponent kind, headed by
+    /// lowercase strategy names.
+    pub fn to_csv(&self) -> String {
+        let names: Vec<String> = Strategy::ALL
+            .iter()
+            .map(|s| s.name().to_lowercase())
+            .collect();
+        let mut csv = format!("from,{}\n", names.join(","));
+        for (from, name) in Strategy::ALL.into_iter().zip(&names) {
+            let row: Vec<String> = Strategy::ALL
+                .into_iter()
+                .map(|to| self.get(from, to).to_string())
+                .collect();
+            csv.push_str(&format!("{},{}\n", name, row.join(",")));
+        }
+        csv
+    }
+}
+
+fn index(from: Strategy, to: Strategy) -> usize {
+    usize::from(from.index()) * KINDS + usize::from(to.index())
+}
+
+/// A table of the kinds that played, earners down the side and opponents across the top.
+impl fmt::Display for PayoffFlow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shown = self.strategies();
+        let width = shown
+            .iter()
+            .map(|s| s.name().len())
+            .max()
+            .unwrap_or(0)
+            .max(9);
+        write!(f, "{:<width$}", "earns\\vs")?;
+        for s in &shown {
+            write!(f, " {:>width$}", s.name())?;
+        }
+        for &from in &shown {
+            write!(f, "\n{:<width$}", from.name())?;
+            for &to in &shown {
+                write!(f, " {:>width$.1}", self.get(from, to))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::builder::EnvironmentBuilder;
+    use crate::env::{Metric, Payoffs};
+    use crate::neighbors::Neighborhood;
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    /// One step of a 2x2 grid with a Deflect in the top left corner and Coops elsewhere.
+    fn corner_deflector(neighborhood: Neighborhood) -> Metric {
+        EnvironmentBuilder::new()
+            .size(2, 2)
+            .payoffs(Payoffs {
+                reward: 3.0,
+                temptation: 5.0,
+                sucker: -1.0,
+                punishment: 1.0,
+            })
+            .interaction_neighborhood(neighborhood)
+            .payoff_flow(true)
+            .agents(|c, _| Agent::new(c, if c == (0, 0) { D } else { C }))
+            .build()
+            .unwrap()
+            .step()
+    }
+
+    #[test]
+    fn test_flow_by_hand() {
+        // Everyone plays everyone else: the deflector exploits three Coops, who also play
+        // each other in six games.
+        let flow = corner_deflector(Neighborhood::Moore(1))
+            .payoff_flow
+            .unwrap();
+        assert_eq!((flow.get(D, C), flow.games(D, C)), (15.0, 3));
+        assert_eq!((flow.get(C, D), flow.games(C, D)), (-3.0, 3));
+        assert_eq!((flow.get(C, C), flow.games(C, C)), (18.0, 6));
+        assert_eq!((flow.get(D, D), flow.games(D, D)), (0.0, 0));
+        assert_eq!(flow.total(), 30.0);
+        assert_eq!(flow.strategies(), vec![D, C]);
+        // Side neighbors only: the deflector and the opposite corner each meet two Coops.
+        let flow = corner_deflector(Neighborhood::VonNeumann(1))
+            .payoff_flow
+            .unwrap();
+        assert_eq!((flow.get(D, C), flow.games(D, C)), (10.0, 2));
+        assert_eq!((flow.get(C, D), flow.games(C, D)), (-2.0, 2));
+        assert_eq!((flow.get(C, C), flow.games(C, C)), (12.0, 4));
+        assert_eq!(flow.get(T, C), 0.0);
+    }
+
+    #[test]
+    fn test_flow_adds_up_to_scores() {
+        let mut env = EnvironmentBuilder::new()
+            .size(5, 5)
+            .seed(8)
+            .noise(0.3)
+            .payoff_flow(true)
+            .strategies(&[C, D, T, Strategy::Random])
+            .build()
+            .unwrap();
+        let metric = env.step();
+        let flow = metric.payoff_flow.unwrap();
+        let scores: f64 = metric.scores.cells().iter().sum();
+        assert!((flow.total() - scores).abs() < 1e-9);
+        let games: usize = Strategy::ALL
+            .into_iter()
+            .flat_map(|s| Strategy::ALL.map(|t| flow.games(s, t)))
+            .sum();
+        // 40 pairs of side neighbors and 32 of diagonal ones, each played from both sides.
+        assert_eq!(games, 144);
+        env.set_payoff_flow(false);
+        assert_eq!(env.step().payoff_flow, None);
+    }
+
+    #[test]
+    fn test_flow_text() {
+        let flow = corner_deflector(Neighborhood::Moore(1))
+            .payoff_flow
+            .unwrap();
+        let csv = flow.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), Strategy::ALL.len() + 1);
+        assert!(lines[0].starts_with("from,deflect,tictoc,coop,"));
+        assert!(lines[1].starts_with("deflect,0,0,15,"));
+        assert!(lines[3].starts_with("coop,-3,0,18,"));
+        assert_eq!(
+            flow.to_string(),
+            "earns\\vs    Deflect      Coop\n\
+             Deflect         0.0      15.0\n\
+             Coop           -3.0      18.0"
+        );
+    }
+}
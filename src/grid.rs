@@ -0,0 +1,262 @@
+use std::ops::Index;
+use std::sync::Arc;
+
+/// A value per cell of a `rows x cols` grid, stored in one row-major allocation shared
+/// between clones, so copying a grid is a reference count bump.
+///
+/// A grid without cells is always `0x0`, however it was built.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Grid<T> {
+    cells: Arc<[T]>,
+    rows: usize,
+    cols: usize,
+}
+
+impl<T> Default for Grid<T> {
+    fn default() -> Self {
+        Grid {
+            cells: Arc::new([]),
+            rows: 0,
+            cols: 0,
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    /// `cells` in row-major order; panics unless there are `rows * cols` of them.
+    pub fn new(rows: usize, cols: usize, cells: impl Into<Arc<[T]>>) -> Grid<T> {
+        let cells = cells.into();
+        assert_eq!(
+            cells.len(),
+            rows * cols,
+            "a {}x{} grid needs {} cells",
+            rows,
+            cols,
+            rows * cols
+        );
+        if cells.is_empty() {
+            return Grid::default();
+        }
+        Grid { cells, rows, cols }
+    }
+
+    /// A grid holding `f(row, col)` in every cell.
+    pub fn from_fn(rows: usize, cols: usize, mut f: impl FnMut(usize, usize) -> T) -> Grid<T> {
+        let cells: Vec<T> = (0..rows * cols).map(|i| f(i / cols, i % cols)).collect();
+        Grid::new(rows, cols, cells)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// `(rows, cols)`.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The value at `(row, col)`, or `None` outside the grid.
+    pub fn get(&self, row: usize, col: usize) -> Option<&T> {
+        (row < self.rows && col < self.cols).then(|| &self.cells[row * self.cols + col])
+    }
+
+    /// The values of `row`, or `None` outside the grid.
+    pub fn row(&self, row: usize) -> Option<&[T]> {
+        (row < self.rows).then(|| &self.cells[row * self.cols..][..self.cols])
+    }
+
+    /// Every row, top to bottom.
+    pub fn iter_rows(&self) -> std::slice::Chunks<'_, T> {
+        self.cells.chunks(self.cols.max(1))
+    }
+
+    /// Every cell with its `(row, col)`, in row-major order.
+    pub fn iter_with_coords(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let cols = self.cols.max(1);
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(i, v)| ((i / cols, i % cols), v))
+    }
+
+    /// Every cell in row-major order.
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// Every cell in row-major order, for writing; copies them first if another grid shares
+    /// them.
+    pub(crate) fn cells_mut(&mut self) -> &mut [T]
+    where
+        T: Clone,
+    {
+        Arc::make_mut(&mut self.cells)
+    }
+
+    /// A grid of the same shape holding `f` of every value.
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        let cells: Vec<U> = self.cells.iter().map(f).collect();
+        Grid::new(self.rows, self.cols, cells)
+    }
+}
+
+/// `grid[(row, col)]`; panics outside the grid, like slice indexing.
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        assert!(
+            row < self.rows && col < self.cols,
+            "cell ({}, {}) is outside a {}x{} grid",
+            row,
+            col,
+            self.rows,
+            self.cols
+        );
+        &self.cells[row * self.cols + col]
+    }
+}
+
+/// Panics unless every row has the same length.
+impl<T> From<Vec<Vec<T>>> for Grid<T> {
+    fn from(rows: Vec<Vec<T>>) -> Grid<T> {
+        let cols = rows.first().map_or(0, |r| r.len());
+        assert!(
+            rows.iter().all(|r| r.len() == cols),
+            "grid rows must all have the same length"
+        );
+        let (num_rows, cells) = (rows.len(), rows.into_iter().flatten().collect::<Vec<T>>());
+        Grid::new(num_rows, cols, cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_by_three() -> Grid<u32> {
+        Grid::from(vec![vec![1, 2, 3], vec![4, 5, 6]])
+    }
+
+    #[test]
+    fn test_indexing() {
+        let grid = two_by_three();
+        assert_eq!(grid.dimensions(), (2, 3));
+        assert_eq!(grid[(0, 1)], 2);
+        assert_eq!(grid[(1, 0)], 4);
+        assert_eq!(grid.get(1, 2), Some(&6));
+        assert_eq!(grid.get(2, 0), None);
+        assert_eq!(grid.get(0, 3), None);
+        assert_eq!(grid.row(1), Some(&[4, 5, 6][..]));
+        assert_eq!(grid.row(2), None);
+        assert_eq!(grid.cells(), &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside a 2x3 grid")]
+    fn test_index_out_of_bounds() {
+        let _ = two_by_three()[(2, 0)];
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let grid = Grid::from_fn(2, 3, |r, c| r * 10 + c);
+        assert_eq!(grid, Grid::from(vec![vec![0, 1, 2], vec![10, 11, 12]]));
+        let mut calls = Vec::new();
+        Grid::from_fn(2, 2, |r, c| calls.push((r, c)));
+        assert_eq!(calls, [(0, 0), (0, 1), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_iteration() {
+        let grid = two_by_three();
+        let rows: Vec<&[u32]> = grid.iter_rows().collect();
+        assert_eq!(rows, vec![&[1, 2, 3][..], &[4, 5, 6]]);
+        let cells: Vec<((usize, usize), u32)> =
+            grid.iter_with_coords().map(|(c, &v)| (c, v)).collect();
+        assert_eq!(
+            cells,
+            [
+                ((0, 0), 1),
+                ((0, 1), 2),
+                ((0, 2), 3),
+                ((1, 0), 4),
+                ((1, 1), 5),
+                ((1, 2), 6)
+            ]
+        );
+        for ((r, c), v) in grid.iter_with_coords() {
+            assert_eq!(grid[(r, c)], *v);
+        }
+    }
+
+    #[test]
+    fn test_map() {
+        let halves = two_by_three().map(|&v| f64::from(v) / 2.0);
+        assert_eq!(halves.dimensions(), (2, 3));
+        assert_eq!(halves[(1, 2)], 3.0);
+        assert!(Grid::<u32>::default().map(|v| v + 1).is_empty());
+    }
+
+    #[test]
+    fn test_single_row_and_column() {
+        let row = Grid::from(vec![vec!['a', 'b', 'c']]);
+        assert_eq!(row.dimensions(), (1, 3));
+        assert_eq!(row.iter_rows().count(), 1);
+        assert_eq!(row.get(0, 2), Some(&'c'));
+        assert_eq!(row.get(1, 0), None);
+        let column = Grid::from(vec![vec!['a'], vec!['b'], vec!['c']]);
+        assert_eq!(column.dimensions(), (3, 1));
+        let rows: Vec<&[char]> = column.iter_rows().collect();
+        assert_eq!(rows, vec![&['a'][..], &['b'], &['c']]);
+        let coords: Vec<(usize, usize)> = column.iter_with_coords().map(|(c, _)| c).collect();
+        assert_eq!(coords, [(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(column.get(0, 1), None);
+        let one = Grid::from_fn(1, 1, |_, _| 7);
+        assert_eq!((one.dimensions(), one[(0, 0)]), ((1, 1), 7));
+    }
+
+    #[test]
+    fn test_empty() {
+        for empty in [
+            Grid::<u8>::default(),
+            Grid::from(vec![]),
+            Grid::from(vec![vec![], vec![]]),
+            Grid::from_fn(0, 5, |_, _| 0),
+            Grid::from_fn(5, 0, |_, _| 0),
+        ] {
+            assert!(empty.is_empty());
+            assert_eq!(empty.dimensions(), (0, 0));
+            assert_eq!(empty.iter_rows().count(), 0);
+            assert_eq!(empty.iter_with_coords().count(), 0);
+            assert_eq!(empty.get(0, 0), None);
+        }
+    }
+
+    #[test]
+    fn test_clones_share_cells() {
+        let grid = two_by_three();
+        let copy = grid.clone();
+        assert!(std::ptr::eq(grid.cells(), copy.cells()));
+    }
+
+    #[test]
+    #[should_panic(expected = "needs 6 cells")]
+    fn test_wrong_cell_count() {
+        let _ = Grid::new(2, 3, vec![1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "same length")]
+    fn test_ragged_rows() {
+        let _ = Grid::from(vec![vec![1, 2], vec![3]]);
+    }
+}
@@ -0,0 +1,91 @@
+//! Group structure for multi-level selection.
+//!
+//! The grid is tiled into equal rectangular groups. Agents still play and imitate their
+//! neighbors as usual, across group boundaries too, but every `period` steps the groups
+//! compete: the group that earned the least since the last competition is replaced by a copy
+//! of the one that earned the most.
+
+use crate::agent::Coord;
+
+/// Equal `rows` x `cols` tiles that compete every `period` steps. Tiles are numbered in
+/// row-major order, and must cover the grid exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Groups {
+    pub rows: usize,
+    pub cols: usize,
+    /// Steps between competitions.
+    pub period: usize,
+}
+
+impl Groups {
+    pub fn new(rows: usize, cols: usize, period: usize) -> Groups {
+        Groups { rows, cols, period }
+    }
+
+    /// Whether the tiles are non-empty, cover a `dimensions` grid exactly and compete at some
+    /// point.
+    pub fn fits(&self, (rows, cols): (usize, usize)) -> bool {
+        self.rows > 0
+            && self.cols > 0
+            && self.period > 0
+            && rows % self.rows == 0
+            && cols % self.cols == 0
+    }
+
+    /// Number of groups on a `dimensions` grid.
+    pub fn count(&self, (rows, cols): (usize, usize)) -> usize {
+        (rows / self.rows) * (cols / self.cols)
+    }
+
+    /// The group `coord` belongs to on a grid `cols` wide.
+    pub fn of(&self, (row, col): Coord, cols: usize) -> usize {
+        (row / self.rows) * (cols / self.cols) + col / self.cols
+    }
+
+    /// The top-left cell of `group` on a grid `cols` wide.
+    pub fn origin(&self, group: usize, cols: usize) -> Coord {
+        let across = cols / self.cols;
+        ((group / across) * self.rows, (group % across) * self.cols)
+    }
+
+    /// Whether `coord` lies on the top or left edge of its group, where a boundary is drawn.
+    pub fn on_boundary(&self, (row, col): Coord) -> bool {
+        row % self.rows == 0 || col % self.cols == 0
+    }
+}
+
+/// How one group did in a step.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GroupMetric {
+    /// Payoff its agents earned this step.
+    pub total_payoff: f64,
+    /// Fraction of its agents' intended actions this step that were cooperative.
+    pub coop_rate: f32,
+    /// The group it was replaced by a copy of after this step's competition, if it lost one.
+    pub replaced_by: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles() {
+        let groups = Groups::new(2, 3, 5);
+        assert!(groups.fits((4, 9)));
+        assert!(!groups.fits((5, 9)));
+        assert!(!Groups::new(2, 3, 0).fits((4, 9)));
+        assert!(!Groups::new(0, 3, 5).fits((4, 9)));
+        assert_eq!(groups.count((4, 9)), 6);
+        assert_eq!(groups.of((0, 0), 9), 0);
+        assert_eq!(groups.of((1, 5), 9), 1);
+        assert_eq!(groups.of((2, 8), 9), 5);
+        for group in 0..6 {
+            assert_eq!(groups.of(groups.origin(group, 9), 9), group);
+        }
+        assert_eq!(groups.origin(4, 9), (2, 3));
+        assert!(groups.on_boundary((2, 4)));
+        assert!(groups.on_boundary((3, 6)));
+        assert!(!groups.on_boundary((3, 4)));
+    }
+}
@@ -0,0 +1,164 @@
+use coop::{Coord, Snapshot, Strategy};
+use ratatui::{
+    style::{Color, Stylize},
+    text::{Line, Span},
+};
+
+use crate::palette::Palette;
+
+/// Upper half block: the foreground paints the top half of the character, the background the
+/// bottom half.
+pub const HALF_BLOCK: &str = "▀";
+/// Lower half block, for a vacant top half over an occupied bottom half.
+const LOWER_HALF_BLOCK: &str = "▄";
+/// Color of the half holding the inspector cursor.
+const CURSOR_COLOR: Color = Color::Black;
+
+/// Packs two snapshot rows into one terminal line, one character per column, with `None` for
+/// vacant cells. Vacant halves and a missing `bottom` row (the last row of an odd-height grid)
+/// are left at the terminal background. `selected` is `(0, col)` for a cell of the top row or
+/// `(1, col)` for the bottom.
+pub fn half_block_line(
+    top: &[Option<Strategy>],
+    bottom: Option<&[Option<Strategy>]>,
+    palette: Palette,
+    selected: Option<Coord>,
+) -> Line<'static> {
+    let color = |half: usize, col: usize, s: Option<Strategy>| {
+        if selected == Some((half, col)) {
+            Some(CURSOR_COLOR)
+        } else {
+            s.map(|s| palette.color(s))
+        }
+    };
+    Line::from_iter(top.iter().enumerate().map(|(col, s)| {
+        let bottom = bottom
+            .and_then(|b| b.get(col))
+            .and_then(|b| color(1, col, *b));
+        match (color(0, col, *s), bottom) {
+            (Some(top), Some(bottom)) => Span::from(HALF_BLOCK).fg(top).bg(bottom),
+            (Some(top), None) => Span::from(HALF_BLOCK).fg(top),
+            (None, Some(bottom)) => Span::from(LOWER_HALF_BLOCK).fg(bottom),
+            (None, None) => Span::from(" "),
+        }
+    }))
+}
+
+/// The whole snapshot at two rows per line; `selected` is a grid coordinate.
+pub fn half_block_lines(
+    snapshot: &Snapshot,
+    palette: Palette,
+    selected: Option<Coord>,
+) -> Vec<Line<'static>> {
+    (0..snapshot.rows().div_ceil(2))
+        .filter_map(|i| {
+            let selected = selected
+                .filter(|(r, _)| r / 2 == i)
+                .map(|(r, c)| (r % 2, c));
+            let top = occupants(snapshot, 2 * i)?;
+            let bottom = occupants(snapshot, 2 * i + 1);
+            Some(half_block_line(&top, bottom.as_deref(), palette, selected))
+        })
+        .collect()
+}
+
+/// The strategies of `row`, `None` where a cell is vacant; `None` outside the grid.
+fn occupants(snapshot: &Snapshot, row: usize) -> Option<Vec<Option<Strategy>>> {
+    (row < snapshot.rows()).then(|| {
+        (0..snapshot.cols())
+            .map(|col| snapshot.occupant(row, col))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    fn colors(line: &Line) -> Vec<(Option<Color>, Option<Color>)> {
+        line.spans
+            .iter()
+            .map(|s| (s.style.fg, s.style.bg))
+            .collect()
+    }
+
+    /// Whole rows of occupied cells.
+    fn full(row: &[Strategy]) -> Vec<Option<Strategy>> {
+        row.iter().copied().map(Some).collect()
+    }
+
+    #[test]
+    fn test_pairs_rows() {
+        let p = Palette::Classic;
+        let line = half_block_line(&full(&[C, D]), Some(&full(&[T, C])), p, None);
+        assert_eq!(line.to_string(), "▀▀");
+        assert_eq!(
+            colors(&line),
+            vec![
+                (Some(p.color(C)), Some(p.color(T))),
+                (Some(p.color(D)), Some(p.color(C))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unpaired_row_keeps_background() {
+        let p = Palette::Colorblind;
+        let line = half_block_line(&full(&[C, D]), None, p, None);
+        assert_eq!(
+            colors(&line),
+            vec![(Some(p.color(C)), None), (Some(p.color(D)), None)]
+        );
+        // A short bottom row only fills the columns it has.
+        let line = half_block_line(&full(&[C, D]), Some(&full(&[T])), p, None);
+        assert_eq!(colors(&line)[1], (Some(p.color(D)), None));
+    }
+
+    #[test]
+    fn test_selected_half() {
+        let p = Palette::Classic;
+        let (top, bottom) = (full(&[C, D]), full(&[T, C]));
+        let line = half_block_line(&top, Some(&bottom), p, Some((1, 0)));
+        assert_eq!(colors(&line)[0], (Some(p.color(C)), Some(CURSOR_COLOR)));
+        let line = half_block_line(&top, Some(&bottom), p, Some((0, 1)));
+        assert_eq!(colors(&line)[1], (Some(CURSOR_COLOR), Some(p.color(C))));
+    }
+
+    #[test]
+    fn test_vacant_halves_are_blank() {
+        let p = Palette::Classic;
+        let line = half_block_line(
+            &[None, None, Some(C)],
+            Some(&[Some(T), None, None]),
+            p,
+            None,
+        );
+        assert_eq!(line.to_string(), "▄ ▀");
+        assert_eq!(
+            colors(&line),
+            vec![
+                (Some(p.color(T)), None),
+                (None, None),
+                (Some(p.color(C)), None)
+            ]
+        );
+        // The cursor still shows on a vacant cell.
+        let line = half_block_line(&[None], None, p, Some((0, 0)));
+        assert_eq!(colors(&line), vec![(Some(CURSOR_COLOR), None)]);
+    }
+
+    #[test]
+    fn test_odd_row_count() {
+        let p = Palette::Classic;
+        let snapshot = Snapshot::from(vec![vec![C], vec![D], vec![T]]);
+        let lines = half_block_lines(&snapshot, p, Some((2, 0)));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            colors(&lines[0]),
+            vec![(Some(p.color(C)), Some(p.color(D)))]
+        );
+        assert_eq!(colors(&lines[1]), vec![(Some(CURSOR_COLOR), None)]);
+        assert!(half_block_lines(&Snapshot::default(), p, None).is_empty());
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+
+use crate::agent::Strategy;
+use crate::bookmarks::Bookmarks;
+use crate::env::Metric;
+use crate::snapshot::Snapshot;
+
+/// Retained steps the cooperation rate is averaged over by [`History::steady_state_window`].
+pub const STEADY_WINDOW: usize = 20;
+/// Change per step of that average below which the rate counts as settled.
+pub const STEADY_SLOPE: f64 = 1e-3;
+
+/// Metrics of a run with bounded memory: the most recent `capacity` steps are kept in full,
+/// and of the older ones only every `keyframe_every`-th step (a keyframe) and every bookmarked
+/// step are retained.
+#[derive(Clone, Debug)]
+pub struct History {
+    recent: VecDeque<Metric>,
+    /// Step number of `recent[0]`.
+    recent_start: usize,
+    /// Retained older steps, in increasing step order and all before `recent_start`.
+    keyframes: Vec<(usize, Metric)>,
+    len: usize,
+    capacity: usize,
+    keyframe_every: usize,
+    bookmarks: Bookmarks,
+    /// Steps left out of summaries as early transients.
+    burn_in: usize,
+    /// Whether older steps keep only their thumbnails.
+    compact: bool,
+}
+
+/// Averages over the retained steps of a history from its burn-in on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Number of retained steps averaged over.
+    pub steps: usize,
+    pub mean_coop_rate: f64,
+    /// Mean number of agents playing each strategy, for the strategies that were played.
+    pub mean_strategies: BTreeMap<Strategy, f64>,
+}
+
+impl History {
+    pub fn new(capacity: usize, keyframe_every: usize) -> History {
+        History {
+            recent: VecDeque::new(),
+            recent_start: 0,
+            keyframes: Vec::new(),
+            len: 0,
+            capacity: capacity.max(1),
+            keyframe_every: keyframe_every.max(1),
+            bookmarks: Bookmarks::default(),
+            burn_in: 0,
+            compact: false,
+        }
+    }
+
+    /// This history dropping the full snapshot of every step that leaves the recent window
+    /// and has a thumbnail to stand in for it, see [`Metric::overview`]. Long runs can then
+    /// keep a keyframe for every step at a fraction of the memory.
+    pub fn compact_snapshots(mut self) -> History {
+        self.compact = true;
+        self
+    }
+
+    /// Whether older steps keep only their thumbnails.
+    pub fn compacts_snapshots(&self) -> bool {
+        self.compact
+    }
+
+    /// This history with its first `burn_in` steps left out of summaries.
+    pub fn with_burn_in(mut self, burn_in: usize) -> History {
+        self.burn_in = burn_in;
+        self
+    }
+
+    pub fn burn_in(&self) -> usize {
+        self.burn_in
+    }
+
+    /// Whether `step` is an early transient left out of summaries.
+    pub fn is_burn_in(&self, step: usize) -> bool {
+        step < self.burn_in
+    }
+
+    /// Appends the metric of the next step and returns its step number.
+    pub fn push(&mut self, metric: Metric) -> usize {
+        self.recent.push_back(metric);
+        self.len += 1;
+        while self.recent.len() > self.capacity {
+            let step = self.recent_start;
+            let mut metric = self.recent.pop_front().unwrap();
+            self.recent_start += 1;
+            if self.compact && metric.thumbnail.is_some() {
+                metric.snapshot = Snapshot::default();
+            }
+            if self.is_keyframe(step) || self.bookmarks.contains(step) {
+                self.keyframes.push((step, metric));
+            }
+        }
+        self.len - 1
+    }
+
+    fn is_keyframe(&self, step: usize) -> bool {
+        step.is_multiple_of(self.keyframe_every)
+    }
+
+    /// Bookmarks `step`, or removes its bookmark. Only retained steps can be bookmarked, and a
+    /// bookmarked step stays retained until the bookmark is removed. Returns whether `step` is
+    /// now bookmarked.
+    pub fn toggle_bookmark(&mut self, step: usize) -> bool {
+        if self.get(step).is_none() {
+            return false;
+        }
+        if self.bookmarks.toggle(step) {
+            return true;
+        }
+        if step < self.recent_start && !self.is_keyframe(step) {
+            self.keyframes.retain(|(s, _)| *s != step);
+        }
+        false
+    }
+
+    pub fn bookmarks(&self) -> &Bookmarks {
+        &self.bookmarks
+    }
+
+    /// Number of steps recorded, retained or not.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of steps whose metrics are still held.
+    pub fn retained(&self) -> usize {
+        self.keyframes.len() + self.recent.len()
+    }
+
+    /// Forgets every step and bookmark.
+    pub fn clear(&mut self) {
+        *self = History {
+            compact: self.compact,
+            ..History::new(self.capacity, self.keyframe_every).with_burn_in(self.burn_in)
+        };
+    }
+
+    /// The metric of `step`, if it's retained.
+    pub fn get(&self, step: usize) -> Option<&Metric> {
+        if step >= self.recent_start {
+            return self.recent.get(step - self.recent_start);
+        }
+        self.keyframes
+            .binary_search_by_key(&step, |(s, _)| *s)
+            .ok()
+            .map(|i| &self.keyframes[i].1)
+    }
+
+    /// The latest retained step before `step`.
+    pub fn prev(&self, step: usize) -> Option<usize> {
+        let last = step.checked_sub(1)?.min(self.len.checked_sub(1)?);
+        if last >= self.recent_start {
+            return Some(last);
+        }
+        let i = self.keyframes.partition_point(|(s, _)| *s <= last);
+        i.checked_sub(1).map(|i| self.keyframes[i].0)
+    }
+
+    /// The earliest retained step after `step`.
+    pub fn next(&self, step: usize) -> Option<usize> {
+        let first = step + 1;
+        if first >= self.len {
+            return None;
+        }
+        if first >= self.recent_start {
+            return Some(first);
+        }
+        let i = self.keyframes.partition_point(|(s, _)| *s < first);
+        Some(self.keyframes.get(i).map_or(self.recent_start, |(s, _)| *s))
+    }
+
+    /// The retained step closest to `step`, preferring the earlier one on a tie.
+    pub fn nearest(&self, step: usize) -> Option<usize> {
+        if self.get(step).is_some() {
+            return Some(step);
+        }
+        match (self.prev(step), self.next(step)) {
+            (Some(p), Some(n)) => Some(if step - p <= n - step { p } else { n }),
+            (p, n) => p.or(n),
+        }
+    }
+
+    /// Every retained `(step, metric)` in step order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Metric)> {
+        self.keyframes
+            .iter()
+            .map(|(s, m)| (*s, m))
+            .chain((self.recent_start..).zip(&self.recent))
+    }
+
+    /// Averages over every retained step past the burn-in; thinned older steps weigh as much
+    /// as the recent ones they stand in for.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for (_, metric) in self.iter().filter(|(s, _)| !self.is_burn_in(*s)) {
+            summary.steps += 1;
+            summary.mean_coop_rate += metric.coop_rate as f64;
+            for (&strategy, &count) in &metric.strategies {
+                *summary.mean_strategies.entry(strategy).or_default() += count as f64;
+            }
+        }
+        if summary.steps > 0 {
+            let n = summary.steps as f64;
+            summary.mean_coop_rate /= n;
+            summary.mean_strategies.values_mut().for_each(|c| *c /= n);
+        }
+        summary
+    }
+
+    /// The steps from where the cooperation rate has settled to the latest, as an alternative
+    /// to a fixed burn-in: the rate's moving average over [`STEADY_WINDOW`] retained steps
+    /// changes by less than [`STEADY_SLOPE`] per step from there on. `None` until enough steps
+    /// are retained to tell, or while the rate is still moving.
+    pub fn steady_state_window(&self) -> Option<Range<usize>> {
+        let w = STEADY_WINDOW;
+        let points: Vec<(usize, f64)> = self.iter().map(|(s, m)| (s, m.coop_rate as f64)).collect();
+        if points.len() < 2 * w {
+            return None;
+        }
+        let averages: Vec<f64> = points
+            .windows(w)
+            .map(|window| window.iter().map(|(_, r)| r).sum::<f64>() / w as f64)
+            .collect();
+        // `averages[i]` ends at point `i + w - 1`; compare it with the one a window earlier.
+        let moving = |i: usize| {
+            let steps = points[i + w - 1].0 - points[i - 1].0;
+            (averages[i] - averages[i - w]).abs() / steps as f64 >= STEADY_SLOPE
+        };
+        let settled = match (w..averages.len()).rev().find(|&i| moving(i)) {
+            Some(i) if i + 1 == averages.len() => return None,
+            // The first window of averages past the last change.
+            Some(i) => i + 1,
+            None => 0,
+        };
+        Some(points[settled].0..self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+
+    /// A metric tagged with `step` in its cooperation count.
+    fn metric(step: usize) -> Metric {
+        Metric {
+            strategies: Default::default(),
+            max_score: Default::default(),
+            avg_score: Default::default(),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 0,
+            coop_actions: step as i32,
+            coop_rate: 0.0,
+            snapshot: Default::default(),
+            founder_lineages: 0,
+            population: 0,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: Grid::default(),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        }
+    }
+
+    fn filled(capacity: usize, keyframe_every: usize, steps: usize) -> History {
+        let mut history = History::new(capacity, keyframe_every);
+        for step in 0..steps {
+            assert_eq!(history.push(metric(step)), step);
+        }
+        history
+    }
+
+    /// A history of `rates`, with as many cooperators out of 10 agents as the rate says.
+    fn rated(rates: impl IntoIterator<Item = f32>, burn_in: usize) -> History {
+        let mut history = History::new(1000, 1).with_burn_in(burn_in);
+        for coop_rate in rates {
+            let coop = (coop_rate * 10.0).round() as usize;
+            history.push(Metric {
+                coop_rate,
+                strategies: [(Strategy::Coop, coop), (Strategy::Deflect, 10 - coop)].into(),
+                ..metric(0)
+            });
+        }
+        history
+    }
+
+    fn steps(history: &History) -> Vec<usize> {
+        history.iter().map(|(s, _)| s).collect()
+    }
+
+    #[test]
+    fn test_keeps_everything_within_capacity() {
+        let history = filled(10, 4, 6);
+        assert_eq!(history.len(), 6);
+        assert_eq!(history.retained(), 6);
+        assert_eq!(steps(&history), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(history.get(3).unwrap().coop_actions, 3);
+        assert!(history.get(6).is_none());
+    }
+
+    #[test]
+    fn test_evicts_to_keyframes() {
+        let history = filled(3, 4, 12);
+        assert_eq!(history.len(), 12);
+        assert_eq!(steps(&history), vec![0, 4, 8, 9, 10, 11]);
+        assert_eq!(history.retained(), 6);
+        assert!(history.get(5).is_none());
+        assert_eq!(history.get(4).unwrap().coop_actions, 4);
+        assert_eq!(history.get(10).unwrap().coop_actions, 10);
+        for (step, metric) in history.iter() {
+            assert_eq!(metric.coop_actions, step as i32);
+        }
+    }
+
+    #[test]
+    fn test_prev_and_next_skip_dropped_steps() {
+        let history = filled(3, 4, 12);
+        assert_eq!(history.prev(0), None);
+        assert_eq!(history.prev(4), Some(0));
+        assert_eq!(history.prev(7), Some(4));
+        assert_eq!(history.prev(9), Some(8));
+        assert_eq!(history.prev(100), Some(11));
+        assert_eq!(history.next(0), Some(4));
+        assert_eq!(history.next(4), Some(8));
+        assert_eq!(history.next(6), Some(8));
+        assert_eq!(history.next(9), Some(10));
+        assert_eq!(history.next(11), None);
+    }
+
+    #[test]
+    fn test_next_reaches_recent_past_last_keyframe() {
+        // Keyframes at 0 and 5; recent steps 7..10.
+        let history = filled(3, 5, 10);
+        assert_eq!(steps(&history), vec![0, 5, 7, 8, 9]);
+        assert_eq!(history.next(5), Some(7));
+        assert_eq!(history.prev(7), Some(5));
+    }
+
+    #[test]
+    fn test_nearest() {
+        let history = filled(3, 4, 12);
+        assert_eq!(history.nearest(4), Some(4));
+        assert_eq!(history.nearest(5), Some(4));
+        assert_eq!(history.nearest(6), Some(4));
+        assert_eq!(history.nearest(7), Some(8));
+        assert_eq!(history.nearest(2), Some(0));
+        assert_eq!(history.nearest(500), Some(11));
+        assert_eq!(History::new(3, 4).nearest(0), None);
+    }
+
+    #[test]
+    fn test_clear_keeps_policy() {
+        let mut history = filled(3, 4, 12);
+        history.clear();
+        assert!(history.is_empty());
+        assert_eq!(history.retained(), 0);
+        assert_eq!(history.push(metric(0)), 0);
+        for step in 1..12 {
+            history.push(metric(step));
+        }
+        assert_eq!(steps(&history), vec![0, 4, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn test_compact_snapshots() {
+        let mut env = crate::EnvironmentBuilder::new()
+            .size(40, 64)
+            .seed(2)
+            .thumbnails(true)
+            .build()
+            .unwrap();
+        let mut history = History::new(3, 1).compact_snapshots();
+        for _ in 0..5 {
+            history.push(env.step());
+        }
+        let old = history.get(1).unwrap();
+        assert!(old.snapshot.is_empty());
+        assert_eq!(old.overview().dimensions(), (20, 32));
+        let recent = history.get(4).unwrap();
+        assert_eq!(recent.overview().dimensions(), (40, 64));
+        assert_eq!(recent.thumbnail.as_ref().unwrap().dimensions(), (20, 32));
+        // Without a thumbnail to stand in, the snapshot stays.
+        let mut history = History::new(1, 1).compact_snapshots();
+        let snapshot = Snapshot::from(vec![vec![Strategy::Coop]]);
+        for step in 0..2 {
+            history.push(Metric {
+                snapshot: snapshot.clone(),
+                ..metric(step)
+            });
+        }
+        assert_eq!(history.get(0).unwrap().overview(), &snapshot);
+        history.clear();
+        assert!(history.compacts_snapshots());
+    }
+
+    #[test]
+    fn test_burn_in_summary() {
+        // Ten steps of full defection before cooperation takes over for good.
+        let rates = || (0..40).map(|s| if s < 10 { 0.0 } else { 0.8 });
+        let all = rated(rates(), 0).summary();
+        assert_eq!(all.steps, 40);
+        assert!((all.mean_coop_rate - 0.6).abs() < 1e-6);
+        assert_eq!(all.mean_strategies[&Strategy::Deflect], 4.0);
+        let steady = rated(rates(), 10).summary();
+        assert_eq!(steady.steps, 30);
+        assert!((steady.mean_coop_rate - 0.8).abs() < 1e-6);
+        assert_eq!(steady.mean_strategies[&Strategy::Coop], 8.0);
+        let mut history = rated(rates(), 10);
+        assert!(history.is_burn_in(9) && !history.is_burn_in(10));
+        history.clear();
+        assert_eq!(history.burn_in(), 10);
+        assert_eq!(history.summary(), Summary::default());
+        // A burn-in past the end leaves nothing to average.
+        assert_eq!(rated(rates(), 100).summary().steps, 0);
+    }
+
+    #[test]
+    fn test_steady_state_window() {
+        // A ramp over the first 50 steps, then a plateau with jitter the average smooths out.
+        let ramp = (0..50).map(|s| s as f32 / 50.0);
+        let plateau = (0..150).map(|s| if s % 2 == 0 { 0.99 } else { 1.0 });
+        let window = rated(ramp.chain(plateau), 0).steady_state_window().unwrap();
+        assert!((50..=70).contains(&window.start), "{:?}", window);
+        assert_eq!(window.end, 200);
+        // Settled from the start, still climbing, or too short to tell.
+        assert_eq!(rated([0.5; 60], 0).steady_state_window(), Some(0..60));
+        let climbing = (0..100).map(|s| s as f32 / 100.0);
+        assert_eq!(rated(climbing, 0).steady_state_window(), None);
+        assert_eq!(rated([0.5; 10], 0).steady_state_window(), None);
+    }
+
+    #[test]
+    fn test_bookmarks_survive_eviction() {
+        let mut history = filled(3, 4, 3);
+        assert!(history.toggle_bookmark(1));
+        for step in 3..12 {
+            history.push(metric(step));
+        }
+        assert_eq!(steps(&history), vec![0, 1, 4, 8, 9, 10, 11]);
+        assert_eq!(history.get(1).unwrap().coop_actions, 1);
+        assert_eq!(history.next(0), Some(1));
+        assert_eq!(history.prev(4), Some(1));
+        assert_eq!(history.bookmarks().iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_unbookmarking_releases_step() {
+        let mut history = filled(3, 4, 3);
+        history.toggle_bookmark(1);
+        history.toggle_bookmark(0);
+        for step in 3..12 {
+            history.push(metric(step));
+        }
+        assert!(!history.toggle_bookmark(1));
+        assert_eq!(steps(&history), vec![0, 4, 8, 9, 10, 11]);
+        // Keyframes stay put when their bookmark goes.
+        assert!(!history.toggle_bookmark(0));
+        assert_eq!(steps(&history), vec![0, 4, 8, 9, 10, 11]);
+        assert!(history.bookmarks().is_empty());
+    }
+
+    #[test]
+    fn test_only_retained_steps_can_be_bookmarked() {
+        let mut history = filled(3, 4, 12);
+        assert!(!history.toggle_bookmark(5));
+        assert!(!history.toggle_bookmark(12));
+        assert!(history.toggle_bookmark(10));
+        history.clear();
+        assert!(history.bookmarks().is_empty());
+    }
+
+    #[test]
+    fn test_degenerate_policy() {
+        let history = filled(0, 0, 3);
+        assert_eq!(steps(&history), vec![0, 1, 2]);
+        let history = filled(1, 1000, 5);
+        assert_eq!(steps(&history), vec![0, 4]);
+    }
+}
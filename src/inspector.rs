@@ -0,0 +1,103 @@
+use coop::Coord;
+use ratatui::layout::Rect;
+
+/// Selected cell in inspect mode, always kept inside the grid.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Cursor {
+    pub fn new(row: usize, col: usize, dims: (usize, usize)) -> Cursor {
+        Cursor {
+            row: row.min(dims.0.saturating_sub(1)),
+            col: col.min(dims.1.saturating_sub(1)),
+        }
+    }
+
+    /// The cursor moved by `(dr, dc)`, clamped to a `dims` grid.
+    pub fn moved(self, dr: isize, dc: isize, dims: (usize, usize)) -> Cursor {
+        Cursor::new(
+            self.row.saturating_add_signed(dr),
+            self.col.saturating_add_signed(dc),
+            dims,
+        )
+    }
+
+    pub fn coord(self) -> Coord {
+        (self.row, self.col)
+    }
+}
+
+/// Maps a terminal position to the grid cell rendered there, given the area the grid was
+/// drawn into (its top-left is the cell `(0, 0)`) and the width of one cell in columns.
+/// Returns `None` for positions outside the area or beyond the last row/column.
+pub fn screen_to_cell(
+    column: u16,
+    row: u16,
+    area: Rect,
+    cell_width: u16,
+    dims: (usize, usize),
+) -> Option<Coord> {
+    if cell_width == 0 || !area.contains((column, row).into()) {
+        return None;
+    }
+    let r = (row - area.y) as usize;
+    let c = ((column - area.x) / cell_width) as usize;
+    (r < dims.0 && c < dims.1).then_some((r, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moves_within_bounds() {
+        let c = Cursor::new(1, 1, (3, 3));
+        assert_eq!(c.moved(1, -1, (3, 3)).coord(), (2, 0));
+        assert_eq!(c.moved(-1, 1, (3, 3)).coord(), (0, 2));
+    }
+
+    #[test]
+    fn test_clamps_at_edges() {
+        let dims = (3, 5);
+        let c = Cursor::new(0, 0, dims);
+        assert_eq!(c.moved(-1, -1, dims).coord(), (0, 0));
+        let c = Cursor::new(2, 4, dims);
+        assert_eq!(c.moved(1, 1, dims).coord(), (2, 4));
+        assert_eq!(c.moved(100, -100, dims).coord(), (2, 0));
+    }
+
+    #[test]
+    fn test_screen_to_cell() {
+        let area = Rect::new(0, 0, 80, 24);
+        assert_eq!(screen_to_cell(0, 0, area, 2, (10, 10)), Some((0, 0)));
+        assert_eq!(screen_to_cell(1, 0, area, 2, (10, 10)), Some((0, 0)));
+        assert_eq!(screen_to_cell(2, 0, area, 2, (10, 10)), Some((0, 1)));
+        assert_eq!(screen_to_cell(19, 9, area, 2, (10, 10)), Some((9, 9)));
+        // Past the last column/row (e.g. the status line).
+        assert_eq!(screen_to_cell(20, 0, area, 2, (10, 10)), None);
+        assert_eq!(screen_to_cell(0, 10, area, 2, (10, 10)), None);
+    }
+
+    #[test]
+    fn test_screen_to_cell_with_origin() {
+        let area = Rect::new(5, 3, 20, 10);
+        assert_eq!(screen_to_cell(4, 3, area, 2, (10, 10)), None);
+        assert_eq!(screen_to_cell(5, 2, area, 2, (10, 10)), None);
+        assert_eq!(screen_to_cell(5, 3, area, 2, (10, 10)), Some((0, 0)));
+        assert_eq!(screen_to_cell(8, 5, area, 2, (10, 10)), Some((2, 1)));
+        // Outside the area even though the grid would extend further.
+        assert_eq!(screen_to_cell(25, 3, area, 2, (10, 20)), None);
+        assert_eq!(screen_to_cell(6, 4, area, 1, (10, 10)), Some((1, 1)));
+        assert_eq!(screen_to_cell(6, 4, area, 0, (10, 10)), None);
+    }
+
+    #[test]
+    fn test_new_clamps() {
+        assert_eq!(Cursor::new(10, 10, (4, 6)).coord(), (3, 5));
+        assert_eq!(Cursor::new(1, 1, (1, 1)).coord(), (0, 0));
+        assert_eq!(Cursor::new(1, 1, (0, 0)).coord(), (0, 0));
+    }
+}
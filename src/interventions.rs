@@ -0,0 +1,133 @@
+//! Interventions: scripted changes to a running environment, such as "at step 500 turn a
+//! 10x10 block to Deflect" or "at step 1000 raise the noise to 0.3".
+//!
+//! [`Environment::schedule`] queues one for a step. Interventions due at a step apply after
+//! that step's imitation phase and before its games, in the order they were scheduled, so
+//! they shape the step's games and show in its snapshot, and imitation never undoes one
+//! before it has played once. The step's [`Metric::interventions`](crate::Metric) lists the
+//! ones applied.
+
+use std::fmt;
+
+use crate::agent::{Coord, Strategy};
+use crate::env::Environment;
+
+/// A change to make to an environment at a scheduled step.
+pub enum Intervention {
+    /// Every agent in the `rows` x `cols` region whose top-left cell is `origin` switches to
+    /// `strategy`, keeping its history, score and lineage. The part of the region outside the
+    /// grid and vacant cells are skipped.
+    SetStrategyRegion {
+        origin: Coord,
+        rows: usize,
+        cols: usize,
+        strategy: Strategy,
+    },
+    /// Sets the noise, clamped to `[0, 1]` like [`Environment::set_noise`].
+    SetNoise(f32),
+    /// Every agent forgets every game it played.
+    ClearHistories,
+    /// A newcomer playing `strategy` takes the cell at `coord`, empty or not: a fresh ID and
+    /// lineage, no history, no score, the old agent's class. Its neighbors forget whoever was
+    /// there before. Ignored outside the grid.
+    ReplaceAgent { coord: Coord, strategy: Strategy },
+    /// Anything else, given the whole environment.
+    Custom(Box<dyn FnOnce(&mut Environment) + Send + Sync>),
+}
+
+impl fmt::Debug for Intervention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Intervention::SetStrategyRegion {
+                origin,
+                rows,
+                cols,
+                strategy,
+            } => f
+                .debug_struct("SetStrategyRegion")
+                .field("origin", origin)
+                .field("rows", rows)
+                .field("cols", cols)
+                .field("strategy", strategy)
+                .finish(),
+            Intervention::SetNoise(noise) => f.debug_tuple("SetNoise").field(noise).finish(),
+            Intervention::ClearHistories => f.write_str("ClearHistories"),
+            Intervention::ReplaceAgent { coord, strategy } => f
+                .debug_struct("ReplaceAgent")
+                .field("coord", coord)
+                .field("strategy", strategy)
+                .finish(),
+            Intervention::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Intervention {
+    /// A copy, unless this is a custom intervention, which can only run once.
+    fn try_clone(&self) -> Option<Intervention> {
+        Some(match *self {
+            Intervention::SetStrategyRegion {
+                origin,
+                rows,
+                cols,
+                strategy,
+            } => Intervention::SetStrategyRegion {
+                origin,
+                rows,
+                cols,
+                strategy,
+            },
+            Intervention::SetNoise(noise) => Intervention::SetNoise(noise),
+            Intervention::ClearHistories => Intervention::ClearHistories,
+            Intervention::ReplaceAgent { coord, strategy } => {
+                Intervention::ReplaceAgent { coord, strategy }
+            }
+            Intervention::Custom(_) => return None,
+        })
+    }
+}
+
+/// The interventions waiting for their step, each with the ID `schedule` returned for it.
+#[derive(Debug, Default)]
+pub(crate) struct Agenda {
+    next_id: usize,
+    pending: Vec<(usize, usize, Intervention)>,
+}
+
+impl Agenda {
+    /// Queues `intervention` for step `step` and returns its ID.
+    pub(crate) fn push(&mut self, step: usize, intervention: Intervention) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push((id, step, intervention));
+        id
+    }
+
+    /// Takes the interventions due at `step`, or overdue, with their IDs, in the order they
+    /// were scheduled.
+    pub(crate) fn take_due(&mut self, step: usize) -> Vec<(usize, Intervention)> {
+        let (due, later) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|&(_, at, _)| at <= step);
+        self.pending = later;
+        due.into_iter().map(|(id, _, i)| (id, i)).collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Copies every pending intervention but the custom ones, which can only run once.
+impl Clone for Agenda {
+    fn clone(&self) -> Self {
+        Agenda {
+            next_id: self.next_id,
+            pending: self
+                .pending
+                .iter()
+                .filter_map(|(id, step, i)| Some((*id, *step, i.try_clone()?)))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Metrics as JSON, for consumers outside Rust: the browser bindings and the server mode.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use crate::agent::{Action, Strategy};
+use crate::env::Metric;
+use crate::observe::Interaction;
+
+/// `metric` without its per-cell parts as a JSON object, e.g.
+/// `{"step":0,"coop_actions":12,"coop_rate":1,"strategies":{"Coop":4},...}`. Strategies
+/// missing from the grid are left out, and non-finite numbers become `null`.
+pub fn metrics_json(metric: &Metric) -> String {
+    let mut json = format!(
+        r#"{{"step":{},"coop_actions":{},"coop_rate":{}"#,
+        metric.step_index,
+        metric.coop_actions,
+        json_number(metric.coop_rate)
+    );
+    let _ = write!(
+        json,
+        r#","strategies":{}"#,
+        json_map(&metric.strategies, |n| n.to_string())
+    );
+    let _ = write!(
+        json,
+        r#","avg_score":{}"#,
+        json_map(&metric.avg_score, |v| json_number(*v))
+    );
+    let _ = write!(
+        json,
+        r#","max_score":{}}}"#,
+        json_map(&metric.max_score, |v| json_number(*v))
+    );
+    json
+}
+
+/// One game as a single-line JSON object, e.g. `{"step":0,"first":[0,0],"second":[0,1],
+/// "strategies":["Coop","Deflect"],"actions":["coop","deflect"],"payoffs":[-1,5]}`.
+pub fn interaction_json(interaction: &Interaction) -> String {
+    let action = |a: Action| match a {
+        Action::Coop => "coop",
+        Action::Deflect => "deflect",
+    };
+    let (first, second) = (interaction.first, interaction.second);
+    format!(
+        r#"{{"step":{},"first":[{},{}],"second":[{},{}],"strategies":["{}","{}"],"actions":["{}","{}"],"payoffs":[{},{}]}}"#,
+        interaction.step,
+        first.0,
+        first.1,
+        second.0,
+        second.1,
+        interaction.first_strategy.name(),
+        interaction.second_strategy.name(),
+        action(interaction.first_action),
+        action(interaction.second_action),
+        json_number(interaction.first_payoff),
+        json_number(interaction.second_payoff)
+    )
+}
+
+fn json_map<V>(map: &BTreeMap<Strategy, V>, value: impl Fn(&V) -> String) -> String {
+    let entries: Vec<String> = map
+        .iter()
+        .map(|(s, v)| format!(r#""{}":{}"#, s.name(), value(v)))
+        .collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+/// `value` as written by `Display`, or `null` for the infinities and NaN JSON can't hold.
+fn json_number<T: Copy + fmt::Display + Into<f64>>(value: T) -> String {
+    if value.into().is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+    use crate::snapshot::Snapshot;
+    use Strategy::{Coop as C, Deflect as D};
+
+    #[test]
+    fn test_metrics_json() {
+        let metric = Metric {
+            strategies: [(C, 3), (D, 1)].into(),
+            max_score: [(C, 9.0), (D, f64::NAN)].into(),
+            avg_score: [(C, 4.5), (D, f64::INFINITY)].into(),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 7,
+            coop_actions: 10,
+            coop_rate: 0.625,
+            snapshot: Snapshot::default(),
+            founder_lineages: 0,
+            population: 0,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: Grid::default(),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        };
+        assert_eq!(
+            metrics_json(&metric),
+            concat!(
+                r#"{"step":7,"coop_actions":10,"coop_rate":0.625,"#,
+                r#""strategies":{"Deflect":1,"Coop":3},"#,
+                r#""avg_score":{"Deflect":null,"Coop":4.5},"#,
+                r#""max_score":{"Deflect":null,"Coop":9}}"#
+            )
+        );
+    }
+}
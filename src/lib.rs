@@ -1,5 +1,72 @@
+#![cfg_attr(not(feature = "ffi"), forbid(unsafe_code))]
+// The C interface can't avoid raw pointers, so it alone may opt in.
+#![cfg_attr(feature = "ffi", deny(unsafe_code))]
+
+pub mod actions;
 pub mod agent;
+pub mod analyze;
+pub mod bookmarks;
+pub mod builder;
+pub mod colors;
+pub mod comparison;
 pub mod env;
+pub mod error;
+pub mod experiments;
+#[cfg(feature = "ffi")]
+#[allow(unsafe_code)]
+pub mod ffi;
+pub mod flow;
+pub mod grid;
+pub mod groups;
+pub mod history;
+pub mod interventions;
+pub mod json;
+pub mod mix;
+mod neighbors;
+#[cfg(feature = "notebook")]
+pub mod notebook;
+pub mod observe;
+pub mod pacing;
+pub mod pairings;
+#[cfg(feature = "arrow")]
+pub mod parquet;
+pub mod presets;
+#[cfg(feature = "python")]
+mod python;
+pub mod runner;
+pub mod schedule;
+pub mod shocks;
+pub mod snapshot;
+pub mod stop;
+pub mod tournament;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use agent::{Agent, Coord, Strategy};
-pub use env::{Environment, Metric};
+pub use actions::{ActionBits, Actions};
+pub use agent::{
+    Action, ActionContext, Agent, Class, Coord, Imitation, InteractionOutcome, ParseStrategyError,
+    Prob, Strategy,
+};
+pub use bookmarks::Bookmarks;
+pub use builder::{EnvironmentBuilder, EnvironmentTemplate};
+pub use comparison::{ComparisonRun, Run};
+pub use env::{
+    Asymmetric, EnergyConfig, EnvFeedback, Environment, Metric, NonFinitePolicy, ParamStats,
+    PartnerChoice, PayoffCtx, Payoffs, StepIter, UpdateMode,
+};
+pub use error::Error;
+pub use flow::PayoffFlow;
+pub use grid::Grid;
+pub use groups::{GroupMetric, Groups};
+pub use history::{History, Summary};
+pub use interventions::Intervention;
+pub use mix::Placement;
+pub use neighbors::Neighborhood;
+pub use observe::{Interaction, Observer, SamplingRecorder, SharedObserver};
+pub use pairings::{PairStats, PairingOutcomes};
+pub use presets::Preset;
+pub use runner::{spawn_runner, Command, RunnerConfig};
+pub use shocks::{FiredShock, Schedule, Shock, ShockEffect};
+pub use snapshot::Snapshot;
+pub use stop::{ParseStopError, StopCondition, Stopper};
+pub use tournament::{Tournament, TournamentResult};
@@ -1,118 +1,875 @@
-use std::time::Duration;
+use std::{
+    fs,
+    io::{self, stdout},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use coop::{Agent, Environment, Metric, Strategy};
-use rand::{thread_rng, Rng};
+use charts::{
+    agent_series, axis_bounds, coop_rate_series, downsample, index_to_step, moving_average,
+    population_series,
+};
+use cli::Args;
+use config::{Override, SimConfig};
+use coop::runner::{CommandSender, MetricReceiver};
+use coop::{
+    spawn_runner, Action, Actions, Agent, Asymmetric, Command, Coord, Environment, Groups, History,
+    Metric, Payoffs, RunnerConfig, Schedule, Shock, ShockEffect, StopCondition, Stopper, Strategy,
+};
+use export::{
+    encode_gif, encode_png, export_filename, gif_snapshots, metrics_csv, sample_frames,
+    snapshot_frames, snapshot_image, ExportKind, GIF_DELAY_CS, MAX_GIF_FRAMES, PNG_CELL_PX,
+};
+use pacer::RateMeter;
+use palette::Palette;
 use ratatui::{
-    crossterm::event::{self, Event, KeyCode},
-    style::{Color, Stylize},
+    crossterm::{
+        event::{self, EnableMouseCapture, Event},
+        execute,
+    },
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    symbols,
     text::Line,
-    widgets::{Paragraph, Widget},
+    widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph, Sparkline, Widget},
+    DefaultTerminal, Frame,
 };
+use render::{render_diff, render_grid_view, render_help, render_scrubber, GridView, DIFF_GAP};
+use setup::{Field, SetupEvent, SetupForm};
+use timeline::ViewMode;
+use ui::{Control, RunMode, UiState};
 
-enum UiState {
-    Latest,
-    Detach,
-}
+mod charts;
+mod cli;
+mod config;
+mod export;
+mod halfblock;
+mod inspector;
+mod pacer;
+mod palette;
+mod params;
+mod playback;
+#[cfg(feature = "plots")]
+mod plots;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod prompt;
+mod render;
+#[cfg(feature = "server")]
+mod server;
+mod setup;
+mod status;
+mod terminal;
+mod timeline;
+mod ui;
+
+/// Width of the bookmark list, borders included.
+const BOOKMARK_WIDTH: u16 = 22;
+/// Steps the runner may get ahead of the UI; also how far an uncapped run overshoots a pause.
+const RUNNER_BUFFER: usize = 256;
+const INSPECTOR_WIDTH: u16 = 36;
+const CHART_HEIGHT: u16 = 12;
+/// Height of the followed agent's score sparkline, borders included.
+const SPARKLINE_HEIGHT: u16 = 6;
+/// Number of recent actions shown per neighbor in the inspector.
+const INSPECTOR_HISTORY: usize = 8;
+/// How long export confirmations stay in the status line.
+const MESSAGE_TTL: Duration = Duration::from_secs(4);
 
 fn main() {
-    let mut env = Environment::new_with_agent_func(50, 50, 0.1, |c| {
-        let rand: f32 = thread_rng().gen();
-        let prob_coop = 0.00;
-        let prob_random = 0.10;
-        let prob_tic = 0.40;
-
-        let strategy = if rand < prob_coop {
-            Strategy::Coop
-        } else if rand < prob_coop + prob_random {
-            Strategy::Random
-        } else if rand < prob_coop + prob_random + prob_tic {
-            Strategy::TicToc
-        } else {
-            Strategy::Deflect
-        };
-        Agent::new(c, strategy)
-    });
+    #[cfg(feature = "server")]
+    if std::env::args().nth(1).as_deref() == Some("serve") {
+        server::main(std::env::args().skip(2));
+        return;
+    }
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if args.list_presets {
+        for preset in coop::presets::all() {
+            println!(
+                "{:<20} {} ({} steps)",
+                preset.name, preset.description, preset.steps
+            );
+        }
+        return;
+    }
+    let base = args
+        .preset
+        .as_ref()
+        .map_or_else(SimConfig::default, SimConfig::from_preset);
+    // A preset pauses at its recommended length unless told otherwise.
+    let mut stop = match &args.preset {
+        Some(preset) if args.stop.is_empty() => vec![StopCondition::MaxSteps(preset.steps)],
+        _ => args.stop.clone(),
+    };
+    stop.extend(args.max_seconds.map(StopCondition::WallClock));
+    let mut config = SimConfig {
+        groups: args
+            .groups
+            .map(|(rows, cols)| Groups::new(rows, cols, args.group_every)),
+        shocks: args
+            .shock_every
+            .map(Schedule::Every)
+            .into_iter()
+            .chain(args.shock_rate.map(Schedule::Poisson))
+            .map(|schedule| Shock::new(schedule, args.shock_size, ShockEffect::Randomize))
+            .collect(),
+        asymmetric: args.visitor_payoffs.map(|visitor| Asymmetric {
+            host: Payoffs::default(),
+            visitor,
+        }),
+        ..base
+    };
 
     color_eyre::install().unwrap();
     let mut term = ratatui::init();
-    let mut buffer: Vec<Metric> = Vec::new();
-    let mut ui_state = UiState::Latest;
-    let mut detach_step = 0;
+    terminal::install_panic_hook();
+    if args.setup {
+        match run_setup(&mut term, &config) {
+            Some(chosen) => config = chosen,
+            None => {
+                terminal::restore();
+                return;
+            }
+        }
+    }
+    let mut configs = comparison_configs(&config, args.compare.as_deref());
+    let mut histories: Vec<History> = configs
+        .iter()
+        .map(|_| {
+            let history =
+                History::new(args.history, args.keyframe_every).with_burn_in(args.burn_in);
+            if args.compact_history {
+                history.compact_snapshots()
+            } else {
+                history
+            }
+        })
+        .collect();
+    let mut ui = UiState::new((configs[0].rows, configs[0].cols));
+    ui.palette = args.palette;
+    ui.keep_running = args.keep_running;
+    ui.playback_end = args.playback_end;
+    let mut runner = match Runner::start(&configs, &mut histories, &ui, &stop) {
+        Ok(runner) => runner,
+        Err(e) => {
+            terminal::restore();
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let _ = execute!(stdout(), EnableMouseCapture);
+    let mut meter = RateMeter::new(Duration::from_secs(1));
+    let clock = Instant::now();
+    let (export_done, export_results) = mpsc::channel();
 
     loop {
-        buffer.push(env.step());
-        let step = match ui_state {
-            UiState::Latest => buffer.len().saturating_sub(1),
-            UiState::Detach => detach_step,
-        };
+        let now = clock.elapsed();
+        let ran = runner.sync(&mut ui, &mut histories);
+        meter.record(now, ran);
+        if let Some((step, condition)) = runner.take_stop() {
+            ui.run_mode = RunMode::Paused;
+            ui.message = Some((format!("Stopped at step {}: {}", step, condition), now));
+        }
+        let history = &histories[0];
+        ui.tick_playback(now, history);
+        while let Ok(message) = export_results.try_recv() {
+            ui.message = Some((message, now));
+        }
+        if ui
+            .message
+            .as_ref()
+            .is_some_and(|(_, at)| now > *at + MESSAGE_TTL)
+        {
+            ui.message = None;
+        }
+        let step = ui.current_step(history);
 
+        let inspection = ui.panel_coord().and_then(|coord| runner.inspect(coord));
         let _ = term.draw(|frame| {
-            frame.render_widget(strategy_canvas(step, buffer[step].clone()), frame.area());
+            (ui.grid_area, ui.scrubber_area) = draw(
+                frame,
+                &histories,
+                &configs,
+                inspection,
+                step,
+                &ui,
+                meter.rate(),
+            );
         });
         if event::poll(Duration::from_millis(10)).unwrap() {
-            if let Ok(Event::Key(key)) = event::read() {
-                match key.code {
-                    KeyCode::Char('q') => {
-                        break;
-                    }
-                    KeyCode::Left => match ui_state {
-                        UiState::Latest => {
-                            ui_state = UiState::Detach;
-                            detach_step = buffer.len().saturating_sub(0);
-                        }
-                        UiState::Detach => {
-                            detach_step = detach_step.saturating_sub(1);
+            let history = &histories[0];
+            match event::read() {
+                Ok(Event::Key(key)) if terminal::is_suspend(key) => {
+                    let _ = terminal::suspend(&mut term);
+                }
+                Ok(Event::Key(key)) => match ui.handle_key(key.code, history) {
+                    Control::Continue => {}
+                    Control::Quit => break,
+                    Control::AdjustNoise(delta) => {
+                        // Every run shares the primary run's noise, as the runner sets them all.
+                        let noise = (configs[0].noise + delta).clamp(0.0, 1.0);
+                        for config in &mut configs {
+                            config.noise = noise;
                         }
-                    },
-                    KeyCode::Right => match ui_state {
-                        UiState::Detach => {
-                            detach_step += 1;
-                            detach_step = detach_step.min(buffer.len().saturating_sub(0));
+                        runner.send(Command::SetNoise(noise));
+                    }
+                    Control::Restart { reseed } => {
+                        if reseed {
+                            configs =
+                                comparison_configs(&configs[0].reseeded(), args.compare.as_deref());
                         }
-                        _ => {}
-                    },
-                    KeyCode::Esc => {
-                        ui_state = UiState::Latest;
+                        // Only the seed and the clamped noise differ from the configs that
+                        // built at startup, so these can't fail.
+                        runner.send(Command::Shutdown);
+                        runner = Runner::start(&configs, &mut histories, &ui, &stop)
+                            .expect("restarted config is valid");
+                        ui.marked = None;
+                        ui.timeline.latest();
                     }
-                    KeyCode::Home => {
-                        ui_state = UiState::Detach;
-                        detach_step = 0;
+                    Control::Export(kind) => {
+                        let message = export(kind, history, step, ui.palette, &export_done);
+                        ui.message = Some((message, clock.elapsed()));
                     }
-                    KeyCode::End => {
-                        ui_state = UiState::Detach;
-                        detach_step = buffer.len().saturating_sub(1);
+                    Control::ToggleBookmark(step) => {
+                        // Bookmark every run, so all of them keep the step retained.
+                        for history in &mut histories {
+                            history.toggle_bookmark(step);
+                        }
                     }
-                    _ => {}
-                }
-                if matches!(key.code, KeyCode::Char('q')) {
-                    break;
+                    Control::NextBookmark => {
+                        if let Some(next) = history.bookmarks().next_after(step) {
+                            ui.timeline.detach_at(next, history);
+                        }
+                    }
+                },
+                Ok(Event::Mouse(mouse)) => ui.handle_mouse(mouse, history),
+                Ok(Event::Resize(width, height)) => {
+                    ui.handle_resize(width, height);
+                    // Redraw everything rather than diff against a buffer of the old size.
+                    let _ = term.clear();
                 }
+                _ => {}
             }
         }
     }
 
-    ratatui::restore();
+    runner.send(Command::Shutdown);
+    terminal::restore();
+    if let Some((step, condition)) = runner.stopped {
+        println!("Stopped at step {}: {}", step, condition);
+    }
+    let history = &histories[0];
+    #[cfg(feature = "plots")]
+    if let Some(path) = &args.plot {
+        let config = plots::PlotConfig {
+            palette: args.palette,
+            ..plots::PlotConfig::default()
+        };
+        match plots::render_run(history, path, &config) {
+            Ok(()) => println!("Wrote charts to {}", path.display()),
+            Err(e) => eprintln!("can't write {}: {}", path.display(), e),
+        }
+    }
+    let summary = history.summary();
+    if args.burn_in > 0 && summary.steps > 0 {
+        println!(
+            "Mean cooperation after a {}-step burn-in: {:.1}% over {} steps",
+            args.burn_in,
+            summary.mean_coop_rate * 100.0,
+            summary.steps
+        );
+    }
+    if !history.bookmarks().is_empty() {
+        println!("Bookmarked steps:");
+        for line in bookmark_lines(history) {
+            println!("  {}", line);
+        }
+    }
+}
+
+/// The primary run's config, followed by the compared run's if there is one.
+fn comparison_configs(primary: &SimConfig, compare: Option<&[Override]>) -> Vec<SimConfig> {
+    let mut configs = vec![primary.clone()];
+    configs.extend(compare.map(|overrides| primary.with_overrides(overrides)));
+    configs
 }
 
-fn strategy_color(strategy: Strategy) -> Color {
-    match strategy {
-        Strategy::Deflect => Color::Red,
-        Strategy::Coop => Color::Green,
-        Strategy::TicToc => Color::Yellow,
-        Strategy::Random => Color::Magenta,
+/// Shows the setup form until a valid configuration is submitted, or `None` if it's dismissed.
+fn run_setup(term: &mut DefaultTerminal, config: &SimConfig) -> Option<SimConfig> {
+    let mut form = SetupForm::new(config);
+    loop {
+        let _ = term.draw(|frame| frame.render_widget(setup_screen(&form), frame.area()));
+        if let Ok(Event::Key(key)) = event::read() {
+            match form.handle_key(key.code) {
+                SetupEvent::Pending => {}
+                SetupEvent::Cancelled => return None,
+                SetupEvent::Start(config) => return Some(config),
+            }
+        }
     }
 }
 
-fn strategy_canvas(step: usize, metric: Metric) -> impl Widget {
-    let status_line = Line::from(format!(
-        "Step: {} Agents: {:?} Score: {:?}",
-        step, metric.strategies, metric.max_score
+fn setup_screen(form: &SetupForm) -> impl Widget {
+    let mut lines = vec![Line::from("")];
+    let mut heading = false;
+    for (field, text) in form.fields() {
+        if matches!(field, Field::Share(_)) && !heading {
+            lines.push(Line::from(" Initial strategies (%)".bold()));
+            heading = true;
+        }
+        let value = format!("{:>20}", text);
+        let mut spans = vec![
+            format!(" {:<8} ", field.label()).into(),
+            if field == form.focus() {
+                value.reversed()
+            } else {
+                value.into()
+            },
+        ];
+        if let Some(e) = form.error(field) {
+            spans.push(format!("  {}", e.message(field)).red());
+        }
+        lines.push(Line::from(spans));
+    }
+    let total = format!(" {:<8} {:>19}%", "Total", form.total());
+    lines.push(Line::from(if form.total_error() {
+        vec![total.red(), "  must be 100%".red()]
+    } else {
+        vec![total.into()]
+    }));
+    lines.push(Line::from(""));
+    lines.push(Line::from(
+        " Tab/↑↓ move · digits edit · Backspace delete · Enter start · Esc quit".dark_gray(),
     ));
-    let mut lines: Vec<Line> = metric
-        .snapshot
+    Paragraph::new(lines).block(Block::bordered().title("New run"))
+}
+
+/// The runs being stepped on a background runner, in lockstep, and what the UI last told it.
+struct Runner {
+    metrics: MetricReceiver<Vec<Metric>>,
+    commands: CommandSender<Vec<Environment>>,
+    running: bool,
+    rate: Option<u32>,
+    /// Answers to `Command::Inspect` requests, and whether one is still outstanding.
+    inspections: (Sender<Option<Inspection>>, Receiver<Option<Inspection>>),
+    awaiting: bool,
+    inspection: Option<Inspection>,
+    /// Checks the primary run's metrics until one of its conditions holds.
+    stopper: Option<Stopper>,
+    /// When the runner started, which wall-clock stop conditions count from, paused time
+    /// included.
+    started: Instant,
+    /// The step the primary run met a stop condition at, and the condition, if it has.
+    stopped: Option<(usize, StopCondition)>,
+    /// Whether the UI has yet to be told about `stopped`.
+    unreported: bool,
+}
+
+impl Runner {
+    /// Builds an environment per config, records their first steps into `histories` (which
+    /// are cleared first) and hands the environments to a new runner in the UI's state.
+    fn start(
+        configs: &[SimConfig],
+        histories: &mut [History],
+        ui: &UiState,
+        stop: &[StopCondition],
+    ) -> Result<Runner, coop::Error> {
+        let mut envs = configs
+            .iter()
+            .map(SimConfig::build)
+            .collect::<Result<Vec<_>, _>>()?;
+        for (history, env) in histories.iter_mut().zip(&mut envs) {
+            env.set_thumbnails(history.compacts_snapshots());
+            history.clear();
+            history.push(env.step());
+        }
+        let running = ui.should_step();
+        let rate = ui.pacer.target_rate();
+        let config = RunnerConfig {
+            rate,
+            paused: !running,
+            buffer: RUNNER_BUFFER,
+            ..RunnerConfig::default()
+        };
+        let (metrics, commands) = spawn_runner(envs, config);
+        let mut runner = Runner {
+            metrics,
+            commands,
+            running,
+            rate,
+            inspections: mpsc::channel(),
+            awaiting: false,
+            inspection: None,
+            stopper: (!stop.is_empty()).then(|| Stopper::new(stop.iter().copied())),
+            started: Instant::now(),
+            stopped: None,
+            unreported: false,
+        };
+        if let Some(metric) = histories[0].get(0) {
+            runner.check_stop(metric);
+        }
+        Ok(runner)
+    }
+
+    /// Checks the primary run's next metric against the stop conditions, which are dropped
+    /// once one holds so a resumed run isn't stopped again.
+    fn check_stop(&mut self, metric: &Metric) {
+        let Some(stopper) = self.stopper.as_mut() else {
+            return;
+        };
+        if let Some(condition) = stopper.check_at(metric, self.started.elapsed()) {
+            self.stopped = Some((metric.step_index, condition));
+            self.unreported = true;
+            self.stopper = None;
+        }
+    }
+
+    /// The stop the UI hasn't been told about yet, if any.
+    fn take_stop(&mut self) -> Option<(usize, StopCondition)> {
+        std::mem::take(&mut self.unreported)
+            .then_some(self.stopped)
+            .flatten()
+    }
+
+    /// A worker that's gone can't be controlled any more; the UI keeps showing what it has.
+    fn send(&self, command: Command<Vec<Environment>>) {
+        let _ = self.commands.send(command);
+    }
+
+    /// Tells the runner about changes to the run mode, speed and requested single steps, and
+    /// records the steps it has taken since into `histories`. Returns how many it took.
+    fn sync(&mut self, ui: &mut UiState, histories: &mut [History]) -> usize {
+        let running = ui.should_step();
+        if running != self.running {
+            self.send(if running {
+                Command::Resume
+            } else {
+                Command::Pause
+            });
+            self.running = running;
+        }
+        if ui.pacer.target_rate() != self.rate {
+            self.rate = ui.pacer.target_rate();
+            self.send(Command::SetSpeed(self.rate));
+        }
+        if !running {
+            while ui.take_single_step() {
+                self.send(Command::Step);
+            }
+        }
+        let mut ran = 0;
+        while let Ok(metrics) = self.metrics.try_recv() {
+            if let Some(metric) = metrics.first() {
+                self.check_stop(metric);
+            }
+            for (history, metric) in histories.iter_mut().zip(metrics) {
+                history.push(metric);
+            }
+            ran += 1;
+        }
+        ran
+    }
+
+    /// The latest copy of the primary run's agent at `coord` and its neighbors, and a request
+    /// for a fresher one. It can lag the displayed step by the time the runner takes to answer.
+    fn inspect(&mut self, coord: Coord) -> Option<&Inspection> {
+        while let Ok(inspection) = self.inspections.1.try_recv() {
+            self.inspection = inspection;
+            self.awaiting = false;
+        }
+        if !self.awaiting {
+            let answer = self.inspections.0.clone();
+            self.send(Command::Inspect(Box::new(
+                move |envs: &Vec<Environment>| {
+                    let env = &envs[0];
+                    let inspection = env.agent_at(coord).map(|agent| Inspection {
+                        agent: agent.clone(),
+                        neighbors: env.neighbors(coord).into_iter().cloned().collect(),
+                    });
+                    let _ = answer.send(inspection);
+                },
+            )));
+            self.awaiting = true;
+        }
+        self.inspection.as_ref().filter(|i| i.agent.coord == coord)
+    }
+}
+
+/// Copies of an agent and its neighbors taken on the runner's thread.
+struct Inspection {
+    agent: Agent,
+    neighbors: Vec<Agent>,
+}
+
+/// Writes the requested export to a timestamped file and returns the status message. The GIF
+/// is encoded on a background thread that sends its own message to `done` when finished.
+fn export(
+    kind: ExportKind,
+    history: &History,
+    step: usize,
+    palette: Palette,
+    done: &Sender<String>,
+) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH);
+    let path = export_filename(kind, now.map_or(0, |d| d.as_secs()));
+    match kind {
+        ExportKind::Frame => {
+            let Some(metric) = history.get(step) else {
+                return format!("Step {} is no longer retained", step);
+            };
+            let image = snapshot_image(metric.overview(), PNG_CELL_PX, palette);
+            export_result(&path, fs::write(&path, encode_png(&image)))
+        }
+        ExportKind::Metrics => export_result(
+            &path,
+            fs::write(
+                &path,
+                metrics_csv(history.iter(), history.bookmarks(), history.burn_in()),
+            ),
+        ),
+        ExportKind::History => {
+            let retained: Vec<&Metric> = history.iter().map(|(_, m)| m).collect();
+            let sampled: Vec<&Metric> = sample_frames(retained.len(), MAX_GIF_FRAMES)
+                .into_iter()
+                .map(|i| retained[i])
+                .collect();
+            let (snapshots, cell_px) = gif_snapshots(&sampled);
+            let done = done.clone();
+            thread::spawn(move || {
+                let frames = snapshot_frames(&snapshots, cell_px, palette);
+                let result = fs::write(&path, encode_gif(&frames, GIF_DELAY_CS));
+                let _ = done.send(export_result(&path, result));
+            });
+            "Writing GIF in the background…".to_string()
+        }
+    }
+}
+
+fn export_result(path: &str, result: io::Result<()>) -> String {
+    match result {
+        Ok(()) => format!("Saved {}", path),
+        Err(e) => format!("Export to {} failed: {}", path, e),
+    }
+}
+
+/// Renders one frame and returns the areas the grid and the scrubber were drawn into.
+fn draw(
+    frame: &mut Frame,
+    histories: &[History],
+    configs: &[SimConfig],
+    inspection: Option<&Inspection>,
+    step: usize,
+    ui: &UiState,
+    rate: f64,
+) -> (Rect, Rect) {
+    let history = &histories[0];
+    let [mut area, scrubber_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    render_scrubber(frame, scrubber_area, history, step);
+    if ui.show_chart {
+        let [top, chart_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(CHART_HEIGHT)]).areas(area);
+        let [population_area, coop_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(chart_area);
+        render_population_chart(frame, population_area, history, step, ui);
+        render_coop_chart(frame, coop_area, history, step, ui);
+        area = top;
+    }
+    let grid_area = match ui.panel_coord() {
+        Some(coord) => {
+            let [grid_area, panel_area] =
+                Layout::horizontal([Constraint::Min(0), Constraint::Length(INSPECTOR_WIDTH)])
+                    .areas(area);
+            if ui.follow == Some(coord) {
+                render_follow_panel(frame, panel_area, inspection, history, coord, ui.palette);
+            } else {
+                frame.render_widget(
+                    inspector_panel(inspection, coord, ui.palette, None),
+                    panel_area,
+                );
+            }
+            grid_area
+        }
+        None if !history.bookmarks().is_empty() => {
+            let [grid_area, panel_area] =
+                Layout::horizontal([Constraint::Min(0), Constraint::Length(BOOKMARK_WIDTH)])
+                    .areas(area);
+            frame.render_widget(bookmark_panel(history, step), panel_area);
+            grid_area
+        }
+        None => area,
+    };
+    let grid_area = match ui.diff_against().filter(|m| history.get(*m).is_some()) {
+        Some(marked) => {
+            render_diff(frame, grid_area, history, step, marked, ui.zoom, ui.palette);
+            grid_area
+        }
+        None => {
+            // Compared runs share the area in equal columns, each with its own status line.
+            let count = histories.len() as u32;
+            let areas = Layout::horizontal(vec![Constraint::Ratio(1, count); count as usize])
+                .spacing(DIFF_GAP)
+                .split(grid_area);
+            for ((history, config), area) in histories.iter().zip(configs).zip(areas.iter()) {
+                let view = GridView {
+                    step,
+                    metric: history.get(step).expect("the viewed step is retained"),
+                    previous: ui.change_base(step).and_then(|s| history.get(s)),
+                    ui,
+                    actual_rate: rate,
+                    config,
+                };
+                render_grid_view(frame, *area, &view);
+            }
+            areas[0]
+        }
+    };
+    if ui.show_help {
+        render_help(frame, frame.area());
+    }
+    (grid_area, scrubber_area)
+}
+
+fn render_population_chart(
+    frame: &mut Frame,
+    area: Rect,
+    history: &History,
+    step: usize,
+    ui: &UiState,
+) {
+    let width = area.width.saturating_sub(2) as usize;
+    let steps: Vec<usize> = history.iter().map(|(s, _)| s).collect();
+    let series: Vec<(Strategy, Vec<(f64, f64)>)> = Strategy::ALL
+        .iter()
+        .map(|s| {
+            let values = population_series(history.iter().map(|(_, m)| m), *s);
+            (*s, index_to_step(&downsample(&values, width), &steps))
+        })
+        .collect();
+    let x_max = history.len().saturating_sub(1).max(1) as f64;
+    let y_max = series
+        .iter()
+        .flat_map(|(_, points)| points.iter().map(|p| p.1))
+        .fold(1.0, f64::max);
+    let marker = [(step as f64, 0.0), (step as f64, y_max)];
+
+    let mut datasets: Vec<Dataset> = series
+        .iter()
+        .map(|(s, points)| {
+            Dataset::default()
+                .name(format!("{:?}", s))
+                .graph_type(GraphType::Line)
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(ui.palette.color(*s)))
+                .data(points)
+        })
+        .collect();
+    if ui.timeline.mode() == ViewMode::Detach {
+        datasets.push(
+            Dataset::default()
+                .graph_type(GraphType::Line)
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(Color::White))
+                .data(&marker),
+        );
+    }
+    let chart = Chart::new(datasets)
+        .block(Block::bordered().title("Population"))
+        .x_axis(
+            Axis::default()
+                .bounds([0.0, x_max])
+                .labels(["0".to_string(), format!("{}", x_max as usize)]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, y_max])
+                .labels(["0".to_string(), format!("{}", y_max as usize)]),
+        );
+    frame.render_widget(chart, area);
+}
+
+/// Cooperation rate over time. In Detach mode the chart is frozen at the viewed step.
+fn render_coop_chart(frame: &mut Frame, area: Rect, history: &History, step: usize, ui: &UiState) {
+    let end = match ui.timeline.mode() {
+        ViewMode::Latest => history.len(),
+        ViewMode::Detach => step + 1,
+    };
+    let (steps, metrics): (Vec<usize>, Vec<&Metric>) =
+        history.iter().take_while(|(s, _)| *s < end).unzip();
+    let rates = coop_rate_series(metrics);
+    let width = area.width.saturating_sub(2) as usize;
+    let points = index_to_step(&downsample(&rates, width), &steps);
+    let average = index_to_step(
+        &downsample(&moving_average(&rates, ui.ma_window), width),
+        &steps,
+    );
+    let [y_min, y_max] = axis_bounds(&rates);
+    let x_max = end.saturating_sub(1).max(1) as f64;
+
+    let mut datasets = vec![Dataset::default()
+        .name("coop rate")
+        .graph_type(GraphType::Line)
+        .marker(symbols::Marker::Braille)
+        .style(Style::default().fg(Color::Cyan))
+        .data(&points)];
+    if ui.ma_window > 1 {
+        datasets.push(
+            Dataset::default()
+                .name(format!("avg {}", ui.ma_window))
+                .graph_type(GraphType::Line)
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(Color::White))
+                .data(&average),
+        );
+    }
+    let chart = Chart::new(datasets)
+        .block(Block::bordered().title("Cooperation rate (w: average)"))
+        .x_axis(
+            Axis::default()
+                .bounds([0.0, x_max])
+                .labels(["0".to_string(), format!("{}", x_max as usize)]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([y_min, y_max])
+                .labels([format!("{:.2}", y_min), format!("{:.2}", y_max)]),
+        );
+    frame.render_widget(chart, area);
+}
+
+/// "step  rate" for every bookmark, e.g. "   120  63.5%".
+fn bookmark_lines(history: &History) -> Vec<String> {
+    history
+        .bookmarks()
+        .iter()
+        .filter_map(|s| history.get(s).map(|m| (s, m)))
+        .map(|(s, m)| format!("{:>8}  {:>5.1}%", s, m.coop_rate * 100.0))
+        .collect()
+}
+
+/// Bookmarked steps with their cooperation rate; the viewed one is highlighted.
+fn bookmark_panel(history: &History, step: usize) -> impl Widget {
+    let lines: Vec<Line> = history
+        .bookmarks()
+        .iter()
+        .zip(bookmark_lines(history))
+        .map(|(s, text)| {
+            if s == step {
+                Line::from(text.reversed())
+            } else {
+                Line::from(text)
+            }
+        })
+        .collect();
+    Paragraph::new(lines).block(Block::bordered().title("Bookmarks (B)"))
+}
+
+/// The inspector for a followed cell, with its strategy and score over the buffered history.
+fn render_follow_panel(
+    frame: &mut Frame,
+    area: Rect,
+    inspection: Option<&Inspection>,
+    history: &History,
+    coord: Coord,
+    palette: Palette,
+) {
+    let [info_area, spark_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(SPARKLINE_HEIGHT)]).areas(area);
+    let width = area.width.saturating_sub(2) as usize;
+    let series = agent_series(history.iter().map(|(_, m)| m), coord);
+    let recent = &series[series.len().saturating_sub(width)..];
+    frame.render_widget(
+        inspector_panel(inspection, coord, palette, Some(recent)),
+        info_area,
+    );
+    let scores: Vec<u64> = recent
         .iter()
-        .map(|row| Line::from_iter(row.iter().map(|s| "██".fg(strategy_color(*s)))))
+        .map(|(_, score)| score.max(0.0).round() as u64)
         .collect();
-    lines.push(status_line);
-    Paragraph::new(lines)
+    let sparkline = Sparkline::default()
+        .block(Block::bordered().title("Score"))
+        .data(&scores);
+    frame.render_widget(sparkline, spark_area);
+}
+
+/// Live details of the agent at `coord`, from the runner's latest `inspection`. `history` is
+/// its recent `(strategy, score)` series, drawn as a strip of strategy colors when following
+/// the cell.
+fn inspector_panel(
+    inspection: Option<&Inspection>,
+    coord: Coord,
+    palette: Palette,
+    history: Option<&[(Strategy, f64)]>,
+) -> impl Widget {
+    let mut lines: Vec<Line> = Vec::new();
+    if let Some(Inspection { agent, neighbors }) = inspection {
+        lines.push(Line::from(format!("Cell: {:?}", coord)));
+        lines.push(Line::from(vec![
+            "Strategy: ".into(),
+            agent.strategy.name().fg(palette.color(agent.strategy)),
+        ]));
+        let params = agent.strategy.params();
+        if !params.is_empty() {
+            let params: Vec<String> = params
+                .iter()
+                .map(|(name, value)| format!("{} {:.2}", name, value))
+                .collect();
+            lines.push(Line::from(format!("Parameters: {}", params.join(", "))));
+        }
+        lines.push(Line::from(format!("Score: {:.1}", agent.score)));
+        if agent.stake != 1.0 {
+            lines.push(Line::from(format!(
+                "Stake: {:.2} ({:.1} per unit)",
+                agent.stake,
+                agent.score_per_stake()
+            )));
+        }
+        lines.push(Line::from(format!("Opponents: {}", agent.num_opponents())));
+        if let Some(history) = history {
+            lines.push(Line::from("Strategy history:"));
+            lines.push(Line::from_iter(
+                history
+                    .iter()
+                    .map(|(s, _)| palette.glyph(*s).to_string().fg(palette.color(*s))),
+            ));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from("Last actions (me/them), payoff:"));
+        for neighbor in neighbors {
+            let theirs = agent.history_with(neighbor.coord);
+            let mine = neighbor.history_with(coord);
+            lines.push(Line::from(format!(
+                "{:?} {} / {} {:.1}",
+                neighbor.coord,
+                recent_actions(mine),
+                recent_actions(theirs),
+                agent.payoff_from(neighbor.coord)
+            )));
+        }
+    }
+    let title = match history {
+        Some(_) => "Following (f: stop)",
+        None => "Inspector (live)",
+    };
+    Paragraph::new(lines).block(Block::bordered().title(title))
+}
+
+/// The last `INSPECTOR_HISTORY` actions as a compact C/D string, oldest first.
+fn recent_actions(history: Actions<'_>) -> String {
+    history
+        .last_n(INSPECTOR_HISTORY)
+        .iter()
+        .map(|a| match a {
+            Action::Coop => 'C',
+            Action::Deflect => 'D',
+        })
+        .collect()
 }
@@ -0,0 +1,126 @@
+//! Initial grids with an exact share of each strategy, rather than the approximate shares
+//! independent per-cell draws give.
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::agent::Strategy;
+use crate::error::Error;
+
+/// Where the agents of a mix go on the grid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Placement {
+    /// Scattered uniformly at random, by the environment's seeded RNG.
+    #[default]
+    Shuffled,
+    /// In contiguous runs, in row-major order and in the order the mix lists them, so each
+    /// strategy starts as one band of rows.
+    Blocked,
+}
+
+/// How many of `cells` each strategy of `mix` gets: its weight's share of the total, rounded
+/// by largest remainder so the counts add up to `cells` exactly. Ties go to the strategy
+/// listed first. Weights are relative, so percentages and fractions both work.
+pub(crate) fn exact_counts(
+    mix: &[(Strategy, f32)],
+    cells: usize,
+) -> Result<Vec<(Strategy, usize)>, Error> {
+    if let Some(&(strategy, fraction)) = mix.iter().find(|(_, f)| !(f.is_finite() && *f >= 0.0)) {
+        return Err(Error::InvalidMix { strategy, fraction });
+    }
+    let total: f64 = mix.iter().map(|&(_, f)| f64::from(f)).sum();
+    if total <= 0.0 {
+        return Err(Error::EmptyStrategyPool);
+    }
+    let quotas: Vec<f64> = mix
+        .iter()
+        .map(|&(_, f)| f64::from(f) / total * cells as f64)
+        .collect();
+    let mut counts: Vec<usize> = quotas.iter().map(|q| q.floor() as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..mix.len()).collect();
+    // Stable, so equal remainders keep the order of the mix.
+    by_remainder.sort_by(|&a, &b| {
+        let remainder = |i: usize| quotas[i] - quotas[i].floor();
+        remainder(b).total_cmp(&remainder(a))
+    });
+    let short = cells - counts.iter().sum::<usize>();
+    for &i in by_remainder.iter().take(short) {
+        counts[i] += 1;
+    }
+    Ok(mix.iter().map(|&(s, _)| s).zip(counts).collect())
+}
+
+/// One strategy per cell, in row-major order, with `counts` of each placed by `placement`.
+pub(crate) fn place<R: Rng + ?Sized>(
+    counts: &[(Strategy, usize)],
+    placement: Placement,
+    rng: &mut R,
+) -> Vec<Strategy> {
+    let mut cells: Vec<Strategy> = counts
+        .iter()
+        .flat_map(|&(s, n)| std::iter::repeat_n(s, n))
+        .collect();
+    if placement == Placement::Shuffled {
+        cells.shuffle(rng);
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    fn counts(mix: &[(Strategy, f32)], cells: usize) -> Vec<usize> {
+        exact_counts(mix, cells)
+            .unwrap()
+            .into_iter()
+            .map(|(_, n)| n)
+            .collect()
+    }
+
+    #[test]
+    fn test_largest_remainder() {
+        assert_eq!(counts(&[(C, 0.1), (D, 0.9)], 400), [40, 360]);
+        // 33.33 each plus one left over, which the first of the tied remainders gets.
+        assert_eq!(counts(&[(C, 1.0), (D, 1.0), (T, 1.0)], 100), [34, 33, 33]);
+        // 7 * 0.15 = 1.05, 7 * 0.35 = 2.45, 7 * 0.5 = 3.5.
+        assert_eq!(counts(&[(C, 0.15), (D, 0.35), (T, 0.5)], 7), [1, 2, 4]);
+        // Percentages that don't add up to 100 are shares of their total.
+        assert_eq!(counts(&[(C, 10.0), (D, 30.0)], 10), [3, 7]);
+        assert_eq!(counts(&[(C, 0.001), (D, 0.999)], 9), [0, 9]);
+        assert_eq!(counts(&[(C, 0.0), (D, 2.0)], 5), [0, 5]);
+    }
+
+    #[test]
+    fn test_invalid_mixes() {
+        assert_eq!(exact_counts(&[], 4), Err(Error::EmptyStrategyPool));
+        assert_eq!(exact_counts(&[(C, 0.0)], 4), Err(Error::EmptyStrategyPool));
+        assert_eq!(
+            exact_counts(&[(C, 0.5), (D, -0.5)], 4),
+            Err(Error::InvalidMix {
+                strategy: D,
+                fraction: -0.5
+            })
+        );
+        assert!(exact_counts(&[(C, f32::NAN)], 4).is_err());
+        assert!(exact_counts(&[(C, f32::INFINITY)], 4).is_err());
+    }
+
+    #[test]
+    fn test_placement() {
+        let counts = [(C, 2), (D, 3), (T, 1)];
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            place(&counts, Placement::Blocked, &mut rng),
+            [C, C, D, D, D, T]
+        );
+        let mut shuffled = place(&counts, Placement::Shuffled, &mut rng);
+        shuffled.sort();
+        let mut blocked = place(&counts, Placement::Blocked, &mut rng);
+        blocked.sort();
+        assert_eq!(shuffled, blocked);
+    }
+}
@@ -0,0 +1,267 @@
+use std::sync::Arc;
+
+/// Which cells around a cell count as its neighbors. Neighborhoods are cut off at the grid's
+/// edges.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Neighborhood {
+    /// Every cell at most this many rows and columns away: the 8 surrounding cells for 1, the
+    /// 24 of the 5x5 square around it for 2.
+    Moore(usize),
+    /// Every cell at most this many horizontal plus vertical steps away: the 4 side neighbors
+    /// for 1, 12 cells for 2.
+    VonNeumann(usize),
+}
+
+impl Default for Neighborhood {
+    fn default() -> Self {
+        Neighborhood::Moore(1)
+    }
+}
+
+impl Neighborhood {
+    pub fn radius(self) -> usize {
+        match self {
+            Neighborhood::Moore(r) | Neighborhood::VonNeumann(r) => r,
+        }
+    }
+
+    /// Whether it holds any cell at all.
+    pub fn is_valid(self) -> bool {
+        self.radius() > 0
+    }
+
+    /// Most neighbors a cell can have, away from the edges.
+    pub fn size(self) -> usize {
+        self.offsets().len()
+    }
+
+    /// `(row, col)` offsets of the neighbors, in row-major order.
+    fn offsets(self) -> Vec<(isize, isize)> {
+        let r = self.radius() as isize;
+        (-r..=r)
+            .flat_map(|dr| (-r..=r).map(move |dc| (dr, dc)))
+            .filter(|&(dr, dc)| (dr, dc) != (0, 0))
+            .filter(|&(dr, dc)| match self {
+                Neighborhood::Moore(_) => true,
+                Neighborhood::VonNeumann(_) => dr.abs() + dc.abs() <= r,
+            })
+            .collect()
+    }
+}
+
+/// The neighbors of every cell of a grid, by row-major cell index, computed once per grid. Each
+/// cell has `width` slots, as many as its neighborhood's size, so per-pair data can live in
+/// flat buffers indexed by `cell * width + slot`.
+#[derive(Clone, Debug)]
+pub(crate) struct NeighborTable {
+    /// Neighbor indices, `width` slots per cell of which the first `counts[cell]` are used, in
+    /// row-major order.
+    slots: Vec<usize>,
+    counts: Vec<usize>,
+    width: usize,
+    /// For each used slot, the slot the cell holds in that neighbor's own list.
+    back: Vec<usize>,
+}
+
+impl NeighborTable {
+    /// The eight surrounding cells of each cell, cut off at the grid's edges.
+    #[cfg(test)]
+    pub fn new(rows: usize, cols: usize) -> NeighborTable {
+        NeighborTable::without(rows, cols, &[])
+    }
+
+    /// Like `new`, but cells marked in `vacant` have no neighbors and are nobody's neighbor.
+    /// Cells past the end of `vacant` are occupied.
+    #[cfg(test)]
+    pub fn without(rows: usize, cols: usize, vacant: &[bool]) -> NeighborTable {
+        NeighborTable::linking(rows, cols, Neighborhood::default(), vacant, |_, _| true)
+    }
+
+    /// Every cell's `neighborhood` but for vacant cells, like `without`, and keeping only the
+    /// cells `linked` says belong together; `linked` must be symmetric.
+    pub fn linking(
+        rows: usize,
+        cols: usize,
+        neighborhood: Neighborhood,
+        vacant: &[bool],
+        linked: impl Fn(usize, usize) -> bool,
+    ) -> NeighborTable {
+        let is_vacant = |cell: usize| vacant.get(cell).copied().unwrap_or(false);
+        let offsets = neighborhood.offsets();
+        let (len, width) = (rows * cols, offsets.len());
+        let mut slots = vec![0; len * width];
+        let mut counts = vec![0; len];
+        for (cell, count) in counts.iter_mut().enumerate() {
+            if is_vacant(cell) {
+                continue;
+            }
+            let (x, y) = (cell / cols, cell % cols);
+            for &(dx, dy) in &offsets {
+                let nx = x.checked_add_signed(dx).filter(|&n| n < rows);
+                let ny = y.checked_add_signed(dy).filter(|&n| n < cols);
+                if let (Some(nx), Some(ny)) = (nx, ny) {
+                    if is_vacant(nx * cols + ny) || !linked(cell, nx * cols + ny) {
+                        continue;
+                    }
+                    slots[cell * width + *count] = nx * cols + ny;
+                    *count += 1;
+                }
+            }
+        }
+        let mut table = NeighborTable {
+            slots,
+            counts,
+            width,
+            back: vec![0; len * width],
+        };
+        for cell in 0..len {
+            for slot in 0..table.counts[cell] {
+                let neighbor = table.slots[cell * width + slot];
+                // Neighborhoods are symmetric, so `cell` is always in its neighbor's list.
+                let back = table.of(neighbor).iter().position(|&n| n == cell);
+                table.back[cell * width + slot] = back.unwrap_or_default();
+            }
+        }
+        table
+    }
+
+    /// Number of cells.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Neighbor indices of `cell` in row-major order.
+    pub fn of(&self, cell: usize) -> &[usize] {
+        &self.slots[cell * self.width..][..self.counts[cell]]
+    }
+
+    /// The slot `cell` holds in the list of its neighbor in `slot`.
+    pub fn back(&self, cell: usize, slot: usize) -> usize {
+        self.back[cell * self.width + slot]
+    }
+
+    /// Slots per cell.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Total number of ordered neighbor pairs.
+    pub fn pairs(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
+
+/// The tables of a grid with no vacant cells and no classes, worked out once and shared by
+/// every environment of that size and those neighborhoods; an environment only builds its own
+/// once cells empty or classes split it.
+#[derive(Clone, Debug)]
+pub(crate) struct Tables {
+    pub interaction: Neighborhood,
+    pub adaptation: Neighborhood,
+    /// Every cell's interaction neighbors, which is also who plays whom.
+    pub topology: Arc<NeighborTable>,
+    /// Whom agents imitate, when that's not who they play.
+    pub models: Option<Arc<NeighborTable>>,
+}
+
+impl Tables {
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        interaction: Neighborhood,
+        adaptation: Neighborhood,
+    ) -> Tables {
+        let table = |n| Arc::new(NeighborTable::linking(rows, cols, n, &[], |_, _| true));
+        Tables {
+            interaction,
+            adaptation,
+            topology: table(interaction),
+            models: (adaptation != interaction).then(|| table(adaptation)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_neighbors() {
+        let table = NeighborTable::new(3, 4);
+        assert_eq!(table.len(), 12);
+        assert_eq!(table.of(0), &[1, 4, 5]);
+        assert_eq!(table.of(5), &[0, 1, 2, 4, 6, 8, 9, 10]);
+        assert_eq!(table.of(11), &[6, 7, 10]);
+        assert_eq!(table.pairs(), 2 * (2 * 4 + 3 * 3 + 2 * 2 * 3));
+    }
+
+    #[test]
+    fn test_back_slots() {
+        let table = NeighborTable::new(4, 5);
+        for cell in 0..table.len() {
+            for (slot, &neighbor) in table.of(cell).iter().enumerate() {
+                assert_eq!(table.of(neighbor)[table.back(cell, slot)], cell);
+            }
+        }
+    }
+
+    #[test]
+    fn test_vacant_cells() {
+        let mut vacant = vec![false; 12];
+        vacant[5] = true;
+        let table = NeighborTable::without(3, 4, &vacant);
+        assert!(table.of(5).is_empty());
+        assert_eq!(table.of(0), &[1, 4]);
+        assert_eq!(table.of(10), &[6, 7, 9, 11]);
+        for cell in 0..table.len() {
+            for (slot, &neighbor) in table.of(cell).iter().enumerate() {
+                assert_eq!(table.of(neighbor)[table.back(cell, slot)], cell);
+            }
+        }
+        let full = NeighborTable::new(3, 4);
+        assert_eq!(table.pairs(), full.pairs() - 2 * full.of(5).len());
+    }
+
+    #[test]
+    fn test_linked_cells() {
+        let checkerboard = |a: usize, b: usize| (a / 4 + a % 4 + b / 4 + b % 4) % 2 == 1;
+        let table = NeighborTable::linking(3, 4, Neighborhood::default(), &[], checkerboard);
+        assert_eq!(table.of(0), &[1, 4]);
+        assert_eq!(table.of(5), &[1, 4, 6, 9]);
+        for cell in 0..table.len() {
+            for (slot, &neighbor) in table.of(cell).iter().enumerate() {
+                assert_eq!(table.of(neighbor)[table.back(cell, slot)], cell);
+            }
+        }
+    }
+
+    #[test]
+    fn test_neighborhood_shapes() {
+        assert_eq!(Neighborhood::Moore(1).size(), 8);
+        assert_eq!(Neighborhood::Moore(2).size(), 24);
+        assert_eq!(Neighborhood::VonNeumann(1).size(), 4);
+        assert_eq!(Neighborhood::VonNeumann(2).size(), 12);
+        assert!(!Neighborhood::Moore(0).is_valid());
+        let table = NeighborTable::linking(5, 5, Neighborhood::VonNeumann(1), &[], |_, _| true);
+        assert_eq!(table.width(), 4);
+        assert_eq!(table.of(12), &[7, 11, 13, 17]);
+        assert_eq!(table.of(0), &[1, 5]);
+        let table = NeighborTable::linking(5, 5, Neighborhood::Moore(2), &[], |_, _| true);
+        assert_eq!(table.of(12).len(), 24);
+        assert_eq!(table.of(0), &[1, 2, 5, 6, 7, 10, 11, 12]);
+        for cell in 0..table.len() {
+            for (slot, &neighbor) in table.of(cell).iter().enumerate() {
+                assert_eq!(table.of(neighbor)[table.back(cell, slot)], cell);
+            }
+        }
+    }
+
+    #[test]
+    fn test_degenerate_grids() {
+        let table = NeighborTable::new(1, 3);
+        assert_eq!(table.of(1), &[0, 2]);
+        let table = NeighborTable::new(1, 1);
+        assert!(table.of(0).is_empty());
+        assert_eq!(table.pairs(), 0);
+    }
+}
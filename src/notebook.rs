@@ -0,0 +1,239 @@
+//! Rich display in Rust notebooks run by evcxr: an [`Environment`] or a [`Snapshot`] at the
+//! end of a cell shows as a colored grid with a legend, and a [`History`] as a small chart of
+//! strategy counts. `to_html` gives the same markup outside a notebook.
+
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::agent::Strategy;
+use crate::colors::Palette;
+use crate::env::Environment;
+use crate::history::History;
+use crate::snapshot::Snapshot;
+
+/// Pixel size of a grid cell.
+const CELL_PX: usize = 6;
+const CHART_WIDTH: usize = 480;
+const CHART_HEIGHT: usize = 160;
+
+/// The palette every cell and chart in the session is drawn with, so a strategy keeps its
+/// color from one cell to the next.
+fn palette() -> &'static Palette {
+    static PALETTE: OnceLock<Palette> = OnceLock::new();
+    PALETTE.get_or_init(Palette::default)
+}
+
+/// The CSS color of `strategy`.
+pub fn strategy_color(strategy: Strategy) -> String {
+    let [r, g, b] = palette().color(strategy);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Hands `html` to evcxr, which renders it below the cell.
+fn display_html(html: &str) {
+    println!("EVCXR_BEGIN_CONTENT text/html\n{}\nEVCXR_END_CONTENT", html);
+}
+
+/// A table with a colored cell per agent, blank where `occupant` is `None`, followed by a
+/// legend of the strategies shown.
+fn grid_html(
+    rows: usize,
+    cols: usize,
+    occupant: impl Fn(usize, usize) -> Option<Strategy>,
+) -> String {
+    let mut shown = Vec::new();
+    let mut html = String::from("<table style=\"border-collapse:collapse\">");
+    for r in 0..rows {
+        html += "<tr>";
+        for c in 0..cols {
+            let background = match occupant(r, c) {
+                Some(strategy) => {
+                    shown.push(strategy.kind());
+                    strategy_color(strategy)
+                }
+                None => "transparent".to_string(),
+            };
+            let _ = write!(
+                html,
+                "<td style=\"width:{px}px;height:{px}px;padding:0;background:{}\"></td>",
+                background,
+                px = CELL_PX
+            );
+        }
+        html += "</tr>";
+    }
+    html += "</table>";
+    shown.sort();
+    shown.dedup();
+    html + &legend(&shown)
+}
+
+fn legend(strategies: &[Strategy]) -> String {
+    let mut html = String::from("<div>");
+    for &strategy in strategies {
+        let _ = write!(
+            html,
+            "<span style=\"margin-right:12px\"><span style=\"display:inline-block;width:10px;\
+             height:10px;background:{}\"></span> {}</span>",
+            strategy_color(strategy),
+            strategy.name()
+        );
+    }
+    html + "</div>"
+}
+
+impl Snapshot {
+    /// The grid as an HTML table with a legend.
+    pub fn to_html(&self) -> String {
+        grid_html(self.rows(), self.cols(), |r, c| self.occupant(r, c))
+    }
+
+    /// Shows the grid when a notebook cell evaluates to this snapshot.
+    pub fn evcxr_display(&self) {
+        display_html(&self.to_html());
+    }
+}
+
+impl Environment {
+    /// The current grid as an HTML table with a legend.
+    pub fn to_html(&self) -> String {
+        let (rows, cols) = self.dimensions();
+        let mut cells = vec![None; rows * cols];
+        for ((r, c), agent) in self.iter_agents() {
+            cells[r * cols + c] = Some(agent.strategy);
+        }
+        grid_html(rows, cols, |r, c| cells[r * cols + c])
+    }
+
+    /// Shows the grid when a notebook cell evaluates to this environment.
+    pub fn evcxr_display(&self) {
+        display_html(&self.to_html());
+    }
+}
+
+impl History {
+    /// An SVG chart of every strategy's count over the retained steps, with a legend.
+    pub fn to_html(&self) -> String {
+        let metrics: Vec<_> = self.iter().collect();
+        let mut strategies: Vec<Strategy> = metrics
+            .iter()
+            .flat_map(|(_, m)| m.strategies.keys().copied())
+            .collect();
+        strategies.sort();
+        strategies.dedup();
+        let first = metrics.first().map_or(0, |(s, _)| *s) as f64;
+        let last = metrics.last().map_or(0, |(s, _)| *s) as f64;
+        let most = metrics
+            .iter()
+            .flat_map(|(_, m)| m.strategies.values())
+            .max()
+            .copied()
+            .unwrap_or(0)
+            .max(1) as f64;
+        let x = |step: usize| {
+            if last > first {
+                (step as f64 - first) / (last - first) * (CHART_WIDTH - 1) as f64
+            } else {
+                0.0
+            }
+        };
+        let y = |count: usize| (CHART_HEIGHT - 1) as f64 * (1.0 - count as f64 / most);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\"><rect width=\"{w}\" height=\"{h}\" fill=\"none\" \
+             stroke=\"#888\"/>",
+            w = CHART_WIDTH,
+            h = CHART_HEIGHT
+        );
+        for &strategy in &strategies {
+            let points: Vec<String> = metrics
+                .iter()
+                .map(|(step, m)| {
+                    let count = m.strategies.get(&strategy).copied().unwrap_or(0);
+                    format!("{:.1},{:.1}", x(*step), y(count))
+                })
+                .collect();
+            let _ = write!(
+                svg,
+                "<polyline fill=\"none\" stroke=\"{}\" points=\"{}\"/>",
+                strategy_color(strategy),
+                points.join(" ")
+            );
+        }
+        svg += "</svg>";
+        svg + &legend(&strategies)
+    }
+
+    /// Shows the chart when a notebook cell evaluates to this history.
+    pub fn evcxr_display(&self) {
+        display_html(&self.to_html());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    #[test]
+    fn test_colors_cover_every_strategy() {
+        let mut colors: Vec<String> = Strategy::ALL.iter().map(|s| strategy_color(*s)).collect();
+        colors.sort();
+        colors.dedup();
+        assert_eq!(colors.len(), Strategy::ALL.len());
+        assert_eq!(strategy_color(D), "#cd3131");
+    }
+
+    #[test]
+    fn test_snapshot_html() {
+        let snapshot = Snapshot::from(vec![vec![D, T, T], vec![C, C, C]]);
+        let html = snapshot.to_html();
+        assert_eq!(html.matches("<tr>").count(), 2);
+        assert_eq!(html.matches("<td ").count(), 6);
+        let cells_of = |s| {
+            html.matches(&format!("background:{}\"></td>", strategy_color(s)))
+                .count()
+        };
+        assert_eq!((cells_of(D), cells_of(T), cells_of(C)), (1, 2, 3));
+        for s in [D, T, C] {
+            assert!(html.contains(&format!("</span> {}</span>", s.name())));
+        }
+        assert!(!html.contains("Random"));
+    }
+
+    #[test]
+    fn test_environment_html() {
+        let env = Environment::new_with_seed(3, 4, 0.0, 1, |c, _| {
+            Agent::new(c, if c.1 == 0 { D } else { C })
+        })
+        .unwrap();
+        let html = env.to_html();
+        assert_eq!(html.matches("<td ").count(), 12);
+        let deflect = format!("background:{}\"></td>", strategy_color(D));
+        assert_eq!(html.matches(&deflect).count(), 3);
+        let expected = Snapshot::from(vec![vec![D, C, C, C]; 3]);
+        assert_eq!(html, expected.to_html());
+    }
+
+    #[test]
+    fn test_history_html() {
+        let mut env = Environment::new_with_seed(4, 4, 0.1, 3, |c, _| {
+            Agent::new(c, if (c.0 + c.1) % 2 == 0 { D } else { T })
+        })
+        .unwrap();
+        let mut history = History::new(50, 10);
+        for _ in 0..20 {
+            history.push(env.step());
+        }
+        let html = history.to_html();
+        assert!(html.starts_with("<svg ") && html.contains("</svg>"));
+        let played = history
+            .iter()
+            .flat_map(|(_, m)| m.strategies.keys().copied())
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(html.matches("<polyline").count(), played.len());
+        assert!(html.contains(&format!("stroke=\"{}\"", strategy_color(D))));
+        assert!(History::new(10, 10).to_html().starts_with("<svg "));
+    }
+}
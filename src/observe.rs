@@ -0,0 +1,324 @@
+//! Watching the games of a run one at a time. An [`Observer`] attached to an environment sees
+//! every game played, after noise, with what it paid; [`SamplingRecorder`] keeps a random
+//! fraction of them for runs too big to keep them all.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::agent::{Action, Coord, Strategy};
+use crate::error::Error;
+use crate::json;
+
+/// One game between two neighbors, as played: `first` is the agent earlier in row-major
+/// order. Actions are the realized ones, after noise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interaction {
+    pub step: usize,
+    pub first: Coord,
+    pub second: Coord,
+    pub first_strategy: Strategy,
+    pub second_strategy: Strategy,
+    pub first_action: Action,
+    pub second_action: Action,
+    pub first_payoff: f64,
+    pub second_payoff: f64,
+}
+
+/// Sees every game an environment plays; attach one with
+/// [`Environment::set_observer`](crate::Environment::set_observer). Games refused under
+/// partner choice aren't played, so they aren't seen.
+pub trait Observer: Send {
+    /// Called once per game, in row-major order of the first agent.
+    fn on_interaction(&mut self, interaction: &Interaction);
+
+    /// Called after the last game of `step`.
+    fn end_step(&mut self, _step: usize) {}
+}
+
+/// An observer shared between the environment calling it and whoever reads it afterwards.
+pub type SharedObserver = Arc<Mutex<dyn Observer>>;
+
+/// Records kept in memory by default before spilling or dropping.
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Records each game with probability `rate`, drawn from a stream of its own, so attaching
+/// it changes nothing about the run. Records are kept in memory up to a capacity; past it
+/// they're written to disk as NDJSON if a spill file was given, and dropped otherwise.
+#[derive(Debug)]
+pub struct SamplingRecorder {
+    rate: f64,
+    rng: StdRng,
+    capacity: usize,
+    records: Vec<Interaction>,
+    spill: Option<BufWriter<File>>,
+    spilled: usize,
+    dropped: usize,
+    /// The first failed write to the spill file, reported by `flush`.
+    error: Option<io::Error>,
+}
+
+impl SamplingRecorder {
+    /// A recorder keeping a `rate` fraction of games, in [0, 1], sampled with `seed`.
+    pub fn new(rate: f64, seed: u64) -> Result<SamplingRecorder, Error> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(Error::InvalidSampleRate(rate));
+        }
+        Ok(SamplingRecorder {
+            rate,
+            rng: StdRng::seed_from_u64(seed),
+            capacity: DEFAULT_CAPACITY,
+            records: Vec::new(),
+            spill: None,
+            spilled: 0,
+            dropped: 0,
+            error: None,
+        })
+    }
+
+    /// Keeps at most `capacity` records in memory.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Writes the records in memory to a new file at `path`, one JSON object per line, each
+    /// time they reach the capacity.
+    pub fn spill_to(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.spill = Some(BufWriter::new(File::create(path)?));
+        Ok(self)
+    }
+
+    /// The fraction of games recorded.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// The records in memory, oldest first.
+    pub fn records(&self) -> &[Interaction] {
+        &self.records
+    }
+
+    /// Takes the records in memory, making room for more.
+    pub fn take(&mut self) -> Vec<Interaction> {
+        std::mem::take(&mut self.records)
+    }
+
+    /// Games sampled so far, whether in memory, spilled or dropped.
+    pub fn sampled(&self) -> usize {
+        self.records.len() + self.spilled + self.dropped
+    }
+
+    /// Records written to the spill file so far.
+    pub fn spilled(&self) -> usize {
+        self.spilled
+    }
+
+    /// Sampled games lost because memory was full and there was nowhere to spill them.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Writes the records in memory to the spill file, if there is one, and flushes it.
+    /// Reports the first write that failed since the last flush.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.spill.is_some() {
+            self.spill_records();
+        }
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        match self.spill.as_mut() {
+            Some(spill) => spill.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn spill_records(&mut self) {
+        let Some(spill) = self.spill.as_mut() else {
+            return;
+        };
+        for record in self.records.drain(..) {
+            match writeln!(spill, "{}", json::interaction_json(&record)) {
+                Ok(()) => self.spilled += 1,
+                Err(e) => {
+                    self.dropped += 1;
+                    self.error.get_or_insert(e);
+                }
+            }
+        }
+    }
+}
+
+impl Observer for SamplingRecorder {
+    fn on_interaction(&mut self, interaction: &Interaction) {
+        if !self.rng.gen_bool(self.rate) {
+            return;
+        }
+        if self.records.len() >= self.capacity {
+            self.spill_records();
+        }
+        if self.records.len() < self.capacity {
+            self.records.push(*interaction);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::builder::EnvironmentBuilder;
+    use crate::env::{Environment, Payoffs, UpdateMode};
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    fn attach(env: &mut Environment, recorder: SamplingRecorder) -> Arc<Mutex<SamplingRecorder>> {
+        let recorder = Arc::new(Mutex::new(recorder));
+        env.set_observer(Some(recorder.clone()));
+        recorder
+    }
+
+    fn mixed(seed: u64, mode: UpdateMode) -> Environment {
+        EnvironmentBuilder::new()
+            .size(12, 12)
+            .seed(seed)
+            .noise(0.1)
+            .update_mode(mode)
+            .strategies(&[C, D, T, Strategy::Random])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_invalid_rate() {
+        for rate in [-0.1, 1.5, f64::NAN] {
+            assert!(matches!(
+                SamplingRecorder::new(rate, 0),
+                Err(Error::InvalidSampleRate(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_sampling_rate() {
+        let mut env = mixed(4, UpdateMode::Synchronous);
+        let recorder = attach(&mut env, SamplingRecorder::new(0.1, 7).unwrap());
+        for _ in 0..20 {
+            env.step();
+        }
+        // 144 agents on a Moore grid play 506 games a step.
+        let games = 506.0 * 20.0;
+        let sampled = recorder.lock().unwrap().sampled() as f64;
+        // Four standard deviations of a binomial draw either way.
+        let sd = (games * 0.1 * 0.9_f64).sqrt();
+        assert!((sampled - games * 0.1).abs() < 4.0 * sd, "{}", sampled);
+    }
+
+    #[test]
+    fn test_full_record_of_tiny_run() {
+        // A Deflect between two Coops in a row: each pair plays once a step.
+        let recorder = Arc::new(Mutex::new(SamplingRecorder::new(1.0, 0).unwrap()));
+        let mut env = EnvironmentBuilder::new()
+            .size(1, 3)
+            .payoffs(Payoffs {
+                reward: 3.0,
+                temptation: 5.0,
+                sucker: -1.0,
+                punishment: 1.0,
+            })
+            .agents(|c, _| Agent::new(c, if c == (0, 1) { D } else { C }))
+            .observer(recorder.clone())
+            .build()
+            .unwrap();
+        let metric = env.step();
+        let records = recorder.lock().unwrap().take();
+        let expected = |first, second| Interaction {
+            step: 0,
+            first,
+            second,
+            first_strategy: if first == (0, 1) { D } else { C },
+            second_strategy: if second == (0, 1) { D } else { C },
+            first_action: if first == (0, 1) {
+                Action::Deflect
+            } else {
+                Action::Coop
+            },
+            second_action: if second == (0, 1) {
+                Action::Deflect
+            } else {
+                Action::Coop
+            },
+            first_payoff: if first == (0, 1) { 5.0 } else { -1.0 },
+            second_payoff: if second == (0, 1) { 5.0 } else { -1.0 },
+        };
+        assert_eq!(
+            records,
+            vec![expected((0, 0), (0, 1)), expected((0, 1), (0, 2))]
+        );
+        let total: f64 = records
+            .iter()
+            .map(|r| r.first_payoff + r.second_payoff)
+            .sum();
+        assert_eq!(total, metric.scores.cells().iter().sum::<f64>());
+    }
+
+    #[test]
+    fn test_recorder_leaves_run_alone() {
+        for mode in [
+            UpdateMode::Legacy,
+            UpdateMode::Synchronous,
+            UpdateMode::Asynchronous,
+        ] {
+            let mut plain = mixed(11, mode);
+            let mut watched = mixed(11, mode);
+            let recorder = attach(&mut watched, SamplingRecorder::new(0.5, 3).unwrap());
+            for _ in 0..10 {
+                let (a, b) = (plain.step(), watched.step());
+                assert_eq!(a.strategies, b.strategies);
+                assert_eq!(a.coop_actions, b.coop_actions);
+                assert_eq!(a.scores.cells(), b.scores.cells());
+            }
+            assert!(recorder.lock().unwrap().sampled() > 0);
+        }
+    }
+
+    #[test]
+    fn test_spill_to_ndjson() {
+        let path = std::env::temp_dir().join(format!("coop-spill-{}.ndjson", std::process::id()));
+        let mut env = mixed(2, UpdateMode::Synchronous);
+        let recorder = SamplingRecorder::new(1.0, 0)
+            .unwrap()
+            .with_capacity(100)
+            .spill_to(&path)
+            .unwrap();
+        let recorder = attach(&mut env, recorder);
+        env.step();
+        env.step();
+        let mut recorder = recorder.lock().unwrap();
+        assert_eq!(recorder.dropped(), 0);
+        assert!(recorder.records().len() <= 100);
+        recorder.flush().unwrap();
+        assert!(recorder.records().is_empty());
+        assert_eq!(recorder.spilled(), 2 * 506);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2 * 506);
+        assert!(lines[0].starts_with(r#"{"step":0,"first":[0,0],"second":[0,1],"#));
+        assert!(lines.last().unwrap().starts_with(r#"{"step":1,"#));
+        // Without a spill file, whatever doesn't fit is dropped.
+        let mut env = mixed(2, UpdateMode::Synchronous);
+        let recorder = attach(
+            &mut env,
+            SamplingRecorder::new(1.0, 0).unwrap().with_capacity(100),
+        );
+        env.step();
+        let recorder = recorder.lock().unwrap();
+        assert_eq!((recorder.records().len(), recorder.dropped()), (100, 406));
+    }
+}
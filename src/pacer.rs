@@ -0,0 +1,144 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// Selectable simulation rates in steps per second; `None` runs as fast as possible.
+pub const SPEEDS: [Option<u32>; 11] = [
+    Some(1),
+    Some(2),
+    Some(5),
+    Some(10),
+    Some(20),
+    Some(50),
+    Some(100),
+    Some(200),
+    Some(500),
+    Some(1000),
+    None,
+];
+
+/// Paces at one of the `SPEEDS`, selected with `faster` and `slower`.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    level: usize,
+    pacing: coop::pacing::Pacer,
+}
+
+impl Pacer {
+    pub fn new(level: usize) -> Pacer {
+        let level = level.min(SPEEDS.len() - 1);
+        Pacer {
+            level,
+            pacing: coop::pacing::Pacer::new(SPEEDS[level]),
+        }
+    }
+
+    /// Target steps per second, or `None` when uncapped.
+    pub fn target_rate(&self) -> Option<u32> {
+        SPEEDS[self.level]
+    }
+
+    pub fn faster(&mut self) {
+        self.set_level(self.level + 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.set_level(self.level.saturating_sub(1));
+    }
+
+    fn set_level(&mut self, level: usize) {
+        self.level = level.min(SPEEDS.len() - 1);
+        self.pacing.set_rate(SPEEDS[self.level]);
+    }
+
+    /// Number of steps to run at `now`; see [`coop::pacing::Pacer::due`].
+    pub fn due(&mut self, now: Duration) -> usize {
+        self.pacing.due(now)
+    }
+}
+
+/// Measures the achieved step rate over a sliding window.
+#[derive(Clone, Debug)]
+pub struct RateMeter {
+    window: Duration,
+    samples: VecDeque<(Duration, usize)>,
+}
+
+impl RateMeter {
+    pub fn new(window: Duration) -> RateMeter {
+        RateMeter {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, now: Duration, steps: usize) {
+        self.samples.push_back((now, steps));
+        while let Some(&(t, _)) = self.samples.front() {
+            if now.saturating_sub(t) > self.window {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Steps per second over the samples currently in the window.
+    pub fn rate(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(&(first, _)), Some(&(last, _))) if last > first => {
+                let steps: usize = self.samples.iter().skip(1).map(|(_, n)| n).sum();
+                steps as f64 / (last - first).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_slow_rate_skips_frames() {
+        let mut pacer = Pacer::new(0);
+        assert_eq!(pacer.target_rate(), Some(1));
+        assert_eq!(pacer.due(ms(0)), 0);
+        let steps: usize = (1..=200).map(|i| pacer.due(ms(i * 10))).sum();
+        assert_eq!(steps, 2);
+    }
+
+    #[test]
+    fn test_fast_rate_runs_multiple_steps_per_frame() {
+        let mut pacer = Pacer::new(9);
+        assert_eq!(pacer.target_rate(), Some(1000));
+        pacer.due(ms(0));
+        assert_eq!(pacer.due(ms(10)), 10);
+        assert_eq!(pacer.due(ms(25)), 15);
+    }
+
+    #[test]
+    fn test_speed_levels_clamp() {
+        let mut pacer = Pacer::new(0);
+        pacer.slower();
+        assert_eq!(pacer.target_rate(), Some(1));
+        for _ in 0..SPEEDS.len() + 2 {
+            pacer.faster();
+        }
+        assert_eq!(pacer.target_rate(), None);
+        assert_eq!(pacer.due(ms(0)), usize::MAX);
+    }
+
+    #[test]
+    fn test_rate_meter() {
+        let mut meter = RateMeter::new(Duration::from_secs(1));
+        assert_eq!(meter.rate(), 0.0);
+        for i in 0..=20 {
+            meter.record(ms(i * 100), 5);
+        }
+        // Only the last second of samples is kept: 10 intervals of 5 steps over 1s.
+        assert!((meter.rate() - 50.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,112 @@
+//! Turning a target rate into a number of due steps, for the runner and the TUI's playback.
+
+use std::time::Duration;
+
+/// Never carry more than this much backlog, so a slow frame doesn't cause a burst of catch-up steps.
+const MAX_BACKLOG: Duration = Duration::from_millis(250);
+
+/// Decides how many steps are due at a given time. Time is passed in explicitly (as the elapsed
+/// duration since some fixed origin) so the pacing can be driven by a fake clock in tests.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    rate: Option<u32>,
+    last: Option<Duration>,
+    backlog: Duration,
+}
+
+impl Pacer {
+    /// `rate` steps per second, or uncapped for `None` or zero.
+    pub fn new(rate: Option<u32>) -> Pacer {
+        Pacer {
+            rate: rate.filter(|&r| r > 0),
+            last: None,
+            backlog: Duration::ZERO,
+        }
+    }
+
+    /// Target steps per second, or `None` when uncapped.
+    pub fn target_rate(&self) -> Option<u32> {
+        self.rate
+    }
+
+    /// A zero rate counts as uncapped, like `None`.
+    pub fn set_rate(&mut self, rate: Option<u32>) {
+        self.rate = rate.filter(|&r| r > 0);
+    }
+
+    /// Forget accumulated time, e.g. while paused, so resuming doesn't replay the pause.
+    pub fn reset(&mut self, now: Duration) {
+        self.last = Some(now);
+        self.backlog = Duration::ZERO;
+    }
+
+    /// Number of steps to run at `now`. Uncapped speed returns `usize::MAX`; the caller is
+    /// expected to bound the work by its own budget in that case.
+    pub fn due(&mut self, now: Duration) -> usize {
+        let last = self.last.unwrap_or(now);
+        self.last = Some(now);
+        let rate = match self.rate {
+            Some(rate) => rate,
+            None => {
+                self.backlog = Duration::ZERO;
+                return usize::MAX;
+            }
+        };
+        let interval = Duration::from_secs(1) / rate;
+        self.backlog = (self.backlog + now.saturating_sub(last)).min(MAX_BACKLOG.max(interval));
+        let steps = (self.backlog.as_nanos() / interval.as_nanos()) as usize;
+        self.backlog -= interval * steps as u32;
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_slow_rate_skips_frames() {
+        let mut pacer = Pacer::new(Some(1));
+        assert_eq!(pacer.due(ms(0)), 0);
+        let steps: usize = (1..=200).map(|i| pacer.due(ms(i * 10))).sum();
+        assert_eq!(steps, 2);
+    }
+
+    #[test]
+    fn test_fast_rate_runs_multiple_steps() {
+        let mut pacer = Pacer::new(Some(1000));
+        pacer.due(ms(0));
+        assert_eq!(pacer.due(ms(10)), 10);
+        assert_eq!(pacer.due(ms(25)), 15);
+    }
+
+    #[test]
+    fn test_backlog_is_capped() {
+        let mut pacer = Pacer::new(Some(100));
+        pacer.due(ms(0));
+        assert_eq!(pacer.due(ms(10_000)), 25);
+    }
+
+    #[test]
+    fn test_reset_discards_paused_time() {
+        let mut pacer = Pacer::new(Some(10));
+        pacer.due(ms(0));
+        pacer.reset(ms(5_000));
+        assert_eq!(pacer.due(ms(5_050)), 0);
+        assert_eq!(pacer.due(ms(5_100)), 1);
+    }
+
+    #[test]
+    fn test_uncapped() {
+        let mut pacer = Pacer::new(Some(0));
+        assert_eq!(pacer.due(ms(0)), usize::MAX);
+        pacer.set_rate(Some(10));
+        assert_eq!(pacer.due(ms(100)), 1);
+        pacer.set_rate(None);
+        assert_eq!(pacer.due(ms(100)), usize::MAX);
+    }
+}
@@ -0,0 +1,335 @@
+//! What happened when strategies met: games tallied by the strategies on both sides at the
+//! moment they were played, so a TicToc that later turned Deflect counts as TicToc for the
+//! games it played as one.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::agent::{Action, Strategy};
+use crate::observe::{Interaction, Observer};
+
+/// The games between agents of two strategy kinds, from the side of `first`. For a kind
+/// against itself, `first` is whichever agent comes earlier in row-major order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PairStats {
+    pub first: Strategy,
+    pub second: Strategy,
+    pub games: usize,
+    /// Games where both sides cooperated.
+    pub mutual_coop: usize,
+    /// Games where `first` defected against a cooperating `second`.
+    pub first_exploits: usize,
+    /// Games where `second` defected against a cooperating `first`.
+    pub second_exploits: usize,
+    /// Everything `first` earned in these games.
+    pub first_payoff: f64,
+    pub second_payoff: f64,
+}
+
+impl PairStats {
+    fn new(first: Strategy, second: Strategy) -> PairStats {
+        PairStats {
+            first,
+            second,
+            games: 0,
+            mutual_coop: 0,
+            first_exploits: 0,
+            second_exploits: 0,
+            first_payoff: 0.0,
+            second_payoff: 0.0,
+        }
+    }
+
+    /// The same games from the side of `second`.
+    pub fn flipped(&self) -> PairStats {
+        PairStats {
+            first: self.second,
+            second: self.first,
+            first_exploits: self.second_exploits,
+            second_exploits: self.first_exploits,
+            first_payoff: self.second_payoff,
+            second_payoff: self.first_payoff,
+            ..*self
+        }
+    }
+
+    pub fn mutual_coop_rate(&self) -> f64 {
+        self.share(self.mutual_coop)
+    }
+
+    /// The share of games where `first` exploited `second`.
+    pub fn first_exploit_rate(&self) -> f64 {
+        self.share(self.first_exploits)
+    }
+
+    pub fn second_exploit_rate(&self) -> f64 {
+        self.share(self.second_exploits)
+    }
+
+    /// What `first` earned per game.
+    pub fn mean_first_payoff(&self) -> f64 {
+        self.first_payoff / self.games.max(1) as f64
+    }
+
+    pub fn mean_second_payoff(&self) -> f64 {
+        self.second_payoff / self.games.max(1) as f64
+    }
+
+    fn share(&self, count: usize) -> f64 {
+        count as f64 / self.games.max(1) as f64
+    }
+}
+
+/// Games tallied for each unordered pair of strategy kinds that met. Attach it to an
+/// environment as an [`Observer`] to count every game of a run, or feed it recorded games
+/// with [`analyze::pairing_outcomes`](crate::analyze::pairing_outcomes).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PairingOutcomes {
+    /// Keyed by the two kinds in `Strategy::ALL` order.
+    pairs: BTreeMap<(Strategy, Strategy), PairStats>,
+}
+
+impl PairingOutcomes {
+    pub fn new() -> PairingOutcomes {
+        PairingOutcomes::default()
+    }
+
+    /// Adds one game.
+    pub fn record(&mut self, interaction: &Interaction) {
+        let sides = [
+            (
+                interaction.first_strategy.kind(),
+                interaction.first_action,
+                interaction.first_payoff,
+            ),
+            (
+                interaction.second_strategy.kind(),
+                interaction.second_action,
+                interaction.second_payoff,
+            ),
+        ];
+        let [(a, a_action, a_payoff), (b, b_action, b_payoff)] = if sides[1].0 < sides[0].0 {
+            [sides[1], sides[0]]
+        } else {
+            sides
+        };
+        let stats = self
+            .pairs
+            .entry((a, b))
+            .or_insert_with(|| PairStats::new(a, b));
+        stats.games += 1;
+        match (a_action, b_action) {
+            (Action::Coop, Action::Coop) => stats.mutual_coop += 1,
+            (Action::Deflect, Action::Coop) => stats.first_exploits += 1,
+            (Action::Coop, Action::Deflect) => stats.second_exploits += 1,
+            (Action::Deflect, Action::Deflect) => {}
+        }
+        stats.first_payoff += a_payoff;
+        stats.second_payoff += b_payoff;
+    }
+
+    /// The games between kinds `first` and `second`, from the side of `first`, if any were
+    /// played.
+    pub fn get(&self, first: Strategy, second: Strategy) -> Option<PairStats> {
+        let (first, second) = (first.kind(), second.kind());
+        if second < first {
+            self.pairs.get(&(second, first)).map(PairStats::flipped)
+        } else {
+            self.pairs.get(&(first, second)).copied()
+        }
+    }
+
+    /// Every pair that met, in `Strategy::ALL` order of the first kind, then the second.
+    pub fn pairs(&self) -> impl Iterator<Item = &PairStats> {
+        self.pairs.values()
+    }
+
+    /// Games tallied over all pairs.
+    pub fn games(&self) -> usize {
+        self.pairs.values().map(|p| p.games).sum()
+    }
+
+    /// One row per pair that met, with rates and mean payoffs, headed by lowercase strategy
+    /// names.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "first,second,games,mutual_coop_rate,first_exploit_rate,second_exploit_rate,\
+             mean_first_payoff,mean_second_payoff\n",
+        );
+        for p in self.pairs() {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                p.first.name().to_lowercase(),
+                p.second.name().to_lowercase(),
+                p.games,
+                p.mutual_coop_rate(),
+                p.first_exploit_rate(),
+                p.second_exploit_rate(),
+                p.mean_first_payoff(),
+                p.mean_second_payoff()
+            ));
+        }
+        csv
+    }
+}
+
+impl Observer for PairingOutcomes {
+    fn on_interaction(&mut self, interaction: &Interaction) {
+        self.record(interaction);
+    }
+}
+
+/// A table with a row per pair that met: games, rates as percentages and mean payoffs.
+impl fmt::Display for PairingOutcomes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<25} {:>8} {:>7} {:>7} {:>7} {:>8} {:>8}",
+            "pair", "games", "mutual", "1st ex", "2nd ex", "1st pay", "2nd pay"
+        )?;
+        for p in self.pairs() {
+            write!(
+                f,
+                "\n{:<25} {:>8} {:>6.1}% {:>6.1}% {:>6.1}% {:>8.2} {:>8.2}",
+                format!("{} v {}", p.first.name(), p.second.name()),
+                p.games,
+                100.0 * p.mutual_coop_rate(),
+                100.0 * p.first_exploit_rate(),
+                100.0 * p.second_exploit_rate(),
+                p.mean_first_payoff(),
+                p.mean_second_payoff()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::builder::EnvironmentBuilder;
+    use crate::env::Payoffs;
+    use std::sync::{Arc, Mutex};
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    const PAYOFFS: Payoffs = Payoffs {
+        reward: 3.0,
+        temptation: 5.0,
+        sucker: -1.0,
+        punishment: 1.0,
+    };
+
+    #[test]
+    fn test_pairings_by_hand() {
+        // A Deflect between two TicToc in a row. In the first step the Deflect exploits both;
+        // both copy it before the second, which is all mutual defection.
+        let outcomes = Arc::new(Mutex::new(PairingOutcomes::new()));
+        let mut env = EnvironmentBuilder::new()
+            .size(1, 3)
+            .payoffs(PAYOFFS)
+            .agents(|c, _| Agent::new(c, if c == (0, 1) { D } else { T }))
+            .observer(outcomes.clone())
+            .build()
+            .unwrap();
+        env.step();
+        env.step();
+        assert!(env.iter_agents().all(|(_, a)| a.strategy == D));
+        let outcomes = outcomes.lock().unwrap();
+        assert_eq!(outcomes.games(), 4);
+        let td = outcomes.get(T, D).unwrap();
+        assert_eq!((td.first, td.second), (T, D));
+        assert_eq!(
+            (
+                td.games,
+                td.mutual_coop,
+                td.first_exploits,
+                td.second_exploits
+            ),
+            (2, 0, 0, 2)
+        );
+        assert_eq!((td.first_payoff, td.second_payoff), (-2.0, 10.0));
+        assert_eq!(td.second_exploit_rate(), 1.0);
+        assert_eq!(td.mean_second_payoff(), 5.0);
+        // Looked up the other way round, the same games from the Deflect's side.
+        assert_eq!(outcomes.get(D, T), Some(td.flipped()));
+        let dd = outcomes.get(D, D).unwrap();
+        assert_eq!((dd.games, dd.first_exploits, dd.second_exploits), (2, 0, 0));
+        assert_eq!(
+            (dd.mean_first_payoff(), dd.mean_second_payoff()),
+            (1.0, 1.0)
+        );
+        assert_eq!(outcomes.get(T, T), None);
+        assert_eq!(outcomes.get(C, D), None);
+    }
+
+    #[test]
+    fn test_pairings_add_up_to_scores() {
+        let outcomes = Arc::new(Mutex::new(PairingOutcomes::new()));
+        let mut env = EnvironmentBuilder::new()
+            .size(8, 8)
+            .seed(5)
+            .noise(0.1)
+            .strategies(&[C, D, T, Strategy::Random])
+            .observer(outcomes.clone())
+            .build()
+            .unwrap();
+        let earned = |outcomes: &PairingOutcomes| -> f64 {
+            outcomes
+                .pairs()
+                .map(|p| p.first_payoff + p.second_payoff)
+                .sum()
+        };
+        // Scores are running totals, so each step's games add up to how much they grew.
+        let (mut earned_before, mut scores_before) = (0.0, 0.0);
+        for _ in 0..5 {
+            let scores: f64 = env.step().scores.cells().iter().sum();
+            let earned = earned(&outcomes.lock().unwrap());
+            assert!((earned - earned_before - (scores - scores_before)).abs() < 1e-9);
+            (earned_before, scores_before) = (earned, scores);
+        }
+        let outcomes = outcomes.lock().unwrap();
+        // 64 agents on a Moore grid play 210 games a step.
+        assert_eq!(outcomes.games(), 5 * 210);
+        for p in outcomes.pairs() {
+            assert!(p.first <= p.second);
+            assert!(p.mutual_coop + p.first_exploits + p.second_exploits <= p.games);
+        }
+    }
+
+    #[test]
+    fn test_pairings_text() {
+        let mut outcomes = PairingOutcomes::new();
+        let game = |first_strategy, first_action, first_payoff| Interaction {
+            step: 0,
+            first: (0, 0),
+            second: (0, 1),
+            first_strategy,
+            second_strategy: C,
+            first_action,
+            second_action: Action::Coop,
+            first_payoff,
+            second_payoff: if first_action == Action::Coop {
+                3.0
+            } else {
+                -1.0
+            },
+        };
+        outcomes.record(&game(D, Action::Deflect, 5.0));
+        outcomes.record(&game(D, Action::Coop, 3.0));
+        outcomes.record(&game(C, Action::Coop, 3.0));
+        assert_eq!(
+            outcomes.to_csv(),
+            "first,second,games,mutual_coop_rate,first_exploit_rate,second_exploit_rate,\
+             mean_first_payoff,mean_second_payoff\n\
+             deflect,coop,2,0.5,0.5,0,4,1\n\
+             coop,coop,1,1,0,0,3,3\n"
+        );
+        assert_eq!(
+            outcomes.to_string(),
+            "pair                         games  mutual  1st ex  2nd ex  1st pay  2nd pay\n\
+             Deflect v Coop                   2   50.0%   50.0%    0.0%     4.00     1.00\n\
+             Coop v Coop                      1  100.0%    0.0%    0.0%     3.00     3.00"
+        );
+    }
+}
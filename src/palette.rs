@@ -0,0 +1,213 @@
+use std::sync::OnceLock;
+
+use coop::colors::{self, CYCLE_COLORS};
+use coop::Strategy;
+use ratatui::style::Color;
+
+/// How strategies are drawn; the single source for the canvas, legend, charts and exporters.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum Palette {
+    /// The original red/yellow/green/magenta colors.
+    #[default]
+    Classic,
+    /// Okabe-Ito colors that stay distinct under the common forms of color blindness.
+    Colorblind,
+    /// Shades of gray with a different block glyph per strategy.
+    Mono,
+}
+
+/// Palettes cycled with 't'.
+pub const PALETTES: [Palette; 3] = [Palette::Classic, Palette::Colorblind, Palette::Mono];
+
+impl Palette {
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Classic => "classic",
+            Palette::Colorblind => "colorblind",
+            Palette::Mono => "mono",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        PALETTES.into_iter().find(|p| p.name() == name)
+    }
+
+    pub fn next(self) -> Palette {
+        let i = PALETTES.iter().position(|p| *p == self).unwrap_or(0);
+        PALETTES[(i + 1) % PALETTES.len()]
+    }
+
+    /// `(strategy, color, glyph, name)` for every strategy in display order.
+    pub fn entries(self) -> Vec<(Strategy, Color, char, &'static str)> {
+        Strategy::ALL
+            .iter()
+            .map(|s| {
+                let (color, glyph) = entry(self, *s);
+                (*s, color, glyph, s.name())
+            })
+            .collect()
+    }
+
+    /// The color of `strategy`; variants with parameters of their own get one from the cycle
+    /// the first time they're drawn, and keep it for the rest of the session.
+    pub fn color(self, strategy: Strategy) -> Color {
+        self.colors().color(strategy)
+    }
+
+    /// The same glyph for every variant of a strategy.
+    pub fn glyph(self, strategy: Strategy) -> char {
+        entry(self, strategy).1
+    }
+
+    fn colors(self) -> &'static colors::Palette<Color> {
+        static COLORS: [OnceLock<colors::Palette<Color>>; 3] =
+            [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+        let i = PALETTES.iter().position(|p| *p == self).unwrap_or(0);
+        COLORS[i].get_or_init(|| {
+            let builtin = Strategy::ALL.map(|s| (s, entry(self, s).0));
+            match self {
+                Palette::Classic | Palette::Colorblind => {
+                    colors::Palette::new(builtin, CYCLE_COLORS.map(|[r, g, b]| Color::Rgb(r, g, b)))
+                }
+                Palette::Mono => colors::Palette::new(
+                    builtin,
+                    [235, 205, 145, 85, 45].map(|v| Color::Rgb(v, v, v)),
+                ),
+            }
+        })
+    }
+
+    /// Glyph and color for a zoomed-out block with no majority strategy.
+    pub fn tie(self) -> (char, Color) {
+        match self {
+            Palette::Mono => ('·', Color::White),
+            Palette::Classic | Palette::Colorblind => ('█', Color::White),
+        }
+    }
+}
+
+/// Color and glyph of a strategy. The exhaustive matches make a new variant a compile error
+/// here rather than a silently missing legend entry.
+fn entry(palette: Palette, strategy: Strategy) -> (Color, char) {
+    match palette {
+        Palette::Classic => match strategy {
+            Strategy::Deflect => (Color::Red, '█'),
+            Strategy::TicToc => (Color::Yellow, '█'),
+            Strategy::Coop => (Color::Green, '█'),
+            Strategy::Random => (Color::Magenta, '█'),
+            Strategy::Choosy => (Color::Cyan, '█'),
+            Strategy::Trusting => (Color::LightGreen, '█'),
+            Strategy::Deceiver => (Color::LightMagenta, '█'),
+            Strategy::Wary => (Color::Blue, '█'),
+            Strategy::GenerousTFT(_) => (Color::LightYellow, '█'),
+            Strategy::RandomP(_) => (Color::LightRed, '█'),
+            Strategy::MemoryOne(_) => (Color::LightCyan, '█'),
+        },
+        Palette::Colorblind => match strategy {
+            Strategy::Deflect => (Color::Rgb(230, 159, 0), '█'),
+            Strategy::TicToc => (Color::Rgb(0, 114, 178), '█'),
+            Strategy::Coop => (Color::Rgb(0, 158, 115), '█'),
+            Strategy::Random => (Color::Rgb(204, 121, 167), '█'),
+            Strategy::Choosy => (Color::Rgb(86, 180, 233), '█'),
+            Strategy::Trusting => (Color::Rgb(240, 228, 66), '█'),
+            Strategy::Deceiver => (Color::Rgb(213, 94, 0), '█'),
+            Strategy::Wary => (Color::Rgb(153, 153, 153), '█'),
+            Strategy::GenerousTFT(_) => (Color::Rgb(255, 200, 120), '█'),
+            Strategy::RandomP(_) => (Color::Rgb(170, 68, 153), '█'),
+            Strategy::MemoryOne(_) => (Color::Rgb(136, 204, 238), '█'),
+        },
+        Palette::Mono => match strategy {
+            Strategy::Deflect => (Color::Rgb(255, 255, 255), '█'),
+            Strategy::TicToc => (Color::Rgb(190, 190, 190), '▓'),
+            Strategy::Coop => (Color::Rgb(125, 125, 125), '▒'),
+            Strategy::Random => (Color::Rgb(70, 70, 70), '░'),
+            Strategy::Choosy => (Color::Rgb(35, 35, 35), '▞'),
+            Strategy::Trusting => (Color::Rgb(220, 220, 220), '▚'),
+            Strategy::Deceiver => (Color::Rgb(100, 100, 100), '▙'),
+            Strategy::Wary => (Color::Rgb(160, 160, 160), '▟'),
+            Strategy::GenerousTFT(_) => (Color::Rgb(240, 240, 240), '▛'),
+            Strategy::RandomP(_) => (Color::Rgb(55, 55, 55), '▜'),
+            Strategy::MemoryOne(_) => (Color::Rgb(110, 110, 110), '▌'),
+        },
+    }
+}
+
+/// `count` as a percentage of `total` with one decimal, e.g. "12.5%". An empty total is 0%.
+pub fn format_percentage(count: usize, total: usize) -> String {
+    if total == 0 {
+        return "0.0%".to_string();
+    }
+    format!("{:.1}%", count as f64 * 100.0 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_palette_covers_every_variant() {
+        for palette in PALETTES {
+            let strategies: Vec<Strategy> = palette.entries().iter().map(|e| e.0).collect();
+            assert_eq!(strategies, Strategy::ALL, "{:?}", palette);
+        }
+    }
+
+    #[test]
+    fn test_palette_entries_distinct() {
+        for palette in PALETTES {
+            let entries = palette.entries();
+            for (i, a) in entries.iter().enumerate() {
+                for b in &entries[i + 1..] {
+                    assert_ne!(a.1, b.1, "{:?}", palette);
+                    assert_ne!(a.3, b.3, "{:?}", palette);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parameterized_variants_get_own_colors() {
+        let variant = Strategy::GenerousTFT(coop::Prob::from_millionths(50_000));
+        for palette in PALETTES {
+            let color = palette.color(variant);
+            assert_ne!(color, palette.color(Strategy::ALL[8]), "{:?}", palette);
+            assert_eq!(palette.color(variant), color);
+            assert_eq!(palette.glyph(variant), palette.glyph(Strategy::ALL[8]));
+        }
+    }
+
+    #[test]
+    fn test_mono_glyphs_distinct() {
+        let mut glyphs: Vec<char> = Strategy::ALL
+            .iter()
+            .map(|s| Palette::Mono.glyph(*s))
+            .collect();
+        glyphs.push(Palette::Mono.tie().0);
+        let count = glyphs.len();
+        glyphs.sort();
+        glyphs.dedup();
+        assert_eq!(glyphs.len(), count);
+    }
+
+    #[test]
+    fn test_names_and_cycle() {
+        for palette in PALETTES {
+            assert_eq!(Palette::from_name(palette.name()), Some(palette));
+        }
+        assert_eq!(Palette::from_name("sepia"), None);
+        let mut palette = Palette::default();
+        for _ in 0..PALETTES.len() {
+            palette = palette.next();
+        }
+        assert_eq!(palette, Palette::default());
+    }
+
+    #[test]
+    fn test_format_percentage() {
+        assert_eq!(format_percentage(0, 100), "0.0%");
+        assert_eq!(format_percentage(0, 0), "0.0%");
+        assert_eq!(format_percentage(1, 8), "12.5%");
+        assert_eq!(format_percentage(2500, 2500), "100.0%");
+        assert_eq!(format_percentage(1, 3), "33.3%");
+    }
+}
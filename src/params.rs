@@ -0,0 +1,122 @@
+/// Noise change per key press.
+pub const NOISE_STEP: f32 = 0.01;
+
+/// Parameters that can be changed while the simulation runs.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum LiveParam {
+    Noise,
+    Speed,
+}
+
+pub const LIVE_PARAMS: [LiveParam; 2] = [LiveParam::Noise, LiveParam::Speed];
+
+impl LiveParam {
+    pub fn name(self) -> &'static str {
+        match self {
+            LiveParam::Noise => "Noise",
+            LiveParam::Speed => "Speed",
+        }
+    }
+}
+
+/// A requested parameter change; `up` is `+` and `!up` is `-`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ParamChange {
+    pub param: LiveParam,
+    pub up: bool,
+}
+
+impl ParamChange {
+    /// Signed noise delta for a noise change, zero otherwise.
+    pub fn noise_delta(self) -> f32 {
+        match (self.param, self.up) {
+            (LiveParam::Noise, true) => NOISE_STEP,
+            (LiveParam::Noise, false) => -NOISE_STEP,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Selection state of the live parameter panel.
+#[derive(Clone, Debug, Default)]
+pub struct ParamPanel {
+    pub open: bool,
+    selected: usize,
+}
+
+impl ParamPanel {
+    pub fn selected(&self) -> LiveParam {
+        LIVE_PARAMS[self.selected]
+    }
+
+    /// Opens the panel, or moves to the next parameter if it's already open.
+    pub fn tab(&mut self) {
+        if self.open {
+            self.selected = (self.selected + 1) % LIVE_PARAMS.len();
+        } else {
+            self.open = true;
+        }
+    }
+
+    pub fn back_tab(&mut self) {
+        if self.open {
+            self.selected = (self.selected + LIVE_PARAMS.len() - 1) % LIVE_PARAMS.len();
+        } else {
+            self.open = true;
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    pub fn adjust(&self, up: bool) -> ParamChange {
+        ParamChange {
+            param: self.selected(),
+            up,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tab_opens_then_cycles() {
+        let mut panel = ParamPanel::default();
+        assert!(!panel.open);
+        panel.tab();
+        assert!(panel.open);
+        assert_eq!(panel.selected(), LiveParam::Noise);
+        panel.tab();
+        assert_eq!(panel.selected(), LiveParam::Speed);
+        panel.tab();
+        assert_eq!(panel.selected(), LiveParam::Noise);
+        panel.back_tab();
+        assert_eq!(panel.selected(), LiveParam::Speed);
+    }
+
+    #[test]
+    fn test_close_keeps_selection() {
+        let mut panel = ParamPanel::default();
+        panel.tab();
+        panel.tab();
+        panel.close();
+        assert!(!panel.open);
+        panel.back_tab();
+        assert!(panel.open);
+        assert_eq!(panel.selected(), LiveParam::Speed);
+    }
+
+    #[test]
+    fn test_adjust() {
+        let mut panel = ParamPanel::default();
+        panel.tab();
+        let change = panel.adjust(false);
+        assert_eq!(change.param, LiveParam::Noise);
+        assert_eq!(change.noise_delta(), -NOISE_STEP);
+        panel.tab();
+        assert_eq!(panel.adjust(true).noise_delta(), 0.0);
+    }
+}
@@ -0,0 +1,348 @@
+//! Histories and sweep results as Parquet files, for sweeps whose CSV would run to millions of
+//! rows. Needs the `arrow` feature.
+//!
+//! A history is one row per `(run_id, step)` in wide format: the scalar metrics, then a
+//! `count_<strategy>` and `avg_score_<strategy>` column for every kind in `Strategy::ALL`,
+//! named in lowercase. Its snapshots can go to a second file of `(run_id, step, rows, cols,
+//! cells)` rows, where `cells` holds [`Snapshot::to_bytes`](crate::Snapshot::to_bytes).
+//!
+//! This is `coop::parquet` rather than `export::parquet`: `export` is the terminal binary's
+//! module, and the library can't reach into it.
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::Compression;
+use ::parquet::file::properties::WriterProperties;
+use arrow_array::{
+    ArrayRef, BinaryArray, Float32Array, Float64Array, Int32Array, RecordBatch, UInt32Array,
+    UInt64Array,
+};
+
+use crate::agent::Strategy;
+use crate::env::Metric;
+use crate::experiments::PhaseDiagram;
+use crate::history::History;
+
+/// What [`write_history`] writes besides the metrics.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParquetOptions {
+    /// Written to every row, so the histories of a sweep's runs can be concatenated.
+    pub run_id: u64,
+    /// Where to write the snapshots table, if anywhere. Steps whose snapshot was dropped by
+    /// a compacting history are left out of it.
+    pub snapshots: Option<PathBuf>,
+}
+
+/// Writes the retained steps of `history` to `path`, and its snapshots where `opts` says.
+pub fn write_history(
+    history: &History,
+    path: impl AsRef<Path>,
+    opts: &ParquetOptions,
+) -> io::Result<()> {
+    let metrics: Vec<&Metric> = history.iter().map(|(_, m)| m).collect();
+    let mut columns = vec![
+        (
+            "run_id".to_string(),
+            column(UInt64Array::from(vec![opts.run_id; metrics.len()])),
+        ),
+        ("step".to_string(), u64_column(&metrics, |m| m.step_index)),
+        (
+            "coop_actions".to_string(),
+            column(Int32Array::from_iter_values(
+                metrics.iter().map(|m| m.coop_actions),
+            )),
+        ),
+        (
+            "coop_rate".to_string(),
+            column(Float32Array::from_iter_values(
+                metrics.iter().map(|m| m.coop_rate),
+            )),
+        ),
+        (
+            "population".to_string(),
+            u64_column(&metrics, |m| m.population),
+        ),
+        ("births".to_string(), u64_column(&metrics, |m| m.births)),
+        ("deaths".to_string(), u64_column(&metrics, |m| m.deaths)),
+        (
+            "imitations".to_string(),
+            u64_column(&metrics, |m| m.imitations),
+        ),
+        (
+            "explorations".to_string(),
+            u64_column(&metrics, |m| m.explorations),
+        ),
+        ("refusals".to_string(), u64_column(&metrics, |m| m.refusals)),
+        (
+            "founder_lineages".to_string(),
+            u64_column(&metrics, |m| m.founder_lineages),
+        ),
+    ];
+    for kind in Strategy::ALL {
+        let name = kind.name().to_lowercase();
+        columns.push((
+            format!("count_{}", name),
+            u64_column(&metrics, |m| kind_count(m, kind)),
+        ));
+        columns.push((
+            format!("avg_score_{}", name),
+            column(Float64Array::from_iter(
+                metrics.iter().map(|m| kind_avg_score(m, kind)),
+            )),
+        ));
+    }
+    write_table(path.as_ref(), columns)?;
+
+    if let Some(snapshots) = &opts.snapshots {
+        let kept: Vec<&Metric> = metrics
+            .into_iter()
+            .filter(|m| !m.snapshot.is_empty())
+            .collect();
+        let cells: Vec<Vec<u8>> = kept.iter().map(|m| m.snapshot.to_bytes()).collect();
+        let dimension = |f: fn(&Metric) -> usize| {
+            column(UInt32Array::from_iter_values(
+                kept.iter().map(|m| f(m) as u32),
+            ))
+        };
+        write_table(
+            snapshots,
+            vec![
+                (
+                    "run_id".to_string(),
+                    column(UInt64Array::from(vec![opts.run_id; kept.len()])),
+                ),
+                ("step".to_string(), u64_column(&kept, |m| m.step_index)),
+                ("rows".to_string(), dimension(|m| m.snapshot.rows())),
+                ("cols".to_string(), dimension(|m| m.snapshot.cols())),
+                (
+                    "cells".to_string(),
+                    column(BinaryArray::from_iter_values(&cells)),
+                ),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes `diagram` with a row per point: the two parameters, in columns named after them,
+/// and the mean final cooperation.
+pub fn write_phase_diagram(diagram: &PhaseDiagram, path: impl AsRef<Path>) -> io::Result<()> {
+    let points = || {
+        diagram
+            .y_values
+            .iter()
+            .zip(&diagram.cooperation)
+            .flat_map(|(&y, row)| {
+                diagram
+                    .x_values
+                    .iter()
+                    .zip(row)
+                    .map(move |(&x, &c)| (x, y, c))
+            })
+    };
+    write_table(
+        path.as_ref(),
+        vec![
+            (
+                diagram.x_param.name().to_string(),
+                column(Float64Array::from_iter_values(points().map(|p| p.0))),
+            ),
+            (
+                diagram.y_param.name().to_string(),
+                column(Float64Array::from_iter_values(points().map(|p| p.1))),
+            ),
+            (
+                "cooperation".to_string(),
+                column(Float64Array::from_iter_values(points().map(|p| p.2))),
+            ),
+        ],
+    )
+}
+
+/// Agents playing any strategy of `kind`, whatever its parameters.
+fn kind_count(metric: &Metric, kind: Strategy) -> usize {
+    metric
+        .strategies
+        .iter()
+        .filter(|(s, _)| s.kind() == kind)
+        .map(|(_, &n)| n)
+        .sum()
+}
+
+/// Mean score of the agents playing a strategy of `kind`, or `None` if none do.
+fn kind_avg_score(metric: &Metric, kind: Strategy) -> Option<f64> {
+    let count = kind_count(metric, kind);
+    let total: f64 = metric
+        .strategies
+        .iter()
+        .filter(|(s, _)| s.kind() == kind)
+        .map(|(s, &n)| metric.avg_score.get(s).copied().unwrap_or(0.0) * n as f64)
+        .sum();
+    (count > 0).then(|| total / count as f64)
+}
+
+fn column(array: impl arrow_array::Array + 'static) -> ArrayRef {
+    Arc::new(array)
+}
+
+fn u64_column(metrics: &[&Metric], f: impl Fn(&Metric) -> usize) -> ArrayRef {
+    column(UInt64Array::from_iter_values(
+        metrics.iter().map(|m| f(m) as u64),
+    ))
+}
+
+/// Writes `columns` as one table, compressed with Snappy.
+fn write_table(path: &Path, columns: Vec<(String, ArrayRef)>) -> io::Result<()> {
+    let batch = RecordBatch::try_from_iter(columns).map_err(io::Error::other)?;
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), Some(props))
+        .map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::EnvironmentBuilder;
+    use crate::env::EnergyConfig;
+    use crate::experiments::{phase_diagram, RunConfig, SweepParam};
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float32Type, Float64Type, UInt32Type, UInt64Type};
+    use arrow_array::Array;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("coop-{}-{}.parquet", name, std::process::id()))
+    }
+
+    fn read(path: &Path) -> RecordBatch {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        batches.remove(0)
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        // Living is costly enough that poorly paid agents starve, leaving vacant cells.
+        let mut env = EnvironmentBuilder::new()
+            .size(6, 7)
+            .seed(3)
+            .noise(0.1)
+            .strategies(&[Strategy::Deflect, Strategy::TicToc, Strategy::Coop])
+            .energy(EnergyConfig {
+                living_cost: 20.0,
+                ..EnergyConfig::default()
+            })
+            .build()
+            .unwrap();
+        let mut history = History::new(100, 1);
+        env.run_with(12, |m| {
+            history.push(m.clone());
+        });
+        let (path, snapshots) = (temp_path("history"), temp_path("snapshots"));
+        let opts = ParquetOptions {
+            run_id: 7,
+            snapshots: Some(snapshots.clone()),
+        };
+        write_history(&history, &path, &opts).unwrap();
+
+        let batch = read(&path);
+        let metrics: Vec<&Metric> = history.iter().map(|(_, m)| m).collect();
+        assert_eq!(batch.num_rows(), 12);
+        let u64s = |name: &str| -> Vec<u64> {
+            let column = batch.column_by_name(name).unwrap();
+            column.as_primitive::<UInt64Type>().values().to_vec()
+        };
+        assert_eq!(u64s("run_id"), vec![7; 12]);
+        assert_eq!(u64s("step"), (0..12).collect::<Vec<u64>>());
+        assert_eq!(
+            u64s("population"),
+            metrics
+                .iter()
+                .map(|m| m.population as u64)
+                .collect::<Vec<_>>()
+        );
+        let rates = batch.column_by_name("coop_rate").unwrap();
+        assert_eq!(
+            rates.as_primitive::<Float32Type>().values().to_vec(),
+            metrics.iter().map(|m| m.coop_rate).collect::<Vec<_>>()
+        );
+        for kind in [Strategy::Deflect, Strategy::TicToc, Strategy::Coop] {
+            let name = kind.name().to_lowercase();
+            assert_eq!(
+                u64s(&format!("count_{}", name)),
+                metrics
+                    .iter()
+                    .map(|m| m.strategies.get(&kind).copied().unwrap_or(0) as u64)
+                    .collect::<Vec<_>>()
+            );
+            let scores = batch
+                .column_by_name(&format!("avg_score_{}", name))
+                .unwrap()
+                .as_primitive::<Float64Type>()
+                .clone();
+            for (i, m) in metrics.iter().enumerate() {
+                assert_eq!(scores.is_valid(i), m.strategies.contains_key(&kind));
+                if let Some(&score) = m.avg_score.get(&kind) {
+                    assert!((scores.value(i) - score).abs() < 1e-9);
+                }
+            }
+        }
+        // Strategies nobody played still get their columns.
+        assert_eq!(u64s("count_memoryone"), vec![0; 12]);
+
+        let batch = read(&snapshots);
+        assert_eq!(batch.num_rows(), 12);
+        let dims = batch.column_by_name("rows").unwrap();
+        assert_eq!(dims.as_primitive::<UInt32Type>().values().to_vec(), [6; 12]);
+        let cells = batch.column_by_name("cells").unwrap().as_binary::<i32>();
+        for (i, m) in metrics.iter().enumerate() {
+            assert_eq!(cells.value(i), m.snapshot.to_bytes());
+        }
+        assert!(metrics.iter().any(|m| m.snapshot.vacancies() > 0));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(snapshots).unwrap();
+    }
+
+    #[test]
+    fn test_phase_diagram_round_trip() {
+        let base = RunConfig {
+            rows: 6,
+            cols: 6,
+            steps: 5,
+            ..RunConfig::default()
+        };
+        let diagram = phase_diagram(
+            SweepParam::Temptation,
+            &[4.0, 6.0, 8.0],
+            SweepParam::Noise,
+            &[0.0, 0.2],
+            &base,
+            2,
+        )
+        .unwrap();
+        let path = temp_path("sweep");
+        diagram.save(&path).unwrap();
+        let batch = read(&path);
+        let floats = |name: &str| -> Vec<f64> {
+            let column = batch.column_by_name(name).unwrap();
+            column.as_primitive::<Float64Type>().values().to_vec()
+        };
+        assert_eq!(floats("temptation"), [4.0, 6.0, 8.0, 4.0, 6.0, 8.0]);
+        assert_eq!(floats("noise"), [0.0, 0.0, 0.0, 0.2, 0.2, 0.2]);
+        assert_eq!(floats("cooperation"), diagram.cooperation.concat());
+        std::fs::remove_file(path).unwrap();
+    }
+}
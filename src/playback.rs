@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use crate::pacer::Pacer;
+
+/// Pacer level playback starts at: 10 frames per second.
+const START_LEVEL: usize = 3;
+
+/// What happens when playback reaches the newest buffered step.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum PlaybackEnd {
+    /// Stay detached on the newest step.
+    #[default]
+    Stop,
+    /// Return to the live view.
+    Live,
+}
+
+pub const PLAYBACK_ENDS: [PlaybackEnd; 2] = [PlaybackEnd::Stop, PlaybackEnd::Live];
+
+impl PlaybackEnd {
+    pub fn name(self) -> &'static str {
+        match self {
+            PlaybackEnd::Stop => "stop",
+            PlaybackEnd::Live => "live",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PlaybackEnd> {
+        PLAYBACK_ENDS.into_iter().find(|e| e.name() == name)
+    }
+}
+
+/// Replays buffered history at its own frame rate, adjusted with the speed keys.
+#[derive(Clone, Debug)]
+pub struct Playback {
+    pacer: Pacer,
+}
+
+impl Playback {
+    pub fn new() -> Playback {
+        Playback {
+            pacer: Pacer::new(START_LEVEL),
+        }
+    }
+
+    /// Frames per second, or `None` for one frame per redraw.
+    pub fn rate(&self) -> Option<u32> {
+        self.pacer.target_rate()
+    }
+
+    pub fn faster(&mut self) {
+        self.pacer.faster();
+    }
+
+    pub fn slower(&mut self) {
+        self.pacer.slower();
+    }
+
+    /// Frames to advance at `now`. The first call only starts the clock.
+    pub fn due(&mut self, now: Duration) -> usize {
+        match self.pacer.due(now) {
+            usize::MAX => 1,
+            frames => frames,
+        }
+    }
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_paced_frames() {
+        let mut playback = Playback::new();
+        assert_eq!(playback.rate(), Some(10));
+        assert_eq!(playback.due(ms(1000)), 0);
+        assert_eq!(playback.due(ms(1050)), 0);
+        assert_eq!(playback.due(ms(1100)), 1);
+        assert_eq!(playback.due(ms(1300)), 2);
+        playback.faster();
+        assert_eq!(playback.rate(), Some(20));
+        assert_eq!(playback.due(ms(1400)), 2);
+    }
+
+    #[test]
+    fn test_uncapped_is_one_frame_per_call() {
+        let mut playback = Playback::new();
+        for _ in 0..20 {
+            playback.faster();
+        }
+        assert_eq!(playback.rate(), None);
+        assert_eq!(playback.due(ms(0)), 1);
+        assert_eq!(playback.due(ms(5000)), 1);
+    }
+
+    #[test]
+    fn test_end_names() {
+        for end in PLAYBACK_ENDS {
+            assert_eq!(PlaybackEnd::from_name(end.name()), Some(end));
+        }
+        assert_eq!(PlaybackEnd::from_name("loop"), None);
+        assert_eq!(PlaybackEnd::default(), PlaybackEnd::Stop);
+    }
+}
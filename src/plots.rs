@@ -0,0 +1,460 @@
+//! Time-series charts of a finished run, written as PNG or SVG: strategy populations, the
+//! cooperation rate with its moving average, and each strategy's mean score. The series come
+//! from the same extraction as the TUI charts; the PNG goes through the image exporter, so
+//! it has no text, while the SVG titles every panel.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use coop::{History, Metric, Strategy};
+
+use crate::charts::{
+    axis_bounds, coop_rate_series, downsample, index_to_step, moving_average, population_series,
+};
+use crate::export::{encode_png, rgb, Image};
+use crate::palette::Palette;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const FRAME: [u8; 3] = [0, 0, 0];
+const COOP_RATE: [u8; 3] = [170, 170, 170];
+const COOP_RATE_AVERAGE: [u8; 3] = [0, 90, 200];
+/// Space around and between panels, in pixels.
+const MARGIN: usize = 12;
+
+/// A chart panel; each is drawn in its own band, top to bottom in the order given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Series {
+    /// Agents playing each strategy.
+    Populations,
+    /// Fraction of actions that cooperated, and its moving average.
+    CoopRate,
+    /// Mean score of each strategy's agents.
+    MeanScores,
+}
+
+impl Series {
+    pub const ALL: [Series; 3] = [Series::Populations, Series::CoopRate, Series::MeanScores];
+
+    fn title(self) -> &'static str {
+        match self {
+            Series::Populations => "Strategy populations",
+            Series::CoopRate => "Cooperation rate",
+            Series::MeanScores => "Mean score per strategy",
+        }
+    }
+}
+
+/// What to draw and how big.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotConfig {
+    /// Image size in pixels.
+    pub width: usize,
+    pub height: usize,
+    pub series: Vec<Series>,
+    /// Steps in the cooperation rate's trailing moving average.
+    pub average_window: usize,
+    pub palette: Palette,
+}
+
+impl Default for PlotConfig {
+    fn default() -> Self {
+        PlotConfig {
+            width: 800,
+            height: 600,
+            series: Series::ALL.to_vec(),
+            average_window: 20,
+            palette: Palette::default(),
+        }
+    }
+}
+
+/// Writes the charts of `history` to `path`, as SVG if it ends in `.svg` and PNG otherwise.
+pub fn render_run(history: &History, path: &Path, config: &PlotConfig) -> io::Result<()> {
+    let svg = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("svg"));
+    if svg {
+        fs::write(path, render_svg(history, config))
+    } else {
+        fs::write(path, encode_png(&render_image(history, config)))
+    }
+}
+
+/// One polyline, in step and value coordinates; `NaN` values leave gaps.
+#[derive(Clone, Debug, PartialEq)]
+struct Line {
+    color: [u8; 3],
+    points: Vec<(f64, f64)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Panel {
+    series: Series,
+    lines: Vec<Line>,
+    x_bounds: [f64; 2],
+    y_bounds: [f64; 2],
+}
+
+/// Pixel rectangle of a panel: left, top, width, height.
+type Rect = (usize, usize, usize, usize);
+
+/// Mean score of the agents playing `strategy` at every buffered step, `NaN` where none do.
+fn mean_score_series<'a>(
+    buffer: impl IntoIterator<Item = &'a Metric>,
+    strategy: Strategy,
+) -> Vec<f64> {
+    buffer
+        .into_iter()
+        .map(|m| m.avg_score.get(&strategy).copied().unwrap_or(f64::NAN))
+        .collect()
+}
+
+/// The panels of `config.series`, their lines reduced to about one point per pixel column.
+fn panels(history: &History, config: &PlotConfig) -> Vec<Panel> {
+    let (steps, metrics): (Vec<usize>, Vec<&Metric>) = history.iter().unzip();
+    // Strategies that were ever played, in the usual order.
+    let mut played: Vec<Strategy> = metrics
+        .iter()
+        .flat_map(|m| m.strategies.keys().copied())
+        .collect();
+    played.sort();
+    played.dedup();
+    let columns = config.width.saturating_sub(2 * MARGIN).max(1);
+    let line = |color, values: &[f64]| Line {
+        color,
+        points: index_to_step(&downsample(values, columns), &steps),
+    };
+    let x_bounds = axis_bounds(&[
+        steps.first().copied().unwrap_or(0) as f64,
+        steps.last().copied().unwrap_or(0) as f64,
+    ]);
+    config
+        .series
+        .iter()
+        .map(|&series| {
+            let (lines, y_bounds) = match series {
+                Series::Populations | Series::MeanScores => {
+                    let values: Vec<(Strategy, Vec<f64>)> = played
+                        .iter()
+                        .map(|&s| {
+                            let values = match series {
+                                Series::Populations => population_series(metrics.clone(), s),
+                                _ => mean_score_series(metrics.clone(), s),
+                            };
+                            (s, values)
+                        })
+                        .collect();
+                    let all: Vec<f64> = values.iter().flat_map(|(_, v)| v.clone()).collect();
+                    let lines = values
+                        .iter()
+                        .map(|(s, v)| line(rgb(config.palette.color(*s)), v))
+                        .collect();
+                    (lines, axis_bounds(&all))
+                }
+                Series::CoopRate => {
+                    let rate = coop_rate_series(metrics.clone());
+                    let average = moving_average(&rate, config.average_window);
+                    let lines = vec![line(COOP_RATE, &rate), line(COOP_RATE_AVERAGE, &average)];
+                    (lines, [0.0, 1.0])
+                }
+            };
+            Panel {
+                series,
+                lines,
+                x_bounds,
+                y_bounds,
+            }
+        })
+        .collect()
+}
+
+/// Splits the height into one band per panel, separated by the margin.
+fn layout(width: usize, height: usize, panels: usize) -> Vec<Rect> {
+    let inner_width = width.saturating_sub(2 * MARGIN);
+    let band = height.saturating_sub(MARGIN * (panels + 1)) / panels.max(1);
+    (0..panels)
+        .map(|i| (MARGIN, MARGIN + i * (band + MARGIN), inner_width, band))
+        .collect()
+}
+
+/// Pixel position of `(x, y)` in `rect`, y growing downwards.
+fn to_pixel(panel: &Panel, rect: Rect, (x, y): (f64, f64)) -> (f64, f64) {
+    let (left, top, width, height) = rect;
+    let [x0, x1] = panel.x_bounds;
+    let [y0, y1] = panel.y_bounds;
+    let px = left as f64 + (x - x0) / (x1 - x0) * (width.saturating_sub(1)) as f64;
+    let py = (top + height.saturating_sub(1)) as f64
+        - (y - y0) / (y1 - y0) * (height.saturating_sub(1)) as f64;
+    (px, py)
+}
+
+/// The charts as an indexed image, e.g. for [`encode_png`].
+pub fn render_image(history: &History, config: &PlotConfig) -> Image {
+    let mut canvas = Canvas::new(config.width, config.height);
+    let panels = panels(history, config);
+    for (panel, rect) in panels
+        .iter()
+        .zip(layout(config.width, config.height, panels.len()))
+    {
+        let (left, top, width, height) = rect;
+        if width < 2 || height < 2 {
+            continue;
+        }
+        let (right, bottom) = (left + width - 1, top + height - 1);
+        canvas.line((left, top), (right, top), FRAME);
+        canvas.line((left, bottom), (right, bottom), FRAME);
+        canvas.line((left, top), (left, bottom), FRAME);
+        canvas.line((right, top), (right, bottom), FRAME);
+        for line in &panel.lines {
+            for pair in line.points.windows(2) {
+                let (a, b) = (
+                    to_pixel(panel, rect, pair[0]),
+                    to_pixel(panel, rect, pair[1]),
+                );
+                if a.1.is_nan() || b.1.is_nan() {
+                    continue;
+                }
+                let round = |(x, y): (f64, f64)| (x.round() as usize, y.round() as usize);
+                canvas.line(round(a), round(b), line.color);
+            }
+            // A single point still shows.
+            if let [point] = line.points[..] {
+                let (x, y) = to_pixel(panel, rect, point);
+                if !y.is_nan() {
+                    canvas.set(x.round() as usize, y.round() as usize, line.color);
+                }
+            }
+        }
+    }
+    canvas.image
+}
+
+/// An image that grows its palette as colors are drawn.
+struct Canvas {
+    image: Image,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            image: Image {
+                width,
+                height,
+                palette: vec![BACKGROUND],
+                pixels: vec![0; width * height],
+            },
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        let image = &mut self.image;
+        if x >= image.width || y >= image.height {
+            return;
+        }
+        let index = match image.palette.iter().position(|c| *c == color) {
+            Some(index) => index,
+            None => {
+                image.palette.push(color);
+                image.palette.len() - 1
+            }
+        };
+        image.pixels[y * image.width + x] = index as u8;
+    }
+
+    /// Bresenham's line between two pixels, both included.
+    fn line(&mut self, (x0, y0): (usize, usize), (x1, y1): (usize, usize), color: [u8; 3]) {
+        let (mut x, mut y) = (x0 as i64, y0 as i64);
+        let (x1, y1) = (x1 as i64, y1 as i64);
+        let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+        let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+        let mut err = dx + dy;
+        loop {
+            self.set(x as usize, y as usize, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+}
+
+/// The charts as an SVG document, each panel titled.
+pub fn render_svg(history: &History, config: &PlotConfig) -> String {
+    let (width, height) = (config.width, config.height);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\">\n<rect width=\"{w}\" height=\"{h}\" fill=\"{}\"/>\n",
+        hex(BACKGROUND),
+        w = width,
+        h = height,
+    );
+    let panels = panels(history, config);
+    for (panel, rect) in panels.iter().zip(layout(width, height, panels.len())) {
+        let (left, top, w, h) = rect;
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"{}\"/>\n",
+            left,
+            top,
+            w,
+            h,
+            hex(FRAME)
+        );
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"11\">{}</text>\n",
+            left + 4,
+            top + 13,
+            panel.series.title()
+        );
+        for line in &panel.lines {
+            // A gap in the values ends one polyline and starts the next.
+            for run in line.points.split(|(_, y)| y.is_nan()) {
+                if run.is_empty() {
+                    continue;
+                }
+                let points: Vec<String> = run
+                    .iter()
+                    .map(|&p| {
+                        let (x, y) = to_pixel(panel, rect, p);
+                        format!("{:.1},{:.1}", x, y)
+                    })
+                    .collect();
+                svg += &format!(
+                    "<polyline fill=\"none\" stroke=\"{}\" points=\"{}\"/>\n",
+                    hex(line.color),
+                    points.join(" ")
+                );
+            }
+        }
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use coop::{Agent, Environment};
+
+    use super::*;
+
+    /// 30 steps of a small mixed grid.
+    fn history() -> History {
+        let mut env = Environment::new_with_seed(6, 6, 0.1, 5, |c, _| {
+            let strategy = match (c.0 + c.1) % 3 {
+                0 => Strategy::Deflect,
+                1 => Strategy::TicToc,
+                _ => Strategy::Coop,
+            };
+            Agent::new(c, strategy)
+        })
+        .unwrap();
+        let mut history = History::new(100, 10);
+        for _ in 0..30 {
+            history.push(env.step());
+        }
+        history
+    }
+
+    fn config(series: &[Series]) -> PlotConfig {
+        PlotConfig {
+            width: 200,
+            height: 150,
+            series: series.to_vec(),
+            ..PlotConfig::default()
+        }
+    }
+
+    fn uses(image: &Image, color: [u8; 3]) -> bool {
+        let index = image.palette.iter().position(|c| *c == color);
+        index.is_some_and(|i| image.pixels.contains(&(i as u8)))
+    }
+
+    #[test]
+    fn test_image() {
+        let config = config(&Series::ALL);
+        let image = render_image(&history(), &config);
+        assert_eq!((image.width, image.height), (200, 150));
+        assert_eq!(image.pixels.len(), 200 * 150);
+        let drawn = image.pixels.iter().filter(|&&p| p != 0).count();
+        assert!(drawn > 1000, "only {} pixels drawn", drawn);
+        assert!(uses(&image, FRAME));
+        assert!(uses(&image, COOP_RATE_AVERAGE));
+        for s in [Strategy::Deflect, Strategy::TicToc, Strategy::Coop] {
+            assert!(uses(&image, rgb(config.palette.color(s))), "{:?}", s);
+        }
+        // The margins stay blank.
+        assert!(image.pixels[..200 * MARGIN].iter().all(|&p| p == 0));
+        let png = encode_png(&image);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_series_selection() {
+        let image = render_image(&history(), &config(&[Series::CoopRate]));
+        assert!(uses(&image, COOP_RATE) && uses(&image, COOP_RATE_AVERAGE));
+        assert!(!uses(
+            &image,
+            rgb(Palette::default().color(Strategy::Deflect))
+        ));
+        let blank = render_image(&history(), &config(&[]));
+        assert!(blank.pixels.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = render_svg(&history(), &config(&Series::ALL));
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        for series in Series::ALL {
+            assert!(svg.contains(series.title()));
+        }
+        // Three strategies in two panels, and the rate with its average.
+        assert_eq!(svg.matches("<polyline").count(), 3 + 2 + 3);
+    }
+
+    #[test]
+    fn test_render_run() {
+        let dir = std::env::temp_dir();
+        let (png, svg) = (
+            dir.join("coop-plot-test.png"),
+            dir.join("coop-plot-test.svg"),
+        );
+        let config = config(&[Series::Populations]);
+        render_run(&history(), &png, &config).unwrap();
+        render_run(&history(), &svg, &config).unwrap();
+        assert!(fs::read(&png).unwrap().starts_with(b"\x89PNG"));
+        assert!(fs::read_to_string(&svg).unwrap().starts_with("<svg "));
+        let _ = (fs::remove_file(png), fs::remove_file(svg));
+    }
+
+    #[test]
+    fn test_mean_score_series() {
+        let history = history();
+        let metrics: Vec<&Metric> = history.iter().map(|(_, m)| m).collect();
+        let scores = mean_score_series(metrics.clone(), Strategy::TicToc);
+        assert_eq!(scores.len(), 30);
+        assert_eq!(scores[0], metrics[0].avg_score[&Strategy::TicToc]);
+        assert!(mean_score_series(metrics, Strategy::Choosy)
+            .iter()
+            .all(|s| s.is_nan()));
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(layout(100, 100, 1), vec![(12, 12, 76, 76)]);
+        let bands = layout(100, 100, 2);
+        assert_eq!(bands, vec![(12, 12, 76, 32), (12, 56, 76, 32)]);
+        assert!(layout(10, 10, 3).iter().all(|r| r.3 == 0));
+    }
+}
@@ -0,0 +1,193 @@
+//! Ready-made experiments that reproduce well-known results from the spatial-games
+//! literature, for something meaningful to run straight away. Each preset has a fixed seed
+//! its headline result was checked with and the number of steps it takes to show.
+
+use crate::agent::{Agent, Strategy};
+use crate::builder::EnvironmentBuilder;
+use crate::env::{Environment, Payoffs, UpdateMode};
+use crate::error::Error;
+use crate::mix::Placement;
+
+/// The grid a preset starts from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Start {
+    /// Exact shares of each strategy, scattered at random.
+    Mix(Vec<(Strategy, f32)>),
+    /// A grid of `resident` with a single `invader` in the middle.
+    Invader {
+        resident: Strategy,
+        invader: Strategy,
+    },
+}
+
+/// A fully configured experiment. Fields can be changed before building, e.g. for a bigger
+/// grid or another seed, though the headline result is only promised as shipped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    /// What `by_name` finds the preset under.
+    pub name: &'static str,
+    /// What the preset shows, in a sentence.
+    pub description: &'static str,
+    pub rows: usize,
+    pub cols: usize,
+    pub noise: f32,
+    pub payoffs: Payoffs,
+    pub update_mode: UpdateMode,
+    pub start: Start,
+    pub seed: u64,
+    /// Steps to run for the result to show.
+    pub steps: usize,
+}
+
+impl Preset {
+    /// A builder with every setting of the preset.
+    pub fn builder(&self) -> EnvironmentBuilder<'static> {
+        let builder = EnvironmentBuilder::new()
+            .size(self.rows, self.cols)
+            .noise(self.noise)
+            .payoffs(self.payoffs)
+            .update_mode(self.update_mode)
+            .seed(self.seed);
+        match self.start {
+            Start::Mix(ref mix) => builder.mix(mix, Placement::Shuffled),
+            Start::Invader { resident, invader } => {
+                let middle = (self.rows / 2, self.cols / 2);
+                builder
+                    .agents(move |c, _| Agent::new(c, if c == middle { invader } else { resident }))
+            }
+        }
+    }
+
+    pub fn build(&self) -> Result<Environment, Error> {
+        self.builder().build()
+    }
+}
+
+/// Every preset, in the order `--list-presets` shows them.
+pub fn all() -> Vec<Preset> {
+    vec![
+        nowak_may_1992(),
+        defector_takeover(),
+        noise_resilience(),
+        single_invader(),
+    ]
+}
+
+/// The preset called `name`, if there is one.
+pub fn by_name(name: &str) -> Option<Preset> {
+    all().into_iter().find(|p| p.name == name)
+}
+
+/// Nowak and May's spatial dilemma ("Evolutionary games and spatial chaos", 1992): pure
+/// cooperators and defectors who copy their best neighbor, with a temptation of 1.85 and
+/// nothing for being exploited or mutual defection. Neither strategy can take over; they
+/// coexist in ever-shifting clusters.
+pub fn nowak_may_1992() -> Preset {
+    Preset {
+        name: "nowak_may_1992",
+        description: "cooperators and defectors coexist in shifting clusters (Nowak & May 1992)",
+        rows: 50,
+        cols: 50,
+        noise: 0.0,
+        payoffs: Payoffs {
+            reward: 1.0,
+            temptation: 1.85,
+            sucker: 0.0,
+            punishment: 0.0,
+        },
+        update_mode: UpdateMode::Synchronous,
+        start: Start::Mix(vec![(Strategy::Coop, 0.9), (Strategy::Deflect, 0.1)]),
+        seed: 1,
+        steps: 200,
+    }
+}
+
+/// The same game as [`nowak_may_1992`] with a temptation of 2.5, past the point where
+/// clusters of cooperators can hold out: defection takes over the grid.
+pub fn defector_takeover() -> Preset {
+    Preset {
+        name: "defector_takeover",
+        description: "a temptation past 2 lets defectors wipe out cooperators",
+        payoffs: Payoffs {
+            temptation: 2.5,
+            ..nowak_may_1992().payoffs
+        },
+        steps: 50,
+        ..nowak_may_1992()
+    }
+}
+
+/// TicToc against generous TicToc under 5% noise: one mistake sets two TicToc players
+/// punishing each other until the next, while generous players forgive, so generosity wins
+/// and cooperation stays high (Nowak and Sigmund, "Tit for tat in heterogeneous
+/// populations", 1992).
+pub fn noise_resilience() -> Preset {
+    let generous: Strategy = "gtft".parse().expect("gtft is a strategy alias");
+    Preset {
+        name: "noise_resilience",
+        description: "under noise, generous TicToc outlasts TicToc and keeps cooperation high",
+        rows: 40,
+        cols: 40,
+        noise: 0.05,
+        payoffs: Payoffs::default(),
+        update_mode: UpdateMode::Synchronous,
+        start: Start::Mix(vec![(Strategy::TicToc, 0.5), (generous, 0.5)]),
+        seed: 1,
+        steps: 300,
+    }
+}
+
+/// A lone defector dropped into a grid of TicToc players: it exploits each neighbor once and
+/// is punished from then on, so it can't spread and dies out (Axelrod, "The Evolution of
+/// Cooperation", 1984).
+pub fn single_invader() -> Preset {
+    Preset {
+        name: "single_invader",
+        description: "a lone defector can't invade TicToc and dies out",
+        rows: 21,
+        cols: 21,
+        noise: 0.0,
+        payoffs: Payoffs::default(),
+        update_mode: UpdateMode::Synchronous,
+        start: Start::Invader {
+            resident: Strategy::TicToc,
+            invader: Strategy::Deflect,
+        },
+        seed: 1,
+        steps: 100,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_are_unique_and_found() {
+        let presets = all();
+        for preset in &presets {
+            assert_eq!(by_name(preset.name).as_ref(), Some(preset));
+            assert_eq!(presets.iter().filter(|p| p.name == preset.name).count(), 1);
+        }
+        assert_eq!(by_name("kaleidoscope"), None);
+    }
+
+    #[test]
+    fn test_every_preset_builds_as_configured() {
+        for preset in all() {
+            let env = preset.build().unwrap();
+            assert_eq!(env.dimensions(), (preset.rows, preset.cols));
+            assert_eq!(env.seed(), preset.seed);
+            assert_eq!(env.payoffs(), preset.payoffs);
+        }
+        let preset = single_invader();
+        let env = preset.build().unwrap();
+        assert_eq!(env.agent_at((10, 10)).unwrap().strategy, Strategy::Deflect);
+        assert_eq!(
+            env.iter_agents()
+                .filter(|(_, a)| a.strategy == Strategy::TicToc)
+                .count(),
+            440
+        );
+    }
+}
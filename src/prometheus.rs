@@ -0,0 +1,214 @@
+//! The `/metrics` endpoint of `coop serve`, in the Prometheus text exposition format.
+//!
+//! Every name is prefixed with `coopsim_` and only ever added to, so dashboards and alerts
+//! built on them keep working:
+//!
+//! - `coopsim_strategy_count{strategy="tictoc"}`: agents playing each strategy, with every
+//!   strategy listed even when it has died out.
+//! - `coopsim_cooperation_rate`: fraction of the latest step's actions that cooperated.
+//! - `coopsim_mean_score`: mean score over all agents.
+//! - `coopsim_steps_per_second`: steps achieved over the last second.
+//! - `coopsim_steps_total`: steps taken since the server started, restarts included.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use coop::{Metric, Strategy};
+
+use crate::pacer::RateMeter;
+
+/// The content type scrapers expect for the text format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Keeps the exported values up to date as steps are published.
+pub struct Exporter {
+    counts: Vec<usize>,
+    coop_rate: f32,
+    mean_score: f64,
+    meter: RateMeter,
+    steps: u64,
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Exporter {
+            counts: vec![0; Strategy::ALL.len()],
+            coop_rate: 0.0,
+            mean_score: 0.0,
+            meter: RateMeter::new(Duration::from_secs(1)),
+            steps: 0,
+        }
+    }
+}
+
+impl Exporter {
+    /// Records a step published at `now`, time since some fixed origin.
+    pub fn observe(&mut self, metric: &Metric, now: Duration) {
+        for (count, strategy) in self.counts.iter_mut().zip(Strategy::ALL) {
+            *count = metric.strategies.get(&strategy).copied().unwrap_or(0);
+        }
+        self.coop_rate = metric.coop_rate;
+        let agents: usize = metric.strategies.values().sum();
+        let total: f64 = metric
+            .avg_score
+            .iter()
+            .map(|(s, avg)| avg * metric.strategies.get(s).copied().unwrap_or(0) as f64)
+            .sum();
+        self.mean_score = if agents == 0 {
+            0.0
+        } else {
+            total / agents as f64
+        };
+        self.meter.record(now, 1);
+        self.steps += 1;
+    }
+
+    /// The current values in the text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "coopsim_strategy_count",
+            "gauge",
+            "Agents playing each strategy.",
+        );
+        for (count, strategy) in self.counts.iter().zip(Strategy::ALL) {
+            let _ = writeln!(
+                out,
+                "coopsim_strategy_count{{strategy=\"{}\"}} {}",
+                strategy.name().to_ascii_lowercase(),
+                count
+            );
+        }
+        let scalars = [
+            (
+                "coopsim_cooperation_rate",
+                "gauge",
+                "Fraction of the latest step's actions that cooperated.",
+                f64::from(self.coop_rate),
+            ),
+            (
+                "coopsim_mean_score",
+                "gauge",
+                "Mean score over all agents.",
+                self.mean_score,
+            ),
+            (
+                "coopsim_steps_per_second",
+                "gauge",
+                "Steps achieved over the last second.",
+                self.meter.rate(),
+            ),
+            (
+                "coopsim_steps_total",
+                "counter",
+                "Steps taken since the server started.",
+                self.steps as f64,
+            ),
+        ];
+        for (name, kind, help, value) in scalars {
+            header(&mut out, name, kind, help);
+            let _ = writeln!(out, "{} {}", name, sample(value));
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// A sample value, spelled the way Prometheus spells the non-finite ones.
+fn sample(value: f64) -> String {
+    match value {
+        v if v.is_nan() => "NaN".to_string(),
+        v if v == f64::INFINITY => "+Inf".to_string(),
+        v if v == f64::NEG_INFINITY => "-Inf".to_string(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coop::{Agent, Environment};
+
+    use super::*;
+
+    /// A step of a 2x3 grid of Coop agents and one of Deflect agents.
+    fn steps() -> (Metric, Metric) {
+        let step = |strategy| {
+            Environment::new_with_seed(2, 3, 0.0, 1, |c, _| Agent::new(c, strategy))
+                .unwrap()
+                .step()
+        };
+        (step(Strategy::Coop), step(Strategy::Deflect))
+    }
+
+    fn value<'a>(text: &'a str, series: &str) -> &'a str {
+        text.lines()
+            .find_map(|l| l.strip_prefix(series)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("no {} in\n{}", series, text))
+    }
+
+    #[test]
+    fn test_render() {
+        let (coop, deflect) = steps();
+        let mut exporter = Exporter::default();
+        exporter.observe(&coop, Duration::ZERO);
+        let text = exporter.render();
+        assert_eq!(
+            value(&text, "coopsim_strategy_count{strategy=\"coop\"}"),
+            "6"
+        );
+        assert_eq!(
+            value(&text, "coopsim_strategy_count{strategy=\"deflect\"}"),
+            "0"
+        );
+        assert_eq!(value(&text, "coopsim_cooperation_rate"), "1");
+        assert_eq!(
+            value(&text, "coopsim_mean_score").parse::<f64>().unwrap(),
+            coop.avg_score[&Strategy::Coop]
+        );
+        assert_eq!(value(&text, "coopsim_steps_total"), "1");
+
+        exporter.observe(&deflect, Duration::from_millis(500));
+        let text = exporter.render();
+        assert_eq!(
+            value(&text, "coopsim_strategy_count{strategy=\"coop\"}"),
+            "0"
+        );
+        assert_eq!(value(&text, "coopsim_cooperation_rate"), "0");
+        assert_eq!(value(&text, "coopsim_steps_total"), "2");
+        assert_eq!(value(&text, "coopsim_steps_per_second"), "2");
+    }
+
+    #[test]
+    fn test_format() {
+        let mut exporter = Exporter::default();
+        exporter.observe(&steps().0, Duration::ZERO);
+        let text = exporter.render();
+        assert!(text.ends_with('\n'));
+        let mut typed = Vec::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(["gauge", "counter"].contains(&kind), "{}", line);
+                typed.push(name);
+            } else if !line.starts_with("# HELP ") {
+                let (series, value) = line.rsplit_once(' ').unwrap();
+                let name = series.split('{').next().unwrap();
+                assert!(name.starts_with("coopsim_"), "{}", line);
+                assert_eq!(typed.last(), Some(&name), "{} is typed right above", name);
+                value.parse::<f64>().unwrap();
+            }
+        }
+        let strategies = text
+            .lines()
+            .filter(|l| l.starts_with("coopsim_strategy_count{"))
+            .count();
+        assert_eq!(strategies, Strategy::ALL.len());
+        assert_eq!(sample(f64::NAN), "NaN");
+        assert_eq!(sample(f64::NEG_INFINITY), "-Inf");
+    }
+}
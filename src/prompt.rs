@@ -0,0 +1,127 @@
+use ratatui::crossterm::event::KeyCode;
+
+/// Most digits accepted; anything longer can't be a real step index anyway.
+const MAX_DIGITS: usize = 20;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PromptError {
+    Empty,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PromptEvent {
+    /// Still editing.
+    Pending,
+    /// The prompt was dismissed without a value.
+    Cancelled,
+    /// A step number was entered.
+    Submitted(usize),
+}
+
+/// Minimal numeric text input used by the "go to step" prompt.
+#[derive(Clone, Debug, Default)]
+pub struct StepPrompt {
+    digits: String,
+    error: Option<PromptError>,
+}
+
+impl StepPrompt {
+    pub fn new() -> StepPrompt {
+        StepPrompt::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.digits
+    }
+
+    pub fn error(&self) -> Option<PromptError> {
+        self.error
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> PromptEvent {
+        match code {
+            KeyCode::Char(c) if c.is_ascii_digit() => {
+                if self.digits.len() < MAX_DIGITS {
+                    self.digits.push(c);
+                }
+                self.error = None;
+            }
+            KeyCode::Backspace => {
+                self.digits.pop();
+                self.error = None;
+            }
+            KeyCode::Esc => return PromptEvent::Cancelled,
+            KeyCode::Enter => match self.submit() {
+                Ok(step) => return PromptEvent::Submitted(step),
+                Err(e) => self.error = Some(e),
+            },
+            _ => {}
+        }
+        PromptEvent::Pending
+    }
+
+    /// Parses the buffered digits. Values too large for `usize` saturate, since the caller
+    /// clamps to the buffer anyway.
+    pub fn submit(&self) -> Result<usize, PromptError> {
+        if self.digits.is_empty() {
+            return Err(PromptError::Empty);
+        }
+        Ok(self.digits.parse().unwrap_or(usize::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(keys: &str) -> StepPrompt {
+        let mut prompt = StepPrompt::new();
+        for c in keys.chars() {
+            prompt.handle_key(KeyCode::Char(c));
+        }
+        prompt
+    }
+
+    #[test]
+    fn test_digits_and_backspace() {
+        let mut prompt = typed("123");
+        assert_eq!(prompt.text(), "123");
+        prompt.handle_key(KeyCode::Backspace);
+        assert_eq!(prompt.text(), "12");
+        assert_eq!(
+            prompt.handle_key(KeyCode::Enter),
+            PromptEvent::Submitted(12)
+        );
+    }
+
+    #[test]
+    fn test_non_digits_ignored() {
+        let prompt = typed("4x-2 a");
+        assert_eq!(prompt.text(), "42");
+    }
+
+    #[test]
+    fn test_empty_is_rejected() {
+        let mut prompt = StepPrompt::new();
+        assert_eq!(prompt.handle_key(KeyCode::Enter), PromptEvent::Pending);
+        assert_eq!(prompt.error(), Some(PromptError::Empty));
+        prompt.handle_key(KeyCode::Char('1'));
+        assert_eq!(prompt.error(), None);
+        prompt.handle_key(KeyCode::Backspace);
+        prompt.handle_key(KeyCode::Backspace);
+        assert_eq!(prompt.submit(), Err(PromptError::Empty));
+    }
+
+    #[test]
+    fn test_escape_cancels() {
+        let mut prompt = typed("5");
+        assert_eq!(prompt.handle_key(KeyCode::Esc), PromptEvent::Cancelled);
+    }
+
+    #[test]
+    fn test_overflow_saturates() {
+        let prompt = typed(&"9".repeat(40));
+        assert_eq!(prompt.text().len(), MAX_DIGITS);
+        assert_eq!(prompt.submit(), Ok(usize::MAX));
+    }
+}
@@ -0,0 +1,234 @@
+//! Python bindings, built with maturin (`maturin develop` from the repository root; see
+//! `pyproject.toml` and `python/tests`).
+
+// The `#[pymethods]` expansion converts every `PyResult` into itself.
+#![allow(clippy::useless_conversion)]
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rand::Rng;
+
+use crate::agent::{Agent, Strategy};
+use crate::builder::EnvironmentBuilder;
+use crate::env::{Environment, Metric};
+use crate::snapshot::VACANT_CELL;
+
+/// Spatial prisoner's dilemma simulations.
+///
+/// ```python
+/// import coop
+///
+/// env = coop.CoopEnv(50, 50, noise=0.1, seed=7, strategy_mix={"TicToc": 0.6, "Deflect": 0.4})
+/// first = env.step()            # {"step": 0, "coop_rate": ..., "coop_actions": ..., "strategies": {...}}
+/// rest = env.run(99)            # a list of such dicts, e.g. for pandas.DataFrame(rest)
+/// grid = numpy.array(env.snapshot())  # rows x cols of strategy numbers
+/// ```
+///
+/// Strategy numbers index `coop.STRATEGIES`, which never reorders or reuses entries;
+/// `coop.VACANT` isn't one of them.
+/// Strategy names are case-insensitive and accept the same aliases as the command line,
+/// e.g. "tft" for TicToc.
+#[pymodule]
+fn coop(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<CoopEnv>()?;
+    m.add("STRATEGIES", strategy_names())?;
+    m.add("VACANT", VACANT_CELL)?;
+    Ok(())
+}
+
+/// Strategy names indexed by strategy number.
+fn strategy_names() -> Vec<&'static str> {
+    let mut names = Strategy::ALL.map(|s| (s.index(), s.name()));
+    names.sort();
+    names.into_iter().map(|(_, name)| name).collect()
+}
+
+/// A seeded simulation. `strategy_mix` maps strategy names to relative weights of the initial
+/// placement; without it agents are Deflect or TicToc with equal odds.
+#[pyclass(module = "coop", unsendable)]
+pub struct CoopEnv {
+    env: Environment,
+}
+
+#[pymethods]
+impl CoopEnv {
+    #[new]
+    #[pyo3(signature = (rows, cols, noise = 0.0, seed = 0, strategy_mix = None))]
+    fn new(
+        rows: usize,
+        cols: usize,
+        noise: f32,
+        seed: u64,
+        strategy_mix: Option<HashMap<String, f32>>,
+    ) -> PyResult<CoopEnv> {
+        let mut builder = EnvironmentBuilder::new()
+            .size(rows, cols)
+            .noise(noise)
+            .seed(seed);
+        if let Some(mix) = strategy_mix {
+            let weights = parse_mix(&mix).map_err(PyValueError::new_err)?;
+            builder = builder.agents(move |c, rng| Agent::new(c, pick(&weights, rng.gen())));
+        }
+        let env = builder
+            .build()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(CoopEnv { env })
+    }
+
+    /// Plays one step and returns its metrics as a dict.
+    fn step<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        metric_dict(py, &self.env.step())
+    }
+
+    /// Plays `n` steps and returns a list of their metric dicts.
+    fn run<'py>(&mut self, py: Python<'py>, n: usize) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.env.run(n).iter().map(|m| metric_dict(py, m)).collect()
+    }
+
+    /// The current strategy number of every cell, or `coop.VACANT` for a cell without an
+    /// agent, as a list of rows.
+    fn snapshot(&self) -> Vec<Vec<u8>> {
+        let (rows, cols) = self.env.dimensions();
+        (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .map(|col| {
+                        self.env
+                            .agent_at((row, col))
+                            .map_or(VACANT_CELL, |agent| agent.strategy.index())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[getter]
+    fn shape(&self) -> (usize, usize) {
+        self.env.dimensions()
+    }
+
+    #[getter]
+    fn noise(&self) -> f32 {
+        self.env.noise()
+    }
+
+    /// Clamped to `[0, 1]`.
+    #[setter]
+    fn set_noise(&mut self, noise: f32) {
+        self.env.set_noise(noise);
+    }
+
+    #[getter]
+    fn steps_taken(&self) -> usize {
+        self.env.steps_taken()
+    }
+}
+
+/// `{"step", "coop_rate", "coop_actions", "strategies"}`, the last mapping every strategy
+/// name to its count, including zeros so data frames get stable columns.
+fn metric_dict<'py>(py: Python<'py>, metric: &Metric) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("step", metric.step_index)?;
+    dict.set_item("coop_rate", metric.coop_rate)?;
+    dict.set_item("coop_actions", metric.coop_actions)?;
+    let counts = PyDict::new_bound(py);
+    for s in Strategy::ALL {
+        counts.set_item(s.name(), metric.strategies.get(&s).copied().unwrap_or(0))?;
+    }
+    dict.set_item("strategies", counts)?;
+    Ok(dict)
+}
+
+/// Cumulative placement probabilities from named weights, in strategy order so equal seeds
+/// place equally whatever order the mapping had.
+fn parse_mix(mix: &HashMap<String, f32>) -> Result<Vec<(Strategy, f32)>, String> {
+    let mut weights: BTreeMap<Strategy, f32> = BTreeMap::new();
+    for (name, weight) in mix {
+        let strategy: Strategy = name.parse().map_err(|e| format!("{}", e))?;
+        if !weight.is_finite() || *weight < 0.0 {
+            return Err(format!(
+                "weight of {} must be non-negative, got {}",
+                name, weight
+            ));
+        }
+        *weights.entry(strategy).or_insert(0.0) += weight;
+    }
+    let total: f32 = weights.values().sum();
+    if total <= 0.0 {
+        return Err("strategy_mix needs a positive weight".to_string());
+    }
+    let mut acc = 0.0;
+    Ok(weights
+        .into_iter()
+        .filter(|(_, w)| *w > 0.0)
+        .map(|(s, w)| {
+            acc += w / total;
+            (s, acc)
+        })
+        .collect())
+}
+
+/// The strategy whose cumulative probability first exceeds the uniform draw `r`.
+fn pick(cumulative: &[(Strategy, f32)], r: f32) -> Strategy {
+    cumulative
+        .iter()
+        .find(|(_, acc)| r < *acc)
+        .or(cumulative.last())
+        .map_or(Strategy::Deflect, |(s, _)| *s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mix(entries: &[(&str, f32)]) -> HashMap<String, f32> {
+        entries.iter().map(|(n, w)| (n.to_string(), *w)).collect()
+    }
+
+    #[test]
+    fn test_strategy_names() {
+        assert_eq!(
+            strategy_names(),
+            vec![
+                "Deflect",
+                "TicToc",
+                "Coop",
+                "Random",
+                "Choosy",
+                "Trusting",
+                "Deceiver",
+                "Wary",
+                "GenerousTFT",
+                "RandomP",
+                "MemoryOne"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_mix() {
+        let weights = parse_mix(&mix(&[("coop", 1.0), ("tft", 3.0), ("Random", 0.0)])).unwrap();
+        assert_eq!(
+            weights,
+            vec![(Strategy::TicToc, 0.75), (Strategy::Coop, 1.0)]
+        );
+        assert_eq!(pick(&weights, 0.5), Strategy::TicToc);
+        assert_eq!(pick(&weights, 0.9), Strategy::Coop);
+        assert_eq!(pick(&weights, 1.0), Strategy::Coop);
+    }
+
+    #[test]
+    fn test_invalid_mix() {
+        assert!(parse_mix(&mix(&[("pavlov", 1.0)]))
+            .unwrap_err()
+            .contains("unknown strategy"));
+        assert!(parse_mix(&mix(&[("coop", -1.0)])).is_err());
+        assert!(parse_mix(&mix(&[("coop", f32::NAN)])).is_err());
+        assert!(parse_mix(&mix(&[("coop", 0.0)])).is_err());
+        assert!(parse_mix(&mix(&[])).is_err());
+    }
+}
@@ -0,0 +1,609 @@
+//! Drawing the main view: the grid with its overlays, legend and status line, the diff
+//! view, the history scrubber and the help overlay. Everything is drawn from the data passed
+//! in, into the area passed in, so it renders the same on a test backend.
+
+use std::cmp::Ordering;
+
+use coop::analyze::{aggregate_blocks, any_in_tiles, diff_snapshots};
+use coop::{Class, Coord, FiredShock, Groups, History, Metric, Snapshot};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+
+use crate::config::SimConfig;
+use crate::export::EXPORT_KINDS;
+use crate::halfblock::half_block_lines;
+use crate::palette::{format_percentage, Palette};
+use crate::params::{LiveParam, LIVE_PARAMS};
+use crate::prompt::PromptError;
+use crate::status::{format_status, RunState};
+use crate::timeline::{column_to_step, step_to_column, Steps, ViewMode};
+use crate::ui::{UiState, Zoom, KEY_BINDINGS};
+
+/// Columns between the two grids of the diff view.
+pub const DIFF_GAP: u16 = 2;
+
+/// One-row history bar: each column is colored by the cooperation rate of the retained step
+/// nearest to the one it stands for (red = none, green = full), filled up to the viewed step,
+/// with a marker on it.
+pub fn render_scrubber(frame: &mut Frame, area: Rect, history: &History, step: usize) {
+    let width = area.width;
+    let marker = step_to_column(step, history.len(), width);
+    let bookmarked: Vec<u16> = history
+        .bookmarks()
+        .iter()
+        .map(|s| step_to_column(s, history.len(), width))
+        .collect();
+    let spans: Vec<Span> = (0..width)
+        .map(|column| {
+            let column_step = column_to_step(column, history.len(), width);
+            let rate = history
+                .get(Steps::nearest(&history, column_step))
+                .map_or(0.0, |m| m.coop_rate);
+            let heat = Color::Rgb((255.0 * (1.0 - rate)) as u8, (255.0 * rate) as u8, 0);
+            match column.cmp(&marker) {
+                Ordering::Equal => "┃".fg(Color::White).bold(),
+                _ if bookmarked.contains(&column) => "◆".fg(Color::Cyan).bold(),
+                Ordering::Less => "█".fg(heat),
+                Ordering::Greater => "▁".fg(heat),
+            }
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+/// Shows the viewed step next to the marked one with differing cells highlighted, or just the
+/// viewed step with the highlight when two grids don't fit side by side.
+pub fn render_diff(
+    frame: &mut Frame,
+    area: Rect,
+    history: &History,
+    step: usize,
+    marked: usize,
+    zoom: Zoom,
+    palette: Palette,
+) {
+    let (Some(current), Some(other)) = (history.get(step), history.get(marked)) else {
+        return;
+    };
+    let (current, other) = (current.overview(), other.overview());
+    let cell_width = match zoom {
+        Zoom::Double => 2,
+        Zoom::Single | Zoom::Half | Zoom::Block(_) => 1,
+    };
+    let grid_width = current.cols() * cell_width;
+    let status = Line::from(format!(
+        "Step {} vs marked {}: {} cells differ (m: unmark)",
+        step,
+        marked,
+        diff_snapshots(current, other).count
+    ));
+
+    if grid_width * 2 + DIFF_GAP as usize <= area.width as usize {
+        let [grids, status_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let [left, _, right] = Layout::horizontal([
+            Constraint::Length(grid_width as u16),
+            Constraint::Length(DIFF_GAP),
+            Constraint::Min(0),
+        ])
+        .areas(grids);
+        for (rect, title, snapshot, against) in [
+            (left, format!("Step {}", step), current, other),
+            (right, format!("Marked {}", marked), other, current),
+        ] {
+            let changed = diff_snapshots(snapshot, against).changed;
+            let mut lines = vec![Line::from(title)];
+            lines.extend(diff_lines(snapshot, &changed, cell_width, palette));
+            frame.render_widget(Paragraph::new(lines), rect);
+        }
+        frame.render_widget(Paragraph::new(status), status_area);
+    } else {
+        let changed = diff_snapshots(current, other).changed;
+        let mut lines = diff_lines(current, &changed, cell_width, palette);
+        lines.push(status);
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+}
+
+/// Grid rows with changed cells at full brightness and unchanged cells dimmed; vacant cells
+/// are blank.
+fn diff_lines(
+    snapshot: &Snapshot,
+    changed: &[Vec<bool>],
+    cell_width: usize,
+    palette: Palette,
+) -> Vec<Line<'static>> {
+    changed
+        .iter()
+        .enumerate()
+        .map(|(r, changed_row)| {
+            Line::from_iter(changed_row.iter().enumerate().map(|(c, changed)| {
+                let span = match snapshot.occupant(r, c) {
+                    Some(s) => palette
+                        .glyph(s)
+                        .to_string()
+                        .repeat(cell_width)
+                        .fg(palette.color(s)),
+                    None => " ".repeat(cell_width).into(),
+                };
+                if *changed {
+                    span.bold()
+                } else {
+                    span.dim()
+                }
+            }))
+        })
+        .collect()
+}
+
+/// One colored block, name, count, and share per strategy.
+fn legend_line(metric: &Metric, palette: Palette) -> Line<'static> {
+    let total: usize = metric.strategies.values().sum();
+    let mut spans = Vec::new();
+    for (strategy, color, glyph, name) in palette.entries() {
+        let count = metric.strategies.get(&strategy).cloned().unwrap_or(0);
+        spans.push(glyph.to_string().repeat(2).fg(color));
+        spans.push(
+            format!(
+                " {} {} ({})  ",
+                name,
+                count,
+                format_percentage(count, total)
+            )
+            .into(),
+        );
+    }
+    Line::from(spans)
+}
+
+/// The live parameter panel, with the selected entry highlighted.
+fn param_lines(ui: &UiState, speed: &str, noise: f32) -> Vec<Line<'static>> {
+    LIVE_PARAMS
+        .iter()
+        .map(|p| {
+            let value = match p {
+                LiveParam::Noise => format!("{:.2}", noise),
+                LiveParam::Speed => speed.to_string(),
+            };
+            let text = format!(
+                " {:<6} {:>8}  (+/- to adjust, Tab next, Esc close)",
+                p.name(),
+                value
+            );
+            if *p == ui.params.selected() {
+                Line::from(text.reversed())
+            } else {
+                Line::from(text)
+            }
+        })
+        .collect()
+}
+
+/// The key bindings, drawn over whatever is in `area`.
+pub fn render_help(frame: &mut Frame, area: Rect) {
+    let lines: Vec<Line> = KEY_BINDINGS
+        .iter()
+        .map(|(key, action)| Line::from(vec![format!("{:>14}  ", key).bold(), (*action).into()]))
+        .collect();
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Help (press any key to close)")),
+        area,
+    );
+}
+
+/// One run's grid at one step, with its overlays, legend and status line below.
+pub struct GridView<'a> {
+    pub step: usize,
+    pub metric: &'a Metric,
+    /// The step changes are highlighted against, if any.
+    pub previous: Option<&'a Metric>,
+    pub ui: &'a UiState,
+    /// Measured steps per second.
+    pub actual_rate: f64,
+    pub config: &'a SimConfig,
+}
+
+/// Draws `view` into `area`, the status line taking the area's width.
+pub fn render_grid_view(frame: &mut Frame, area: Rect, view: &GridView) {
+    let GridView {
+        metric, ui, config, ..
+    } = *view;
+    // Steps browsed past the recent window may only have their thumbnails left, which the
+    // overlays drawn in full-grid coordinates don't fit.
+    let snapshot = metric.overview();
+    let full = snapshot.dimensions() == metric.scores.dimensions();
+    let changes = view
+        .previous
+        .filter(|p| p.overview().dimensions() == snapshot.dimensions())
+        .map(|p| diff_snapshots(snapshot, p.overview()));
+    let selected = ui.inspect.map(|c| c.coord());
+    let mut lines = grid_lines(snapshot, ui.zoom, selected, ui.palette);
+    if config.asymmetric.is_some() {
+        dim_visitors(&mut lines, snapshot, ui.zoom);
+    }
+    if let Some(diff) = &changes {
+        highlight_changes(&mut lines, &diff.changed, ui.zoom);
+    }
+    if let Some(groups) = config.groups.filter(|_| ui.show_groups && full) {
+        outline_groups(&mut lines, groups, snapshot.dimensions(), ui.zoom);
+    }
+    if full {
+        flash_shocks(&mut lines, &metric.shocks, snapshot.dimensions(), ui.zoom);
+    }
+    if let Some((r, c)) = ui.follow {
+        let rows = ui.zoom.block() * ui.zoom.rows_per_line();
+        let span = lines
+            .get_mut(r / rows)
+            .and_then(|line| line.spans.get_mut(c / ui.zoom.block()));
+        if let Some(span) = span {
+            span.style = span.style.add_modifier(Modifier::REVERSED);
+        }
+    }
+    if ui.show_legend {
+        lines.push(legend_line(metric, ui.palette));
+    }
+    if ui.params.open {
+        let speed = match ui.pacer.target_rate() {
+            Some(rate) => format!("{}/s", rate),
+            None => "max".to_string(),
+        };
+        lines.extend(param_lines(ui, &speed, config.noise));
+    }
+    lines.push(status_line(view, changes.map(|d| d.count), area.width));
+    frame.render_widget(Paragraph::new(lines), area);
+}
+
+/// The step prompt or export menu while one is open, else the status bar fit to `width`
+/// with any message at its end.
+fn status_line(view: &GridView, changed: Option<usize>, width: u16) -> Line<'static> {
+    let ui = view.ui;
+    let message = ui
+        .message
+        .as_ref()
+        .map(|(message, _)| format!(" | {}", message));
+    let message_width = message
+        .as_ref()
+        .map_or(0, |m| Span::from(m.as_str()).width());
+    let run = RunState {
+        step: view.step,
+        noise: view.config.noise,
+        seed: view.config.seed,
+        target_rate: ui.pacer.target_rate(),
+        actual_rate: view.actual_rate,
+        run_mode: ui.run_mode,
+        detached: ui.timeline.mode() == ViewMode::Detach,
+        browsing_paused: ui.browsing_paused(),
+        playback: ui.playback.as_ref().map(|p| p.rate()),
+        changed,
+        palette: ui.palette,
+        width: width.saturating_sub(message_width as u16),
+    };
+    let mut status = format_status(view.metric, &run);
+    status.extend(message.map(Span::from));
+    match (&ui.prompt, &ui.export_menu) {
+        (Some(prompt), _) => {
+            let hint = match prompt.error() {
+                Some(PromptError::Empty) => "  enter a step number (Esc to cancel)",
+                None => "",
+            };
+            Line::from(format!("Go to step: {}_{}", prompt.text(), hint))
+        }
+        (None, Some(menu)) => {
+            let mut spans = vec![Span::from("Export:")];
+            for (i, kind) in EXPORT_KINDS.iter().enumerate() {
+                let entry = format!(" {} {} ", i + 1, kind.label());
+                spans.push(if *kind == menu.selected() {
+                    entry.reversed()
+                } else {
+                    entry.into()
+                });
+            }
+            spans.push(Span::from(" (Enter to export, Esc to cancel)"));
+            Line::from(spans)
+        }
+        (None, None) => Line::from(status),
+    }
+}
+
+/// Grid rows styled for the given zoom level, with the cell (or block) under the inspector
+/// cursor drawn inverted (or black in half-block mode). Vacant cells are blank.
+fn grid_lines(
+    snapshot: &Snapshot,
+    zoom: Zoom,
+    selected: Option<Coord>,
+    palette: Palette,
+) -> Vec<Line<'static>> {
+    match zoom {
+        Zoom::Double | Zoom::Single => {
+            let (width, cursor) = match zoom {
+                Zoom::Double => (2, "<>"),
+                _ => (1, "X"),
+            };
+            snapshot
+                .iter_rows()
+                .enumerate()
+                .map(|(i, row)| {
+                    Line::from_iter(row.iter().enumerate().map(|(j, s)| {
+                        let vacant = snapshot.is_vacant(i, j);
+                        if selected == Some((i, j)) {
+                            let background = if vacant {
+                                Color::White
+                            } else {
+                                palette.color(*s)
+                            };
+                            cursor.fg(Color::Black).bg(background)
+                        } else if vacant {
+                            " ".repeat(width).into()
+                        } else {
+                            palette
+                                .glyph(*s)
+                                .to_string()
+                                .repeat(width)
+                                .fg(palette.color(*s))
+                        }
+                    }))
+                })
+                .collect()
+        }
+        Zoom::Half => half_block_lines(snapshot, palette, selected),
+        Zoom::Block(n) => {
+            let selected = selected.map(|(r, c)| (r / n, c / n));
+            aggregate_blocks(snapshot, n)
+                .iter()
+                .enumerate()
+                .map(|(i, row)| {
+                    Line::from_iter(row.iter().enumerate().map(|(j, s)| {
+                        let (glyph, color) =
+                            s.map_or(palette.tie(), |s| (palette.glyph(s), palette.color(s)));
+                        if selected == Some((i, j)) {
+                            "X".fg(Color::Black).bg(color)
+                        } else {
+                            glyph.to_string().fg(color)
+                        }
+                    }))
+                })
+                .collect()
+        }
+    }
+}
+
+/// Draws rendered units that hold a changed cell bold and dims the rest.
+fn highlight_changes(lines: &mut [Line], changed: &[Vec<bool>], zoom: Zoom) {
+    let tiles = any_in_tiles(changed, zoom.block() * zoom.rows_per_line(), zoom.block());
+    for (line, row) in lines.iter_mut().zip(&tiles) {
+        for (span, changed) in line.spans.iter_mut().zip(row) {
+            let modifier = if *changed {
+                Modifier::BOLD
+            } else {
+                Modifier::DIM
+            };
+            span.style = span.style.add_modifier(modifier);
+        }
+    }
+}
+
+/// Dims the rendered units along every group's top and left edges, unless the zoom level packs
+/// a whole group into a single unit.
+fn outline_groups(lines: &mut [Line], groups: Groups, (rows, cols): (usize, usize), zoom: Zoom) {
+    let (unit_rows, unit_cols) = (zoom.block() * zoom.rows_per_line(), zoom.block());
+    if unit_rows >= groups.rows || unit_cols >= groups.cols {
+        return;
+    }
+    let edges: Vec<Vec<bool>> = (0..rows)
+        .map(|r| (0..cols).map(|c| groups.on_boundary((r, c))).collect())
+        .collect();
+    let tiles = any_in_tiles(&edges, unit_rows, unit_cols);
+    for (line, row) in lines.iter_mut().zip(&tiles) {
+        for (span, edge) in line.spans.iter_mut().zip(row) {
+            if *edge {
+                span.style = span.style.add_modifier(Modifier::DIM);
+            }
+        }
+    }
+}
+
+/// Dims visitors, when every rendered unit is a single cell, so the two classes of an
+/// asymmetric game stand apart.
+fn dim_visitors(lines: &mut [Line], snapshot: &Snapshot, zoom: Zoom) {
+    if zoom.block() * zoom.rows_per_line() > 1 {
+        return;
+    }
+    for (r, line) in lines.iter_mut().enumerate() {
+        for (c, span) in line.spans.iter_mut().enumerate() {
+            if snapshot.class(r, c) == Some(Class::Visitor) {
+                span.style = span.style.add_modifier(Modifier::DIM);
+            }
+        }
+    }
+}
+
+/// Draws the rendered units that hold a cell hit by one of the step's shocks inverted.
+fn flash_shocks(
+    lines: &mut [Line],
+    shocks: &[FiredShock],
+    (rows, cols): (usize, usize),
+    zoom: Zoom,
+) {
+    if shocks.is_empty() {
+        return;
+    }
+    let hit: Vec<Vec<bool>> = (0..rows)
+        .map(|r| {
+            (0..cols)
+                .map(|c| shocks.iter().any(|s| s.contains((r, c))))
+                .collect()
+        })
+        .collect();
+    let tiles = any_in_tiles(&hit, zoom.block() * zoom.rows_per_line(), zoom.block());
+    for (line, row) in lines.iter_mut().zip(&tiles) {
+        for (span, hit) in line.spans.iter_mut().zip(row) {
+            if *hit {
+                span.style = span.style.add_modifier(Modifier::REVERSED);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use coop::Grid;
+    use coop::Strategy::{Coop as C, Deflect as D};
+    use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+    use super::*;
+
+    /// Step 5 of a 2x3 grid with a column of Deflect agents between two of Coop agents.
+    fn metric() -> Metric {
+        Metric {
+            strategies: BTreeMap::from([(C, 4), (D, 2)]),
+            max_score: Default::default(),
+            avg_score: Default::default(),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 5,
+            coop_actions: 8,
+            coop_rate: 0.5,
+            snapshot: Snapshot::from(vec![vec![C, D, C], vec![C, D, C]]),
+            founder_lineages: 0,
+            population: 6,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: Grid::new(2, 3, vec![0.0; 6]),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        }
+    }
+
+    fn ui(show_legend: bool) -> UiState {
+        let mut ui = UiState::new((2, 3));
+        ui.palette = Palette::Classic;
+        ui.show_legend = show_legend;
+        ui
+    }
+
+    /// The grid view of `metric()` on a `width`x4 terminal.
+    fn render(ui: &UiState, width: u16) -> Buffer {
+        let metric = metric();
+        let config = SimConfig {
+            noise: 0.1,
+            seed: 7,
+            ..SimConfig::default()
+        };
+        let view = GridView {
+            step: 5,
+            metric: &metric,
+            previous: None,
+            ui,
+            actual_rate: 10.0,
+            config: &config,
+        };
+        let mut terminal = Terminal::new(TestBackend::new(width, 4)).unwrap();
+        terminal
+            .draw(|frame| render_grid_view(frame, frame.area(), &view))
+            .unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    fn row(buffer: &Buffer, y: u16) -> String {
+        (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect()
+    }
+
+    #[test]
+    fn test_grid() {
+        let buffer = render(&ui(false), 12);
+        assert_eq!(row(&buffer, 0), "██████      ");
+        assert_eq!(row(&buffer, 1), "██████      ");
+        let colors = [Color::Green, Color::Red, Color::Green];
+        for y in 0..2 {
+            for x in 0..6 {
+                assert_eq!(buffer[(x, y)].fg, colors[x as usize / 2], "({}, {})", x, y);
+            }
+            assert_eq!(buffer[(6, y)].fg, Color::Reset);
+        }
+        // Only the step fits next to the grid.
+        assert_eq!(row(&buffer, 2), "Step 5      ");
+        assert_eq!(row(&buffer, 3), " ".repeat(12));
+    }
+
+    #[test]
+    fn test_status_line() {
+        let buffer = render(&ui(false), 200);
+        let status = row(&buffer, 2);
+        assert!(
+            status.starts_with("Step 5 │ █  33.3% │ █   0.0% │ █  66.7% │ "),
+            "{}",
+            status
+        );
+        assert!(status.trim_end().ends_with("│ Noise 0.10"), "{}", status);
+        for (x, color) in [(9, Color::Red), (20, Color::Yellow), (31, Color::Green)] {
+            assert_eq!(buffer[(x, 2)].fg, color);
+            assert_eq!(buffer[(x + 7, 2)].fg, color);
+        }
+        assert_eq!(buffer[(7, 2)].fg, Color::Reset);
+    }
+
+    #[test]
+    fn test_legend() {
+        let buffer = render(&ui(true), 200);
+        let legend = row(&buffer, 2);
+        assert!(
+            legend.starts_with("██ Deflect 2 (33.3%)  ██ TicToc 0 (0.0%)  ██ Coop 4 (66.7%)  "),
+            "{}",
+            legend
+        );
+        for (x, color) in [(0, Color::Red), (22, Color::Yellow), (42, Color::Green)] {
+            assert_eq!((buffer[(x, 2)].fg, buffer[(x + 1, 2)].fg), (color, color));
+            assert_eq!(buffer[(x + 2, 2)].fg, Color::Reset);
+        }
+        assert!(row(&buffer, 3).starts_with("Step 5 │ "));
+    }
+
+    #[test]
+    fn test_detach_indicator() {
+        let mut ui = ui(false);
+        ui.timeline.detach_at(5, 6usize);
+        let buffer = render(&ui, 200);
+        let status = row(&buffer, 2);
+        assert!(
+            status.starts_with("Step 5 DETACH @ 5 PAUSED (k: keep running) │ "),
+            "{}",
+            status
+        );
+        let indicator = Modifier::BOLD | Modifier::REVERSED;
+        assert!(buffer[(7, 2)].modifier.contains(indicator));
+        assert!(buffer[(16, 2)].modifier.contains(indicator));
+        assert!(buffer[(17, 2)].modifier.is_empty());
+        ui.keep_running = true;
+        let status = row(&render(&ui, 200), 2);
+        assert!(status.starts_with("Step 5 DETACH @ 5 │ "), "{}", status);
+        ui.timeline.latest();
+        let status = row(&render(&ui, 200), 2);
+        assert!(status.starts_with("Step 5 │ "), "{}", status);
+    }
+}
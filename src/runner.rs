@@ -0,0 +1,344 @@
+//! Stepping on a background thread.
+//!
+//! [`spawn_runner`] moves a simulation onto a worker thread that steps it at a target rate and
+//! sends every step's output over a channel, so a UI only drains metrics and never waits on a
+//! step. The worker owns the simulation from then on; it's controlled with [`Command`]s, and
+//! [`Command::Inspect`] runs a closure on the worker to read state the metrics don't carry.
+//! An [`Environment`] is `Send`, so it can be moved there, and several can be stepped in
+//! lockstep as a `Vec<Environment>`.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::env::{Environment, Metric};
+use crate::pacing::Pacer;
+
+/// How long the worker waits for commands between looking at the clock when no step is due.
+const IDLE_POLL: Duration = Duration::from_millis(1);
+
+/// Something a runner can step.
+pub trait Simulation: Send + 'static {
+    /// What one step produces.
+    type Output: Send + 'static;
+
+    fn step(&mut self) -> Self::Output;
+
+    fn set_noise(&mut self, noise: f32);
+}
+
+impl Simulation for Environment {
+    type Output = Metric;
+
+    fn step(&mut self) -> Metric {
+        Environment::step(self)
+    }
+
+    fn set_noise(&mut self, noise: f32) {
+        Environment::set_noise(self, noise);
+    }
+}
+
+/// Environments stepped in lockstep, producing one metric each per step.
+impl Simulation for Vec<Environment> {
+    type Output = Vec<Metric>;
+
+    fn step(&mut self) -> Vec<Metric> {
+        self.iter_mut().map(Environment::step).collect()
+    }
+
+    fn set_noise(&mut self, noise: f32) {
+        for env in self {
+            env.set_noise(noise);
+        }
+    }
+}
+
+/// Controls a running simulation.
+pub enum Command<S> {
+    /// Stops stepping until `Resume`; time spent paused isn't caught up on.
+    Pause,
+    Resume,
+    /// Takes one step now, paused or not.
+    Step,
+    /// Target steps per second, or `None` for as fast as possible.
+    SetSpeed(Option<u32>),
+    SetNoise(f32),
+    /// Calls the closure with the simulation between two steps.
+    Inspect(Box<dyn FnOnce(&S) + Send>),
+    /// Stops the worker, which drops the simulation and closes the metric channel.
+    Shutdown,
+}
+
+pub type CommandSender<S> = Sender<Command<S>>;
+pub type MetricReceiver<T> = Receiver<T>;
+
+/// How a runner starts out.
+pub struct RunnerConfig {
+    /// Target steps per second, or `None` for as fast as possible.
+    pub rate: Option<u32>,
+    pub paused: bool,
+    /// Outputs the worker may get ahead of the receiver by before it waits for it to catch up.
+    pub buffer: usize,
+    /// Time since some fixed origin; replaceable so tests can drive the pacing.
+    pub clock: Box<dyn Fn() -> Duration + Send>,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        let start = Instant::now();
+        RunnerConfig {
+            rate: Some(100),
+            paused: false,
+            buffer: 1024,
+            clock: Box::new(move || start.elapsed()),
+        }
+    }
+}
+
+/// Moves `sim` to a new thread that steps it as `config` says and sends every output to the
+/// returned receiver. The worker stops on `Shutdown` or once either channel end is dropped.
+pub fn spawn_runner<S: Simulation>(
+    sim: S,
+    config: RunnerConfig,
+) -> (MetricReceiver<S::Output>, CommandSender<S>) {
+    let (metric_tx, metric_rx) = mpsc::sync_channel(config.buffer);
+    let (command_tx, command_rx) = mpsc::channel();
+    thread::spawn(move || run(sim, config, command_rx, metric_tx));
+    (metric_rx, command_tx)
+}
+
+/// The worker loop: handles every waiting command, then takes one due step, and waits for
+/// commands in between when nothing is due.
+fn run<S: Simulation>(
+    mut sim: S,
+    config: RunnerConfig,
+    commands: Receiver<Command<S>>,
+    metrics: SyncSender<S::Output>,
+) {
+    let clock = config.clock;
+    let mut pacer = Pacer::new(config.rate);
+    pacer.reset(clock());
+    let mut paused = config.paused;
+    let mut pending = None;
+    // Steps the pacer has granted that haven't been taken yet.
+    let mut owed = 0;
+    loop {
+        if paused && pending.is_none() {
+            pending = commands.recv().ok().or(Some(Command::Shutdown));
+        }
+        loop {
+            let command = match pending.take().map_or_else(|| commands.try_recv(), Ok) {
+                Ok(command) => command,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            };
+            match command {
+                Command::Pause => {
+                    paused = true;
+                    owed = 0;
+                }
+                Command::Resume => {
+                    if paused {
+                        pacer.reset(clock());
+                    }
+                    paused = false;
+                }
+                Command::Step => {
+                    if metrics.send(sim.step()).is_err() {
+                        return;
+                    }
+                }
+                Command::SetSpeed(rate) => {
+                    pacer.set_rate(rate);
+                    owed = 0;
+                }
+                Command::SetNoise(noise) => sim.set_noise(noise),
+                Command::Inspect(f) => f(&sim),
+                Command::Shutdown => return,
+            }
+        }
+        if paused {
+            continue;
+        }
+        if owed == 0 {
+            owed = pacer.due(clock());
+        }
+        if owed == 0 {
+            match commands.recv_timeout(IDLE_POLL) {
+                Ok(command) => pending = Some(command),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            continue;
+        }
+        if metrics.send(sim.step()).is_err() {
+            return;
+        }
+        owed -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::builder::EnvironmentBuilder;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    /// Generous bound on how long the worker may take to react.
+    const REACT: Duration = Duration::from_secs(5);
+
+    /// A clock that only moves when told to, in milliseconds.
+    fn fake_clock() -> (Arc<AtomicU64>, Box<dyn Fn() -> Duration + Send>) {
+        let now = Arc::new(AtomicU64::new(0));
+        let read = Arc::clone(&now);
+        (now, Box::new(move || ms(read.load(Ordering::SeqCst))))
+    }
+
+    fn env() -> Environment {
+        EnvironmentBuilder::new()
+            .size(4, 4)
+            .seed(1)
+            .noise(0.1)
+            .build()
+            .unwrap()
+    }
+
+    /// Nothing arrives within a short while.
+    fn assert_quiet<T>(metrics: &MetricReceiver<T>) {
+        assert!(metrics.recv_timeout(ms(50)).is_err());
+    }
+
+    #[test]
+    fn test_steps_at_the_target_rate() {
+        let (now, clock) = fake_clock();
+        let config = RunnerConfig {
+            rate: Some(10),
+            clock,
+            ..RunnerConfig::default()
+        };
+        let (metrics, _commands) = spawn_runner(env(), config);
+        assert_quiet(&metrics);
+        for step in 0..5 {
+            now.fetch_add(100, Ordering::SeqCst);
+            let metric = metrics.recv_timeout(REACT).unwrap();
+            assert_eq!(metric.step_index, step);
+        }
+        assert_quiet(&metrics);
+    }
+
+    #[test]
+    fn test_pause_resume_and_single_steps() {
+        let (now, clock) = fake_clock();
+        let config = RunnerConfig {
+            rate: Some(10),
+            paused: true,
+            clock,
+            ..RunnerConfig::default()
+        };
+        let (metrics, commands) = spawn_runner(env(), config);
+        now.fetch_add(1_000, Ordering::SeqCst);
+        assert_quiet(&metrics);
+        commands.send(Command::Step).unwrap();
+        assert_eq!(metrics.recv_timeout(REACT).unwrap().step_index, 0);
+        assert_quiet(&metrics);
+
+        // The paused second isn't caught up on.
+        commands.send(Command::Resume).unwrap();
+        assert_quiet(&metrics);
+        now.fetch_add(100, Ordering::SeqCst);
+        assert_eq!(metrics.recv_timeout(REACT).unwrap().step_index, 1);
+
+        commands.send(Command::Pause).unwrap();
+        // Wait until the pause has been handled before moving the clock.
+        let (done, seen) = mpsc::channel();
+        let inspect = move |_: &Environment| done.send(()).unwrap();
+        commands.send(Command::Inspect(Box::new(inspect))).unwrap();
+        seen.recv_timeout(REACT).unwrap();
+        now.fetch_add(500, Ordering::SeqCst);
+        assert_quiet(&metrics);
+    }
+
+    #[test]
+    fn test_set_speed() {
+        let (now, clock) = fake_clock();
+        let config = RunnerConfig {
+            rate: Some(1),
+            buffer: 8,
+            clock,
+            ..RunnerConfig::default()
+        };
+        let (metrics, commands) = spawn_runner(env(), config);
+        assert_quiet(&metrics);
+        // Uncapped, the worker runs ahead until the buffer is full, without the clock moving.
+        commands.send(Command::SetSpeed(None)).unwrap();
+        let steps: Vec<usize> = metrics.iter().take(20).map(|m| m.step_index).collect();
+        assert_eq!(steps, (0..20).collect::<Vec<_>>());
+
+        commands.send(Command::SetSpeed(Some(20))).unwrap();
+        // Drain what the uncapped worker had buffered before it saw the new speed.
+        while metrics.recv_timeout(ms(50)).is_ok() {}
+        now.fetch_add(50, Ordering::SeqCst);
+        assert!(metrics.recv_timeout(REACT).is_ok());
+        assert_quiet(&metrics);
+    }
+
+    #[test]
+    fn test_set_noise_and_inspect() {
+        let config = RunnerConfig {
+            paused: true,
+            ..RunnerConfig::default()
+        };
+        let (_metrics, commands) = spawn_runner(env(), config);
+        commands.send(Command::SetNoise(0.4)).unwrap();
+        let (noise_tx, noise_rx) = mpsc::channel();
+        let inspect = move |env: &Environment| noise_tx.send(env.noise()).unwrap();
+        commands.send(Command::Inspect(Box::new(inspect))).unwrap();
+        assert_eq!(noise_rx.recv_timeout(REACT), Ok(0.4));
+    }
+
+    #[test]
+    fn test_shutdown_closes_the_metric_channel() {
+        let config = RunnerConfig {
+            paused: true,
+            ..RunnerConfig::default()
+        };
+        let (metrics, commands) = spawn_runner(env(), config);
+        commands.send(Command::Step).unwrap();
+        commands.send(Command::Shutdown).unwrap();
+        assert_eq!(metrics.recv_timeout(REACT).unwrap().step_index, 0);
+        assert_eq!(
+            metrics.recv_timeout(REACT),
+            Err(RecvTimeoutError::Disconnected)
+        );
+        // Dropping the command sender stops a worker too.
+        let (metrics, commands) = spawn_runner(env(), RunnerConfig::default());
+        drop(commands);
+        while metrics.recv_timeout(REACT).is_ok() {}
+    }
+
+    #[test]
+    fn test_lockstep_environments() {
+        let config = RunnerConfig {
+            paused: true,
+            ..RunnerConfig::default()
+        };
+        let mut alone = env();
+        let (metrics, commands) = spawn_runner(vec![env(), env()], config);
+        commands.send(Command::Step).unwrap();
+        commands.send(Command::Step).unwrap();
+        for _ in 0..2 {
+            let expected = alone.step();
+            assert_eq!(
+                metrics.recv_timeout(REACT).unwrap(),
+                vec![expected.clone(), expected]
+            );
+        }
+    }
+}
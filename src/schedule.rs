@@ -0,0 +1,71 @@
+//! Random orders for visiting the agents one at a time. Each step's order is a pure function
+//! of the run's seed and the step, drawn from a stream of its own, so it can be recomputed
+//! after the fact and no other random draw can shift it.
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::env::splitmix64;
+
+/// Tag keeping the orders' stream apart from the keyed streams of individual cells.
+const ORDER_DRAWS: u64 = u64::MAX;
+
+/// The order `len` agents, by row-major index, are visited in during `step` of a run seeded
+/// with `seed`: a permutation of `0..len`.
+pub fn permutation(seed: u64, step: usize, len: usize) -> Vec<usize> {
+    let key = [step as u64, ORDER_DRAWS]
+        .into_iter()
+        .fold(splitmix64(seed), |h, v| splitmix64(h ^ v));
+    let mut order: Vec<usize> = (0..len).collect();
+    order.shuffle(&mut StdRng::seed_from_u64(key));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, Strategy};
+    use crate::builder::EnvironmentBuilder;
+    use crate::env::UpdateMode;
+
+    #[test]
+    fn test_permutation_is_bijection() {
+        for len in [0, 1, 2, 17, 400] {
+            let mut order = permutation(9, 3, len);
+            order.sort_unstable();
+            assert_eq!(order, (0..len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_permutation_depends_on_seed_and_step() {
+        assert_eq!(permutation(9, 3, 100), permutation(9, 3, 100));
+        assert_ne!(permutation(9, 3, 100), permutation(9, 4, 100));
+        assert_ne!(permutation(9, 3, 100), permutation(10, 3, 100));
+    }
+
+    #[test]
+    fn test_step_order_ignores_other_draws() {
+        // Random agents under noise draw plenty; Coop agents draw nothing.
+        let run = |strategy: Strategy, noise: f32| {
+            let mut env = EnvironmentBuilder::new()
+                .size(6, 7)
+                .seed(21)
+                .noise(noise)
+                .update_mode(UpdateMode::Asynchronous)
+                .agents(|c, _| Agent::new(c, strategy))
+                .build()
+                .unwrap();
+            (0..5)
+                .map(|_| {
+                    env.step();
+                    env.last_update_order().unwrap().to_vec()
+                })
+                .collect::<Vec<_>>()
+        };
+        let orders = run(Strategy::Random, 0.2);
+        assert_eq!(orders, run(Strategy::Coop, 0.0));
+        for (step, order) in orders.iter().enumerate() {
+            assert_eq!(*order, permutation(21, step, 42));
+        }
+    }
+}
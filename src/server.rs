@@ -0,0 +1,1048 @@
+//! `coop serve`: runs a simulation headlessly and serves it over HTTP, for browser dashboards.
+//!
+//! - `GET /state` returns the latest step's metrics as JSON, see [`metrics_json`].
+//! - `GET /snapshot.png` returns the latest step's grid as a PNG.
+//! - `POST /control` takes a form-encoded command: `command=pause`, `resume`, `step`,
+//!   `set-noise&noise=0.2`, or `restart`, optionally with `seed=N`. Browsers may only send
+//!   it from an origin allowed with `--allow-origin`.
+//! - `GET /stream` upgrades to a WebSocket that gets every step's metrics JSON as a text
+//!   message. A client that falls behind misses steps rather than holding the run back.
+//!
+//! - `GET /metrics` returns Prometheus metrics with the `prometheus` feature, see
+//!   [`crate::prometheus`].
+//!
+//! The server listens on localhost unless told otherwise with `--bind`, since anyone who can
+//! reach it can control the run. Responses carry CORS headers only for the allowed origins.
+//!
+//! The simulation steps on a background runner as in the TUI. A single pump thread drains its
+//! metrics into the latest state and the stream subscribers and forwards control commands, so
+//! requests only ever read the last published step and never wait on the environment.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "prometheus")]
+use std::time::Instant;
+
+use coop::json::metrics_json;
+use coop::runner::{CommandSender, MetricReceiver};
+use coop::{spawn_runner, Command, Environment, Metric, RunnerConfig};
+
+use crate::config::SimConfig;
+use crate::export::{encode_png, snapshot_image, PNG_CELL_PX};
+use crate::palette::{Palette, PALETTES};
+#[cfg(feature = "prometheus")]
+use crate::prometheus::{self, Exporter};
+
+/// Address listened on by default.
+pub const DEFAULT_BIND: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+/// Port served on by default.
+pub const DEFAULT_PORT: u16 = 8080;
+/// Steps per second by default; dashboards rarely redraw faster.
+pub const DEFAULT_RATE: u32 = 10;
+/// Steps the runner may get ahead of the pump.
+const RUNNER_BUFFER: usize = 256;
+/// Messages a stream client may fall behind by before it starts missing steps.
+const STREAM_BUFFER: usize = 64;
+/// How long the pump waits for a step before looking at the control commands again.
+const PUMP_POLL: Duration = Duration::from_millis(5);
+/// How long a client may take to send its request or to take a response before it's dropped.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a stream goes without a step before it's pinged, which finds clients that left.
+const STREAM_PING: Duration = Duration::from_secs(15);
+/// Longest request body accepted; commands are a few bytes.
+const MAX_BODY: usize = 4096;
+/// An empty WebSocket ping, which clients answer and otherwise ignore.
+const PING_FRAME: [u8; 2] = [0x89, 0x00];
+/// Appended to a WebSocket key before hashing, as RFC 6455 specifies.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Options of `coop serve`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServeArgs {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Target steps per second.
+    pub rate: u32,
+    /// Start paused, waiting for a `resume` or `step` command.
+    pub paused: bool,
+    pub palette: Palette,
+    /// Origins, e.g. `http://localhost:3000`, whose pages may read the responses and send
+    /// control commands.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for ServeArgs {
+    fn default() -> Self {
+        ServeArgs {
+            bind: DEFAULT_BIND,
+            port: DEFAULT_PORT,
+            rate: DEFAULT_RATE,
+            paused: false,
+            palette: Palette::default(),
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl ServeArgs {
+    /// Parses the arguments after `serve`, in the style of [`crate::cli::Args::parse`].
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<ServeArgs, String> {
+        let mut parsed = ServeArgs::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--bind" => {
+                    let addr = value()?;
+                    parsed.bind = addr
+                        .parse()
+                        .map_err(|_| format!("{} expects an IP address, got {:?}", flag, addr))?;
+                }
+                "--allow-origin" => parsed.allowed_origins.push(value()?),
+                "--port" => {
+                    let port = value()?;
+                    parsed.port = port
+                        .parse()
+                        .map_err(|_| format!("{} expects a port number, got {:?}", flag, port))?;
+                }
+                "--rate" => {
+                    let rate = value()?;
+                    parsed.rate = match rate.parse() {
+                        Ok(r) if r > 0 => r,
+                        _ => {
+                            return Err(format!(
+                                "{} expects a positive integer, got {:?}",
+                                flag, rate
+                            ))
+                        }
+                    };
+                }
+                "--palette" => {
+                    let name = value()?;
+                    parsed.palette = Palette::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = PALETTES.iter().map(|p| p.name()).collect();
+                        format!(
+                            "unknown palette {:?}, expected one of {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                }
+                "--paused" if inline.is_none() => parsed.paused = true,
+                _ => return Err(format!("unknown argument: {}", flag)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Runs `coop serve` with the arguments after `serve` until the process is killed.
+pub fn main(args: impl IntoIterator<Item = String>) {
+    let args = match ServeArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let addr = SocketAddr::new(args.bind, args.port);
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("can't listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+    match start(listener, SimConfig::default(), &args) {
+        Ok(()) => println!("Serving on http://{}", addr),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+    loop {
+        thread::park();
+    }
+}
+
+/// Something `POST /control` asks of the pump.
+enum Control {
+    Runner(Command<Environment>),
+    SetNoise(f32),
+    /// Rebuilds the run from its config, with a different seed if given.
+    Restart(Option<u64>),
+}
+
+/// A control command with where the pump answers whether it was carried out.
+type ControlRequest = (Control, Sender<Result<(), String>>);
+
+/// What the pump publishes for requests to read.
+struct Shared {
+    latest: Mutex<Metric>,
+    subscribers: Mutex<Vec<SyncSender<String>>>,
+    palette: Palette,
+    allowed_origins: Vec<String>,
+    /// When serving started, the origin of the times steps are published at.
+    #[cfg(feature = "prometheus")]
+    started: Instant,
+    #[cfg(feature = "prometheus")]
+    exporter: Mutex<Exporter>,
+}
+
+impl Shared {
+    fn new(first: Metric, args: &ServeArgs) -> Shared {
+        let shared = Shared {
+            latest: Mutex::new(first),
+            subscribers: Mutex::new(Vec::new()),
+            palette: args.palette,
+            allowed_origins: args.allowed_origins.clone(),
+            #[cfg(feature = "prometheus")]
+            started: Instant::now(),
+            #[cfg(feature = "prometheus")]
+            exporter: Mutex::new(Exporter::default()),
+        };
+        #[cfg(feature = "prometheus")]
+        shared
+            .exporter
+            .lock()
+            .unwrap()
+            .observe(&shared.latest.lock().unwrap(), Duration::ZERO);
+        shared
+    }
+
+    /// The request's origin, if it's one that may read responses and send commands.
+    fn allowed_origin<'a>(&self, request: &'a Request) -> Option<&'a str> {
+        request
+            .header("origin")
+            .filter(|o| self.allowed_origins.iter().any(|a| a == o))
+    }
+
+    /// Makes `metric` the latest and queues it for every stream client, forgetting the
+    /// clients that have gone.
+    fn publish(&self, metric: Metric) {
+        #[cfg(feature = "prometheus")]
+        self.exporter
+            .lock()
+            .unwrap()
+            .observe(&metric, self.started.elapsed());
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if !subscribers.is_empty() {
+            let json = metrics_json(&metric);
+            subscribers.retain(|s| {
+                !matches!(s.try_send(json.clone()), Err(TrySendError::Disconnected(_)))
+            });
+        }
+        *self.latest.lock().unwrap() = metric;
+    }
+}
+
+/// Builds a run of `config`, takes its first step so there's always a state to serve, and
+/// serves it on `listener` from background threads.
+pub fn start(
+    listener: TcpListener,
+    config: SimConfig,
+    args: &ServeArgs,
+) -> Result<(), coop::Error> {
+    let mut env = config.build()?;
+    let shared = Arc::new(Shared::new(env.step(), args));
+    let runner = spawn(env, args.rate, args.paused);
+    let (controls, control_rx) = mpsc::channel();
+    let pump_shared = Arc::clone(&shared);
+    let (rate, paused) = (args.rate, args.paused);
+    thread::spawn(move || pump(config, rate, paused, runner, control_rx, pump_shared));
+    thread::spawn(move || accept(listener, shared, controls));
+    Ok(())
+}
+
+fn spawn(
+    env: Environment,
+    rate: u32,
+    paused: bool,
+) -> (MetricReceiver<Metric>, CommandSender<Environment>) {
+    let config = RunnerConfig {
+        rate: Some(rate),
+        paused,
+        buffer: RUNNER_BUFFER,
+        ..RunnerConfig::default()
+    };
+    spawn_runner(env, config)
+}
+
+/// Publishes the runner's steps and carries out control commands until every request
+/// handler is gone.
+fn pump(
+    mut config: SimConfig,
+    rate: u32,
+    mut paused: bool,
+    (mut metrics, mut commands): (MetricReceiver<Metric>, CommandSender<Environment>),
+    controls: Receiver<ControlRequest>,
+    shared: Arc<Shared>,
+) {
+    loop {
+        match metrics.recv_timeout(PUMP_POLL) {
+            Ok(metric) => shared.publish(metric),
+            Err(RecvTimeoutError::Timeout) => {}
+            // The worker only stops when told to; wait for a restart if it died anyway.
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(PUMP_POLL),
+        }
+        loop {
+            let (control, reply) = match controls.try_recv() {
+                Ok(request) => request,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    let _ = commands.send(Command::Shutdown);
+                    return;
+                }
+            };
+            let result = match control {
+                Control::Runner(command) => {
+                    match command {
+                        Command::Pause => paused = true,
+                        Command::Resume => paused = false,
+                        _ => {}
+                    }
+                    let _ = commands.send(command);
+                    Ok(())
+                }
+                Control::SetNoise(noise) => {
+                    config.noise = noise;
+                    let _ = commands.send(Command::SetNoise(noise));
+                    Ok(())
+                }
+                Control::Restart(seed) => {
+                    let restarted = SimConfig {
+                        seed: seed.unwrap_or(config.seed),
+                        ..config.clone()
+                    };
+                    // A run that can't be rebuilt leaves the current one going.
+                    match restarted.build() {
+                        Ok(mut env) => {
+                            let _ = commands.send(Command::Shutdown);
+                            config = restarted;
+                            shared.publish(env.step());
+                            (metrics, commands) = spawn(env, rate, paused);
+                            Ok(())
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+            // The handler may have given up waiting.
+            let _ = reply.send(result.map_err(|e| e.to_string()));
+        }
+    }
+}
+
+fn accept(listener: TcpListener, shared: Arc<Shared>, controls: Sender<ControlRequest>) {
+    for stream in listener.incoming().flatten() {
+        // A client that stalls mid-request or stops reading gives its thread back.
+        let timeouts = stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(IO_TIMEOUT)));
+        if timeouts.is_err() {
+            continue;
+        }
+        let shared = Arc::clone(&shared);
+        let controls = controls.clone();
+        // A client that hangs up mid-request has nothing left to be told.
+        thread::spawn(move || handle(stream, &shared, &controls));
+    }
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    /// The path without any query string.
+    path: String,
+    /// Header names lowercased.
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let method = method.to_string();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("request ended in the headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: String::new(),
+    };
+    let length = match request.header("content-length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| invalid("malformed content length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    request.body = String::from_utf8(body).map_err(|_| invalid("request body isn't UTF-8"))?;
+    Ok(request)
+}
+
+fn handle(stream: TcpStream, shared: &Shared, controls: &Sender<ControlRequest>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(e) => {
+            return respond(
+                &mut stream,
+                None,
+                400,
+                "text/plain",
+                e.to_string().as_bytes(),
+            )
+        }
+    };
+    let cors = shared.allowed_origin(&request);
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/state") => {
+            let json = metrics_json(&shared.latest.lock().unwrap());
+            respond(&mut stream, cors, 200, "application/json", json.as_bytes())
+        }
+        ("GET", "/snapshot.png") => {
+            let snapshot = shared.latest.lock().unwrap().snapshot.clone();
+            let png = encode_png(&snapshot_image(&snapshot, PNG_CELL_PX, shared.palette));
+            respond(&mut stream, cors, 200, "image/png", &png)
+        }
+        // Browsers send form posts across origins without asking first, so a page from
+        // anywhere else could otherwise drive the run.
+        ("POST", "/control") if request.header("origin").is_some() && cors.is_none() => {
+            respond(&mut stream, None, 403, "text/plain", b"origin not allowed")
+        }
+        ("POST", "/control") => match parse_control(&request.body) {
+            Ok(control) => {
+                let (reply, answer) = mpsc::channel();
+                // The pump only stops once every handler is gone, this one included.
+                let _ = controls.send((control, reply));
+                match answer.recv_timeout(IO_TIMEOUT) {
+                    Ok(Ok(())) => respond(&mut stream, cors, 200, "text/plain", b"ok"),
+                    Ok(Err(e)) => respond(&mut stream, cors, 400, "text/plain", e.as_bytes()),
+                    Err(_) => respond(
+                        &mut stream,
+                        cors,
+                        503,
+                        "text/plain",
+                        b"the run didn't answer",
+                    ),
+                }
+            }
+            Err(e) => respond(&mut stream, cors, 400, "text/plain", e.as_bytes()),
+        },
+        ("GET", "/stream") => stream_metrics(stream, &request, shared),
+        #[cfg(feature = "prometheus")]
+        ("GET", "/metrics") => {
+            let text = shared.exporter.lock().unwrap().render();
+            respond(
+                &mut stream,
+                cors,
+                200,
+                prometheus::CONTENT_TYPE,
+                text.as_bytes(),
+            )
+        }
+        (_, "/state" | "/snapshot.png" | "/control" | "/stream") => {
+            respond(&mut stream, cors, 405, "text/plain", b"method not allowed")
+        }
+        _ => respond(&mut stream, cors, 404, "text/plain", b"not found"),
+    }
+}
+
+/// Writes a complete response. `cors` is the request's origin when it's allowed to read it.
+fn respond(
+    stream: &mut TcpStream,
+    cors: Option<&str>,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    let cors = cors.map_or_else(String::new, |origin| {
+        format!(
+            "Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n",
+            origin
+        )
+    });
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}\
+         Connection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        cors
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// A form-encoded control command, e.g. `command=set-noise&noise=0.2`.
+fn parse_control(body: &str) -> Result<Control, String> {
+    let field = |name: &str| {
+        body.trim()
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    };
+    match field("command") {
+        Some("pause") => Ok(Control::Runner(Command::Pause)),
+        Some("resume") => Ok(Control::Runner(Command::Resume)),
+        Some("step") => Ok(Control::Runner(Command::Step)),
+        Some("set-noise") => {
+            let noise = field("noise").ok_or("set-noise needs a noise")?;
+            match noise.parse::<f32>() {
+                Ok(n) if (0.0..=1.0).contains(&n) => Ok(Control::SetNoise(n)),
+                _ => Err(format!("noise must be in [0, 1], got {:?}", noise)),
+            }
+        }
+        Some("restart") => match field("seed") {
+            None => Ok(Control::Restart(None)),
+            Some(seed) => seed
+                .parse()
+                .map(|seed| Control::Restart(Some(seed)))
+                .map_err(|_| format!("seed must be an integer, got {:?}", seed)),
+        },
+        Some(other) => Err(format!(
+            "unknown command {:?}, expected pause, resume, step, set-noise or restart",
+            other
+        )),
+        None => Err("missing command".to_string()),
+    }
+}
+
+/// Completes a WebSocket handshake and sends every published step to the client until it
+/// goes away. Messages from the client are never read; while no steps come, pings find out
+/// whether it's still there.
+fn stream_metrics(mut stream: TcpStream, request: &Request, shared: &Shared) -> io::Result<()> {
+    let upgrade = request
+        .header("upgrade")
+        .is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+    let Some(key) = request.header("sec-websocket-key").filter(|_| upgrade) else {
+        return respond(
+            &mut stream,
+            shared.allowed_origin(request),
+            400,
+            "text/plain",
+            b"expected a WebSocket upgrade",
+        );
+    };
+    let (tx, rx) = mpsc::sync_channel(STREAM_BUFFER);
+    // Subscribed before the handshake completes, so the client sees every step after it.
+    shared.subscribers.lock().unwrap().push(tx);
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    loop {
+        match rx.recv_timeout(STREAM_PING) {
+            Ok(json) => stream.write_all(&text_frame(&json))?,
+            Err(RecvTimeoutError::Timeout) => stream.write_all(&PING_FRAME)?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+/// The `Sec-WebSocket-Accept` answer to a client's `Sec-WebSocket-Key`.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+/// An unmasked, unfragmented WebSocket text frame, as servers send them.
+fn text_frame(text: &str) -> Vec<u8> {
+    let len = text.len();
+    let mut frame = vec![0x81];
+    if len < 126 {
+        frame.push(len as u8);
+    } else if len <= usize::from(u16::MAX) {
+        frame.push(126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+/// SHA-1, which the WebSocket handshake needs and nothing else here does.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5A827999),
+                20..40 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (out, word) in digest.chunks_mut(4).zip(h) {
+        out.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Standard padded base64.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::SocketAddr;
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Generous bound on how long the server may take to react.
+    const REACT: Duration = Duration::from_secs(5);
+    /// The one origin test servers let in.
+    const DASHBOARD: &str = "http://dashboard.test";
+
+    /// A paused 3x4 run served on a free local port.
+    fn serve() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = SimConfig {
+            rows: 3,
+            cols: 4,
+            seed: 7,
+            ..SimConfig::default()
+        };
+        let args = ServeArgs {
+            paused: true,
+            allowed_origins: vec![DASHBOARD.to_string()],
+            ..ServeArgs::default()
+        };
+        let addr = listener.local_addr().unwrap();
+        start(listener, config, &args).unwrap();
+        addr
+    }
+
+    /// Sends a request and returns the status, the lowercased headers and the body.
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String, Vec<u8>) {
+        request_from(addr, None, method, path, body)
+    }
+
+    /// [`request`] as a browser sends it from a page of `origin`.
+    fn request_from(
+        addr: SocketAddr,
+        origin: Option<&str>,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> (u16, String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let origin = origin.map_or_else(String::new, |o| format!("Origin: {}\r\n", o));
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: test\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            origin,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(response[..end].to_vec()).unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (
+            status,
+            head.to_ascii_lowercase(),
+            response[end + 4..].to_vec(),
+        )
+    }
+
+    fn control(addr: SocketAddr, body: &str) -> u16 {
+        request(addr, "POST", "/control", body).0
+    }
+
+    /// The step number of the latest state.
+    fn step(addr: SocketAddr) -> usize {
+        let (status, _, body) = request(addr, "GET", "/state", "");
+        assert_eq!(status, 200);
+        let json = String::from_utf8(body).unwrap();
+        let rest = json.strip_prefix(r#"{"step":"#).unwrap();
+        rest[..rest.find(',').unwrap()].parse().unwrap()
+    }
+
+    fn wait_for_step(addr: SocketAddr, expected: usize) {
+        let deadline = Instant::now() + REACT;
+        while step(addr) != expected {
+            assert!(Instant::now() < deadline, "never reached step {}", expected);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_state() {
+        let addr = serve();
+        let (status, head, body) = request(addr, "GET", "/state", "");
+        assert_eq!(status, 200);
+        assert!(head.contains("content-type: application/json"));
+        let json = String::from_utf8(body).unwrap();
+        assert!(json.starts_with(r#"{"step":0,"coop_actions":"#), "{}", json);
+        assert!(json.ends_with('}'));
+    }
+
+    #[test]
+    fn test_snapshot_png() {
+        let addr = serve();
+        let (status, head, png) = request(addr, "GET", "/snapshot.png", "");
+        assert_eq!(status, 200);
+        assert!(head.contains("content-type: image/png"));
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        let size = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+        assert_eq!(
+            (size(16), size(20)),
+            (4 * PNG_CELL_PX as u32, 3 * PNG_CELL_PX as u32)
+        );
+    }
+
+    #[test]
+    fn test_control() {
+        let addr = serve();
+        assert_eq!(control(addr, "command=step"), 200);
+        wait_for_step(addr, 1);
+        assert_eq!(control(addr, "command=step"), 200);
+        wait_for_step(addr, 2);
+        assert_eq!(control(addr, "command=set-noise&noise=0.5"), 200);
+        assert_eq!(control(addr, "command=restart&seed=3"), 200);
+        wait_for_step(addr, 0);
+        assert_eq!(control(addr, "command=resume"), 200);
+        let deadline = Instant::now() + REACT;
+        while step(addr) == 0 {
+            assert!(Instant::now() < deadline, "never resumed");
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(control(addr, "command=pause"), 200);
+        for bad in [
+            "",
+            "command=fly",
+            "command=set-noise",
+            "command=set-noise&noise=1.5",
+            "command=restart&seed=x",
+        ] {
+            assert_eq!(control(addr, bad), 400, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_failed_restart_keeps_the_run() {
+        let config = SimConfig {
+            rows: 3,
+            cols: 4,
+            ..SimConfig::default()
+        };
+        let mut env = config.build().unwrap();
+        let shared = Arc::new(Shared::new(env.step(), &ServeArgs::default()));
+        // A config that can't be rebuilt, which the pump must refuse rather than die on.
+        let broken = SimConfig {
+            noise: 2.0,
+            ..config
+        };
+        let (controls, control_rx) = mpsc::channel();
+        let pump_shared = Arc::clone(&shared);
+        let runner = spawn(env, DEFAULT_RATE, true);
+        thread::spawn(move || pump(broken, DEFAULT_RATE, true, runner, control_rx, pump_shared));
+        let send = |control| {
+            let (reply, answer) = mpsc::channel();
+            controls.send((control, reply)).unwrap();
+            answer.recv_timeout(REACT).unwrap()
+        };
+        let error = send(Control::Restart(Some(1))).unwrap_err();
+        assert!(error.contains("noise"), "{}", error);
+        assert_eq!(send(Control::Runner(Command::Step)), Ok(()));
+        let deadline = Instant::now() + REACT;
+        while shared.latest.lock().unwrap().step_index != 1 {
+            assert!(Instant::now() < deadline, "the old run stopped");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_cors_only_for_allowed_origins() {
+        let addr = serve();
+        let (status, head, _) = request(addr, "GET", "/state", "");
+        assert_eq!(status, 200);
+        assert!(!head.contains("access-control-allow-origin"));
+        let (status, head, _) = request_from(addr, Some(DASHBOARD), "GET", "/state", "");
+        assert_eq!(status, 200);
+        assert!(head.contains(&format!("access-control-allow-origin: {}\r\n", DASHBOARD)));
+        assert!(head.contains("vary: origin"));
+        let elsewhere = Some("http://elsewhere.test");
+        let (_, head, _) = request_from(addr, elsewhere, "GET", "/state", "");
+        assert!(!head.contains("access-control-allow-origin"));
+        // Pages from other origins can't drive the run; allowed ones and non-browsers can.
+        let post = |origin| request_from(addr, origin, "POST", "/control", "command=step").0;
+        assert_eq!(post(elsewhere), 403);
+        assert_eq!(step(addr), 0);
+        assert_eq!(post(Some(DASHBOARD)), 200);
+        wait_for_step(addr, 1);
+        assert_eq!(post(None), 200);
+        wait_for_step(addr, 2);
+    }
+
+    #[test]
+    fn test_routes() {
+        let addr = serve();
+        assert_eq!(request(addr, "GET", "/nowhere", "").0, 404);
+        assert_eq!(request(addr, "POST", "/state", "").0, 405);
+        assert_eq!(request(addr, "GET", "/control", "").0, 405);
+        assert_eq!(request(addr, "GET", "/state?pretty=1", "").0, 200);
+        // A plain request to the stream isn't an upgrade.
+        assert_eq!(request(addr, "GET", "/stream", "").0, 400);
+    }
+
+    #[test]
+    fn test_stream() {
+        let addr = serve();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET /stream HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            reader.read_line(&mut head).unwrap();
+        }
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        for expected in 1..=2 {
+            assert_eq!(control(addr, "command=step"), 200);
+            let mut header = [0; 2];
+            reader.read_exact(&mut header).unwrap();
+            assert_eq!(header[0], 0x81);
+            let len = match header[1] {
+                126 => {
+                    let mut len = [0; 2];
+                    reader.read_exact(&mut len).unwrap();
+                    usize::from(u16::from_be_bytes(len))
+                }
+                len => usize::from(len),
+            };
+            let mut payload = vec![0; len];
+            reader.read_exact(&mut payload).unwrap();
+            let json = String::from_utf8(payload).unwrap();
+            assert!(
+                json.starts_with(&format!(r#"{{"step":{},"#, expected)),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn test_metrics() {
+        let addr = serve();
+        let (status, head, body) = request(addr, "GET", "/metrics", "");
+        assert_eq!(status, 200);
+        assert!(head.contains("content-type: text/plain; version=0.0.4"));
+        let text = String::from_utf8(body).unwrap();
+        // The served run's first step, rebuilt from the same config.
+        let first = SimConfig {
+            rows: 3,
+            cols: 4,
+            seed: 7,
+            ..SimConfig::default()
+        }
+        .build()
+        .unwrap()
+        .step();
+        let counts: Vec<(String, usize)> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("coopsim_strategy_count{strategy=\""))
+            .map(|l| {
+                let (name, count) = l.split_once("\"} ").unwrap();
+                (name.to_string(), count.parse().unwrap())
+            })
+            .collect();
+        let expected: Vec<(String, usize)> = coop::Strategy::ALL
+            .iter()
+            .map(|s| {
+                let count = first.strategies.get(s).copied().unwrap_or(0);
+                (s.name().to_ascii_lowercase(), count)
+            })
+            .collect();
+        assert_eq!(counts, expected);
+        assert!(text.contains("\n# TYPE coopsim_steps_total counter\ncoopsim_steps_total 1\n"));
+        assert!(text.contains(&format!("\ncoopsim_cooperation_rate {}\n", first.coop_rate)));
+
+        assert_eq!(control(addr, "command=step"), 200);
+        wait_for_step(addr, 1);
+        let (_, _, body) = request(addr, "GET", "/metrics", "");
+        assert!(String::from_utf8(body)
+            .unwrap()
+            .contains("\ncoopsim_steps_total 2\n"));
+    }
+
+    #[test]
+    fn test_text_frame() {
+        assert_eq!(text_frame("hi"), b"\x81\x02hi");
+        let long = "x".repeat(300);
+        assert_eq!(&text_frame(&long)[..4], &[0x81, 126, 1, 44]);
+        assert_eq!(text_frame(&"x".repeat(70_000))[1], 127);
+    }
+
+    #[test]
+    fn test_sha1_and_base64() {
+        let hex = |d: [u8; 20]| d.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(hex(sha1(long)), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        for (data, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v")] {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_serve_args() {
+        let parse = |args: &[&str]| ServeArgs::parse(args.iter().map(|a| a.to_string()));
+        assert_eq!(parse(&[]), Ok(ServeArgs::default()));
+        assert_eq!(ServeArgs::default().bind, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let args = parse(&[
+            "--bind",
+            "0.0.0.0",
+            "--allow-origin=http://a",
+            "--allow-origin",
+            "b",
+        ]);
+        let args = args.unwrap();
+        assert_eq!(args.bind, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(args.allowed_origins, ["http://a", "b"]);
+        assert_eq!(parse(&["--bind=::1"]).unwrap().bind.to_string(), "::1");
+        let args = parse(&["--port", "9000", "--rate=30", "--paused"]).unwrap();
+        assert_eq!((args.port, args.rate, args.paused), (9000, 30, true));
+        assert_eq!(parse(&["--palette=mono"]).unwrap().palette, Palette::Mono);
+        for bad in [
+            &["--port", "99999"][..],
+            &["--rate", "0"],
+            &["--paused=yes"],
+            &["--port"],
+            &["--bind", "localhost"],
+            &["--allow-origin"],
+        ] {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use coop::Strategy;
+use ratatui::crossterm::event::KeyCode;
+
+use crate::config::SimConfig;
+
+/// Largest grid side accepted by the form.
+pub const MAX_SIDE: usize = 500;
+/// Most characters typed into a single field.
+const MAX_CHARS: usize = 20;
+
+/// One editable value of the setup form.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Field {
+    Rows,
+    Cols,
+    Noise,
+    Seed,
+    /// Initial share of a strategy, in percent.
+    Share(Strategy),
+}
+
+impl Field {
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::Rows => "Rows",
+            Field::Cols => "Cols",
+            Field::Noise => "Noise",
+            Field::Seed => "Seed",
+            Field::Share(s) => s.name(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FieldError {
+    Empty,
+    NotANumber,
+    OutOfRange,
+}
+
+impl FieldError {
+    /// Message shown next to an invalid field.
+    pub fn message(self, field: Field) -> String {
+        match self {
+            FieldError::Empty => "required".to_string(),
+            FieldError::NotANumber => "not a number".to_string(),
+            FieldError::OutOfRange => match field {
+                Field::Rows | Field::Cols => format!("must be 1-{}", MAX_SIDE),
+                Field::Noise => "must be 0-1".to_string(),
+                Field::Seed => "too large".to_string(),
+                Field::Share(_) => "must be 0-100".to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SetupEvent {
+    /// Still editing.
+    Pending,
+    /// The form was dismissed; the program should exit.
+    Cancelled,
+    /// The values are valid and the run should start.
+    Start(SimConfig),
+}
+
+/// State of the setup screen: one text buffer per field, the focused field, and whether a
+/// start was attempted (errors are only highlighted after that or once a field is edited).
+#[derive(Clone, Debug)]
+pub struct SetupForm {
+    fields: Vec<(Field, String)>,
+    focus: usize,
+    /// Fields edited since the form opened; their errors show as you type.
+    touched: Vec<bool>,
+    submitted: bool,
+}
+
+impl SetupForm {
+    /// A form prefilled from `config`.
+    pub fn new(config: &SimConfig) -> SetupForm {
+        let mut fields = vec![
+            (Field::Rows, config.rows.to_string()),
+            (Field::Cols, config.cols.to_string()),
+            (Field::Noise, config.noise.to_string()),
+            (Field::Seed, config.seed.to_string()),
+        ];
+        for s in Strategy::ALL {
+            let share = config
+                .mix
+                .iter()
+                .filter(|(m, _)| *m == s)
+                .map(|(_, p)| p)
+                .sum::<f32>();
+            fields.push((
+                Field::Share(s),
+                ((share * 100.0).round() as u32).to_string(),
+            ));
+        }
+        let touched = vec![false; fields.len()];
+        SetupForm {
+            fields,
+            focus: 0,
+            touched,
+            submitted: false,
+        }
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = (Field, &str)> {
+        self.fields.iter().map(|(f, text)| (*f, text.as_str()))
+    }
+
+    pub fn focus(&self) -> Field {
+        self.fields[self.focus].0
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> SetupEvent {
+        match code {
+            KeyCode::Tab | KeyCode::Down => self.focus = (self.focus + 1) % self.fields.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                self.focus = (self.focus + self.fields.len() - 1) % self.fields.len()
+            }
+            KeyCode::Char(c) if c.is_ascii_digit() || (c == '.' && self.accepts_point()) => {
+                let text = &mut self.fields[self.focus].1;
+                if text.len() < MAX_CHARS {
+                    text.push(c);
+                }
+                self.touched[self.focus] = true;
+            }
+            KeyCode::Backspace => {
+                self.fields[self.focus].1.pop();
+                self.touched[self.focus] = true;
+            }
+            KeyCode::Esc => return SetupEvent::Cancelled,
+            KeyCode::Enter => {
+                self.submitted = true;
+                match self.config() {
+                    Some(config) => return SetupEvent::Start(config),
+                    None => {
+                        if let Some(i) = (0..self.fields.len()).find(|i| self.check(*i).is_err()) {
+                            self.focus = i;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        SetupEvent::Pending
+    }
+
+    /// Only the noise field takes a decimal point, and only one.
+    fn accepts_point(&self) -> bool {
+        let (field, text) = &self.fields[self.focus];
+        *field == Field::Noise && !text.contains('.')
+    }
+
+    /// The error to display next to `field`, if it's invalid and has been edited or a start
+    /// was attempted.
+    pub fn error(&self, field: Field) -> Option<FieldError> {
+        let i = self.fields.iter().position(|(f, _)| *f == field)?;
+        if !(self.submitted || self.touched[i]) {
+            return None;
+        }
+        self.check(i).err()
+    }
+
+    /// Sum of the strategy shares, counting invalid ones as zero.
+    pub fn total(&self) -> u32 {
+        (0..self.fields.len())
+            .filter_map(|i| match self.fields[i].0 {
+                Field::Share(_) => self.check(i).ok().map(|v| v as u32),
+                _ => None,
+            })
+            .sum()
+    }
+
+    /// Whether the shares fail to add up to 100%.
+    pub fn total_error(&self) -> bool {
+        self.total() != 100
+    }
+
+    fn check(&self, i: usize) -> Result<f64, FieldError> {
+        let (field, text) = &self.fields[i];
+        if text.is_empty() {
+            return Err(FieldError::Empty);
+        }
+        let in_range = |ok: bool, value: f64| {
+            if ok {
+                Ok(value)
+            } else {
+                Err(FieldError::OutOfRange)
+            }
+        };
+        match field {
+            Field::Rows | Field::Cols => match text.parse::<usize>() {
+                Ok(n) => in_range((1..=MAX_SIDE).contains(&n), n as f64),
+                Err(_) => Err(FieldError::OutOfRange),
+            },
+            Field::Noise => match text.parse::<f32>() {
+                Ok(n) => in_range((0.0..=1.0).contains(&n), n as f64),
+                Err(_) => Err(FieldError::NotANumber),
+            },
+            Field::Seed => text
+                .parse::<u64>()
+                .map(|_| 0.0)
+                .map_err(|_| FieldError::OutOfRange),
+            Field::Share(_) => match text.parse::<u32>() {
+                Ok(n) => in_range(n <= 100, n as f64),
+                Err(_) => Err(FieldError::OutOfRange),
+            },
+        }
+    }
+
+    /// The configuration described by the form, if every field is valid and the shares sum
+    /// to 100%.
+    pub fn config(&self) -> Option<SimConfig> {
+        if (0..self.fields.len()).any(|i| self.check(i).is_err()) || self.total_error() {
+            return None;
+        }
+        let mut config = SimConfig {
+            mix: vec![],
+            ..SimConfig::default()
+        };
+        for (i, (field, text)) in self.fields.iter().enumerate() {
+            match field {
+                Field::Rows => config.rows = text.parse().ok()?,
+                Field::Cols => config.cols = text.parse().ok()?,
+                Field::Noise => config.noise = text.parse().ok()?,
+                Field::Seed => config.seed = text.parse().ok()?,
+                Field::Share(s) => config.mix.push((*s, self.check(i).ok()? as f32 / 100.0)),
+            }
+        }
+        Some(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SimConfig {
+        SimConfig {
+            seed: 7,
+            ..SimConfig::default()
+        }
+    }
+
+    fn type_text(form: &mut SetupForm, text: &str) {
+        for c in text.chars() {
+            form.handle_key(KeyCode::Char(c));
+        }
+    }
+
+    fn clear(form: &mut SetupForm) {
+        for _ in 0..MAX_CHARS {
+            form.handle_key(KeyCode::Backspace);
+        }
+    }
+
+    /// Moves the focus to `field`.
+    fn focus(form: &mut SetupForm, field: Field) {
+        while form.focus() != field {
+            form.handle_key(KeyCode::Tab);
+        }
+    }
+
+    #[test]
+    fn test_prefilled_from_config() {
+        let form = SetupForm::new(&config());
+        let fields: Vec<_> = form.fields().collect();
+        assert_eq!(fields[0], (Field::Rows, "50"));
+        assert_eq!(fields[3], (Field::Seed, "7"));
+        assert_eq!(fields.len(), 4 + Strategy::ALL.len());
+        assert_eq!(form.total(), 100);
+        let built = form.config().unwrap();
+        assert_eq!(
+            (built.rows, built.cols, built.noise, built.seed),
+            (50, 50, 0.1, 7)
+        );
+        for (s, p) in config().mix {
+            assert!(built.mix.contains(&(s, p)), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_navigation_wraps() {
+        let mut form = SetupForm::new(&config());
+        assert_eq!(form.focus(), Field::Rows);
+        form.handle_key(KeyCode::Up);
+        assert_eq!(
+            form.focus(),
+            Field::Share(Strategy::ALL[Strategy::ALL.len() - 1])
+        );
+        form.handle_key(KeyCode::Down);
+        form.handle_key(KeyCode::Tab);
+        assert_eq!(form.focus(), Field::Cols);
+        form.handle_key(KeyCode::BackTab);
+        assert_eq!(form.focus(), Field::Rows);
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut form = SetupForm::new(&config());
+        form.handle_key(KeyCode::Backspace);
+        type_text(&mut form, "2x");
+        assert_eq!(form.fields().next(), Some((Field::Rows, "52")));
+        // A decimal point is only accepted once, and only for noise.
+        type_text(&mut form, ".");
+        assert_eq!(form.fields().next(), Some((Field::Rows, "52")));
+        focus(&mut form, Field::Noise);
+        clear(&mut form);
+        type_text(&mut form, "0.2.5");
+        assert_eq!(form.fields().nth(2), Some((Field::Noise, "0.25")));
+        let config = form.config().unwrap();
+        assert_eq!((config.rows, config.noise), (52, 0.25));
+    }
+
+    #[test]
+    fn test_field_errors() {
+        let mut form = SetupForm::new(&config());
+        clear(&mut form);
+        assert_eq!(form.error(Field::Rows), Some(FieldError::Empty));
+        type_text(&mut form, "0");
+        assert_eq!(form.error(Field::Rows), Some(FieldError::OutOfRange));
+        clear(&mut form);
+        type_text(&mut form, "501");
+        assert_eq!(form.error(Field::Rows), Some(FieldError::OutOfRange));
+        form.handle_key(KeyCode::Backspace);
+        assert_eq!(form.error(Field::Rows), None);
+
+        focus(&mut form, Field::Noise);
+        clear(&mut form);
+        type_text(&mut form, ".");
+        assert_eq!(form.error(Field::Noise), Some(FieldError::NotANumber));
+        type_text(&mut form, "5");
+        assert_eq!(form.error(Field::Noise), None);
+        clear(&mut form);
+        type_text(&mut form, "2");
+        assert_eq!(form.error(Field::Noise), Some(FieldError::OutOfRange));
+
+        focus(&mut form, Field::Seed);
+        type_text(&mut form, &"9".repeat(MAX_CHARS));
+        assert_eq!(form.error(Field::Seed), Some(FieldError::OutOfRange));
+    }
+
+    #[test]
+    fn test_shares_must_sum_to_100() {
+        let mut form = SetupForm::new(&config());
+        let first = Field::Share(Strategy::ALL[0]);
+        focus(&mut form, first);
+        type_text(&mut form, "0");
+        assert_eq!(form.error(first), Some(FieldError::OutOfRange));
+        form.handle_key(KeyCode::Backspace);
+        form.handle_key(KeyCode::Backspace);
+        assert!(form.total_error());
+        assert_eq!(form.handle_key(KeyCode::Enter), SetupEvent::Pending);
+        type_text(&mut form, "0");
+        assert_eq!(form.total(), 100);
+        assert!(!form.total_error());
+        assert!(matches!(
+            form.handle_key(KeyCode::Enter),
+            SetupEvent::Start(_)
+        ));
+    }
+
+    #[test]
+    fn test_enter_focuses_first_error() {
+        let mut form = SetupForm::new(&config());
+        focus(&mut form, Field::Cols);
+        clear(&mut form);
+        focus(&mut form, Field::Seed);
+        assert_eq!(form.handle_key(KeyCode::Enter), SetupEvent::Pending);
+        assert_eq!(form.focus(), Field::Cols);
+        // Untouched fields only report errors once a start was attempted.
+        let mut form = SetupForm::new(&config());
+        form.fields[3].1.clear();
+        assert_eq!(form.error(Field::Seed), None);
+        form.handle_key(KeyCode::Enter);
+        assert_eq!(form.error(Field::Seed), Some(FieldError::Empty));
+    }
+
+    #[test]
+    fn test_start_builds_environment() {
+        let mut form = SetupForm::new(&config());
+        clear(&mut form);
+        type_text(&mut form, "12");
+        focus(&mut form, Field::Cols);
+        clear(&mut form);
+        type_text(&mut form, "8");
+        for (s, share) in Strategy::ALL.into_iter().zip(["0", "0", "100", "0"]) {
+            focus(&mut form, Field::Share(s));
+            clear(&mut form);
+            type_text(&mut form, share);
+        }
+        let SetupEvent::Start(config) = form.handle_key(KeyCode::Enter) else {
+            panic!("form should be valid");
+        };
+        assert_eq!(config.seed, 7);
+        let mut env = config.build().unwrap();
+        assert_eq!(env.dimensions(), (12, 8));
+        assert_eq!(env.seed(), 7);
+        let metric = env.step();
+        assert!(metric.snapshot.cells().iter().all(|s| *s == Strategy::Coop));
+    }
+
+    #[test]
+    fn test_escape_cancels() {
+        let mut form = SetupForm::new(&config());
+        assert_eq!(form.handle_key(KeyCode::Esc), SetupEvent::Cancelled);
+    }
+}
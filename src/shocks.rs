@@ -0,0 +1,184 @@
+//! Shocks: external disruptions that scramble a region of the grid.
+//!
+//! When a shock fires, a rectangle at a random position has its agents' strategies replaced
+//! and their histories wiped, as if a storm had swept through. Shocks fire after a step's games,
+//! imitation and group competition, so the step's metric already shows the damage.
+
+use rand::Rng;
+
+use crate::agent::{Coord, Strategy};
+
+/// When a shock fires.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Schedule {
+    /// Once, during the step with this zero-based index.
+    At(usize),
+    /// During every `n`-th step: steps `n - 1`, `2n - 1`, and so on.
+    Every(usize),
+    /// A Poisson-distributed number of times per step with this mean, so it can fire several
+    /// times in one step.
+    Poisson(f64),
+}
+
+/// What a shock does to the agents it hits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShockEffect {
+    /// Every agent gets a strategy drawn uniformly from all strategies.
+    #[default]
+    Randomize,
+    /// Every agent switches to this strategy.
+    Set(Strategy),
+}
+
+/// A disruption that hits a `rows` x `cols` region whenever its schedule says so. Regions
+/// larger than the grid are cut down to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shock {
+    pub schedule: Schedule,
+    pub rows: usize,
+    pub cols: usize,
+    pub effect: ShockEffect,
+}
+
+impl Shock {
+    pub fn new(schedule: Schedule, (rows, cols): (usize, usize), effect: ShockEffect) -> Shock {
+        Shock {
+            schedule,
+            rows,
+            cols,
+            effect,
+        }
+    }
+
+    /// Whether the region is non-empty and the schedule can be followed: a positive period or
+    /// a finite, non-negative rate.
+    pub fn is_valid(&self) -> bool {
+        self.rows > 0
+            && self.cols > 0
+            && match self.schedule {
+                Schedule::At(_) => true,
+                Schedule::Every(period) => period > 0,
+                Schedule::Poisson(rate) => rate.is_finite() && rate >= 0.0,
+            }
+    }
+
+    /// How many times the shock fires during the step with index `step`.
+    pub(crate) fn firings<R: Rng + ?Sized>(&self, step: usize, rng: &mut R) -> usize {
+        match self.schedule {
+            Schedule::At(at) => usize::from(at == step),
+            Schedule::Every(period) => usize::from((step + 1).is_multiple_of(period)),
+            Schedule::Poisson(rate) => poisson(rate, rng),
+        }
+    }
+
+    /// Fires the shock on a `dimensions` grid at a random position.
+    pub(crate) fn fire<R: Rng + ?Sized>(
+        &self,
+        index: usize,
+        (rows, cols): (usize, usize),
+        rng: &mut R,
+    ) -> FiredShock {
+        let (height, width) = (self.rows.min(rows), self.cols.min(cols));
+        FiredShock {
+            shock: index,
+            origin: (
+                rng.gen_range(0..=rows - height),
+                rng.gen_range(0..=cols - width),
+            ),
+            rows: height,
+            cols: width,
+        }
+    }
+}
+
+/// A shock that fired, and where.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FiredShock {
+    /// Index of the shock in the environment's list.
+    pub shock: usize,
+    /// Top-left cell of the region it hit.
+    pub origin: Coord,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl FiredShock {
+    /// Whether `coord` lies in the region.
+    pub fn contains(&self, (row, col): Coord) -> bool {
+        (self.origin.0..self.origin.0 + self.rows).contains(&row)
+            && (self.origin.1..self.origin.1 + self.cols).contains(&col)
+    }
+
+    /// Every cell of the region, row-major.
+    pub fn cells(&self) -> impl Iterator<Item = Coord> + '_ {
+        let (top, left) = self.origin;
+        (top..top + self.rows).flat_map(move |r| (left..left + self.cols).map(move |c| (r, c)))
+    }
+}
+
+/// A draw from the Poisson distribution with mean `rate`, by counting uniform draws until
+/// their product falls below `e^-rate`; fine for the small rates shocks come at.
+fn poisson<R: Rng + ?Sized>(rate: f64, rng: &mut R) -> usize {
+    let limit = (-rate).exp();
+    let mut product: f64 = rng.gen();
+    let mut count = 0;
+    while product > limit {
+        product *= rng.gen::<f64>();
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_schedules() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let at = Shock::new(Schedule::At(3), (2, 2), ShockEffect::Randomize);
+        let fired: Vec<usize> = (0..6).map(|s| at.firings(s, &mut rng)).collect();
+        assert_eq!(fired, vec![0, 0, 0, 1, 0, 0]);
+        let every = Shock::new(Schedule::Every(3), (2, 2), ShockEffect::Randomize);
+        let fired: Vec<usize> = (0..6).map(|s| every.firings(s, &mut rng)).collect();
+        assert_eq!(fired, vec![0, 0, 1, 0, 0, 1]);
+        let never = Shock::new(Schedule::Poisson(0.0), (2, 2), ShockEffect::Randomize);
+        assert!((0..100).all(|s| never.firings(s, &mut rng) == 0));
+    }
+
+    #[test]
+    fn test_poisson_mean() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let draws = 20_000;
+        let total: usize = (0..draws).map(|_| poisson(1.5, &mut rng)).sum();
+        assert!((total as f64 / draws as f64 - 1.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_regions_stay_on_the_grid() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let shock = Shock::new(Schedule::At(0), (3, 8), ShockEffect::Set(Strategy::Deflect));
+        for _ in 0..100 {
+            let fired = shock.fire(4, (10, 5), &mut rng);
+            assert_eq!((fired.shock, fired.rows, fired.cols), (4, 3, 5));
+            assert_eq!(fired.origin.1, 0);
+            assert!(fired.origin.0 <= 7);
+            assert_eq!(fired.cells().count(), 15);
+            assert!(fired.cells().all(|c| fired.contains(c)));
+            assert!(!fired.contains((fired.origin.0 + 3, 0)));
+        }
+    }
+
+    #[test]
+    fn test_validity() {
+        let effect = ShockEffect::Randomize;
+        assert!(Shock::new(Schedule::At(0), (1, 1), effect).is_valid());
+        assert!(!Shock::new(Schedule::At(0), (0, 1), effect).is_valid());
+        assert!(!Shock::new(Schedule::Every(0), (1, 1), effect).is_valid());
+        assert!(!Shock::new(Schedule::Poisson(-1.0), (1, 1), effect).is_valid());
+        assert!(!Shock::new(Schedule::Poisson(f64::NAN), (1, 1), effect).is_valid());
+    }
+}
@@ -0,0 +1,317 @@
+use std::ops::Index;
+use std::sync::Arc;
+
+use crate::agent::{Class, Strategy};
+use crate::analyze::aggregate_blocks;
+use crate::grid::Grid;
+
+/// Longest side, in cells, of the thumbnails metrics carry; see [`Snapshot::thumbnail`].
+pub const THUMBNAIL_SIZE: usize = 32;
+/// The byte [`Snapshot::to_bytes`] writes for a vacant cell; no strategy number uses it.
+pub const VACANT_CELL: u8 = u8::MAX;
+
+/// The strategy of every cell after a step, a [`Grid`] shared between clones, so copying a
+/// snapshot is a reference count bump.
+///
+/// Cells can be vacant once agents die, see [`EnergyConfig`](crate::EnergyConfig). Indexing,
+/// `get` and `cells` still give such a cell the strategy of its last agent; `occupant` and
+/// `is_vacant` tell them apart.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    grid: Grid<Strategy>,
+    /// Which cells are vacant, row-major; `None` when all are occupied.
+    vacant: Option<Arc<[bool]>>,
+    /// Which cells hold visitors, row-major; `None` when all hold hosts.
+    visitors: Option<Arc<[bool]>>,
+}
+
+impl Snapshot {
+    /// A snapshot of `grid` with no vacant cells and only hosts.
+    pub(crate) fn new(grid: Grid<Strategy>) -> Snapshot {
+        Snapshot {
+            grid,
+            vacant: None,
+            visitors: None,
+        }
+    }
+
+    /// This snapshot with the cells marked in `vacant`, row-major, left empty.
+    pub(crate) fn with_vacancies(mut self, vacant: &[bool]) -> Snapshot {
+        debug_assert_eq!(vacant.len(), self.grid.cells().len());
+        self.vacant = vacant.contains(&true).then(|| vacant.into());
+        self
+    }
+
+    /// This snapshot with every cell's class, row-major.
+    pub(crate) fn with_classes(mut self, classes: impl IntoIterator<Item = Class>) -> Snapshot {
+        let visitors: Vec<bool> = classes.into_iter().map(|c| c == Class::Visitor).collect();
+        debug_assert_eq!(visitors.len(), self.grid.cells().len());
+        self.visitors = visitors.contains(&true).then(|| visitors.into());
+        self
+    }
+
+    /// Every cell's strategy, vacant cells included.
+    pub fn grid(&self) -> &Grid<Strategy> {
+        &self.grid
+    }
+
+    pub fn rows(&self) -> usize {
+        self.grid.rows()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.grid.cols()
+    }
+
+    /// `(rows, cols)`.
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.grid.dimensions()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grid.is_empty()
+    }
+
+    /// The strategy at `(row, col)`, or `None` outside the grid.
+    pub fn get(&self, row: usize, col: usize) -> Option<Strategy> {
+        self.grid.get(row, col).copied()
+    }
+
+    /// The strategies of `row`, or `None` outside the grid.
+    pub fn row(&self, row: usize) -> Option<&[Strategy]> {
+        self.grid.row(row)
+    }
+
+    /// Every row, top to bottom.
+    pub fn iter_rows(&self) -> std::slice::Chunks<'_, Strategy> {
+        self.grid.iter_rows()
+    }
+
+    /// Every cell's strategy with its `(row, col)`, in row-major order.
+    pub fn iter_with_coords(&self) -> impl Iterator<Item = ((usize, usize), Strategy)> + '_ {
+        self.grid.iter_with_coords().map(|(c, &s)| (c, s))
+    }
+
+    /// Every cell in row-major order.
+    pub fn cells(&self) -> &[Strategy] {
+        self.grid.cells()
+    }
+
+    /// Whether `(row, col)` is a cell without an agent; false outside the grid.
+    pub fn is_vacant(&self, row: usize, col: usize) -> bool {
+        let (rows, cols) = self.dimensions();
+        row < rows && col < cols && self.vacant.as_ref().is_some_and(|v| v[row * cols + col])
+    }
+
+    /// The strategy of the agent at `(row, col)`, or `None` if the cell is vacant or outside
+    /// the grid.
+    pub fn occupant(&self, row: usize, col: usize) -> Option<Strategy> {
+        self.get(row, col).filter(|_| !self.is_vacant(row, col))
+    }
+
+    /// The class of the agent at `(row, col)`, or of its last agent if it's vacant; `None`
+    /// outside the grid.
+    pub fn class(&self, row: usize, col: usize) -> Option<Class> {
+        let (rows, cols) = self.dimensions();
+        let visitor = |v: &Arc<[bool]>| v[row * cols + col];
+        (row < rows && col < cols).then(|| {
+            if self.visitors.as_ref().is_some_and(visitor) {
+                Class::Visitor
+            } else {
+                Class::Host
+            }
+        })
+    }
+
+    /// Every cell's strategy number (see [`Strategy::index`]) in row-major order, with
+    /// [`VACANT_CELL`] for vacant cells.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.iter_with_coords()
+            .map(|((row, col), s)| {
+                if self.is_vacant(row, col) {
+                    VACANT_CELL
+                } else {
+                    s.index()
+                }
+            })
+            .collect()
+    }
+
+    /// Number of vacant cells.
+    pub fn vacancies(&self) -> usize {
+        self.vacant
+            .as_ref()
+            .map_or(0, |v| v.iter().filter(|&&v| v).count())
+    }
+
+    /// This snapshot shrunk until neither side exceeds `max_side` cells: every square block
+    /// of cells becomes one cell of its majority strategy, as in [`aggregate_blocks`], and
+    /// blocks with a tie or no agents become vacant. A snapshot that already fits is returned
+    /// as it is.
+    pub fn thumbnail(&self, max_side: usize) -> Snapshot {
+        let block = self.rows().max(self.cols()).div_ceil(max_side.max(1));
+        if block <= 1 {
+            return self.clone();
+        }
+        let blocks = aggregate_blocks(self, block);
+        let (rows, cols) = (blocks.len(), blocks.first().map_or(0, Vec::len));
+        let vacant: Vec<bool> = blocks.iter().flatten().map(Option::is_none).collect();
+        // Vacant cells still need a strategy; nothing shows it.
+        let cells: Vec<Strategy> = blocks
+            .into_iter()
+            .flatten()
+            .map(|s| s.unwrap_or(Strategy::Deflect))
+            .collect();
+        Snapshot::new(Grid::new(rows, cols, cells)).with_vacancies(&vacant)
+    }
+}
+
+/// `snapshot[(row, col)]`; panics outside the grid, like slice indexing.
+impl Index<(usize, usize)> for Snapshot {
+    type Output = Strategy;
+
+    fn index(&self, index: (usize, usize)) -> &Strategy {
+        &self.grid[index]
+    }
+}
+
+/// Panics unless every row has the same length.
+impl From<Vec<Vec<Strategy>>> for Snapshot {
+    fn from(rows: Vec<Vec<Strategy>>) -> Snapshot {
+        Snapshot {
+            grid: Grid::from(rows),
+            vacant: None,
+            visitors: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Strategy::{Coop as C, Deflect as D, Random as R, TicToc as T};
+
+    #[test]
+    fn test_indexing() {
+        let snapshot = Snapshot::from(vec![vec![C, D, T], vec![R, C, D]]);
+        assert_eq!(snapshot.dimensions(), (2, 3));
+        assert_eq!(snapshot[(0, 1)], D);
+        assert_eq!(snapshot[(1, 0)], R);
+        assert_eq!(snapshot.get(1, 2), Some(D));
+        assert_eq!(snapshot.get(2, 0), None);
+        assert_eq!(snapshot.get(0, 3), None);
+        assert_eq!(snapshot.row(1), Some(&[R, C, D][..]));
+        assert_eq!(snapshot.row(2), None);
+        assert_eq!(snapshot.cells(), &[C, D, T, R, C, D]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside a 2x3 grid")]
+    fn test_index_out_of_bounds() {
+        let snapshot = Snapshot::from(vec![vec![C, D, T], vec![R, C, D]]);
+        let _ = snapshot[(0, 3)];
+    }
+
+    #[test]
+    fn test_shape() {
+        let snapshot = Snapshot::from(vec![vec![C], vec![D], vec![T]]);
+        assert_eq!((snapshot.rows(), snapshot.cols()), (3, 1));
+        let rows: Vec<&[Strategy]> = snapshot.iter_rows().collect();
+        assert_eq!(rows, vec![&[C][..], &[D], &[T]]);
+        for empty in [
+            Snapshot::default(),
+            Snapshot::from(vec![]),
+            Snapshot::from(vec![vec![]]),
+        ] {
+            assert!(empty.is_empty());
+            assert_eq!(empty.dimensions(), (0, 0));
+            assert_eq!(empty.iter_rows().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_vacancies() {
+        let full = Snapshot::from(vec![vec![C, D], vec![T, R]]);
+        assert_eq!(full.vacancies(), 0);
+        assert_eq!(full.clone().with_vacancies(&[false; 4]), full);
+        let snapshot = full.with_vacancies(&[false, true, false, false]);
+        assert!(snapshot.is_vacant(0, 1));
+        assert!(!snapshot.is_vacant(0, 0));
+        assert!(!snapshot.is_vacant(2, 0));
+        assert_eq!(snapshot.occupant(0, 1), None);
+        assert_eq!(snapshot.occupant(1, 0), Some(T));
+        // The last agent's strategy stays readable.
+        assert_eq!(snapshot[(0, 1)], D);
+        assert_eq!(snapshot.vacancies(), 1);
+        assert_eq!(
+            snapshot.to_bytes(),
+            [C.index(), VACANT_CELL, T.index(), R.index()]
+        );
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        // 4x4 blocks of a 128x80 grid: the left half Coop, the right half Deflect, but for a
+        // block split evenly between the two and a block with a TicToc minority.
+        let snapshot = Snapshot::from(
+            (0..128)
+                .map(|r| {
+                    (0..80)
+                        .map(|c| match (r, c) {
+                            (0..4, 0..4) if c < 2 => D,
+                            (4..8, 0..4) if c == 0 => T,
+                            _ if c < 40 => C,
+                            _ => D,
+                        })
+                        .collect()
+                })
+                .collect::<Vec<Vec<Strategy>>>(),
+        );
+        let thumbnail = snapshot.thumbnail(THUMBNAIL_SIZE);
+        assert_eq!(thumbnail.dimensions(), (32, 20));
+        assert_eq!(thumbnail.occupant(0, 0), None);
+        assert_eq!(thumbnail.occupant(1, 0), Some(C));
+        assert_eq!(thumbnail.occupant(31, 9), Some(C));
+        assert_eq!(thumbnail.occupant(31, 10), Some(D));
+        assert_eq!(thumbnail.vacancies(), 1);
+        // Uneven sides: the longer one sets the block size, and edge blocks are smaller.
+        let tall = Snapshot::from(vec![vec![C; 5]; 100]).thumbnail(THUMBNAIL_SIZE);
+        assert_eq!(tall.dimensions(), (25, 2));
+        assert!(tall.cells().iter().all(|s| *s == C));
+    }
+
+    #[test]
+    fn test_small_thumbnail() {
+        let snapshot = Snapshot::from(vec![vec![C, D], vec![T, R]])
+            .with_vacancies(&[false, true, false, false]);
+        assert_eq!(snapshot.thumbnail(THUMBNAIL_SIZE), snapshot);
+        assert_eq!(snapshot.thumbnail(2), snapshot);
+        assert_eq!(snapshot.thumbnail(0).dimensions(), (1, 1));
+        assert!(Snapshot::default().thumbnail(THUMBNAIL_SIZE).is_empty());
+    }
+
+    #[test]
+    fn test_classes() {
+        let hosts = Snapshot::from(vec![vec![C, D], vec![T, R]]);
+        assert_eq!(hosts.clone().with_classes([Class::Host; 4]), hosts);
+        assert_eq!(hosts.class(1, 1), Some(Class::Host));
+        let snapshot = hosts.with_classes([0, 1, 1, 0].map(|i| Class::ALL[i]));
+        assert_eq!(snapshot.class(0, 1), Some(Class::Visitor));
+        assert_eq!(snapshot.class(1, 1), Some(Class::Host));
+        assert_eq!(snapshot.class(2, 0), None);
+    }
+
+    #[test]
+    fn test_clones_share_cells() {
+        let snapshot = Snapshot::from(vec![vec![C, D]]);
+        let copy = snapshot.clone();
+        assert!(std::ptr::eq(snapshot.cells(), copy.cells()));
+        assert_eq!(copy, snapshot);
+    }
+
+    #[test]
+    #[should_panic(expected = "same length")]
+    fn test_ragged_rows() {
+        let _ = Snapshot::from(vec![vec![C, D], vec![T]]);
+    }
+}
@@ -0,0 +1,407 @@
+use coop::Metric;
+use ratatui::{style::Stylize, text::Span};
+
+use crate::palette::{format_percentage, Palette};
+use crate::ui::RunMode;
+
+const SEPARATOR: &str = " │ ";
+
+/// Everything besides the metric that the status bar shows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunState {
+    /// The step being shown.
+    pub step: usize,
+    pub noise: f32,
+    pub seed: u64,
+    /// Target steps per second, `None` when uncapped.
+    pub target_rate: Option<u32>,
+    /// Measured steps per second.
+    pub actual_rate: f64,
+    pub run_mode: RunMode,
+    /// Whether `step` is a browsed history step rather than the latest one.
+    pub detached: bool,
+    /// Whether stepping is held because history is being browsed.
+    pub browsing_paused: bool,
+    /// Frames per second of a running history playback; `Some(None)` when uncapped.
+    pub playback: Option<Option<u32>>,
+    /// Cells that changed since the previous step, when change highlighting is on.
+    pub changed: Option<usize>,
+    pub palette: Palette,
+    /// Columns available to the status bar.
+    pub width: u16,
+}
+
+impl RunState {
+    /// Mode indicators in display order, e.g. `["DETACH @ 120", "PAUSED"]`.
+    pub fn modes(&self) -> Vec<String> {
+        let mut modes = Vec::new();
+        if self.detached {
+            modes.push(format!("DETACH @ {}", self.step));
+        }
+        match self.playback {
+            Some(Some(rate)) => modes.push(format!("PLAYBACK {}/s", rate)),
+            Some(None) => modes.push("PLAYBACK max".to_string()),
+            None => {}
+        }
+        match self.run_mode {
+            RunMode::Running if self.browsing_paused && self.playback.is_none() => {
+                modes.push("PAUSED (k: keep running)".to_string())
+            }
+            RunMode::Running => {}
+            RunMode::Paused => modes.push("PAUSED".to_string()),
+            RunMode::SingleStep => modes.push("SINGLE-STEP (n: next)".to_string()),
+        }
+        modes
+    }
+}
+
+/// How much of the status fits; each level drops or shortens something from the previous.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+enum Detail {
+    Full,
+    NoExtras,
+    NoAverages,
+    NoSpeed,
+    Shares,
+    NoNoise,
+    Minimal,
+}
+
+const DETAILS: [Detail; 7] = [
+    Detail::Full,
+    Detail::NoExtras,
+    Detail::NoAverages,
+    Detail::NoSpeed,
+    Detail::Shares,
+    Detail::NoNoise,
+    Detail::Minimal,
+];
+
+/// The status bar: step, mode indicators, a column per strategy with its count, share and
+/// average score, then noise, speed and seed. Parts are shortened or dropped, least useful
+/// first, until it fits `run.width`; whatever still doesn't fit is cut off.
+pub fn format_status(metric: &Metric, run: &RunState) -> Vec<Span<'static>> {
+    let width = run.width as usize;
+    let mut spans = Vec::new();
+    for detail in DETAILS {
+        spans = status_spans(metric, run, detail);
+        if spans_width(&spans) <= width {
+            return spans;
+        }
+    }
+    truncate(spans, width)
+}
+
+fn status_spans(metric: &Metric, run: &RunState, detail: Detail) -> Vec<Span<'static>> {
+    let mut spans = vec![Span::from(format!("Step {}", run.step))];
+    for mode in run.modes() {
+        spans.push(Span::from(" "));
+        spans.push(mode.bold().reversed());
+    }
+    if metric.frozen {
+        spans.push(Span::from(" "));
+        spans.push("FROZEN".bold().reversed());
+    }
+    if detail < Detail::Minimal {
+        let total: usize = metric.strategies.values().sum();
+        let digits = total.to_string().len();
+        for (strategy, color, glyph, name) in run.palette.entries() {
+            let count = metric.strategies.get(&strategy).copied().unwrap_or(0);
+            let share = format_percentage(count, total);
+            spans.push(Span::from(SEPARATOR));
+            let text = match detail {
+                Detail::Full | Detail::NoExtras => {
+                    let avg = match metric.avg_score.get(&strategy) {
+                        Some(avg) => format!("{:>7.1}", avg),
+                        None => format!("{:>7}", "-"),
+                    };
+                    format!(
+                        "{} {:<11} {:>digits$} {:>6} avg{}",
+                        glyph, name, count, share, avg
+                    )
+                }
+                Detail::NoAverages | Detail::NoSpeed => {
+                    format!("{} {:<11} {:>digits$} {:>6}", glyph, name, count, share)
+                }
+                _ => format!("{} {:>6}", glyph, share),
+            };
+            spans.push(text.fg(color));
+        }
+    }
+    if detail < Detail::NoNoise {
+        spans.push(Span::from(format!("{}Noise {:.2}", SEPARATOR, run.noise)));
+    }
+    if detail < Detail::NoSpeed {
+        let target = match run.target_rate {
+            Some(rate) => format!("{}/s", rate),
+            None => "max".to_string(),
+        };
+        spans.push(Span::from(format!(
+            "{}Speed {} ({:.1}/s)",
+            SEPARATOR, target, run.actual_rate
+        )));
+    }
+    if detail == Detail::Full {
+        spans.push(Span::from(format!("{}Seed {}", SEPARATOR, run.seed)));
+        if let Some(changed) = run.changed {
+            spans.push(Span::from(format!("{}Changed {}", SEPARATOR, changed)));
+        }
+    }
+    spans
+}
+
+fn spans_width(spans: &[Span]) -> usize {
+    spans.iter().map(|s| s.width()).sum()
+}
+
+/// Cuts `spans` down to at most `width` columns.
+fn truncate(spans: Vec<Span<'static>>, width: usize) -> Vec<Span<'static>> {
+    let mut left = width;
+    let mut out = Vec::new();
+    for span in spans {
+        if span.width() <= left {
+            left -= span.width();
+            out.push(span);
+            continue;
+        }
+        let mut text = String::new();
+        for c in span.content.chars() {
+            let w = Span::from(c.to_string()).width();
+            if w > left {
+                break;
+            }
+            left -= w;
+            text.push(c);
+        }
+        out.push(Span::styled(text, span.style));
+        break;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use coop::Grid;
+    use coop::Strategy::{self, Choosy as Ch, Coop as C, Deflect as D, Random as R, TicToc as T};
+
+    use super::*;
+
+    fn metric() -> Metric {
+        Metric {
+            strategies: BTreeMap::from([(C, 30), (D, 60), (T, 10)]),
+            max_score: BTreeMap::from([(C, 40.0), (D, 90.0), (T, 20.0)]),
+            avg_score: BTreeMap::from([(C, 12.25), (D, 45.5), (T, 3.0)]),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 0,
+            coop_actions: 0,
+            coop_rate: 0.0,
+            snapshot: Default::default(),
+            founder_lineages: 0,
+            population: 0,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: Grid::default(),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        }
+    }
+
+    fn run() -> RunState {
+        RunState {
+            step: 1234,
+            noise: 0.1,
+            seed: 42,
+            target_rate: Some(100),
+            actual_rate: 98.25,
+            run_mode: RunMode::Running,
+            detached: false,
+            browsing_paused: false,
+            playback: None,
+            changed: None,
+            palette: Palette::Classic,
+            width: 500,
+        }
+    }
+
+    fn text(spans: &[Span]) -> String {
+        spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn test_full_status() {
+        let status = text(&format_status(&metric(), &run()));
+        assert!(status.starts_with("Step 1234 │ "), "{}", status);
+        assert!(
+            status.contains("Deflect      60  60.0% avg   45.5"),
+            "{}",
+            status
+        );
+        assert!(
+            status.contains("TicToc       10  10.0% avg    3.0"),
+            "{}",
+            status
+        );
+        assert!(
+            status.contains("Coop         30  30.0% avg   12.2"),
+            "{}",
+            status
+        );
+        assert!(
+            status.contains("Random        0   0.0% avg      -"),
+            "{}",
+            status
+        );
+        assert!(status.contains("Noise 0.10"), "{}", status);
+        assert!(status.contains("Speed 100/s (98.2/s)"), "{}", status);
+        assert!(status.ends_with("Seed 42"), "{}", status);
+        assert!(!status.contains("{"));
+    }
+
+    #[test]
+    fn test_strategy_columns_are_colored() {
+        let spans = format_status(&metric(), &run());
+        let colored: Vec<_> = spans.iter().filter(|s| s.style.fg.is_some()).collect();
+        assert_eq!(colored.len(), Strategy::ALL.len());
+        assert_eq!(colored[0].style.fg, Some(Palette::Classic.color(D)));
+        assert_eq!(colored[3].style.fg, Some(Palette::Classic.color(R)));
+        assert_eq!(colored[4].style.fg, Some(Palette::Classic.color(Ch)));
+        // Columns line up: every entry has the same width.
+        assert!(colored.iter().all(|s| s.width() == colored[0].width()));
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut run = run();
+        assert!(run.modes().is_empty());
+        run.detached = true;
+        run.browsing_paused = true;
+        run.step = 120;
+        assert_eq!(
+            run.modes(),
+            vec!["DETACH @ 120", "PAUSED (k: keep running)"]
+        );
+        run.playback = Some(Some(10));
+        assert_eq!(run.modes(), vec!["DETACH @ 120", "PLAYBACK 10/s"]);
+        run.playback = Some(None);
+        run.run_mode = RunMode::Paused;
+        assert_eq!(run.modes(), vec!["DETACH @ 120", "PLAYBACK max", "PAUSED"]);
+        run.detached = false;
+        run.playback = None;
+        run.run_mode = RunMode::SingleStep;
+        assert_eq!(run.modes(), vec!["SINGLE-STEP (n: next)"]);
+        let status = text(&format_status(&metric(), &run));
+        assert!(status.starts_with("Step 120 SINGLE-STEP (n: next) │ "));
+    }
+
+    #[test]
+    fn test_frozen_indicator() {
+        let mut run = run();
+        run.run_mode = RunMode::Paused;
+        let frozen = Metric {
+            frozen: true,
+            ..metric()
+        };
+        let status = text(&format_status(&frozen, &run));
+        assert!(
+            status.starts_with("Step 1234 PAUSED FROZEN │ "),
+            "{}",
+            status
+        );
+        assert!(!text(&format_status(&metric(), &run)).contains("FROZEN"));
+    }
+
+    #[test]
+    fn test_changed_count() {
+        let mut run = run();
+        run.changed = Some(17);
+        let status = text(&format_status(&metric(), &run));
+        assert!(status.ends_with("Seed 42 │ Changed 17"), "{}", status);
+    }
+
+    #[test]
+    fn test_narrow_terminals_degrade() {
+        let full = spans_width(&format_status(&metric(), &run()));
+        let mut previous = full;
+        for width in (0..full as u16).rev() {
+            let mut run = run();
+            run.width = width;
+            let spans = format_status(&metric(), &run);
+            let used = spans_width(&spans);
+            assert!(used <= width as usize, "{} > {}", used, width);
+            assert!(used <= previous);
+            previous = used;
+        }
+        let mut narrow = run();
+        narrow.width = 60;
+        narrow.run_mode = RunMode::Paused;
+        let status = text(&format_status(&metric(), &narrow));
+        assert!(status.contains("Step 1234 PAUSED"), "{}", status);
+        assert!(!status.contains("avg"), "{}", status);
+        assert!(!status.contains("Seed"), "{}", status);
+        narrow.width = 5;
+        assert_eq!(text(&format_status(&metric(), &narrow)), "Step ");
+    }
+
+    #[test]
+    fn test_empty_metric() {
+        let metric = Metric {
+            strategies: BTreeMap::new(),
+            max_score: BTreeMap::new(),
+            avg_score: BTreeMap::new(),
+            mean_stake: Default::default(),
+            score_per_interaction: Default::default(),
+            score_per_step: Default::default(),
+            top_agent: Default::default(),
+            parameters: Default::default(),
+            step_index: 0,
+            coop_actions: 0,
+            coop_rate: 0.0,
+            snapshot: Default::default(),
+            founder_lineages: 0,
+            population: 0,
+            births: 0,
+            deaths: 0,
+            imitations: 0,
+            explorations: 0,
+            refusals: 0,
+            rejected_payoffs: 0,
+            honest_signals: 0,
+            dishonest_signals: 0,
+            class_strategies: Vec::new(),
+            groups: Vec::new(),
+            env_state: 1.0,
+            shocks: Vec::new(),
+            interventions: Vec::new(),
+            frozen: false,
+            scores: Grid::default(),
+            payoff_flow: None,
+            coop_rate_map: None,
+            thumbnail: None,
+        };
+        let status = text(&format_status(&metric, &run()));
+        assert!(
+            status.contains("Deflect     0   0.0% avg      -"),
+            "{}",
+            status
+        );
+    }
+}
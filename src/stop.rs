@@ -0,0 +1,390 @@
+//! When to stop a run: thresholds on cooperation or on a strategy's share, checked against
+//! every metric, or a limit on wall-clock time. Conditions combine by listing them; the first
+//! to hold stops the run.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::agent::Strategy;
+use crate::env::Metric;
+
+/// One reason to stop a run. Rate thresholds only hold once they've held for a number of
+/// steps in a row, so a run isn't stopped by a single noisy step; 0 and 1 both mean the
+/// first step that crosses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopCondition {
+    /// The cooperation rate stayed below this for this many steps.
+    CoopRateBelow(f32, usize),
+    /// The cooperation rate stayed above this for this many steps.
+    CoopRateAbove(f32, usize),
+    /// No agent plays this strategy's kind any more.
+    StrategyExtinct(Strategy),
+    /// Agents of this strategy's kind make up more than this fraction of the living ones.
+    StrategyShareAbove(Strategy, f32),
+    /// The environment has taken this many steps in all.
+    MaxSteps(usize),
+    /// The run has gone on for this long in wall-clock time, as of the end of a step.
+    WallClock(Duration),
+}
+
+impl StopCondition {
+    /// Whether `metric`, reached `elapsed` into the run, on its own meets the condition,
+    /// before any steps in a row are required.
+    fn holds(&self, metric: &Metric, elapsed: Duration) -> bool {
+        let count = |s: Strategy| metric.strategies.get(&s.kind()).copied().unwrap_or(0);
+        match *self {
+            StopCondition::CoopRateBelow(rate, _) => metric.coop_rate < rate,
+            StopCondition::CoopRateAbove(rate, _) => metric.coop_rate > rate,
+            StopCondition::StrategyExtinct(s) => count(s) == 0,
+            StopCondition::StrategyShareAbove(s, share) => {
+                let living: usize = metric.strategies.values().sum();
+                living > 0 && count(s) as f32 / living as f32 > share
+            }
+            StopCondition::MaxSteps(steps) => metric.step_index + 1 >= steps,
+            StopCondition::WallClock(limit) => elapsed >= limit,
+        }
+    }
+
+    /// Steps in a row the condition must hold for.
+    fn steps_needed(&self) -> usize {
+        match *self {
+            StopCondition::CoopRateBelow(_, n) | StopCondition::CoopRateAbove(_, n) => n.max(1),
+            _ => 1,
+        }
+    }
+}
+
+/// The syntax `FromStr` reads, e.g. `coop_below=0.05:100` or `share_above=tft:0.9`.
+impl fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |s: Strategy| s.name().to_lowercase();
+        match *self {
+            StopCondition::CoopRateBelow(rate, n) => write!(f, "coop_below={}:{}", rate, n),
+            StopCondition::CoopRateAbove(rate, n) => write!(f, "coop_above={}:{}", rate, n),
+            StopCondition::StrategyExtinct(s) => write!(f, "extinct={}", name(s)),
+            StopCondition::StrategyShareAbove(s, share) => {
+                write!(f, "share_above={}:{}", name(s), share)
+            }
+            StopCondition::MaxSteps(steps) => write!(f, "max_steps={}", steps),
+            StopCondition::WallClock(limit) => {
+                write!(f, "max_seconds={}", limit.as_secs_f64())
+            }
+        }
+    }
+}
+
+/// Text that isn't a stop condition, with what's wrong with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseStopError(pub String);
+
+impl fmt::Display for ParseStopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseStopError {}
+
+impl FromStr for StopCondition {
+    type Err = ParseStopError;
+
+    /// One of `coop_below=RATE[:STEPS]`, `coop_above=RATE[:STEPS]`, `extinct=STRATEGY`,
+    /// `share_above=STRATEGY:FRACTION`, `max_steps=STEPS` or `max_seconds=SECONDS`. Rates
+    /// and fractions are in [0, 1]; strategies are names or aliases in any case.
+    fn from_str(text: &str) -> Result<StopCondition, ParseStopError> {
+        let invalid =
+            |why: &str| ParseStopError(format!("invalid stop condition {:?}: {}", text, why));
+        let (name, value) = text
+            .split_once('=')
+            .ok_or_else(|| invalid("expected NAME=VALUE"))?;
+        let fraction = |v: &str| {
+            v.trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|f| (0.0..=1.0).contains(f))
+                .ok_or_else(|| invalid("expected a fraction in [0, 1]"))
+        };
+        let steps = |v: &str| {
+            v.trim()
+                .parse::<usize>()
+                .map_err(|_| invalid("expected a number of steps"))
+        };
+        let strategy = |v: &str| v.parse::<Strategy>().map_err(|e| invalid(&e.to_string()));
+        let rate_for = |v: &str| match v.split_once(':') {
+            Some((rate, n)) => Ok((fraction(rate)?, steps(n)?)),
+            None => Ok((fraction(v)?, 1)),
+        };
+        match name.trim() {
+            "coop_below" => rate_for(value).map(|(r, n)| StopCondition::CoopRateBelow(r, n)),
+            "coop_above" => rate_for(value).map(|(r, n)| StopCondition::CoopRateAbove(r, n)),
+            "extinct" => strategy(value).map(StopCondition::StrategyExtinct),
+            "share_above" => {
+                let (s, share) = value
+                    .split_once(':')
+                    .ok_or_else(|| invalid("expected STRATEGY:FRACTION"))?;
+                Ok(StopCondition::StrategyShareAbove(
+                    strategy(s)?,
+                    fraction(share)?,
+                ))
+            }
+            "max_steps" => steps(value).map(StopCondition::MaxSteps),
+            "max_seconds" => value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                .map(StopCondition::WallClock)
+                .ok_or_else(|| invalid("expected a number of seconds")),
+            _ => Err(invalid(
+                "expected coop_below, coop_above, extinct, share_above, max_steps or max_seconds",
+            )),
+        }
+    }
+}
+
+/// Checks a list of conditions against a run's metrics as they come, keeping count of how
+/// long each has held.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stopper {
+    conditions: Vec<StopCondition>,
+    /// Steps in a row each condition has held for, up to the latest metric.
+    streaks: Vec<usize>,
+}
+
+impl Stopper {
+    pub fn new(conditions: impl IntoIterator<Item = StopCondition>) -> Stopper {
+        let conditions: Vec<StopCondition> = conditions.into_iter().collect();
+        Stopper {
+            streaks: vec![0; conditions.len()],
+            conditions,
+        }
+    }
+
+    pub fn conditions(&self) -> &[StopCondition] {
+        &self.conditions
+    }
+
+    /// Takes in the next metric of the run and returns the first condition, in list order,
+    /// that now holds. Takes no time into account; see [`Stopper::check_at`].
+    pub fn check(&mut self, metric: &Metric) -> Option<StopCondition> {
+        self.check_at(metric, Duration::ZERO)
+    }
+
+    /// [`Stopper::check`] for a metric that came `elapsed` after the run started, by
+    /// whatever clock the caller keeps.
+    pub fn check_at(&mut self, metric: &Metric, elapsed: Duration) -> Option<StopCondition> {
+        let mut triggered = None;
+        for (condition, streak) in self.conditions.iter().zip(&mut self.streaks) {
+            *streak = if condition.holds(metric, elapsed) {
+                *streak + 1
+            } else {
+                0
+            };
+            if triggered.is_none() && *streak >= condition.steps_needed() {
+                triggered = Some(*condition);
+            }
+        }
+        triggered
+    }
+
+    /// Forgets the metrics seen so far, for a run that starts over.
+    pub fn reset(&mut self) {
+        self.streaks.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::EnvironmentBuilder;
+    use crate::env::Environment;
+    use std::collections::BTreeMap;
+    use Strategy::{Coop as C, Deflect as D, TicToc as T};
+
+    /// A metric of a one-cell run with this index, cooperation rate and strategy counts.
+    fn metric(step_index: usize, coop_rate: f32, strategies: &[(Strategy, usize)]) -> Metric {
+        let mut metric = EnvironmentBuilder::new().size(1, 1).build().unwrap().step();
+        metric.step_index = step_index;
+        metric.coop_rate = coop_rate;
+        metric.strategies = strategies.iter().copied().collect::<BTreeMap<_, _>>();
+        metric
+    }
+
+    /// The step at which each of `rates` in turn stops a run checked against `condition`.
+    fn stops(condition: StopCondition, rates: &[f32]) -> Option<usize> {
+        let mut stopper = Stopper::new([condition]);
+        rates
+            .iter()
+            .enumerate()
+            .position(|(i, &r)| stopper.check(&metric(i, r, &[(C, 1)])).is_some())
+    }
+
+    #[test]
+    fn test_rate_thresholds() {
+        let below = StopCondition::CoopRateBelow(0.1, 1);
+        assert_eq!(stops(below, &[0.5, 0.2, 0.05, 0.0]), Some(2));
+        assert_eq!(stops(below, &[0.5, 0.1, 0.3]), None);
+        let above = StopCondition::CoopRateAbove(0.9, 0);
+        assert_eq!(stops(above, &[0.5, 0.95]), Some(1));
+        assert_eq!(stops(above, &[0.9, 0.2]), None);
+    }
+
+    #[test]
+    fn test_sustained_thresholds() {
+        // Dips that don't last three steps don't count; the streak starts over after them.
+        let below = StopCondition::CoopRateBelow(0.1, 3);
+        let rates = [0.0, 0.0, 0.5, 0.0, 0.05, 0.2, 0.0, 0.0, 0.0, 0.0];
+        assert_eq!(stops(below, &rates), Some(8));
+        assert_eq!(stops(below, &rates[..8]), None);
+        let above = StopCondition::CoopRateAbove(0.5, 2);
+        assert_eq!(stops(above, &[0.6, 0.4, 0.6, 0.7]), Some(3));
+    }
+
+    #[test]
+    fn test_strategy_conditions() {
+        let mut extinct = Stopper::new([StopCondition::StrategyExtinct(T)]);
+        assert_eq!(extinct.check(&metric(0, 0.5, &[(T, 1), (D, 3)])), None);
+        assert_eq!(
+            extinct.check(&metric(1, 0.5, &[(T, 0), (D, 4)])),
+            Some(StopCondition::StrategyExtinct(T))
+        );
+        assert!(extinct.check(&metric(2, 0.5, &[(D, 4)])).is_some());
+        // Shares count the living agents, by kind.
+        let generous: Strategy = "gtft".parse().unwrap();
+        let share = StopCondition::StrategyShareAbove(generous, 0.75);
+        let mut takeover = Stopper::new([share]);
+        assert_eq!(
+            takeover.check(&metric(0, 0.5, &[(generous, 3), (D, 1)])),
+            None
+        );
+        assert_eq!(
+            takeover.check(&metric(1, 0.5, &[(generous, 4), (D, 1)])),
+            Some(share)
+        );
+        assert_eq!(takeover.check(&metric(2, 0.5, &[])), None);
+    }
+
+    #[test]
+    fn test_first_listed_condition_wins() {
+        let conditions = [
+            StopCondition::CoopRateAbove(0.5, 2),
+            StopCondition::MaxSteps(3),
+        ];
+        let mut stopper = Stopper::new(conditions);
+        assert_eq!(stopper.check(&metric(0, 0.9, &[(C, 1)])), None);
+        assert_eq!(stopper.check(&metric(1, 0.4, &[(C, 1)])), None);
+        assert_eq!(
+            stopper.check(&metric(2, 0.9, &[(C, 1)])),
+            Some(conditions[1])
+        );
+        assert_eq!(
+            stopper.check(&metric(3, 0.9, &[(C, 1)])),
+            Some(conditions[0])
+        );
+        stopper.reset();
+        assert_eq!(stopper.check(&metric(0, 0.9, &[(C, 1)])), None);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let build = || -> Environment {
+            EnvironmentBuilder::new()
+                .size(8, 8)
+                .seed(3)
+                .strategies(&[C, D])
+                .build()
+                .unwrap()
+        };
+        let (metrics, stopped) = build().run_until(&[StopCondition::MaxSteps(5)]);
+        assert_eq!(
+            (metrics.len(), stopped),
+            (5, Some(StopCondition::MaxSteps(5)))
+        );
+        // Deflect takes over a mix without reciprocity, so Coop dies out first.
+        let conditions = [
+            StopCondition::StrategyExtinct(C),
+            StopCondition::MaxSteps(200),
+        ];
+        let (metrics, stopped) = build().run_until(&conditions);
+        assert_eq!(stopped, Some(conditions[0]));
+        assert!(!metrics.last().unwrap().strategies.contains_key(&C));
+        assert!(metrics[..metrics.len() - 1]
+            .iter()
+            .all(|m| m.strategies.contains_key(&C)));
+        // Nothing can stop a run without conditions, so it doesn't start.
+        let mut env = build();
+        assert_eq!(env.run_until(&[]), (vec![], None));
+        assert_eq!(env.steps_taken(), 0);
+    }
+
+    #[test]
+    fn test_wall_clock() {
+        let limit = StopCondition::WallClock(Duration::from_secs(10));
+        let mut stopper = Stopper::new([limit]);
+        let at = |s: u64| Duration::from_secs(s);
+        assert_eq!(stopper.check_at(&metric(0, 0.5, &[(C, 1)]), at(9)), None);
+        assert_eq!(
+            stopper.check_at(&metric(1, 0.5, &[(C, 1)]), at(10)),
+            Some(limit)
+        );
+        // Without a clock no time passes.
+        assert_eq!(stopper.check(&metric(2, 0.5, &[(C, 1)])), None);
+        // A simulated clock that moves three seconds a step: the run stops at the first step
+        // to end at or past the limit, with every metric up to it returned.
+        let mut env = EnvironmentBuilder::new().size(4, 4).build().unwrap();
+        let mut steps = 0;
+        let (metrics, stopped) =
+            env.run_until_with_clock(&[limit, StopCondition::MaxSteps(100)], || {
+                steps += 1;
+                at(3 * steps)
+            });
+        assert_eq!((metrics.len(), stopped), (4, Some(limit)));
+        assert_eq!(env.steps_taken(), 4);
+    }
+
+    #[test]
+    fn test_parse() {
+        let cases = [
+            (
+                "coop_below=0.05:100",
+                StopCondition::CoopRateBelow(0.05, 100),
+            ),
+            ("coop_below=0.05", StopCondition::CoopRateBelow(0.05, 1)),
+            ("coop_above=1:3", StopCondition::CoopRateAbove(1.0, 3)),
+            ("extinct=TFT", StopCondition::StrategyExtinct(T)),
+            (
+                "share_above=deflect:0.9",
+                StopCondition::StrategyShareAbove(D, 0.9),
+            ),
+            ("max_steps=500", StopCondition::MaxSteps(500)),
+            (
+                "max_seconds=90",
+                StopCondition::WallClock(Duration::from_secs(90)),
+            ),
+            (
+                "max_seconds=0.25",
+                StopCondition::WallClock(Duration::from_millis(250)),
+            ),
+        ];
+        for (text, condition) in cases {
+            assert_eq!(text.parse(), Ok(condition), "{}", text);
+            assert_eq!(condition.to_string().parse(), Ok(condition));
+        }
+        for bad in [
+            "coop_below",
+            "coop_below=1.5",
+            "coop_below=0.1:-2",
+            "coop_below=0.1:",
+            "extinct=grudger",
+            "share_above=tft",
+            "share_above=tft:2",
+            "max_steps=lots",
+            "max_seconds=-1",
+            "max_seconds=inf",
+            "fixation=tft",
+        ] {
+            assert!(bad.parse::<StopCondition>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Handing the terminal back in the state it was found in: on quitting, on a panic, and while
+//! the process is suspended.
+
+use std::{io, panic};
+
+use ratatui::{
+    crossterm::{
+        event::{DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEvent, KeyModifiers},
+        execute,
+        terminal::{enable_raw_mode, EnterAlternateScreen},
+    },
+    DefaultTerminal,
+};
+
+/// Leaves the alternate screen, raw mode and mouse capture. Safe to call more than once.
+pub fn restore() {
+    let _ = execute!(io::stdout(), DisableMouseCapture);
+    ratatui::restore();
+}
+
+/// Restores the terminal before any panic is reported, so the report isn't lost to the
+/// alternate screen and the shell isn't left in raw mode.
+pub fn install_panic_hook() {
+    chain_panic_hook(restore);
+}
+
+/// Runs `before` ahead of whatever panic hook is installed now.
+fn chain_panic_hook(before: impl Fn() + Send + Sync + 'static) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        before();
+        previous(info);
+    }));
+}
+
+/// Whether `key` is Ctrl+Z, which raw mode delivers as a key instead of suspending.
+pub fn is_suspend(key: KeyEvent) -> bool {
+    key.code == KeyCode::Char('z') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Hands the terminal back and stops the process as Ctrl+Z would outside raw mode, then takes
+/// the terminal over again and redraws from scratch once the shell continues it.
+#[cfg(unix)]
+pub fn suspend(term: &mut DefaultTerminal) -> io::Result<()> {
+    restore();
+    // The standard library can't raise signals, so `kill` stops us; it returns after `fg`.
+    std::process::Command::new("kill")
+        .args(["-TSTP", &std::process::id().to_string()])
+        .status()?;
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    term.clear()
+}
+
+/// Suspending is a Unix job-control feature; elsewhere Ctrl+Z does nothing.
+#[cfg(not(unix))]
+pub fn suspend(_term: &mut DefaultTerminal) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_panic_hook_runs_first() {
+        let restored = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&restored);
+        // The previous hook only runs after the restore, and stands in for the printing one.
+        let seen_by_previous = Arc::new(AtomicBool::new(false));
+        let seen = Arc::clone(&seen_by_previous);
+        let check = Arc::clone(&restored);
+        panic::set_hook(Box::new(move |_| {
+            seen.store(check.load(Ordering::SeqCst), Ordering::SeqCst);
+        }));
+        chain_panic_hook(move || flag.store(true, Ordering::SeqCst));
+        assert!(panic::catch_unwind(|| panic!("boom")).is_err());
+        let _ = panic::take_hook();
+        assert!(restored.load(Ordering::SeqCst));
+        assert!(seen_by_previous.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_ctrl_z_suspends() {
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert!(is_suspend(key(KeyCode::Char('z'), KeyModifiers::CONTROL)));
+        assert!(!is_suspend(key(KeyCode::Char('z'), KeyModifiers::NONE)));
+        assert!(!is_suspend(key(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+    }
+}
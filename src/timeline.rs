@@ -0,0 +1,452 @@
+use coop::History;
+
+/// Number of steps PageUp/PageDown move the view.
+pub const PAGE: usize = 10;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ViewMode {
+    Latest,
+    Detach,
+}
+
+/// The steps a [`Timeline`] can show: `0..len()`, of which only some may be retained.
+pub trait Steps {
+    /// Number of steps, retained or not.
+    fn len(&self) -> usize;
+    /// The retained step closest to `step`.
+    fn nearest(&self, step: usize) -> usize;
+    /// The latest retained step before `step`.
+    fn prev(&self, step: usize) -> Option<usize>;
+    /// The earliest retained step after `step`.
+    fn next(&self, step: usize) -> Option<usize>;
+}
+
+/// A buffer length: every step below it is retained.
+impl Steps for usize {
+    fn len(&self) -> usize {
+        *self
+    }
+
+    fn nearest(&self, step: usize) -> usize {
+        step.min(self.saturating_sub(1))
+    }
+
+    fn prev(&self, step: usize) -> Option<usize> {
+        step.min(*self).checked_sub(1)
+    }
+
+    fn next(&self, step: usize) -> Option<usize> {
+        (step + 1 < *self).then_some(step + 1)
+    }
+}
+
+impl<T: Steps> Steps for &T {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn nearest(&self, step: usize) -> usize {
+        (**self).nearest(step)
+    }
+
+    fn prev(&self, step: usize) -> Option<usize> {
+        (**self).prev(step)
+    }
+
+    fn next(&self, step: usize) -> Option<usize> {
+        (**self).next(step)
+    }
+}
+
+impl Steps for History {
+    fn len(&self) -> usize {
+        History::len(self)
+    }
+
+    fn nearest(&self, step: usize) -> usize {
+        History::nearest(self, step).unwrap_or(0)
+    }
+
+    fn prev(&self, step: usize) -> Option<usize> {
+        History::prev(self, step)
+    }
+
+    fn next(&self, step: usize) -> Option<usize> {
+        History::next(self, step)
+    }
+}
+
+/// Which step is being viewed. All methods take the steps on offer so the viewed step is
+/// always a retained one within `0..len`.
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    mode: ViewMode,
+    step: usize,
+}
+
+impl Timeline {
+    pub fn new() -> Timeline {
+        Timeline {
+            mode: ViewMode::Latest,
+            step: 0,
+        }
+    }
+
+    pub fn mode(&self) -> ViewMode {
+        self.mode
+    }
+
+    /// The step to render.
+    pub fn current(&self, steps: impl Steps + Copy) -> usize {
+        let last = steps.len().saturating_sub(1);
+        match self.mode {
+            ViewMode::Latest => last,
+            ViewMode::Detach => steps.nearest(self.step.min(last)),
+        }
+    }
+
+    /// Moves one step back. From Latest this freezes the view on the newest frame.
+    pub fn back(&mut self, steps: impl Steps + Copy) {
+        match self.mode {
+            ViewMode::Latest => self.end(steps),
+            ViewMode::Detach => {
+                let current = self.current(steps);
+                self.step = steps.prev(current).unwrap_or(current);
+            }
+        }
+    }
+
+    /// Moves one step forward; moving past the newest frame returns to Latest.
+    pub fn forward(&mut self, steps: impl Steps + Copy) {
+        if self.mode == ViewMode::Detach {
+            match steps.next(self.current(steps)) {
+                Some(next) => self.step = next,
+                None => self.latest(),
+            }
+        }
+    }
+
+    /// Jumps `PAGE` steps back, or at least to the previous retained step.
+    pub fn page_back(&mut self, steps: impl Steps + Copy) {
+        let current = self.current(steps);
+        let mut target = steps.nearest(current.saturating_sub(PAGE));
+        if target >= current {
+            target = steps.prev(current).unwrap_or(current);
+        }
+        self.detach_at(target, steps);
+    }
+
+    /// Jumps `PAGE` steps forward, stopping at the newest frame; paging from the newest frame
+    /// returns to Latest.
+    pub fn page_forward(&mut self, steps: impl Steps + Copy) {
+        if self.mode == ViewMode::Detach {
+            let current = self.current(steps);
+            let Some(next) = steps.next(current) else {
+                self.latest();
+                return;
+            };
+            let last = steps.len().saturating_sub(1);
+            self.step = steps.nearest((current + PAGE).min(last)).max(next);
+        }
+    }
+
+    pub fn home(&mut self, steps: impl Steps + Copy) {
+        self.detach_at(0, steps);
+    }
+
+    pub fn end(&mut self, steps: impl Steps + Copy) {
+        self.detach_at(steps.len().saturating_sub(1), steps);
+    }
+
+    pub fn latest(&mut self) {
+        self.mode = ViewMode::Latest;
+    }
+
+    /// Detaches at the retained step nearest to `step`, clamped to the last step.
+    pub fn detach_at(&mut self, step: usize, steps: impl Steps + Copy) {
+        self.mode = ViewMode::Detach;
+        self.step = steps.nearest(step.min(steps.len().saturating_sub(1)));
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline::new()
+    }
+}
+
+/// Column of a `width`-column scrubber nearest to buffer index `step` of `len`. The first
+/// and last steps always sit at the first and last columns, so buffers both shorter and longer
+/// than the bar span its full width.
+pub fn step_to_column(step: usize, len: usize, width: u16) -> u16 {
+    if len <= 1 || width <= 1 {
+        return 0;
+    }
+    let step = step.min(len - 1) as u128;
+    let last = len as u128 - 1;
+    ((step * (width as u128 - 1) + last / 2) / last) as u16
+}
+
+/// Buffer index represented by scrubber column `column`, rounding to the nearest step. This is
+/// the inverse of [`step_to_column`] wherever a column holds exactly one step.
+pub fn column_to_step(column: u16, len: usize, width: u16) -> usize {
+    if len <= 1 || width <= 1 {
+        return len.saturating_sub(1);
+    }
+    let column = column.min(width - 1) as u128;
+    let span = width as u128 - 1;
+    ((column * (len as u128 - 1) + span / 2) / span) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coop::{Grid, Metric};
+
+    fn detached(step: usize, len: usize) -> Timeline {
+        let mut t = Timeline::new();
+        t.detach_at(step, len);
+        t
+    }
+
+    #[test]
+    fn test_latest_follows_buffer() {
+        let t = Timeline::new();
+        assert_eq!(t.current(0), 0);
+        assert_eq!(t.current(1), 0);
+        assert_eq!(t.current(10), 9);
+    }
+
+    #[test]
+    fn test_back_from_latest_freezes_newest_frame() {
+        let mut t = Timeline::new();
+        t.back(10);
+        assert_eq!(t.mode(), ViewMode::Detach);
+        assert_eq!(t.current(10), 9);
+        assert_eq!(t.current(20), 9);
+        t.back(20);
+        assert_eq!(t.current(20), 8);
+    }
+
+    #[test]
+    fn test_back_saturates_at_zero() {
+        let mut t = detached(0, 10);
+        t.back(10);
+        assert_eq!(t.current(10), 0);
+        let mut t = Timeline::new();
+        t.back(0);
+        assert_eq!(t.current(0), 0);
+        t.back(1);
+        assert_eq!(t.current(1), 0);
+    }
+
+    #[test]
+    fn test_forward_never_passes_last_index() {
+        let mut t = detached(7, 10);
+        t.forward(10);
+        assert_eq!((t.mode(), t.current(10)), (ViewMode::Detach, 8));
+        t.forward(10);
+        assert_eq!((t.mode(), t.current(10)), (ViewMode::Detach, 9));
+        t.forward(10);
+        assert_eq!(t.mode(), ViewMode::Latest);
+        assert_eq!(t.current(10), 9);
+    }
+
+    #[test]
+    fn test_forward_in_latest_is_noop() {
+        let mut t = Timeline::new();
+        t.forward(10);
+        assert_eq!(t.mode(), ViewMode::Latest);
+        t.page_forward(10);
+        assert_eq!(t.mode(), ViewMode::Latest);
+    }
+
+    #[test]
+    fn test_page_back() {
+        let mut t = Timeline::new();
+        t.page_back(25);
+        assert_eq!(t.current(25), 14);
+        t.page_back(25);
+        assert_eq!(t.current(25), 4);
+        t.page_back(25);
+        assert_eq!(t.current(25), 0);
+        t.page_back(25);
+        assert_eq!(t.current(25), 0);
+    }
+
+    #[test]
+    fn test_page_forward() {
+        let mut t = detached(0, 25);
+        t.page_forward(25);
+        assert_eq!(t.current(25), 10);
+        t.page_forward(25);
+        assert_eq!(t.current(25), 20);
+        t.page_forward(25);
+        assert_eq!((t.mode(), t.current(25)), (ViewMode::Detach, 24));
+        t.page_forward(25);
+        assert_eq!(t.mode(), ViewMode::Latest);
+    }
+
+    #[test]
+    fn test_home_and_end() {
+        let mut t = Timeline::new();
+        t.home(10);
+        assert_eq!((t.mode(), t.current(10)), (ViewMode::Detach, 0));
+        t.end(10);
+        assert_eq!((t.mode(), t.current(10)), (ViewMode::Detach, 9));
+        t.end(0);
+        assert_eq!(t.current(0), 0);
+    }
+
+    #[test]
+    fn test_detach_at_clamps() {
+        let t = detached(100, 10);
+        assert_eq!(t.current(10), 9);
+        let t = detached(3, 0);
+        assert_eq!(t.current(0), 0);
+    }
+
+    #[test]
+    fn test_scrubber_ends_map_to_ends() {
+        for (len, width) in [(2, 80), (10, 80), (80, 80), (81, 80), (100_000, 80), (5, 2)] {
+            assert_eq!(step_to_column(0, len, width), 0);
+            assert_eq!(step_to_column(len - 1, len, width), width - 1);
+            assert_eq!(column_to_step(0, len, width), 0);
+            assert_eq!(column_to_step(width - 1, len, width), len - 1);
+        }
+    }
+
+    #[test]
+    fn test_scrubber_short_buffer_round_trips() {
+        let (len, width) = (10, 80);
+        for step in 0..len {
+            assert_eq!(
+                column_to_step(step_to_column(step, len, width), len, width),
+                step
+            );
+        }
+        // Columns between two steps snap to the nearer one.
+        assert_eq!(column_to_step(5, len, width), 1);
+        assert_eq!(column_to_step(3, len, width), 0);
+    }
+
+    #[test]
+    fn test_scrubber_long_buffer_is_monotonic() {
+        let (len, width) = (1_000_000, 120);
+        let steps: Vec<usize> = (0..width).map(|c| column_to_step(c, len, width)).collect();
+        assert!(steps.windows(2).all(|w| w[0] < w[1]));
+        for c in 0..width {
+            assert_eq!(step_to_column(column_to_step(c, len, width), len, width), c);
+        }
+        assert_eq!(step_to_column(len / 3, len, width), 40);
+    }
+
+    #[test]
+    fn test_scrubber_degenerate() {
+        assert_eq!(step_to_column(0, 0, 80), 0);
+        assert_eq!(step_to_column(0, 1, 80), 0);
+        assert_eq!(step_to_column(5, 10, 0), 0);
+        assert_eq!(column_to_step(10, 0, 80), 0);
+        assert_eq!(column_to_step(10, 1, 80), 0);
+        assert_eq!(column_to_step(0, 10, 1), 9);
+        // Out-of-range inputs are clamped.
+        assert_eq!(step_to_column(50, 10, 80), 79);
+        assert_eq!(column_to_step(500, 10, 80), 9);
+    }
+
+    fn history(steps: usize, capacity: usize, keyframe_every: usize) -> History {
+        let mut history = History::new(capacity, keyframe_every);
+        for _ in 0..steps {
+            history.push(Metric {
+                strategies: Default::default(),
+                max_score: Default::default(),
+                avg_score: Default::default(),
+                mean_stake: Default::default(),
+                score_per_interaction: Default::default(),
+                score_per_step: Default::default(),
+                top_agent: Default::default(),
+                parameters: Default::default(),
+                step_index: 0,
+                coop_actions: 0,
+                coop_rate: 0.0,
+                snapshot: Default::default(),
+                founder_lineages: 0,
+                population: 0,
+                births: 0,
+                deaths: 0,
+                imitations: 0,
+                explorations: 0,
+                refusals: 0,
+                rejected_payoffs: 0,
+                honest_signals: 0,
+                dishonest_signals: 0,
+                class_strategies: Vec::new(),
+                groups: Vec::new(),
+                env_state: 1.0,
+                shocks: Vec::new(),
+                interventions: Vec::new(),
+                frozen: false,
+                scores: Grid::default(),
+                payoff_flow: None,
+                coop_rate_map: None,
+                thumbnail: None,
+            });
+        }
+        history
+    }
+
+    /// Twelve steps with only 0, 4, 8, 9, 10 and 11 retained.
+    fn sparse() -> History {
+        history(12, 4, 4)
+    }
+
+    #[test]
+    fn test_steps_snap_to_retained() {
+        let h = sparse();
+        let mut t = Timeline::new();
+        t.home(&h);
+        let mut visited = vec![t.current(&h)];
+        for _ in 0..5 {
+            t.forward(&h);
+            visited.push(t.current(&h));
+        }
+        assert_eq!(visited, vec![0, 4, 8, 9, 10, 11]);
+        t.forward(&h);
+        assert_eq!(t.mode(), ViewMode::Latest);
+        t.detach_at(9, &h);
+        t.back(&h);
+        assert_eq!(t.current(&h), 8);
+        t.back(&h);
+        assert_eq!(t.current(&h), 4);
+        // A dropped step snaps to the nearest retained one, the earlier one on a tie.
+        t.detach_at(6, &h);
+        assert_eq!(t.current(&h), 4);
+        t.detach_at(7, &h);
+        assert_eq!(t.current(&h), 8);
+    }
+
+    #[test]
+    fn test_paging_over_sparse_steps() {
+        let h = sparse();
+        let mut t = Timeline::new();
+        t.page_back(&h);
+        assert_eq!(t.current(&h), 0);
+        t.page_forward(&h);
+        assert_eq!(t.current(&h), 10);
+        t.detach_at(4, &h);
+        t.page_forward(&h);
+        assert_eq!(t.current(&h), 11);
+        // Paging within a gap wider than a page still moves.
+        let h = history(300, 1, 100);
+        t.detach_at(200, &h);
+        t.page_back(&h);
+        assert_eq!(t.current(&h), 100);
+    }
+
+    #[test]
+    fn test_latest_resets_mode() {
+        let mut t = detached(3, 10);
+        t.latest();
+        assert_eq!((t.mode(), t.current(10)), (ViewMode::Latest, 9));
+    }
+}
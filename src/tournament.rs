@@ -0,0 +1,372 @@
+//! Axelrod-style round-robin tournaments, without a grid.
+//!
+//! Every strategy plays an iterated game against every strategy, itself included, with the
+//! same strategies, payoffs and noise model as an [`Environment`](crate::Environment): both
+//! sides pick an action from what they've seen the other play (and, with cheap talk, what it
+//! signaled), then noise may flip each action before it's scored and remembered. [`ecological`] then plays the results forward as
+//! population dynamics.
+
+pub mod ecological;
+
+use std::fmt::Write;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::actions::ActionBits;
+use crate::agent::{ActionContext, InteractionOutcome, Strategy};
+use crate::env::Payoffs;
+use crate::error::Error;
+
+/// A round-robin between a set of strategies.
+#[derive(Clone, Debug)]
+pub struct Tournament {
+    strategies: Vec<Strategy>,
+    rounds: usize,
+    noise: f32,
+    seed: u64,
+    trials: usize,
+    payoffs: Payoffs,
+    signaling: bool,
+}
+
+impl Tournament {
+    /// Matches of `rounds` rounds between every pair of `strategies`, each played once with
+    /// the default payoffs. Repeated strategies only take part once.
+    pub fn new(strategies: &[Strategy], rounds: usize, noise: f32, seed: u64) -> Tournament {
+        let mut unique = Vec::with_capacity(strategies.len());
+        for &strategy in strategies {
+            if !unique.contains(&strategy) {
+                unique.push(strategy);
+            }
+        }
+        Tournament {
+            strategies: unique,
+            rounds,
+            noise,
+            seed,
+            trials: 1,
+            payoffs: Payoffs::default(),
+            signaling: false,
+        }
+    }
+
+    /// Plays every match `trials` times and averages them; at least once.
+    pub fn trials(mut self, trials: usize) -> Self {
+        self.trials = trials.max(1);
+        self
+    }
+
+    pub fn payoffs(mut self, payoffs: Payoffs) -> Self {
+        self.payoffs = payoffs;
+        self
+    }
+
+    /// Has both sides of every round announce their intent first, as
+    /// [`Strategy::signal`] says.
+    pub fn signaling(mut self, signaling: bool) -> Self {
+        self.signaling = signaling;
+        self
+    }
+
+    /// Plays every match. Equal settings give equal results. Fails on an empty strategy list,
+    /// noise outside `[0, 1]` or non-finite payoffs.
+    pub fn run(&self) -> Result<TournamentResult, Error> {
+        if self.strategies.is_empty() {
+            return Err(Error::EmptyStrategyPool);
+        }
+        if !(0.0..=1.0).contains(&self.noise) {
+            return Err(Error::InvalidNoise(self.noise));
+        }
+        if !self.payoffs.is_finite() {
+            return Err(Error::InvalidPayoffs(self.payoffs));
+        }
+        let n = self.strategies.len();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut totals = vec![vec![0.0; n]; n];
+        for _ in 0..self.trials {
+            for (i, &a) in self.strategies.iter().enumerate() {
+                for (j, &b) in self.strategies.iter().enumerate().skip(i) {
+                    let (first, second) = self.play(a, b, &mut rng);
+                    if i == j {
+                        // Both sides are the same strategy, so either side's payoff is its own.
+                        totals[i][i] += (first + second) / 2.0;
+                    } else {
+                        totals[i][j] += first;
+                        totals[j][i] += second;
+                    }
+                }
+            }
+        }
+        let games = (self.trials * self.rounds) as f64;
+        let payoffs: Vec<Vec<f64>> = totals
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|total| if games > 0.0 { total / games } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+        let mut rankings: Vec<(Strategy, f64)> = self
+            .strategies
+            .iter()
+            .zip(&payoffs)
+            .map(|(&s, row)| (s, row.iter().sum::<f64>() / n as f64))
+            .collect();
+        // Stable, so ties keep the order the strategies were given in.
+        rankings.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(TournamentResult {
+            strategies: self.strategies.clone(),
+            payoffs,
+            rankings,
+            rounds: self.rounds,
+            trials: self.trials,
+        })
+    }
+
+    /// One match; returns the total payoff of each side. The sides play as if on cells
+    /// `(0, 0)` and `(0, 1)`.
+    fn play(&self, first: Strategy, second: Strategy, rng: &mut StdRng) -> (f64, f64) {
+        let coords = [(0, 0), (0, 1)];
+        // What each side has seen the other play, after noise.
+        let mut seen_by_first = ActionBits::new();
+        let mut seen_by_second = ActionBits::new();
+        let mut last: Option<[InteractionOutcome; 2]> = None;
+        let (mut first_total, mut second_total) = (0.0, 0.0);
+        let signals = self.signaling.then(|| (first.signal(), second.signal()));
+        for round in 0..self.rounds {
+            let first_intended = first.get_action(&mut ActionContext {
+                history: seen_by_first.actions(),
+                own_history: seen_by_second.actions(),
+                last_outcome: last.as_ref().map(|l| &l[0]),
+                signal: signals.map(|s| s.1),
+                step: round,
+                ..ActionContext::new(coords[1], &self.payoffs, rng)
+            });
+            let second_intended = second.get_action(&mut ActionContext {
+                history: seen_by_second.actions(),
+                own_history: seen_by_first.actions(),
+                last_outcome: last.as_ref().map(|l| &l[1]),
+                signal: signals.map(|s| s.0),
+                step: round,
+                ..ActionContext::new(coords[0], &self.payoffs, rng)
+            });
+            let first_action = first_intended.with_noise(self.noise, rng);
+            let second_action = second_intended.with_noise(self.noise, rng);
+            let first_payoff = self.payoffs.score(first_action, second_action);
+            let second_payoff = self.payoffs.score(second_action, first_action);
+            first_total += first_payoff;
+            second_total += second_payoff;
+            seen_by_first.push(second_action);
+            seen_by_second.push(first_action);
+            last = Some([
+                InteractionOutcome {
+                    opponent: coords[1],
+                    intended: first_intended,
+                    realized: first_action,
+                    their_action: second_action,
+                    payoff: first_payoff,
+                    step: round,
+                },
+                InteractionOutcome {
+                    opponent: coords[0],
+                    intended: second_intended,
+                    realized: second_action,
+                    their_action: first_action,
+                    payoff: second_payoff,
+                    step: round,
+                },
+            ]);
+        }
+        (first_total, second_total)
+    }
+}
+
+/// What a tournament's matches earned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TournamentResult {
+    /// The strategies that took part, in the order they were given.
+    pub strategies: Vec<Strategy>,
+    /// `payoffs[i][j]` is the average payoff per round of `strategies[i]` against
+    /// `strategies[j]`.
+    pub payoffs: Vec<Vec<f64>>,
+    /// Every strategy with its average payoff per round over all its matches, best first.
+    pub rankings: Vec<(Strategy, f64)>,
+    pub rounds: usize,
+    pub trials: usize,
+}
+
+impl TournamentResult {
+    /// The average payoff per round of `strategy` against `opponent`, if both took part.
+    pub fn payoff(&self, strategy: Strategy, opponent: Strategy) -> Option<f64> {
+        let i = self.strategies.iter().position(|&s| s == strategy)?;
+        let j = self.strategies.iter().position(|&s| s == opponent)?;
+        Some(self.payoffs[i][j])
+    }
+
+    /// The best-ranked strategy.
+    pub fn winner(&self) -> Strategy {
+        self.rankings[0].0
+    }
+
+    /// The rankings followed by the payoff matrix, rows against columns, as aligned text.
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "Round-robin: {} rounds, {} trial(s)\n\n",
+            self.rounds, self.trials
+        );
+        for (place, (strategy, score)) in self.rankings.iter().enumerate() {
+            let _ = writeln!(
+                text,
+                "{:>2}. {:<8} {:>8.3}",
+                place + 1,
+                strategy.name(),
+                score
+            );
+        }
+        text.push_str("\n        ");
+        for strategy in &self.strategies {
+            let _ = write!(text, " {:>8}", strategy.name());
+        }
+        text.push('\n');
+        for (strategy, row) in self.strategies.iter().zip(&self.payoffs) {
+            let _ = write!(text, "{:<8}", strategy.name());
+            for payoff in row {
+                let _ = write!(text, " {:>8.3}", payoff);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// One row per strategy in ranking order: its average, then its payoff against every
+    /// strategy in the order they were given.
+    pub fn to_csv(&self) -> String {
+        let names: Vec<String> = self
+            .strategies
+            .iter()
+            .map(|s| s.name().to_lowercase())
+            .collect();
+        let mut csv = format!(
+            "rank,strategy,average,{}\n",
+            names
+                .iter()
+                .map(|n| format!("vs_{}", n))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        for (place, (strategy, score)) in self.rankings.iter().enumerate() {
+            let i = self.strategies.iter().position(|s| s == strategy).unwrap();
+            let row: Vec<String> = self.payoffs[i].iter().map(|p| p.to_string()).collect();
+            let _ = writeln!(
+                csv,
+                "{},{},{},{}",
+                place + 1,
+                names[i],
+                score,
+                row.join(",")
+            );
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Strategy::*;
+
+    #[test]
+    fn test_deflect_beats_coop_head_to_head() {
+        let result = Tournament::new(&[Coop, Deflect], 10, 0.0, 1).run().unwrap();
+        assert_eq!(result.payoff(Deflect, Coop), Some(4.0));
+        assert_eq!(result.payoff(Coop, Deflect), Some(0.0));
+        assert_eq!(result.payoff(Coop, Coop), Some(3.0));
+        assert_eq!(result.winner(), Deflect);
+    }
+
+    #[test]
+    fn test_tictoc_wins_the_round_robin() {
+        let result = Tournament::new(&[Coop, Deflect, TicToc, Random], 200, 0.0, 7)
+            .trials(5)
+            .run()
+            .unwrap();
+        assert_eq!(result.winner(), TicToc, "{}", result.to_text());
+        // Only the first round against an unconditional defector is lost.
+        assert_eq!(result.payoff(Deflect, TicToc), Some(4.0 / 200.0));
+        assert_eq!(result.payoff(TicToc, TicToc), Some(3.0));
+    }
+
+    #[test]
+    fn test_deceivers_exploit_the_trusting() {
+        let strategies = [Trusting, Deceiver, Wary];
+        let result = Tournament::new(&strategies, 100, 0.0, 1)
+            .signaling(true)
+            .run()
+            .unwrap();
+        // Taken at its word every round, the deceiver earns the temptation throughout...
+        assert_eq!(result.payoff(Deceiver, Trusting), Some(4.0));
+        assert_eq!(result.payoff(Trusting, Deceiver), Some(0.0));
+        // ...but is believed only once by the wary.
+        assert_eq!(result.payoff(Deceiver, Wary), Some(4.0 / 100.0));
+        assert_eq!(result.payoff(Wary, Trusting), Some(3.0));
+        // Without cheap talk, the trusting cooperate unconditionally anyway.
+        let silent = Tournament::new(&strategies, 100, 0.0, 1).run().unwrap();
+        assert_eq!(silent.payoff(Deceiver, Trusting), Some(4.0));
+        assert_eq!(silent.payoff(Deceiver, Wary), Some(4.0 / 100.0));
+    }
+
+    #[test]
+    fn test_equal_seeds_give_equal_results() {
+        let tournament = Tournament::new(&Strategy::ALL, 50, 0.1, 3).trials(3);
+        assert_eq!(tournament.run().unwrap(), tournament.run().unwrap());
+        let other = Tournament::new(&Strategy::ALL, 50, 0.1, 4).trials(3);
+        assert_ne!(tournament.run().unwrap(), other.run().unwrap());
+    }
+
+    #[test]
+    fn test_full_noise_flips_every_action() {
+        // Both sides always mean to cooperate and always end up defecting.
+        let result = Tournament::new(&[Coop], 10, 1.0, 0).run().unwrap();
+        assert_eq!(result.payoff(Coop, Coop), Some(0.0));
+    }
+
+    #[test]
+    fn test_rejects_invalid_settings() {
+        assert_eq!(
+            Tournament::new(&[], 10, 0.0, 0).run(),
+            Err(Error::EmptyStrategyPool)
+        );
+        assert_eq!(
+            Tournament::new(&[Coop], 10, 1.5, 0).run(),
+            Err(Error::InvalidNoise(1.5))
+        );
+        let payoffs = Payoffs {
+            reward: f64::NAN,
+            ..Payoffs::default()
+        };
+        assert!(matches!(
+            Tournament::new(&[Coop], 10, 0.0, 0).payoffs(payoffs).run(),
+            Err(Error::InvalidPayoffs(_))
+        ));
+    }
+
+    #[test]
+    fn test_reports() {
+        let result = Tournament::new(&[Coop, Deflect, Coop], 4, 0.0, 0)
+            .run()
+            .unwrap();
+        assert_eq!(result.strategies, vec![Coop, Deflect]);
+        assert_eq!(
+            result.to_csv(),
+            "rank,strategy,average,vs_coop,vs_deflect\n\
+             1,deflect,2,4,0\n\
+             2,coop,1.5,3,0\n"
+        );
+        let text = result.to_text();
+        assert!(text.starts_with("Round-robin: 4 rounds, 1 trial(s)"));
+        assert!(text.contains(" 1. Deflect     2.000"), "{}", text);
+        assert!(text.contains("Coop        3.000    0.000"), "{}", text);
+    }
+}
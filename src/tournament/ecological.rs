@@ -0,0 +1,241 @@
+//! Ecological tournaments: population dynamics over a round-robin's payoff matrix.
+//!
+//! Every strategy starts with an equal share of the population. Each generation, a strategy's
+//! share grows in proportion to its average payoff against the current mix, relative to the
+//! population's average payoff, until the shares stop changing.
+
+use crate::agent::Strategy;
+use crate::tournament::TournamentResult;
+
+/// Population dynamics over the payoffs of a round-robin.
+#[derive(Clone, Debug)]
+pub struct Ecology {
+    strategies: Vec<Strategy>,
+    payoffs: Vec<Vec<f64>>,
+    max_generations: usize,
+    tolerance: f64,
+    extinction: f64,
+}
+
+impl Ecology {
+    /// Dynamics over `result`'s payoff matrix, for at most 1000 generations. Strategies whose
+    /// share falls below one in a million die out.
+    pub fn new(result: &TournamentResult) -> Ecology {
+        // Growth needs non-negative fitness; shifting every payoff by the same amount keeps
+        // which strategy earns more against whom.
+        let lowest = result
+            .payoffs
+            .iter()
+            .flatten()
+            .fold(0.0_f64, |lowest, &p| lowest.min(p));
+        Ecology {
+            strategies: result.strategies.clone(),
+            payoffs: result
+                .payoffs
+                .iter()
+                .map(|row| row.iter().map(|p| p - lowest).collect())
+                .collect(),
+            max_generations: 1000,
+            tolerance: 1e-9,
+            extinction: 1e-6,
+        }
+    }
+
+    pub fn max_generations(mut self, generations: usize) -> Self {
+        self.max_generations = generations;
+        self
+    }
+
+    /// Stops once no share changes by more than `tolerance` in a generation.
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Shares below `threshold` are set to zero, and the strategy can't come back.
+    pub fn extinction(mut self, threshold: f64) -> Self {
+        self.extinction = threshold;
+        self
+    }
+
+    /// Iterates generations from equal shares until they converge or the generation limit is
+    /// reached.
+    pub fn run(&self) -> EcologyResult {
+        let n = self.strategies.len();
+        let mut shares = vec![1.0 / n as f64; n];
+        let mut history = vec![shares.clone()];
+        let mut converged = false;
+        for _ in 0..self.max_generations {
+            let next = self.generation(&shares);
+            let change = next
+                .iter()
+                .zip(&shares)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max);
+            shares = next;
+            history.push(shares.clone());
+            if change <= self.tolerance {
+                converged = true;
+                break;
+            }
+        }
+        EcologyResult {
+            strategies: self.strategies.clone(),
+            shares: history,
+            converged,
+        }
+    }
+
+    /// The shares after one generation.
+    fn generation(&self, shares: &[f64]) -> Vec<f64> {
+        let fitness: Vec<f64> = self
+            .payoffs
+            .iter()
+            .map(|row| row.iter().zip(shares).map(|(p, s)| p * s).sum())
+            .collect();
+        let mean: f64 = fitness.iter().zip(shares).map(|(f, s)| f * s).sum();
+        if mean <= 0.0 {
+            // Nobody earns anything, so nobody grows.
+            return shares.to_vec();
+        }
+        let mut next: Vec<f64> = shares
+            .iter()
+            .zip(&fitness)
+            .map(|(s, f)| s * f / mean)
+            .map(|s| if s < self.extinction { 0.0 } else { s })
+            .collect();
+        let total: f64 = next.iter().sum();
+        if total > 0.0 {
+            for share in &mut next {
+                *share /= total;
+            }
+        }
+        next
+    }
+}
+
+/// The course of an ecological tournament.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EcologyResult {
+    /// The strategies, in the order of the tournament they came from.
+    pub strategies: Vec<Strategy>,
+    /// `shares[g][i]` is the share of `strategies[i]` at generation `g`, starting with the
+    /// equal shares of generation 0.
+    pub shares: Vec<Vec<f64>>,
+    /// Whether the shares settled before the generation limit.
+    pub converged: bool,
+}
+
+impl EcologyResult {
+    /// Generations played after the starting one.
+    pub fn generations(&self) -> usize {
+        self.shares.len() - 1
+    }
+
+    /// The shares after the last generation.
+    pub fn final_shares(&self) -> &[f64] {
+        self.shares.last().unwrap()
+    }
+
+    /// The share of `strategy` at every generation, if it took part.
+    pub fn series(&self, strategy: Strategy) -> Option<Vec<f64>> {
+        let i = self.strategies.iter().position(|&s| s == strategy)?;
+        Some(self.shares.iter().map(|g| g[i]).collect())
+    }
+
+    /// Strategies that haven't died out, in tournament order.
+    pub fn survivors(&self) -> Vec<Strategy> {
+        self.strategies
+            .iter()
+            .zip(self.final_shares())
+            .filter(|(_, &share)| share > 0.0)
+            .map(|(&s, _)| s)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::Payoffs;
+    use crate::tournament::Tournament;
+
+    use Strategy::*;
+
+    /// Axelrod's payoffs, under which defecting against a defector still earns something.
+    const AXELROD: Payoffs = Payoffs {
+        reward: 3.0,
+        temptation: 5.0,
+        sucker: 0.0,
+        punishment: 1.0,
+    };
+
+    fn ecology(strategies: &[Strategy]) -> EcologyResult {
+        let result = Tournament::new(strategies, 200, 0.0, 1)
+            .payoffs(AXELROD)
+            .run()
+            .unwrap();
+        Ecology::new(&result).run()
+    }
+
+    #[test]
+    fn test_exploiters_die_out_with_their_prey() {
+        let result = ecology(&[Coop, Deflect, TicToc]);
+        assert!(result.converged);
+        let deflect = result.series(Deflect).unwrap();
+        // Deflect thrives while there are cooperators to exploit...
+        assert!(deflect[1] > deflect[0]);
+        // ...and disappears once they've dwindled and only reciprocators are left to meet.
+        assert_eq!(*deflect.last().unwrap(), 0.0);
+        assert!(result.survivors().contains(&TicToc));
+        let coop = result.series(Coop).unwrap();
+        assert!(coop.last().unwrap() < &coop[0]);
+        assert_eq!(result, ecology(&[Coop, Deflect, TicToc]));
+    }
+
+    #[test]
+    fn test_shares_stay_a_distribution() {
+        let result = ecology(&Strategy::ALL);
+        assert_eq!(result.shares[0], vec![1.0 / 11.0; 11]);
+        for shares in &result.shares {
+            assert!((shares.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!(shares.iter().all(|&s| s >= 0.0));
+        }
+    }
+
+    #[test]
+    fn test_defectors_take_over_naive_cooperators() {
+        let result = ecology(&[Coop, Deflect]);
+        assert_eq!(result.survivors(), vec![Deflect]);
+        assert_eq!(result.final_shares(), &[0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_generation_limit() {
+        let tournament = Tournament::new(&[Coop, Deflect, TicToc], 200, 0.0, 1)
+            .payoffs(AXELROD)
+            .run()
+            .unwrap();
+        let result = Ecology::new(&tournament).max_generations(3).run();
+        assert!(!result.converged);
+        assert_eq!(result.generations(), 3);
+        assert_eq!(result.shares.len(), 4);
+    }
+
+    #[test]
+    fn test_zero_payoffs_leave_shares_alone() {
+        let zero = Payoffs {
+            reward: 0.0,
+            temptation: 0.0,
+            sucker: 0.0,
+            punishment: 0.0,
+        };
+        let tournament = Tournament::new(&[Coop, Deflect], 10, 0.0, 1)
+            .payoffs(zero)
+            .run()
+            .unwrap();
+        let result = Ecology::new(&tournament).run();
+        assert!(result.converged);
+        assert_eq!(result.final_shares(), &[0.5, 0.5]);
+    }
+}
@@ -0,0 +1,1123 @@
+use std::time::Duration;
+
+use coop::Coord;
+use ratatui::{
+    crossterm::event::{KeyCode, MouseButton, MouseEvent, MouseEventKind},
+    layout::Rect,
+};
+
+use crate::{
+    export::{ExportKind, ExportMenu, MenuEvent},
+    inspector::{screen_to_cell, Cursor},
+    pacer::Pacer,
+    palette::Palette,
+    params::{LiveParam, ParamChange, ParamPanel, NOISE_STEP},
+    playback::{Playback, PlaybackEnd},
+    prompt::{PromptEvent, StepPrompt},
+    timeline::{column_to_step, Steps, Timeline, ViewMode},
+};
+
+/// Speed level the TUI starts at (100 steps/sec, roughly the old fixed cadence).
+const DEFAULT_SPEED_LEVEL: usize = 6;
+
+/// How the grid is drawn: two or one terminal columns per cell, two cells stacked in one
+/// half-block character, or one character per `n`x`n` block of cells colored by the block's
+/// majority strategy.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Zoom {
+    Double,
+    Single,
+    Half,
+    Block(usize),
+}
+
+/// Zoom levels cycled with 'z'.
+pub const ZOOM_LEVELS: [Zoom; 6] = [
+    Zoom::Double,
+    Zoom::Single,
+    Zoom::Half,
+    Zoom::Block(2),
+    Zoom::Block(4),
+    Zoom::Block(8),
+];
+
+impl Zoom {
+    /// Terminal columns used for one rendered unit.
+    pub fn cell_width(self) -> u16 {
+        match self {
+            Zoom::Double => 2,
+            Zoom::Single | Zoom::Half | Zoom::Block(_) => 1,
+        }
+    }
+
+    /// Grid rows packed into one terminal row.
+    pub fn rows_per_line(self) -> usize {
+        match self {
+            Zoom::Half => 2,
+            Zoom::Double | Zoom::Single | Zoom::Block(_) => 1,
+        }
+    }
+
+    /// Grid cells per rendered unit along each axis.
+    pub fn block(self) -> usize {
+        match self {
+            Zoom::Block(n) => n.max(1),
+            Zoom::Double | Zoom::Single | Zoom::Half => 1,
+        }
+    }
+}
+
+/// Every key binding as `(key, description)`, shown by the help overlay.
+pub const KEY_BINDINGS: [(&str, &str); 33] = [
+    ("q", "quit"),
+    ("?", "show this help"),
+    ("r / R", "restart with a new / the same seed"),
+    ("n / N", "noise -0.01 / +0.01 (n steps in single-step mode)"),
+    ("Tab / S-Tab", "open the parameter panel / select parameter"),
+    ("space / p", "pause or resume"),
+    ("p", "play history from the viewed step (while browsing)"),
+    ("s", "toggle single-step mode"),
+    ("n / Enter", "advance one step (single-step mode)"),
+    ("+ / ] / =", "faster (playback, or the selected parameter)"),
+    ("- / [", "slower (playback, or the selected parameter)"),
+    ("Left / Right", "step back / forward through history"),
+    ("PgUp / PgDn", "jump 10 steps through history"),
+    ("Home / End", "first / last buffered step"),
+    ("Esc", "return to the latest step (or leave inspect mode)"),
+    (
+        "k",
+        "keep running while browsing history (paused by default)",
+    ),
+    ("g", "go to step number (while browsing history)"),
+    ("d", "highlight cells that changed since the previous step"),
+    ("o", "show / hide group boundaries (with --groups)"),
+    ("m", "mark / unmark the viewed step for a diff view"),
+    (
+        "b / B",
+        "bookmark the viewed step / jump to the next bookmark",
+    ),
+    ("i", "toggle the cell inspector"),
+    ("arrows", "move the inspector cursor"),
+    ("f", "follow the inspected cell / stop following"),
+    ("c", "toggle charts"),
+    ("w", "cycle the cooperation moving average"),
+    ("z", "cycle zoom level"),
+    ("l", "toggle the legend"),
+    ("t", "cycle palette (classic / colorblind / mono)"),
+    (
+        "e",
+        "export the frame (PNG), history (GIF) or metrics (CSV)",
+    ),
+    ("click", "inspect a cell, or jump via the timeline bar"),
+    ("scroll", "step through history"),
+    ("Backspace", "delete a digit in the step prompt"),
+];
+
+/// Moving-average windows cycled with 'w'.
+pub const MA_WINDOWS: [usize; 4] = [0, 10, 50, 200];
+
+/// How the simulation advances: continuously, not at all, or one step per request.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum RunMode {
+    Running,
+    Paused,
+    SingleStep,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    Continue,
+    Quit,
+    /// Rebuild the environment from its config, with a fresh seed if `reseed`.
+    Restart {
+        reseed: bool,
+    },
+    /// Change the environment's noise by this amount (the environment clamps it).
+    AdjustNoise(f32),
+    Export(ExportKind),
+    /// Bookmark this step, or remove its bookmark.
+    ToggleBookmark(usize),
+    /// View the next bookmarked step after the current one.
+    NextBookmark,
+}
+
+/// Input-driven state of the TUI, kept free of terminal I/O so key sequences can be tested.
+#[derive(Clone, Debug)]
+pub struct UiState {
+    pub timeline: Timeline,
+    pub run_mode: RunMode,
+    /// Keep stepping while browsing history; by default browsing pauses the run.
+    pub keep_running: bool,
+    /// History replay in progress; only while detached.
+    pub playback: Option<Playback>,
+    pub playback_end: PlaybackEnd,
+    pub pending_steps: usize,
+    pub pacer: Pacer,
+    /// Open "go to step" prompt; while set it receives all key input.
+    pub prompt: Option<StepPrompt>,
+    /// Selected cell while inspect mode is on; arrow keys move it instead of the timeline.
+    pub inspect: Option<Cursor>,
+    /// Cell tracked by follow mode; it keeps its marker and info panel as the run advances.
+    pub follow: Option<Coord>,
+    /// Grid size as `(rows, cols)`, used to clamp the cursor.
+    pub dims: (usize, usize),
+    /// Where the grid was last drawn, for mapping mouse clicks back to cells.
+    pub grid_area: Rect,
+    /// Where the timeline scrubber was last drawn.
+    pub scrubber_area: Rect,
+    /// Whether the population chart is shown below the grid.
+    pub show_chart: bool,
+    /// Moving-average window for the cooperation chart; 0 disables the overlay.
+    pub ma_window: usize,
+    pub zoom: Zoom,
+    pub show_legend: bool,
+    /// Whether cells that changed strategy since the previous step are highlighted.
+    pub show_changes: bool,
+    /// Whether group boundaries are outlined, when the run has groups.
+    pub show_groups: bool,
+    pub palette: Palette,
+    /// Full-screen key binding help; the next key press closes it.
+    pub show_help: bool,
+    pub params: ParamPanel,
+    /// Step marked for comparison; while browsing history the view shows a diff against it.
+    pub marked: Option<usize>,
+    /// Open export menu; while set it receives all key input.
+    pub export_menu: Option<ExportMenu>,
+    /// Transient status message and the time it was posted.
+    pub message: Option<(String, Duration)>,
+}
+
+impl UiState {
+    pub fn new(dims: (usize, usize)) -> UiState {
+        UiState {
+            timeline: Timeline::new(),
+            run_mode: RunMode::Running,
+            keep_running: false,
+            playback: None,
+            playback_end: PlaybackEnd::default(),
+            pending_steps: 0,
+            pacer: Pacer::new(DEFAULT_SPEED_LEVEL),
+            prompt: None,
+            inspect: None,
+            follow: None,
+            dims,
+            grid_area: Rect::default(),
+            scrubber_area: Rect::default(),
+            show_chart: false,
+            ma_window: MA_WINDOWS[0],
+            zoom: Zoom::Double,
+            show_legend: true,
+            show_changes: false,
+            show_groups: true,
+            palette: Palette::default(),
+            show_help: false,
+            params: ParamPanel::default(),
+            marked: None,
+            export_menu: None,
+            message: None,
+        }
+    }
+
+    /// Whether the main loop should advance the environment this iteration.
+    pub fn should_step(&self) -> bool {
+        self.run_mode == RunMode::Running && !self.browsing_paused()
+    }
+
+    /// Whether stepping is held only because history is being browsed.
+    pub fn browsing_paused(&self) -> bool {
+        self.run_mode == RunMode::Running
+            && self.timeline.mode() == ViewMode::Detach
+            && !self.keep_running
+    }
+
+    /// Advances a running playback by the frames due at `now`. Reaching the newest step ends
+    /// it, either staying there or going live depending on `playback_end`.
+    pub fn tick_playback(&mut self, now: Duration, steps: impl Steps + Copy) {
+        if self.timeline.mode() != ViewMode::Detach {
+            self.playback = None;
+        }
+        let Some(playback) = self.playback.as_mut() else {
+            return;
+        };
+        for _ in 0..playback.due(now) {
+            if steps.next(self.timeline.current(steps)).is_none() {
+                self.playback = None;
+                if self.playback_end == PlaybackEnd::Live {
+                    self.timeline.latest();
+                }
+                return;
+            }
+            self.timeline.forward(steps);
+        }
+    }
+
+    /// Consumes one requested single step, if any.
+    pub fn take_single_step(&mut self) -> bool {
+        if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The marked step to diff against, if the diff view is active.
+    pub fn diff_against(&self) -> Option<usize> {
+        match self.timeline.mode() {
+            ViewMode::Detach => self.marked,
+            ViewMode::Latest => None,
+        }
+    }
+
+    /// The step to highlight changes against when viewing `step`; there is none before the
+    /// first buffered step.
+    pub fn change_base(&self, step: usize) -> Option<usize> {
+        if self.show_changes {
+            step.checked_sub(1)
+        } else {
+            None
+        }
+    }
+
+    /// The cell whose info panel is shown: the inspector cursor, else the followed cell.
+    pub fn panel_coord(&self) -> Option<Coord> {
+        self.inspect.map(|c| c.coord()).or(self.follow)
+    }
+
+    /// The step to render, always one that is still retained.
+    pub fn current_step(&self, steps: impl Steps + Copy) -> usize {
+        self.timeline.current(steps)
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode, steps: impl Steps + Copy) -> Control {
+        if self.show_help {
+            self.show_help = false;
+            return Control::Continue;
+        }
+        if let Some(prompt) = self.prompt.as_mut() {
+            match prompt.handle_key(code) {
+                PromptEvent::Pending => {}
+                PromptEvent::Cancelled => self.prompt = None,
+                PromptEvent::Submitted(step) => {
+                    self.timeline.detach_at(step, steps);
+                    self.prompt = None;
+                }
+            }
+            return Control::Continue;
+        }
+        if let Some(menu) = self.export_menu.as_mut() {
+            match menu.handle_key(code) {
+                MenuEvent::Pending => return Control::Continue,
+                MenuEvent::Cancelled => self.export_menu = None,
+                MenuEvent::Chosen(kind) => {
+                    self.export_menu = None;
+                    return Control::Export(kind);
+                }
+            }
+            return Control::Continue;
+        }
+        if self.params.open {
+            match code {
+                KeyCode::Esc => {
+                    self.params.close();
+                    return Control::Continue;
+                }
+                KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Char(']') => {
+                    return self.apply(self.params.adjust(true));
+                }
+                KeyCode::Char('-') | KeyCode::Char('[') => {
+                    return self.apply(self.params.adjust(false));
+                }
+                _ => {}
+            }
+        }
+        if let Some(cursor) = self.inspect {
+            let moved = match code {
+                KeyCode::Up => Some(cursor.moved(-1, 0, self.dims)),
+                KeyCode::Down => Some(cursor.moved(1, 0, self.dims)),
+                KeyCode::Left => Some(cursor.moved(0, -1, self.dims)),
+                KeyCode::Right => Some(cursor.moved(0, 1, self.dims)),
+                _ => None,
+            };
+            if let Some(moved) = moved {
+                self.inspect = Some(moved);
+                return Control::Continue;
+            }
+            if code == KeyCode::Esc {
+                self.inspect = None;
+                return Control::Continue;
+            }
+            if code == KeyCode::Char('f') {
+                self.follow = Some(cursor.coord());
+                self.inspect = None;
+                return Control::Continue;
+            }
+        }
+        if is_navigation(code) {
+            self.playback = None;
+        }
+        if code == KeyCode::Char('p') && self.timeline.mode() == ViewMode::Detach {
+            self.playback = match self.playback {
+                Some(_) => None,
+                None => Some(Playback::new()),
+            };
+            return Control::Continue;
+        }
+        let single_stepping = self.run_mode == RunMode::SingleStep;
+        let (run_mode, step_once) = run_transition(self.run_mode, code);
+        self.run_mode = run_mode;
+        if step_once {
+            self.pending_steps += 1;
+        }
+        match code {
+            KeyCode::Char('q') => return Control::Quit,
+            KeyCode::Char('r') => return Control::Restart { reseed: true },
+            KeyCode::Char('R') => return Control::Restart { reseed: false },
+            KeyCode::Char('N') => return Control::AdjustNoise(NOISE_STEP),
+            KeyCode::Char('b') => return Control::ToggleBookmark(self.current_step(steps)),
+            KeyCode::Char('B') => return Control::NextBookmark,
+            KeyCode::Char('n') if !single_stepping => return Control::AdjustNoise(-NOISE_STEP),
+            KeyCode::Tab => self.params.tab(),
+            KeyCode::BackTab => self.params.back_tab(),
+            KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Char(']') => {
+                match self.playback.as_mut() {
+                    Some(playback) => playback.faster(),
+                    None => self.pacer.faster(),
+                }
+            }
+            KeyCode::Char('-') | KeyCode::Char('[') => match self.playback.as_mut() {
+                Some(playback) => playback.slower(),
+                None => self.pacer.slower(),
+            },
+            KeyCode::Left => self.timeline.back(steps),
+            KeyCode::Right => self.timeline.forward(steps),
+            KeyCode::PageUp => self.timeline.page_back(steps),
+            KeyCode::PageDown => self.timeline.page_forward(steps),
+            KeyCode::Home => self.timeline.home(steps),
+            KeyCode::End => self.timeline.end(steps),
+            KeyCode::Esc => self.timeline.latest(),
+            KeyCode::Char('z') => {
+                let i = ZOOM_LEVELS.iter().position(|z| *z == self.zoom);
+                self.zoom = ZOOM_LEVELS[i.map_or(0, |i| (i + 1) % ZOOM_LEVELS.len())];
+            }
+            KeyCode::Char('m') if self.timeline.mode() == ViewMode::Detach => {
+                self.marked = match self.marked {
+                    Some(_) => None,
+                    None => Some(self.current_step(steps)),
+                };
+            }
+            KeyCode::Char('?') => self.show_help = true,
+            KeyCode::Char('e') => self.export_menu = Some(ExportMenu::default()),
+            KeyCode::Char('l') => self.show_legend = !self.show_legend,
+            KeyCode::Char('d') => self.show_changes = !self.show_changes,
+            KeyCode::Char('o') => self.show_groups = !self.show_groups,
+            KeyCode::Char('k') => self.keep_running = !self.keep_running,
+            KeyCode::Char('f') => self.follow = None,
+            KeyCode::Char('t') => self.palette = self.palette.next(),
+            KeyCode::Char('c') => self.show_chart = !self.show_chart,
+            KeyCode::Char('w') => {
+                let i = MA_WINDOWS.iter().position(|w| *w == self.ma_window);
+                self.ma_window = MA_WINDOWS[i.map_or(0, |i| (i + 1) % MA_WINDOWS.len())];
+            }
+            KeyCode::Char('i') => {
+                self.inspect = match self.inspect {
+                    Some(_) => None,
+                    None => Some(Cursor::new(self.dims.0 / 2, self.dims.1 / 2, self.dims)),
+                };
+            }
+            KeyCode::Char('g') if self.timeline.mode() == ViewMode::Detach => {
+                self.prompt = Some(StepPrompt::new());
+            }
+            _ => {}
+        }
+        Control::Continue
+    }
+}
+
+impl UiState {
+    fn apply(&mut self, change: ParamChange) -> Control {
+        match change.param {
+            LiveParam::Noise => Control::AdjustNoise(change.noise_delta()),
+            LiveParam::Speed => {
+                if change.up {
+                    self.pacer.faster();
+                } else {
+                    self.pacer.slower();
+                }
+                Control::Continue
+            }
+        }
+    }
+
+    /// Cuts the areas drawn last down to a terminal resized to `width`x`height`, so mouse
+    /// input never maps to cells that are no longer on screen before the next draw lays
+    /// them out again.
+    pub fn handle_resize(&mut self, width: u16, height: u16) {
+        let screen = Rect::new(0, 0, width, height);
+        self.grid_area = self.grid_area.intersection(screen);
+        self.scrubber_area = self.scrubber_area.intersection(screen);
+    }
+
+    pub fn handle_mouse(&mut self, event: MouseEvent, steps: impl Steps + Copy) {
+        if matches!(
+            event.kind,
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown
+        ) || self
+            .scrubber_area
+            .contains((event.column, event.row).into())
+        {
+            self.playback = None;
+        }
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left)
+                if self
+                    .scrubber_area
+                    .contains((event.column, event.row).into()) =>
+            {
+                let column = event.column - self.scrubber_area.x;
+                let step = column_to_step(column, steps.len(), self.scrubber_area.width);
+                self.timeline.detach_at(step, steps);
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                let block = self.zoom.block();
+                let rows = block * self.zoom.rows_per_line();
+                let shown = (self.dims.0.div_ceil(rows), self.dims.1.div_ceil(block));
+                self.inspect = screen_to_cell(
+                    event.column,
+                    event.row,
+                    self.grid_area,
+                    self.zoom.cell_width(),
+                    shown,
+                )
+                .map(|(r, c)| Cursor::new(r * rows, c * block, self.dims));
+            }
+            MouseEventKind::ScrollUp => self.timeline.back(steps),
+            MouseEventKind::ScrollDown => self.timeline.forward(steps),
+            _ => {}
+        }
+    }
+}
+
+/// Keys that move through history, which also stop playback.
+fn is_navigation(code: KeyCode) -> bool {
+    matches!(
+        code,
+        KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::PageUp
+            | KeyCode::PageDown
+            | KeyCode::Home
+            | KeyCode::End
+            | KeyCode::Esc
+            | KeyCode::Char('g')
+            | KeyCode::Char('B')
+    )
+}
+
+/// Pure transition function for the run mode. Returns the next mode and whether exactly one
+/// step should be taken in response to `code`.
+pub fn run_transition(mode: RunMode, code: KeyCode) -> (RunMode, bool) {
+    match (mode, code) {
+        (RunMode::Running, KeyCode::Char(' ') | KeyCode::Char('p')) => (RunMode::Paused, false),
+        (RunMode::Paused, KeyCode::Char(' ') | KeyCode::Char('p')) => (RunMode::Running, false),
+        (RunMode::SingleStep, KeyCode::Char(' ') | KeyCode::Char('p')) => (RunMode::Running, false),
+        (RunMode::SingleStep, KeyCode::Char('s')) => (RunMode::Running, false),
+        (_, KeyCode::Char('s')) => (RunMode::SingleStep, false),
+        (RunMode::SingleStep, KeyCode::Char('n') | KeyCode::Enter) => (RunMode::SingleStep, true),
+        (mode, _) => (mode, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMS: (usize, usize) = (10, 10);
+
+    #[test]
+    fn test_pause_toggle() {
+        let mut ui = UiState::new(DIMS);
+        assert!(ui.should_step());
+        ui.handle_key(KeyCode::Char(' '), 10);
+        assert!(!ui.should_step());
+        ui.handle_key(KeyCode::Char('p'), 10);
+        assert!(ui.should_step());
+    }
+
+    #[test]
+    fn test_pause_navigate_resume() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Char('p'), 10);
+        assert_eq!(ui.current_step(10), 9);
+
+        ui.handle_key(KeyCode::Home, 10);
+        assert_eq!(ui.current_step(10), 0);
+        ui.handle_key(KeyCode::Right, 10);
+        ui.handle_key(KeyCode::Right, 10);
+        assert_eq!(ui.current_step(10), 2);
+        assert_eq!(ui.run_mode, RunMode::Paused);
+
+        ui.handle_key(KeyCode::Char(' '), 10);
+        assert_eq!(ui.run_mode, RunMode::Running);
+        assert!(ui.browsing_paused());
+        ui.handle_key(KeyCode::Char('k'), 10);
+        assert!(ui.should_step());
+        // The detached view stays put while the buffer grows.
+        assert_eq!(ui.current_step(20), 2);
+
+        ui.handle_key(KeyCode::Esc, 20);
+        assert_eq!(ui.current_step(20), 19);
+    }
+
+    #[test]
+    fn test_browsing_pauses_stepping() {
+        let mut ui = UiState::new(DIMS);
+        assert!(ui.should_step());
+        assert!(!ui.browsing_paused());
+
+        ui.handle_key(KeyCode::Left, 10);
+        assert_eq!(ui.timeline.mode(), ViewMode::Detach);
+        assert_eq!(ui.run_mode, RunMode::Running);
+        assert!(ui.browsing_paused());
+        assert!(!ui.should_step());
+
+        ui.handle_key(KeyCode::Esc, 10);
+        assert!(!ui.browsing_paused());
+        assert!(ui.should_step());
+
+        // With keep-running, browsing leaves the run going.
+        ui.handle_key(KeyCode::Char('k'), 10);
+        ui.handle_key(KeyCode::Home, 10);
+        assert!(!ui.browsing_paused());
+        assert!(ui.should_step());
+
+        // An explicit pause isn't reported as a browsing pause.
+        ui.handle_key(KeyCode::Char('k'), 10);
+        ui.handle_key(KeyCode::Char(' '), 10);
+        assert!(!ui.browsing_paused());
+        assert!(!ui.should_step());
+    }
+
+    #[test]
+    fn test_detach_while_paused_stays_in_bounds() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Char(' '), 5);
+        ui.handle_key(KeyCode::Left, 5);
+        assert_eq!(ui.timeline.mode(), ViewMode::Detach);
+        assert_eq!(ui.current_step(5), 4);
+        ui.handle_key(KeyCode::End, 5);
+        assert_eq!(ui.current_step(5), 4);
+    }
+
+    #[test]
+    fn test_right_from_last_frame_returns_to_latest() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Char(' '), 5);
+        ui.handle_key(KeyCode::Left, 5);
+        ui.handle_key(KeyCode::Left, 5);
+        assert_eq!(ui.current_step(5), 3);
+        ui.handle_key(KeyCode::Right, 5);
+        ui.handle_key(KeyCode::Right, 5);
+        assert_eq!(ui.timeline.mode(), ViewMode::Latest);
+        assert_eq!(ui.current_step(5), 4);
+    }
+
+    #[test]
+    fn test_page_keys() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::PageUp, 30);
+        assert_eq!(ui.current_step(30), 19);
+        ui.handle_key(KeyCode::PageDown, 30);
+        assert_eq!(ui.current_step(30), 29);
+    }
+
+    #[test]
+    fn test_goto_prompt() {
+        let mut ui = UiState::new(DIMS);
+        // The prompt is only available while browsing history.
+        ui.handle_key(KeyCode::Char('g'), 100);
+        assert!(ui.prompt.is_none());
+
+        ui.handle_key(KeyCode::Home, 100);
+        ui.handle_key(KeyCode::Char('g'), 100);
+        assert!(ui.prompt.is_some());
+        // Keys go to the prompt rather than quitting or navigating.
+        for code in [KeyCode::Char('4'), KeyCode::Char('q'), KeyCode::Char('2')] {
+            assert_eq!(ui.handle_key(code, 100), Control::Continue);
+        }
+        ui.handle_key(KeyCode::Enter, 100);
+        assert!(ui.prompt.is_none());
+        assert_eq!(ui.current_step(100), 42);
+    }
+
+    #[test]
+    fn test_goto_clamps_and_cancels() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::End, 10);
+        ui.handle_key(KeyCode::Char('g'), 10);
+        ui.handle_key(KeyCode::Char('3'), 10);
+        ui.handle_key(KeyCode::Esc, 10);
+        assert!(ui.prompt.is_none());
+        assert_eq!(ui.current_step(10), 9);
+        assert_eq!(ui.timeline.mode(), ViewMode::Detach);
+
+        ui.handle_key(KeyCode::Char('g'), 10);
+        ui.handle_key(KeyCode::Enter, 10);
+        assert!(ui.prompt.is_some());
+        ui.handle_key(KeyCode::Char('5'), 10);
+        ui.handle_key(KeyCode::Char('0'), 10);
+        ui.handle_key(KeyCode::Enter, 10);
+        assert_eq!(ui.current_step(10), 9);
+    }
+
+    #[test]
+    fn test_inspect_cursor() {
+        let mut ui = UiState::new((4, 6));
+        ui.handle_key(KeyCode::Char('i'), 10);
+        assert_eq!(ui.inspect.unwrap().coord(), (2, 3));
+        for _ in 0..10 {
+            ui.handle_key(KeyCode::Right, 10);
+            ui.handle_key(KeyCode::Up, 10);
+        }
+        assert_eq!(ui.inspect.unwrap().coord(), (0, 5));
+        // Arrows move the cursor, not the timeline.
+        assert_eq!(ui.timeline.mode(), ViewMode::Latest);
+        ui.handle_key(KeyCode::Down, 10);
+        ui.handle_key(KeyCode::Left, 10);
+        assert_eq!(ui.inspect.unwrap().coord(), (1, 4));
+
+        ui.handle_key(KeyCode::Esc, 10);
+        assert!(ui.inspect.is_none());
+        ui.handle_key(KeyCode::Left, 10);
+        assert_eq!(ui.timeline.mode(), ViewMode::Detach);
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: ratatui::crossterm::event::KeyModifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn test_mouse_click_selects_and_deselects() {
+        let mut ui = UiState::new((4, 4));
+        ui.grid_area = Rect::new(0, 0, 40, 5);
+        let click = MouseEventKind::Down(MouseButton::Left);
+        ui.handle_mouse(mouse(click, 5, 1), 10);
+        assert_eq!(ui.inspect.unwrap().coord(), (1, 2));
+        // Row 4 is the status line below a 4-row grid.
+        ui.handle_mouse(mouse(click, 5, 4), 10);
+        assert!(ui.inspect.is_none());
+        ui.handle_mouse(mouse(click, 7, 3), 10);
+        ui.handle_mouse(mouse(click, 39, 0), 10);
+        assert!(ui.inspect.is_none());
+    }
+
+    #[test]
+    fn test_resize_clamps_areas() {
+        let mut ui = UiState::new((20, 20));
+        ui.grid_area = Rect::new(0, 0, 40, 20);
+        ui.scrubber_area = Rect::new(0, 21, 40, 1);
+        ui.handle_resize(30, 10);
+        assert_eq!(ui.grid_area, Rect::new(0, 0, 30, 10));
+        assert!(ui.scrubber_area.is_empty());
+        // A click on the grid's former bottom half no longer selects anything.
+        let click = MouseEventKind::Down(MouseButton::Left);
+        ui.handle_mouse(mouse(click, 5, 15), 10);
+        assert!(ui.inspect.is_none());
+        ui.handle_mouse(mouse(click, 5, 5), 10);
+        assert_eq!(ui.inspect.unwrap().coord(), (5, 2));
+        // Growing doesn't stretch anything before the next draw.
+        ui.handle_resize(100, 100);
+        assert_eq!(ui.grid_area, Rect::new(0, 0, 30, 10));
+    }
+
+    #[test]
+    fn test_mouse_click_respects_zoom() {
+        let mut ui = UiState::new((10, 10));
+        ui.grid_area = Rect::new(0, 0, 40, 11);
+        let click = MouseEventKind::Down(MouseButton::Left);
+        ui.handle_key(KeyCode::Char('z'), 1);
+        assert_eq!(ui.zoom, Zoom::Single);
+        ui.handle_mouse(mouse(click, 5, 1), 10);
+        assert_eq!(ui.inspect.unwrap().coord(), (1, 5));
+        ui.handle_key(KeyCode::Char('z'), 1);
+        assert_eq!(ui.zoom, Zoom::Half);
+        // Half blocks stack two rows per line; a click selects the upper one.
+        ui.handle_mouse(mouse(click, 5, 3), 10);
+        assert_eq!(ui.inspect.unwrap().coord(), (6, 5));
+        ui.handle_mouse(mouse(click, 5, 5), 10);
+        assert!(ui.inspect.is_none());
+        ui.handle_key(KeyCode::Char('z'), 1);
+        assert_eq!(ui.zoom, Zoom::Block(2));
+        ui.handle_mouse(mouse(click, 4, 2), 10);
+        assert_eq!(ui.inspect.unwrap().coord(), (4, 8));
+        // A 10x10 grid at block 2 is 5 characters wide.
+        ui.handle_mouse(mouse(click, 5, 2), 10);
+        assert!(ui.inspect.is_none());
+    }
+
+    #[test]
+    fn test_zoom_cycles() {
+        let mut ui = UiState::new(DIMS);
+        for expected in ZOOM_LEVELS.iter().skip(1).chain(ZOOM_LEVELS.iter().take(1)) {
+            ui.handle_key(KeyCode::Char('z'), 1);
+            assert_eq!(ui.zoom, *expected);
+        }
+    }
+
+    #[test]
+    fn test_export_menu() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Char('e'), 10);
+        assert!(ui.export_menu.is_some());
+        // Keys go to the menu while it's open.
+        assert_eq!(ui.handle_key(KeyCode::Char('q'), 10), Control::Continue);
+        assert_eq!(ui.handle_key(KeyCode::Down, 10), Control::Continue);
+        assert_eq!(
+            ui.handle_key(KeyCode::Enter, 10),
+            Control::Export(ExportKind::History)
+        );
+        assert!(ui.export_menu.is_none());
+        ui.handle_key(KeyCode::Char('e'), 10);
+        assert_eq!(ui.handle_key(KeyCode::Esc, 10), Control::Continue);
+        assert!(ui.export_menu.is_none());
+        assert_eq!(ui.timeline.mode(), ViewMode::Latest);
+    }
+
+    #[test]
+    fn test_mouse_click_on_scrubber() {
+        let mut ui = UiState::new(DIMS);
+        ui.inspect = Some(Cursor::new(1, 1, DIMS));
+        ui.scrubber_area = Rect::new(0, 20, 11, 1);
+        let click = MouseEventKind::Down(MouseButton::Left);
+        ui.handle_mouse(mouse(click, 5, 20), 101);
+        assert_eq!(ui.timeline.mode(), ViewMode::Detach);
+        assert_eq!(ui.current_step(101), 50);
+        // Scrubber clicks don't touch the inspector selection.
+        assert!(ui.inspect.is_some());
+    }
+
+    #[test]
+    fn test_mouse_scroll_navigates_history() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_mouse(mouse(MouseEventKind::ScrollUp, 0, 0), 10);
+        ui.handle_mouse(mouse(MouseEventKind::ScrollUp, 0, 0), 10);
+        assert_eq!(ui.current_step(10), 8);
+        ui.handle_mouse(mouse(MouseEventKind::ScrollDown, 0, 0), 10);
+        ui.handle_mouse(mouse(MouseEventKind::ScrollDown, 0, 0), 10);
+        assert_eq!(ui.timeline.mode(), ViewMode::Latest);
+    }
+
+    #[test]
+    fn test_moving_average_window_cycles() {
+        let mut ui = UiState::new(DIMS);
+        let seen: Vec<usize> = (0..5)
+            .map(|_| {
+                ui.handle_key(KeyCode::Char('w'), 1);
+                ui.ma_window
+            })
+            .collect();
+        assert_eq!(seen, vec![10, 50, 200, 0, 10]);
+    }
+
+    #[test]
+    fn test_help_dismissed_by_any_key() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Char('?'), 10);
+        assert!(ui.show_help);
+        // The dismissing key is swallowed, even 'q'.
+        assert_eq!(ui.handle_key(KeyCode::Char('q'), 10), Control::Continue);
+        assert!(!ui.show_help);
+        assert_eq!(ui.run_mode, RunMode::Running);
+    }
+
+    #[test]
+    fn test_legend_toggle() {
+        let mut ui = UiState::new(DIMS);
+        assert!(ui.show_legend);
+        ui.handle_key(KeyCode::Char('l'), 10);
+        assert!(!ui.show_legend);
+        assert!(ui.show_groups);
+        ui.handle_key(KeyCode::Char('o'), 10);
+        assert!(!ui.show_groups);
+    }
+
+    #[test]
+    fn test_follow_mode() {
+        let mut ui = UiState::new(DIMS);
+        // 'f' only starts following from inspect mode.
+        ui.handle_key(KeyCode::Char('f'), 10);
+        assert_eq!(ui.follow, None);
+        ui.handle_key(KeyCode::Char('i'), 10);
+        ui.handle_key(KeyCode::Up, 10);
+        ui.handle_key(KeyCode::Char('f'), 10);
+        assert_eq!(ui.follow, Some((4, 5)));
+        assert!(ui.inspect.is_none());
+        assert_eq!(ui.panel_coord(), Some((4, 5)));
+        // Arrows browse history again while following.
+        ui.handle_key(KeyCode::Left, 10);
+        assert_eq!(ui.timeline.mode(), ViewMode::Detach);
+        // Inspecting shows the cursor's cell without dropping the followed one.
+        ui.handle_key(KeyCode::Char('i'), 10);
+        assert_eq!(ui.panel_coord(), Some((5, 5)));
+        ui.handle_key(KeyCode::Esc, 10);
+        ui.handle_key(KeyCode::Char('f'), 10);
+        assert_eq!(ui.follow, None);
+        assert_eq!(ui.panel_coord(), None);
+    }
+
+    #[test]
+    fn test_change_highlight() {
+        let mut ui = UiState::new(DIMS);
+        assert_eq!(ui.change_base(5), None);
+        ui.handle_key(KeyCode::Char('d'), 10);
+        assert_eq!(ui.change_base(5), Some(4));
+        // Step 0 (including right after a restart) has nothing to compare against.
+        assert_eq!(ui.change_base(0), None);
+        ui.handle_key(KeyCode::Char('d'), 10);
+        assert_eq!(ui.change_base(5), None);
+    }
+
+    #[test]
+    fn test_palette_cycles() {
+        let mut ui = UiState::new(DIMS);
+        assert_eq!(ui.palette, Palette::Classic);
+        ui.handle_key(KeyCode::Char('t'), 10);
+        assert_eq!(ui.palette, Palette::Colorblind);
+        ui.handle_key(KeyCode::Char('t'), 10);
+        ui.handle_key(KeyCode::Char('t'), 10);
+        assert_eq!(ui.palette, Palette::Classic);
+    }
+
+    #[test]
+    fn test_run_transition() {
+        use RunMode::*;
+        let space = KeyCode::Char(' ');
+        let s = KeyCode::Char('s');
+        let n = KeyCode::Char('n');
+        assert_eq!(run_transition(Running, space), (Paused, false));
+        assert_eq!(run_transition(Paused, space), (Running, false));
+        assert_eq!(run_transition(Running, s), (SingleStep, false));
+        assert_eq!(run_transition(Paused, s), (SingleStep, false));
+        assert_eq!(run_transition(SingleStep, s), (Running, false));
+        assert_eq!(run_transition(SingleStep, space), (Running, false));
+        assert_eq!(run_transition(SingleStep, n), (SingleStep, true));
+        assert_eq!(
+            run_transition(SingleStep, KeyCode::Enter),
+            (SingleStep, true)
+        );
+        assert_eq!(run_transition(Running, n), (Running, false));
+        assert_eq!(run_transition(Paused, KeyCode::Enter), (Paused, false));
+        assert_eq!(
+            run_transition(SingleStep, KeyCode::Left),
+            (SingleStep, false)
+        );
+    }
+
+    #[test]
+    fn test_single_step_requests() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Char('s'), 1);
+        assert!(!ui.should_step());
+        assert!(!ui.take_single_step());
+        ui.handle_key(KeyCode::Char('n'), 1);
+        ui.handle_key(KeyCode::Enter, 1);
+        assert!(ui.take_single_step());
+        assert!(ui.take_single_step());
+        assert!(!ui.take_single_step());
+        // History navigation still works while single-stepping.
+        ui.handle_key(KeyCode::Home, 3);
+        assert_eq!(ui.current_step(3), 0);
+        assert_eq!(ui.run_mode, RunMode::SingleStep);
+    }
+
+    #[test]
+    fn test_speed_keys() {
+        let mut ui = UiState::new(DIMS);
+        let initial = ui.pacer.target_rate();
+        ui.handle_key(KeyCode::Char('+'), 1);
+        assert!(ui.pacer.target_rate() > initial || ui.pacer.target_rate().is_none());
+        ui.handle_key(KeyCode::Char('['), 1);
+        assert_eq!(ui.pacer.target_rate(), initial);
+    }
+
+    #[test]
+    fn test_restart_keys() {
+        let mut ui = UiState::new(DIMS);
+        assert_eq!(
+            ui.handle_key(KeyCode::Char('r'), 1),
+            Control::Restart { reseed: true }
+        );
+        assert_eq!(
+            ui.handle_key(KeyCode::Char('R'), 1),
+            Control::Restart { reseed: false }
+        );
+    }
+
+    #[test]
+    fn test_noise_keys() {
+        let mut ui = UiState::new(DIMS);
+        assert_eq!(
+            ui.handle_key(KeyCode::Char('N'), 1),
+            Control::AdjustNoise(NOISE_STEP)
+        );
+        assert_eq!(
+            ui.handle_key(KeyCode::Char('n'), 1),
+            Control::AdjustNoise(-NOISE_STEP)
+        );
+        // In single-step mode 'n' advances a step instead.
+        ui.handle_key(KeyCode::Char('s'), 1);
+        assert_eq!(ui.handle_key(KeyCode::Char('n'), 1), Control::Continue);
+        assert!(ui.take_single_step());
+    }
+
+    #[test]
+    fn test_param_panel_keys() {
+        let mut ui = UiState::new(DIMS);
+        let speed = ui.pacer.target_rate();
+        ui.handle_key(KeyCode::Tab, 1);
+        assert!(ui.params.open);
+        assert_eq!(
+            ui.handle_key(KeyCode::Char('+'), 1),
+            Control::AdjustNoise(NOISE_STEP)
+        );
+        assert_eq!(ui.pacer.target_rate(), speed);
+
+        ui.handle_key(KeyCode::Tab, 1);
+        assert_eq!(ui.params.selected(), LiveParam::Speed);
+        assert_eq!(ui.handle_key(KeyCode::Char('-'), 1), Control::Continue);
+        assert!(ui.pacer.target_rate() < speed);
+
+        // Esc closes the panel before it affects the timeline.
+        ui.handle_key(KeyCode::Left, 10);
+        ui.handle_key(KeyCode::Esc, 10);
+        assert!(!ui.params.open);
+        assert_eq!(ui.timeline.mode(), ViewMode::Detach);
+    }
+
+    #[test]
+    fn test_mark_for_diff() {
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Char('m'), 10);
+        assert_eq!(ui.marked, None);
+        ui.handle_key(KeyCode::PageUp, 20);
+        ui.handle_key(KeyCode::Char('m'), 20);
+        assert_eq!(ui.marked, Some(9));
+        ui.handle_key(KeyCode::End, 20);
+        assert_eq!(ui.diff_against(), Some(9));
+        ui.handle_key(KeyCode::Esc, 20);
+        assert_eq!(ui.diff_against(), None);
+        ui.handle_key(KeyCode::Home, 20);
+        ui.handle_key(KeyCode::Char('m'), 20);
+        assert_eq!(ui.marked, None);
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_playback_advances_with_clock() {
+        let mut ui = UiState::new(DIMS);
+        // 'p' toggles pause while live and only plays back while browsing.
+        ui.handle_key(KeyCode::Char('p'), 10);
+        assert!(ui.playback.is_none());
+        assert_eq!(ui.run_mode, RunMode::Paused);
+        ui.handle_key(KeyCode::Char('p'), 10);
+
+        ui.handle_key(KeyCode::Home, 10);
+        ui.handle_key(KeyCode::Char('p'), 10);
+        assert!(ui.playback.is_some());
+        assert_eq!(ui.run_mode, RunMode::Running);
+        assert!(!ui.should_step());
+        ui.tick_playback(ms(0), 10);
+        assert_eq!(ui.current_step(10), 0);
+        ui.tick_playback(ms(100), 10);
+        assert_eq!(ui.current_step(10), 1);
+        ui.tick_playback(ms(350), 10);
+        assert_eq!(ui.current_step(10), 3);
+
+        // The speed keys now drive playback rather than the simulation.
+        let rate = ui.pacer.target_rate();
+        ui.handle_key(KeyCode::Char('+'), 10);
+        assert_eq!(ui.pacer.target_rate(), rate);
+        assert_eq!(ui.playback.as_ref().unwrap().rate(), Some(20));
+        // 50ms carried over plus 100ms at 20 frames per second.
+        ui.tick_playback(ms(450), 10);
+        assert_eq!(ui.current_step(10), 6);
+
+        ui.handle_key(KeyCode::Char('p'), 10);
+        assert!(ui.playback.is_none());
+        ui.tick_playback(ms(2000), 10);
+        assert_eq!(ui.current_step(10), 6);
+    }
+
+    #[test]
+    fn test_navigation_stops_playback() {
+        for code in [
+            KeyCode::Left,
+            KeyCode::End,
+            KeyCode::Esc,
+            KeyCode::Char('g'),
+        ] {
+            let mut ui = UiState::new(DIMS);
+            ui.handle_key(KeyCode::Home, 10);
+            ui.handle_key(KeyCode::Char('p'), 10);
+            ui.handle_key(code, 10);
+            assert!(ui.playback.is_none(), "{:?}", code);
+        }
+        // Unrelated keys leave it running.
+        let mut ui = UiState::new(DIMS);
+        ui.handle_key(KeyCode::Home, 10);
+        ui.handle_key(KeyCode::Char('p'), 10);
+        ui.handle_key(KeyCode::Char('c'), 10);
+        assert!(ui.playback.is_some());
+    }
+
+    #[test]
+    fn test_playback_end() {
+        for (end, mode, step) in [
+            (PlaybackEnd::Stop, ViewMode::Detach, 9),
+            (PlaybackEnd::Live, ViewMode::Latest, 19),
+        ] {
+            let mut ui = UiState::new(DIMS);
+            ui.playback_end = end;
+            ui.handle_key(KeyCode::Left, 10);
+            ui.handle_key(KeyCode::Left, 10);
+            ui.handle_key(KeyCode::Char('p'), 10);
+            ui.tick_playback(ms(0), 10);
+            ui.tick_playback(ms(100), 10);
+            assert!(ui.playback.is_some());
+            ui.tick_playback(ms(300), 10);
+            assert!(ui.playback.is_none(), "{:?}", end);
+            assert_eq!(ui.timeline.mode(), mode);
+            assert_eq!(ui.current_step(20), step);
+        }
+    }
+
+    #[test]
+    fn test_bookmark_keys() {
+        let mut ui = UiState::new(DIMS);
+        assert_eq!(
+            ui.handle_key(KeyCode::Char('b'), 10),
+            Control::ToggleBookmark(9)
+        );
+        ui.handle_key(KeyCode::Home, 10);
+        assert_eq!(
+            ui.handle_key(KeyCode::Char('b'), 10),
+            Control::ToggleBookmark(0)
+        );
+        assert_eq!(ui.handle_key(KeyCode::Char('B'), 10), Control::NextBookmark);
+    }
+
+    #[test]
+    fn test_quit() {
+        let mut ui = UiState::new(DIMS);
+        assert_eq!(ui.handle_key(KeyCode::Char('x'), 1), Control::Continue);
+        assert_eq!(ui.handle_key(KeyCode::Char('q'), 1), Control::Quit);
+    }
+}
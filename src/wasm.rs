@@ -0,0 +1,116 @@
+//! JavaScript bindings for driving a simulation from a browser, built with
+//! `wasm-pack build --target web --no-default-features --features wasm`. See `examples/web`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::builder::EnvironmentBuilder;
+use crate::env::Environment;
+use crate::json::metrics_json;
+use crate::snapshot::Snapshot;
+
+/// A seeded simulation of random Deflect and TicToc agents.
+#[wasm_bindgen]
+pub struct WasmSim {
+    env: Environment,
+}
+
+#[wasm_bindgen]
+impl WasmSim {
+    /// Fails with the reason if the grid is empty or `noise` isn't in `[0, 1]`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rows: usize, cols: usize, noise: f32, seed: u64) -> Result<WasmSim, JsError> {
+        EnvironmentBuilder::new()
+            .size(rows, cols)
+            .noise(noise)
+            .seed(seed)
+            .build()
+            .map(|env| WasmSim { env })
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn step(&mut self) -> StepResult {
+        let metric = self.env.step();
+        StepResult {
+            cells: encode_cells(&metric.snapshot),
+            metrics: metrics_json(&metric),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.env.dimensions().0
+    }
+
+    pub fn cols(&self) -> usize {
+        self.env.dimensions().1
+    }
+
+    pub fn noise(&self) -> f32 {
+        self.env.noise()
+    }
+
+    /// Clamps `noise` to `[0, 1]` and returns the value applied.
+    #[wasm_bindgen(js_name = setNoise)]
+    pub fn set_noise(&mut self, noise: f32) -> f32 {
+        self.env.set_noise(noise)
+    }
+}
+
+/// What one step produced.
+#[wasm_bindgen]
+pub struct StepResult {
+    cells: Vec<u8>,
+    metrics: String,
+}
+
+#[wasm_bindgen]
+impl StepResult {
+    /// Every cell's strategy number (see `Strategy::index`) in row-major order, as a
+    /// `Uint8Array`.
+    #[wasm_bindgen(getter)]
+    pub fn cells(&self) -> Vec<u8> {
+        self.cells.clone()
+    }
+
+    /// The step's metrics as a JSON object.
+    #[wasm_bindgen(getter)]
+    pub fn metrics(&self) -> String {
+        self.metrics.clone()
+    }
+}
+
+/// The strategy number of every cell in row-major order.
+pub fn encode_cells(snapshot: &Snapshot) -> Vec<u8> {
+    snapshot.cells().iter().map(|s| s.index()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Strategy;
+    use Strategy::{Coop as C, Deflect as D, Random as R, TicToc as T};
+
+    #[test]
+    fn test_encode_cells() {
+        let snapshot = Snapshot::from(vec![vec![D, T], vec![C, R]]);
+        assert_eq!(encode_cells(&snapshot), vec![0, 1, 2, 3]);
+        assert!(encode_cells(&Snapshot::default()).is_empty());
+    }
+
+    #[test]
+    fn test_sim_steps() {
+        let mut sim = WasmSim::new(3, 5, 0.1, 42).unwrap();
+        assert_eq!((sim.rows(), sim.cols()), (3, 5));
+        let result = sim.step();
+        assert_eq!(result.cells().len(), 15);
+        assert!(result
+            .cells()
+            .iter()
+            .all(|&i| Strategy::from_index(i).is_some()));
+        assert!(result.metrics().starts_with(r#"{"step":0,"#));
+        assert!(sim.step().metrics().starts_with(r#"{"step":1,"#));
+        assert_eq!(sim.set_noise(2.0), 1.0);
+        assert_eq!(sim.noise(), 1.0);
+        let mut same = WasmSim::new(3, 5, 0.1, 42).unwrap();
+        assert_eq!(same.step().cells(), result.cells());
+    }
+}
@@ -0,0 +1,56 @@
+//! The C interface called the way a C host would: through `extern "C"` declarations matching
+//! `include/coop.h`, resolved against the library's exported symbols.
+#![cfg(feature = "ffi")]
+
+use std::ptr;
+
+use coop::ffi::{CoopMetricHandle, COOP_ERR_INVALID, COOP_ERR_NULL, COOP_OK};
+use coop::Strategy;
+
+/// Opaque, as C sees it.
+#[repr(C)]
+struct CoopEnv {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn coop_env_new(rows: usize, cols: usize, noise: f32, seed: u64) -> *mut CoopEnv;
+    fn coop_env_step(env: *mut CoopEnv) -> CoopMetricHandle;
+    fn coop_env_snapshot(env: *const CoopEnv, out_buf: *mut u8, len: usize) -> usize;
+    fn coop_env_set_noise(env: *mut CoopEnv, noise: f32) -> i32;
+    fn coop_env_free(env: *mut CoopEnv);
+}
+
+#[test]
+fn test_drive_through_c_abi() {
+    unsafe {
+        let env = coop_env_new(4, 6, 0.05, 11);
+        assert!(!env.is_null());
+        for step in 0..10 {
+            let metric = coop_env_step(env);
+            assert_eq!((metric.status, metric.step), (COOP_OK, step));
+            assert_eq!(metric.population, 24);
+            assert!(metric.coop_actions >= 0);
+        }
+        let needed = coop_env_snapshot(env, ptr::null_mut(), 0);
+        assert_eq!(needed, 24);
+        let mut cells = vec![0xff; needed];
+        assert_eq!(coop_env_snapshot(env, cells.as_mut_ptr(), cells.len()), 24);
+        assert!(cells.iter().all(|&i| Strategy::from_index(i).is_some()));
+        assert_eq!(coop_env_set_noise(env, 0.3), COOP_OK);
+        assert_eq!(coop_env_set_noise(env, f32::NAN), COOP_ERR_INVALID);
+        coop_env_free(env);
+    }
+}
+
+#[test]
+fn test_null_and_invalid_arguments() {
+    unsafe {
+        assert!(coop_env_new(3, 0, 0.1, 1).is_null());
+        assert!(coop_env_new(3, 3, -0.1, 1).is_null());
+        assert_eq!(coop_env_step(ptr::null_mut()).status, COOP_ERR_NULL);
+        assert_eq!(coop_env_snapshot(ptr::null(), ptr::null_mut(), 0), 0);
+        assert_eq!(coop_env_set_noise(ptr::null_mut(), 0.1), COOP_ERR_NULL);
+        coop_env_free(ptr::null_mut());
+    }
+}
@@ -0,0 +1,168 @@
+//! Recorded dynamics of fixed scenarios, so refactors can't silently change what a seeded run
+//! does. Each scenario's per-step strategy counts and cooperation rates are checked in under
+//! `tests/goldens/` as JSON with one step per line.
+//!
+//! After an intentional change to the dynamics, regenerate them with
+//! `REGEN_GOLDENS=1 cargo test --test goldens` and review the diff.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use coop::{Agent, Environment, EnvironmentBuilder, Metric, Strategy, UpdateMode};
+
+/// Steps recorded per scenario.
+const STEPS: usize = 40;
+
+struct Scenario {
+    name: &'static str,
+    build: fn() -> Environment,
+}
+
+const SCENARIOS: [Scenario; 4] = [
+    Scenario {
+        name: "default_pd_20x20",
+        build: || {
+            EnvironmentBuilder::new()
+                .size(20, 20)
+                .seed(20)
+                .build()
+                .unwrap()
+        },
+    },
+    Scenario {
+        name: "default_pd_20x20_synchronous",
+        build: || {
+            EnvironmentBuilder::new()
+                .size(20, 20)
+                .seed(20)
+                .update_mode(UpdateMode::Synchronous)
+                .build()
+                .unwrap()
+        },
+    },
+    Scenario {
+        name: "all_tictoc_noise",
+        build: || {
+            EnvironmentBuilder::new()
+                .size(15, 15)
+                .seed(7)
+                .noise(0.05)
+                .agents(|c, _| Agent::new(c, Strategy::TicToc))
+                .build()
+                .unwrap()
+        },
+    },
+    Scenario {
+        name: "single_deflect_invader",
+        build: || {
+            EnvironmentBuilder::new()
+                .size(11, 11)
+                .seed(3)
+                .agents(|c, _| {
+                    let strategy = if c == (5, 5) {
+                        Strategy::Deflect
+                    } else {
+                        Strategy::TicToc
+                    };
+                    Agent::new(c, strategy)
+                })
+                .build()
+                .unwrap()
+        },
+    },
+];
+
+fn golden_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "goldens", name]
+        .iter()
+        .collect::<PathBuf>()
+        .with_extension("json")
+}
+
+/// One JSON object per step, each on its own line so diffs point at steps.
+fn render(name: &str, metrics: &[Metric]) -> String {
+    let mut json = format!("{{\n  \"scenario\": \"{}\",\n  \"steps\": [\n", name);
+    for (i, metric) in metrics.iter().enumerate() {
+        let counts: Vec<String> = Strategy::ALL
+            .iter()
+            .map(|s| {
+                format!(
+                    "\"{}\": {}",
+                    s.name(),
+                    metric.strategies.get(s).unwrap_or(&0)
+                )
+            })
+            .collect();
+        let _ = write!(
+            json,
+            "    {{\"step\": {}, \"coop_rate\": {}, \"strategies\": {{{}}}}}",
+            metric.step_index,
+            metric.coop_rate,
+            counts.join(", ")
+        );
+        json.push_str(if i + 1 < metrics.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("  ]\n}\n");
+    json
+}
+
+/// The first line where `actual` departs from `expected`, with both versions.
+fn first_difference(expected: &str, actual: &str) -> Option<String> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (e, a) if e == a => continue,
+            (e, a) => {
+                return Some(format!(
+                    "first difference at line {}:\n  expected: {}\n  actual:   {}",
+                    line,
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>")
+                ))
+            }
+        }
+    }
+    None
+}
+
+#[test]
+fn test_goldens() {
+    let regen = std::env::var_os("REGEN_GOLDENS").is_some();
+    let mut failures = Vec::new();
+    for scenario in &SCENARIOS {
+        let actual = render(scenario.name, &(scenario.build)().run(STEPS));
+        let path = golden_path(scenario.name);
+        if regen {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) => {
+                if let Some(difference) = first_difference(&expected, &actual) {
+                    failures.push(format!("{}: {}", scenario.name, difference));
+                }
+            }
+            Err(e) => failures.push(format!("{}: can't read {:?}: {}", scenario.name, path, e)),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "dynamics changed; if intended, rerun with REGEN_GOLDENS=1\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_first_difference() {
+    assert_eq!(first_difference("a\nb\n", "a\nb\n"), None);
+    let report = first_difference("a\nb\nc\n", "a\nx\nc\n").unwrap();
+    assert!(report.contains("line 2"), "{}", report);
+    assert!(report.contains("expected: b") && report.contains("actual:   x"));
+    assert!(first_difference("a\n", "a\nb\n")
+        .unwrap()
+        .contains("expected: <end of file>"));
+}
@@ -0,0 +1,39 @@
+//! Group selection can keep cooperation alive where imitating neighbors alone wipes it out.
+
+use coop::{EnvironmentBuilder, Groups, Payoffs, Strategy};
+
+/// Mean cooperation rate over the last 25 of 150 steps of a 20x20 grid that starts with 80%
+/// cooperators and tempts defection with 6 points.
+fn late_cooperation(seed: u64, groups: Option<Groups>) -> f32 {
+    let mut mix = vec![Strategy::Coop; 4];
+    mix.push(Strategy::Deflect);
+    let mut builder = EnvironmentBuilder::new()
+        .size(20, 20)
+        .seed(seed)
+        .payoffs(Payoffs {
+            temptation: 6.0,
+            ..Payoffs::default()
+        })
+        .strategies(&mix);
+    if let Some(groups) = groups {
+        builder = builder.groups(groups);
+    }
+    let metrics = builder.build().unwrap().run(150);
+    metrics[125..].iter().map(|m| m.coop_rate).sum::<f32>() / 25.0
+}
+
+#[test]
+fn test_group_selection_rescues_cooperation() {
+    let seeds = [2, 3, 4, 5];
+    let mean = |groups: Option<Groups>| {
+        seeds
+            .iter()
+            .map(|&s| late_cooperation(s, groups))
+            .sum::<f32>()
+            / seeds.len() as f32
+    };
+    let individual = mean(None);
+    let grouped = mean(Some(Groups::new(2, 2, 1)));
+    assert!(individual < 0.1, "individual selection: {}", individual);
+    assert!(grouped > 0.5, "group selection: {}", grouped);
+}
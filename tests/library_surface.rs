@@ -0,0 +1,17 @@
+//! The library API has to stay usable without the `tui` feature, e.g. with
+//! `cargo test --no-default-features`.
+
+use coop::{Agent, Environment, EnvironmentBuilder, Metric, Strategy};
+
+#[test]
+fn test_core_types_without_tui() {
+    let mut env: Environment = EnvironmentBuilder::new()
+        .size(3, 4)
+        .seed(1)
+        .agents(|c, _| Agent::new(c, Strategy::Coop))
+        .build()
+        .unwrap();
+    let metric: Metric = env.step();
+    assert_eq!(metric.strategies[&Strategy::Coop], 12);
+    assert_eq!(metric.coop_rate, 1.0);
+}
@@ -0,0 +1,54 @@
+//! Swapping the interaction and adaptation ranges changes where a run ends up.
+
+use coop::{EnvironmentBuilder, Metric, Neighborhood, Strategy};
+
+/// The last of 100 steps of a 20x20 grid of Coop, Deflect and TicToc under a little noise,
+/// playing within `interaction` squares and imitating within `adaptation` squares.
+fn final_metric(seed: u64, interaction: usize, adaptation: usize) -> Metric {
+    EnvironmentBuilder::new()
+        .size(20, 20)
+        .seed(seed)
+        .noise(0.02)
+        .strategies(&[Strategy::Coop, Strategy::Deflect, Strategy::TicToc])
+        .interaction_neighborhood(Neighborhood::Moore(interaction))
+        .adaptation_neighborhood(Neighborhood::Moore(adaptation))
+        .build()
+        .unwrap()
+        .run(100)
+        .pop()
+        .unwrap()
+}
+
+/// Single runs land in one of a few basins, whichever ranges they use, so the ranges are
+/// compared over many seeds.
+const SEEDS: u64 = 16;
+
+#[test]
+fn test_ranges_shape_the_outcome() {
+    let runs: Vec<(Metric, Metric)> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..SEEDS)
+            .map(|seed| s.spawn(move || (final_metric(seed, 1, 2), final_metric(seed, 2, 1))))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mean_rate = |metrics: &[&Metric]| {
+        metrics.iter().map(|m| m.coop_rate as f64).sum::<f64>() / metrics.len() as f64
+    };
+    let takeovers = |metrics: &[&Metric]| {
+        metrics
+            .iter()
+            .filter(|m| m.strategies.get(&Strategy::Coop) > Some(&300))
+            .count()
+    };
+    let narrow_play: Vec<_> = runs.iter().map(|(narrow, _)| narrow).collect();
+    let wide_play: Vec<_> = runs.iter().map(|(_, wide)| wide).collect();
+    let (narrow_rate, wide_rate) = (mean_rate(&narrow_play), mean_rate(&wide_play));
+    // Playing locally but imitating widely often lets unconditional cooperators take over...
+    assert!(
+        narrow_rate > wide_rate + 0.25,
+        "{narrow_rate} vs {wide_rate}"
+    );
+    assert!(takeovers(&narrow_play) >= 4, "{}", takeovers(&narrow_play));
+    // ...while playing widely but imitating locally almost never does.
+    assert!(takeovers(&wide_play) <= 1, "{}", takeovers(&wide_play));
+}
@@ -0,0 +1,71 @@
+//! Every preset shows its headline result under its own seed.
+
+use coop::presets::{self, Preset};
+use coop::{Metric, Strategy};
+
+fn run(preset: &Preset) -> Vec<Metric> {
+    preset.build().unwrap().run(preset.steps)
+}
+
+fn count(metric: &Metric, strategy: Strategy) -> usize {
+    metric
+        .strategies
+        .get(&strategy.kind())
+        .copied()
+        .unwrap_or(0)
+}
+
+#[test]
+fn test_nowak_may_coexistence() {
+    let metrics = run(&presets::nowak_may_1992());
+    for metric in &metrics {
+        assert!(
+            count(metric, Strategy::Coop) > 0,
+            "step {}",
+            metric.step_index
+        );
+        assert!(
+            count(metric, Strategy::Deflect) > 0,
+            "step {}",
+            metric.step_index
+        );
+    }
+    // Defectors spread from their 10% but never take over.
+    let last = metrics.last().unwrap();
+    assert!(
+        (0.3..0.8).contains(&last.coop_rate),
+        "cooperation {}",
+        last.coop_rate
+    );
+}
+
+#[test]
+fn test_defector_takeover() {
+    let metrics = run(&presets::defector_takeover());
+    let last = metrics.last().unwrap();
+    assert_eq!(count(last, Strategy::Coop), 0);
+    assert_eq!(last.coop_rate, 0.0);
+}
+
+#[test]
+fn test_noise_resilience() {
+    let preset = presets::noise_resilience();
+    let generous: Strategy = "gtft".parse().unwrap();
+    let metrics = run(&preset);
+    let last = metrics.last().unwrap();
+    assert_eq!(count(last, Strategy::TicToc), 0);
+    assert_eq!(count(last, generous), preset.rows * preset.cols);
+    let late = &metrics[metrics.len() - 50..];
+    let mean = late.iter().map(|m| m.coop_rate).sum::<f32>() / late.len() as f32;
+    assert!(mean > 0.85, "cooperation {}", mean);
+}
+
+#[test]
+fn test_single_invader_dies_out() {
+    let preset = presets::single_invader();
+    let metrics = run(&preset);
+    assert_eq!(count(&metrics[0], Strategy::Deflect), 1);
+    let last = metrics.last().unwrap();
+    assert_eq!(count(last, Strategy::Deflect), 0);
+    assert_eq!(count(last, Strategy::TicToc), preset.rows * preset.cols);
+}
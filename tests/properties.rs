@@ -0,0 +1,245 @@
+//! Invariants that should hold for any environment, checked over randomly generated ones.
+//!
+//! Each case is drawn from its own seed, and a failure names that seed; rerun just that case
+//! with `PROPERTY_SEED=<seed> cargo test --test properties`. Cases draw the kind and radius of
+//! both neighborhoods; grids never wrap, so the neighborhood properties only cover bounded ones.
+
+use std::collections::BTreeSet;
+
+use coop::{Coord, Environment, EnvironmentBuilder, Metric, Neighborhood, Strategy, UpdateMode};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Generated cases per property.
+const CASES: u64 = 64;
+const MAX_SIDE: usize = 30;
+const MAX_STEPS: usize = 4;
+const MAX_RADIUS: usize = 3;
+/// Degenerate shapes every property sees before the random ones.
+const EDGE_SHAPES: [(usize, usize); 5] = [(1, 1), (1, 2), (2, 1), (1, MAX_SIDE), (MAX_SIDE, 1)];
+
+#[derive(Debug)]
+struct Case {
+    rows: usize,
+    cols: usize,
+    noise: f32,
+    seed: u64,
+    mix: Vec<Strategy>,
+    update_mode: UpdateMode,
+    steps: usize,
+    interaction: Neighborhood,
+    adaptation: Neighborhood,
+}
+
+impl Case {
+    fn generate(case_seed: u64, shape: Option<(usize, usize)>) -> Case {
+        let mut rng = StdRng::seed_from_u64(case_seed);
+        let (rows, cols) =
+            shape.unwrap_or_else(|| (rng.gen_range(1..=MAX_SIDE), rng.gen_range(1..=MAX_SIDE)));
+        let mut mix = Strategy::ALL.to_vec();
+        mix.shuffle(&mut rng);
+        mix.truncate(rng.gen_range(1..=mix.len()));
+        Case {
+            rows,
+            cols,
+            // Both ends of the noise range come up often enough to matter.
+            noise: match rng.gen_range(0..4) {
+                0 => 0.0,
+                1 => 1.0,
+                _ => rng.gen(),
+            },
+            seed: rng.gen(),
+            mix,
+            update_mode: if rng.gen() {
+                UpdateMode::Synchronous
+            } else {
+                UpdateMode::Legacy
+            },
+            steps: rng.gen_range(1..=MAX_STEPS),
+            interaction: random_neighborhood(&mut rng),
+            adaptation: random_neighborhood(&mut rng),
+        }
+    }
+
+    fn build(&self) -> Environment {
+        EnvironmentBuilder::new()
+            .size(self.rows, self.cols)
+            .noise(self.noise)
+            .seed(self.seed)
+            .strategies(&self.mix)
+            .update_mode(self.update_mode)
+            .interaction_neighborhood(self.interaction)
+            .adaptation_neighborhood(self.adaptation)
+            .build()
+            .unwrap()
+    }
+}
+
+fn random_neighborhood(rng: &mut StdRng) -> Neighborhood {
+    let radius = rng.gen_range(1..=MAX_RADIUS);
+    if rng.gen() {
+        Neighborhood::Moore(radius)
+    } else {
+        Neighborhood::VonNeumann(radius)
+    }
+}
+
+/// Whether `b` is in `a`'s `neighborhood`, worked out from the distance between them.
+fn in_neighborhood(neighborhood: Neighborhood, a: Coord, b: Coord) -> bool {
+    let (dr, dc) = (a.0.abs_diff(b.0), a.1.abs_diff(b.1));
+    a != b
+        && match neighborhood {
+            Neighborhood::Moore(r) => dr <= r && dc <= r,
+            Neighborhood::VonNeumann(r) => dr + dc <= r,
+        }
+}
+
+/// Runs `property` on every generated case, or only on `PROPERTY_SEED`'s when that's set, and
+/// panics with the failing case and its seed.
+fn check(name: &str, property: impl Fn(&Case) -> Result<(), String>) {
+    let only = std::env::var("PROPERTY_SEED")
+        .ok()
+        .map(|s| s.parse::<u64>().expect("PROPERTY_SEED must be a u64"));
+    let seeds: Vec<u64> = match only {
+        Some(seed) => vec![seed],
+        None => {
+            let mut master = StdRng::seed_from_u64(name.bytes().map(u64::from).sum());
+            (0..EDGE_SHAPES.len() as u64 + CASES)
+                .map(|_| master.gen())
+                .collect()
+        }
+    };
+    for (i, &case_seed) in seeds.iter().enumerate() {
+        let shape = only
+            .is_none()
+            .then(|| EDGE_SHAPES.get(i).copied())
+            .flatten();
+        let case = Case::generate(case_seed, shape);
+        if let Err(failure) = property(&case) {
+            panic!(
+                "{} failed for {:?}: {}\nrerun with PROPERTY_SEED={}{}",
+                name,
+                case,
+                failure,
+                case_seed,
+                if shape.is_some() {
+                    " (an edge shape; the rerun draws its own size)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+}
+
+/// Turns a failed condition into the property's error.
+fn ensure(condition: bool, failure: impl FnOnce() -> String) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(failure())
+    }
+}
+
+fn check_metric(case: &Case, metric: &Metric) -> Result<(), String> {
+    let step = metric.step_index;
+    let total: usize = metric.strategies.values().sum();
+    ensure(total == case.rows * case.cols, || {
+        format!("step {}: strategy counts sum to {}", step, total)
+    })?;
+    ensure(
+        metric.snapshot.dimensions() == (case.rows, case.cols),
+        || {
+            format!(
+                "step {}: snapshot is {:?}",
+                step,
+                metric.snapshot.dimensions()
+            )
+        },
+    )?;
+    ensure(metric.scores.dimensions() == (case.rows, case.cols), || {
+        format!("step {}: scores aren't shaped like the grid", step)
+    })?;
+    ensure((0.0..=1.0).contains(&metric.coop_rate), || {
+        format!("step {}: coop rate {}", step, metric.coop_rate)
+    })?;
+    for (cell, &score) in metric.scores.iter_with_coords() {
+        ensure(score.is_finite() && score >= 0.0, || {
+            format!("step {}: score {} at {:?}", step, score, cell)
+        })?;
+    }
+    Ok(())
+}
+
+fn neighbor_coords(env: &Environment, coord: Coord) -> BTreeSet<Coord> {
+    env.neighbors(coord).iter().map(|a| a.coord).collect()
+}
+
+#[test]
+fn test_metrics_match_the_grid() {
+    check("metrics_match_the_grid", |case| {
+        let mut env = case.build();
+        for metric in env.run(case.steps) {
+            check_metric(case, &metric)?;
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn test_neighbors_match_the_neighborhood() {
+    check("neighbors_match_the_neighborhood", |case| {
+        let env = case.build();
+        for (coord, _) in env.iter_agents() {
+            let neighbors = neighbor_coords(&env, coord);
+            let expected: BTreeSet<Coord> = (0..case.rows)
+                .flat_map(|row| (0..case.cols).map(move |col| (row, col)))
+                .filter(|&other| in_neighborhood(case.interaction, coord, other))
+                .collect();
+            ensure(neighbors == expected, || {
+                format!("{:?} has neighbors {:?}", coord, neighbors)
+            })?;
+            for &other in &neighbors {
+                ensure(neighbor_coords(&env, other).contains(&coord), || {
+                    format!("{:?} neighbors {:?} but not the reverse", coord, other)
+                })?;
+            }
+        }
+        ensure(
+            env.neighbors((case.rows, 0)).is_empty() && env.neighbors((0, case.cols)).is_empty(),
+            || "cells outside the grid have neighbors".to_string(),
+        )
+    });
+}
+
+#[test]
+fn test_histories_only_hold_neighbors() {
+    check("histories_only_hold_neighbors", |case| {
+        let mut env = case.build();
+        env.run(case.steps);
+        for (coord, agent) in env.iter_agents() {
+            let neighbors = neighbor_coords(&env, coord);
+            for opponent in agent.opponents() {
+                ensure(neighbors.contains(&opponent), || {
+                    format!("{:?} has a history with non-neighbor {:?}", coord, opponent)
+                })?;
+            }
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn test_counts_cover_only_the_mix() {
+    check("counts_cover_only_the_mix", |case| {
+        let metric = case.build().step();
+        // Imitation only copies strategies that are already on the grid.
+        for (strategy, &count) in &metric.strategies {
+            ensure(count == 0 || case.mix.contains(strategy), || {
+                format!("{} agents play {:?}", count, strategy)
+            })?;
+        }
+        Ok(())
+    });
+}